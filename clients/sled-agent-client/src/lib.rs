@@ -464,6 +464,11 @@ impl From<omicron_common::api::internal::shared::SourceNatConfig>
 pub trait TestInterfaces {
     async fn instance_finish_transition(&self, id: Uuid);
     async fn disk_finish_transition(&self, id: Uuid);
+    async fn disk_set_sim_state(
+        &self,
+        id: Uuid,
+        runtime: omicron_common::api::internal::nexus::DiskRuntimeState,
+    );
 }
 
 #[async_trait]
@@ -489,4 +494,21 @@ impl TestInterfaces for Client {
             .await
             .expect("disk_finish_transition() failed unexpectedly");
     }
+
+    async fn disk_set_sim_state(
+        &self,
+        id: Uuid,
+        runtime: omicron_common::api::internal::nexus::DiskRuntimeState,
+    ) {
+        let baseurl = self.baseurl();
+        let client = self.client();
+        let url = format!("{}/disks/{}/sim-state", baseurl, id);
+        client
+            .post(url)
+            .json(&runtime)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .expect("disk_set_sim_state() failed unexpectedly");
+    }
 }
@@ -20,6 +20,8 @@ progenitor::generate_api!(
     derives = [schemars::JsonSchema],
     patch =
         {
+        ArtifactId = { derives = [ PartialEq, Eq, PartialOrd, Ord ] },
+        SpCabooses = { derives = [PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize] },
         SpComponentCaboose = { derives = [PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize] },
         SpIdentifier = { derives = [Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize] },
         SpState = { derives = [ PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize] },
@@ -311,6 +311,8 @@ pub struct BackgroundTaskConfig {
     pub dns_external: DnsTasksConfig,
     /// configuration for external endpoint list watcher
     pub external_endpoints: ExternalEndpointsConfig,
+    /// configuration for the orphaned external IP reaper
+    pub external_ip_reaper: ExternalIpReaperConfig,
 }
 
 #[serde_as]
@@ -345,6 +347,19 @@ pub struct ExternalEndpointsConfig {
     // allow/disallow wildcard certs, don't serve expired certs, etc.)
 }
 
+#[serde_as]
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ExternalIpReaperConfig {
+    /// period (in seconds) for periodic activations of this background task
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub period_secs: Duration,
+
+    /// minimum age (in seconds) of an orphaned instance external IP before it
+    /// is released back to its pool
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub grace_period_secs: Duration,
+}
+
 /// Configuration for a nexus server
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 pub struct PackageConfig {
@@ -450,7 +465,7 @@ mod test {
     use crate::nexus_config::{
         BackgroundTaskConfig, ConfigDropshotWithTls, Database,
         DeploymentConfig, DnsTasksConfig, DpdConfig, ExternalEndpointsConfig,
-        InternalDns, LoadErrorKind,
+        ExternalIpReaperConfig, InternalDns, LoadErrorKind,
     };
     use dropshot::ConfigDropshot;
     use dropshot::ConfigLogging;
@@ -596,6 +611,8 @@ mod test {
             dns_external.period_secs_propagation = 7
             dns_external.max_concurrent_server_updates = 8
             external_endpoints.period_secs = 9
+            external_ip_reaper.period_secs = 10
+            external_ip_reaper.grace_period_secs = 11
             [default_region_allocation_strategy]
             type = "random"
             seed = 0
@@ -680,6 +697,10 @@ mod test {
                         },
                         external_endpoints: ExternalEndpointsConfig {
                             period_secs: Duration::from_secs(9),
+                        },
+                        external_ip_reaper: ExternalIpReaperConfig {
+                            period_secs: Duration::from_secs(10),
+                            grace_period_secs: Duration::from_secs(11),
                         }
                     },
                     default_region_allocation_strategy:
@@ -733,6 +754,8 @@ mod test {
             dns_external.period_secs_propagation = 7
             dns_external.max_concurrent_server_updates = 8
             external_endpoints.period_secs = 9
+            external_ip_reaper.period_secs = 10
+            external_ip_reaper.grace_period_secs = 11
             [default_region_allocation_strategy]
             type = "random"
             "##,
@@ -36,6 +36,8 @@
 use anyhow::Context;
 use clap::Parser;
 use clap::Subcommand;
+use gateway_client::types::SpIdentifier;
+use gateway_client::types::SpType;
 use omicron_common::address::Ipv6Subnet;
 use std::net::SocketAddr;
 use std::net::SocketAddrV6;
@@ -45,6 +47,7 @@ mod mgs;
 mod nexus;
 mod oximeter;
 mod sled_agent;
+mod wicketd;
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
@@ -62,6 +65,7 @@ async fn main() -> Result<(), anyhow::Error> {
         OmdbCommands::Nexus(nexus) => nexus.run_cmd(&args, &log).await,
         OmdbCommands::Oximeter(oximeter) => oximeter.run_cmd(&log).await,
         OmdbCommands::SledAgent(sled) => sled.run_cmd(&args, &log).await,
+        OmdbCommands::Wicketd(wicketd) => wicketd.run_cmd(&args, &log).await,
     }
 }
 
@@ -165,6 +169,31 @@ enum OmdbCommands {
     Oximeter(oximeter::OximeterArgs),
     /// Debug a specific Sled
     SledAgent(sled_agent::SledAgentArgs),
+    /// Debug a specific wicketd instance
+    Wicketd(wicketd::WicketdArgs),
+}
+
+/// Parses an SP identifier of the form `TYPE:SLOT` (e.g., `sled:5`).
+fn parse_sp_identifier(s: &str) -> Result<SpIdentifier, String> {
+    let Some((type_, slot)) = s.split_once(':') else {
+        return Err(format!(
+            "expected TYPE:SLOT (e.g., \"sled:5\"), got {s:?}"
+        ));
+    };
+    let type_ = match type_ {
+        "sled" => SpType::Sled,
+        "switch" => SpType::Switch,
+        "power" => SpType::Power,
+        _ => {
+            return Err(format!(
+                "unknown SP type {type_:?} (expected \"sled\", \"switch\", \
+                 or \"power\")"
+            ))
+        }
+    };
+    let slot =
+        slot.parse().map_err(|_| format!("invalid SP slot: {slot:?}"))?;
+    Ok(SpIdentifier { type_, slot })
 }
 
 fn parse_dropshot_log_level(
@@ -5,6 +5,7 @@
 //! Prototype code for collecting information from systems in the rack
 
 use crate::Omdb;
+use anyhow::bail;
 use anyhow::Context;
 use clap::Args;
 use clap::Subcommand;
@@ -20,6 +21,8 @@ use gateway_client::types::SpIgnitionInfo;
 use gateway_client::types::SpIgnitionSystemType;
 use gateway_client::types::SpState;
 use gateway_client::types::SpType;
+use std::io::BufRead;
+use std::io::Write;
 use tabled::Tabled;
 
 /// Arguments to the "omdb mgs" subcommand
@@ -37,11 +40,31 @@ pub struct MgsArgs {
 enum MgsCommands {
     /// Show information about devices and components visible to MGS
     Inventory(InventoryArgs),
+    /// Reset a component of an SP (disruptive!)
+    ResetComponent(ResetComponentArgs),
 }
 
 #[derive(Debug, Args)]
 struct InventoryArgs {}
 
+#[derive(Debug, Args)]
+struct ResetComponentArgs {
+    /// SP whose component should be reset (e.g., "sled:5" or "switch:0")
+    #[clap(value_parser = crate::parse_sp_identifier)]
+    sp: SpIdentifier,
+
+    /// name of the component to reset (e.g., "sp" or "rot")
+    component: String,
+
+    /// print what would be reset without resetting anything
+    #[clap(long)]
+    dry_run: bool,
+
+    /// reset without asking for confirmation
+    #[clap(long)]
+    yes: bool,
+}
+
 impl MgsArgs {
     pub(crate) async fn run_cmd(
         &self,
@@ -74,6 +97,9 @@ impl MgsArgs {
             MgsCommands::Inventory(inventory_args) => {
                 cmd_mgs_inventory(&mgs_client, inventory_args).await
             }
+            MgsCommands::ResetComponent(reset_args) => {
+                cmd_mgs_reset_component(&mgs_client, reset_args).await
+            }
         }
     }
 }
@@ -148,6 +174,50 @@ async fn cmd_mgs_inventory(
     Ok(())
 }
 
+/// Runs `omdb mgs reset-component`
+///
+/// Resets a single component of an SP through MGS.  This is the same
+/// operation the wicketd update driver uses after writing a new image, and is
+/// occasionally needed to recover a wedged component by hand.  Because it's
+/// disruptive, the operator must confirm it first, unless `--yes` is given.
+async fn cmd_mgs_reset_component(
+    mgs_client: &gateway_client::Client,
+    args: &ResetComponentArgs,
+) -> Result<(), anyhow::Error> {
+    let sp = args.sp;
+    let description = format!(
+        "component {:?} of SP {} {}",
+        args.component,
+        sp_type_to_str(&sp.type_),
+        sp.slot
+    );
+
+    if args.dry_run {
+        println!("would reset {}", description);
+        return Ok(());
+    }
+
+    if !args.yes {
+        print!("about to reset {}; type \"yes\" to continue: ", description);
+        std::io::stdout().flush().context("flushing stdout")?;
+        let mut answer = String::new();
+        std::io::stdin()
+            .lock()
+            .read_line(&mut answer)
+            .context("reading confirmation")?;
+        if answer.trim() != "yes" {
+            bail!("reset of {} not confirmed", description);
+        }
+    }
+
+    mgs_client
+        .sp_component_reset(sp.type_, sp.slot, &args.component)
+        .await
+        .with_context(|| format!("resetting {}", description))?;
+    println!("reset {}", description);
+    Ok(())
+}
+
 fn sp_type_to_str(s: &SpType) -> &'static str {
     match s {
         SpType::Sled => "Sled",
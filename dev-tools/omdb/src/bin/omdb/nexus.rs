@@ -478,6 +478,24 @@ fn print_task_details(bgtask: &BackgroundTask, details: &serde_json::Value) {
                 }
             }
         }
+    } else if name == "external_ip_reaper" {
+        // The "external_ip_reaper" task emits the number of orphaned external
+        // IPs that it released.
+        #[derive(Deserialize)]
+        struct ReaperSuccess {
+            released: usize,
+        }
+
+        match serde_json::from_value::<ReaperSuccess>(details.clone()) {
+            Err(error) => eprintln!(
+                "warning: failed to interpret task details: {:?}: {:?}",
+                error, details
+            ),
+            Ok(found) => println!(
+                "    orphaned external IPs released: {}",
+                found.released
+            ),
+        };
     } else {
         println!(
             "warning: unknown background task: {:?} \
@@ -5,10 +5,25 @@
 //! omdb commands that query or update specific Sleds
 
 use crate::Omdb;
+use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::Context;
 use clap::Args;
 use clap::Subcommand;
+use omicron_common::backoff::retry_notify;
+use omicron_common::backoff::retry_policy_local;
+use omicron_common::backoff::Backoff;
+use omicron_common::backoff::BackoffError;
+use omicron_common::backoff::ExponentialBackoff;
+use serde::Serialize;
+use sled_agent_client::types::SledRole;
+use sled_agent_client::types::TimeSync;
+use sled_agent_client::types::Zpool;
+use sled_agent_client::ResponseValue;
+use std::future::Future;
+use std::num::NonZeroU64;
+use std::time::Duration;
+use std::time::SystemTime;
 
 /// Arguments to the "omdb sled-agent" subcommand
 #[derive(Debug, Args)]
@@ -17,6 +32,26 @@ pub struct SledAgentArgs {
     #[clap(long, env("OMDB_SLED_AGENT_URL"))]
     sled_agent_url: Option<String>,
 
+    /// re-run the command periodically until interrupted
+    #[clap(long)]
+    watch: bool,
+
+    /// seconds between runs with --watch
+    #[clap(
+        long,
+        requires = "watch",
+        default_value_t = NonZeroU64::new(2).unwrap()
+    )]
+    interval: NonZeroU64,
+
+    /// how many times to retry requests that fail transiently
+    #[clap(long, default_value_t = 3)]
+    retries: u32,
+
+    /// seconds to wait for the sled agent to respond to each request
+    #[clap(long, default_value_t = NonZeroU64::new(15).unwrap())]
+    timeout: NonZeroU64,
+
     #[command(subcommand)]
     command: SledAgentCommands,
 }
@@ -31,6 +66,9 @@ enum SledAgentCommands {
     /// print information about zpools
     #[clap(subcommand)]
     Zpools(ZpoolCommands),
+
+    /// print a summary of the sled's role, time sync, zpools, and zones
+    Inventory(InventoryArgs),
 }
 
 #[derive(Debug, Subcommand)]
@@ -45,6 +83,13 @@ enum ZpoolCommands {
     List,
 }
 
+#[derive(Debug, Args)]
+struct InventoryArgs {
+    /// print the summary as a single JSON document
+    #[clap(long)]
+    json: bool,
+}
+
 impl SledAgentArgs {
     /// Run a `omdb sled-agent` subcommand.
     pub(crate) async fn run_cmd(
@@ -60,53 +105,463 @@ impl SledAgentArgs {
                 OMDB_SLED_AGENT_URL"
             );
         };
-        let client =
-            sled_agent_client::Client::new(sled_agent_url, log.clone());
+        let client = make_client(
+            sled_agent_url,
+            Duration::from_secs(self.timeout.get()),
+            log,
+        )?;
+
+        if !self.watch {
+            return self.run_subcommand(&client).await;
+        }
+
+        let interval = Duration::from_secs(self.interval.get());
+        let interrupted = tokio::signal::ctrl_c();
+        tokio::pin!(interrupted);
+        loop {
+            // Clear the screen and move the cursor to the top left.
+            print!("\x1b[2J\x1b[H");
+            println!(
+                "every {}: updated {}\n",
+                humantime::format_duration(interval),
+                humantime::format_rfc3339_seconds(SystemTime::now()),
+            );
 
+            // Errors are expected while the sled agent is coming up (or going
+            // down), so report them and keep watching.
+            let iteration = async {
+                if let Err(error) = self.run_subcommand(&client).await {
+                    eprintln!("error: {:#}", error);
+                }
+                tokio::time::sleep(interval).await;
+            };
+
+            tokio::select! {
+                _ = iteration => {}
+                result = &mut interrupted => {
+                    result.context("waiting for ctrl-c")?;
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Runs the selected `omdb sled-agent` subcommand once.
+    async fn run_subcommand(
+        &self,
+        client: &sled_agent_client::Client,
+    ) -> Result<(), anyhow::Error> {
         match &self.command {
             SledAgentCommands::Zones(ZoneCommands::List) => {
-                cmd_zones_list(&client).await
+                cmd_zones_list(client, self.retries).await
             }
             SledAgentCommands::Zpools(ZpoolCommands::List) => {
-                cmd_zpools_list(&client).await
+                cmd_zpools_list(client, self.retries).await
+            }
+            SledAgentCommands::Inventory(args) => {
+                cmd_inventory(client, self.retries, args).await
             }
         }
     }
 }
 
+/// Returns a sled agent client whose requests give up after `timeout`
+fn make_client(
+    sled_agent_url: &str,
+    timeout: Duration,
+    log: &slog::Logger,
+) -> Result<sled_agent_client::Client, anyhow::Error> {
+    let client = reqwest::ClientBuilder::new()
+        .connect_timeout(timeout)
+        .timeout(timeout)
+        .build()
+        .context("building HTTP client")?;
+    Ok(sled_agent_client::Client::new_with_client(
+        sled_agent_url,
+        client,
+        log.clone(),
+    ))
+}
+
+/// Backoff policy for sled agent requests that gives up after a fixed number
+/// of retries
+struct BoundedBackoff {
+    inner: ExponentialBackoff,
+    retries_left: u32,
+}
+
+impl BoundedBackoff {
+    fn new(retries: u32) -> BoundedBackoff {
+        BoundedBackoff { inner: retry_policy_local(), retries_left: retries }
+    }
+}
+
+impl Backoff for BoundedBackoff {
+    fn next_backoff(&mut self) -> Option<Duration> {
+        if self.retries_left == 0 {
+            return None;
+        }
+        self.retries_left -= 1;
+        self.inner.next_backoff()
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+/// Returns whether a failed sled agent request is worth retrying
+///
+/// Communication errors, including timeouts, and 5xx responses are usually
+/// transient (e.g., the sled agent is restarting, or briefly too busy to
+/// respond).  Anything else (like a 400 or 404) will fail the same way every
+/// time.
+fn is_retryable(
+    error: &sled_agent_client::Error<sled_agent_client::types::Error>,
+) -> bool {
+    match error {
+        sled_agent_client::Error::CommunicationError(_) => true,
+        _ => error.status().map_or(false, |status| status.is_server_error()),
+    }
+}
+
+/// Issues the sled agent request made by `request`, retrying transient
+/// failures up to `retries` times
+///
+/// `what` describes the request for error messages.
+async fn with_retries<'a, T, F, Fut>(
+    client: &'a sled_agent_client::Client,
+    retries: u32,
+    what: &str,
+    mut request: F,
+) -> Result<T, anyhow::Error>
+where
+    F: FnMut(&'a sled_agent_client::Client) -> Fut,
+    Fut: Future<
+        Output = Result<
+            ResponseValue<T>,
+            sled_agent_client::Error<sled_agent_client::types::Error>,
+        >,
+    >,
+{
+    retry_notify(
+        BoundedBackoff::new(retries),
+        || {
+            let response = request(client);
+            async move {
+                response.await.map(|response| response.into_inner()).map_err(
+                    |error| {
+                        if is_retryable(&error) {
+                            BackoffError::transient(error)
+                        } else {
+                            BackoffError::permanent(error)
+                        }
+                    },
+                )
+            }
+        },
+        |error, delay| {
+            eprintln!(
+                "warn: {} failed (retrying in {:?}): {}",
+                what, delay, error
+            );
+        },
+    )
+    .await
+    .map_err(|error| match error {
+        sled_agent_client::Error::CommunicationError(error)
+            if error.is_timeout() =>
+        {
+            anyhow!("timed out contacting sled agent at {}", client.baseurl())
+        }
+        error => anyhow::Error::new(error),
+    })
+    .with_context(|| what.to_string())
+}
+
 /// Runs `omdb sled-agent zones list`
 async fn cmd_zones_list(
     client: &sled_agent_client::Client,
+    retries: u32,
 ) -> Result<(), anyhow::Error> {
-    let response = client.zones_list().await.context("listing zones")?;
-    let zones = response.into_inner();
-    let zones: Vec<_> = zones.into_iter().collect();
+    print_zones(&list_zones(client, retries).await?);
+    Ok(())
+}
+
+async fn list_zones(
+    client: &sled_agent_client::Client,
+    retries: u32,
+) -> Result<Vec<String>, anyhow::Error> {
+    with_retries(client, retries, "listing zones", |client| client.zones_list())
+        .await
+}
 
+fn print_zones(zones: &[String]) {
     println!("zones:");
     if zones.is_empty() {
         println!("    <none>");
     }
-    for zone in &zones {
+    for zone in zones {
         println!("    {:?}", zone);
     }
-
-    Ok(())
 }
 
 /// Runs `omdb sled-agent zpools list`
 async fn cmd_zpools_list(
     client: &sled_agent_client::Client,
+    retries: u32,
 ) -> Result<(), anyhow::Error> {
-    let response = client.zpools_get().await.context("listing zpools")?;
-    let zpools = response.into_inner();
+    print_zpools(&list_zpools(client, retries).await?);
+    Ok(())
+}
+
+async fn list_zpools(
+    client: &sled_agent_client::Client,
+    retries: u32,
+) -> Result<Vec<Zpool>, anyhow::Error> {
+    with_retries(client, retries, "listing zpools", |client| {
+        client.zpools_get()
+    })
+    .await
+}
 
+fn print_zpools(zpools: &[Zpool]) {
     println!("zpools:");
     if zpools.is_empty() {
         println!("    <none>");
     }
-    for zpool in &zpools {
+    for zpool in zpools {
         println!("    {:?}", zpool);
     }
+}
+
+/// One section of the report printed by `omdb sled-agent inventory`
+///
+/// A section that couldn't be fetched records the error, rather than failing
+/// the whole report.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum InventorySection<T> {
+    Ok(T),
+    Error(String),
+}
+
+impl<T> From<Result<T, anyhow::Error>> for InventorySection<T> {
+    fn from(result: Result<T, anyhow::Error>) -> Self {
+        match result {
+            Ok(value) => InventorySection::Ok(value),
+            Err(error) => InventorySection::Error(format!("{:#}", error)),
+        }
+    }
+}
+
+impl<T> InventorySection<T> {
+    /// Prints this section under `title`, using `print` for its contents
+    fn print(&self, title: &str, print: impl FnOnce(&T)) {
+        match self {
+            InventorySection::Ok(value) => print(value),
+            InventorySection::Error(error) => {
+                println!("{}:", title);
+                println!("    error: {}", error);
+            }
+        }
+    }
+}
+
+/// The report printed by `omdb sled-agent inventory`, in the order its
+/// sections are printed
+#[derive(Serialize)]
+struct SledInventory {
+    sled_role: InventorySection<SledRole>,
+    timesync: InventorySection<TimeSync>,
+    zpools: InventorySection<Vec<Zpool>>,
+    zones: InventorySection<Vec<String>>,
+}
+
+/// Runs `omdb sled-agent inventory`
+async fn cmd_inventory(
+    client: &sled_agent_client::Client,
+    retries: u32,
+    args: &InventoryArgs,
+) -> Result<(), anyhow::Error> {
+    let sled_role =
+        with_retries(client, retries, "fetching sled role", |client| {
+            client.sled_role_get()
+        })
+        .await;
+    let timesync =
+        with_retries(client, retries, "fetching time sync status", |client| {
+            client.timesync_get()
+        })
+        .await;
+    let inventory = SledInventory {
+        sled_role: sled_role.into(),
+        timesync: timesync.into(),
+        zpools: list_zpools(client, retries).await.into(),
+        zones: list_zones(client, retries).await.into(),
+    };
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&inventory)
+            .context("serializing inventory")?;
+        println!("{}", json);
+        return Ok(());
+    }
+
+    inventory.sled_role.print("sled role", |role| {
+        println!("sled role: {:?}", role);
+    });
+    println!();
+    inventory.timesync.print("time sync", |timesync| {
+        println!("time sync:");
+        println!("    synced:     {}", timesync.sync);
+        println!("    stratum:    {}", timesync.stratum);
+        println!("    reference:  {}", timesync.ip_addr);
+        println!("    correction: {}", timesync.correction);
+    });
+    println!();
+    inventory.zpools.print("zpools", |zpools| print_zpools(zpools));
+    println!();
+    inventory.zones.print("zones", |zones| print_zones(zones));
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::list_zones;
+    use super::make_client;
+    use httptest::matchers::request;
+    use httptest::responders::delay_and_then;
+    use httptest::responders::status_code;
+    use httptest::Expectation;
+    use omicron_test_utils::dev::test_setup_log;
+    use std::time::Duration;
+
+    /// Returns a responder for an error with the given status code, with the
+    /// body a dropshot server would send
+    fn error_response(code: u16) -> impl httptest::responders::Responder {
+        status_code(code).body(
+            serde_json::json!({
+                "request_id": "test",
+                "message": "injected failure",
+            })
+            .to_string(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_transient_failure_is_retried() {
+        let logctx = test_setup_log("test_transient_failure_is_retried");
+        let server = httptest::Server::run();
+        let client = sled_agent_client::Client::new(
+            &format!("http://{}", server.addr()),
+            logctx.log.clone(),
+        );
+
+        // The first request fails as though the sled agent were restarting;
+        // the retry succeeds.
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/zones"))
+                .times(2)
+                .respond_with(httptest::cycle![
+                    error_response(503),
+                    status_code(200)
+                        .body(serde_json::json!(["oxz_ntp"]).to_string()),
+                ]),
+        );
+        let zones = list_zones(&client, 3).await.expect("listed zones");
+        assert_eq!(zones, vec![String::from("oxz_ntp")]);
+        server.verify_and_clear();
+
+        // With no retries left, the same failure is reported.
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/zones"))
+                .times(1)
+                .respond_with(error_response(503)),
+        );
+        list_zones(&client, 0).await.expect_err("failure was not reported");
+        server.verify_and_clear();
+
+        logctx.cleanup_successful();
+    }
+
+    #[tokio::test]
+    async fn test_client_error_is_not_retried() {
+        let logctx = test_setup_log("test_client_error_is_not_retried");
+        let server = httptest::Server::run();
+        let client = sled_agent_client::Client::new(
+            &format!("http://{}", server.addr()),
+            logctx.log.clone(),
+        );
+
+        // A 404 would fail the same way every time, so it's reported after
+        // a single request even though retries remain.
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/zones"))
+                .times(1)
+                .respond_with(error_response(404)),
+        );
+        list_zones(&client, 3).await.expect_err("failure was not reported");
+        server.verify_and_clear();
+
+        logctx.cleanup_successful();
+    }
+
+    #[tokio::test]
+    async fn test_timeout_is_retried() {
+        let logctx = test_setup_log("test_timeout_is_retried");
+        let server = httptest::Server::run();
+        let client = make_client(
+            &format!("http://{}", server.addr()),
+            Duration::from_secs(1),
+            &logctx.log,
+        )
+        .expect("made client");
+
+        // The first request isn't answered in time, as though the sled agent
+        // were briefly too busy; the retry succeeds.
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/zones"))
+                .times(2)
+                .respond_with(httptest::cycle![
+                    delay_and_then(Duration::from_secs(2), error_response(503)),
+                    status_code(200)
+                        .body(serde_json::json!(["oxz_ntp"]).to_string()),
+                ]),
+        );
+        let zones = list_zones(&client, 3).await.expect("listed zones");
+        assert_eq!(zones, vec![String::from("oxz_ntp")]);
+        server.verify_and_clear();
+
+        logctx.cleanup_successful();
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_sled_agent_times_out() {
+        let logctx = test_setup_log("test_unresponsive_sled_agent_times_out");
+
+        // The kernel completes connections to a listening socket, but since
+        // nothing accepts them, no request is ever answered.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bound listener");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let client = make_client(&url, Duration::from_secs(1), &logctx.log)
+            .expect("made client");
+
+        let error =
+            list_zones(&client, 1).await.expect_err("request did not fail");
+        let message = format!("{:#}", error);
+        assert!(
+            message.contains(&format!(
+                "timed out contacting sled agent at {}",
+                url
+            )),
+            "unexpected error: {}",
+            message
+        );
+
+        logctx.cleanup_successful();
+    }
+}
@@ -0,0 +1,262 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! omdb commands that query a specific wicketd instance
+
+use crate::Omdb;
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use clap::Args;
+use clap::Subcommand;
+use tabled::Tabled;
+use wicket_common::update_events::EventReport;
+use wicket_common::update_events::StepEventKind;
+use wicket_common::update_events::StepOutcome;
+use wicketd_client::types::GetArtifactsAndEventReportsParams;
+use wicketd_client::types::SpIdentifier;
+use wicketd_client::types::SpType;
+
+/// Arguments to the "omdb wicketd" subcommand
+#[derive(Debug, Args)]
+pub struct WicketdArgs {
+    /// URL of the wicketd internal API
+    #[clap(long, env("OMDB_WICKETD_URL"))]
+    wicketd_url: Option<String>,
+
+    #[command(subcommand)]
+    command: WicketdCommands,
+}
+
+/// Subcommands for the "omdb wicketd" subcommand
+#[derive(Debug, Subcommand)]
+enum WicketdCommands {
+    /// Print the step events of SP updates driven by wicketd
+    UpdateStatus(UpdateStatusArgs),
+}
+
+#[derive(Debug, Args)]
+struct UpdateStatusArgs {
+    /// only show the update for this SP (e.g., "sled:5" or "switch:0")
+    #[clap(long, value_parser = parse_sp_identifier)]
+    sp: Option<SpIdentifier>,
+
+    /// print the raw event reports as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+impl WicketdArgs {
+    /// Run a `omdb wicketd` subcommand.
+    pub(crate) async fn run_cmd(
+        &self,
+        _omdb: &Omdb,
+        log: &slog::Logger,
+    ) -> Result<(), anyhow::Error> {
+        // As with `omdb sled-agent`, the URL is required, but can come from
+        // the environment. wicketd isn't in internal DNS, so there's nothing
+        // to fall back to.
+        let Some(wicketd_url) = &self.wicketd_url else {
+            bail!(
+                "wicketd URL must be specified with --wicketd-url or \
+                OMDB_WICKETD_URL"
+            );
+        };
+        let client = wicketd_client::Client::new(wicketd_url, log.clone());
+
+        match &self.command {
+            WicketdCommands::UpdateStatus(args) => {
+                cmd_update_status(&client, args).await
+            }
+        }
+    }
+}
+
+/// Runs `omdb wicketd update-status`
+///
+/// Shows the step events for the update of a single SP, or of every SP that
+/// wicketd has update state for.
+async fn cmd_update_status(
+    client: &wicketd_client::Client,
+    args: &UpdateStatusArgs,
+) -> Result<(), anyhow::Error> {
+    let reports = match args.sp {
+        Some(sp) => {
+            let report = client
+                .get_update_sp(sp.type_, sp.slot)
+                .await
+                .with_context(|| {
+                    format!("fetching event report for {}", sp_to_string(&sp))
+                })?
+                .into_inner();
+            vec![(sp, report)]
+        }
+        None => {
+            let response = client
+                .get_artifacts_and_event_reports(
+                    &GetArtifactsAndEventReportsParams {
+                        last_seen: Default::default(),
+                    },
+                )
+                .await
+                .context("fetching event reports")?
+                .into_inner();
+            let mut reports = Vec::new();
+            for (sp_type, by_slot) in response.event_reports {
+                for (slot, report) in by_slot {
+                    let sp = parse_sp_identifier(&format!("{sp_type}:{slot}"))
+                        .map_err(|error| anyhow!(error))
+                        .context("parsing SP from wicketd response")?;
+                    reports.push((sp, report));
+                }
+            }
+            reports.sort_by(|(a, _), (b, _)| a.cmp(b));
+            reports
+        }
+    };
+
+    if args.json {
+        let reports: Vec<_> = reports
+            .iter()
+            .map(|(sp, report)| {
+                serde_json::json!({ "sp": sp, "event_report": report })
+            })
+            .collect();
+        let json = serde_json::to_string_pretty(&reports)
+            .context("serializing event reports")?;
+        println!("{json}");
+        return Ok(());
+    }
+
+    if reports.is_empty() {
+        println!("no update state found");
+    }
+    for (sp, report) in &reports {
+        println!("UPDATE FOR {}\n", sp_to_string(sp).to_uppercase());
+        show_step_events(report)?;
+        println!();
+    }
+
+    Ok(())
+}
+
+fn show_step_events(report: &EventReport) -> Result<(), anyhow::Error> {
+    #[derive(Tabled)]
+    #[tabled(rename_all = "SCREAMING_SNAKE_CASE")]
+    struct StepEventRow {
+        index: usize,
+        elapsed: String,
+        event: String,
+    }
+
+    if report.step_events.is_empty() {
+        println!("    <no step events>");
+        return Ok(());
+    }
+
+    let table_rows = report.step_events.iter().map(|event| StepEventRow {
+        index: event.event_index,
+        elapsed: format!("{:.1}s", event.total_elapsed.as_secs_f64()),
+        event: describe_step_event_kind(&event.kind),
+    });
+    let table = tabled::Table::new(table_rows)
+        .with(tabled::settings::Style::empty())
+        .with(tabled::settings::Padding::new(0, 1, 0, 0))
+        .to_string();
+    println!("{}", textwrap::indent(&table.to_string(), "    "));
+    Ok(())
+}
+
+fn describe_step_event_kind(kind: &StepEventKind) -> String {
+    match kind {
+        StepEventKind::NoStepsDefined => "no steps defined".to_string(),
+        StepEventKind::ExecutionStarted { steps, first_step, .. } => format!(
+            "started {} steps, beginning with {:?}",
+            steps.len(),
+            first_step.info.description
+        ),
+        StepEventKind::ProgressReset { step, attempt, message, .. } => {
+            format!(
+                "{:?} (attempt {attempt}): progress reset: {message}",
+                step.info.description
+            )
+        }
+        StepEventKind::AttemptRetry { step, next_attempt, message, .. } => {
+            format!(
+                "{:?}: retrying (attempt {next_attempt}): {message}",
+                step.info.description
+            )
+        }
+        StepEventKind::StepCompleted { step, outcome, .. } => format!(
+            "{:?}: {}",
+            step.info.description,
+            describe_outcome(outcome)
+        ),
+        StepEventKind::ExecutionCompleted {
+            last_step, last_outcome, ..
+        } => {
+            format!(
+                "{:?}: {}; update completed",
+                last_step.info.description,
+                describe_outcome(last_outcome)
+            )
+        }
+        StepEventKind::ExecutionFailed {
+            failed_step, message, causes, ..
+        } => {
+            let mut description = format!(
+                "{:?}: update failed: {message}",
+                failed_step.info.description
+            );
+            for cause in causes {
+                description.push_str(&format!(": {cause}"));
+            }
+            description
+        }
+        StepEventKind::ExecutionAborted { aborted_step, message, .. } => {
+            format!(
+                "{:?}: update aborted: {message}",
+                aborted_step.info.description
+            )
+        }
+        StepEventKind::Nested { step, .. } => {
+            format!("{:?}: nested step event", step.info.description)
+        }
+        StepEventKind::Unknown => "unknown event".to_string(),
+    }
+}
+
+fn describe_outcome(outcome: &StepOutcome) -> String {
+    match outcome {
+        StepOutcome::Success { message, .. } => match message {
+            Some(message) => format!("succeeded: {message}"),
+            None => "succeeded".to_string(),
+        },
+        StepOutcome::Warning { message, .. } => {
+            format!("succeeded with warning: {message}")
+        }
+        StepOutcome::Skipped { message, .. } => format!("skipped: {message}"),
+    }
+}
+
+/// Parses an SP identifier of the form `TYPE:SLOT` (e.g., `sled:5`), as
+/// accepted by `omdb mgs`.
+fn parse_sp_identifier(s: &str) -> Result<SpIdentifier, String> {
+    let sp = crate::parse_sp_identifier(s)?;
+    let type_ = match sp.type_ {
+        gateway_client::types::SpType::Sled => SpType::Sled,
+        gateway_client::types::SpType::Switch => SpType::Switch,
+        gateway_client::types::SpType::Power => SpType::Power,
+    };
+    Ok(SpIdentifier { type_, slot: sp.slot })
+}
+
+fn sp_to_string(sp: &SpIdentifier) -> String {
+    let type_ = match sp.type_ {
+        SpType::Sled => "sled",
+        SpType::Switch => "switch",
+        SpType::Power => "power",
+    };
+    format!("{type_} {}", sp.slot)
+}
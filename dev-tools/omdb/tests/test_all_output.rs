@@ -43,11 +43,14 @@ async fn test_omdb_usage_errors() {
         &["db", "services"],
         &["db", "network"],
         &["mgs"],
+        &["mgs", "reset-component"],
         &["nexus"],
         &["nexus", "background-tasks"],
         &["sled-agent"],
         &["sled-agent", "zones"],
         &["sled-agent", "zpools"],
+        &["wicketd"],
+        &["wicketd", "update-status", "--sp", "not-an-sp"],
     ];
 
     for args in invocations {
@@ -78,6 +81,7 @@ async fn test_omdb_success_cases(cptestctx: &ControlPlaneTestContext) {
         &["db", "services", "list-by-sled"],
         &["db", "sleds"],
         &["mgs", "inventory"],
+        &["mgs", "reset-component", "--dry-run", "sled:0", "rot"],
         &["nexus", "background-tasks", "doc"],
         &["nexus", "background-tasks", "show"],
         // We can't easily test the sled agent output because that's only
@@ -105,6 +105,17 @@ mod inner {
 
         Ok(output)
     }
+
+    // Helper function for starting a process without waiting for it, so that
+    // the caller can kill it if it takes too long.
+    pub fn spawn(
+        command: &mut std::process::Command,
+    ) -> Result<std::process::Child, ExecutionError> {
+        command.spawn().map_err(|err| ExecutionError::ExecutionStart {
+            command: to_string(command),
+            err,
+        })
+    }
 }
 
 cfg_if! {
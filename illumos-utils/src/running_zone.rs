@@ -383,28 +383,60 @@ mod zenter {
     }
 }
 
-/// Represents a running zone.
-pub struct RunningZone {
+/// A handle for running commands within a [`RunningZone`].
+#[derive(Clone)]
+#[cfg_attr(not(target_os = "illumos"), allow(dead_code))]
+pub struct ZoneCommandRunner {
     // The `zoneid_t` for the zone, while it's running, or `None` if not.
     id: Option<i32>,
-    inner: InstalledZone,
+    name: String,
+    log: Logger,
 }
 
-impl RunningZone {
+impl ZoneCommandRunner {
     pub fn name(&self) -> &str {
-        &self.inner.name
+        &self.name
     }
 
-    /// Returns the filesystem path to the zone's root
-    pub fn root(&self) -> Utf8PathBuf {
-        self.inner.zonepath.join("root")
+    /// Runs a command within the Zone, return the output.
+    //
+    // NOTE: It's important that this function is synchronous.
+    //
+    // See `ZoneCommandRunner::in_zone` for details.
+    pub fn run_cmd<I, S>(&self, args: I) -> Result<String, RunCommandError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.in_zone(args, |command| crate::execute(command))
+            .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
     }
 
-    pub fn control_interface(&self) -> AddrObject {
-        AddrObject::new(self.inner.get_control_vnic_name(), "omicron6").unwrap()
+    /// Starts a command within the Zone, without waiting for it to finish.
+    ///
+    /// The command's stdout and stderr are piped, and it's run in its own
+    /// process group, so that the caller can kill it and any children it has
+    /// started if it takes too long.
+    pub fn spawn_cmd<I, S>(
+        &self,
+        args: I,
+    ) -> Result<std::process::Child, RunCommandError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        use std::os::unix::process::CommandExt;
+        self.in_zone(args, |command| {
+            command
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::piped())
+                .process_group(0);
+            crate::spawn(command)
+        })
     }
 
-    /// Runs a command within the Zone, return the output.
+    // Build a command that runs within the Zone, and pass it to `f`, which
+    // starts it.
     //
     // NOTE: It's important that this function is synchronous.
     //
@@ -422,7 +454,13 @@ impl RunningZone {
     // `svccfg` directly in a forked child. That would obviate the need to work
     // on the contract at all.
     #[cfg(target_os = "illumos")]
-    pub fn run_cmd<I, S>(&self, args: I) -> Result<String, RunCommandError>
+    fn in_zone<I, S, T>(
+        &self,
+        args: I,
+        f: impl FnOnce(
+            &mut std::process::Command,
+        ) -> Result<T, crate::ExecutionError>,
+    ) -> Result<T, RunCommandError>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<std::ffi::OsStr>,
@@ -440,7 +478,7 @@ impl RunningZone {
             })?);
         let tmpl = std::sync::Arc::clone(&template);
         let mut command = std::process::Command::new(crate::PFEXEC);
-        let logger = self.inner.log.clone();
+        let logger = self.log.clone();
         let zone = self.name().to_string();
         command.env_clear();
         unsafe {
@@ -468,34 +506,79 @@ impl RunningZone {
 
         // Capture the result, and be sure to clear the template for this
         // process itself before returning.
-        let res = crate::execute(command).map_err(|err| RunCommandError {
+        let res = f(command).map_err(|err| RunCommandError {
             zone: self.name().to_string(),
             err,
         });
         template.clear();
-
-        res.map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+        res
     }
 
-    /// Runs a command within the Zone, return the output.
     #[cfg(not(target_os = "illumos"))]
-    pub fn run_cmd<I, S>(&self, args: I) -> Result<String, RunCommandError>
+    fn in_zone<I, S, T>(
+        &self,
+        args: I,
+        f: impl FnOnce(
+            &mut std::process::Command,
+        ) -> Result<T, crate::ExecutionError>,
+    ) -> Result<T, RunCommandError>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<std::ffi::OsStr>,
     {
         // NOTE: This implementation is useless, and will never work. However,
-        // it must actually call `crate::execute()` for the testing purposes.
-        // That's mocked by `mockall` to return known data, and so the command
-        // that's actually run is irrelevant.
+        // `f` must actually call `crate::execute()` or `crate::spawn()` for
+        // testing purposes. Those are mocked by `mockall` to return known data,
+        // and so the command that's actually run is irrelevant.
         let mut command = std::process::Command::new("echo");
         let command = command.args(args);
-        crate::execute(command)
-            .map_err(|err| RunCommandError {
-                zone: self.name().to_string(),
-                err,
-            })
-            .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+        f(command).map_err(|err| RunCommandError {
+            zone: self.name().to_string(),
+            err,
+        })
+    }
+}
+
+/// Represents a running zone.
+pub struct RunningZone {
+    // The `zoneid_t` for the zone, while it's running, or `None` if not.
+    id: Option<i32>,
+    inner: InstalledZone,
+}
+
+impl RunningZone {
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// Returns the filesystem path to the zone's root
+    pub fn root(&self) -> Utf8PathBuf {
+        self.inner.zonepath.join("root")
+    }
+
+    pub fn control_interface(&self) -> AddrObject {
+        AddrObject::new(self.inner.get_control_vnic_name(), "omicron6").unwrap()
+    }
+
+    /// Runs a command within the Zone, return the output.
+    ///
+    /// See [`ZoneCommandRunner::run_cmd`].
+    pub fn run_cmd<I, S>(&self, args: I) -> Result<String, RunCommandError>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<std::ffi::OsStr>,
+    {
+        self.command_runner().run_cmd(args)
+    }
+
+    /// Returns a handle for running commands within the Zone, which doesn't
+    /// borrow the zone itself (e.g., to run a command on another thread).
+    pub fn command_runner(&self) -> ZoneCommandRunner {
+        ZoneCommandRunner {
+            id: self.id,
+            name: self.name().to_string(),
+            log: self.inner.log.clone(),
+        }
     }
 
     /// Boots a new zone.
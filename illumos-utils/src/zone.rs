@@ -416,6 +416,30 @@ impl Zones {
         }
     }
 
+    /// Return the names of all running zones that may be managed by the Sled
+    /// Agent.
+    //
+    // NOTE: As with `Zones::id()`, this exists so that callers can be tested
+    // by supplying `mockall` with a value to return.
+    pub async fn running_names() -> Result<Vec<String>, AdmError> {
+        Ok(Self::get()
+            .await?
+            .into_iter()
+            .filter(|zone| matches!(zone.state(), zone::State::Running))
+            .map(|zone| zone.name().to_string())
+            .collect())
+    }
+
+    /// Return the zonepath for a zone with the specified name, in any state.
+    //
+    // NOTE: As with `Zones::id()`, this exists so that callers can be tested
+    // by supplying `mockall` with a value to return.
+    pub async fn zonepath(
+        name: &str,
+    ) -> Result<Option<std::path::PathBuf>, AdmError> {
+        Ok(Self::find(name).await?.map(|zn| zn.path().to_path_buf()))
+    }
+
     /// Returns the name of the VNIC used to communicate with the control plane.
     pub fn get_control_interface(
         zone: &str,
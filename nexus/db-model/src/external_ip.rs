@@ -7,6 +7,7 @@
 
 use crate::impl_enum_type;
 use crate::schema::external_ip;
+use crate::IpPool;
 use crate::Name;
 use crate::SqlU16;
 use chrono::DateTime;
@@ -16,6 +17,7 @@ use diesel::Selectable;
 use ipnetwork::IpNetwork;
 use nexus_types::external_api::shared;
 use nexus_types::external_api::views;
+use nexus_types::identity::Resource;
 use omicron_common::address::NUM_SOURCE_NAT_PORTS;
 use omicron_common::api::external::Error;
 use std::convert::TryFrom;
@@ -295,6 +297,34 @@ impl TryFrom<IpKind> for shared::IpKind {
     }
 }
 
+impl From<IpKind> for shared::ExternalIpKind {
+    fn from(kind: IpKind) -> Self {
+        match kind {
+            IpKind::SNat => shared::ExternalIpKind::Snat,
+            IpKind::Ephemeral => shared::ExternalIpKind::Ephemeral,
+            IpKind::Floating => shared::ExternalIpKind::Floating,
+        }
+    }
+}
+
+impl TryFrom<(ExternalIp, IpPool)> for views::ExternalIpInfo {
+    type Error = Error;
+
+    fn try_from((ip, pool): (ExternalIp, IpPool)) -> Result<Self, Self::Error> {
+        if ip.is_service {
+            return Err(Error::internal_error(
+                "Service IPs should not be exposed in the API",
+            ));
+        }
+        Ok(views::ExternalIpInfo {
+            ip: ip.ip.ip(),
+            kind: ip.kind.into(),
+            ip_pool_id: pool.id(),
+            ip_pool_name: pool.name().clone(),
+        })
+    }
+}
+
 impl TryFrom<ExternalIp> for views::ExternalIp {
     type Error = Error;
 
@@ -1179,6 +1179,9 @@ allow_tables_to_appear_in_same_query!(
 
 allow_tables_to_appear_in_same_query!(dns_zone, dns_version, dns_name);
 allow_tables_to_appear_in_same_query!(external_ip, service);
+allow_tables_to_appear_in_same_query!(external_ip, instance);
+allow_tables_to_appear_in_same_query!(external_ip, ip_pool);
+joinable!(external_ip -> ip_pool (ip_pool_id));
 
 allow_tables_to_appear_in_same_query!(
     switch_port,
@@ -11,27 +11,37 @@ use crate::context::OpContext;
 use crate::db;
 use crate::db::error::public_error_from_diesel;
 use crate::db::error::ErrorHandler;
+use crate::db::error::TransactionError;
 use crate::db::lookup::LookupPath;
 use crate::db::model::ExternalIp;
 use crate::db::model::IncompleteExternalIp;
+use crate::db::model::InstanceState;
 use crate::db::model::IpKind;
+use crate::db::model::IpPool;
 use crate::db::model::Name;
 use crate::db::pool::DbConnection;
 use crate::db::queries::external_ip::NextExternalIp;
 use crate::db::update_and_check::UpdateAndCheck;
 use crate::db::update_and_check::UpdateStatus;
 use async_bb8_diesel::AsyncRunQueryDsl;
+use chrono::DateTime;
 use chrono::Utc;
 use diesel::prelude::*;
 use nexus_types::identity::Resource;
+use omicron_common::api::external;
 use omicron_common::api::external::CreateResult;
 use omicron_common::api::external::Error;
+use omicron_common::api::external::ListResultVec;
 use omicron_common::api::external::LookupResult;
 use std::net::IpAddr;
 use uuid::Uuid;
 
 impl DataStore {
     /// Create an external IP address for source NAT for an instance.
+    ///
+    /// `ip_id` is the ID of the new record and also serves as the request's
+    /// idempotency key: repeating the call with the same ID returns the
+    /// address already allocated under it rather than allocating another.
     pub async fn allocate_instance_snat_ip(
         &self,
         opctx: &OpContext,
@@ -48,6 +58,10 @@ impl DataStore {
     }
 
     /// Create an Ephemeral IP address for an instance.
+    ///
+    /// As with [`Self::allocate_instance_snat_ip`], `ip_id` is an idempotency
+    /// key. Sagas should generate it once, outside the action that calls this,
+    /// so that replaying the action returns the original address.
     pub async fn allocate_instance_ephemeral_ip(
         &self,
         opctx: &OpContext,
@@ -55,6 +69,98 @@ impl DataStore {
         instance_id: Uuid,
         pool_name: Option<Name>,
     ) -> CreateResult<ExternalIp> {
+        let pool = self.ephemeral_ip_pool(opctx, pool_name).await?;
+        let pool_id = pool.identity.id;
+        let data =
+            IncompleteExternalIp::for_ephemeral(ip_id, instance_id, pool_id);
+        self.allocate_external_ip(opctx, data).await
+    }
+
+    /// Attach a new Ephemeral IP address to an existing, stopped instance.
+    ///
+    /// The instance's state and the number of external IP addresses it
+    /// already has, other than its SNAT address, are checked in the same
+    /// transaction as the allocation. Concurrent requests therefore can't
+    /// together give the instance more than `max_external_ips` addresses, or
+    /// attach one as the instance starts.
+    pub async fn instance_attach_ephemeral_ip(
+        &self,
+        opctx: &OpContext,
+        ip_id: Uuid,
+        instance_id: Uuid,
+        pool_name: Option<Name>,
+        max_external_ips: usize,
+    ) -> CreateResult<ExternalIp> {
+        use db::schema::external_ip::dsl;
+        use db::schema::instance::dsl as instance_dsl;
+
+        let pool = self.ephemeral_ip_pool(opctx, pool_name).await?;
+        let data = IncompleteExternalIp::for_ephemeral(
+            ip_id,
+            instance_id,
+            pool.identity.id,
+        );
+
+        type TxnError = TransactionError<Error>;
+        self.pool_connection_authorized(opctx)
+            .await?
+            .transaction_async(|conn| async move {
+                let state: InstanceState = instance_dsl::instance
+                    .filter(instance_dsl::id.eq(instance_id))
+                    .filter(instance_dsl::time_deleted.is_null())
+                    .select(instance_dsl::state)
+                    .get_result_async(&conn)
+                    .await?;
+                if state.0 != external::InstanceState::Stopped {
+                    return Err(TxnError::CustomError(Error::invalid_request(
+                        "Instance must be stopped to attach or detach an \
+                        ephemeral IP",
+                    )));
+                }
+
+                let attached: i64 = dsl::external_ip
+                    .filter(dsl::is_service.eq(false))
+                    .filter(dsl::parent_id.eq(instance_id))
+                    .filter(dsl::time_deleted.is_null())
+                    .filter(dsl::kind.ne(IpKind::SNat))
+                    .count()
+                    .get_result_async(&conn)
+                    .await?;
+                if usize::try_from(attached).unwrap_or(usize::MAX)
+                    >= max_external_ips
+                {
+                    return Err(TxnError::CustomError(Error::invalid_request(
+                        &format!(
+                            "An instance may not have more than {} external \
+                            IP addresses",
+                            max_external_ips,
+                        ),
+                    )));
+                }
+
+                Ok(Self::allocate_external_ip_on_connection(&conn, data)
+                    .await?)
+            })
+            .await
+            .map_err(|e| match e {
+                TxnError::CustomError(e) => e,
+                e if e.retry_transaction() => Error::unavail(
+                    "Instance external IPs were changed concurrently; \
+                    retry the request",
+                ),
+                TxnError::Connection(e) => {
+                    public_error_from_diesel(e, ErrorHandler::Server)
+                }
+            })
+    }
+
+    /// Look up the IP pool from which an Ephemeral IP address is allocated,
+    /// by name if one is provided, or the default pool otherwise.
+    async fn ephemeral_ip_pool(
+        &self,
+        opctx: &OpContext,
+        pool_name: Option<Name>,
+    ) -> LookupResult<IpPool> {
         let pool = match pool_name {
             Some(name) => {
                 let (.., authz_pool, pool) = LookupPath::new(opctx, &self)
@@ -81,11 +187,7 @@ impl DataStore {
             // If no name given, use the default logic
             None => self.ip_pools_fetch_default(&opctx).await?,
         };
-
-        let pool_id = pool.identity.id;
-        let data =
-            IncompleteExternalIp::for_ephemeral(ip_id, instance_id, pool_id);
-        self.allocate_external_ip(opctx, data).await
+        Ok(pool)
     }
 
     /// Allocates an IP address for internal service usage.
@@ -240,6 +342,47 @@ impl DataStore {
             .map_err(|e| public_error_from_diesel(e, ErrorHandler::Server))
     }
 
+    /// Release instance external IP addresses that no longer belong to a live
+    /// instance.
+    ///
+    /// An address is orphaned if its instance doesn't exist or has been
+    /// deleted, which can happen if a saga fails after allocating the address
+    /// and then fails to unwind. Both the allocation and any instance deletion
+    /// must predate `cutoff`, so that this doesn't race with sagas that are
+    /// still allocating or releasing addresses.
+    ///
+    /// Returns the records that were released.
+    pub async fn deallocate_orphaned_instance_external_ips(
+        &self,
+        opctx: &OpContext,
+        cutoff: DateTime<Utc>,
+    ) -> ListResultVec<ExternalIp> {
+        opctx.authorize(authz::Action::Modify, &authz::FLEET).await?;
+
+        use db::schema::external_ip::dsl;
+        use db::schema::instance::dsl as instance_dsl;
+        let live_instance = instance_dsl::instance
+            .filter(instance_dsl::id.nullable().eq(dsl::parent_id))
+            .filter(
+                instance_dsl::time_deleted
+                    .is_null()
+                    .or(instance_dsl::time_deleted.ge(cutoff)),
+            );
+        let now = Utc::now();
+        diesel::update(dsl::external_ip)
+            .filter(dsl::time_deleted.is_null())
+            .filter(dsl::is_service.eq(false))
+            // Floating IPs legitimately exist without an instance.
+            .filter(dsl::kind.ne(IpKind::Floating))
+            .filter(dsl::time_created.lt(cutoff))
+            .filter(diesel::dsl::not(diesel::dsl::exists(live_instance)))
+            .set(dsl::time_deleted.eq(now))
+            .returning(ExternalIp::as_returning())
+            .get_results_async(&*self.pool_connection_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel(e, ErrorHandler::Server))
+    }
+
     /// Delete all external IP addresses associated with the provided instance
     /// ID.
     ///
@@ -282,4 +425,26 @@ impl DataStore {
             .await
             .map_err(|e| public_error_from_diesel(e, ErrorHandler::Server))
     }
+
+    /// Fetch all external IP addresses of any kind (including the SNAT IP)
+    /// for the provided instance, along with the IP pool each was allocated
+    /// from.
+    pub async fn instance_lookup_external_ips_with_pools(
+        &self,
+        opctx: &OpContext,
+        instance_id: Uuid,
+    ) -> ListResultVec<(ExternalIp, IpPool)> {
+        use db::schema::external_ip::dsl;
+        use db::schema::ip_pool;
+        dsl::external_ip
+            .inner_join(ip_pool::table)
+            .filter(dsl::is_service.eq(false))
+            .filter(dsl::parent_id.eq(instance_id))
+            .filter(dsl::time_deleted.is_null())
+            .order_by(dsl::ip)
+            .select((ExternalIp::as_select(), IpPool::as_select()))
+            .get_results_async(&*self.pool_connection_authorized(opctx).await?)
+            .await
+            .map_err(|e| public_error_from_diesel(e, ErrorHandler::Server))
+    }
 }
@@ -19,6 +19,8 @@ use async_bb8_diesel::AsyncConnection;
 use async_bb8_diesel::AsyncRunQueryDsl;
 use chrono::Utc;
 use diesel::prelude::*;
+use nexus_types::external_api::params::SledListFilter;
+use nexus_types::external_api::params::SledRole;
 use omicron_common::api::external;
 use omicron_common::api::external::CreateResult;
 use omicron_common::api::external::DataPageParams;
@@ -63,10 +65,35 @@ impl DataStore {
         &self,
         opctx: &OpContext,
         pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<Sled> {
+        self.sled_list_filtered(opctx, &SledListFilter::default(), pagparams)
+            .await
+    }
+
+    /// Lists the sleds matching `filter`.
+    ///
+    /// The filter is applied by the database (not after loading each page), so
+    /// every page is full unless it's the last one.
+    pub async fn sled_list_filtered(
+        &self,
+        opctx: &OpContext,
+        filter: &SledListFilter,
+        pagparams: &DataPageParams<'_, Uuid>,
     ) -> ListResultVec<Sled> {
         opctx.authorize(authz::Action::ListChildren, &authz::FLEET).await?;
         use db::schema::sled::dsl;
-        paginated(dsl::sled, dsl::id, pagparams)
+        let mut query = paginated(dsl::sled, dsl::id, pagparams);
+        if let Some(rack_id) = filter.rack_id {
+            query = query.filter(dsl::rack_id.eq(rack_id));
+        }
+        if let Some(role) = filter.role {
+            let is_scrimlet = match role {
+                SledRole::Gimlet => false,
+                SledRole::Scrimlet => true,
+            };
+            query = query.filter(dsl::is_scrimlet.eq(is_scrimlet));
+        }
+        query
             .select(Sled::as_select())
             .load_async(&*self.pool_connection_authorized(opctx).await?)
             .await
@@ -1696,6 +1696,62 @@ mod tests {
         context.success().await;
     }
 
+    #[tokio::test]
+    async fn test_insert_ephemeral_ip_is_idempotent() {
+        let context =
+            TestContext::new("test_insert_ephemeral_ip_is_idempotent").await;
+
+        // Create a pool with exactly one address, so that a second allocation
+        // could only succeed by returning the first.
+        let range = IpRange::try_from((
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 1),
+        ))
+        .unwrap();
+        context.initialize_ip_pool("default", range).await;
+
+        // Allocate the same Ephemeral IP twice, as a replayed saga node would.
+        let instance_id = Uuid::new_v4();
+        let id = Uuid::new_v4();
+        let ip = context
+            .db_datastore
+            .allocate_instance_ephemeral_ip(
+                &context.opctx,
+                id,
+                instance_id,
+                /* pool_name = */ None,
+            )
+            .await
+            .expect("Failed to allocate Ephemeral IP address");
+        let new_ip = context
+            .db_datastore
+            .allocate_instance_ephemeral_ip(
+                &context.opctx,
+                id,
+                instance_id,
+                /* pool_name = */ None,
+            )
+            .await
+            .expect("Failed to replay Ephemeral IP address allocation");
+        assert_eq!(ip.id, new_ip.id);
+        assert_eq!(ip.kind, IpKind::Ephemeral);
+        assert_eq!(ip.kind, new_ip.kind);
+        assert_eq!(ip.ip, new_ip.ip);
+        assert_eq!(ip.first_port, new_ip.first_port);
+        assert_eq!(ip.last_port, new_ip.last_port);
+
+        // Only one address was actually allocated to the instance.
+        let ips = context
+            .db_datastore
+            .instance_lookup_external_ips(&context.opctx, instance_id)
+            .await
+            .expect("Failed to look up instance external IPs");
+        assert_eq!(ips.len(), 1);
+        assert_eq!(ips[0].id, id);
+
+        context.success().await;
+    }
+
     #[tokio::test]
     async fn test_next_external_ip_is_restricted_to_pools() {
         let context =
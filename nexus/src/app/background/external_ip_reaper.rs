@@ -0,0 +1,176 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Background task for releasing instance external IPs that were orphaned,
+//! e.g., by a saga that allocated an address and then failed to unwind
+
+use super::common::BackgroundTask;
+use chrono::Utc;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use nexus_db_queries::context::OpContext;
+use nexus_db_queries::db::DataStore;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Background task that releases orphaned instance external IPs back to their
+/// pools
+pub struct ExternalIpReaper {
+    datastore: Arc<DataStore>,
+    grace_period: Duration,
+}
+
+impl ExternalIpReaper {
+    pub fn new(
+        datastore: Arc<DataStore>,
+        grace_period: Duration,
+    ) -> ExternalIpReaper {
+        ExternalIpReaper { datastore, grace_period }
+    }
+}
+
+impl BackgroundTask for ExternalIpReaper {
+    fn activate<'a, 'b, 'c>(
+        &'a mut self,
+        opctx: &'b OpContext,
+    ) -> BoxFuture<'c, serde_json::Value>
+    where
+        'a: 'c,
+        'b: 'c,
+    {
+        async {
+            let log = &opctx.log;
+
+            let grace_period =
+                match chrono::Duration::from_std(self.grace_period) {
+                    Ok(grace_period) => grace_period,
+                    Err(error) => {
+                        return json!({
+                            "error":
+                                format!("invalid grace period: {:#}", error)
+                        });
+                    }
+                };
+            let cutoff = Utc::now() - grace_period;
+
+            let result = self
+                .datastore
+                .deallocate_orphaned_instance_external_ips(opctx, cutoff)
+                .await;
+
+            match result {
+                Err(error) => {
+                    warn!(
+                        &log,
+                        "failed to release orphaned external IPs";
+                        "error" => format!("{:#}", error)
+                    );
+                    json!({
+                        "error":
+                            format!(
+                                "failed to release orphaned external IPs: \
+                                {:#}",
+                                error
+                            )
+                    })
+                }
+                Ok(released) => {
+                    for ip in &released {
+                        info!(
+                            &log,
+                            "released orphaned external IP";
+                            "id" => %ip.id,
+                            "ip" => %ip.ip,
+                            "instance_id" => ?ip.parent_id,
+                        );
+                    }
+                    json!({ "released": released.len() })
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ExternalIpReaper;
+    use crate::app::background::common::BackgroundTask;
+    use nexus_db_queries::context::OpContext;
+    use nexus_test_utils::resource_helpers::create_instance;
+    use nexus_test_utils::resource_helpers::create_project;
+    use nexus_test_utils::resource_helpers::populate_ip_pool;
+    use nexus_test_utils_macros::nexus_test;
+    use serde_json::json;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    type ControlPlaneTestContext =
+        nexus_test_utils::ControlPlaneTestContext<crate::Server>;
+
+    #[nexus_test(server = crate::Server)]
+    async fn test_orphaned_ip_is_released_after_grace_period(
+        cptestctx: &ControlPlaneTestContext,
+    ) {
+        let client = &cptestctx.external_client;
+        let nexus = &cptestctx.server.apictx().nexus;
+        let datastore = nexus.datastore();
+        let opctx = OpContext::for_tests(
+            cptestctx.logctx.log.clone(),
+            datastore.clone(),
+        );
+
+        // Create a live instance, which holds a SNAT address that must never
+        // be reaped.
+        populate_ip_pool(client, "default", None).await;
+        create_project(client, "reaper-project").await;
+        let instance =
+            create_instance(client, "reaper-project", "reaper-instance").await;
+
+        // Allocate an address for an instance that doesn't exist, as a saga
+        // that failed to unwind would leave behind.
+        let orphan_instance_id = Uuid::new_v4();
+        datastore
+            .allocate_instance_ephemeral_ip(
+                &opctx,
+                Uuid::new_v4(),
+                orphan_instance_id,
+                /* pool_name = */ None,
+            )
+            .await
+            .expect("Failed to allocate orphaned IP");
+
+        // Within the grace period, the orphan is indistinguishable from an
+        // in-flight allocation and must be left alone.
+        let grace_period = Duration::from_secs(1);
+        let mut task = ExternalIpReaper::new(datastore.clone(), grace_period);
+        let value = task.activate(&opctx).await;
+        assert_eq!(value, json!({ "released": 0 }));
+        let orphan_ips = datastore
+            .instance_lookup_external_ips(&opctx, orphan_instance_id)
+            .await
+            .unwrap();
+        assert_eq!(orphan_ips.len(), 1);
+
+        // Once the grace period passes, only the orphan is released.
+        tokio::time::sleep(grace_period).await;
+        let value = task.activate(&opctx).await;
+        assert_eq!(value, json!({ "released": 1 }));
+        let orphan_ips = datastore
+            .instance_lookup_external_ips(&opctx, orphan_instance_id)
+            .await
+            .unwrap();
+        assert!(orphan_ips.is_empty());
+        let live_ips = datastore
+            .instance_lookup_external_ips(&opctx, instance.identity.id)
+            .await
+            .unwrap();
+        assert_eq!(live_ips.len(), 1);
+
+        // Nothing is left to release.
+        let value = task.activate(&opctx).await;
+        assert_eq!(value, json!({ "released": 0 }));
+    }
+}
@@ -9,6 +9,7 @@ use super::dns_config;
 use super::dns_propagation;
 use super::dns_servers;
 use super::external_endpoints;
+use super::external_ip_reaper;
 use nexus_db_model::DnsGroup;
 use nexus_db_queries::context::OpContext;
 use nexus_db_queries::db::DataStore;
@@ -42,6 +43,9 @@ pub struct BackgroundTasks {
     pub external_endpoints: tokio::sync::watch::Receiver<
         Option<external_endpoints::ExternalEndpoints>,
     >,
+
+    /// task handle for the task that releases orphaned external IPs
+    pub task_external_ip_reaper: common::TaskHandle,
 }
 
 impl BackgroundTasks {
@@ -70,8 +74,9 @@ impl BackgroundTasks {
 
         // Background task: External endpoints list watcher
         let (task_external_endpoints, external_endpoints) = {
-            let watcher =
-                external_endpoints::ExternalEndpointsWatcher::new(datastore);
+            let watcher = external_endpoints::ExternalEndpointsWatcher::new(
+                datastore.clone(),
+            );
             let watcher_channel = watcher.watcher();
             let task = driver.register(
                 String::from("external_endpoints"),
@@ -88,6 +93,23 @@ impl BackgroundTasks {
             (task, watcher_channel)
         };
 
+        // Background task: orphaned external IP reaper
+        let task_external_ip_reaper = driver.register(
+            String::from("external_ip_reaper"),
+            String::from(
+                "releases instance external IPs whose instance no longer \
+                exists (e.g., because a saga failed to unwind) back to their \
+                IP pools",
+            ),
+            config.external_ip_reaper.period_secs,
+            Box::new(external_ip_reaper::ExternalIpReaper::new(
+                datastore,
+                config.external_ip_reaper.grace_period_secs,
+            )),
+            opctx.child(BTreeMap::new()),
+            vec![],
+        );
+
         BackgroundTasks {
             driver,
             task_internal_dns_config,
@@ -96,6 +118,7 @@ impl BackgroundTasks {
             task_external_dns_servers,
             task_external_endpoints,
             external_endpoints,
+            task_external_ip_reaper,
         }
     }
 
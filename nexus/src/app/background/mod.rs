@@ -9,6 +9,7 @@ mod dns_config;
 mod dns_propagation;
 mod dns_servers;
 mod external_endpoints;
+mod external_ip_reaper;
 mod init;
 mod status;
 
@@ -4,12 +4,22 @@
 
 //! External IP addresses for instances
 
+use super::MAX_EXTERNAL_IPS_PER_INSTANCE;
+use crate::external_api::params;
 use crate::external_api::views::ExternalIp;
+use crate::external_api::views::ExternalIpInfo;
 use nexus_db_queries::authz;
 use nexus_db_queries::context::OpContext;
+use nexus_db_queries::db;
+use nexus_db_queries::db::identity::Resource;
 use nexus_db_queries::db::lookup;
 use nexus_db_queries::db::model::IpKind;
+use omicron_common::api::external::DeleteResult;
+use omicron_common::api::external::Error;
+use omicron_common::api::external::InstanceState;
 use omicron_common::api::external::ListResultVec;
+use omicron_common::api::external::UpdateResult;
+use uuid::Uuid;
 
 impl super::Nexus {
     pub(crate) async fn instance_list_external_ips(
@@ -33,4 +43,101 @@ impl super::Nexus {
             })
             .collect::<Vec<_>>())
     }
+
+    /// Lists every external IP address held by an instance, including its
+    /// SNAT address, along with the IP pool each was allocated from.
+    pub(crate) async fn instance_external_ips(
+        &self,
+        opctx: &OpContext,
+        instance_lookup: &lookup::Instance<'_>,
+    ) -> ListResultVec<ExternalIpInfo> {
+        let (.., authz_instance) =
+            instance_lookup.lookup_for(authz::Action::Read).await?;
+        self.db_datastore
+            .instance_lookup_external_ips_with_pools(opctx, authz_instance.id())
+            .await?
+            .into_iter()
+            .map(ExternalIpInfo::try_from)
+            .collect()
+    }
+
+    /// Allocates an ephemeral IP address for an existing instance.
+    ///
+    /// The instance must be stopped. The address is given to the instance's
+    /// network interface the next time it starts. See
+    /// `check_external_ip_change_allowed` for running instances.
+    pub(crate) async fn instance_attach_ephemeral_ip(
+        &self,
+        opctx: &OpContext,
+        instance_lookup: &lookup::Instance<'_>,
+        params: &params::EphemeralIpCreate,
+    ) -> UpdateResult<ExternalIp> {
+        let (.., authz_instance, db_instance) =
+            instance_lookup.fetch_for(authz::Action::Modify).await?;
+        check_external_ip_change_allowed(&db_instance)?;
+
+        // The datastore checks the instance's state and its number of
+        // addresses again, in the same transaction as the allocation, since
+        // either may have changed since we looked.
+        let pool_name =
+            params.pool_name.as_ref().map(|name| db::model::Name(name.clone()));
+        self.db_datastore
+            .instance_attach_ephemeral_ip(
+                opctx,
+                Uuid::new_v4(),
+                authz_instance.id(),
+                pool_name,
+                MAX_EXTERNAL_IPS_PER_INSTANCE,
+            )
+            .await?
+            .try_into()
+    }
+
+    /// Releases a stopped instance's ephemeral IP address.
+    pub(crate) async fn instance_detach_ephemeral_ip(
+        &self,
+        opctx: &OpContext,
+        instance_lookup: &lookup::Instance<'_>,
+    ) -> DeleteResult {
+        let (.., authz_instance, db_instance) =
+            instance_lookup.fetch_for(authz::Action::Modify).await?;
+        check_external_ip_change_allowed(&db_instance)?;
+
+        let ip = self
+            .db_datastore
+            .instance_lookup_external_ips(opctx, authz_instance.id())
+            .await?
+            .into_iter()
+            .find(|ip| ip.kind == IpKind::Ephemeral)
+            .ok_or_else(|| {
+                Error::invalid_request(
+                    "instance does not have an ephemeral IP address",
+                )
+            })?;
+        self.db_datastore.deallocate_external_ip(opctx, ip.id).await?;
+        Ok(())
+    }
+}
+
+/// Checks whether an instance's ephemeral IP can be changed in its current
+/// state.
+///
+/// OPTE ports are configured with their external IPs when they're created,
+/// and the OPTE version in use can't change them on a live port. Only stopped
+/// instances, which have no port and can't be migrating, can have their
+/// addresses changed.
+///
+/// TODO: Support running instances. That needs an OPTE that can update a live
+/// port's external IPs, a sled agent endpoint to apply them, NAT entries on
+/// the boundary switches updated to match, and a way to hold off or follow a
+/// migration while the change is made.
+fn check_external_ip_change_allowed(
+    db_instance: &db::model::Instance,
+) -> Result<(), Error> {
+    if db_instance.runtime().state.0 != InstanceState::Stopped {
+        return Err(Error::invalid_request(
+            "Instance must be stopped to attach or detach an ephemeral IP",
+        ));
+    }
+    Ok(())
 }
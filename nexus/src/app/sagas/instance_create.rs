@@ -877,6 +877,9 @@ async fn sic_allocate_instance_external_ip(
         &saga_params.serialized_authn,
     );
     let instance_id = repeat_saga_params.instance_id;
+    // This ID is fixed when the saga DAG is built, so it acts as the
+    // allocation's idempotency key: if this action is replayed, the datastore
+    // returns the address it already allocated under this ID.
     let ip_id = repeat_saga_params.new_id;
 
     // Collect the possible pool name for this IP address
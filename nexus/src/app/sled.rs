@@ -4,6 +4,7 @@
 
 //! Sleds, and the hardware and services within them.
 
+use crate::external_api::params;
 use crate::internal_api::params::{
     PhysicalDiskDeleteRequest, PhysicalDiskPutRequest, SledAgentStartupInfo,
     SledRole, ZpoolPutRequest,
@@ -84,6 +85,15 @@ impl super::Nexus {
         self.db_datastore.sled_list(&opctx, pagparams).await
     }
 
+    pub(crate) async fn sled_list_filtered(
+        &self,
+        opctx: &OpContext,
+        filter: &params::SledListFilter,
+        pagparams: &DataPageParams<'_, Uuid>,
+    ) -> ListResultVec<db::model::Sled> {
+        self.db_datastore.sled_list_filtered(&opctx, filter, pagparams).await
+    }
+
     pub async fn sled_client(
         &self,
         id: &Uuid,
@@ -190,6 +190,9 @@ pub(crate) fn external_api() -> NexusApiDescription {
         api.register(instance_network_interface_delete)?;
 
         api.register(instance_external_ip_list)?;
+        api.register(instance_external_ip_info_list)?;
+        api.register(instance_ephemeral_ip_attach)?;
+        api.register(instance_ephemeral_ip_detach)?;
 
         api.register(vpc_router_list)?;
         api.register(vpc_router_view)?;
@@ -3235,6 +3238,108 @@ async fn instance_external_ip_list(
     apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
 }
 
+/// List all external IP addresses, including source NAT
+///
+/// Unlike listing external IP addresses, this includes the address the
+/// instance uses for source NAT, and reports the IP pool each address was
+/// allocated from.
+#[endpoint {
+    method = GET,
+    path = "/v1/instances/{instance}/external-ips/all",
+    tags = ["instances"],
+}]
+async fn instance_external_ip_info_list(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    query_params: Query<params::OptionalProjectSelector>,
+    path_params: Path<params::InstancePath>,
+) -> Result<HttpResponseOk<ResultsPage<views::ExternalIpInfo>>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let instance_selector = params::InstanceSelector {
+            project: query.project,
+            instance: path.instance,
+        };
+        let instance_lookup =
+            nexus.instance_lookup(&opctx, instance_selector)?;
+        let ips = nexus.instance_external_ips(&opctx, &instance_lookup).await?;
+        Ok(HttpResponseOk(ResultsPage { items: ips, next_page: None }))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Allocate and attach an ephemeral IP to an instance
+///
+/// The instance must be stopped.
+#[endpoint {
+    method = POST,
+    path = "/v1/instances/{instance}/external-ips/ephemeral",
+    tags = ["instances"],
+}]
+async fn instance_ephemeral_ip_attach(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::InstancePath>,
+    query_params: Query<params::OptionalProjectSelector>,
+    ip_to_create: TypedBody<params::EphemeralIpCreate>,
+) -> Result<HttpResponseAccepted<views::ExternalIp>, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let instance_selector = params::InstanceSelector {
+            project: query.project,
+            instance: path.instance,
+        };
+        let instance_lookup =
+            nexus.instance_lookup(&opctx, instance_selector)?;
+        let ip = nexus
+            .instance_attach_ephemeral_ip(
+                &opctx,
+                &instance_lookup,
+                &ip_to_create.into_inner(),
+            )
+            .await?;
+        Ok(HttpResponseAccepted(ip))
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
+/// Detach and deallocate an ephemeral IP from an instance
+///
+/// The instance must be stopped.
+#[endpoint {
+    method = DELETE,
+    path = "/v1/instances/{instance}/external-ips/ephemeral",
+    tags = ["instances"],
+}]
+async fn instance_ephemeral_ip_detach(
+    rqctx: RequestContext<Arc<ServerContext>>,
+    path_params: Path<params::InstancePath>,
+    query_params: Query<params::OptionalProjectSelector>,
+) -> Result<HttpResponseDeleted, HttpError> {
+    let apictx = rqctx.context();
+    let handler = async {
+        let nexus = &apictx.nexus;
+        let path = path_params.into_inner();
+        let query = query_params.into_inner();
+        let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
+        let instance_selector = params::InstanceSelector {
+            project: query.project,
+            instance: path.instance,
+        };
+        let instance_lookup =
+            nexus.instance_lookup(&opctx, instance_selector)?;
+        nexus.instance_detach_ephemeral_ip(&opctx, &instance_lookup).await?;
+        Ok(HttpResponseDeleted())
+    };
+    apictx.external_latencies.instrument_dropshot_handler(&rqctx, handler).await
+}
+
 // Snapshots
 
 /// List snapshots
@@ -4159,15 +4264,17 @@ async fn rack_view(
 }]
 async fn sled_list(
     rqctx: RequestContext<Arc<ServerContext>>,
-    query_params: Query<PaginatedById>,
+    query_params: Query<PaginatedById<params::SledListFilter>>,
 ) -> Result<HttpResponseOk<ResultsPage<Sled>>, HttpError> {
     let apictx = rqctx.context();
     let handler = async {
         let nexus = &apictx.nexus;
         let query = query_params.into_inner();
+        let pagparams = data_page_params_for(&rqctx, &query)?;
+        let scan_params = ScanById::from_query(&query)?;
         let opctx = crate::context::op_context_for_external_api(&rqctx).await?;
         let sleds = nexus
-            .sled_list(&opctx, &data_page_params_for(&rqctx, &query)?)
+            .sled_list_filtered(&opctx, &scan_params.selector, &pagparams)
             .await?
             .into_iter()
             .map(|s| s.into())
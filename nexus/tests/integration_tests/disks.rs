@@ -34,6 +34,7 @@ use omicron_common::api::external::IdentityMetadataCreateParams;
 use omicron_common::api::external::Instance;
 use omicron_common::api::external::Name;
 use omicron_common::api::external::NameOrId;
+use omicron_common::api::internal::nexus::DiskRuntimeState;
 use omicron_nexus::app::{MAX_DISK_SIZE_BYTES, MIN_DISK_SIZE_BYTES};
 use omicron_nexus::Nexus;
 use omicron_nexus::TestInterfaces as _;
@@ -1668,6 +1669,66 @@ async fn test_disk_create_for_importing(cptestctx: &ControlPlaneTestContext) {
     disks_eq(&disks[0], &disk);
 }
 
+// Tests that Nexus records a fault that the sled agent reports for an attached
+// disk.
+#[nexus_test]
+async fn test_disk_faulted_by_sled_agent(cptestctx: &ControlPlaneTestContext) {
+    let client = &cptestctx.external_client;
+    DiskTest::new(&cptestctx).await;
+    create_org_and_project(client).await;
+    let nexus = &cptestctx.server.apictx().nexus;
+
+    // Create a disk, and a running instance with the disk attached, so that
+    // the simulated sled agent knows about the disk.
+    let disk = create_disk(&client, PROJECT_NAME, DISK_NAME).await;
+    let instance = create_instance_with(
+        &client,
+        PROJECT_NAME,
+        INSTANCE_NAME,
+        &params::InstanceNetworkInterfaceAttachment::Default,
+        vec![params::InstanceDiskAttachment::Attach(
+            params::InstanceDiskAttach { name: DISK_NAME.parse().unwrap() },
+        )],
+        Vec::<params::ExternalIpCreate>::new(),
+    )
+    .await;
+    let instance_id = instance.identity.id;
+    instance_simulate(nexus, &instance_id).await;
+    let disk_url = get_disk_url(DISK_NAME);
+    assert_eq!(
+        disk_get(&client, &disk_url).await.state,
+        DiskState::Attached(instance_id)
+    );
+
+    // Have the sled agent report that the disk faulted, starting from the
+    // state that Nexus has for it.
+    let datastore = nexus.datastore();
+    let opctx =
+        OpContext::for_tests(cptestctx.logctx.log.new(o!()), datastore.clone());
+    let (.., db_disk) = LookupPath::new(&opctx, &datastore)
+        .disk_id(disk.identity.id)
+        .fetch()
+        .await
+        .unwrap();
+    let mut runtime: DiskRuntimeState = db_disk.runtime().into();
+    let old_gen = runtime.gen;
+    runtime.disk_state = DiskState::Faulted;
+    let sa = nexus.instance_sled_by_id(&instance_id).await.unwrap();
+    sa.disk_set_sim_state(disk.identity.id, runtime).await;
+
+    // Nexus should have accepted the newer state, and now report the disk as
+    // faulted.
+    let disk = disk_get(&client, &disk_url).await;
+    assert_eq!(disk.state, DiskState::Faulted);
+    let (.., db_disk) = LookupPath::new(&opctx, &datastore)
+        .disk_id(disk.identity.id)
+        .fetch()
+        .await
+        .unwrap();
+    let runtime: DiskRuntimeState = db_disk.runtime().into();
+    assert!(runtime.gen > old_gen);
+}
+
 async fn disk_get(client: &ClientTestContext, disk_url: &str) -> Disk {
     NexusRequest::object_get(client, disk_url)
         .authn_as(AuthnMode::PrivilegedUser)
@@ -284,6 +284,14 @@ lazy_static! {
         format!("/v1/network-interfaces?project={}&instance={}", *DEMO_PROJECT_NAME, *DEMO_INSTANCE_NAME);
     pub static ref DEMO_INSTANCE_EXTERNAL_IPS_URL: String =
         format!("/v1/instances/{}/external-ips?{}", *DEMO_INSTANCE_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_INSTANCE_EXTERNAL_IPS_ALL_URL: String =
+        format!("/v1/instances/{}/external-ips/all?{}", *DEMO_INSTANCE_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_INSTANCE_EPHEMERAL_IP_URL: String =
+        format!("/v1/instances/{}/external-ips/ephemeral?{}", *DEMO_INSTANCE_NAME, *DEMO_PROJECT_SELECTOR);
+    pub static ref DEMO_EPHEMERAL_IP_CREATE: params::EphemeralIpCreate =
+        params::EphemeralIpCreate {
+            pool_name: Some(DEMO_IP_POOL_NAME.clone()),
+        };
     pub static ref DEMO_INSTANCE_CREATE: params::InstanceCreate =
         params::InstanceCreate {
             identity: IdentityMetadataCreateParams {
@@ -1486,6 +1494,25 @@ lazy_static! {
             allowed_methods: vec![AllowedMethod::Get],
         },
 
+        VerifyEndpoint {
+            url: &DEMO_INSTANCE_EXTERNAL_IPS_ALL_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![AllowedMethod::Get],
+        },
+
+        VerifyEndpoint {
+            url: &DEMO_INSTANCE_EPHEMERAL_IP_URL,
+            visibility: Visibility::Protected,
+            unprivileged_access: UnprivilegedAccess::None,
+            allowed_methods: vec![
+                AllowedMethod::Post(
+                    serde_json::to_value(&*DEMO_EPHEMERAL_IP_CREATE).unwrap()
+                ),
+                AllowedMethod::Delete,
+            ],
+        },
+
         /* IAM */
 
         VerifyEndpoint {
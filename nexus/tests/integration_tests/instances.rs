@@ -27,6 +27,7 @@ use nexus_test_utils::resource_helpers::objects_list_page_authz;
 use nexus_test_utils::resource_helpers::populate_ip_pool;
 use nexus_test_utils::resource_helpers::DiskTest;
 use nexus_test_utils::start_sled_agent;
+use nexus_types::external_api::shared::ExternalIpKind;
 use nexus_types::external_api::shared::IpKind;
 use nexus_types::external_api::shared::IpRange;
 use nexus_types::external_api::shared::Ipv4Range;
@@ -51,6 +52,7 @@ use omicron_nexus::app::MAX_VCPU_PER_INSTANCE;
 use omicron_nexus::app::MIN_MEMORY_BYTES_PER_INSTANCE;
 use omicron_nexus::Nexus;
 use omicron_nexus::TestInterfaces as _;
+use omicron_sled_agent::params::InstanceStateRequested;
 use omicron_sled_agent::sim::SledAgent;
 use sled_agent_client::TestInterfaces as _;
 use std::convert::TryFrom;
@@ -500,6 +502,44 @@ async fn test_instances_create_reboot_halt(
     .unwrap();
 }
 
+#[nexus_test]
+async fn test_instance_sim_pending_transitions(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let apictx = &cptestctx.server.apictx();
+    let nexus = &apictx.nexus;
+    let sled_agent = &cptestctx.sled_agent.sled_agent;
+    let instance_name = "patiently-waiting";
+
+    create_org_and_project(&client).await;
+
+    // Nothing is pending before we ask for anything.
+    let pending = sled_agent.sim_pending().await;
+    assert!(pending.instances.is_empty());
+    assert!(pending.disks.is_empty());
+
+    // Creating an instance registers and starts it, which should queue exactly
+    // one transition, to running.
+    let instance = create_instance(client, PROJECT_NAME, instance_name).await;
+    let instance_id = instance.identity.id;
+    let pending = sled_agent.sim_pending().await;
+    assert_eq!(pending.instances.len(), 1);
+    assert!(matches!(
+        pending.instances.get(&instance_id),
+        Some(InstanceStateRequested::Running)
+    ));
+    assert!(pending.disks.is_empty());
+
+    // Poking the instance completes the transition, draining the queue.
+    instance_simulate(nexus, &instance_id).await;
+    let pending = sled_agent.sim_pending().await;
+    assert!(pending.instances.is_empty());
+    let instance =
+        instance_get(&client, &get_instance_url(instance_name)).await;
+    assert_eq!(instance.runtime.run_state, InstanceState::Running);
+}
+
 #[nexus_test]
 async fn test_instance_start_creates_networking_state(
     cptestctx: &ControlPlaneTestContext,
@@ -3451,6 +3491,320 @@ async fn test_instance_ephemeral_ip_from_correct_pool(
     );
 }
 
+#[nexus_test]
+async fn test_instance_external_ip_info_list(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+
+    let _ = create_project(&client, PROJECT_NAME).await;
+
+    // Source NAT addresses come from the default pool; give the instance an
+    // ephemeral address from a different one.
+    let default_pool_range = IpRange::V4(
+        Ipv4Range::new(
+            std::net::Ipv4Addr::new(10, 0, 0, 1),
+            std::net::Ipv4Addr::new(10, 0, 0, 5),
+        )
+        .unwrap(),
+    );
+    let other_pool_range = IpRange::V4(
+        Ipv4Range::new(
+            std::net::Ipv4Addr::new(10, 1, 0, 1),
+            std::net::Ipv4Addr::new(10, 1, 0, 5),
+        )
+        .unwrap(),
+    );
+    populate_ip_pool(&client, "default", Some(default_pool_range)).await;
+    let other_pool =
+        create_ip_pool(&client, "other-pool", Some(other_pool_range)).await.0;
+    create_instance_with_pool(client, "ip-info-inst", Some("other-pool")).await;
+    let ephemeral_ip =
+        fetch_instance_ephemeral_ip(client, "ip-info-inst").await;
+
+    let ips_url = format!(
+        "/v1/instances/ip-info-inst/external-ips/all?project={}",
+        PROJECT_NAME
+    );
+    let ips = NexusRequest::object_get(client, &ips_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .expect("Failed to fetch external IPs")
+        .parsed_body::<ResultsPage<views::ExternalIpInfo>>()
+        .expect("Failed to parse external IPs")
+        .items;
+    assert_eq!(ips.len(), 2, "unexpected external IPs: {ips:?}");
+
+    // The ephemeral address is the one the regular listing reports, from the
+    // pool the instance asked for.
+    let ephemeral: Vec<_> =
+        ips.iter().filter(|ip| ip.kind == ExternalIpKind::Ephemeral).collect();
+    assert_eq!(ephemeral.len(), 1);
+    assert_eq!(ephemeral[0].ip, ephemeral_ip.ip);
+    assert_eq!(ephemeral[0].ip_pool_id, other_pool.identity.id);
+    assert_eq!(ephemeral[0].ip_pool_name.as_str(), "other-pool");
+
+    // The source NAT address, which the regular listing doesn't report, comes
+    // from the default pool.
+    let snat: Vec<_> =
+        ips.iter().filter(|ip| ip.kind == ExternalIpKind::Snat).collect();
+    assert_eq!(snat.len(), 1);
+    assert!(
+        snat[0].ip >= default_pool_range.first_address()
+            && snat[0].ip <= default_pool_range.last_address(),
+        "Expected SNAT IP to come from default pool"
+    );
+    assert_eq!(snat[0].ip_pool_name.as_str(), "default");
+}
+
+#[nexus_test]
+async fn test_instance_ephemeral_ip_attach_detach(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let apictx = &cptestctx.server.apictx();
+    let nexus = &apictx.nexus;
+    let instance_name = "attach-inst";
+
+    let _ = create_project(&client, PROJECT_NAME).await;
+    populate_ip_pool(&client, "default", None).await;
+    let other_pool_range = IpRange::V4(
+        Ipv4Range::new(
+            std::net::Ipv4Addr::new(10, 1, 0, 1),
+            std::net::Ipv4Addr::new(10, 1, 0, 5),
+        )
+        .unwrap(),
+    );
+    create_ip_pool(&client, "other-pool", Some(other_pool_range)).await;
+
+    // Start an instance with no external IPs other than its SNAT address.
+    let instance = nexus_test_utils::resource_helpers::create_instance_with(
+        client,
+        PROJECT_NAME,
+        instance_name,
+        &params::InstanceNetworkInterfaceAttachment::Default,
+        Vec::<params::InstanceDiskAttachment>::new(),
+        Vec::<params::ExternalIpCreate>::new(),
+    )
+    .await;
+    let instance_id = instance.identity.id;
+    instance_simulate(nexus, &instance_id).await;
+    assert!(fetch_instance_external_ips(client, instance_name)
+        .await
+        .is_empty());
+
+    // Addresses can't be attached while the instance is running.
+    let ephemeral_url = format!(
+        "/v1/instances/{}/external-ips/ephemeral?project={}",
+        instance_name, PROJECT_NAME
+    );
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &ephemeral_url,
+        &params::EphemeralIpCreate { pool_name: None },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "Instance must be stopped to attach or detach an ephemeral IP"
+    );
+    assert!(fetch_instance_external_ips(client, instance_name)
+        .await
+        .is_empty());
+
+    // Once it's stopped, an address from a specific pool can be attached.
+    let instance = instance_post(client, instance_name, InstanceOp::Stop).await;
+    instance_simulate(nexus, &instance.identity.id).await;
+    let ip = NexusRequest::new(
+        RequestBuilder::new(client, Method::POST, &ephemeral_url)
+            .body(Some(&params::EphemeralIpCreate {
+                pool_name: Some("other-pool".parse().unwrap()),
+            }))
+            .expect_status(Some(StatusCode::ACCEPTED)),
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .expect("Failed to attach ephemeral IP")
+    .parsed_body::<views::ExternalIp>()
+    .unwrap();
+    assert_eq!(ip.kind, IpKind::Ephemeral);
+    assert!(
+        ip.ip >= other_pool_range.first_address()
+            && ip.ip <= other_pool_range.last_address(),
+        "Expected ephemeral IP to come from other-pool"
+    );
+
+    let ips = fetch_instance_external_ips(client, instance_name).await;
+    assert_eq!(ips.len(), 1);
+    assert_eq!(ips[0].ip, ip.ip);
+
+    // Detaching it releases the address.
+    NexusRequest::object_delete(client, &ephemeral_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .expect("Failed to detach ephemeral IP");
+    assert!(fetch_instance_external_ips(client, instance_name)
+        .await
+        .is_empty());
+
+    // There's nothing left to detach.
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::DELETE,
+        &ephemeral_url,
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(error.message, "instance does not have an ephemeral IP address");
+}
+
+#[nexus_test]
+async fn test_instance_ephemeral_ip_attach_exceeds_cap(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let apictx = &cptestctx.server.apictx();
+    let nexus = &apictx.nexus;
+    let instance_name = "capped-inst";
+
+    let _ = create_project(&client, PROJECT_NAME).await;
+    populate_ip_pool(&client, "default", None).await;
+
+    // An instance created with an ephemeral IP is already at the cap.
+    let instance = create_instance_with_pool(client, instance_name, None).await;
+    instance_simulate(nexus, &instance.identity.id).await;
+    let instance = instance_post(client, instance_name, InstanceOp::Stop).await;
+    instance_simulate(nexus, &instance.identity.id).await;
+    let existing = fetch_instance_ephemeral_ip(client, instance_name).await;
+
+    let ephemeral_url = format!(
+        "/v1/instances/{}/external-ips/ephemeral?project={}",
+        instance_name, PROJECT_NAME
+    );
+    let error: HttpErrorResponseBody = NexusRequest::expect_failure_with_body(
+        client,
+        StatusCode::BAD_REQUEST,
+        Method::POST,
+        &ephemeral_url,
+        &params::EphemeralIpCreate { pool_name: None },
+    )
+    .authn_as(AuthnMode::PrivilegedUser)
+    .execute()
+    .await
+    .unwrap()
+    .parsed_body()
+    .unwrap();
+    assert_eq!(
+        error.message,
+        "An instance may not have more than 1 external IP addresses"
+    );
+
+    // The original address is untouched.
+    let ips = fetch_instance_external_ips(client, instance_name).await;
+    assert_eq!(ips.len(), 1);
+    assert_eq!(ips[0].ip, existing.ip);
+}
+
+#[nexus_test]
+async fn test_instance_ephemeral_ip_concurrent_attach_respects_cap(
+    cptestctx: &ControlPlaneTestContext,
+) {
+    let client = &cptestctx.external_client;
+    let apictx = &cptestctx.server.apictx();
+    let nexus = &apictx.nexus;
+    let instance_name = "racing-inst";
+
+    let _ = create_project(&client, PROJECT_NAME).await;
+    populate_ip_pool(&client, "default", None).await;
+
+    // Create a stopped instance with no external IPs other than its SNAT
+    // address.
+    let instance = nexus_test_utils::resource_helpers::create_instance_with(
+        client,
+        PROJECT_NAME,
+        instance_name,
+        &params::InstanceNetworkInterfaceAttachment::Default,
+        Vec::<params::InstanceDiskAttachment>::new(),
+        Vec::<params::ExternalIpCreate>::new(),
+    )
+    .await;
+    instance_simulate(nexus, &instance.identity.id).await;
+    let instance = instance_post(client, instance_name, InstanceOp::Stop).await;
+    instance_simulate(nexus, &instance.identity.id).await;
+
+    // Attach several addresses at once. The cap is checked in the same
+    // transaction as each allocation, so only one can succeed.
+    let ephemeral_url = format!(
+        "/v1/instances/{}/external-ips/ephemeral?project={}",
+        instance_name, PROJECT_NAME
+    );
+    let attaches = (0..4).map(|_| {
+        NexusRequest::new(
+            RequestBuilder::new(client, Method::POST, &ephemeral_url)
+                .body(Some(&params::EphemeralIpCreate { pool_name: None })),
+        )
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+    });
+    let statuses: Vec<_> = futures::future::join_all(attaches)
+        .await
+        .into_iter()
+        .map(|response| response.expect("failed to make request").status)
+        .collect();
+    assert_eq!(
+        statuses
+            .iter()
+            .filter(|status| **status == StatusCode::ACCEPTED)
+            .count(),
+        1,
+        "expected exactly one attach to succeed: {statuses:?}"
+    );
+    for status in statuses.iter().filter(|s| **s != StatusCode::ACCEPTED) {
+        assert!(
+            *status == StatusCode::BAD_REQUEST
+                || *status == StatusCode::SERVICE_UNAVAILABLE,
+            "unexpected status for a rejected attach: {status}"
+        );
+    }
+    assert_eq!(
+        fetch_instance_external_ips(client, instance_name).await.len(),
+        1
+    );
+}
+
+async fn fetch_instance_external_ips(
+    client: &ClientTestContext,
+    instance_name: &str,
+) -> Vec<views::ExternalIp> {
+    let ips_url = format!(
+        "/v1/instances/{}/external-ips?project={}",
+        instance_name, PROJECT_NAME
+    );
+    NexusRequest::object_get(client, &ips_url)
+        .authn_as(AuthnMode::PrivilegedUser)
+        .execute()
+        .await
+        .expect("Failed to fetch external IPs")
+        .parsed_body::<ResultsPage<views::ExternalIp>>()
+        .expect("Failed to parse external IPs")
+        .items
+}
+
 async fn create_instance_with_pool(
     client: &ClientTestContext,
     instance_name: &str,
@@ -3473,20 +3827,10 @@ async fn fetch_instance_ephemeral_ip(
     client: &ClientTestContext,
     instance_name: &str,
 ) -> views::ExternalIp {
-    let ips_url = format!(
-        "/v1/instances/{}/external-ips?project={}",
-        instance_name, PROJECT_NAME
-    );
-    let ips = NexusRequest::object_get(client, &ips_url)
-        .authn_as(AuthnMode::PrivilegedUser)
-        .execute()
-        .await
-        .expect("Failed to fetch external IPs")
-        .parsed_body::<ResultsPage<views::ExternalIp>>()
-        .expect("Failed to parse external IPs");
-    assert_eq!(ips.items.len(), 1);
-    assert_eq!(ips.items[0].kind, IpKind::Ephemeral);
-    ips.items[0].clone()
+    let ips = fetch_instance_external_ips(client, instance_name).await;
+    assert_eq!(ips.len(), 1);
+    assert_eq!(ips[0].kind, IpKind::Ephemeral);
+    ips[0].clone()
 }
 
 #[nexus_test]
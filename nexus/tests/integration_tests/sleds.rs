@@ -6,7 +6,13 @@
 
 use camino::Utf8Path;
 use dropshot::test_util::ClientTestContext;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use http::method::Method;
+use http::StatusCode;
 use nexus_test_interface::NexusServer;
+use nexus_test_utils::http_testing::NexusRequest;
+use nexus_test_utils::http_testing::RequestBuilder;
 use nexus_test_utils::resource_helpers::create_instance;
 use nexus_test_utils::resource_helpers::create_physical_disk;
 use nexus_test_utils::resource_helpers::create_project;
@@ -19,7 +25,15 @@ use nexus_test_utils_macros::nexus_test;
 use nexus_types::external_api::params::PhysicalDiskKind;
 use nexus_types::external_api::views::SledInstance;
 use nexus_types::external_api::views::{PhysicalDisk, Sled};
+use nexus_types::internal_api::params::Baseboard;
+use nexus_types::internal_api::params::SledAgentStartupInfo;
+use nexus_types::internal_api::params::SledRole;
+use omicron_common::api::external::ByteCount;
 use omicron_sled_agent::sim;
+use sled_agent_client::types::BundleDetailLevel;
+use sled_agent_client::types::ZoneBundleCause;
+use std::net::Ipv6Addr;
+use std::net::SocketAddrV6;
 use std::str::FromStr;
 use uuid::Uuid;
 
@@ -30,6 +44,50 @@ async fn sleds_list(client: &ClientTestContext, sleds_url: &str) -> Vec<Sled> {
     objects_list_page_authz::<Sled>(client, sleds_url).await.items
 }
 
+/// Registers a sled with the given `role` through the internal API, without
+/// starting a sled agent for it.
+async fn sled_register(
+    internal_client: &ClientTestContext,
+    role: SledRole,
+    index: u16,
+) -> Uuid {
+    let sled_id = Uuid::new_v4();
+    let info = SledAgentStartupInfo {
+        sa_address: SocketAddrV6::new(
+            Ipv6Addr::new(0xfd00, 0x1122, 0x3344, 0x0200 + index, 0, 0, 0, 1),
+            12345,
+            0,
+            0,
+        ),
+        role,
+        baseboard: Baseboard {
+            serial_number: format!("test-sled-{index}"),
+            part_number: "test-part".to_string(),
+            revision: 0,
+        },
+        usable_hardware_threads: 32,
+        usable_physical_ram: ByteCount::from_gibibytes_u32(64),
+        reservoir_size: ByteCount::from_gibibytes_u32(16),
+    };
+    NexusRequest::new(
+        RequestBuilder::new(
+            internal_client,
+            Method::POST,
+            &format!("/sled-agents/{sled_id}"),
+        )
+        .body(Some(&info))
+        .expect_status(Some(StatusCode::NO_CONTENT)),
+    )
+    .execute()
+    .await
+    .expect("failed to register sled");
+    sled_id
+}
+
+fn sled_ids(sleds: Vec<Sled>) -> Vec<Uuid> {
+    sleds.into_iter().map(|sled| sled.identity.id).collect()
+}
+
 async fn physical_disks_list(
     client: &ClientTestContext,
     url: &str,
@@ -90,6 +148,91 @@ async fn test_sleds_list(cptestctx: &ControlPlaneTestContext) {
     }
 }
 
+#[nexus_test]
+async fn test_sleds_list_filtered(cptestctx: &ControlPlaneTestContext) {
+    let external_client = &cptestctx.external_client;
+    let internal_client = &cptestctx.internal_client;
+    let sleds_url = "/v1/system/hardware/sleds";
+
+    // The simulated sled we start with is a gimlet. Add two more gimlets and
+    // two scrimlets.
+    let mut gimlets = vec![Uuid::from_str(&SLED_AGENT_UUID).unwrap()];
+    let mut scrimlets = Vec::new();
+    for index in 0..4 {
+        if index % 2 == 0 {
+            gimlets.push(
+                sled_register(internal_client, SledRole::Gimlet, index).await,
+            );
+        } else {
+            scrimlets.push(
+                sled_register(internal_client, SledRole::Scrimlet, index).await,
+            );
+        }
+    }
+    gimlets.sort();
+    scrimlets.sort();
+
+    // Filter by role.
+    let found =
+        sleds_list(&external_client, &format!("{sleds_url}?role=gimlet")).await;
+    assert_eq!(sled_ids(found), gimlets);
+    let found =
+        sleds_list(&external_client, &format!("{sleds_url}?role=scrimlet"))
+            .await;
+    assert_eq!(sled_ids(found), scrimlets);
+
+    // Filter by rack. Every sled is in the same rack.
+    let all = sleds_list(&external_client, sleds_url).await;
+    assert_eq!(all.len(), gimlets.len() + scrimlets.len());
+    let rack_id = all[0].rack_id;
+    let found =
+        sleds_list(&external_client, &format!("{sleds_url}?rack_id={rack_id}"))
+            .await;
+    assert_eq!(sled_ids(found), sled_ids(all));
+    let other_rack_id = Uuid::new_v4();
+    let found = sleds_list(
+        &external_client,
+        &format!("{sleds_url}?rack_id={other_rack_id}"),
+    )
+    .await;
+    assert!(found.is_empty());
+
+    // Filters combine.
+    let found = sleds_list(
+        &external_client,
+        &format!("{sleds_url}?rack_id={rack_id}&role=scrimlet"),
+    )
+    .await;
+    assert_eq!(sled_ids(found), scrimlets);
+
+    // Paging through a filtered list: the filter is carried in the page token,
+    // and is applied before the limit, so every page but the last is full of
+    // matching sleds.
+    let collection = NexusRequest::iter_collection_authn::<Sled>(
+        &external_client,
+        sleds_url,
+        "role=gimlet",
+        Some(2),
+    )
+    .await
+    .expect("failed to list gimlets");
+    assert_eq!(collection.npages, 2);
+    assert_eq!(sled_ids(collection.all_items), gimlets);
+
+    // With a limit that evenly divides the matching sleds, the last page is
+    // empty.
+    let collection = NexusRequest::iter_collection_authn::<Sled>(
+        &external_client,
+        sleds_url,
+        "role=scrimlet",
+        Some(1),
+    )
+    .await
+    .expect("failed to list scrimlets");
+    assert_eq!(collection.npages, 3);
+    assert_eq!(sled_ids(collection.all_items), scrimlets);
+}
+
 #[nexus_test]
 async fn test_physical_disk_create_list_delete(
     cptestctx: &ControlPlaneTestContext,
@@ -159,3 +302,55 @@ async fn test_sled_instance_list(cptestctx: &ControlPlaneTestContext) {
     assert_eq!(project.identity.name, sled_instances[0].project_name);
     assert_eq!(instance.identity.name, sled_instances[0].name);
 }
+
+// Tests that the simulated sled agent can create, list, and serve fake zone
+// bundles, so that clients of those endpoints can be tested without illumos.
+#[nexus_test]
+async fn test_sim_sled_agent_zone_bundles(cptestctx: &ControlPlaneTestContext) {
+    let sled_agent = &cptestctx.sled_agent;
+    let client = sled_agent_client::Client::new(
+        &format!("http://{}", sled_agent.http_server.local_addr()),
+        cptestctx.logctx.log.clone(),
+    );
+
+    let created = client
+        .zone_bundle_create(
+            "oxz_fake",
+            Some("ticket=1234"),
+            Some(BundleDetailLevel::Minimal),
+        )
+        .await
+        .expect("failed to create zone bundle")
+        .into_inner();
+    assert_eq!(created.id.zone_name, "oxz_fake");
+    assert_eq!(created.cause, ZoneBundleCause::ExplicitRequest);
+    assert_eq!(created.detail, BundleDetailLevel::Minimal);
+    assert!(created.content_hash.is_some());
+
+    // The bundle is listed, and only when it matches the filters.
+    let listed: Vec<_> = client
+        .zone_bundle_list_all_stream(None, None, None)
+        .try_collect()
+        .await
+        .expect("failed to list zone bundles");
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id.bundle_id, created.id.bundle_id);
+    let listed: Vec<_> = client
+        .zone_bundle_list_all_stream(Some("ticket=5678"), None, None)
+        .try_collect()
+        .await
+        .expect("failed to list zone bundles");
+    assert!(listed.is_empty());
+
+    // The bundle's contents are a gzipped tarball.
+    let mut stream = client
+        .zone_bundle_get(&created.id.zone_name, &created.id.bundle_id)
+        .await
+        .expect("failed to get zone bundle")
+        .into_inner();
+    let mut contents = Vec::new();
+    while let Some(bytes) = stream.next().await {
+        contents.extend_from_slice(&bytes.unwrap());
+    }
+    assert!(contents.starts_with(&[0x1f, 0x8b]), "bundle isn't gzipped");
+}
@@ -17,7 +17,9 @@ use http::{Method, Response, StatusCode};
 use hyper::Body;
 use nexus_test_utils::http_testing::{AuthnMode, NexusRequest, RequestBuilder};
 use nexus_test_utils::{load_test_config, test_setup, test_setup_with_config};
+use omicron_common::api::external::SemverVersion;
 use omicron_common::api::internal::nexus::KnownArtifactKind;
+use omicron_common::api::internal::nexus::UpdateArtifactId;
 use omicron_common::nexus_config::UpdatesConfig;
 use omicron_common::update::{Artifact, ArtifactKind, ArtifactsDocument};
 use omicron_sled_agent::sim;
@@ -302,3 +304,36 @@ async fn test_download_with_dots_fails() {
 
     cptestctx.teardown().await;
 }
+
+// Tests that the simulated sled agent records the artifacts it's asked to
+// apply, so that tests can check what Nexus requested.
+#[tokio::test]
+async fn test_sim_sled_agent_records_artifacts() {
+    let cptestctx = test_setup::<omicron_nexus::Server>(
+        "test_sim_sled_agent_records_artifacts",
+    )
+    .await;
+    let sled_agent = &cptestctx.sled_agent;
+    let client = sled_agent_client::Client::new(
+        &format!("http://{}", sled_agent.http_server.local_addr()),
+        cptestctx.logctx.log.clone(),
+    );
+
+    let artifacts =
+        ["first-artifact", "second-artifact"].map(|name| UpdateArtifactId {
+            name: name.to_string(),
+            version: SemverVersion::new(1, 0, 0),
+            kind: KnownArtifactKind::ControlPlane,
+        });
+    for artifact in &artifacts {
+        // Nexus has no such artifacts, so the sled agent can't download them,
+        // but the requests are recorded regardless.
+        client
+            .update_artifact(&artifact.clone().into())
+            .await
+            .expect_err("downloading a nonexistent artifact should fail");
+    }
+    assert_eq!(sled_agent.sled_agent.sim_artifacts().await, artifacts);
+
+    cptestctx.teardown().await;
+}
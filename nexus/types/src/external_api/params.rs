@@ -80,6 +80,30 @@ pub struct SwitchSelector {
     pub switch: Uuid,
 }
 
+/// The role of a sled within the rack, as used to filter the sled listing.
+#[derive(
+    Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum SledRole {
+    /// The sled is a general compute sled.
+    Gimlet,
+    /// The sled is attached to the network switch, and has additional
+    /// responsibilities.
+    Scrimlet,
+}
+
+/// Filters applied when listing sleds.
+#[derive(
+    Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq,
+)]
+pub struct SledListFilter {
+    /// If present, only list sleds in the rack with this ID
+    pub rack_id: Option<Uuid>,
+    /// If present, only list sleds with this role
+    pub role: Option<SledRole>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq)]
 pub struct SiloSelector {
     /// Name or ID of the silo
@@ -821,6 +845,14 @@ pub enum ExternalIpCreate {
     // TODO: Add floating IPs: https://github.com/oxidecomputer/omicron/issues/1334
 }
 
+/// Parameters for attaching an ephemeral IP address to an existing instance.
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+pub struct EphemeralIpCreate {
+    /// The IP pool to allocate the address from. If not specified, the
+    /// address is allocated from the default pool.
+    pub pool_name: Option<Name>,
+}
+
 /// Create-time parameters for an `Instance`
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct InstanceCreate {
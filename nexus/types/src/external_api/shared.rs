@@ -228,6 +228,16 @@ pub enum IpKind {
     Floating,
 }
 
+/// The kind of any external IP address held by an instance, including the
+/// address it uses for source NAT
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExternalIpKind {
+    Snat,
+    Ephemeral,
+    Floating,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum UpdateableComponentType {
@@ -5,7 +5,7 @@
 //! Views are response bodies, most of which are public lenses onto DB models.
 
 use crate::external_api::shared::{
-    self, IpKind, IpRange, ServiceUsingCertificate,
+    self, ExternalIpKind, IpKind, IpRange, ServiceUsingCertificate,
 };
 use crate::identity::AssetIdentityMetadata;
 use api_identity::ObjectIdentity;
@@ -265,6 +265,18 @@ pub struct ExternalIp {
     pub kind: IpKind,
 }
 
+/// An external IP address held by an instance, along with the IP pool it was
+/// allocated from
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct ExternalIpInfo {
+    pub ip: IpAddr,
+    pub kind: ExternalIpKind,
+    /// ID of the IP pool the address was allocated from
+    pub ip_pool_id: Uuid,
+    /// Name of the IP pool the address was allocated from
+    pub ip_pool_name: Name,
+}
+
 // RACKS
 
 /// View of an Rack
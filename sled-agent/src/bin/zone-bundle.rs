@@ -16,7 +16,9 @@ use clap::Args;
 use clap::Parser;
 use clap::Subcommand;
 use futures::stream::StreamExt;
+use futures::stream::TryStreamExt;
 use omicron_common::address::SLED_AGENT_PORT;
+use sled_agent_client::types::BundleDetailLevel;
 use sled_agent_client::types::CleanupContextUpdate;
 use sled_agent_client::types::Duration;
 use sled_agent_client::types::PriorityDimension;
@@ -41,6 +43,28 @@ fn parse_log_level(s: &str) -> anyhow::Result<Level> {
     s.parse().map_err(|_| anyhow!("Invalid log level"))
 }
 
+fn parse_annotation(s: &str) -> anyhow::Result<(String, String)> {
+    // Annotations are sent as a comma-separated list.
+    if s.contains(',') {
+        bail!("Invalid annotation, must not contain ','");
+    }
+    s.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| anyhow!("Invalid annotation, expected KEY=VALUE"))
+}
+
+// Format annotations as the comma-separated list expected by the sled agent.
+fn annotations_param(annotations: &[(String, String)]) -> Option<String> {
+    if annotations.is_empty() {
+        return None;
+    }
+    let annotations: Vec<_> = annotations
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect();
+    Some(annotations.join(","))
+}
+
 /// Operate on sled agent zone bundles.
 ///
 /// Zone bundles are the collected state of a service zone. This includes
@@ -145,6 +169,9 @@ enum Cmd {
         /// with a name containing the provided substring will be used, and its
         /// zone bundles listed.
         filter: Option<String>,
+        /// Only list bundles with this annotation, formatted as `KEY=VALUE`.
+        #[arg(long)]
+        annotation: Option<String>,
         /// Generate parseable output.
         #[arg(long, short, default_value_t = false)]
         parseable: bool,
@@ -156,6 +183,27 @@ enum Cmd {
     Create {
         /// The name of the zone to list bundles for.
         zone_name: String,
+        /// An annotation to record in the bundle, formatted as `KEY=VALUE`.
+        ///
+        /// This may be provided multiple times.
+        #[arg(long = "annotation", value_parser = parse_annotation)]
+        annotations: Vec<(String, String)>,
+        /// Only collect zone-wide information, skipping per-process
+        /// introspection and log files.
+        #[arg(long, default_value_t = false)]
+        minimal: bool,
+    },
+    /// Request the sled agent create a new zone bundle for every running zone.
+    CreateAll {
+        /// An annotation to record in each bundle, formatted as `KEY=VALUE`.
+        ///
+        /// This may be provided multiple times.
+        #[arg(long = "annotation", value_parser = parse_annotation)]
+        annotations: Vec<(String, String)>,
+        /// Only collect zone-wide information, skipping per-process
+        /// introspection and log files.
+        #[arg(long, default_value_t = false)]
+        minimal: bool,
     },
     /// Get a zone bundle from the sled agent.
     Get {
@@ -238,6 +286,16 @@ struct SetCleanupContextArgs {
     /// This should be expressed as percentage of the dataset quota.
     #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
     storage_limit: Option<u8>,
+    /// The number of most recent bundles of each zone to always keep.
+    ///
+    /// These are kept even if that means exceeding the storage limit.
+    #[arg(long)]
+    min_keep_per_zone: Option<u32>,
+    /// Whether to recompress old bundles while there's no storage pressure.
+    ///
+    /// This trades idle CPU time for space.
+    #[arg(long)]
+    recompress_when_idle: Option<bool>,
 }
 
 // Fetch an address on `underlay0/sled6` if it exists, or use localhost.
@@ -334,12 +392,16 @@ async fn main() -> anyhow::Result<()> {
                 println!("{zone}");
             }
         }
-        Cmd::List { filter, parseable, fields } => {
-            let bundles = client
-                .zone_bundle_list_all(filter.as_deref())
+        Cmd::List { filter, annotation, parseable, fields } => {
+            let bundles: Vec<_> = client
+                .zone_bundle_list_all_stream(
+                    annotation.as_deref(),
+                    filter.as_deref(),
+                    None,
+                )
+                .try_collect()
                 .await
-                .context("failed to list zone bundles")?
-                .into_inner();
+                .context("failed to list zone bundles")?;
             if bundles.is_empty() {
                 return Ok(());
             }
@@ -415,9 +477,19 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
         }
-        Cmd::Create { zone_name } => {
+        Cmd::Create { zone_name, annotations, minimal } => {
+            let detail = if minimal {
+                BundleDetailLevel::Minimal
+            } else {
+                BundleDetailLevel::Full
+            };
+            let annotations = annotations_param(&annotations);
             let bundle = client
-                .zone_bundle_create(&zone_name)
+                .zone_bundle_create(
+                    &zone_name,
+                    annotations.as_deref(),
+                    Some(detail),
+                )
                 .await
                 .context("failed to create zone bundle")?
                 .into_inner();
@@ -426,10 +498,33 @@ async fn main() -> anyhow::Result<()> {
                 bundle.id.zone_name, bundle.id.bundle_id
             );
         }
+        Cmd::CreateAll { annotations, minimal } => {
+            let detail = if minimal {
+                BundleDetailLevel::Minimal
+            } else {
+                BundleDetailLevel::Full
+            };
+            let annotations = annotations_param(&annotations);
+            let bundles = client
+                .zone_bundle_create_all(annotations.as_deref(), Some(detail))
+                .await
+                .context("failed to create zone bundles")?
+                .into_inner();
+            for bundle in bundles.iter() {
+                println!(
+                    "Created zone bundle: {}/{}",
+                    bundle.id.zone_name, bundle.id.bundle_id
+                );
+            }
+        }
         Cmd::Get { zone_name, bundle_id, create, output } => {
             let bundle_id = if create {
                 let bundle = client
-                    .zone_bundle_create(&zone_name)
+                    .zone_bundle_create(
+                        &zone_name,
+                        None,
+                        Some(BundleDetailLevel::Full),
+                    )
                     .await
                     .context("failed to create zone bundle")?
                     .into_inner();
@@ -479,6 +574,8 @@ async fn main() -> anyhow::Result<()> {
             println!("Period: {}s", context.period.0.secs);
             println!("Priority: {:?}", context.priority.0);
             println!("Storage limit: {}%", context.storage_limit.0);
+            println!("Min kept per zone: {}", context.min_keep_per_zone);
+            println!("Recompress when idle: {}", context.recompress_when_idle);
         }
         Cmd::SetCleanupContext(args) => {
             let priority = match args.priority {
@@ -497,6 +594,8 @@ async fn main() -> anyhow::Result<()> {
                 period: args.period.map(|secs| Duration { nanos: 0, secs }),
                 priority,
                 storage_limit: args.storage_limit,
+                min_keep_per_zone: args.min_keep_per_zone,
+                recompress_when_idle: args.recompress_when_idle,
             };
             client
                 .zone_bundle_cleanup_context_update(&ctx)
@@ -508,6 +607,7 @@ async fn main() -> anyhow::Result<()> {
                 .zone_bundle_utilization()
                 .await
                 .context("failed to get zone bundle utilization")?;
+            warn_skipped_directories(utilization_by_dir.headers());
             const BYTES_USED_SIZE: usize = 16;
             const BYTES_AVAIL_SIZE: usize = 16;
             const QUOTA_SIZE: usize = 16;
@@ -624,6 +724,7 @@ async fn main() -> anyhow::Result<()> {
                 .zone_bundle_cleanup()
                 .await
                 .context("failed to trigger zone bundle cleanup")?;
+            warn_skipped_directories(cleaned.headers());
             const COUNT_SIZE: usize = 5;
             const BYTES_SIZE: usize = 16;
             if !cleaned.is_empty() {
@@ -691,7 +792,11 @@ async fn main() -> anyhow::Result<()> {
                     }
                     // Create and fetch the bundle.
                     let metadata = client
-                        .zone_bundle_create(&new_zone)
+                        .zone_bundle_create(
+                            &new_zone,
+                            None,
+                            Some(BundleDetailLevel::Full),
+                        )
                         .await
                         .context("failed to create zone bundle")?
                         .into_inner();
@@ -777,6 +882,17 @@ async fn create_megabundle_filename() -> anyhow::Result<Utf8PathBuf> {
         .map(|p| p.join(format!("{}-{}.tar.gz", hostname.trim(), timestamp)))
 }
 
+// Warn about any storage directories the sled agent skipped, e.g., because
+// their disk has faulted, which may mean a response is incomplete.
+fn warn_skipped_directories(headers: &reqwest::header::HeaderMap) {
+    for value in headers.get_all("x-zone-bundle-directory-error") {
+        eprintln!(
+            "WARNING: skipped zone bundle directory {}",
+            String::from_utf8_lossy(value.as_bytes()),
+        );
+    }
+}
+
 // Compute used / avail as a percentage.
 fn as_pct(used: u64, avail: u64) -> u64 {
     (used * 100) / avail
@@ -66,6 +66,7 @@ pub(crate) fn api() -> BootstrapApiDescription {
     ) -> Result<(), String> {
         api.register(baseboard_get)?;
         api.register(components_get)?;
+        api.register(host_boot_slot_get)?;
         api.register(rack_initialization_status)?;
         api.register(rack_initialize)?;
         api.register(rack_reset)?;
@@ -151,6 +152,38 @@ async fn components_get(
     Ok(HttpResponseOk(components))
 }
 
+/// The host boot flash slot a sled booted from.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema,
+)]
+pub struct HostBootSlot {
+    /// The boot flash slot (0 or 1) the host booted from.
+    pub slot: u16,
+}
+
+/// Return the host boot flash slot this sled booted from.
+///
+/// This is the slot the host actually booted, which may differ from the
+/// active slot its SP was told to boot if the host fell back to the other
+/// one. It's intended to let Wicket verify that an update took effect.
+#[endpoint {
+    method = GET,
+    path = "/host-boot-slot",
+}]
+async fn host_boot_slot_get(
+    rqctx: RequestContext<BootstrapServerContext>,
+) -> Result<HttpResponseOk<HostBootSlot>, HttpError> {
+    let ctx = rqctx.context();
+    let slot =
+        ctx.storage_resources.boot_disk_host_slot().await.ok_or_else(|| {
+            HttpError::for_unavail(
+                None,
+                "boot disk not yet identified".to_string(),
+            )
+        })?;
+    Ok(HttpResponseOk(HostBootSlot { slot }))
+}
+
 /// Get the current status of rack initialization or reset.
 #[endpoint {
     method = GET,
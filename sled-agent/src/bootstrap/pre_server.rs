@@ -226,8 +226,13 @@ impl BootstrapAgentStartup {
         };
 
         // Create a `StorageManager` and (possibly) synthetic disks.
-        let storage_manager =
-            StorageManager::new(&base_log, storage_key_requester).await;
+        let storage_manager = StorageManager::new(
+            &base_log,
+            storage_key_requester,
+            &config.zone_bundle,
+        )
+        .await
+        .map_err(StartError::ConfigureZoneBundles)?;
         upsert_synthetic_zpools_if_needed(&log, &storage_manager, &config)
             .await;
 
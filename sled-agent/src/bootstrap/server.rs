@@ -27,6 +27,7 @@ use crate::config::ConfigError;
 use crate::server::Server as SledAgentServer;
 use crate::sled_agent::SledAgent;
 use crate::storage_manager::StorageResources;
+use crate::zone_bundle::BundleError;
 use bootstore::schemes::v0 as bootstore;
 use camino::Utf8PathBuf;
 use cancel_safe_futures::TryStreamExt;
@@ -153,6 +154,9 @@ pub enum StartError {
 
     #[error("Failed to bind sprocket server")]
     BindSprocketsServer(#[source] io::Error),
+
+    #[error("Invalid zone bundle configuration")]
+    ConfigureZoneBundles(#[source] BundleError),
 }
 
 /// Server for the bootstrap agent.
@@ -5,6 +5,7 @@
 //! Interfaces for working with sled agent configuration
 
 use crate::updates::ConfigUpdates;
+use crate::zone_bundle::ZoneBundleConfig;
 use camino::{Utf8Path, Utf8PathBuf};
 use dropshot::ConfigLogging;
 use illumos_utils::dladm::Dladm;
@@ -87,6 +88,10 @@ pub struct Config {
     /// mode maghemite there.
     #[serde(default)]
     pub switch_zone_maghemite_links: Vec<PhysicalLink>,
+
+    /// Configuration for zone bundles.
+    #[serde(default)]
+    pub zone_bundle: ZoneBundleConfig,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -9,8 +9,8 @@ use crate::params::{
     CleanupContextUpdate, DiskEnsureBody, InstanceEnsureBody,
     InstancePutMigrationIdsBody, InstancePutStateBody,
     InstancePutStateResponse, InstanceUnregisterResponse, ServiceEnsureBody,
-    SledRole, TimeSync, VpcFirewallRulesEnsureBody, ZoneBundleId,
-    ZoneBundleMetadata, Zpool,
+    SledRole, TimeSync, VpcFirewallRulesEnsureBody, ZoneBundleCreate,
+    ZoneBundleFilter, ZoneBundleId, ZoneBundleMetadata, ZoneBundlePage, Zpool,
 };
 use crate::sled_agent::Error as SledAgentError;
 use crate::zone_bundle;
@@ -18,7 +18,8 @@ use camino::Utf8PathBuf;
 use dropshot::{
     endpoint, ApiDescription, FreeformBody, HttpError, HttpResponseCreated,
     HttpResponseDeleted, HttpResponseHeaders, HttpResponseOk,
-    HttpResponseUpdatedNoContent, Path, Query, RequestContext, TypedBody,
+    HttpResponseUpdatedNoContent, PaginationParams, Path, Query,
+    RequestContext, ResultsPage, TypedBody, WhichPage,
 };
 use illumos_utils::opte::params::{
     DeleteVirtualNetworkInterfaceHost, SetVirtualNetworkInterfaceHost,
@@ -49,8 +50,11 @@ pub fn api() -> SledApiDescription {
         api.register(zone_bundle_list)?;
         api.register(zone_bundle_list_all)?;
         api.register(zone_bundle_create)?;
+        api.register(zone_bundle_create_all)?;
         api.register(zone_bundle_get)?;
+        api.register(zone_bundle_file_get)?;
         api.register(zone_bundle_delete)?;
+        api.register(zone_bundle_delete_all)?;
         api.register(zone_bundle_utilization)?;
         api.register(zone_bundle_cleanup_context)?;
         api.register(zone_bundle_cleanup_context_update)?;
@@ -79,12 +83,6 @@ struct ZonePathParam {
     zone_name: String,
 }
 
-#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
-struct ZoneBundleFilter {
-    /// An optional substring used to filter zone bundles.
-    filter: Option<String>,
-}
-
 /// List all zone bundles that exist, even for now-deleted zones.
 #[endpoint {
     method = GET,
@@ -92,14 +90,76 @@ struct ZoneBundleFilter {
 }]
 async fn zone_bundle_list_all(
     rqctx: RequestContext<SledAgent>,
-    query: Query<ZoneBundleFilter>,
-) -> Result<HttpResponseOk<Vec<ZoneBundleMetadata>>, HttpError> {
+    query: Query<PaginationParams<ZoneBundleFilter, ZoneBundlePage>>,
+) -> Result<
+    HttpResponseHeaders<HttpResponseOk<ResultsPage<ZoneBundleMetadata>>>,
+    HttpError,
+> {
     let sa = rqctx.context();
-    let filter = query.into_inner().filter;
-    sa.list_all_zone_bundles(filter.as_deref())
+    let pagination = query.into_inner();
+    let limit = rqctx.page_limit(&pagination)?.get() as usize;
+    let (scan_params, last_seen) = match pagination.page {
+        WhichPage::First(scan_params) => (scan_params, None),
+        WhichPage::Next(ZoneBundlePage { filter, last_seen }) => {
+            (filter, Some(last_seen))
+        }
+    };
+    let annotation = scan_params
+        .annotation()
+        .map_err(|msg| HttpError::for_bad_request(None, msg))?;
+    let zone_bundle::PartialResult { result: bundles, directory_errors } = sa
+        .list_all_zone_bundles(
+            scan_params.filter.as_deref(),
+            annotation,
+            last_seen.as_ref(),
+            limit,
+        )
         .await
-        .map(HttpResponseOk)
-        .map_err(HttpError::from)
+        .map_err(HttpError::from)?;
+    let page = ResultsPage::new(
+        bundles,
+        &scan_params,
+        |bundle: &ZoneBundleMetadata, scan_params| ZoneBundlePage {
+            filter: scan_params.clone(),
+            last_seen: bundle.id.clone(),
+        },
+    )?;
+    partial_response(zone_bundle::PartialResult {
+        result: page,
+        directory_errors,
+    })
+}
+
+// The header used to report zone bundle storage directories that were skipped.
+const DIRECTORY_ERROR_HEADER: &str = "x-zone-bundle-directory-error";
+
+// Build a response from the results of an operation across the zone bundle
+// storage directories.
+//
+// Directories which couldn't be read are skipped, and each is reported in a
+// `x-zone-bundle-directory-error` header as `<directory>: <error>`, so that
+// clients can tell a partial result from a complete one.
+fn partial_response<T>(
+    result: zone_bundle::PartialResult<T>,
+) -> Result<HttpResponseHeaders<HttpResponseOk<T>>, HttpError>
+where
+    T: JsonSchema + Serialize + Send + Sync + 'static,
+{
+    let zone_bundle::PartialResult { result, directory_errors } = result;
+    let mut response = HttpResponseHeaders::new_unnamed(HttpResponseOk(result));
+    for (directory, err) in directory_errors.iter() {
+        // Header values may not contain control characters.
+        let value =
+            format!("{directory}: {err}").replace(char::is_control, " ");
+        let value =
+            http::HeaderValue::from_bytes(value.as_bytes()).map_err(|e| {
+                HttpError::for_internal_error(format!(
+                    "invalid zone bundle directory error '{value}': {e}"
+                ))
+            })?;
+        response.headers_mut().append(DIRECTORY_ERROR_HEADER, value);
+    }
+    Ok(response)
 }
 
 /// List the zone bundles that are available for a running zone.
@@ -110,14 +170,16 @@ async fn zone_bundle_list_all(
 async fn zone_bundle_list(
     rqctx: RequestContext<SledAgent>,
     params: Path<ZonePathParam>,
-) -> Result<HttpResponseOk<Vec<ZoneBundleMetadata>>, HttpError> {
+) -> Result<
+    HttpResponseHeaders<HttpResponseOk<Vec<ZoneBundleMetadata>>>,
+    HttpError,
+> {
     let params = params.into_inner();
     let zone_name = params.zone_name;
     let sa = rqctx.context();
-    sa.list_zone_bundles(&zone_name)
-        .await
-        .map(HttpResponseOk)
-        .map_err(HttpError::from)
+    let bundles =
+        sa.list_zone_bundles(&zone_name).await.map_err(HttpError::from)?;
+    partial_response(bundles)
 }
 
 /// Ask the sled agent to create a zone bundle.
@@ -128,11 +190,36 @@ async fn zone_bundle_list(
 async fn zone_bundle_create(
     rqctx: RequestContext<SledAgent>,
     params: Path<ZonePathParam>,
+    query: Query<ZoneBundleCreate>,
 ) -> Result<HttpResponseCreated<ZoneBundleMetadata>, HttpError> {
     let params = params.into_inner();
     let zone_name = params.zone_name;
+    let query = query.into_inner();
+    let annotations = query
+        .annotations()
+        .map_err(|msg| HttpError::for_bad_request(None, msg))?;
     let sa = rqctx.context();
-    sa.create_zone_bundle(&zone_name)
+    sa.create_zone_bundle(&zone_name, annotations, query.detail())
+        .await
+        .map(HttpResponseCreated)
+        .map_err(HttpError::from)
+}
+
+/// Ask the sled agent to create a zone bundle for every running zone.
+#[endpoint {
+    method = POST,
+    path = "/zones/bundles",
+}]
+async fn zone_bundle_create_all(
+    rqctx: RequestContext<SledAgent>,
+    query: Query<ZoneBundleCreate>,
+) -> Result<HttpResponseCreated<Vec<ZoneBundleMetadata>>, HttpError> {
+    let query = query.into_inner();
+    let annotations = query
+        .annotations()
+        .map_err(|msg| HttpError::for_bad_request(None, msg))?;
+    let sa = rqctx.context();
+    sa.create_all_zone_bundles(annotations, query.detail())
         .await
         .map(HttpResponseCreated)
         .map_err(HttpError::from)
@@ -151,12 +238,10 @@ async fn zone_bundle_get(
     let zone_name = params.zone_name;
     let bundle_id = params.bundle_id;
     let sa = rqctx.context();
-    let Some(path) = sa
-        .get_zone_bundle_paths(&zone_name, &bundle_id)
+    let Some((guard, metadata)) = sa
+        .get_zone_bundle(&zone_name, &bundle_id)
         .await
         .map_err(HttpError::from)?
-        .into_iter()
-        .next()
     else {
         return Err(HttpError::for_not_found(
             None,
@@ -166,12 +251,17 @@ async fn zone_bundle_get(
             ),
         ));
     };
-    let f = tokio::fs::File::open(&path).await.map_err(|e| {
+    let path = guard.path();
+    let f = tokio::fs::File::open(path).await.map_err(|e| {
         HttpError::for_internal_error(format!(
             "failed to open zone bundle file at {}: {:?}",
             path, e,
         ))
     })?;
+
+    // Bundles are replaced atomically when recompressed, so once the file is
+    // open, its contents will continue to match the metadata.
+    drop(guard);
     let stream = hyper_staticfile::FileBytesStream::new(f);
     let body = FreeformBody(stream.into_body());
     let mut response = HttpResponseHeaders::new_unnamed(HttpResponseOk(body));
@@ -179,9 +269,82 @@ async fn zone_bundle_get(
         http::header::CONTENT_TYPE,
         "application/gzip".try_into().unwrap(),
     );
+    if let Some(content_hash) = &metadata.content_hash {
+        append_content_hash_headers(response.headers_mut(), content_hash)?;
+    }
     Ok(response)
 }
 
+// Append headers describing the SHA-256 hash of a zone bundle, so that clients
+// can verify its integrity.
+//
+// The hash is provided as a hex string, as recorded in the bundle's metadata.
+// It's reported both as an RFC 9530 `Content-Digest` and, for simpler clients,
+// as hex in `x-bundle-sha256`.
+pub(crate) fn append_content_hash_headers(
+    headers: &mut http::HeaderMap,
+    content_hash: &str,
+) -> Result<(), HttpError> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        HttpError::for_internal_error(format!(
+            "invalid zone bundle hash '{content_hash}': {e}"
+        ))
+    };
+    let digest = hex::decode(content_hash).map_err(|e| invalid(&e))?;
+    let content_digest = format!(
+        "sha-256=:{}:",
+        base64::Engine::encode(
+            &base64::engine::general_purpose::STANDARD,
+            digest
+        )
+    );
+    headers.append(
+        "content-digest",
+        content_digest.try_into().map_err(|e| invalid(&e))?,
+    );
+    headers.append(
+        "x-bundle-sha256",
+        content_hash.try_into().map_err(|e| invalid(&e))?,
+    );
+    Ok(())
+}
+
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+struct ZoneBundleFilePathParam {
+    /// The name of the zone the bundle is derived from.
+    zone_name: String,
+    /// The ID of the bundle.
+    bundle_id: Uuid,
+    /// The name of the file within the bundle.
+    path: String,
+}
+
+/// Fetch the contents of a single file from a zone bundle.
+///
+/// The file is streamed from the bundle as it's decompressed. Files larger
+/// than 256 MiB are rejected.
+#[endpoint {
+    method = GET,
+    path = "/zones/bundles/{zone_name}/{bundle_id}/files/{path}",
+}]
+async fn zone_bundle_file_get(
+    rqctx: RequestContext<SledAgent>,
+    params: Path<ZoneBundleFilePathParam>,
+) -> Result<HttpResponseOk<FreeformBody>, HttpError> {
+    let params = params.into_inner();
+    let sa = rqctx.context();
+    let file = sa
+        .extract_zone_bundle_file(
+            &params.zone_name,
+            &params.bundle_id,
+            &params.path,
+        )
+        .await
+        .map_err(HttpError::from)?;
+    let body = hyper::Body::wrap_stream(file.into_stream());
+    Ok(HttpResponseOk(FreeformBody(body)))
+}
+
 /// Delete a zone bundle.
 #[endpoint {
     method = DELETE,
@@ -218,6 +381,44 @@ async fn zone_bundle_delete(
     Ok(HttpResponseDeleted())
 }
 
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+struct ZoneBundleDeleteAllConfirm {
+    /// Must be set to `true` to confirm that all of the zone's bundles should
+    /// be deleted.
+    #[serde(default)]
+    confirm: bool,
+}
+
+/// Delete all zone bundles for a zone, from all storage directories.
+#[endpoint {
+    method = DELETE,
+    path = "/zones/bundles/{zone_name}",
+}]
+async fn zone_bundle_delete_all(
+    rqctx: RequestContext<SledAgent>,
+    params: Path<ZonePathParam>,
+    query: Query<ZoneBundleDeleteAllConfirm>,
+) -> Result<
+    HttpResponseHeaders<HttpResponseOk<zone_bundle::CleanupCount>>,
+    HttpError,
+> {
+    let zone_name = params.into_inner().zone_name;
+    if !query.into_inner().confirm {
+        return Err(HttpError::for_bad_request(
+            None,
+            format!(
+                "Deleting all zone bundles for zone '{}' requires \
+                confirmation with `confirm=true`",
+                zone_name
+            ),
+        ));
+    }
+    let sa = rqctx.context();
+    let count =
+        sa.delete_zone_bundles(&zone_name).await.map_err(HttpError::from)?;
+    partial_response(count)
+}
+
 /// Return utilization information about all zone bundles.
 #[endpoint {
     method = GET,
@@ -226,14 +427,15 @@ async fn zone_bundle_delete(
 async fn zone_bundle_utilization(
     rqctx: RequestContext<SledAgent>,
 ) -> Result<
-    HttpResponseOk<BTreeMap<Utf8PathBuf, zone_bundle::BundleUtilization>>,
+    HttpResponseHeaders<
+        HttpResponseOk<BTreeMap<Utf8PathBuf, zone_bundle::BundleUtilization>>,
+    >,
     HttpError,
 > {
     let sa = rqctx.context();
-    sa.zone_bundle_utilization()
-        .await
-        .map(HttpResponseOk)
-        .map_err(HttpError::from)
+    let utilization =
+        sa.zone_bundle_utilization().await.map_err(HttpError::from)?;
+    partial_response(utilization)
 }
 
 /// Return context used by the zone-bundle cleanup task.
@@ -270,10 +472,16 @@ async fn zone_bundle_cleanup_context_update(
         .map(zone_bundle::StorageLimit::new)
         .transpose()
         .map_err(|e| HttpError::from(SledAgentError::from(e)))?;
-    sa.update_zone_bundle_cleanup_context(new_period, new_limit, new_priority)
-        .await
-        .map(|_| HttpResponseUpdatedNoContent())
-        .map_err(HttpError::from)
+    sa.update_zone_bundle_cleanup_context(
+        new_period,
+        new_limit,
+        new_priority,
+        params.min_keep_per_zone,
+        params.recompress_when_idle,
+    )
+    .await
+    .map(|_| HttpResponseUpdatedNoContent())
+    .map_err(HttpError::from)
 }
 
 /// Trigger a zone bundle cleanup.
@@ -284,11 +492,14 @@ async fn zone_bundle_cleanup_context_update(
 async fn zone_bundle_cleanup(
     rqctx: RequestContext<SledAgent>,
 ) -> Result<
-    HttpResponseOk<BTreeMap<Utf8PathBuf, zone_bundle::CleanupCount>>,
+    HttpResponseHeaders<
+        HttpResponseOk<BTreeMap<Utf8PathBuf, zone_bundle::CleanupCount>>,
+    >,
     HttpError,
 > {
     let sa = rqctx.context();
-    sa.zone_bundle_cleanup().await.map(HttpResponseOk).map_err(HttpError::from)
+    let counts = sa.zone_bundle_cleanup().await.map_err(HttpError::from)?;
+    partial_response(counts)
 }
 
 /// List the zones that are currently managed by the sled agent.
@@ -622,3 +833,27 @@ async fn timesync_get(
     let sa = rqctx.context();
     Ok(HttpResponseOk(sa.timesync_get().await.map_err(|e| Error::from(e))?))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::append_content_hash_headers;
+
+    #[test]
+    fn test_zone_bundle_content_hash_headers() {
+        // The SHA-256 hash of the empty string.
+        let content_hash =
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let mut headers = http::HeaderMap::new();
+        append_content_hash_headers(&mut headers, content_hash).unwrap();
+        assert_eq!(
+            headers.get("content-digest").unwrap(),
+            "sha-256=:47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=:"
+        );
+        assert_eq!(headers.get("x-bundle-sha256").unwrap(), content_hash);
+
+        // A malformed hash is an error, rather than a misleading header.
+        let mut headers = http::HeaderMap::new();
+        assert!(append_content_hash_headers(&mut headers, "not-hex").is_err());
+        assert!(headers.is_empty());
+    }
+}
@@ -18,6 +18,7 @@ use crate::params::{
 };
 use crate::profile::*;
 use crate::storage_manager::StorageResources;
+use crate::zone_bundle::BundleDetailLevel;
 use crate::zone_bundle::BundleError;
 use crate::zone_bundle::ZoneBundler;
 use anyhow::anyhow;
@@ -42,6 +43,7 @@ use rand::prelude::SliceRandom;
 use rand::SeedableRng;
 use sled_hardware::disk::ZONE_DATASET;
 use slog::Logger;
+use std::collections::BTreeMap;
 use std::net::IpAddr;
 use std::net::{SocketAddr, SocketAddrV6};
 use std::sync::Arc;
@@ -551,20 +553,23 @@ impl InstanceInner {
         };
 
         // Take a zone bundle whenever this instance stops.
-        if let Err(e) = self
+        match self
             .zone_bundler
             .create(
                 &running_state.running_zone,
                 ZoneBundleCause::TerminatedInstance,
+                BTreeMap::new(),
+                BundleDetailLevel::Full,
             )
             .await
         {
-            error!(
+            Ok(_) | Err(BundleError::AutoBundleExcluded { .. }) => {}
+            Err(e) => error!(
                 self.log,
                 "Failed to take zone bundle for terminated instance";
                 "zone_name" => &zname,
                 "reason" => ?e,
-            );
+            ),
         }
 
         // Ensure that no zone exists. This succeeds even if no zone was ever
@@ -667,6 +672,9 @@ impl Instance {
     /// Create bundle from an instance zone.
     pub async fn request_zone_bundle(
         &self,
+        cause: ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+        detail: BundleDetailLevel,
     ) -> Result<ZoneBundleMetadata, BundleError> {
         let inner = self.inner.lock().await;
         let name = propolis_zone_name(inner.propolis_id());
@@ -680,7 +688,7 @@ impl Instance {
             } => {
                 inner
                     .zone_bundler
-                    .create(running_zone, ZoneBundleCause::ExplicitRequest)
+                    .create(running_zone, cause, annotations, detail)
                     .await
             }
         }
@@ -13,7 +13,9 @@ use crate::params::{
     InstanceStateRequested, InstanceUnregisterResponse,
 };
 use crate::storage_manager::StorageResources;
+use crate::zone_bundle::BundleDetailLevel;
 use crate::zone_bundle::BundleError;
+use crate::zone_bundle::ZoneBundleCause;
 use crate::zone_bundle::ZoneBundler;
 use illumos_utils::dladm::Etherstub;
 use illumos_utils::link::VnicAllocator;
@@ -336,6 +338,9 @@ impl InstanceManager {
     pub async fn create_zone_bundle(
         &self,
         name: &str,
+        cause: ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+        detail: BundleDetailLevel,
     ) -> Result<ZoneBundleMetadata, BundleError> {
         // We need to find the instance and take its lock, but:
         //
@@ -358,7 +363,7 @@ impl InstanceManager {
         else {
             return Err(BundleError::NoSuchZone { name: name.to_string() });
         };
-        instance.request_zone_bundle().await
+        instance.request_zone_bundle(cause, annotations, detail).await
     }
 }
 
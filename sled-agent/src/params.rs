@@ -2,6 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+pub use crate::zone_bundle::BundleDetailLevel;
 use crate::zone_bundle::PriorityOrder;
 pub use crate::zone_bundle::ZoneBundleCause;
 pub use crate::zone_bundle::ZoneBundleId;
@@ -18,6 +19,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sled_hardware::Baseboard;
 pub use sled_hardware::DendriteAsic;
+use std::collections::BTreeMap;
 use std::fmt::{Debug, Display, Formatter, Result as FormatResult};
 use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
 use std::time::Duration;
@@ -878,6 +880,83 @@ pub enum SledRole {
     Scrimlet,
 }
 
+/// Query parameters used to create a zone bundle.
+///
+/// These are all optional query parameters, rather than a request body, so
+/// that clients which send an empty request can still create bundles.
+#[derive(Clone, Debug, Default, Deserialize, JsonSchema, Serialize)]
+pub struct ZoneBundleCreate {
+    /// Free-form annotations recorded in the bundle's metadata, such as a
+    /// ticket or incident ID, formatted as comma-separated `KEY=VALUE` pairs.
+    pub annotations: Option<String>,
+    /// How much information to collect in the bundle.
+    pub detail: Option<BundleDetailLevel>,
+}
+
+impl ZoneBundleCreate {
+    /// Return the annotations to record, split into their keys and values.
+    pub fn annotations(&self) -> Result<BTreeMap<String, String>, String> {
+        let Some(annotations) = self.annotations.as_deref() else {
+            return Ok(BTreeMap::new());
+        };
+        annotations
+            .split(',')
+            .map(|annotation| {
+                annotation
+                    .split_once('=')
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .ok_or_else(|| {
+                        format!(
+                            "Invalid annotation '{annotation}', expected \
+                             KEY=VALUE"
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Return the level of detail to collect, defaulting to a full bundle.
+    pub fn detail(&self) -> BundleDetailLevel {
+        self.detail.unwrap_or_default()
+    }
+}
+
+/// Query parameters used to filter the list of all zone bundles.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct ZoneBundleFilter {
+    /// An optional substring used to filter zone bundles.
+    pub filter: Option<String>,
+    /// An optional annotation, formatted as `KEY=VALUE`, which listed zone
+    /// bundles must have.
+    pub annotation: Option<String>,
+}
+
+impl ZoneBundleFilter {
+    /// Return the annotation to filter on, split into its key and value.
+    pub fn annotation(&self) -> Result<Option<(&str, &str)>, String> {
+        self.annotation
+            .as_deref()
+            .map(|annotation| {
+                annotation.split_once('=').ok_or_else(|| {
+                    format!(
+                        "Invalid annotation '{annotation}', expected KEY=VALUE"
+                    )
+                })
+            })
+            .transpose()
+    }
+}
+
+/// Parameters for paginating the list of all zone bundles.
+///
+/// The filter is carried along in the page token, so that every page is
+/// filtered the same way as the first.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct ZoneBundlePage {
+    pub filter: ZoneBundleFilter,
+    pub last_seen: ZoneBundleId,
+}
+
 /// Parameters used to update the zone bundle cleanup context.
 #[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
 pub struct CleanupContextUpdate {
@@ -887,4 +966,8 @@ pub struct CleanupContextUpdate {
     pub priority: Option<PriorityOrder>,
     /// The new limit on the underlying dataset quota allowed for bundles.
     pub storage_limit: Option<u8>,
+    /// The new number of most recent bundles of each zone to always keep.
+    pub min_keep_per_zone: Option<u32>,
+    /// Whether to recompress old bundles while there's no storage pressure.
+    pub recompress_when_idle: Option<bool>,
 }
@@ -39,6 +39,7 @@ use crate::profile::*;
 use crate::smf_helper::Service;
 use crate::smf_helper::SmfHelper;
 use crate::storage_manager::StorageResources;
+use crate::zone_bundle::BundleDetailLevel;
 use crate::zone_bundle::BundleError;
 use crate::zone_bundle::ZoneBundler;
 use anyhow::anyhow;
@@ -1715,6 +1716,18 @@ impl ServiceManager {
                         String::from("/opt/oxide/baseboard.json"),
                     )?;
 
+                    // wicketd exports its metrics over the underlay, once we
+                    // have one.
+                    smfh.setprop("config/id", request.zone.id)?;
+                    if let Some(address) = request.zone.addresses.get(0) {
+                        if *address != Ipv6Addr::LOCALHOST {
+                            smfh.setprop(
+                                "config/underlay-address",
+                                address.to_string(),
+                            )?;
+                        }
+                    }
+
                     smfh.refresh()?;
                 }
                 ServiceType::Dendrite { asic } => {
@@ -2074,6 +2087,9 @@ impl ServiceManager {
     pub async fn create_zone_bundle(
         &self,
         name: &str,
+        cause: ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+        detail: BundleDetailLevel,
     ) -> Result<ZoneBundleMetadata, BundleError> {
         // Search for the named zone.
         if let SledLocalZone::Running { zone, .. } =
@@ -2083,7 +2099,7 @@ impl ServiceManager {
                 return self
                     .inner
                     .zone_bundler
-                    .create(zone, ZoneBundleCause::ExplicitRequest)
+                    .create(zone, cause, annotations, detail)
                     .await;
             }
         }
@@ -2091,7 +2107,7 @@ impl ServiceManager {
             return self
                 .inner
                 .zone_bundler
-                .create(zone, ZoneBundleCause::ExplicitRequest)
+                .create(zone, cause, annotations, detail)
                 .await;
         }
         Err(BundleError::NoSuchZone { name: name.to_string() })
@@ -2175,18 +2191,24 @@ impl ServiceManager {
                     "removing an existing zone";
                     "zone_name" => &expected_zone_name,
                 );
-                if let Err(e) = self
+                match self
                     .inner
                     .zone_bundler
-                    .create(&zone, ZoneBundleCause::UnexpectedZone)
+                    .create(
+                        &zone,
+                        ZoneBundleCause::UnexpectedZone,
+                        BTreeMap::new(),
+                        BundleDetailLevel::Full,
+                    )
                     .await
                 {
-                    error!(
+                    Ok(_) | Err(BundleError::AutoBundleExcluded { .. }) => {}
+                    Err(e) => error!(
                         log,
                         "Failed to take bundle of unexpected zone";
                         "zone_name" => &expected_zone_name,
                         "reason" => ?e,
-                    );
+                    ),
                 }
                 if let Err(e) = zone.stop().await {
                     error!(log, "Failed to stop zone {}: {e}", zone.name());
@@ -2826,6 +2848,24 @@ impl ServiceManager {
                             smfh.addpropvalue("config/mode", &mode)?;
                             smfh.refresh()?;
                         }
+                        ServiceType::Wicketd { .. } => {
+                            // Now that we have an underlay address, wicketd can
+                            // export its metrics once it's next started. We
+                            // don't restart it here, since it may be in the
+                            // middle of an update.
+                            let underlay = request
+                                .addresses
+                                .get(0)
+                                .filter(|addr| **addr != Ipv6Addr::LOCALHOST);
+                            if let Some(address) = underlay {
+                                smfh.setprop("config/id", request.id)?;
+                                smfh.setprop(
+                                    "config/underlay-address",
+                                    address.to_string(),
+                                )?;
+                                smfh.refresh()?;
+                            }
+                        }
                         _ => (),
                     }
                 }
@@ -3175,6 +3215,7 @@ mod test {
             log.clone(),
             resources.clone(),
             Default::default(),
+            None,
         );
         let mgr = ServiceManager::new(
             &log,
@@ -3223,6 +3264,7 @@ mod test {
             log.clone(),
             resources.clone(),
             Default::default(),
+            None,
         );
         let mgr = ServiceManager::new(
             &log,
@@ -3276,6 +3318,7 @@ mod test {
             log.clone(),
             resources.clone(),
             Default::default(),
+            None,
         );
         let mgr = ServiceManager::new(
             &log,
@@ -3359,6 +3402,7 @@ mod test {
             log.clone(),
             resources.clone(),
             Default::default(),
+            None,
         );
         let mgr = ServiceManager::new(
             &log,
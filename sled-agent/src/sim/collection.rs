@@ -318,21 +318,7 @@ impl<S: Simulatable + 'static> SimCollection<S> {
             if let Some(current_object) = maybe_current_object {
                 (current_object, false)
             } else {
-                // Create a new SimObject
-                let idc = *id;
-                let log = self.log.new(o!("id" => idc.to_string()));
-
-                if let SimMode::Auto = self.sim_mode {
-                    let (object, rx) =
-                        SimObject::new_simulated_auto(&current, log);
-                    let selfc = Arc::clone(self);
-                    tokio::spawn(async move {
-                        selfc.sim_step(idc, rx).await;
-                    });
-                    (object, true)
-                } else {
-                    (SimObject::new_simulated_explicit(&current, log), true)
-                }
+                (self.sim_new_object(id, &current), true)
             }
         };
 
@@ -348,6 +334,108 @@ impl<S: Simulatable + 'static> SimCollection<S> {
         rv
     }
 
+    /// Create a new `SimObject` for `id` in state `current`.  If the
+    /// simulation mode is `SimMode::Auto`, this also starts the background task
+    /// that simulates its asynchronous transitions.
+    fn sim_new_object(
+        self: &Arc<Self>,
+        id: &Uuid,
+        current: &S::CurrentState,
+    ) -> SimObject<S> {
+        let idc = *id;
+        let log = self.log.new(o!("id" => idc.to_string()));
+
+        if let SimMode::Auto = self.sim_mode {
+            let (object, rx) = SimObject::new_simulated_auto(current, log);
+            let selfc = Arc::clone(self);
+            tokio::spawn(async move {
+                selfc.sim_step(idc, rx).await;
+            });
+            object
+        } else {
+            SimObject::new_simulated_explicit(current, log)
+        }
+    }
+
+    /// Returns the current state of every object in the collection.
+    ///
+    /// Only the state each object is in right now is included, not any
+    /// asynchronous transition it may be in the middle of.
+    pub async fn sim_snapshot(&self) -> BTreeMap<Uuid, S::CurrentState> {
+        self.objects
+            .lock()
+            .await
+            .iter()
+            .map(|(id, object)| (*id, object.object.current()))
+            .collect()
+    }
+
+    /// Returns the requested state of every object with an asynchronous
+    /// transition still pending.
+    pub async fn sim_pending(&self) -> BTreeMap<Uuid, S::RequestedState> {
+        self.objects
+            .lock()
+            .await
+            .iter()
+            .filter_map(|(id, object)| {
+                object.object.desired().map(|desired| (*id, desired))
+            })
+            .collect()
+    }
+
+    /// Replaces every object in the collection with a new one created from
+    /// `states`.
+    ///
+    /// Existing objects are dropped without notifying Nexus, and any
+    /// asynchronous transitions they had in progress are abandoned.
+    pub async fn sim_restore(
+        self: &Arc<Self>,
+        states: BTreeMap<Uuid, S::CurrentState>,
+    ) {
+        let mut objects = self.objects.lock().await;
+        for (_, old_object) in std::mem::take(&mut *objects) {
+            if let Some(mut tx) = old_object.channel_tx {
+                tx.close_channel();
+            }
+        }
+        for (id, current) in states {
+            let object = self.sim_new_object(&id, &current);
+            objects.insert(id, object);
+        }
+    }
+
+    /// Applies `modify` to the object identified by `id`, and notifies Nexus
+    /// of the state that results.
+    ///
+    /// This is used to inject states that the object would not reach through
+    /// any requested transition.
+    pub async fn sim_modify_and_notify<F>(
+        &self,
+        id: &Uuid,
+        modify: F,
+    ) -> Result<S::CurrentState, Error>
+    where
+        F: FnOnce(&mut S),
+    {
+        // As in `sim_poke()`, finish with the lock before notifying Nexus.
+        let new_state = {
+            let mut objects = self.objects.lock().await;
+            let object = objects.get_mut(id).ok_or_else(|| {
+                Error::not_found_by_id(S::resource_type(), id)
+            })?;
+            let before = object.object.current();
+            modify(&mut object.object);
+            let after = object.object.current();
+            info!(object.log, "injected state";
+                "state_before" => ?before,
+                "state_after" => ?after,
+            );
+            after
+        };
+        S::notify(&self.nexus_client, id, new_state.clone()).await?;
+        Ok(new_state)
+    }
+
     pub async fn contains_key(self: &Arc<Self>, id: &Uuid) -> bool {
         self.objects.lock().await.contains_key(id)
     }
@@ -397,8 +485,11 @@ impl<S: Simulatable + Clone + 'static> SimCollection<S> {
 
 #[cfg(test)]
 mod test {
+    use crate::nexus::NexusClient;
     use crate::params::{DiskStateRequested, InstanceStateRequested};
+    use crate::sim::collection::SimCollection;
     use crate::sim::collection::SimObject;
+    use crate::sim::config::SimMode;
     use crate::sim::disk::SimDisk;
     use crate::sim::instance::SimInstance;
     use crate::sim::simulatable::Simulatable;
@@ -414,6 +505,7 @@ mod test {
     use omicron_common::api::internal::nexus::DiskRuntimeState;
     use omicron_common::api::internal::nexus::InstanceRuntimeState;
     use omicron_test_utils::dev::test_setup_log;
+    use std::sync::Arc;
 
     fn make_instance(
         logctx: &LogContext,
@@ -815,4 +907,87 @@ mod test {
 
         logctx.cleanup_successful();
     }
+
+    fn make_collection<S: Simulatable + 'static>(
+        logctx: &LogContext,
+    ) -> Arc<SimCollection<S>> {
+        let nexus_client =
+            Arc::new(NexusClient::new("http://[::1]:0", logctx.log.new(o!())));
+        Arc::new(SimCollection::new(
+            nexus_client,
+            logctx.log.new(o!()),
+            SimMode::Explicit,
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_sim_snapshot_restore() {
+        let logctx = test_setup_log("test_sim_snapshot_restore");
+        let disks = make_collection::<SimDisk>(&logctx);
+        let initial = |disk_state| DiskRuntimeState {
+            disk_state,
+            gen: Generation::new(),
+            time_updated: Utc::now(),
+        };
+
+        // Set up a few disks, one of them partway through attaching.
+        let instance_id = uuid::Uuid::new_v4();
+        let attaching_id = uuid::Uuid::new_v4();
+        let detached_id = uuid::Uuid::new_v4();
+        disks
+            .sim_ensure(
+                &attaching_id,
+                initial(DiskState::Detached),
+                Some(DiskStateRequested::Attached(instance_id)),
+            )
+            .await
+            .unwrap();
+        disks
+            .sim_ensure(&detached_id, initial(DiskState::Detached), None)
+            .await
+            .unwrap();
+        let snapshot = disks.sim_snapshot().await;
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(
+            snapshot[&attaching_id].disk_state,
+            DiskState::Attaching(instance_id)
+        );
+        assert_eq!(snapshot[&detached_id].disk_state, DiskState::Detached);
+
+        // Restore the snapshot, after a trip through its serialized form, into
+        // a collection with an unrelated disk of its own. That disk should be
+        // replaced, leaving exactly the disks in the snapshot.
+        let serialized = serde_json::to_value(&snapshot).unwrap();
+        let restored = make_collection::<SimDisk>(&logctx);
+        restored
+            .sim_ensure(
+                &uuid::Uuid::new_v4(),
+                initial(DiskState::Detached),
+                None,
+            )
+            .await
+            .unwrap();
+        restored
+            .sim_restore(serde_json::from_value(serialized.clone()).unwrap())
+            .await;
+        assert_eq!(restored.size().await, 2);
+        assert_eq!(
+            serde_json::to_value(&restored.sim_snapshot().await).unwrap(),
+            serialized
+        );
+
+        // Restored objects pick up where the snapshot left off.
+        let current = restored
+            .sim_ensure(
+                &attaching_id,
+                snapshot[&attaching_id].clone(),
+                Some(DiskStateRequested::Detached),
+            )
+            .await
+            .unwrap();
+        assert_eq!(current.disk_state, DiskState::Detaching(instance_id));
+        assert!(current.gen > snapshot[&attaching_id].gen);
+
+        logctx.cleanup_successful();
+    }
 }
@@ -194,6 +194,21 @@ impl SimDisk {
         self.producer.replace(server);
         Ok(())
     }
+
+    /// Forces the disk into the state in `runtime`, abandoning any transition
+    /// in progress.
+    ///
+    /// The generation is advanced past both the disk's own generation and the
+    /// one in `runtime`, so that the new state supersedes whatever Nexus last
+    /// recorded.
+    pub fn force_state(&mut self, runtime: DiskRuntimeState) {
+        let gen = std::cmp::max(self.state.current().gen, runtime.gen).next();
+        self.state = DiskStates::new(DiskRuntimeState {
+            disk_state: runtime.disk_state,
+            gen,
+            time_updated: chrono::Utc::now(),
+        });
+    }
 }
 
 #[async_trait]
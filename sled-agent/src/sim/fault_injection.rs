@@ -0,0 +1,197 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Injection of latency and failures into the simulated sled agent's API
+
+use dropshot::HttpError;
+use futures::lock::Mutex;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+/// A fault to inject into the next calls to one endpoint of the simulated sled
+/// agent.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct SimFault {
+    /// The name of the endpoint's handler, e.g., `instance_put_state`.
+    pub endpoint: String,
+    /// The number of calls to the endpoint that are affected.
+    ///
+    /// Once this many calls have been made, the endpoint behaves normally
+    /// again. Zero clears any fault already set for the endpoint.
+    pub count: u32,
+    /// The latency to add to each affected call, in milliseconds.
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// The HTTP status with which each affected call fails, if any.
+    ///
+    /// If this is not provided, affected calls are only delayed.
+    #[serde(default)]
+    pub status: Option<u16>,
+}
+
+/// The faults currently set for each endpoint of the simulated sled agent.
+#[derive(Debug)]
+pub struct FaultInjector {
+    // The names of the endpoints into which faults may be injected.
+    endpoints: BTreeSet<String>,
+    faults: Mutex<BTreeMap<String, SimFault>>,
+}
+
+impl FaultInjector {
+    /// Creates an injector for faults in the named endpoints.
+    pub fn new(endpoints: BTreeSet<String>) -> Self {
+        FaultInjector { endpoints, faults: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Sets the fault for the endpoint named in `fault`, replacing any fault
+    /// that was already set for it.
+    pub async fn set(&self, fault: SimFault) -> Result<(), HttpError> {
+        // A typo in the name would otherwise silently inject nothing.
+        if !self.endpoints.contains(&fault.endpoint) {
+            return Err(HttpError::for_bad_request(
+                None,
+                format!("no endpoint named {:?}", fault.endpoint),
+            ));
+        }
+        if let Some(status) = fault.status {
+            let status = StatusCode::from_u16(status).map_err(|e| {
+                HttpError::for_bad_request(None, format!("invalid status: {e}"))
+            })?;
+            if !status.is_client_error() && !status.is_server_error() {
+                return Err(HttpError::for_bad_request(
+                    None,
+                    format!("status {status} is not an error"),
+                ));
+            }
+        }
+        let mut faults = self.faults.lock().await;
+        if fault.count == 0 {
+            faults.remove(&fault.endpoint);
+        } else {
+            faults.insert(fault.endpoint.clone(), fault);
+        }
+        Ok(())
+    }
+
+    /// Applies any fault set for `endpoint` to the current call.
+    ///
+    /// Endpoints call this before doing any of their normal work, and return
+    /// the error, if any, to the client.
+    pub async fn check(&self, endpoint: &str) -> Result<(), HttpError> {
+        let fault = {
+            let mut faults = self.faults.lock().await;
+            let Some(fault) = faults.get_mut(endpoint) else {
+                return Ok(());
+            };
+            fault.count -= 1;
+            let fault = fault.clone();
+            if fault.count == 0 {
+                faults.remove(endpoint);
+            }
+            fault
+        };
+
+        if fault.latency_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(fault.latency_ms)).await;
+        }
+        let Some(status) = fault.status else {
+            return Ok(());
+        };
+        let message = format!("injected fault for {endpoint}");
+
+        // The helpers for constructing an HttpError each pick a status, or
+        // only accept client errors, so build it by hand to keep the exact
+        // status configured.
+        Err(HttpError {
+            status_code: StatusCode::from_u16(status).unwrap(),
+            error_code: Some(String::from("SimFaultInjected")),
+            external_message: message.clone(),
+            internal_message: message,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FaultInjector;
+    use super::SimFault;
+    use crate::sim::http_entrypoints::endpoint_names;
+    use http::StatusCode;
+
+    fn injector() -> FaultInjector {
+        FaultInjector::new(endpoint_names())
+    }
+
+    #[tokio::test]
+    async fn test_fault_injection_count() {
+        let injector = injector();
+        injector
+            .set(SimFault {
+                endpoint: String::from("instance_put_state"),
+                count: 2,
+                latency_ms: 0,
+                status: Some(503),
+            })
+            .await
+            .unwrap();
+
+        // The first two calls fail, and the third succeeds.
+        for _ in 0..2 {
+            let err = injector.check("instance_put_state").await.unwrap_err();
+            assert_eq!(err.status_code, StatusCode::SERVICE_UNAVAILABLE);
+        }
+        injector.check("instance_put_state").await.unwrap();
+
+        // Other endpoints are never affected.
+        injector.check("disk_put").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_fault_injection_preserves_status() {
+        let injector = injector();
+        for status in [500, 502, 503, 504, 507] {
+            injector
+                .set(SimFault {
+                    endpoint: String::from("disk_put"),
+                    count: 1,
+                    latency_ms: 0,
+                    status: Some(status),
+                })
+                .await
+                .unwrap();
+            let err = injector.check("disk_put").await.unwrap_err();
+            assert_eq!(err.status_code.as_u16(), status);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fault_injection_clear_and_validate() {
+        let injector = injector();
+        let fault = SimFault {
+            endpoint: String::from("disk_put"),
+            count: 1,
+            latency_ms: 0,
+            status: Some(409),
+        };
+        injector.set(fault.clone()).await.unwrap();
+        injector.set(SimFault { count: 0, ..fault.clone() }).await.unwrap();
+        injector.check("disk_put").await.unwrap();
+
+        // Statuses must be errors.
+        injector
+            .set(SimFault { status: Some(200), ..fault.clone() })
+            .await
+            .unwrap_err();
+
+        // Endpoints must exist.
+        injector
+            .set(SimFault { endpoint: String::from("disk_putt"), ..fault })
+            .await
+            .unwrap_err();
+    }
+}
@@ -4,19 +4,28 @@
 
 //! HTTP entrypoint functions for the sled agent's exposed API
 
+use crate::http_entrypoints::append_content_hash_headers;
 use crate::params::{
     DiskEnsureBody, InstanceEnsureBody, InstancePutMigrationIdsBody,
     InstancePutStateBody, InstancePutStateResponse, InstanceUnregisterResponse,
-    VpcFirewallRulesEnsureBody,
+    VpcFirewallRulesEnsureBody, ZoneBundleCreate, ZoneBundleFilter,
+    ZoneBundleId, ZoneBundleMetadata, ZoneBundlePage,
 };
 use dropshot::endpoint;
 use dropshot::ApiDescription;
+use dropshot::FreeformBody;
 use dropshot::HttpError;
+use dropshot::HttpResponseCreated;
+use dropshot::HttpResponseHeaders;
 use dropshot::HttpResponseOk;
 use dropshot::HttpResponseUpdatedNoContent;
+use dropshot::PaginationParams;
 use dropshot::Path;
+use dropshot::Query;
 use dropshot::RequestContext;
+use dropshot::ResultsPage;
 use dropshot::TypedBody;
+use dropshot::WhichPage;
 use illumos_utils::opte::params::DeleteVirtualNetworkInterfaceHost;
 use illumos_utils::opte::params::SetVirtualNetworkInterfaceHost;
 use omicron_common::api::internal::nexus::DiskRuntimeState;
@@ -24,9 +33,13 @@ use omicron_common::api::internal::nexus::InstanceRuntimeState;
 use omicron_common::api::internal::nexus::UpdateArtifactId;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
 use std::sync::Arc;
 use uuid::Uuid;
 
+use super::fault_injection::SimFault;
+use super::sled_agent::SimPendingTransitions;
+use super::sled_agent::SimSnapshot;
 use super::sled_agent::SledAgent;
 
 type SledApiDescription = ApiDescription<Arc<SledAgent>>;
@@ -41,11 +54,20 @@ pub fn api() -> SledApiDescription {
         api.register(instance_poke_post)?;
         api.register(disk_put)?;
         api.register(disk_poke_post)?;
+        api.register(disk_sim_state_post)?;
         api.register(update_artifact)?;
         api.register(instance_issue_disk_snapshot_request)?;
         api.register(vpc_firewall_rules_put)?;
         api.register(set_v2p)?;
         api.register(del_v2p)?;
+        api.register(zone_bundle_list_all)?;
+        api.register(zone_bundle_create)?;
+        api.register(zone_bundle_get)?;
+        api.register(sim_snapshot_get)?;
+        api.register(sim_pending_get)?;
+        api.register(sim_artifacts_get)?;
+        api.register(sim_restore_post)?;
+        api.register(sim_fault_injection_post)?;
 
         Ok(())
     }
@@ -57,6 +79,24 @@ pub fn api() -> SledApiDescription {
     api
 }
 
+/// Returns the names of the endpoints registered in [`api`], as used to
+/// identify them when injecting faults.
+pub fn endpoint_names() -> BTreeSet<String> {
+    let spec = api()
+        .openapi("Simulated Sled Agent API", "0.0.1")
+        .json()
+        .expect("failed to generate API description");
+    spec["paths"]
+        .as_object()
+        .into_iter()
+        .flat_map(|paths| paths.values())
+        .filter_map(|path| path.as_object())
+        .flat_map(|operations| operations.values())
+        .filter_map(|operation| operation["operationId"].as_str())
+        .map(String::from)
+        .collect()
+}
+
 /// Path parameters for Instance requests (sled agent API)
 #[derive(Deserialize, JsonSchema)]
 struct InstancePathParam {
@@ -73,6 +113,7 @@ async fn instance_register(
     body: TypedBody<InstanceEnsureBody>,
 ) -> Result<HttpResponseOk<InstanceRuntimeState>, HttpError> {
     let sa = rqctx.context();
+    sa.fault_injector().check("instance_register").await?;
     let instance_id = path_params.into_inner().instance_id;
     let body_args = body.into_inner();
     Ok(HttpResponseOk(
@@ -89,6 +130,7 @@ async fn instance_unregister(
     path_params: Path<InstancePathParam>,
 ) -> Result<HttpResponseOk<InstanceUnregisterResponse>, HttpError> {
     let sa = rqctx.context();
+    sa.fault_injector().check("instance_unregister").await?;
     let instance_id = path_params.into_inner().instance_id;
     Ok(HttpResponseOk(sa.instance_unregister(instance_id).await?))
 }
@@ -103,6 +145,7 @@ async fn instance_put_state(
     body: TypedBody<InstancePutStateBody>,
 ) -> Result<HttpResponseOk<InstancePutStateResponse>, HttpError> {
     let sa = rqctx.context();
+    sa.fault_injector().check("instance_put_state").await?;
     let instance_id = path_params.into_inner().instance_id;
     let body_args = body.into_inner();
     Ok(HttpResponseOk(
@@ -120,6 +163,7 @@ async fn instance_put_migration_ids(
     body: TypedBody<InstancePutMigrationIdsBody>,
 ) -> Result<HttpResponseOk<InstanceRuntimeState>, HttpError> {
     let sa = rqctx.context();
+    sa.fault_injector().check("instance_put_migration_ids").await?;
     let instance_id = path_params.into_inner().instance_id;
     let body_args = body.into_inner();
     Ok(HttpResponseOk(
@@ -141,6 +185,7 @@ async fn instance_poke_post(
     path_params: Path<InstancePathParam>,
 ) -> Result<HttpResponseUpdatedNoContent, HttpError> {
     let sa = rqctx.context();
+    sa.fault_injector().check("instance_poke_post").await?;
     let instance_id = path_params.into_inner().instance_id;
     sa.instance_poke(instance_id).await;
     Ok(HttpResponseUpdatedNoContent())
@@ -162,6 +207,7 @@ async fn disk_put(
     body: TypedBody<DiskEnsureBody>,
 ) -> Result<HttpResponseOk<DiskRuntimeState>, HttpError> {
     let sa = rqctx.context();
+    sa.fault_injector().check("disk_put").await?;
     let disk_id = path_params.into_inner().disk_id;
     let body_args = body.into_inner();
     Ok(HttpResponseOk(
@@ -183,11 +229,30 @@ async fn disk_poke_post(
     path_params: Path<DiskPathParam>,
 ) -> Result<HttpResponseUpdatedNoContent, HttpError> {
     let sa = rqctx.context();
+    sa.fault_injector().check("disk_poke_post").await?;
     let disk_id = path_params.into_inner().disk_id;
     sa.disk_poke(disk_id).await;
     Ok(HttpResponseUpdatedNoContent())
 }
 
+/// Force a disk into a state, as though the sled agent had observed it
+///
+/// The generation of the new state is always newer than both the generation
+/// provided and the one the simulated disk already had.
+#[endpoint {
+    method = POST,
+    path = "/disks/{disk_id}/sim-state",
+}]
+async fn disk_sim_state_post(
+    rqctx: RequestContext<Arc<SledAgent>>,
+    path_params: Path<DiskPathParam>,
+    body: TypedBody<DiskRuntimeState>,
+) -> Result<HttpResponseOk<DiskRuntimeState>, HttpError> {
+    let sa = rqctx.context();
+    let disk_id = path_params.into_inner().disk_id;
+    Ok(HttpResponseOk(sa.disk_set_sim_state(disk_id, body.into_inner()).await?))
+}
+
 #[endpoint {
     method = POST,
     path = "/update"
@@ -197,11 +262,11 @@ async fn update_artifact(
     artifact: TypedBody<UpdateArtifactId>,
 ) -> Result<HttpResponseUpdatedNoContent, HttpError> {
     let sa = rqctx.context();
+    sa.fault_injector().check("update_artifact").await?;
+    let artifact = artifact.into_inner();
+    sa.record_artifact(artifact.clone()).await;
     sa.updates()
-        .download_artifact(
-            artifact.into_inner(),
-            rqctx.context().nexus_client.as_ref(),
-        )
+        .download_artifact(artifact, rqctx.context().nexus_client.as_ref())
         .await
         .map_err(|e| HttpError::for_internal_error(e.to_string()))?;
     Ok(HttpResponseUpdatedNoContent())
@@ -235,6 +300,7 @@ async fn instance_issue_disk_snapshot_request(
 ) -> Result<HttpResponseOk<InstanceIssueDiskSnapshotRequestResponse>, HttpError>
 {
     let sa = rqctx.context();
+    sa.fault_injector().check("instance_issue_disk_snapshot_request").await?;
     let path_params = path_params.into_inner();
     let body = body.into_inner();
 
@@ -266,7 +332,8 @@ async fn vpc_firewall_rules_put(
     path_params: Path<VpcPathParam>,
     body: TypedBody<VpcFirewallRulesEnsureBody>,
 ) -> Result<HttpResponseUpdatedNoContent, HttpError> {
-    let _sa = rqctx.context();
+    let sa = rqctx.context();
+    sa.fault_injector().check("vpc_firewall_rules_put").await?;
     let _vpc_id = path_params.into_inner().vpc_id;
     let _body_args = body.into_inner();
 
@@ -290,6 +357,7 @@ async fn set_v2p(
     body: TypedBody<SetVirtualNetworkInterfaceHost>,
 ) -> Result<HttpResponseUpdatedNoContent, HttpError> {
     let sa = rqctx.context();
+    sa.fault_injector().check("set_v2p").await?;
     let interface_id = path_params.into_inner().interface_id;
     let body_args = body.into_inner();
 
@@ -311,6 +379,7 @@ async fn del_v2p(
     body: TypedBody<DeleteVirtualNetworkInterfaceHost>,
 ) -> Result<HttpResponseUpdatedNoContent, HttpError> {
     let sa = rqctx.context();
+    sa.fault_injector().check("del_v2p").await?;
     let interface_id = path_params.into_inner().interface_id;
     let body_args = body.into_inner();
 
@@ -320,3 +389,170 @@ async fn del_v2p(
 
     Ok(HttpResponseUpdatedNoContent())
 }
+
+/// Path parameters for zone bundle requests (sled agent API)
+#[derive(Deserialize, JsonSchema)]
+struct ZonePathParam {
+    /// The name of the zone.
+    zone_name: String,
+}
+
+/// List all fake zone bundles
+#[endpoint {
+    method = GET,
+    path = "/zones/bundles",
+}]
+async fn zone_bundle_list_all(
+    rqctx: RequestContext<Arc<SledAgent>>,
+    query: Query<PaginationParams<ZoneBundleFilter, ZoneBundlePage>>,
+) -> Result<HttpResponseOk<ResultsPage<ZoneBundleMetadata>>, HttpError> {
+    let sa = rqctx.context();
+    sa.fault_injector().check("zone_bundle_list_all").await?;
+    let pagination = query.into_inner();
+    let limit = rqctx.page_limit(&pagination)?.get() as usize;
+    let (scan_params, last_seen) = match pagination.page {
+        WhichPage::First(scan_params) => (scan_params, None),
+        WhichPage::Next(ZoneBundlePage { filter, last_seen }) => {
+            (filter, Some(last_seen))
+        }
+    };
+    let annotation = scan_params
+        .annotation()
+        .map_err(|msg| HttpError::for_bad_request(None, msg))?;
+    let bundles = sa
+        .list_all_zone_bundles(
+            scan_params.filter.as_deref(),
+            annotation,
+            last_seen.as_ref(),
+            limit,
+        )
+        .await;
+    ResultsPage::new(
+        bundles,
+        &scan_params,
+        |bundle: &ZoneBundleMetadata, scan_params| ZoneBundlePage {
+            filter: scan_params.clone(),
+            last_seen: bundle.id.clone(),
+        },
+    )
+    .map(HttpResponseOk)
+}
+
+/// Create a fake zone bundle
+#[endpoint {
+    method = POST,
+    path = "/zones/bundles/{zone_name}",
+}]
+async fn zone_bundle_create(
+    rqctx: RequestContext<Arc<SledAgent>>,
+    path_params: Path<ZonePathParam>,
+    query: Query<ZoneBundleCreate>,
+) -> Result<HttpResponseCreated<ZoneBundleMetadata>, HttpError> {
+    let sa = rqctx.context();
+    sa.fault_injector().check("zone_bundle_create").await?;
+    let zone_name = path_params.into_inner().zone_name;
+    let query = query.into_inner();
+    let annotations = query
+        .annotations()
+        .map_err(|msg| HttpError::for_bad_request(None, msg))?;
+    Ok(HttpResponseCreated(
+        sa.create_zone_bundle(&zone_name, annotations, query.detail()).await?,
+    ))
+}
+
+/// Fetch the contents of a fake zone bundle
+#[endpoint {
+    method = GET,
+    path = "/zones/bundles/{zone_name}/{bundle_id}",
+}]
+async fn zone_bundle_get(
+    rqctx: RequestContext<Arc<SledAgent>>,
+    path_params: Path<ZoneBundleId>,
+) -> Result<HttpResponseHeaders<HttpResponseOk<FreeformBody>>, HttpError> {
+    let sa = rqctx.context();
+    sa.fault_injector().check("zone_bundle_get").await?;
+    let id = path_params.into_inner();
+    let Some((metadata, contents)) = sa.get_zone_bundle(&id).await else {
+        return Err(HttpError::for_not_found(
+            None,
+            format!(
+                "No zone bundle for zone '{}' with ID '{}'",
+                id.zone_name, id.bundle_id
+            ),
+        ));
+    };
+    let body = FreeformBody(contents.into());
+    let mut response = HttpResponseHeaders::new_unnamed(HttpResponseOk(body));
+    response.headers_mut().append(
+        http::header::CONTENT_TYPE,
+        "application/gzip".try_into().unwrap(),
+    );
+    if let Some(content_hash) = &metadata.content_hash {
+        append_content_hash_headers(response.headers_mut(), content_hash)?;
+    }
+    Ok(response)
+}
+
+/// Fetch the state of every simulated instance and disk
+#[endpoint {
+    method = GET,
+    path = "/sim/snapshot",
+}]
+async fn sim_snapshot_get(
+    rqctx: RequestContext<Arc<SledAgent>>,
+) -> Result<HttpResponseOk<SimSnapshot>, HttpError> {
+    let sa = rqctx.context();
+    Ok(HttpResponseOk(sa.sim_snapshot().await))
+}
+
+/// Fetch the asynchronous transitions pending on simulated instances and disks
+#[endpoint {
+    method = GET,
+    path = "/sim/pending",
+}]
+async fn sim_pending_get(
+    rqctx: RequestContext<Arc<SledAgent>>,
+) -> Result<HttpResponseOk<SimPendingTransitions>, HttpError> {
+    let sa = rqctx.context();
+    Ok(HttpResponseOk(sa.sim_pending().await))
+}
+
+/// List the artifacts Nexus has asked this sled to apply
+#[endpoint {
+    method = GET,
+    path = "/sim/artifacts",
+}]
+async fn sim_artifacts_get(
+    rqctx: RequestContext<Arc<SledAgent>>,
+) -> Result<HttpResponseOk<Vec<UpdateArtifactId>>, HttpError> {
+    let sa = rqctx.context();
+    Ok(HttpResponseOk(sa.sim_artifacts().await))
+}
+
+/// Replace every simulated instance and disk with those in a snapshot
+#[endpoint {
+    method = POST,
+    path = "/sim/restore",
+}]
+async fn sim_restore_post(
+    rqctx: RequestContext<Arc<SledAgent>>,
+    body: TypedBody<SimSnapshot>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let sa = rqctx.context();
+    sa.sim_restore(body.into_inner()).await?;
+    Ok(HttpResponseUpdatedNoContent())
+}
+
+/// Inject latency or failures into the next calls to an endpoint
+#[endpoint {
+    method = POST,
+    path = "/sim/fault-injection",
+}]
+async fn sim_fault_injection_post(
+    rqctx: RequestContext<Arc<SledAgent>>,
+    body: TypedBody<SimFault>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let sa = rqctx.context();
+    sa.fault_injector().set(body.into_inner()).await?;
+    Ok(HttpResponseUpdatedNoContent())
+}
@@ -7,6 +7,7 @@
 mod collection;
 mod config;
 mod disk;
+mod fault_injection;
 mod http_entrypoints;
 mod http_entrypoints_pantry;
 mod http_entrypoints_storage;
@@ -18,5 +19,6 @@ mod storage;
 
 pub use crate::updates::ConfigUpdates;
 pub use config::{Config, ConfigHardware, ConfigStorage, ConfigZpool, SimMode};
+pub use fault_injection::SimFault;
 pub use server::{run_standalone_server, RssArgs, Server};
-pub use sled_agent::SledAgent;
+pub use sled_agent::{SimPendingTransitions, SimSnapshot, SledAgent};
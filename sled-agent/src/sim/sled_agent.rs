@@ -7,28 +7,36 @@
 use super::collection::{PokeMode, SimCollection};
 use super::config::Config;
 use super::disk::SimDisk;
+use super::fault_injection::FaultInjector;
+use super::http_entrypoints::endpoint_names;
 use super::instance::SimInstance;
 use super::storage::CrucibleData;
 use super::storage::Storage;
 
 use crate::nexus::NexusClient;
 use crate::params::{
-    DiskStateRequested, InstanceHardware, InstanceMigrationSourceParams,
-    InstancePutStateResponse, InstanceStateRequested,
-    InstanceUnregisterResponse,
+    BundleDetailLevel, DiskStateRequested, InstanceHardware,
+    InstanceMigrationSourceParams, InstancePutStateResponse,
+    InstanceStateRequested, InstanceUnregisterResponse, ZoneBundleCause,
+    ZoneBundleId, ZoneBundleMetadata,
 };
 use crate::sim::simulatable::Simulatable;
 use crate::updates::UpdateManager;
+use crate::zone_bundle;
 use futures::lock::Mutex;
 use omicron_common::api::external::{DiskState, Error, ResourceType};
 use omicron_common::api::internal::nexus::DiskRuntimeState;
 use omicron_common::api::internal::nexus::InstanceRuntimeState;
+use omicron_common::api::internal::nexus::UpdateArtifactId;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use slog::Logger;
 use std::net::{IpAddr, Ipv6Addr, SocketAddr};
 use std::sync::Arc;
 use uuid::Uuid;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
 
 use crucible_client_types::VolumeConstructionRequest;
@@ -63,6 +71,52 @@ pub struct SledAgent {
     pub v2p_mappings: Mutex<HashMap<Uuid, Vec<SetVirtualNetworkInterfaceHost>>>,
     mock_propolis:
         Mutex<Option<(HttpServer<Arc<PropolisContext>>, PropolisClient)>>,
+    fault_injector: FaultInjector,
+    /// artifacts Nexus has asked this sled to apply, in the order requested
+    artifacts: Mutex<Vec<UpdateArtifactId>>,
+    /// fake zone bundles, with the contents of each, indexed by bundle ID
+    zone_bundles: Mutex<BTreeMap<ZoneBundleId, (ZoneBundleMetadata, Vec<u8>)>>,
+}
+
+/// The state of every instance and disk on a simulated sled agent.
+///
+/// Tests can save this, or build it from scratch, and restore it to set up an
+/// arbitrary starting world in one step.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct SimSnapshot {
+    /// The runtime state of each instance, by ID.
+    pub instances: BTreeMap<Uuid, InstanceRuntimeState>,
+    /// The runtime state of each disk, by ID.
+    pub disks: BTreeMap<Uuid, DiskRuntimeState>,
+}
+
+/// The asynchronous transitions pending on a simulated sled agent.
+///
+/// Each of these is completed by poking the object, or in time if the agent
+/// simulates transitions automatically.
+#[derive(Clone, Debug, Deserialize, JsonSchema, Serialize)]
+pub struct SimPendingTransitions {
+    /// The state each instance with a pending transition is moving to, by ID.
+    pub instances: BTreeMap<Uuid, InstanceStateRequested>,
+    /// The state each disk with a pending transition is moving to, by ID.
+    pub disks: BTreeMap<Uuid, DiskStateRequested>,
+}
+
+// Build the gzipped tarball for a fake zone bundle, which contains only the
+// bundle's metadata.
+fn fake_zone_bundle(
+    metadata: &ZoneBundleMetadata,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let gz =
+        flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    let mut builder = tar::Builder::new(gz);
+    let contents = toml::to_string(metadata)?;
+    zone_bundle::insert_data(
+        &mut builder,
+        zone_bundle::ZONE_BUNDLE_METADATA_FILENAME,
+        contents.as_bytes(),
+    )?;
+    Ok(builder.into_inner()?.finish()?)
 }
 
 fn extract_targets_from_volume_construction_request(
@@ -152,6 +206,9 @@ impl SledAgent {
             disk_id_to_region_ids: Mutex::new(HashMap::new()),
             v2p_mappings: Mutex::new(HashMap::new()),
             mock_propolis: Mutex::new(None),
+            fault_injector: FaultInjector::new(endpoint_names()),
+            artifacts: Mutex::new(Vec::new()),
+            zone_bundles: Mutex::new(BTreeMap::new()),
         })
     }
 
@@ -441,6 +498,78 @@ impl SledAgent {
         &self.updates
     }
 
+    pub fn fault_injector(&self) -> &FaultInjector {
+        &self.fault_injector
+    }
+
+    /// Records that Nexus asked this sled to apply `artifact`.
+    pub async fn record_artifact(&self, artifact: UpdateArtifactId) {
+        self.artifacts.lock().await.push(artifact);
+    }
+
+    /// Returns every artifact Nexus has asked this sled to apply, in the order
+    /// requested, whether or not it could be downloaded.
+    pub async fn sim_artifacts(&self) -> Vec<UpdateArtifactId> {
+        self.artifacts.lock().await.clone()
+    }
+
+    /// Creates a fake bundle for the named zone.
+    ///
+    /// No zone is actually inspected. The bundle is a small tarball containing
+    /// just its own metadata, as any real bundle does.
+    pub async fn create_zone_bundle(
+        &self,
+        zone_name: &str,
+        annotations: BTreeMap<String, String>,
+        detail: BundleDetailLevel,
+    ) -> Result<ZoneBundleMetadata, Error> {
+        let mut metadata = ZoneBundleMetadata::new(
+            zone_name,
+            ZoneBundleCause::ExplicitRequest,
+            annotations,
+        );
+        metadata.detail = detail;
+        let contents = fake_zone_bundle(&metadata)
+            .map_err(|e| Error::internal_error(&e.to_string()))?;
+        metadata.content_hash =
+            Some(hex::encode(Sha256::digest(contents.as_slice())));
+        self.zone_bundles
+            .lock()
+            .await
+            .insert(metadata.id.clone(), (metadata.clone(), contents));
+        Ok(metadata)
+    }
+
+    /// Lists a page of the fake zone bundles, in order of their IDs.
+    pub async fn list_all_zone_bundles(
+        &self,
+        filter: Option<&str>,
+        annotation: Option<(&str, &str)>,
+        last_seen: Option<&ZoneBundleId>,
+        limit: usize,
+    ) -> Vec<ZoneBundleMetadata> {
+        self.zone_bundles
+            .lock()
+            .await
+            .values()
+            .map(|(metadata, _)| metadata)
+            .filter(|metadata| {
+                last_seen.map_or(true, |id| &metadata.id > id)
+                    && zone_bundle::bundle_matches(metadata, filter, annotation)
+            })
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the metadata and contents of a fake zone bundle, if it exists.
+    pub async fn get_zone_bundle(
+        &self,
+        id: &ZoneBundleId,
+    ) -> Option<(ZoneBundleMetadata, Vec<u8>)> {
+        self.zone_bundles.lock().await.get(id).cloned()
+    }
+
     pub async fn instance_count(&self) -> usize {
         self.instances.size().await
     }
@@ -449,10 +578,58 @@ impl SledAgent {
         self.disks.size().await
     }
 
+    /// Returns the current state of every simulated instance and disk.
+    pub async fn sim_snapshot(&self) -> SimSnapshot {
+        SimSnapshot {
+            instances: self.instances.sim_snapshot().await,
+            disks: self.disks.sim_snapshot().await,
+        }
+    }
+
+    /// Returns the transitions still pending for simulated instances and disks.
+    pub async fn sim_pending(&self) -> SimPendingTransitions {
+        SimPendingTransitions {
+            instances: self.instances.sim_pending().await,
+            disks: self.disks.sim_pending().await,
+        }
+    }
+
+    /// Replaces every simulated instance and disk with those in `snapshot`.
+    ///
+    /// Nexus is not notified about any of the objects that are replaced or
+    /// created, and any transitions in progress are abandoned.
+    pub async fn sim_restore(
+        self: &Arc<Self>,
+        snapshot: SimSnapshot,
+    ) -> Result<(), Error> {
+        let disk_ids: Vec<_> = snapshot.disks.keys().copied().collect();
+        self.instances.sim_restore(snapshot.instances).await;
+        self.disks.sim_restore(snapshot.disks).await;
+        for id in disk_ids {
+            self.disks
+                .sim_ensure_producer(&id, (self.nexus_address, id))
+                .await?;
+        }
+        Ok(())
+    }
+
     pub async fn instance_poke(&self, id: Uuid) {
         self.instances.sim_poke(id, PokeMode::Drain).await;
     }
 
+    /// Forces a disk into the state in `runtime`, and notifies Nexus.
+    ///
+    /// See `SimDisk::force_state()` for how the generation is chosen.
+    pub async fn disk_set_sim_state(
+        &self,
+        disk_id: Uuid,
+        runtime: DiskRuntimeState,
+    ) -> Result<DiskRuntimeState, Error> {
+        self.disks
+            .sim_modify_and_notify(&disk_id, |disk| disk.force_state(runtime))
+            .await
+    }
+
     pub async fn disk_poke(&self, id: Uuid) {
         self.disks.sim_poke(id, PokeMode::SingleStep).await;
     }
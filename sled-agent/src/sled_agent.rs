@@ -15,15 +15,17 @@ use crate::params::{
     DiskStateRequested, InstanceHardware, InstanceMigrationSourceParams,
     InstancePutStateResponse, InstanceStateRequested,
     InstanceUnregisterResponse, ServiceEnsureBody, SledRole, TimeSync,
-    VpcFirewallRule, ZoneBundleMetadata, Zpool,
+    VpcFirewallRule, ZoneBundleId, ZoneBundleMetadata, Zpool,
 };
 use crate::services::{self, ServiceManager};
 use crate::storage_manager::{self, StorageManager};
 use crate::updates::{ConfigUpdates, UpdateManager};
 use crate::zone_bundle;
 use crate::zone_bundle::BundleError;
+use crate::zone_bundle::ManagedZones;
 use bootstore::schemes::v0 as bootstore;
 use camino::Utf8PathBuf;
+use dropshot::ConfigDropshot;
 use dropshot::HttpError;
 use illumos_utils::opte::params::{
     DeleteVirtualNetworkInterfaceHost, SetVirtualNetworkInterfaceHost,
@@ -31,10 +33,12 @@ use illumos_utils::opte::params::{
 use illumos_utils::opte::PortManager;
 use illumos_utils::zone::PROPOLIS_ZONE_PREFIX;
 use illumos_utils::zone::ZONE_PREFIX;
+use internal_dns::ServiceName;
 use omicron_common::address::{
     get_sled_address, get_switch_zone_address, Ipv6Subnet, SLED_PREFIX,
 };
 use omicron_common::api::external::Vni;
+use omicron_common::api::internal::nexus::ProducerEndpoint;
 use omicron_common::api::internal::shared::RackNetworkConfig;
 use omicron_common::api::{
     internal::nexus::DiskRuntimeState, internal::nexus::InstanceRuntimeState,
@@ -44,12 +48,16 @@ use omicron_common::backoff::{
     retry_notify, retry_notify_ext, retry_policy_internal_service_aggressive,
     BackoffError,
 };
+use oximeter::types::ProducerRegistry;
+use oximeter_producer::LogConfig;
+use oximeter_producer::Server as ProducerServer;
 use sled_hardware::underlay;
 use sled_hardware::HardwareManager;
 use slog::Logger;
 use std::collections::BTreeMap;
-use std::net::{Ipv6Addr, SocketAddrV6};
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
 use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
 #[cfg(not(test))]
@@ -57,6 +65,9 @@ use illumos_utils::{dladm::Dladm, zone::Zones};
 #[cfg(test)]
 use illumos_utils::{dladm::MockDladm as Dladm, zone::MockZones as Zones};
 
+// How often oximeter collects the sled agent's own metrics.
+const METRICS_COLLECTION_INTERVAL: Duration = Duration::from_secs(30);
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Configuration error: {0}")]
@@ -176,8 +187,32 @@ impl From<Error> for dropshot::HttpError {
                 BundleError::NoSuchZone { .. } => {
                     HttpError::for_not_found(None, inner.to_string())
                 }
+                BundleError::NoSuchBundle { .. } => {
+                    HttpError::for_client_error(
+                        Some(String::from("NoSuchBundle")),
+                        http::StatusCode::NOT_FOUND,
+                        inner.to_string(),
+                    )
+                }
+                BundleError::NoSuchBundleEntry { .. } => {
+                    HttpError::for_client_error(
+                        Some(String::from("NoSuchBundleEntry")),
+                        http::StatusCode::NOT_FOUND,
+                        inner.to_string(),
+                    )
+                }
+                BundleError::BundleEntryTooLarge { .. } => {
+                    HttpError::for_client_error(
+                        Some(String::from("BundleEntryTooLarge")),
+                        http::StatusCode::PAYLOAD_TOO_LARGE,
+                        inner.to_string(),
+                    )
+                }
                 BundleError::InvalidStorageLimit
-                | BundleError::InvalidCleanupPeriod => {
+                | BundleError::InvalidCleanupPeriod
+                | BundleError::InvalidCaptureInterval
+                | BundleError::DisallowedCommand { .. }
+                | BundleError::InvalidZoneName { .. } => {
                     HttpError::for_bad_request(None, inner.to_string())
                 }
                 _ => HttpError::for_internal_error(err.to_string()),
@@ -240,6 +275,29 @@ impl SledAgentInner {
     }
 }
 
+#[async_trait::async_trait]
+impl ManagedZones for SledAgentInner {
+    async fn create_zone_bundle(
+        &self,
+        zone_name: &str,
+        cause: zone_bundle::ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+        detail: zone_bundle::BundleDetailLevel,
+    ) -> Result<ZoneBundleMetadata, BundleError> {
+        if zone_name.starts_with(PROPOLIS_ZONE_PREFIX) {
+            self.instances
+                .create_zone_bundle(zone_name, cause, annotations, detail)
+                .await
+        } else if zone_name.starts_with(ZONE_PREFIX) {
+            self.services
+                .create_zone_bundle(zone_name, cause, annotations, detail)
+                .await
+        } else {
+            Err(BundleError::NoSuchZone { name: zone_name.to_string() })
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct SledAgent {
     inner: Arc<SledAgentInner>,
@@ -447,6 +505,15 @@ impl SledAgent {
             log: log.clone(),
         };
 
+        // Let the bundler create bundles it captures on its own, e.g., on a
+        // schedule, from the zones we manage.
+        let managed_zones: std::sync::Weak<dyn ManagedZones> =
+            Arc::downgrade(&sled_agent.inner);
+        sled_agent.inner.zone_bundler.set_managed_zones(managed_zones).await;
+
+        // Export the sled agent's own metrics, e.g., about zone bundles.
+        sled_agent.start_metrics_producer(*sled_address.ip());
+
         // We immediately add a notification to the request queue about our
         // existence. If inspection of the hardware later informs us that we're
         // actually running on a scrimlet, that's fine, the updated value will
@@ -590,19 +657,96 @@ impl SledAgent {
             });
     }
 
+    // Start a server from which oximeter collects the sled agent's own
+    // metrics, listening on any port of the provided address.
+    //
+    // The server is registered with Nexus once it's reachable, retrying until
+    // then, and runs for the life of the sled agent.
+    fn start_metrics_producer(&self, address: Ipv6Addr) {
+        let log = self.log.new(o!("component" => "MetricsProducer"));
+        let id = self.inner.id;
+        let registry = ProducerRegistry::with_id(id);
+        if let Err(err) = self.inner.zone_bundler.register_metrics(&registry) {
+            error!(
+                log,
+                "failed to register zone bundle metrics";
+                "error" => ?err,
+            );
+        }
+        let resolver = self.inner.nexus_client.resolver().clone();
+        tokio::spawn(async move {
+            let address = SocketAddr::new(address.into(), 0);
+            let start_server = || async {
+                let nexus_address = resolver
+                    .lookup_socket_v6(ServiceName::Nexus)
+                    .await
+                    .map_err(|err| BackoffError::transient(err.to_string()))?;
+                let config = oximeter_producer::Config {
+                    server_info: ProducerEndpoint {
+                        id,
+                        address,
+                        base_route: String::from("/collect"),
+                        interval: METRICS_COLLECTION_INTERVAL,
+                    },
+                    registration_address: nexus_address.into(),
+                    dropshot: ConfigDropshot {
+                        bind_address: address,
+                        ..Default::default()
+                    },
+                    log: LogConfig::Logger(log.clone()),
+                };
+                ProducerServer::with_registry(registry.clone(), &config)
+                    .await
+                    .map_err(|err| BackoffError::transient(err.to_string()))
+            };
+            let log_failure = |err, delay| {
+                warn!(
+                    log,
+                    "failed to start metrics producer, will retry in {:?}",
+                    delay;
+                    "error" => err,
+                );
+            };
+            let server = retry_notify(
+                retry_policy_internal_service_aggressive(),
+                start_server,
+                log_failure,
+            )
+            .await
+            .expect("Expected an infinite retry loop starting the server");
+            info!(
+                log,
+                "started metrics producer";
+                "address" => %server.address(),
+            );
+            if let Err(err) = server.serve_forever().await {
+                error!(log, "metrics producer failed"; "error" => %err);
+            }
+        });
+    }
+
     /// List all zone bundles on the system, for any zones live or dead.
     pub async fn list_all_zone_bundles(
         &self,
         filter: Option<&str>,
-    ) -> Result<Vec<ZoneBundleMetadata>, Error> {
-        self.inner.zone_bundler.list(filter).await.map_err(Error::from)
+        annotation: Option<(&str, &str)>,
+        last_seen: Option<&ZoneBundleId>,
+        limit: usize,
+    ) -> Result<zone_bundle::PartialResult<Vec<ZoneBundleMetadata>>, Error>
+    {
+        self.inner
+            .zone_bundler
+            .list_page(filter, annotation, last_seen, limit)
+            .await
+            .map_err(Error::from)
     }
 
     /// List zone bundles for the provided zone.
     pub async fn list_zone_bundles(
         &self,
         name: &str,
-    ) -> Result<Vec<ZoneBundleMetadata>, Error> {
+    ) -> Result<zone_bundle::PartialResult<Vec<ZoneBundleMetadata>>, Error>
+    {
         self.inner.zone_bundler.list_for_zone(name).await.map_err(Error::from)
     }
 
@@ -610,24 +754,59 @@ impl SledAgent {
     pub async fn create_zone_bundle(
         &self,
         name: &str,
+        annotations: BTreeMap<String, String>,
+        detail: zone_bundle::BundleDetailLevel,
     ) -> Result<ZoneBundleMetadata, Error> {
-        if name.starts_with(PROPOLIS_ZONE_PREFIX) {
-            self.inner
-                .instances
-                .create_zone_bundle(name)
-                .await
-                .map_err(Error::from)
-        } else if name.starts_with(ZONE_PREFIX) {
-            self.inner
-                .services
-                .create_zone_bundle(name)
+        if !name.starts_with(ZONE_PREFIX) {
+            return Err(Error::from(BundleError::NoSuchZone {
+                name: name.to_string(),
+            }));
+        }
+        let result = self
+            .inner
+            .create_zone_bundle(
+                name,
+                zone_bundle::ZoneBundleCause::ExplicitRequest,
+                annotations.clone(),
+                detail,
+            )
+            .await;
+        match result {
+            // We aren't managing the zone, but it may still exist at the OS
+            // level, e.g., if it was left half-created or half-destroyed. That's
+            // exactly when a bundle is most useful, so collect what we can.
+            Err(BundleError::NoSuchZone { .. }) => self
+                .inner
+                .zone_bundler
+                .create_by_name(
+                    name,
+                    zone_bundle::ZoneBundleCause::ExplicitRequest,
+                    annotations,
+                    detail,
+                )
                 .await
-                .map_err(Error::from)
-        } else {
-            Err(Error::from(BundleError::NoSuchZone { name: name.to_string() }))
+                .map_err(Error::from),
+            result => result.map_err(Error::from),
         }
     }
 
+    /// Create a zone bundle for every running zone.
+    pub async fn create_all_zone_bundles(
+        &self,
+        annotations: BTreeMap<String, String>,
+        detail: zone_bundle::BundleDetailLevel,
+    ) -> Result<Vec<ZoneBundleMetadata>, Error> {
+        self.inner
+            .zone_bundler
+            .create_all(
+                zone_bundle::ZoneBundleCause::ExplicitRequest,
+                annotations,
+                detail,
+            )
+            .await
+            .map_err(Error::from)
+    }
+
     /// Fetch the paths to all zone bundles with the provided name and ID.
     pub async fn get_zone_bundle_paths(
         &self,
@@ -641,21 +820,49 @@ impl SledAgent {
             .map_err(Error::from)
     }
 
+    /// Delete all zone bundles for the zone with the provided name.
+    pub async fn delete_zone_bundles(
+        &self,
+        name: &str,
+    ) -> Result<zone_bundle::PartialResult<zone_bundle::CleanupCount>, Error>
+    {
+        self.inner.zone_bundler.delete_for_zone(name).await.map_err(Error::from)
+    }
+
+    /// Fetch the path to and metadata of a zone bundle with the provided name
+    /// and ID, if it exists.
+    ///
+    /// The path is available from the returned guard, which marks the bundle
+    /// as being read until it's dropped.
+    pub async fn get_zone_bundle(
+        &self,
+        name: &str,
+        id: &Uuid,
+    ) -> Result<Option<(zone_bundle::BundleReadGuard, ZoneBundleMetadata)>, Error>
+    {
+        self.inner.zone_bundler.find_bundle(name, id).await.map_err(Error::from)
+    }
+
+    /// Extract a single file from the zone bundle with the provided name and
+    /// ID.
+    pub async fn extract_zone_bundle_file(
+        &self,
+        name: &str,
+        id: &Uuid,
+        entry_path: &str,
+    ) -> Result<zone_bundle::BundleFile, Error> {
+        self.inner
+            .zone_bundler
+            .extract_file(name, id, entry_path)
+            .await
+            .map_err(Error::from)
+    }
+
     /// List the zones that the sled agent is currently managing.
     pub async fn zones_list(&self) -> Result<Vec<String>, Error> {
-        Zones::get()
+        Zones::running_names()
             .await
-            .map(|zones| {
-                let mut zn: Vec<_> = zones
-                    .into_iter()
-                    .filter_map(|zone| {
-                        if matches!(zone.state(), zone::State::Running) {
-                            Some(String::from(zone.name()))
-                        } else {
-                            None
-                        }
-                    })
-                    .collect();
+            .map(|mut zn| {
                 zn.sort();
                 zn
             })
@@ -675,10 +882,18 @@ impl SledAgent {
         period: Option<zone_bundle::CleanupPeriod>,
         storage_limit: Option<zone_bundle::StorageLimit>,
         priority: Option<zone_bundle::PriorityOrder>,
+        min_keep_per_zone: Option<u32>,
+        recompress_when_idle: Option<bool>,
     ) -> Result<(), Error> {
         self.inner
             .zone_bundler
-            .update_cleanup_context(period, storage_limit, priority)
+            .update_cleanup_context(
+                period,
+                storage_limit,
+                priority,
+                min_keep_per_zone,
+                recompress_when_idle,
+            )
             .await
             .map_err(Error::from)
     }
@@ -686,15 +901,24 @@ impl SledAgent {
     /// Fetch the current utilization of the relevant datasets for zone bundles.
     pub async fn zone_bundle_utilization(
         &self,
-    ) -> Result<BTreeMap<Utf8PathBuf, zone_bundle::BundleUtilization>, Error>
-    {
+    ) -> Result<
+        zone_bundle::PartialResult<
+            BTreeMap<Utf8PathBuf, zone_bundle::BundleUtilization>,
+        >,
+        Error,
+    > {
         self.inner.zone_bundler.utilization().await.map_err(Error::from)
     }
 
     /// Trigger an explicit request to cleanup old zone bundles.
     pub async fn zone_bundle_cleanup(
         &self,
-    ) -> Result<BTreeMap<Utf8PathBuf, zone_bundle::CleanupCount>, Error> {
+    ) -> Result<
+        zone_bundle::PartialResult<
+            BTreeMap<Utf8PathBuf, zone_bundle::CleanupCount>,
+        >,
+        Error,
+    > {
         self.inner.zone_bundler.cleanup().await.map_err(Error::from)
     }
 
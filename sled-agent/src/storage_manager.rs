@@ -7,6 +7,9 @@
 use crate::nexus::NexusClientWithResolver;
 use crate::storage::dataset::DatasetName;
 use crate::storage::dump_setup::DumpSetup;
+use crate::zone_bundle::BundleError;
+use crate::zone_bundle::CleanupContext;
+use crate::zone_bundle::ZoneBundleConfig;
 use crate::zone_bundle::ZoneBundler;
 use camino::Utf8PathBuf;
 use derive_more::From;
@@ -298,6 +301,21 @@ impl StorageResources {
         })
     }
 
+    /// Returns the host boot flash slot the host booted from, as implied by
+    /// which M.2 it chose as its boot disk.
+    ///
+    /// If this returns `None`, we have not processed the boot disk yet, or
+    /// are running with synthetic disks, which have no slot.
+    pub async fn boot_disk_host_slot(&self) -> Option<u16> {
+        let disks = self.disks.lock().await;
+        disks.values().find_map(|disk| match disk {
+            DiskWrapper::Real { disk, .. } if disk.is_boot_disk() => {
+                disk.host_boot_slot()
+            }
+            _ => None,
+        })
+    }
+
     // TODO: Could be generic over DiskVariant
 
     /// Returns all M.2 zpools
@@ -1237,7 +1255,13 @@ pub struct StorageManager {
 
 impl StorageManager {
     /// Creates a new [`StorageManager`] which should manage local storage.
-    pub async fn new(log: &Logger, key_requester: StorageKeyRequester) -> Self {
+    ///
+    /// This fails if `zone_bundle_config` is invalid.
+    pub async fn new(
+        log: &Logger,
+        key_requester: StorageKeyRequester,
+        zone_bundle_config: &ZoneBundleConfig,
+    ) -> Result<Self, BundleError> {
         let log = log.new(o!("component" => "StorageManager"));
         let resources = StorageResources {
             disks: Arc::new(Mutex::new(HashMap::new())),
@@ -1246,10 +1270,38 @@ impl StorageManager {
         let (tx, rx) = mpsc::channel(30);
 
         let zb_log = log.new(o!("component" => "ZoneBundler"));
-        let zone_bundler =
-            ZoneBundler::new(zb_log, resources.clone(), Default::default());
+        let zone_bundler = ZoneBundler::new(
+            zb_log,
+            resources.clone(),
+            CleanupContext::default(),
+            None,
+        );
+        if zone_bundle_config.run_cleanup_on_start {
+            zone_bundler.cleanup_on_start().await;
+        }
+        if let Some(secs) = zone_bundle_config.command_timeout_secs {
+            zone_bundler.set_command_timeout(Duration::from_secs(secs)).await;
+        }
+        if !zone_bundle_config.auto_bundle_exclusions.is_empty() {
+            zone_bundler
+                .set_auto_bundle_exclusions(
+                    zone_bundle_config.auto_bundle_exclusions.clone(),
+                )
+                .await;
+        }
+        if let Some(commands) = &zone_bundle_config.zone_wide_commands {
+            zone_bundler.set_zone_wide_commands(commands.clone()).await?;
+        }
+        for (zone_name, secs) in zone_bundle_config.scheduled_captures.iter() {
+            zone_bundler
+                .schedule_capture(zone_name, Duration::from_secs(*secs))
+                .await?;
+        }
+        if zone_bundle_config.single_pass_copies {
+            zone_bundler.set_single_pass_copies(true).await;
+        }
 
-        StorageManager {
+        Ok(StorageManager {
             inner: Arc::new(StorageManagerInner {
                 log: log.clone(),
                 resources: resources.clone(),
@@ -1269,7 +1321,7 @@ impl StorageManager {
                 }),
             }),
             zone_bundler,
-        }
+        })
     }
 
     /// Return a reference to the object used to manage zone bundles.
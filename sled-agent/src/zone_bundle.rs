@@ -19,9 +19,25 @@ use illumos_utils::running_zone::is_oxide_smf_log_file;
 use illumos_utils::running_zone::RunningZone;
 use illumos_utils::zfs::ZFS;
 use illumos_utils::zone::AdmError;
+#[cfg(test)]
+use illumos_utils::zone::MockZones as Zones;
+#[cfg(not(test))]
+use illumos_utils::zone::Zones;
+use illumos_utils::zone::ZLOGIN;
+use illumos_utils::PFEXEC;
+use omicron_common::backoff::retry_policy_internal_service;
+use omicron_common::backoff::Backoff;
+use oximeter::types::ProducerRegistry;
+use oximeter::MetricsError;
+#[cfg(test)]
+use proptest::strategy::Just;
+#[cfg(test)]
+use proptest::strategy::Strategy;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
 use slog::Logger;
 use std::cmp::Ord;
 use std::cmp::Ordering;
@@ -29,8 +45,12 @@ use std::cmp::PartialOrd;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::collections::HashSet;
+use std::future::Future;
 use std::io::Cursor;
+use std::io::Read;
+use std::os::unix::process::CommandExt;
 use std::sync::Arc;
+use std::sync::Weak;
 use std::time::Duration;
 use std::time::SystemTime;
 use tar::Archive;
@@ -87,10 +107,13 @@ pub struct ZoneBundleId {
 )]
 #[serde(rename_all = "snake_case")]
 #[non_exhaustive]
+#[cfg_attr(test, derive(test_strategy::Arbitrary))]
 pub enum ZoneBundleCause {
     /// Some other, unspecified reason.
     #[default]
     Other,
+    /// Taken periodically, on a schedule registered for the zone.
+    Scheduled,
     /// A zone bundle taken when a sled agent finds a zone that it does not
     /// expect to be running.
     UnexpectedZone,
@@ -100,6 +123,43 @@ pub enum ZoneBundleCause {
     ExplicitRequest,
 }
 
+impl ZoneBundleCause {
+    /// Return true if bundles with this cause are created automatically by
+    /// the sled agent, rather than in response to a request.
+    pub fn is_automatic(&self) -> bool {
+        matches!(
+            self,
+            ZoneBundleCause::UnexpectedZone
+                | ZoneBundleCause::TerminatedInstance
+        )
+    }
+}
+
+/// How much detail a zone bundle contains.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    Hash,
+    JsonSchema,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum BundleDetailLevel {
+    /// Only the output of the zone-wide commands, which is quick to collect.
+    Minimal,
+    /// The output of the zone-wide commands, along with that of the commands
+    /// run on each service process and the service log files.
+    #[default]
+    Full,
+}
+
 /// Metadata about a zone bundle.
 #[derive(
     Clone,
@@ -122,13 +182,50 @@ pub struct ZoneBundleMetadata {
     pub version: u8,
     /// The reason or cause a bundle was created.
     pub cause: ZoneBundleCause,
+    /// The SHA-256 hash of the bundle file, as a hex string, if known.
+    ///
+    /// This can't be recorded in the metadata stored inside the bundle itself,
+    /// and is filled in when the bundle is created or indexed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// Free-form annotations attached to the bundle when it was created.
+    ///
+    /// These can be used to group related bundles, e.g., all those collected
+    /// while investigating a single incident. Bundles created before version 1
+    /// have no annotations.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+    /// The zone-wide commands run when the bundle was created, each as the
+    /// binary followed by its arguments.
+    ///
+    /// Bundles created before these were configurable don't record them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zone_wide_commands: Vec<Vec<String>>,
+    /// How much detail the bundle contains.
+    ///
+    /// Bundles created before this was configurable are all full.
+    #[serde(default)]
+    pub detail: BundleDetailLevel,
 }
 
 impl ZoneBundleMetadata {
-    const VERSION: u8 = 0;
+    // The version of the metadata format written by this agent.
+    //
+    // Bump this whenever older agents can't correctly parse the metadata we
+    // write, so they reject the bundle rather than misreading it. New fields
+    // with a default, like `zone_wide_commands` and `detail`, don't need one:
+    // older agents ignore them and still read everything else correctly.
+    //
+    // - 1: Added `annotations`.
+    // - 2: Added the `scheduled` cause.
+    const VERSION: u8 = 2;
 
     /// Create a new set of metadata for the provided zone.
-    pub(crate) fn new(zone_name: &str, cause: ZoneBundleCause) -> Self {
+    pub(crate) fn new(
+        zone_name: &str,
+        cause: ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+    ) -> Self {
         Self {
             id: ZoneBundleId {
                 zone_name: zone_name.to_string(),
@@ -137,8 +234,138 @@ impl ZoneBundleMetadata {
             time_created: Utc::now(),
             version: Self::VERSION,
             cause,
+            content_hash: None,
+            annotations,
+            zone_wide_commands: Vec::new(),
+            detail: BundleDetailLevel::Full,
         }
     }
+
+    /// Return true if the bundle has the provided annotation.
+    pub fn has_annotation(&self, key: &str, value: &str) -> bool {
+        self.annotations.get(key).map(|v| v == value).unwrap_or(false)
+    }
+}
+
+/// The largest entry, in bytes, for which a textual diff is generated when
+/// comparing zone bundles.
+pub const MAX_TEXT_DIFF_SIZE: u64 = 1024 * 1024;
+
+/// The largest file, in bytes, that may be extracted from a zone bundle.
+pub const MAX_EXTRACTED_FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+// The size of each chunk read from a file extracted from a zone bundle.
+const EXTRACTED_FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+// The number of chunks of an extracted file buffered before the reader waits
+// for them to be consumed.
+const EXTRACTED_FILE_CHUNK_COUNT: usize = 4;
+
+/// A single file being extracted from a zone bundle.
+#[derive(Debug)]
+pub struct BundleFile {
+    /// The size of the file, in bytes.
+    pub size: u64,
+    chunks: tokio::sync::mpsc::Receiver<Result<Vec<u8>, std::io::Error>>,
+}
+
+impl BundleFile {
+    /// Return a stream of the file's contents, read in chunks as the stream
+    /// is polled.
+    pub fn into_stream(
+        self,
+    ) -> impl futures::Stream<Item = Result<Vec<u8>, std::io::Error>> {
+        futures::stream::unfold(self.chunks, |mut chunks| async move {
+            chunks.recv().await.map(|chunk| (chunk, chunks))
+        })
+    }
+}
+
+/// How a single entry differs between two bundles of a zone.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BundleEntryDiff {
+    /// The entry is only in the first bundle.
+    OnlyInFirst,
+    /// The entry is only in the second bundle.
+    OnlyInSecond,
+    /// The entry is text in both bundles, and differs as described by the
+    /// unified diff.
+    Text { diff: String },
+    /// The entry differs, but is too large or isn't text, so no diff is
+    /// generated.
+    Differs,
+}
+
+/// The differences between two bundles of the same zone.
+#[derive(Clone, Debug, Deserialize, Eq, JsonSchema, PartialEq, Serialize)]
+pub struct BundleDiff {
+    /// The metadata of the first bundle compared.
+    pub first: ZoneBundleMetadata,
+    /// The metadata of the second bundle compared.
+    pub second: ZoneBundleMetadata,
+    /// The entries which differ between the bundles, by their path.
+    ///
+    /// Entries which are identical in both bundles are omitted, as is the
+    /// metadata stored in each bundle, which always differs.
+    pub entries: BTreeMap<String, BundleEntryDiff>,
+}
+
+/// Configuration for zone bundles, from the sled agent's config file.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneBundleConfig {
+    /// How long, in seconds, each command run when creating a bundle may
+    /// take before it's killed.
+    ///
+    /// If unset, [`ZoneBundler::DEFAULT_COMMAND_TIMEOUT`] is used.
+    pub command_timeout_secs: Option<u64>,
+    /// Whether to run a bundle cleanup as soon as the sled agent starts.
+    ///
+    /// See [`ZoneBundler::cleanup_on_start`].
+    #[serde(default)]
+    pub run_cleanup_on_start: bool,
+    /// Zone name patterns for which bundles are never created automatically.
+    ///
+    /// See [`ZoneBundler::set_auto_bundle_exclusions`].
+    #[serde(default)]
+    pub auto_bundle_exclusions: BTreeSet<String>,
+    /// The zone-wide commands run when creating each bundle, in place of the
+    /// defaults.
+    ///
+    /// See [`ZoneBundler::set_zone_wide_commands`] for the commands allowed.
+    pub zone_wide_commands: Option<Vec<Vec<String>>>,
+    /// The interval, in seconds, at which bundles are captured from each zone
+    /// with a schedule, by zone name.
+    ///
+    /// See [`ZoneBundler::schedule_capture`].
+    #[serde(default)]
+    pub scheduled_captures: BTreeMap<String, u64>,
+    /// Whether every copy of a bundle is written as it's created, rather than
+    /// writing one copy and then copying it to the other bundle directories.
+    ///
+    /// See [`ZoneBundler::set_single_pass_copies`].
+    #[serde(default)]
+    pub single_pass_copies: bool,
+}
+
+/// The zones the sled agent manages, through which bundles the bundler creates
+/// on its own, e.g., of every running zone, include everything that
+/// [`ZoneBundler::create`] collects.
+#[async_trait::async_trait]
+pub trait ManagedZones: Send + Sync {
+    /// Create a bundle from the named zone with [`ZoneBundler::create`].
+    ///
+    /// This returns [`BundleError::NoSuchZone`] if the sled agent isn't
+    /// managing the zone, in which case the bundler falls back to
+    /// [`ZoneBundler::create_by_name`].
+    async fn create_zone_bundle(
+        &self,
+        zone_name: &str,
+        cause: ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+        detail: BundleDetailLevel,
+    ) -> Result<ZoneBundleMetadata, BundleError>;
 }
 
 /// A type managing zone bundle creation and automatic cleanup.
@@ -146,15 +373,24 @@ impl ZoneBundleMetadata {
 pub struct ZoneBundler {
     log: Logger,
     inner: Arc<Mutex<Inner>>,
+    // The bundles currently being read, which must not be recompressed.
+    active_reads: ActiveReads,
     // Channel for notifying the cleanup task that it should reevaluate.
     notify_cleanup: Arc<Notify>,
-    // Tokio task handle running the period cleanup operation.
+    // Tokio task handle supervising the period cleanup operation.
     cleanup_task: Arc<tokio::task::JoinHandle<()>>,
+    // Channel for notifying the capture task that the schedule has changed.
+    notify_schedule: Arc<Notify>,
+    // Tokio task handle for the scheduled capture operation.
+    capture_task: Arc<tokio::task::JoinHandle<()>>,
+    // Metrics about each bundle created.
+    metrics: metrics::ZoneBundleMetrics,
 }
 
 impl Drop for ZoneBundler {
     fn drop(&mut self) {
         self.cleanup_task.abort();
+        self.capture_task.abort();
     }
 }
 
@@ -162,8 +398,34 @@ impl Drop for ZoneBundler {
 // tasks or between a creation and cleanup.
 struct Inner {
     resources: StorageResources,
+    // Directories used for bundles in place of those on the debug datasets.
+    storage_dirs_override: Option<Vec<Utf8PathBuf>>,
     cleanup_context: CleanupContext,
-    last_cleanup_at: Instant,
+    // The time of the last cleanup, or `None` if a cleanup should be run as
+    // soon as possible.
+    last_cleanup_at: Option<Instant>,
+    // Zone name patterns for which bundles are never created automatically.
+    auto_bundle_exclusions: BTreeSet<String>,
+    // The zone-wide commands run when creating each bundle.
+    zone_wide_commands: Vec<Vec<String>>,
+    // How long to wait for each command run when creating a bundle.
+    command_timeout: Duration,
+    // Zones for which bundles are captured periodically, by zone name.
+    scheduled_captures: BTreeMap<String, ScheduledCapture>,
+    // Whether every copy of a bundle is written as it's created.
+    single_pass_copies: bool,
+    // The zones the sled agent manages, once it's started.
+    //
+    // This is weak because the managers of those zones themselves hold the
+    // bundler.
+    managed_zones: Option<Weak<dyn ManagedZones>>,
+}
+
+// The schedule on which bundles are captured for a single zone.
+#[derive(Clone, Copy, Debug)]
+struct ScheduledCapture {
+    interval: Duration,
+    next_capture_at: Instant,
 }
 
 impl Inner {
@@ -172,12 +434,33 @@ impl Inner {
     //
     // The instant may be in the past, in which case duration would be 0.
     fn next_cleanup(&self) -> (Instant, Duration) {
-        let next =
-            self.last_cleanup_at + self.cleanup_context.period.as_duration();
+        let next = match self.last_cleanup_at {
+            Some(last) => last + self.cleanup_context.period.as_duration(),
+            None => Instant::now(),
+        };
         let delta = next.saturating_duration_since(Instant::now());
         (next, delta)
     }
 
+    // Return the time at which the next scheduled capture is due, if any
+    // captures are scheduled at all.
+    fn next_capture(&self) -> Option<Instant> {
+        self.scheduled_captures.values().map(|s| s.next_capture_at).min()
+    }
+
+    // Return the names of the zones whose scheduled capture is due, advancing
+    // each of their schedules by one interval.
+    fn take_due_captures(&mut self, now: Instant) -> Vec<String> {
+        let mut due = Vec::new();
+        for (zone_name, schedule) in self.scheduled_captures.iter_mut() {
+            if schedule.next_capture_at <= now {
+                schedule.next_capture_at = now + schedule.interval;
+                due.push(zone_name.clone());
+            }
+        }
+        due
+    }
+
     // Ensure that the zone bundle directories that _can_ exist in fact do.
     //
     // The zone bundles are stored in a ZFS dataset on each M.2. These datasets
@@ -188,8 +471,14 @@ impl Inner {
     // This method takes the _expected_ zone bundle directories; creates any
     // that can exist but do not, i.e., those whose parent datasets already
     // exist; and returns those.
+    //
+    // If the directories have been overridden, those are used instead, and the
+    // datasets aren't consulted at all.
     async fn bundle_directories(&self) -> Vec<Utf8PathBuf> {
-        let expected = self.resources.all_zone_bundle_directories().await;
+        let expected = match &self.storage_dirs_override {
+            Some(dirs) => dirs.clone(),
+            None => self.resources.all_zone_bundle_directories().await,
+        };
         let mut out = Vec::with_capacity(expected.len());
         for each in expected.into_iter() {
             if tokio::fs::create_dir_all(&each).await.is_ok() {
@@ -201,6 +490,16 @@ impl Inner {
 }
 
 impl ZoneBundler {
+    /// The shortest interval at which bundles may be captured on a schedule.
+    pub const MIN_CAPTURE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+    /// How long each command run when creating a bundle may take, by default.
+    pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+    // How often to check for bundle directories while a cleanup on start is
+    // waiting for them to exist.
+    const START_CLEANUP_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
     // A task run in the background that periodically cleans up bundles.
     //
     // This waits for:
@@ -214,6 +513,7 @@ impl ZoneBundler {
         log: Logger,
         inner: Arc<Mutex<Inner>>,
         notify_cleanup: Arc<Notify>,
+        active_reads: ActiveReads,
     ) {
         let (mut next_cleanup, mut time_to_next_cleanup) =
             inner.lock().await.next_cleanup();
@@ -229,13 +529,47 @@ impl ZoneBundler {
             // context has been changed.
             tokio::select! {
                 _ = sleep(time_to_next_cleanup) => {
-                    info!(log, "running automatic periodic zone bundle cleanup");
                     let mut inner_ = inner.lock().await;
                     let dirs = inner_.bundle_directories().await;
-                    let res = run_cleanup(&log, &dirs, &inner_.cleanup_context).await;
-                    inner_.last_cleanup_at = Instant::now();
+
+                    // A cleanup requested on start may be due before the debug
+                    // datasets exist. Wait for them, rather than "cleaning up"
+                    // nothing and pushing the next cleanup out a full period.
+                    if dirs.is_empty() && inner_.last_cleanup_at.is_none() {
+                        debug!(
+                            log,
+                            "deferring cleanup until bundle directories exist"
+                        );
+                        time_to_next_cleanup =
+                            Self::START_CLEANUP_RETRY_INTERVAL;
+                        next_cleanup = Instant::now() + time_to_next_cleanup;
+                        continue;
+                    }
+                    info!(log, "running automatic periodic zone bundle cleanup");
+                    let context = inner_.cleanup_context;
+                    let res = run_cleanup(&log, &dirs, &context).await;
+                    inner_.last_cleanup_at = Some(Instant::now());
                     (next_cleanup, time_to_next_cleanup) = inner_.next_cleanup();
                     debug!(log, "cleanup completed"; "result" => ?res);
+
+                    // Recompression takes the lock itself, only as needed, so
+                    // that it doesn't block other operations on the bundles.
+                    drop(inner_);
+                    if context.recompress_when_idle {
+                        let reclaimed = run_recompaction(
+                            &log,
+                            &dirs,
+                            &context,
+                            &active_reads,
+                            &*inner,
+                        )
+                        .await;
+                        debug!(
+                            log,
+                            "recompaction completed";
+                            "reclaimed" => ?reclaimed,
+                        );
+                    }
                 }
                 _ = notify_cleanup.notified() => {
                     debug!(log, "notified about cleanup context change");
@@ -246,51 +580,211 @@ impl ZoneBundler {
         }
     }
 
+    // Periodically capture bundles from the zones with a registered schedule.
+    //
+    // This runs until aborted when the bundler is dropped. Each capture is
+    // made by zone name, exactly as by [`ZoneBundler::create_by_name`].
+    async fn periodic_capture(
+        log: Logger,
+        inner: Arc<Mutex<Inner>>,
+        metrics: metrics::ZoneBundleMetrics,
+        notify_schedule: Arc<Notify>,
+    ) {
+        loop {
+            let next_capture = inner.lock().await.next_capture();
+            debug!(
+                log,
+                "top of scheduled capture loop";
+                "next_capture" => ?next_capture,
+            );
+
+            // Wait until the next capture is due, or a notification that the
+            // schedule has changed. With nothing scheduled, only the latter
+            // can wake us.
+            let due = async {
+                match next_capture {
+                    Some(at) => tokio::time::sleep_until(at).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = due => {}
+                _ = notify_schedule.notified() => {
+                    debug!(log, "notified about zone bundle schedule change");
+                    continue;
+                }
+            }
+
+            let due_zones =
+                inner.lock().await.take_due_captures(Instant::now());
+            for zone_name in due_zones.iter() {
+                match Self::create_managed_or_by_name(
+                    &log,
+                    &inner,
+                    &metrics,
+                    zone_name,
+                    ZoneBundleCause::Scheduled,
+                    BTreeMap::new(),
+                    BundleDetailLevel::Full,
+                )
+                .await
+                {
+                    Ok(metadata) => info!(
+                        log,
+                        "created scheduled zone bundle";
+                        "zone_name" => zone_name,
+                        "bundle_id" => %metadata.id.bundle_id,
+                    ),
+                    Err(e) => warn!(
+                        log,
+                        "failed to create scheduled zone bundle";
+                        "zone_name" => zone_name,
+                        "error" => ?e,
+                    ),
+                }
+            }
+        }
+    }
+
     /// Create a new zone bundler.
     ///
     /// This creates an object that manages zone bundles on the system. It can
     /// be used to create bundles from running zones, and runs a period task to
     /// clean them up to free up space.
+    ///
+    /// Bundles are normally stored in the debug datasets on the M.2s, as found
+    /// from `resources`. If `storage_dirs_override` is provided, bundles are
+    /// stored in exactly those directories instead, e.g., for testing or for
+    /// systems with a different storage layout. Note that automatic cleanup
+    /// still requires each directory to be in a ZFS dataset, from which the
+    /// space available to bundles is derived.
     pub fn new(
         log: Logger,
         resources: StorageResources,
         cleanup_context: CleanupContext,
+        storage_dirs_override: Option<Vec<Utf8PathBuf>>,
     ) -> Self {
         let notify_cleanup = Arc::new(Notify::new());
         let inner = Arc::new(Mutex::new(Inner {
             resources,
+            storage_dirs_override,
             cleanup_context,
-            last_cleanup_at: Instant::now(),
+            last_cleanup_at: Some(Instant::now()),
+            auto_bundle_exclusions: BTreeSet::new(),
+            zone_wide_commands: default_zone_wide_commands(),
+            command_timeout: Self::DEFAULT_COMMAND_TIMEOUT,
+            scheduled_captures: BTreeMap::new(),
+            single_pass_copies: false,
+            managed_zones: None,
         }));
         let cleanup_log = log.new(slog::o!("component" => "auto-cleanup-task"));
+        let supervisor_log = cleanup_log.clone();
         let notify_clone = notify_cleanup.clone();
         let inner_clone = inner.clone();
+        let active_reads = ActiveReads::default();
+        let active_reads_clone = active_reads.clone();
         let cleanup_task = Arc::new(tokio::task::spawn(
-            Self::periodic_cleanup(cleanup_log, inner_clone, notify_clone),
+            supervise_cleanup_task(supervisor_log, move || {
+                Self::periodic_cleanup(
+                    cleanup_log.clone(),
+                    inner_clone.clone(),
+                    notify_clone.clone(),
+                    active_reads_clone.clone(),
+                )
+            }),
         ));
-        Self { log, inner, notify_cleanup, cleanup_task }
+        let metrics = metrics::ZoneBundleMetrics::new();
+        let notify_schedule = Arc::new(Notify::new());
+        let capture_task =
+            Arc::new(tokio::task::spawn(Self::periodic_capture(
+                log.new(slog::o!("component" => "scheduled-capture-task")),
+                inner.clone(),
+                metrics.clone(),
+                notify_schedule.clone(),
+            )));
+        Self {
+            log,
+            inner,
+            active_reads,
+            notify_cleanup,
+            cleanup_task,
+            notify_schedule,
+            capture_task,
+            metrics,
+        }
+    }
+
+    /// Run the next automatic cleanup as soon as any bundle directories exist,
+    /// rather than one period from now.
+    ///
+    /// This is intended for use at startup, when bundles left by a previous
+    /// run may already be over the storage limit. The debug datasets holding
+    /// the bundles may not exist yet, in which case the cleanup waits for them.
+    pub async fn cleanup_on_start(&self) {
+        let mut inner = self.inner.lock().await;
+        inner.last_cleanup_at = None;
+        self.notify_cleanup.notify_one();
     }
 
     /// Trigger an immediate cleanup of low-priority zone bundles.
     pub async fn cleanup(
         &self,
-    ) -> Result<BTreeMap<Utf8PathBuf, CleanupCount>, BundleError> {
+    ) -> Result<PartialResult<BTreeMap<Utf8PathBuf, CleanupCount>>, BundleError>
+    {
         let mut inner = self.inner.lock().await;
         let dirs = inner.bundle_directories().await;
         let res = run_cleanup(&self.log, &dirs, &inner.cleanup_context).await;
-        inner.last_cleanup_at = Instant::now();
+        inner.last_cleanup_at = Some(Instant::now());
         self.notify_cleanup.notify_one();
         res
     }
 
+    /// Estimate what an immediate cleanup would do, without removing anything.
+    ///
+    /// This examines the same bundles and makes the same decisions as
+    /// [`ZoneBundler::cleanup`], so operators can decide whether a cleanup is
+    /// worth running now.
+    pub async fn estimate_cleanup(
+        &self,
+    ) -> Result<CleanupEstimate, BundleError> {
+        let inner = self.inner.lock().await;
+        let dirs = inner.bundle_directories().await;
+        let plans =
+            plan_cleanup(&self.log, &dirs, &inner.cleanup_context).await?;
+        Ok(plans.result.values().map(DirectoryCleanupPlan::estimate).sum())
+    }
+
     /// Return the utilization of the system for zone bundles.
     pub async fn utilization(
         &self,
-    ) -> Result<BTreeMap<Utf8PathBuf, BundleUtilization>, BundleError> {
+    ) -> Result<
+        PartialResult<BTreeMap<Utf8PathBuf, BundleUtilization>>,
+        BundleError,
+    > {
         let inner = self.inner.lock().await;
         let dirs = inner.bundle_directories().await;
-        compute_bundle_utilization(&self.log, &dirs, &inner.cleanup_context)
+        let (utilization, errors) = compute_bundle_utilization(
+            &self.log,
+            &dirs,
+            &inner.cleanup_context,
+        )
+        .await;
+        partial_result(&self.log, dirs.len(), utilization, errors)
+    }
+
+    /// Return the utilization of the system for zone bundles, summed across
+    /// all storage directories.
+    ///
+    /// See [`BundleUtilization`]'s implementation of [`std::iter::Sum`] for
+    /// details on how the directories are combined. Directories whose
+    /// utilization couldn't be computed are excluded from the total, and
+    /// reported in the `directory_errors` of the result.
+    pub async fn total_utilization(
+        &self,
+    ) -> Result<PartialResult<BundleUtilization>, BundleError> {
+        self.utilization()
             .await
+            .map(|u| u.map(|result| result.into_values().sum()))
     }
 
     /// Return the context used to periodically clean up zone bundles.
@@ -304,6 +798,8 @@ impl ZoneBundler {
         new_period: Option<CleanupPeriod>,
         new_storage_limit: Option<StorageLimit>,
         new_priority: Option<PriorityOrder>,
+        new_min_keep_per_zone: Option<u32>,
+        new_recompress_when_idle: Option<bool>,
     ) -> Result<(), BundleError> {
         let mut inner = self.inner.lock().await;
         info!(
@@ -312,6 +808,8 @@ impl ZoneBundler {
             "period" => ?new_period,
             "priority" => ?new_priority,
             "storage_limit" => ?new_storage_limit,
+            "min_keep_per_zone" => ?new_min_keep_per_zone,
+            "recompress_when_idle" => ?new_recompress_when_idle,
         );
         let mut notify_cleanup_task = false;
         if let Some(new_period) = new_period {
@@ -339,1441 +837,6412 @@ impl ZoneBundler {
             }
             inner.cleanup_context.storage_limit = new_storage_limit;
         }
+        if let Some(new_min_keep_per_zone) = new_min_keep_per_zone {
+            inner.cleanup_context.min_keep_per_zone = new_min_keep_per_zone;
+        }
+        if let Some(new_recompress_when_idle) = new_recompress_when_idle {
+            inner.cleanup_context.recompress_when_idle =
+                new_recompress_when_idle;
+        }
         if notify_cleanup_task {
             self.notify_cleanup.notify_one();
         }
         Ok(())
     }
 
-    /// Create a bundle from the provided zone.
-    pub async fn create(
+    /// Return the zone name patterns excluded from automatic bundling.
+    pub async fn auto_bundle_exclusions(&self) -> BTreeSet<String> {
+        self.inner.lock().await.auto_bundle_exclusions.clone()
+    }
+
+    /// Replace the zone name patterns excluded from automatic bundling.
+    ///
+    /// A zone matches a pattern if its name contains the pattern anywhere, as
+    /// with the filter in [`ZoneBundler::list`]. Bundles are never created
+    /// automatically for a matching zone (i.e., for an unexpected zone or a
+    /// terminated instance), but explicit requests still create them.
+    pub async fn set_auto_bundle_exclusions(
         &self,
-        zone: &RunningZone,
-        cause: ZoneBundleCause,
-    ) -> Result<ZoneBundleMetadata, BundleError> {
-        let inner = self.inner.lock().await;
-        let storage_dirs = inner.bundle_directories().await;
-        let extra_log_dirs = inner
-            .resources
-            .all_u2_mountpoints(sled_hardware::disk::U2_DEBUG_DATASET)
-            .await
-            .into_iter()
-            .map(|p| p.join(zone.name()))
-            .collect();
-        let context = ZoneBundleContext { cause, storage_dirs, extra_log_dirs };
+        exclusions: BTreeSet<String>,
+    ) {
+        let mut inner = self.inner.lock().await;
         info!(
             self.log,
-            "creating zone bundle";
-            "zone_name" => zone.name(),
-            "context" => ?context,
+            "updating zone auto-bundle exclusions";
+            "exclusions" => ?exclusions,
         );
-        create(&self.log, zone, &context).await
+        inner.auto_bundle_exclusions = exclusions;
     }
 
-    /// Return the paths for all bundles of the provided zone and ID.
-    pub async fn bundle_paths(
+    /// Register a producer of metrics about the bundles created, with the
+    /// provided registry.
+    ///
+    /// For each bundle, this reports the time taken to create it and its size,
+    /// labeled by the cause of the bundle.
+    pub fn register_metrics(
         &self,
-        name: &str,
-        id: &Uuid,
-    ) -> Result<Vec<Utf8PathBuf>, BundleError> {
-        let inner = self.inner.lock().await;
-        let dirs = inner.bundle_directories().await;
-        get_zone_bundle_paths(&self.log, &dirs, name, id).await
+        registry: &ProducerRegistry,
+    ) -> Result<(), MetricsError> {
+        registry.register_producer(self.metrics.clone())
     }
 
-    /// List bundles for a zone with the provided name.
-    pub async fn list_for_zone(
-        &self,
-        name: &str,
-    ) -> Result<Vec<ZoneBundleMetadata>, BundleError> {
-        // The zone bundles are replicated in several places, so we'll use a set
-        // to collect them all, to avoid duplicating.
-        let mut bundles = BTreeSet::new();
-        let inner = self.inner.lock().await;
-        let dirs = inner.bundle_directories().await;
-        for dir in dirs.iter() {
-            bundles.extend(
-                list_bundles_for_zone(&self.log, &dir, name)
-                    .await?
-                    .into_iter()
-                    .map(|(_path, bdl)| bdl),
-            );
-        }
-        Ok(bundles.into_iter().collect())
+    /// Return the zone-wide commands run when creating each bundle.
+    pub async fn zone_wide_commands(&self) -> Vec<Vec<String>> {
+        self.inner.lock().await.zone_wide_commands.clone()
     }
 
-    /// List all zone bundles that match the provided filter, if any.
+    /// Replace the zone-wide commands run when creating each bundle.
     ///
-    /// The filter is a simple substring match -- any zone bundle with a zone
-    /// name that contains the filter anywhere will match. If no filter is
-    /// provided, all extant bundles will be listed.
-    pub async fn list(
+    /// Each command is the binary followed by its arguments, and its output is
+    /// stored in the bundle in a file named for the binary. Only the binaries
+    /// in an allow-list of diagnostic tools may be run, and those which can
+    /// also modify the zone are limited to subcommands that don't. If any
+    /// command isn't allowed, this returns [`BundleError::DisallowedCommand`]
+    /// and leaves the current commands in place.
+    pub async fn set_zone_wide_commands(
         &self,
-        filter: Option<&str>,
-    ) -> Result<Vec<ZoneBundleMetadata>, BundleError> {
-        // The zone bundles are replicated in several places, so we'll use a set
-        // to collect them all, to avoid duplicating.
-        let mut bundles = BTreeSet::new();
-        let inner = self.inner.lock().await;
-        let dirs = inner.bundle_directories().await;
-        for dir in dirs.iter() {
-            let mut rd = tokio::fs::read_dir(dir).await.map_err(|err| {
-                BundleError::ReadDirectory { directory: dir.to_owned(), err }
-            })?;
-            while let Some(entry) = rd.next_entry().await.map_err(|err| {
-                BundleError::ReadDirectory { directory: dir.to_owned(), err }
-            })? {
-                let search_dir = Utf8PathBuf::try_from(entry.path())?;
-                bundles.extend(
-                    filter_zone_bundles(&self.log, &search_dir, |md| {
-                        filter
-                            .map(|filt| md.id.zone_name.contains(filt))
-                            .unwrap_or(true)
-                    })
-                    .await?
-                    .into_values(),
-                );
-            }
+        commands: Vec<Vec<String>>,
+    ) -> Result<(), BundleError> {
+        for command in commands.iter() {
+            validate_zone_wide_command(command)?;
         }
-        Ok(bundles.into_iter().collect())
+        let mut inner = self.inner.lock().await;
+        info!(
+            self.log,
+            "updating zone-wide bundle commands";
+            "commands" => ?commands,
+        );
+        inner.zone_wide_commands = commands;
+        Ok(())
     }
-}
 
-// Context for creating a bundle of a specified zone.
-#[derive(Debug, Default)]
-struct ZoneBundleContext {
-    // The directories into which the zone bundles are written.
-    storage_dirs: Vec<Utf8PathBuf>,
-    // The reason or cause for creating a zone bundle.
-    cause: ZoneBundleCause,
-    // Extra directories searched for logfiles for the name zone.
-    //
-    // Logs are periodically archived out of their original location, and onto
-    // one or more U.2 drives. This field is used to specify that archive
-    // location, so that rotated logs for the zone's services may be found.
-    extra_log_dirs: Vec<Utf8PathBuf>,
-}
+    /// Return whether every copy of a bundle is written as it's created.
+    pub async fn single_pass_copies(&self) -> bool {
+        self.inner.lock().await.single_pass_copies
+    }
 
-// The set of zone-wide commands, which don't require any details about the
-// processes we've launched in the zone.
-const ZONE_WIDE_COMMANDS: [&[&str]; 6] = [
-    &["ptree"],
-    &["uptime"],
-    &["last"],
-    &["who"],
-    &["svcs", "-p"],
-    &["netstat", "-an"],
-];
+    /// Set whether every copy of a bundle is written as it's created.
+    ///
+    /// By default, a bundle is written into the first bundle directory, and
+    /// then copied into the others. Writing every copy as the bundle is
+    /// created instead avoids reading it back to copy it. Either way, a copy
+    /// which can't be written is dropped, keeping the others.
+    pub async fn set_single_pass_copies(&self, single_pass: bool) {
+        let mut inner = self.inner.lock().await;
+        info!(
+            self.log,
+            "updating how bundle copies are written";
+            "single_pass" => single_pass,
+        );
+        inner.single_pass_copies = single_pass;
+    }
 
-// The name for zone bundle metadata files.
-const ZONE_BUNDLE_METADATA_FILENAME: &str = "metadata.toml";
+    /// Return how long each command run when creating a bundle may take.
+    pub async fn command_timeout(&self) -> Duration {
+        self.inner.lock().await.command_timeout
+    }
 
-/// Errors related to managing service zone bundles.
-#[derive(Debug, thiserror::Error)]
-pub enum BundleError {
-    #[error("I/O error running command '{cmd}'")]
-    Command {
-        cmd: String,
-        #[source]
-        err: std::io::Error,
-    },
+    /// Set how long each command run when creating a bundle may take.
+    ///
+    /// A command that takes longer is killed, along with anything it started,
+    /// and the bundle records that it timed out in place of its output.
+    pub async fn set_command_timeout(&self, timeout: Duration) {
+        let mut inner = self.inner.lock().await;
+        info!(
+            self.log,
+            "updating zone bundle command timeout";
+            "timeout" => ?timeout,
+        );
+        inner.command_timeout = timeout;
+    }
 
-    #[error("I/O error creating directory '{directory}'")]
-    CreateDirectory {
-        directory: Utf8PathBuf,
-        #[source]
-        err: std::io::Error,
-    },
+    /// Return the interval at which bundles are captured for each zone with a
+    /// schedule.
+    pub async fn scheduled_captures(&self) -> BTreeMap<String, Duration> {
+        self.inner
+            .lock()
+            .await
+            .scheduled_captures
+            .iter()
+            .map(|(zone_name, s)| (zone_name.clone(), s.interval))
+            .collect()
+    }
+
+    /// Capture a bundle from the named zone every `interval`.
+    ///
+    /// The first bundle is captured one interval from now, and replaces any
+    /// existing schedule for the zone. Bundles are captured as by
+    /// [`ZoneBundler::create_all`], through the sled agent if it manages the
+    /// zone and by name if not. They count against the same storage limit as
+    /// all other bundles, and are among the first removed during cleanup.
+    pub async fn schedule_capture(
+        &self,
+        zone_name: &str,
+        interval: Duration,
+    ) -> Result<(), BundleError> {
+        if interval < Self::MIN_CAPTURE_INTERVAL {
+            return Err(BundleError::InvalidCaptureInterval);
+        }
+        let mut inner = self.inner.lock().await;
+        info!(
+            self.log,
+            "scheduling zone bundle captures";
+            "zone_name" => zone_name,
+            "interval" => ?interval,
+        );
+        let schedule = ScheduledCapture {
+            interval,
+            next_capture_at: Instant::now() + interval,
+        };
+        inner.scheduled_captures.insert(zone_name.to_string(), schedule);
+        self.notify_schedule.notify_one();
+        Ok(())
+    }
+
+    /// Stop capturing bundles from the named zone on a schedule.
+    ///
+    /// Return true if the zone had a schedule.
+    pub async fn unschedule_capture(&self, zone_name: &str) -> bool {
+        let mut inner = self.inner.lock().await;
+        let removed = inner.scheduled_captures.remove(zone_name).is_some();
+        if removed {
+            info!(
+                self.log,
+                "unscheduled zone bundle captures";
+                "zone_name" => zone_name,
+            );
+            self.notify_schedule.notify_one();
+        }
+        removed
+    }
+
+    /// Create a bundle from the provided zone.
+    ///
+    /// If the bundle would be created automatically and the zone has been
+    /// excluded from automatic bundling, this returns
+    /// [`BundleError::AutoBundleExcluded`] without creating anything.
+    ///
+    /// A [`BundleDetailLevel::Minimal`] bundle contains only the output of the
+    /// zone-wide commands, skipping the slower introspection of each service
+    /// process and the copying of its logs.
+    pub async fn create(
+        &self,
+        zone: &RunningZone,
+        cause: ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+        detail: BundleDetailLevel,
+    ) -> Result<ZoneBundleMetadata, BundleError> {
+        Self::create_impl(
+            &self.log,
+            &self.inner,
+            &self.metrics,
+            &BundleZone::Running(zone),
+            cause,
+            annotations,
+            detail,
+        )
+        .await
+    }
+
+    /// Create a bundle from a zone the sled agent isn't currently managing.
+    ///
+    /// The zone is looked up by name at the OS level, and may be in any state,
+    /// e.g., left half-created or half-destroyed. Without the metadata of a
+    /// [`RunningZone`], the bundle contains only the output of the zone-wide
+    /// commands and any log files that can be found for the zone. If the OS
+    /// has no zone with the provided name, this returns
+    /// [`BundleError::NoSuchZone`].
+    pub async fn create_by_name(
+        &self,
+        zone_name: &str,
+        cause: ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+        detail: BundleDetailLevel,
+    ) -> Result<ZoneBundleMetadata, BundleError> {
+        Self::create_by_name_impl(
+            &self.log,
+            &self.inner,
+            &self.metrics,
+            zone_name,
+            cause,
+            annotations,
+            detail,
+        )
+        .await
+    }
+
+    /// Set the zones the sled agent manages.
+    ///
+    /// Bundles the bundler creates on its own are created from these zones
+    /// with [`ZoneBundler::create`], and only zones the sled agent doesn't
+    /// manage are bundled by name.
+    pub async fn set_managed_zones(&self, zones: Weak<dyn ManagedZones>) {
+        self.inner.lock().await.managed_zones = Some(zones);
+    }
+
+    /// Create a bundle from every running zone on the sled.
+    ///
+    /// Zones the sled agent manages are bundled as by [`ZoneBundler::create`],
+    /// and any others by name, as by [`ZoneBundler::create_by_name`]. Zones are
+    /// bundled one at a time, like any other request for a bundle. A cleanup is
+    /// run after each bundle, so that capturing many zones at once stays within
+    /// the storage limit. A failure to bundle one zone is logged and doesn't
+    /// prevent bundling the rest.
+    pub async fn create_all(
+        &self,
+        cause: ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+        detail: BundleDetailLevel,
+    ) -> Result<Vec<ZoneBundleMetadata>, BundleError> {
+        let zone_names = Zones::running_names().await?;
+        info!(
+            self.log,
+            "creating zone bundles for all running zones";
+            "zones" => ?zone_names,
+            "cause" => ?cause,
+        );
+        let mut bundles = Vec::with_capacity(zone_names.len());
+        for zone_name in zone_names.iter() {
+            match Self::create_managed_or_by_name(
+                &self.log,
+                &self.inner,
+                &self.metrics,
+                zone_name,
+                cause,
+                annotations.clone(),
+                detail,
+            )
+            .await
+            {
+                Ok(metadata) => bundles.push(metadata),
+                Err(e) => {
+                    error!(
+                        self.log,
+                        "failed to create zone bundle";
+                        "zone_name" => zone_name,
+                        "error" => ?e,
+                    );
+                    continue;
+                }
+            }
+            let mut inner = self.inner.lock().await;
+            let dirs = inner.bundle_directories().await;
+            if let Err(e) =
+                run_cleanup(&self.log, &dirs, &inner.cleanup_context).await
+            {
+                warn!(
+                    self.log,
+                    "failed to clean up zone bundles";
+                    "error" => ?e,
+                );
+            }
+            inner.last_cleanup_at = Some(Instant::now());
+        }
+        self.notify_cleanup.notify_one();
+        Ok(bundles)
+    }
+
+    // Create a bundle from the named zone through the sled agent if it manages
+    // the zone, or by name if not.
+    async fn create_managed_or_by_name(
+        log: &Logger,
+        inner: &Mutex<Inner>,
+        metrics: &metrics::ZoneBundleMetrics,
+        zone_name: &str,
+        cause: ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+        detail: BundleDetailLevel,
+    ) -> Result<ZoneBundleMetadata, BundleError> {
+        // Don't hold the lock while bundling through the sled agent, which
+        // takes it again to create the bundle.
+        let managed_zones =
+            inner.lock().await.managed_zones.as_ref().and_then(Weak::upgrade);
+        if let Some(managed_zones) = managed_zones {
+            match managed_zones
+                .create_zone_bundle(
+                    zone_name,
+                    cause,
+                    annotations.clone(),
+                    detail,
+                )
+                .await
+            {
+                Err(BundleError::NoSuchZone { .. }) => {}
+                result => return result,
+            }
+        }
+        Self::create_by_name_impl(
+            log,
+            inner,
+            metrics,
+            zone_name,
+            cause,
+            annotations,
+            detail,
+        )
+        .await
+    }
+
+    async fn create_by_name_impl(
+        log: &Logger,
+        inner: &Mutex<Inner>,
+        metrics: &metrics::ZoneBundleMetrics,
+        zone_name: &str,
+        cause: ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+        detail: BundleDetailLevel,
+    ) -> Result<ZoneBundleMetadata, BundleError> {
+        let Some(zonepath) = Zones::zonepath(zone_name).await? else {
+            return Err(BundleError::NoSuchZone {
+                name: zone_name.to_string(),
+            });
+        };
+        let zone = BundleZone::Unmanaged {
+            name: zone_name,
+            zonepath: Utf8PathBuf::try_from(zonepath)?,
+        };
+        Self::create_impl(
+            log,
+            inner,
+            metrics,
+            &zone,
+            cause,
+            annotations,
+            detail,
+        )
+        .await
+    }
+
+    // Create a bundle from either kind of zone.
+    //
+    // This takes the bundler's parts rather than `&self`, so that it can also
+    // be called from the scheduled capture task.
+    async fn create_impl(
+        log: &Logger,
+        inner: &Mutex<Inner>,
+        metrics: &metrics::ZoneBundleMetrics,
+        zone: &BundleZone<'_>,
+        cause: ZoneBundleCause,
+        annotations: BTreeMap<String, String>,
+        detail: BundleDetailLevel,
+    ) -> Result<ZoneBundleMetadata, BundleError> {
+        let inner = inner.lock().await;
+        if is_excluded_from_auto_bundle(
+            &inner.auto_bundle_exclusions,
+            zone.name(),
+            cause,
+        ) {
+            info!(
+                log,
+                "skipping automatic zone bundle for excluded zone";
+                "zone_name" => zone.name(),
+                "cause" => ?cause,
+            );
+            return Err(BundleError::AutoBundleExcluded {
+                name: zone.name().to_string(),
+            });
+        }
+        let storage_dirs = inner.bundle_directories().await;
+        let extra_log_dirs = inner
+            .resources
+            .all_u2_mountpoints(sled_hardware::disk::U2_DEBUG_DATASET)
+            .await
+            .into_iter()
+            .map(|p| p.join(zone.name()))
+            .collect();
+        // If bundles are recompressed while idle, save time now and leave the
+        // expensive compression until then.
+        let compression = if inner.cleanup_context.recompress_when_idle {
+            flate2::Compression::fast()
+        } else {
+            flate2::Compression::best()
+        };
+        let context = ZoneBundleContext {
+            cause,
+            storage_dirs,
+            extra_log_dirs,
+            annotations,
+            compression,
+            zone_wide_commands: inner.zone_wide_commands.clone(),
+            command_timeout: inner.command_timeout,
+            detail,
+            single_pass_copies: inner.single_pass_copies,
+        };
+        info!(
+            log,
+            "creating zone bundle";
+            "zone_name" => zone.name(),
+            "context" => ?context,
+        );
+        let start = Instant::now();
+        let info = create(log, zone, &context).await?;
+        let duration = start.elapsed();
+        if let Err(e) = metrics.record(zone.name(), cause, duration, info.bytes)
+        {
+            warn!(
+                log,
+                "failed to record zone bundle metrics";
+                "zone_name" => zone.name(),
+                "error" => ?e,
+            );
+        }
+        Ok(info.metadata)
+    }
+
+    /// Extract a single file from the bundle of the provided zone and ID.
+    ///
+    /// The bundle is only decompressed as far as the requested file, and that
+    /// file's contents are then streamed out in chunks, rather than read into
+    /// memory. Files larger than [`MAX_EXTRACTED_FILE_SIZE`] are rejected.
+    pub async fn extract_file(
+        &self,
+        name: &str,
+        id: &Uuid,
+        entry_path: &str,
+    ) -> Result<BundleFile, BundleError> {
+        let Some(path) = self.bundle_paths(name, id).await?.into_iter().next()
+        else {
+            return Err(BundleError::NoSuchBundle {
+                zone_name: name.to_string(),
+                bundle_id: *id,
+            });
+        };
+        debug!(
+            self.log,
+            "extracting file from zone bundle";
+            "path" => %path,
+            "entry_path" => entry_path,
+        );
+        let guard = self.active_reads.start(&path);
+        let id = *id;
+        let entry_path = entry_path.to_string();
+
+        // The blocking task reports whether it found the file, and then sends
+        // its contents over a bounded channel, so that at most a few chunks
+        // are held in memory at once.
+        let (found_tx, found_rx) = tokio::sync::oneshot::channel();
+        let (chunk_tx, chunks) =
+            tokio::sync::mpsc::channel(EXTRACTED_FILE_CHUNK_COUNT);
+        let _task = tokio::task::spawn_blocking(move || {
+            let _guard = guard;
+            let mut found_tx = Some(found_tx);
+            let result = extract_zone_bundle_file_impl(
+                &path,
+                &id,
+                &entry_path,
+                MAX_EXTRACTED_FILE_SIZE,
+                |size, entry| {
+                    let found_tx = found_tx.take().unwrap();
+                    if found_tx.send(Ok(size)).is_err() {
+                        return;
+                    }
+                    let mut buf = vec![0; EXTRACTED_FILE_CHUNK_SIZE];
+                    loop {
+                        let chunk = match entry.read(&mut buf) {
+                            Ok(0) => return,
+                            Ok(n) => Ok(buf[..n].to_vec()),
+                            Err(e) => Err(e),
+                        };
+                        let failed = chunk.is_err();
+                        // Stop reading if the receiver has gone away, e.g.,
+                        // because the client disconnected.
+                        if chunk_tx.blocking_send(chunk).is_err() || failed {
+                            return;
+                        }
+                    }
+                },
+            );
+            if let (Err(e), Some(found_tx)) = (result, found_tx) {
+                let _ = found_tx.send(Err(e));
+            }
+        });
+        let size = found_rx.await.map_err(|_| {
+            BundleError::BundleFailed(anyhow!(
+                "zone bundle extraction task exited unexpectedly"
+            ))
+        })??;
+        Ok(BundleFile { size, chunks })
+    }
+
+    /// Compare two bundles of the provided zone.
+    ///
+    /// This reads both bundles in full, and reports which entries differ
+    /// between them, e.g., command output or log files. Text entries up to
+    /// [`MAX_TEXT_DIFF_SIZE`] bytes include a unified diff, while larger or
+    /// binary entries are only reported as differing.
+    pub async fn diff(
+        &self,
+        name: &str,
+        first: &Uuid,
+        second: &Uuid,
+    ) -> Result<BundleDiff, BundleError> {
+        let no_such_bundle = |bundle_id: &Uuid| BundleError::NoSuchBundle {
+            zone_name: name.to_string(),
+            bundle_id: *bundle_id,
+        };
+        let Some((first_guard, first_metadata)) =
+            self.find_bundle(name, first).await?
+        else {
+            return Err(no_such_bundle(first));
+        };
+        let Some((second_guard, second_metadata)) =
+            self.find_bundle(name, second).await?
+        else {
+            return Err(no_such_bundle(second));
+        };
+        debug!(
+            self.log,
+            "comparing zone bundles";
+            "first" => %first_guard.path(),
+            "second" => %second_guard.path(),
+        );
+        let task = tokio::task::spawn_blocking(move || {
+            diff_zone_bundles_impl(first_guard.path(), second_guard.path())
+        });
+        let entries = task.await??;
+        Ok(BundleDiff {
+            first: first_metadata,
+            second: second_metadata,
+            entries,
+        })
+    }
+
+    /// Return the paths for all bundles of the provided zone and ID.
+    pub async fn bundle_paths(
+        &self,
+        name: &str,
+        id: &Uuid,
+    ) -> Result<Vec<Utf8PathBuf>, BundleError> {
+        validate_zone_name(name)?;
+        let inner = self.inner.lock().await;
+        let dirs = inner.bundle_directories().await;
+        let (bundles, errors) =
+            get_zone_bundles(&self.log, &dirs, name, id).await;
+        let bundles = partial_result(&self.log, dirs.len(), bundles, errors)?;
+        Ok(bundles.result.into_iter().map(|(path, _metadata)| path).collect())
+    }
+
+    /// Return the path and metadata of a bundle of the provided zone and ID,
+    /// if one exists.
+    ///
+    /// Bundles are replicated in several places, and this returns the first
+    /// copy found. The bundle is marked as being read until the returned guard
+    /// is dropped.
+    pub async fn find_bundle(
+        &self,
+        name: &str,
+        id: &Uuid,
+    ) -> Result<Option<(BundleReadGuard, ZoneBundleMetadata)>, BundleError>
+    {
+        validate_zone_name(name)?;
+        let inner = self.inner.lock().await;
+        let dirs = inner.bundle_directories().await;
+        let (bundles, errors) =
+            get_zone_bundles(&self.log, &dirs, name, id).await;
+        let bundles = partial_result(&self.log, dirs.len(), bundles, errors)?;
+        Ok(bundles
+            .result
+            .into_iter()
+            .next()
+            .map(|(path, md)| (self.active_reads.start(&path), md)))
+    }
+
+    /// List bundles for a zone with the provided name.
+    pub async fn list_for_zone(
+        &self,
+        name: &str,
+    ) -> Result<PartialResult<Vec<ZoneBundleMetadata>>, BundleError> {
+        validate_zone_name(name)?;
+        // The zone bundles are replicated in several places, so we'll use a set
+        // to collect them all, to avoid duplicating.
+        let mut bundles = BTreeSet::new();
+        let mut errors = DirectoryErrors::new();
+        let inner = self.inner.lock().await;
+        let dirs = inner.bundle_directories().await;
+        for dir in dirs.iter() {
+            match list_bundles_for_zone(&self.log, &dir, name).await {
+                Ok(found) => {
+                    bundles.extend(found.into_iter().map(|(_path, bdl)| bdl))
+                }
+                Err(e) => {
+                    errors.insert(dir.clone(), e);
+                }
+            }
+        }
+        partial_result(
+            &self.log,
+            dirs.len(),
+            bundles.into_iter().collect(),
+            errors,
+        )
+    }
+
+    /// Delete all bundles for the provided zone, from every storage
+    /// directory.
+    ///
+    /// Bundles are replicated in several places, and each copy removed is
+    /// counted separately.
+    pub async fn delete_for_zone(
+        &self,
+        name: &str,
+    ) -> Result<PartialResult<CleanupCount>, BundleError> {
+        validate_zone_name(name)?;
+        let inner = self.inner.lock().await;
+        let dirs = inner.bundle_directories().await;
+        let (count, errors) =
+            delete_bundles_for_zone(&self.log, &dirs, name).await;
+        partial_result(&self.log, dirs.len(), count, errors)
+    }
+
+    /// List all zone bundles that match the provided filter, if any.
+    ///
+    /// The filter is a simple substring match -- any zone bundle with a zone
+    /// name that contains the filter anywhere will match. If an annotation is
+    /// provided as a `(key, value)` pair, only bundles with exactly that
+    /// annotation will match. If neither is provided, all extant bundles will
+    /// be listed.
+    pub async fn list(
+        &self,
+        filter: Option<&str>,
+        annotation: Option<(&str, &str)>,
+    ) -> Result<PartialResult<Vec<ZoneBundleMetadata>>, BundleError> {
+        let inner = self.inner.lock().await;
+        let dirs = inner.bundle_directories().await;
+        let (bundles, errors) = list_zone_bundles(&self.log, &dirs, |md| {
+            bundle_matches(md, filter, annotation)
+        })
+        .await;
+        partial_result(&self.log, dirs.len(), dedup_by_id(bundles), errors)
+    }
+
+    /// List a page of the zone bundles that match the provided filter, if any.
+    ///
+    /// Bundles are listed in order of their IDs, starting after `last_seen`
+    /// when it's provided, and filtered as in [`ZoneBundler::list`]. Each
+    /// bundle is mirrored into every storage directory, so this pages over the
+    /// logical set of bundles: a bundle appears once, however many directories
+    /// it's in.
+    ///
+    /// Only the indexes of the zones in the page are read, so each page costs
+    /// about the same however many bundles precede it.
+    pub async fn list_page(
+        &self,
+        filter: Option<&str>,
+        annotation: Option<(&str, &str)>,
+        last_seen: Option<&ZoneBundleId>,
+        limit: usize,
+    ) -> Result<PartialResult<Vec<ZoneBundleMetadata>>, BundleError> {
+        let inner = self.inner.lock().await;
+        let dirs = inner.bundle_directories().await;
+        let (bundles, errors) = list_zone_bundles_page(
+            &self.log,
+            &dirs,
+            |md| bundle_matches(md, filter, annotation),
+            last_seen,
+            limit,
+        )
+        .await;
+        partial_result(&self.log, dirs.len(), bundles, errors)
+    }
+}
+
+// Return one copy of each of the provided bundles, in order of their IDs.
+//
+// The copies of a bundle in different storage directories may not have quite
+// the same metadata, e.g., if only one has been indexed with its content hash.
+// Since metadata are ordered by ID first, any such copies are adjacent.
+fn dedup_by_id(
+    bundles: BTreeSet<ZoneBundleMetadata>,
+) -> Vec<ZoneBundleMetadata> {
+    let mut bundles: Vec<_> = bundles.into_iter().collect();
+    bundles.dedup_by(|a, b| a.id == b.id);
+    bundles
+}
+
+// Return true if the bundle matches the zone name filter and annotation, when
+// either is provided.
+pub(crate) fn bundle_matches(
+    md: &ZoneBundleMetadata,
+    filter: Option<&str>,
+    annotation: Option<(&str, &str)>,
+) -> bool {
+    filter.map(|filt| md.id.zone_name.contains(filt)).unwrap_or(true)
+        && annotation
+            .map(|(key, value)| md.has_annotation(key, value))
+            .unwrap_or(true)
+}
+
+// The bundles currently being read, with the number of readers of each.
+#[derive(Clone, Debug, Default)]
+struct ActiveReads(Arc<std::sync::Mutex<BTreeMap<Utf8PathBuf, usize>>>);
+
+impl ActiveReads {
+    // Mark the bundle at `path` as being read, until the guard is dropped.
+    fn start(&self, path: &Utf8Path) -> BundleReadGuard {
+        *self.0.lock().unwrap().entry(path.to_owned()).or_default() += 1;
+        BundleReadGuard { path: path.to_owned(), reads: self.clone() }
+    }
+
+    // Return true if the bundle at `path` is being read.
+    fn contains(&self, path: &Utf8Path) -> bool {
+        self.0.lock().unwrap().contains_key(path)
+    }
+}
+
+/// A guard marking a zone bundle as being read.
+///
+/// Bundles are never recompressed while they're being read.
+#[derive(Debug)]
+pub struct BundleReadGuard {
+    path: Utf8PathBuf,
+    reads: ActiveReads,
+}
+
+impl BundleReadGuard {
+    /// Return the path to the bundle being read.
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+}
+
+impl Drop for BundleReadGuard {
+    fn drop(&mut self) {
+        let mut reads = self.reads.0.lock().unwrap();
+        if let Some(count) = reads.get_mut(&self.path) {
+            *count -= 1;
+            if *count == 0 {
+                reads.remove(&self.path);
+            }
+        }
+    }
+}
+
+/// Errors encountered reading individual storage directories, keyed by the
+/// directory.
+pub type DirectoryErrors = BTreeMap<Utf8PathBuf, BundleError>;
+
+/// The results of an operation across all zone bundle storage directories.
+///
+/// Zone bundles are replicated across the storage directories on each M.2. If
+/// one of those can't be read, e.g., because its disk has faulted, operations
+/// skip it and report the error alongside the results from the others, rather
+/// than failing entirely.
+#[derive(Debug)]
+pub struct PartialResult<T> {
+    /// The results from the directories that could be read.
+    pub result: T,
+    /// The errors from the directories that were skipped, if any.
+    pub directory_errors: DirectoryErrors,
+}
+
+impl<T> PartialResult<T> {
+    /// Apply a function to the results, keeping the directory errors.
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> PartialResult<U> {
+        PartialResult {
+            result: f(self.result),
+            directory_errors: self.directory_errors,
+        }
+    }
+}
+
+// Log any storage directories that were skipped, and return the results from
+// the remainder, along with those errors.
+//
+// If every one of the `n_dirs` directories failed, there are no results to
+// speak of, and an error is returned instead.
+fn partial_result<T>(
+    log: &Logger,
+    n_dirs: usize,
+    result: T,
+    errors: DirectoryErrors,
+) -> Result<PartialResult<T>, BundleError> {
+    for (directory, err) in errors.iter() {
+        warn!(
+            log,
+            "skipping unreadable zone bundle directory";
+            "directory" => %directory,
+            "reason" => ?err,
+        );
+    }
+    if n_dirs > 0 && errors.len() == n_dirs {
+        return Err(errors.into_values().next().unwrap());
+    }
+    Ok(PartialResult { result, directory_errors: errors })
+}
+
+// Return true if a bundle of the named zone, for the provided cause, should be
+// skipped because the zone is excluded from automatic bundling.
+fn is_excluded_from_auto_bundle(
+    exclusions: &BTreeSet<String>,
+    zone_name: &str,
+    cause: ZoneBundleCause,
+) -> bool {
+    cause.is_automatic()
+        && exclusions.iter().any(|pattern| zone_name.contains(pattern.as_str()))
+}
+
+// A task handle which aborts the task when dropped.
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+// How long the cleanup task must run before exiting for its supervisor to
+// consider it healthy, and restart it without any accumulated backoff.
+const CLEANUP_TASK_HEALTHY_RUNTIME: Duration = Duration::from_secs(10 * 60);
+
+// Run the task created by `start_task`, restarting it whenever it exits.
+//
+// The periodic cleanup task is the only thing which keeps zone bundles from
+// filling their datasets, so if it panics, it is restarted (after a backoff, in
+// case it panics again right away) rather than letting cleanup silently stop.
+// The backoff is reset once the task has run for a while, so that occasional
+// panics far apart don't each wait as long as the last.
+//
+// The supervised task is aborted when this future is dropped, e.g., when the
+// task running it is aborted as the `ZoneBundler` is dropped.
+async fn supervise_cleanup_task<F, Fut>(log: Logger, mut start_task: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut backoff = retry_policy_internal_service();
+    loop {
+        let started_at = Instant::now();
+        let mut task = AbortOnDrop(tokio::task::spawn(start_task()));
+        match (&mut task.0).await {
+            Ok(()) => {
+                error!(
+                    log,
+                    "zone bundle cleanup task exited unexpectedly, \
+                    it will be restarted"
+                );
+            }
+            Err(e) if e.is_panic() => {
+                error!(
+                    log,
+                    "zone bundle cleanup task panicked, \
+                    it will be restarted";
+                    "error" => %e,
+                );
+            }
+            Err(e) => {
+                warn!(
+                    log,
+                    "zone bundle cleanup task was cancelled, \
+                    no longer supervising it";
+                    "error" => %e,
+                );
+                return;
+            }
+        }
+        if started_at.elapsed() >= CLEANUP_TASK_HEALTHY_RUNTIME {
+            backoff.reset();
+        }
+        let delay = backoff.next_backoff().unwrap_or(backoff.max_interval);
+        warn!(
+            log,
+            "restarting zone bundle cleanup task";
+            "delay" => ?delay,
+        );
+        sleep(delay).await;
+    }
+}
+
+// Oximeter timeseries names are derived from the names of the target and
+// metric types, so they're kept in their own module.
+mod metrics {
+    use super::ZoneBundleCause;
+    use oximeter::MetricsError;
+    use oximeter::Sample;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// A zone from which bundles are created.
+    #[derive(Debug, Clone, oximeter::Target)]
+    struct ZoneBundle {
+        zone_name: String,
+    }
+
+    /// The time taken to create a bundle, in seconds.
+    #[derive(Debug, Clone, oximeter::Metric)]
+    struct CreationDuration {
+        cause: String,
+        #[datum]
+        seconds: f64,
+    }
+
+    /// The size of a newly-created bundle, in bytes.
+    #[derive(Debug, Clone, oximeter::Metric)]
+    struct Size {
+        cause: String,
+        #[datum]
+        bytes: u64,
+    }
+
+    // Return the label used for a bundle's cause, matching its serialized
+    // form.
+    fn cause_label(cause: ZoneBundleCause) -> &'static str {
+        match cause {
+            ZoneBundleCause::Other => "other",
+            ZoneBundleCause::Scheduled => "scheduled",
+            ZoneBundleCause::UnexpectedZone => "unexpected_zone",
+            ZoneBundleCause::TerminatedInstance => "terminated_instance",
+            ZoneBundleCause::ExplicitRequest => "explicit_request",
+        }
+    }
+
+    // The most samples kept between collections. Past this, the oldest are
+    // dropped, so that nothing grows without bound if samples aren't collected.
+    pub(super) const MAX_SAMPLES: usize = 1024;
+
+    /// A producer of samples for each bundle created.
+    ///
+    /// Samples are recorded as each bundle is created, and drained when
+    /// collected. At most [`MAX_SAMPLES`] are kept between collections.
+    #[derive(Debug, Default, Clone)]
+    pub(super) struct ZoneBundleMetrics {
+        samples: Arc<Mutex<Vec<Sample>>>,
+    }
+
+    impl ZoneBundleMetrics {
+        pub(super) fn new() -> Self {
+            Self::default()
+        }
+
+        // Record the samples for a newly-created bundle.
+        pub(super) fn record(
+            &self,
+            zone_name: &str,
+            cause: ZoneBundleCause,
+            duration: Duration,
+            bytes: u64,
+        ) -> Result<(), MetricsError> {
+            let target = ZoneBundle { zone_name: zone_name.to_string() };
+            let cause = cause_label(cause).to_string();
+            let mut new_samples = vec![
+                Sample::new(
+                    &target,
+                    &CreationDuration {
+                        cause: cause.clone(),
+                        seconds: duration.as_secs_f64(),
+                    },
+                )?,
+                Sample::new(&target, &Size { cause, bytes })?,
+            ];
+            let mut samples = self.samples.lock().unwrap();
+            samples.append(&mut new_samples);
+            let excess = samples.len().saturating_sub(MAX_SAMPLES);
+            samples.drain(..excess);
+            Ok(())
+        }
+    }
+
+    impl oximeter::Producer for ZoneBundleMetrics {
+        fn produce(
+            &mut self,
+        ) -> Result<Box<dyn Iterator<Item = Sample> + 'static>, MetricsError>
+        {
+            let samples = std::mem::take(&mut *self.samples.lock().unwrap());
+            Ok(Box::new(samples.into_iter()))
+        }
+    }
+}
+
+// Context for creating a bundle of a specified zone.
+#[derive(Debug, Default)]
+struct ZoneBundleContext {
+    // The directories into which the zone bundles are written.
+    storage_dirs: Vec<Utf8PathBuf>,
+    // The reason or cause for creating a zone bundle.
+    cause: ZoneBundleCause,
+    // Extra directories searched for logfiles for the name zone.
+    //
+    // Logs are periodically archived out of their original location, and onto
+    // one or more U.2 drives. This field is used to specify that archive
+    // location, so that rotated logs for the zone's services may be found.
+    extra_log_dirs: Vec<Utf8PathBuf>,
+    // Annotations recorded in the bundle's metadata.
+    annotations: BTreeMap<String, String>,
+    // The level at which the bundle is compressed.
+    compression: flate2::Compression,
+    // The zone-wide commands run in the zone.
+    zone_wide_commands: Vec<Vec<String>>,
+    // How long to wait for each command run in the zone.
+    command_timeout: Duration,
+    // How much detail to include in the bundle.
+    detail: BundleDetailLevel,
+    // Whether every copy of the bundle is written as it's created, rather than
+    // just the first.
+    single_pass_copies: bool,
+}
+
+// A zone from which a bundle is created.
+enum BundleZone<'a> {
+    // A zone the sled agent is managing, along with its service metadata.
+    Running(&'a RunningZone),
+    // A zone that exists at the OS level, but which the sled agent isn't
+    // currently managing.
+    Unmanaged { name: &'a str, zonepath: Utf8PathBuf },
+}
+
+impl BundleZone<'_> {
+    fn name(&self) -> &str {
+        match self {
+            BundleZone::Running(zone) => zone.name(),
+            BundleZone::Unmanaged { name, .. } => name,
+        }
+    }
+
+    // Run a command in the zone, returning its output or a description of the
+    // failure.
+    //
+    // If the command doesn't finish within `timeout`, e.g., `pstack` on a
+    // deadlocked process, it's killed and that's recorded in place of its
+    // output. That way, one hung command can't wedge the whole bundle, which is
+    // often being captured _because_ something is wedged.
+    async fn run_cmd<S: AsRef<std::ffi::OsStr>>(
+        &self,
+        args: &[S],
+        timeout: Duration,
+    ) -> String {
+        let child = match self {
+            BundleZone::Running(zone) => {
+                zone.command_runner().spawn_cmd(args).map_err(|e| e.to_string())
+            }
+            BundleZone::Unmanaged { name, .. } => {
+                // We have no contract template for this zone, so go through
+                // `zlogin` instead. This fails if the zone isn't running, which
+                // is recorded in place of the output.
+                let mut command = std::process::Command::new(PFEXEC);
+                command
+                    .env_clear()
+                    .arg(ZLOGIN)
+                    .arg(name)
+                    .args(args)
+                    .stdout(std::process::Stdio::piped())
+                    .stderr(std::process::Stdio::piped())
+                    .process_group(0);
+                illumos_utils::spawn(&mut command).map_err(|e| e.to_string())
+            }
+        };
+        match child {
+            Ok(child) => wait_for_command(child, timeout).await,
+            Err(e) => e,
+        }
+    }
+}
+
+// Wait up to `timeout` for a command run in a zone to finish, returning its
+// output or a description of the failure.
+//
+// The command is waited for on a blocking thread, and killed along with the
+// rest of its process group if it times out, which also frees the thread.
+async fn wait_for_command(
+    child: std::process::Child,
+    timeout: Duration,
+) -> String {
+    let pid = child.id();
+    let task = tokio::task::spawn_blocking(move || child.wait_with_output());
+    match tokio::time::timeout(timeout, task).await {
+        Ok(Ok(Ok(output))) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).to_string()
+        }
+        Ok(Ok(Ok(output))) => format!(
+            "command failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr),
+        ),
+        Ok(Ok(Err(e))) => format!("failed to wait for command: {e}"),
+        Ok(Err(_)) => String::from("command panicked"),
+        Err(_) => {
+            // The blocking task was still waiting for the command, so at
+            // worst it's only just been reaped, and its PID (and so process
+            // group ID) being reused in that window is vanishingly unlikely.
+            //
+            // Safety: `kill(2)` has no memory safety requirements.
+            unsafe {
+                libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+            }
+            format!("command timed out after {:?}", timeout)
+        }
+    }
+}
+
+// The default set of zone-wide commands, which don't require any details about
+// the processes we've launched in the zone.
+const ZONE_WIDE_COMMANDS: [&[&str]; 6] = [
+    &["ptree"],
+    &["uptime"],
+    &["last"],
+    &["who"],
+    &["svcs", "-p"],
+    &["netstat", "-an"],
+];
+
+// The binaries which may be run as zone-wide commands.
+//
+// Some of these can also modify the zone's state, e.g., `ipadm delete-addr`.
+// For those, the first argument must start with the provided prefix, which
+// limits them to subcommands or flags that only report on the zone.
+const ALLOWED_ZONE_WIDE_BINARIES: [(&str, Option<&str>); 12] = [
+    ("ptree", None),
+    ("uptime", None),
+    ("last", None),
+    ("who", None),
+    ("svcs", None),
+    ("netstat", None),
+    ("ps", None),
+    ("df", None),
+    ("ipadm", Some("show-")),
+    ("dladm", Some("show-")),
+    ("arp", Some("-a")),
+    ("route", Some("get")),
+];
+
+// Return the default set of zone-wide commands, as owned strings.
+fn default_zone_wide_commands() -> Vec<Vec<String>> {
+    ZONE_WIDE_COMMANDS
+        .iter()
+        .map(|cmd| cmd.iter().map(|arg| arg.to_string()).collect())
+        .collect()
+}
+
+// Check that a zone name can be used as the name of its bundle directory.
+//
+// Zone names come from API clients, and the bundles of each zone are kept in a
+// directory named for it, so a name which isn't a single path component could
+// read or write outside the bundle directories.
+fn validate_zone_name(name: &str) -> Result<(), BundleError> {
+    if name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.contains(|c| c == '/' || c == '\0')
+    {
+        return Err(BundleError::InvalidZoneName { name: name.to_string() });
+    }
+    Ok(())
+}
+
+// Check that a zone-wide command runs one of the allowed binaries.
+fn validate_zone_wide_command(command: &[String]) -> Result<(), BundleError> {
+    let disallowed =
+        || BundleError::DisallowedCommand { command: command.to_vec() };
+    let Some((binary, args)) = command.split_first() else {
+        return Err(disallowed());
+    };
+    let Some((_, required_prefix)) = ALLOWED_ZONE_WIDE_BINARIES
+        .iter()
+        .find(|(allowed, _)| allowed == binary)
+    else {
+        return Err(disallowed());
+    };
+    if let Some(prefix) = required_prefix {
+        if !args.first().map_or(false, |arg| arg.starts_with(prefix)) {
+            return Err(disallowed());
+        }
+    }
+    Ok(())
+}
+
+// The name for zone bundle metadata files.
+pub(crate) const ZONE_BUNDLE_METADATA_FILENAME: &str = "metadata.toml";
+
+// The name of the index of bundles kept in each zone's bundle directory.
+const ZONE_BUNDLE_INDEX_FILENAME: &str = "index.json";
+
+// The name of the temporary file used while writing a new index.
+const ZONE_BUNDLE_INDEX_TMP_FILENAME: &str = "index.json.tmp";
+
+// The suffix of the temporary file to which a bundle is written while it's
+// being recompressed.
+const ZONE_BUNDLE_RECOMPRESS_TMP_SUFFIX: &str = ".recompress.tmp";
+
+// The suffix of the temporary file to which a new bundle is written, until
+// it's complete and renamed into place.
+const ZONE_BUNDLE_CREATE_TMP_SUFFIX: &str = ".create.tmp";
+
+// The maximum number of bundles recompressed in each directory, each time the
+// periodic cleanup runs.
+const MAX_RECOMPRESSED_PER_CLEANUP: usize = 4;
+
+/// Errors related to managing service zone bundles.
+#[derive(Debug, thiserror::Error)]
+pub enum BundleError {
+    #[error("I/O error running command '{cmd}'")]
+    Command {
+        cmd: String,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error("I/O error creating directory '{directory}'")]
+    CreateDirectory {
+        directory: Utf8PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error("I/O error opening bundle tarball '{path}'")]
+    OpenBundleFile {
+        path: Utf8PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error("I/O error adding bundle tarball data to '{tarball_path}'")]
+    AddBundleData {
+        tarball_path: Utf8PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error("I/O error reading bundle tarball data from '{path}'")]
+    ReadBundleData {
+        path: Utf8PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error("I/O error copying bundle tarball from '{from}' to '{to}'")]
+    CopyArchive {
+        from: Utf8PathBuf,
+        to: Utf8PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error("I/O error reading directory '{directory}'")]
+    ReadDirectory {
+        directory: Utf8PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error("I/O error fetching metadata for '{path}'")]
+    Metadata {
+        path: Utf8PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error("TOML serialization failure")]
+    Serialization(#[from] toml::ser::Error),
+
+    #[error("TOML deserialization failure")]
+    Deserialization(#[from] toml::de::Error),
+
+    #[error(
+        "Zone bundle metadata version {found} is newer than the \
+        supported version {supported}"
+    )]
+    UnsupportedVersion { found: u8, supported: u8 },
+
+    #[error("No zone named '{name}' is available for bundling")]
+    NoSuchZone { name: String },
+
+    #[error("No zone bundle for zone '{zone_name}' with ID '{bundle_id}'")]
+    NoSuchBundle { zone_name: String, bundle_id: Uuid },
+
+    #[error("Zone bundle '{bundle_id}' has no file named '{entry_path}'")]
+    NoSuchBundleEntry { bundle_id: Uuid, entry_path: String },
+
+    #[error(
+        "File '{entry_path}' in zone bundle '{bundle_id}' is {size} bytes, \
+        larger than the maximum of {max_size} bytes"
+    )]
+    BundleEntryTooLarge {
+        bundle_id: Uuid,
+        entry_path: String,
+        size: u64,
+        max_size: u64,
+    },
+
+    #[error("No storage available for bundles")]
+    NoStorage,
+
+    #[error("Failed to join zone bundling task")]
+    Task(#[from] tokio::task::JoinError),
+
+    #[error("Failed to create bundle: {0}")]
+    BundleFailed(#[from] anyhow::Error),
+
+    #[error("Zone error")]
+    Zone(#[from] AdmError),
+
+    #[error(transparent)]
+    PathBuf(#[from] FromPathBufError),
+
+    #[error("Zone '{name}' cannot currently be bundled")]
+    Unavailable { name: String },
+
+    #[error("Zone '{name}' is excluded from automatic bundling")]
+    AutoBundleExcluded { name: String },
+
+    #[error("Command {command:?} is not allowed in zone bundles")]
+    DisallowedCommand { command: Vec<String> },
+
+    #[error("Invalid zone name {name:?}")]
+    InvalidZoneName { name: String },
+
+    #[error(
+        "Capture interval must be at least {min:?}",
+        min = ZoneBundler::MIN_CAPTURE_INTERVAL,
+    )]
+    InvalidCaptureInterval,
+
+    #[error("Storage limit must be expressed as a percentage in (0, 100]")]
+    InvalidStorageLimit,
+
+    #[error(
+        "Cleanup period must be between {min:?} and {max:?}, inclusive",
+        min = CleanupPeriod::MIN,
+        max = CleanupPeriod::MAX,
+    )]
+    InvalidCleanupPeriod,
+
+    #[error(
+        "Invalid priority ordering. Each element must appear exactly once."
+    )]
+    InvalidPriorityOrder,
+
+    #[error("Cleanup failed")]
+    Cleanup(#[source] anyhow::Error),
+
+    #[error("I/O error writing zone bundle index '{path}'")]
+    WriteIndex {
+        path: Utf8PathBuf,
+        #[source]
+        err: std::io::Error,
+    },
+
+    #[error("JSON serialization failure")]
+    IndexSerialization(#[from] serde_json::Error),
+}
+
+impl BundleError {
+    /// Returns true if the operation that failed with this error may succeed
+    /// if it's retried.
+    ///
+    /// Errors from I/O, or from the current state of the sled (such as there
+    /// being no storage yet), are transient. Errors in the request or the
+    /// configuration, or in data which will be the same when read again, are
+    /// permanent.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            // I/O errors, including those running commands or administering
+            // zones, may be momentary.
+            BundleError::Command { .. }
+            | BundleError::CreateDirectory { .. }
+            | BundleError::OpenBundleFile { .. }
+            | BundleError::AddBundleData { .. }
+            | BundleError::ReadBundleData { .. }
+            | BundleError::CopyArchive { .. }
+            | BundleError::ReadDirectory { .. }
+            | BundleError::Metadata { .. }
+            | BundleError::WriteIndex { .. }
+            | BundleError::Zone(_) => true,
+
+            // Storage may become available, and a zone may become bundleable
+            // once it finishes starting up.
+            BundleError::NoStorage | BundleError::Unavailable { .. } => true,
+
+            // A cancelled task may run to completion next time, but one that
+            // panicked is likely to panic again.
+            BundleError::Task(err) => err.is_cancelled(),
+
+            // Malformed data or paths are the same each time we read them.
+            BundleError::Serialization(_)
+            | BundleError::Deserialization(_)
+            | BundleError::IndexSerialization(_)
+            | BundleError::UnsupportedVersion { .. }
+            | BundleError::PathBuf(_) => false,
+
+            // Whatever was requested doesn't exist, or may never be bundled.
+            BundleError::NoSuchZone { .. }
+            | BundleError::NoSuchBundle { .. }
+            | BundleError::NoSuchBundleEntry { .. }
+            | BundleError::BundleEntryTooLarge { .. }
+            | BundleError::AutoBundleExcluded { .. }
+            | BundleError::DisallowedCommand { .. }
+            | BundleError::InvalidZoneName { .. } => false,
+
+            // Invalid configuration is rejected every time.
+            BundleError::InvalidCaptureInterval
+            | BundleError::InvalidStorageLimit
+            | BundleError::InvalidCleanupPeriod
+            | BundleError::InvalidPriorityOrder => false,
+
+            // These wrap arbitrary errors, so we can't tell whether retrying
+            // would help. Err on the side of not retrying forever.
+            BundleError::BundleFailed(_) | BundleError::Cleanup(_) => false,
+        }
+    }
+}
+
+// Helper function to write an array of bytes into the tar archive, with
+// the provided name.
+pub(crate) fn insert_data<W: std::io::Write>(
+    builder: &mut Builder<W>,
+    name: &str,
+    contents: &[u8],
+) -> Result<(), BundleError> {
+    let mtime = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .context("failed to compute mtime")?
+        .as_secs();
+
+    let mut hdr = Header::new_ustar();
+    hdr.set_size(contents.len().try_into().unwrap());
+    hdr.set_mode(0o444);
+    hdr.set_mtime(mtime);
+    hdr.set_entry_type(tar::EntryType::Regular);
+    // NOTE: This internally sets the path and checksum. Names which don't fit
+    // in the ustar header, e.g., those of zones or services with long names,
+    // are written with a GNU long name entry preceding this one.
+    builder.append_data(&mut hdr, name, Cursor::new(contents)).map_err(|err| {
+        BundleError::AddBundleData { tarball_path: name.into(), err }
+    })
+}
+
+// Debugging commands run on the specific processes this zone defines.
+const ZONE_PROCESS_COMMANDS: [&str; 4] = [
+    "pfiles", "pstack", "pargs",
+    // The zone-wide `ptree` shows every process, but not which of them belong
+    // to each service. This shows the service process's own ancestors and
+    // descendants, e.g., any workers a supervisor has spawned.
+    "ptree",
+    // TODO-completeness: We may want `gcore`, since that encompasses
+    // the above commands and much more. It seems like overkill now,
+    // however.
+];
+
+// Run each of `ZONE_PROCESS_COMMANDS` on the process `pid` using `run_cmd`, and
+// insert the output into the bundle.
+async fn insert_process_command_outputs<W, F>(
+    log: &Logger,
+    zone_name: &str,
+    builder: &mut Builder<W>,
+    pid: u32,
+    run_cmd: impl Fn(Vec<String>) -> F,
+) where
+    W: std::io::Write,
+    F: Future<Output = String>,
+{
+    let pid_s = pid.to_string();
+    for cmd in ZONE_PROCESS_COMMANDS {
+        let args: &[&str] = &[cmd, &pid_s];
+        debug!(
+            log,
+            "running zone bundle command";
+            "zone" => zone_name,
+            "command" => ?args,
+        );
+        let output =
+            run_cmd(args.iter().map(|arg| arg.to_string()).collect()).await;
+        let contents = format!("Command: {:?}\n{}", args, output).into_bytes();
+
+        // There may be multiple Oxide service processes for which we want to
+        // capture the command output. Name each output after the command and
+        // PID to disambiguate.
+        let filename = format!("{}.{}", cmd, pid);
+        if let Err(e) = insert_data(builder, &filename, &contents) {
+            error!(
+                log,
+                "failed to save zone bundle command output";
+                "zone" => zone_name,
+                "command" => ?args,
+                "error" => ?e,
+            );
+        }
+    }
+}
+
+// Create a service bundle for the provided zone.
+//
+// This runs a series of debugging commands in the zone, to collect data about
+// the state of the zone and any Oxide service processes running inside. The
+// data is packaged into a tarball, and placed in the provided output
+// directories.
+async fn create(
+    log: &Logger,
+    zone: &BundleZone<'_>,
+    context: &ZoneBundleContext,
+) -> Result<ZoneBundleInfo, BundleError> {
+    // Fetch the directory into which we'll store data, and ensure it exists.
+    if context.storage_dirs.is_empty() {
+        warn!(log, "no directories available for zone bundles");
+        return Err(BundleError::NoStorage);
+    }
+    let mut zone_bundle_dirs = Vec::with_capacity(context.storage_dirs.len());
+    for dir in context.storage_dirs.iter() {
+        let bundle_dir = dir.join(zone.name());
+        debug!(log, "creating bundle directory"; "dir" => %bundle_dir);
+        tokio::fs::create_dir_all(&bundle_dir).await.map_err(|err| {
+            BundleError::CreateDirectory {
+                directory: bundle_dir.to_owned(),
+                err,
+            }
+        })?;
+        zone_bundle_dirs.push(bundle_dir);
+    }
+
+    // Create metadata and the tarball writer.
+    //
+    // We'll write the contents of the bundle into a gzipped tar archive,
+    // including metadata and a file for the output of each command we run in
+    // the zone.
+    let zone_metadata = ZoneBundleMetadata {
+        zone_wide_commands: context.zone_wide_commands.clone(),
+        detail: context.detail,
+        ..ZoneBundleMetadata::new(
+            zone.name(),
+            context.cause,
+            context.annotations.clone(),
+        )
+    };
+
+    // Each copy of the bundle is written under a temporary name, and renamed
+    // into place only once it's complete. Should creating the bundle fail, the
+    // partial copies are removed when `copies` is dropped.
+    //
+    // The compressed archive is written either to every copy as it's produced,
+    // or to the first copy and then copied to the others once it's complete.
+    let filename = format!("{}.tar.gz", zone_metadata.id.bundle_id);
+    let copies = BundleCopies::new(
+        log,
+        &zone_bundle_dirs,
+        &filename,
+        context.single_pass_copies,
+    );
+    let mut files = Vec::with_capacity(copies.n_written());
+    for copy in copies.iter().take(copies.n_written()) {
+        let file =
+            tokio::fs::File::create(&copy.tmp_path).await.map_err(|err| {
+                error!(
+                    log,
+                    "failed to create bundle file";
+                    "zone" => zone.name(),
+                    "file" => %copy.tmp_path,
+                    "error" => ?err,
+                );
+                BundleError::OpenBundleFile { path: copy.tmp_path.clone(), err }
+            })?;
+        debug!(
+            log,
+            "created bundle tarball file";
+            "zone" => zone.name(),
+            "path" => %copy.tmp_path,
+        );
+        files.push((copy.tmp_path.clone(), file.into_std().await));
+    }
+    let gz = flate2::GzBuilder::new()
+        .filename(filename.as_str())
+        .write(BundleFiles::new(log, files), context.compression);
+    let mut builder = Builder::new(gz);
+
+    // Write the metadata file itself, in TOML format.
+    let contents = toml::to_string(&zone_metadata)?;
+    insert_data(
+        &mut builder,
+        ZONE_BUNDLE_METADATA_FILENAME,
+        contents.as_bytes(),
+    )?;
+    debug!(
+        log,
+        "wrote zone bundle metadata";
+        "zone" => zone.name(),
+    );
+    // Each output is named for the binary. Several commands may run the same
+    // binary, e.g., `ipadm show-addr` and `ipadm show-if`, so number any
+    // repeats to keep the names distinct.
+    let mut output_names = BTreeSet::new();
+    for cmd in context.zone_wide_commands.iter() {
+        let cmd: Vec<&str> = cmd.iter().map(String::as_str).collect();
+        debug!(
+            log,
+            "running zone bundle command";
+            "zone" => zone.name(),
+            "command" => ?cmd,
+        );
+        let output = zone.run_cmd(&cmd, context.command_timeout).await;
+        let contents = format!("Command: {:?}\n{}", cmd, output).into_bytes();
+        let mut name = cmd[0].to_string();
+        let mut n = 0;
+        while !output_names.insert(name.clone()) {
+            n += 1;
+            name = format!("{}-{}", cmd[0], n);
+        }
+        if let Err(e) = insert_data(&mut builder, &name, &contents) {
+            error!(
+                log,
+                "failed to save zone bundle command output";
+                "zone" => zone.name(),
+                "command" => ?cmd,
+                "error" => ?e,
+            );
+        }
+    }
+
+    // A minimal bundle has only the output of the zone-wide commands.
+    if context.detail == BundleDetailLevel::Minimal {
+        return finish_bundle(log, builder, zone_metadata, &filename, copies)
+            .await;
+    }
+
+    // Without the metadata of a running zone, we can't find its service
+    // processes. Collect any log files we can find for the zone instead.
+    let zone = match zone {
+        BundleZone::Running(zone) => zone,
+        BundleZone::Unmanaged { name, zonepath } => {
+            insert_unmanaged_zone_log_files(
+                log,
+                name,
+                zonepath,
+                &context.extra_log_dirs,
+                &mut builder,
+            )
+            .await?;
+            return finish_bundle(
+                log,
+                builder,
+                zone_metadata,
+                &filename,
+                copies,
+            )
+            .await;
+        }
+    };
+    let procs = match zone
+        .service_processes()
+        .context("failed to enumerate zone service processes")
+    {
+        Ok(p) => {
+            debug!(
+                log,
+                "enumerated service processes";
+                "zone" => zone.name(),
+                "procs" => ?p,
+            );
+            p
+        }
+        Err(e) => {
+            error!(
+                log,
+                "failed to enumerate zone service processes";
+                "zone" => zone.name(),
+                "error" => ?e,
+            );
+            return Err(BundleError::from(e));
+        }
+    };
+    for svc in procs.into_iter() {
+        insert_process_command_outputs(
+            log,
+            zone.name(),
+            &mut builder,
+            svc.pid,
+            |args| async move {
+                BundleZone::Running(zone)
+                    .run_cmd(&args, context.command_timeout)
+                    .await
+            },
+        )
+        .await;
+
+        // We may need to extract log files that have been archived out of the
+        // zone filesystem itself. See `crate::dump_setup` for the logic which
+        // does this.
+        let archived_log_files = find_archived_log_files(
+            log,
+            zone.name(),
+            &svc.service_name,
+            &context.extra_log_dirs,
+        )
+        .await;
+
+        // Copy any log files, current and rotated, into the tarball as
+        // well.
+        //
+        // Safety: This pathbuf was retrieved by locating an existing file
+        // on the filesystem, so we're sure it has a name and the unwrap is
+        // safe.
+        debug!(
+            log,
+            "appending current log file to zone bundle";
+            "zone" => zone.name(),
+            "log_file" => %svc.log_file,
+        );
+        if let Err(e) = builder.append_path_with_name(
+            &svc.log_file,
+            svc.log_file.file_name().unwrap(),
+        ) {
+            error!(
+                log,
+                "failed to append current log file to zone bundle";
+                "zone" => zone.name(),
+                "log_file" => %svc.log_file,
+                "error" => ?e,
+            );
+            return Err(BundleError::AddBundleData {
+                tarball_path: svc.log_file.file_name().unwrap().into(),
+                err: e,
+            });
+        }
+        for f in svc.rotated_log_files.iter().chain(archived_log_files.iter()) {
+            debug!(
+                log,
+                "appending rotated log file to zone bundle";
+                "zone" => zone.name(),
+                "log_file" => %f,
+            );
+            if let Err(e) =
+                builder.append_path_with_name(f, f.file_name().unwrap())
+            {
+                error!(
+                    log,
+                    "failed to append rotated log file to zone bundle";
+                    "zone" => zone.name(),
+                    "log_file" => %f,
+                    "error" => ?e,
+                );
+                return Err(BundleError::AddBundleData {
+                    tarball_path: f.file_name().unwrap().into(),
+                    err: e,
+                });
+            }
+        }
+    }
+
+    finish_bundle(log, builder, zone_metadata, &filename, copies).await
+}
+
+// A single copy of a bundle being created.
+struct BundleCopy {
+    // The bundle directory the copy is in.
+    dir: Utf8PathBuf,
+    // The path to which the copy is written.
+    tmp_path: Utf8PathBuf,
+    // The path to which the copy is renamed once it's complete.
+    path: Utf8PathBuf,
+}
+
+// The copies of a bundle being created, one in each bundle directory.
+//
+// Each copy is written under a temporary name, which the bundle index and
+// cleanup ignore. Any copies still under their temporary names are removed
+// when this is dropped, so a bundle which fails partway through, e.g., because
+// a disk fills up, doesn't leave truncated files behind.
+struct BundleCopies {
+    log: Logger,
+    copies: Vec<BundleCopy>,
+    // Whether every copy is written as the bundle is produced, rather than
+    // just the first.
+    single_pass: bool,
+}
+
+impl BundleCopies {
+    fn new(
+        log: &Logger,
+        dirs: &[Utf8PathBuf],
+        filename: &str,
+        single_pass: bool,
+    ) -> Self {
+        let copies = dirs
+            .iter()
+            .map(|dir| BundleCopy {
+                dir: dir.clone(),
+                tmp_path: dir
+                    .join(format!("{filename}{ZONE_BUNDLE_CREATE_TMP_SUFFIX}")),
+                path: dir.join(filename),
+            })
+            .collect();
+        Self { log: log.clone(), copies, single_pass }
+    }
+
+    // The number of copies written as the bundle is produced.
+    fn n_written(&self) -> usize {
+        if self.single_pass {
+            self.copies.len()
+        } else {
+            1
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &BundleCopy> {
+        self.copies.iter()
+    }
+
+    // Stop tracking copies which weren't written, whose temporary files have
+    // already been removed.
+    fn retain_written(&mut self, written: &[Utf8PathBuf]) {
+        self.copies.retain(|copy| written.contains(&copy.tmp_path));
+    }
+
+    // Copy the first copy to all the others, dropping any that fail.
+    async fn copy_from_first(&mut self) {
+        let Some((first, others)) = self.copies.split_first() else {
+            return;
+        };
+        let mut written = vec![first.tmp_path.clone()];
+        for copy in others.iter() {
+            debug!(
+                self.log,
+                "copying bundle";
+                "from" => %first.tmp_path,
+                "to" => %copy.tmp_path,
+            );
+            match tokio::fs::copy(&first.tmp_path, &copy.tmp_path).await {
+                Ok(_) => written.push(copy.tmp_path.clone()),
+                Err(e) => {
+                    warn!(
+                        self.log,
+                        "failed to copy bundle, dropping this copy";
+                        "from" => %first.tmp_path,
+                        "to" => %copy.tmp_path,
+                        "error" => ?e,
+                    );
+                    let _ = tokio::fs::remove_file(&copy.tmp_path).await;
+                }
+            }
+        }
+        self.retain_written(&written);
+    }
+
+    // Rename every copy into place, returning them.
+    async fn persist(mut self) -> Result<Vec<BundleCopy>, BundleError> {
+        for (i, copy) in self.copies.iter().enumerate() {
+            if let Err(err) =
+                tokio::fs::rename(&copy.tmp_path, &copy.path).await
+            {
+                // Remove the copies already renamed along with the others, so
+                // that no directory is left with a bundle the rest don't have.
+                for done in self.copies[..i].iter() {
+                    let _ = tokio::fs::remove_file(&done.path).await;
+                }
+                return Err(BundleError::CopyArchive {
+                    from: copy.tmp_path.clone(),
+                    to: copy.path.clone(),
+                    err,
+                });
+            }
+        }
+        Ok(std::mem::take(&mut self.copies))
+    }
+}
+
+impl Drop for BundleCopies {
+    fn drop(&mut self) {
+        for copy in self.copies.iter() {
+            match std::fs::remove_file(&copy.tmp_path) {
+                Ok(()) => debug!(
+                    self.log,
+                    "removed partial bundle file";
+                    "file" => %copy.tmp_path,
+                ),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => warn!(
+                    self.log,
+                    "failed to remove partial bundle file";
+                    "file" => %copy.tmp_path,
+                    "error" => ?e,
+                ),
+            }
+        }
+    }
+}
+
+// Writes the same data to the copy of a bundle in each of several files.
+//
+// This lets us write every copy of a bundle in a single pass, rather than
+// writing one and then copying it to the others. A copy that fails to write is
+// removed and dropped, keeping the others, so that one failing disk doesn't
+// lose every copy. Writing fails only once every copy has.
+struct BundleFiles {
+    log: Logger,
+    files: Vec<(Utf8PathBuf, std::fs::File)>,
+}
+
+impl BundleFiles {
+    fn new(log: &Logger, files: Vec<(Utf8PathBuf, std::fs::File)>) -> Self {
+        Self { log: log.clone(), files }
+    }
+
+    // Apply `op` to every file, removing those for which it fails.
+    fn for_each_file(
+        &mut self,
+        mut op: impl FnMut(&mut std::fs::File) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let mut last_err = None;
+        self.files.retain_mut(|(path, file)| match op(file) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(
+                    self.log,
+                    "failed to write bundle file, dropping this copy";
+                    "file" => %path,
+                    "error" => ?e,
+                );
+                let _ = std::fs::remove_file(&*path);
+                last_err = Some(e);
+                false
+            }
+        });
+        match last_err {
+            Some(e) if self.files.is_empty() => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    // Return the paths of the files which have been written successfully.
+    fn finish(mut self) -> std::io::Result<Vec<Utf8PathBuf>> {
+        self.for_each_file(|file| file.sync_all())?;
+        Ok(self.files.into_iter().map(|(path, _file)| path).collect())
+    }
+}
+
+impl std::io::Write for BundleFiles {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // Every copy must get all of the data, or they'd differ.
+        self.for_each_file(|file| std::io::Write::write_all(file, buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.for_each_file(std::io::Write::flush)
+    }
+}
+
+// Finish writing the copies of the bundle tarball, rename them into place, and
+// then record the bundle in the index of each bundle directory.
+//
+// This returns the metadata, path, and size of the first copy.
+async fn finish_bundle(
+    log: &Logger,
+    builder: Builder<flate2::write::GzEncoder<BundleFiles>>,
+    mut zone_metadata: ZoneBundleMetadata,
+    filename: &str,
+    mut copies: BundleCopies,
+) -> Result<ZoneBundleInfo, BundleError> {
+    // Finish writing out the tarball itself.
+    let files = builder
+        .into_inner()
+        .and_then(|gz| gz.finish())
+        .context("Failed to build bundle")?;
+    let written = files.finish().context("Failed to write bundle")?;
+    if copies.single_pass {
+        copies.retain_written(&written);
+    } else {
+        copies.copy_from_first().await;
+    }
+
+    // Record the hash of the finished bundle, so clients downloading it can
+    // check its integrity.
+    let first_tmp_path = copies.copies[0].tmp_path.clone();
+    let bytes = tokio::fs::metadata(&first_tmp_path)
+        .await
+        .map_err(|err| BundleError::Metadata {
+            path: first_tmp_path.clone(),
+            err,
+        })?
+        .len();
+    match compute_content_hash(&first_tmp_path).await {
+        Ok(hash) => zone_metadata.content_hash = Some(hash),
+        Err(e) => warn!(
+            log,
+            "failed to compute zone bundle hash";
+            "path" => %first_tmp_path,
+            "reason" => ?e,
+        ),
+    }
+
+    // Record the new bundle in the index of each directory.
+    let copies = copies.persist().await?;
+    for copy in copies.iter() {
+        update_zone_bundle_index(log, &copy.dir, |index| {
+            index.bundles.insert(filename.to_string(), zone_metadata.clone());
+        })
+        .await;
+    }
+
+    info!(log, "finished zone bundle"; "metadata" => ?zone_metadata);
+    Ok(ZoneBundleInfo {
+        metadata: zone_metadata,
+        path: copies[0].path.clone(),
+        bytes,
+    })
+}
+
+// Insert any Oxide-managed SMF service log files for a zone the sled agent
+// isn't managing, from the zone's own log directory and any archive of them.
+//
+// We don't know which services should be running in the zone, so this takes
+// every log file for any Oxide-managed service.
+async fn insert_unmanaged_zone_log_files<W: std::io::Write>(
+    log: &Logger,
+    zone_name: &str,
+    zonepath: &Utf8Path,
+    extra_log_dirs: &[Utf8PathBuf],
+    builder: &mut Builder<W>,
+) -> Result<(), BundleError> {
+    let mut dirs = vec![zonepath.join("root/var/svc/log")];
+    dirs.extend(extra_log_dirs.iter().cloned());
+    // An empty service name matches the log files of all services.
+    let log_files = find_archived_log_files(log, zone_name, "", &dirs).await;
+    for f in log_files.iter() {
+        debug!(
+            log,
+            "appending log file to zone bundle";
+            "zone" => zone_name,
+            "log_file" => %f,
+        );
+        if let Err(e) = builder.append_path_with_name(f, f.file_name().unwrap())
+        {
+            error!(
+                log,
+                "failed to append log file to zone bundle";
+                "zone" => zone_name,
+                "log_file" => %f,
+                "error" => ?e,
+            );
+            return Err(BundleError::AddBundleData {
+                tarball_path: f.file_name().unwrap().into(),
+                err: e,
+            });
+        }
+    }
+    Ok(())
+}
+
+// Find log files for the specified zone / SMF service, which may have been
+// archived out to a U.2 dataset.
+//
+// Note that errors are logged, rather than failing the whole function, so that
+// one failed listing does not prevent collecting any other log files.
+async fn find_archived_log_files(
+    log: &Logger,
+    zone_name: &str,
+    svc_name: &str,
+    dirs: &[Utf8PathBuf],
+) -> Vec<Utf8PathBuf> {
+    // The `dirs` should be things like
+    // `/pool/ext/<ZPOOL_UUID>/crypt/debug/<ZONE_NAME>`, but it's really up to
+    // the caller to verify these exist and possibly contain what they expect.
+    //
+    // Within that, we'll just look for things that appear to be Oxide-managed
+    // SMF service log files.
+    let mut files = Vec::new();
+    for dir in dirs.iter() {
+        if dir.exists() {
+            let mut rd = match tokio::fs::read_dir(&dir).await {
+                Ok(rd) => rd,
+                Err(e) => {
+                    error!(
+                        log,
+                        "failed to read zone debug directory";
+                        "directory" => ?dir,
+                        "reason" => ?e,
+                    );
+                    continue;
+                }
+            };
+            loop {
+                match rd.next_entry().await {
+                    Ok(None) => break,
+                    Ok(Some(entry)) => {
+                        let Ok(path) = Utf8PathBuf::try_from(entry.path())
+                        else {
+                            error!(
+                                log,
+                                "skipping possible archived log file with \
+                                non-UTF-8 path";
+                                "path" => ?entry.path(),
+                            );
+                            continue;
+                        };
+                        let fname = path.file_name().unwrap();
+                        let is_oxide = is_oxide_smf_log_file(fname);
+                        let contains = fname.contains(svc_name);
+                        if is_oxide && contains {
+                            debug!(
+                                log,
+                                "found archived log file";
+                                "zone_name" => zone_name,
+                                "service_name" => svc_name,
+                                "path" => ?path,
+                            );
+                            files.push(path);
+                        } else {
+                            debug!(
+                                log,
+                                "skipping non-matching log file";
+                                "filename" => fname,
+                                "is_oxide_smf_log_file" => is_oxide,
+                                "contains_svc_name" => contains,
+                            );
+                        }
+                    }
+                    Err(e) => {
+                        error!(
+                            log,
+                            "failed to fetch zone debug directory entry";
+                            "directory" => ?dir,
+                            "reason" => ?e,
+                        );
+                    }
+                }
+            }
+        } else {
+            // The logic in `dump_setup` picks some U.2 in which to start
+            // archiving logs, and thereafter tries to keep placing new ones
+            // there, subject to space constraints. It's not really an error for
+            // there to be no entries for the named zone in any particular U.2
+            // debug dataset.
+            slog::trace!(
+                log,
+                "attempting to find archived log files in \
+                non-existent directory";
+                "directory" => ?dir,
+            );
+        }
+    }
+    files
+}
+
+// A reader which hashes everything read through it.
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+// Extract the zone bundle metadata from a file, along with the SHA-256 hash of
+// the whole file as a hex string, reading the file only once.
+fn extract_zone_bundle_metadata_and_hash_impl(
+    path: &Utf8PathBuf,
+) -> Result<(ZoneBundleMetadata, String), BundleError> {
+    let file = std::fs::File::open(path).map_err(|err| {
+        BundleError::OpenBundleFile { path: path.clone(), err }
+    })?;
+    let mut reader = HashingReader { inner: file, hasher: Sha256::new() };
+    let metadata = read_zone_bundle_metadata(path, &mut reader)?;
+
+    // The metadata is usually near the start of the archive, so hash whatever
+    // follows it too.
+    std::io::copy(&mut reader, &mut std::io::sink()).map_err(|err| {
+        BundleError::ReadBundleData { path: path.clone(), err }
+    })?;
+    Ok((metadata, hex::encode(reader.hasher.finalize())))
+}
+
+// Parse the zone bundle metadata from the archive read from `reader`.
+//
+// `path` is used only for reporting errors.
+fn read_zone_bundle_metadata(
+    path: &Utf8PathBuf,
+    reader: impl Read,
+) -> Result<ZoneBundleMetadata, BundleError> {
+    // Build a reader for the whole archive.
+    let buf_reader = std::io::BufReader::new(reader);
+    let gz = GzDecoder::new(buf_reader);
+    let mut archive = Archive::new(gz);
+
+    // Find the metadata entry, if it exists.
+    let entries = archive.entries().map_err(|err| {
+        BundleError::ReadBundleData { path: path.clone(), err }
+    })?;
+    let Some(md_entry) = entries
+        // The `Archive::entries` iterator
+        // returns a result, so filter to those
+        // that are OK first.
+        .filter_map(Result::ok)
+        .find(|entry| {
+            entry
+                .path()
+                .map(|p| p.to_str() == Some(ZONE_BUNDLE_METADATA_FILENAME))
+                .unwrap_or(false)
+        })
+    else {
+        return Err(BundleError::from(anyhow!(
+            "Zone bundle is missing metadata file"
+        )));
+    };
+
+    // Extract its contents and parse as metadata.
+    let contents = std::io::read_to_string(md_entry).map_err(|err| {
+        BundleError::ReadBundleData { path: path.clone(), err }
+    })?;
+
+    // Check the version before parsing the rest, since a newer bundle may
+    // have metadata we can't represent.
+    #[derive(Deserialize)]
+    struct MetadataVersion {
+        version: u8,
+    }
+    let MetadataVersion { version } = toml::from_str(&contents)?;
+    if version > ZoneBundleMetadata::VERSION {
+        return Err(BundleError::UnsupportedVersion {
+            found: version,
+            supported: ZoneBundleMetadata::VERSION,
+        });
+    }
+    toml::from_str(&contents).map_err(BundleError::from)
+}
+
+// Compute the SHA-256 hash of the file at `path`, as a hex string.
+async fn compute_content_hash(
+    path: &Utf8PathBuf,
+) -> Result<String, BundleError> {
+    let path = path.clone();
+    let task = tokio::task::spawn_blocking(move || {
+        let mut file = std::fs::File::open(&path).map_err(|err| {
+            BundleError::OpenBundleFile { path: path.clone(), err }
+        })?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)
+            .map_err(|err| BundleError::ReadBundleData { path, err })?;
+        Ok(hex::encode(hasher.finalize()))
+    });
+    task.await?
+}
+
+// Find the named file in the zone bundle at `path`, and pass its size and a
+// reader for its contents to `read_entry`.
+//
+// Entries are read in order until the requested one is found, so only the
+// portion of the archive up to and including that entry is decompressed.
+// Files larger than `max_size` bytes are rejected without being read.
+fn extract_zone_bundle_file_impl<T>(
+    path: &Utf8PathBuf,
+    bundle_id: &Uuid,
+    entry_path: &str,
+    max_size: u64,
+    read_entry: impl FnOnce(u64, &mut dyn Read) -> T,
+) -> Result<T, BundleError> {
+    let reader = std::fs::File::open(path).map_err(|err| {
+        BundleError::OpenBundleFile { path: path.clone(), err }
+    })?;
+    let buf_reader = std::io::BufReader::new(reader);
+    let gz = GzDecoder::new(buf_reader);
+    let mut archive = Archive::new(gz);
+    let entries = archive.entries().map_err(|err| {
+        BundleError::ReadBundleData { path: path.clone(), err }
+    })?;
+    for entry in entries {
+        let mut entry = entry.map_err(|err| BundleError::ReadBundleData {
+            path: path.clone(),
+            err,
+        })?;
+        let matches = entry
+            .path()
+            .map(|p| p.to_str() == Some(entry_path))
+            .unwrap_or(false);
+        if matches {
+            let size = entry.size();
+            if size > max_size {
+                return Err(BundleError::BundleEntryTooLarge {
+                    bundle_id: *bundle_id,
+                    entry_path: entry_path.to_string(),
+                    size,
+                    max_size,
+                });
+            }
+            return Ok(read_entry(size, &mut entry));
+        }
+    }
+    Err(BundleError::NoSuchBundleEntry {
+        bundle_id: *bundle_id,
+        entry_path: entry_path.to_string(),
+    })
+}
+
+// Call `f` with the path, size, and a reader for the contents of each entry of
+// the zone bundle at `path`, other than its metadata, in archive order.
+fn for_each_zone_bundle_entry(
+    path: &Utf8Path,
+    mut f: impl FnMut(String, u64, &mut dyn Read) -> Result<(), BundleError>,
+) -> Result<(), BundleError> {
+    let read_err = |err: std::io::Error| BundleError::ReadBundleData {
+        path: path.to_path_buf(),
+        err,
+    };
+    let reader = std::fs::File::open(path).map_err(|err| {
+        BundleError::OpenBundleFile { path: path.to_path_buf(), err }
+    })?;
+    let buf_reader = std::io::BufReader::new(reader);
+    let gz = GzDecoder::new(buf_reader);
+    let mut archive = Archive::new(gz);
+    for entry in archive.entries().map_err(read_err)? {
+        let mut entry = entry.map_err(read_err)?;
+        let Some(entry_path) =
+            entry.path().ok().and_then(|p| p.to_str().map(String::from))
+        else {
+            continue;
+        };
+        if entry_path == ZONE_BUNDLE_METADATA_FILENAME {
+            continue;
+        }
+        let size = entry.size();
+        f(entry_path, size, &mut entry)?;
+    }
+    Ok(())
+}
+
+// Read a single zone bundle entry for comparison with the same entry in
+// another bundle.
+//
+// This returns the SHA-256 hash of the contents, and the contents themselves
+// if they're text small enough to diff.
+fn read_entry_for_diff(
+    size: u64,
+    reader: &mut dyn Read,
+) -> std::io::Result<(Vec<u8>, Option<String>)> {
+    if size <= MAX_TEXT_DIFF_SIZE {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        let hash = Sha256::digest(&buf).to_vec();
+        Ok((hash, String::from_utf8(buf).ok()))
+    } else {
+        let mut hasher = Sha256::new();
+        std::io::copy(reader, &mut hasher)?;
+        Ok((hasher.finalize().to_vec(), None))
+    }
+}
+
+// Read the named entry of the zone bundle at `path`, if it exists and is text
+// small enough to diff.
+fn read_zone_bundle_entry_text(
+    path: &Utf8Path,
+    entry_path: &str,
+) -> Result<Option<String>, BundleError> {
+    let mut text = None;
+    let mut found = false;
+    for_each_zone_bundle_entry(path, |this_path, size, reader| {
+        if found || this_path != entry_path {
+            return Ok(());
+        }
+        found = true;
+        text = read_entry_for_diff(size, reader)
+            .map_err(|err| BundleError::ReadBundleData {
+                path: path.to_path_buf(),
+                err,
+            })?
+            .1;
+        Ok(())
+    })?;
+    Ok(text)
+}
+
+// Compare the entries of two zone bundles, returning those which differ.
+//
+// Only the hash of each entry of the first bundle is kept in memory. The second
+// bundle is then compared against those one entry at a time. The first bundle
+// is read again for the text of each entry that differs, if that entry is also
+// text in the second bundle.
+fn diff_zone_bundles_impl(
+    first: &Utf8Path,
+    second: &Utf8Path,
+) -> Result<BTreeMap<String, BundleEntryDiff>, BundleError> {
+    let read_err = |path: &Utf8Path| {
+        let path = path.to_path_buf();
+        move |err| BundleError::ReadBundleData { path, err }
+    };
+
+    let mut first_hashes = BTreeMap::new();
+    for_each_zone_bundle_entry(first, |entry_path, size, reader| {
+        let (hash, _text) =
+            read_entry_for_diff(size, reader).map_err(read_err(first))?;
+        first_hashes.insert(entry_path, hash);
+        Ok(())
+    })?;
+
+    let mut diffs = BTreeMap::new();
+    for_each_zone_bundle_entry(second, |entry_path, size, reader| {
+        let (hash, rhs) =
+            read_entry_for_diff(size, reader).map_err(read_err(second))?;
+        let diff = match first_hashes.remove(&entry_path) {
+            None => BundleEntryDiff::OnlyInSecond,
+            Some(lhs_hash) if lhs_hash == hash => return Ok(()),
+            Some(_) => {
+                let lhs = match rhs {
+                    Some(_) => read_zone_bundle_entry_text(first, &entry_path)?,
+                    None => None,
+                };
+                match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) => {
+                        let text_diff =
+                            similar::TextDiff::from_lines(&lhs, &rhs);
+                        let mut unified = text_diff.unified_diff();
+                        unified.header(&entry_path, &entry_path);
+                        BundleEntryDiff::Text { diff: unified.to_string() }
+                    }
+                    _ => BundleEntryDiff::Differs,
+                }
+            }
+        };
+        diffs.insert(entry_path, diff);
+        Ok(())
+    })?;
+    diffs.extend(
+        first_hashes
+            .into_keys()
+            .map(|path| (path, BundleEntryDiff::OnlyInFirst)),
+    );
+    Ok(diffs)
+}
+
+// List the extant zone bundles for the provided zone, in the provided
+// directory.
+async fn list_bundles_for_zone(
+    log: &Logger,
+    path: &Utf8Path,
+    zone_name: &str,
+) -> Result<Vec<(Utf8PathBuf, ZoneBundleMetadata)>, BundleError> {
+    let zone_bundle_dir = path.join(zone_name);
+    Ok(filter_zone_bundles(log, &zone_bundle_dir, |md| {
+        md.id.zone_name == zone_name
+    })
+    .await?
+    .into_iter()
+    .collect::<Vec<_>>())
+}
+
+// Delete all bundles for the provided zone, in each of the directories.
+//
+// Directories in which the bundles can't be listed or removed are skipped, and
+// their errors are returned alongside the count of bundles removed from the
+// others. A directory with no bundles for the zone at all has nothing to
+// delete, and isn't an error.
+async fn delete_bundles_for_zone(
+    log: &Logger,
+    dirs: &[Utf8PathBuf],
+    zone_name: &str,
+) -> (CleanupCount, DirectoryErrors) {
+    let mut count = CleanupCount::default();
+    let mut errors = DirectoryErrors::new();
+    for dir in dirs.iter() {
+        if let Ok(false) = tokio::fs::try_exists(dir.join(zone_name)).await {
+            continue;
+        }
+        let bundles = match list_bundles_for_zone(log, dir, zone_name).await {
+            Ok(bundles) => bundles,
+            Err(e) => {
+                errors.insert(dir.clone(), e);
+                continue;
+            }
+        };
+        for (path, _metadata) in bundles.iter() {
+            let bytes = match remove_bundle_file(path).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    errors.insert(dir.clone(), e);
+                    break;
+                }
+            };
+            debug!(log, "deleted zone bundle"; "path" => %path);
+            count.bundles += 1;
+            count.bytes += bytes;
+        }
+        let zone_dir = dir.join(zone_name);
+        update_zone_bundle_index(log, &zone_dir, |index| {
+            for (path, _metadata) in bundles.iter() {
+                if let Some(name) = path.file_name() {
+                    index.bundles.remove(name);
+                }
+            }
+        })
+        .await;
+    }
+    (count, errors)
+}
+
+// Remove a single bundle file, returning its size in bytes.
+async fn remove_bundle_file(path: &Utf8Path) -> Result<u64, BundleError> {
+    let bytes = tokio::fs::metadata(path)
+        .await
+        .map_err(|err| BundleError::Metadata { path: path.into(), err })?
+        .len();
+    tokio::fs::remove_file(path).await.map_err(|err| {
+        BundleError::Cleanup(anyhow!("failed to remove bundle '{path}': {err}"))
+    })?;
+    Ok(bytes)
+}
+
+// Extract zone bundle metadata and the hash of the whole file at `path`, in a
+// single pass over the file.
+async fn extract_zone_bundle_metadata_and_hash(
+    path: Utf8PathBuf,
+) -> Result<(ZoneBundleMetadata, String), BundleError> {
+    let task = tokio::task::spawn_blocking(move || {
+        extract_zone_bundle_metadata_and_hash_impl(&path)
+    });
+    task.await?
+}
+
+// An index of the bundles in a single zone's bundle directory.
+//
+// Extracting the metadata from a bundle requires decompressing and parsing the
+// whole archive, so we keep the metadata for each bundle in an index file
+// alongside the bundles themselves. Files that are known not to be valid
+// bundles are also recorded, so that they don't force a rescan of the
+// directory each time it's listed.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+struct ZoneBundleIndex {
+    // The metadata for each bundle, keyed by its filename.
+    bundles: BTreeMap<String, ZoneBundleMetadata>,
+    // The names of files that are not valid zone bundles.
+    invalid: BTreeSet<String>,
+}
+
+impl ZoneBundleIndex {
+    // Return the names of all files described by the index.
+    fn filenames(&self) -> BTreeSet<String> {
+        self.bundles.keys().chain(self.invalid.iter()).cloned().collect()
+    }
+}
+
+// Return the names of all files in a zone's bundle directory, other than the
+// index itself.
+async fn zone_bundle_dir_filenames(
+    directory: &Utf8Path,
+) -> Result<BTreeSet<String>, BundleError> {
+    let mut out = BTreeSet::new();
+    let mut rd = tokio::fs::read_dir(directory).await.map_err(|err| {
+        BundleError::ReadDirectory { directory: directory.to_owned(), err }
+    })?;
+    while let Some(entry) = rd.next_entry().await.map_err(|err| {
+        BundleError::ReadDirectory { directory: directory.to_owned(), err }
+    })? {
+        let path = Utf8PathBuf::try_from(entry.path())?;
+        let Some(name) = path.file_name() else {
+            continue;
+        };
+        if name != ZONE_BUNDLE_INDEX_FILENAME
+            && name != ZONE_BUNDLE_INDEX_TMP_FILENAME
+            && !name.ends_with(ZONE_BUNDLE_RECOMPRESS_TMP_SUFFIX)
+            && !name.ends_with(ZONE_BUNDLE_CREATE_TMP_SUFFIX)
+        {
+            out.insert(name.to_string());
+        }
+    }
+    Ok(out)
+}
+
+// Read the index in a zone's bundle directory, if it exists and is valid.
+async fn read_zone_bundle_index(
+    log: &Logger,
+    directory: &Utf8Path,
+) -> Option<ZoneBundleIndex> {
+    let path = directory.join(ZONE_BUNDLE_INDEX_FILENAME);
+    let contents = match tokio::fs::read(&path).await {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!(
+                log,
+                "failed to read zone bundle index";
+                "path" => %path,
+                "reason" => ?e,
+            );
+            return None;
+        }
+    };
+    match serde_json::from_slice(&contents) {
+        Ok(index) => Some(index),
+        Err(e) => {
+            warn!(
+                log,
+                "failed to parse zone bundle index";
+                "path" => %path,
+                "reason" => ?e,
+            );
+            None
+        }
+    }
+}
+
+// Write the index for a zone's bundle directory.
+//
+// The index is written to a temporary file and then renamed into place, so
+// that a crash while writing it can't leave a partial index behind.
+async fn write_zone_bundle_index(
+    directory: &Utf8Path,
+    index: &ZoneBundleIndex,
+) -> Result<(), BundleError> {
+    let contents = serde_json::to_vec(index)?;
+    let tmp_path = directory.join(ZONE_BUNDLE_INDEX_TMP_FILENAME);
+    tokio::fs::write(&tmp_path, contents).await.map_err(|err| {
+        BundleError::WriteIndex { path: tmp_path.clone(), err }
+    })?;
+    let path = directory.join(ZONE_BUNDLE_INDEX_FILENAME);
+    tokio::fs::rename(&tmp_path, &path)
+        .await
+        .map_err(|err| BundleError::WriteIndex { path, err })
+}
+
+// Load the index for a zone's bundle directory, rebuilding it if needed.
+//
+// The index is used as-is if it describes exactly the files in the directory.
+// If it's missing, corrupt, or stale (e.g., bundles were removed out from under
+// us), every file in the directory is scanned to rebuild it.
+async fn load_zone_bundle_index(
+    log: &Logger,
+    directory: &Utf8Path,
+) -> Result<ZoneBundleIndex, BundleError> {
+    let filenames = zone_bundle_dir_filenames(directory).await?;
+    if let Some(index) = read_zone_bundle_index(log, directory).await {
+        if index.filenames() == filenames {
+            trace!(log, "using zone bundle index"; "directory" => %directory);
+            return Ok(index);
+        }
+        debug!(
+            log,
+            "zone bundle index is stale, rebuilding";
+            "directory" => %directory,
+        );
+    }
+
+    let mut index = ZoneBundleIndex::default();
+    for name in filenames.into_iter() {
+        let path = directory.join(&name);
+        debug!(log, "checking path as zone bundle"; "path" => %path);
+        match extract_zone_bundle_metadata_and_hash(path.clone()).await {
+            Ok((mut md, hash)) => {
+                // The hash of the bundle can't be stored inside it, so
+                // recompute it while we're reading the whole bundle anyway.
+                md.content_hash = Some(hash);
+                trace!(log, "extracted zone bundle metadata"; "metadata" => ?md);
+                index.bundles.insert(name, md);
+            }
+            Err(e) => {
+                // TODO-robustness: What do we do with files that do _not_
+                // appear to be valid zone bundles.
+                //
+                // On the one hand, someone may have put something there
+                // intentionally. On the other hand, that would be weird, and
+                // we _also_ know that it's possible that IO errors happen
+                // while creating the bundle that render it impossible to
+                // recover the metadata. So it's plausible that we end up with
+                // a lot of detritus here in that case.
+                warn!(
+                    log,
+                    "failed to extract zone bundle metadata, skipping";
+                    "path" => %path,
+                    "reason" => ?e,
+                );
+                index.invalid.insert(name);
+            }
+        }
+    }
+    if let Err(e) = write_zone_bundle_index(directory, &index).await {
+        // We can still use the index we just built, we'll only need to
+        // rebuild it again next time.
+        warn!(
+            log,
+            "failed to write zone bundle index";
+            "directory" => %directory,
+            "reason" => ?e,
+        );
+    }
+    Ok(index)
+}
+
+// Update the index in a zone's bundle directory, if there is a valid one.
+//
+// Failures are logged but otherwise ignored. A missing or stale index is
+// rebuilt the next time the directory is listed.
+async fn update_zone_bundle_index(
+    log: &Logger,
+    directory: &Utf8Path,
+    f: impl FnOnce(&mut ZoneBundleIndex),
+) {
+    let Some(mut index) = read_zone_bundle_index(log, directory).await else {
+        return;
+    };
+    f(&mut index);
+    if let Err(e) = write_zone_bundle_index(directory, &index).await {
+        warn!(
+            log,
+            "failed to update zone bundle index";
+            "directory" => %directory,
+            "reason" => ?e,
+        );
+    }
+}
+
+// Find zone bundles in the provided directory, which match the filter function.
+async fn filter_zone_bundles(
+    log: &Logger,
+    directory: &Utf8PathBuf,
+    filter: impl Fn(&ZoneBundleMetadata) -> bool,
+) -> Result<BTreeMap<Utf8PathBuf, ZoneBundleMetadata>, BundleError> {
+    debug!(log, "searching directory for zone bundles"; "directory" => %directory);
+    let index = load_zone_bundle_index(log, directory).await?;
+    Ok(index
+        .bundles
+        .into_iter()
+        .filter(|(_name, md)| filter(md))
+        .map(|(name, md)| (directory.join(name), md))
+        .collect())
+}
+
+// Return the per-zone directories within a storage directory.
+async fn zone_directories(
+    directory: &Utf8Path,
+) -> Result<Vec<Utf8PathBuf>, BundleError> {
+    let mut out = Vec::new();
+    let mut rd = tokio::fs::read_dir(directory).await.map_err(|err| {
+        BundleError::ReadDirectory { directory: directory.to_owned(), err }
+    })?;
+    while let Some(entry) = rd.next_entry().await.map_err(|err| {
+        BundleError::ReadDirectory { directory: directory.to_owned(), err }
+    })? {
+        out.push(Utf8PathBuf::try_from(entry.path())?);
+    }
+    Ok(out)
+}
+
+// Find zone bundles in all the provided storage directories, which match the
+// filter function.
+//
+// Directories which can't be read are skipped, and their errors are returned
+// alongside the bundles found in the others.
+async fn list_zone_bundles(
+    log: &Logger,
+    directories: &[Utf8PathBuf],
+    filter: impl Fn(&ZoneBundleMetadata) -> bool,
+) -> (BTreeSet<ZoneBundleMetadata>, DirectoryErrors) {
+    // The zone bundles are replicated in several places, so we'll use a set to
+    // collect them all, to avoid duplicating.
+    let mut bundles = BTreeSet::new();
+    let mut errors = DirectoryErrors::new();
+    for dir in directories {
+        let found = async {
+            let mut found = Vec::new();
+            for zone_dir in zone_directories(dir).await? {
+                found.extend(
+                    filter_zone_bundles(log, &zone_dir, &filter)
+                        .await?
+                        .into_values(),
+                );
+            }
+            Ok::<_, BundleError>(found)
+        };
+        match found.await {
+            Ok(found) => bundles.extend(found),
+            Err(e) => {
+                errors.insert(dir.clone(), e);
+            }
+        }
+    }
+    (bundles, errors)
+}
+
+// List a page of the zone bundles in all the provided storage directories which
+// match the filter function, in order of their IDs, starting after
+// `last_seen` when it's provided.
+//
+// Bundle IDs are ordered by zone name first, and each zone's bundles are kept
+// in a directory named for it. Rather than reading the index of every zone,
+// this walks the zone directories in order of their names, starting from the
+// zone of `last_seen`, and stops once the page is full. As in
+// `list_zone_bundles`, directories which can't be read are skipped, and their
+// errors are returned alongside the bundles found in the others.
+async fn list_zone_bundles_page(
+    log: &Logger,
+    directories: &[Utf8PathBuf],
+    filter: impl Fn(&ZoneBundleMetadata) -> bool,
+    last_seen: Option<&ZoneBundleId>,
+    limit: usize,
+) -> (Vec<ZoneBundleMetadata>, DirectoryErrors) {
+    let mut errors = DirectoryErrors::new();
+
+    // Collect the directories of each zone across all the storage
+    // directories, by zone name.
+    let mut zones: BTreeMap<String, Vec<(&Utf8PathBuf, Utf8PathBuf)>> =
+        BTreeMap::new();
+    for dir in directories {
+        match zone_directories(dir).await {
+            Ok(zone_dirs) => {
+                for zone_dir in zone_dirs.into_iter() {
+                    let Some(name) = zone_dir.file_name() else {
+                        continue;
+                    };
+                    zones
+                        .entry(name.to_string())
+                        .or_default()
+                        .push((dir, zone_dir));
+                }
+            }
+            Err(e) => {
+                errors.insert(dir.clone(), e);
+            }
+        }
+    }
+
+    let first_zone = last_seen.map(|id| id.zone_name.clone());
+    let mut page = Vec::new();
+    for (zone_name, zone_dirs) in zones.range(first_zone.unwrap_or_default()..)
+    {
+        // The zone bundles are replicated in several places, so we'll use a
+        // set to collect them all, to avoid duplicating.
+        let mut bundles = BTreeSet::new();
+        for (dir, zone_dir) in zone_dirs.iter() {
+            let found = filter_zone_bundles(log, zone_dir, |md| {
+                &md.id.zone_name == zone_name
+                    && last_seen.map_or(true, |id| &md.id > id)
+                    && filter(md)
+            })
+            .await;
+            match found {
+                Ok(found) => bundles.extend(found.into_values()),
+                Err(e) => {
+                    errors.insert((*dir).clone(), e);
+                }
+            }
+        }
+        page.extend(dedup_by_id(bundles));
+        if page.len() >= limit {
+            break;
+        }
+    }
+    page.truncate(limit);
+    (page, errors)
+}
+
+// Get the paths to and metadata of a zone bundle, if it exists.
+//
+// Zone bundles are replicated in multiple storage directories. This returns
+// every path at which the bundle with the provided ID exists, in the same
+// order as `directories`, along with errors for any directories that couldn't
+// be read.
+async fn get_zone_bundles(
+    log: &Logger,
+    directories: &[Utf8PathBuf],
+    zone_name: &str,
+    id: &Uuid,
+) -> (Vec<(Utf8PathBuf, ZoneBundleMetadata)>, DirectoryErrors) {
+    let mut out = Vec::with_capacity(directories.len());
+    let mut errors = DirectoryErrors::new();
+    for dir in directories {
+        let found = async {
+            let mut found = Vec::new();
+            for zone_dir in zone_directories(dir).await? {
+                found.extend(
+                    filter_zone_bundles(log, &zone_dir, |md| {
+                        md.id.zone_name == zone_name && md.id.bundle_id == *id
+                    })
+                    .await?,
+                );
+            }
+            Ok::<_, BundleError>(found)
+        };
+        match found.await {
+            Ok(found) => out.extend(found),
+            Err(e) => {
+                errors.insert(dir.clone(), e);
+            }
+        }
+    }
+    (out, errors)
+}
+
+/// The portion of a debug dataset used for zone bundles.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    JsonSchema,
+    PartialEq,
+    Serialize,
+)]
+pub struct BundleUtilization {
+    /// The total dataset quota, in bytes.
+    pub dataset_quota: u64,
+    /// The total number of bytes available for zone bundles.
+    ///
+    /// This is `dataset_quota` multiplied by the context's storage limit.
+    pub bytes_available: u64,
+    /// Total bundle usage, in bytes.
+    pub bytes_used: u64,
+}
+
+/// Utilization is summed across directories, field by field.
+///
+/// Note that each bundle is copied into every storage directory, and those
+/// directories live on separate (e.g., mirrored M.2) datasets. The sum is
+/// therefore the physical total across all datasets, not the logical capacity
+/// for bundles, which is closer to that of any single directory. The ratio of
+/// `bytes_used` to `bytes_available` is still representative of how full the
+/// bundle storage is on the sled as a whole.
+impl std::iter::Sum for BundleUtilization {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |acc, each| Self {
+            dataset_quota: acc.dataset_quota + each.dataset_quota,
+            bytes_available: acc.bytes_available + each.bytes_available,
+            bytes_used: acc.bytes_used + each.bytes_used,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct ZoneBundleInfo {
+    // The raw metadata for the bundle
+    metadata: ZoneBundleMetadata,
+    // The full path to the bundle
+    path: Utf8PathBuf,
+    // The number of bytes consumed on disk by the bundle
+    bytes: u64,
+}
+
+// Enumerate all zone bundles under the provided directories.
+//
+// Directories which can't be read are skipped, and their errors are returned
+// alongside the bundles found in the others.
+async fn enumerate_zone_bundles(
+    log: &Logger,
+    dirs: &[Utf8PathBuf],
+) -> (BTreeMap<Utf8PathBuf, Vec<ZoneBundleInfo>>, DirectoryErrors) {
+    let mut out = BTreeMap::new();
+    let mut errors = DirectoryErrors::new();
+
+    // Each of these is a storage directory.
+    //
+    // We should have under here zone-names, followed by bundles within each of
+    // those.
+    for dir in dirs.iter() {
+        let info_by_dir = async {
+            let mut info_by_dir = Vec::new();
+            for zone_dir in zone_directories(dir).await? {
+                let index = load_zone_bundle_index(log, &zone_dir).await?;
+                for (name, metadata) in index.bundles.into_iter() {
+                    let path = zone_dir.join(name);
+                    let bytes = tokio::fs::metadata(&path)
+                        .await
+                        .map_err(|err| BundleError::Metadata {
+                            path: path.clone(),
+                            err,
+                        })?
+                        .len();
+                    info_by_dir.push(ZoneBundleInfo { metadata, path, bytes });
+                }
+            }
+            Ok::<_, BundleError>(info_by_dir)
+        };
+        match info_by_dir.await {
+            Ok(info_by_dir) => {
+                out.insert(dir.clone(), info_by_dir);
+            }
+            Err(e) => {
+                errors.insert(dir.clone(), e);
+            }
+        }
+    }
+    (out, errors)
+}
+
+/// The count of bundles / bytes removed during a cleanup operation.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, Serialize)]
+pub struct CleanupCount {
+    /// The number of bundles removed.
+    bundles: u64,
+    /// The number of bytes removed.
+    bytes: u64,
+}
+
+/// An estimate of what a cleanup would do, summed across storage directories.
+#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, Serialize)]
+pub struct CleanupEstimate {
+    /// The number of bundles that would be examined.
+    bundles_examined: u64,
+    /// The number of bytes occupied by the bundles that would be examined.
+    bytes_examined: u64,
+    /// The number of bundles that would be removed.
+    bundles_to_remove: u64,
+    /// The number of bytes that would be removed.
+    bytes_to_remove: u64,
+}
+
+impl std::iter::Sum for CleanupEstimate {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |acc, each| Self {
+            bundles_examined: acc.bundles_examined + each.bundles_examined,
+            bytes_examined: acc.bytes_examined + each.bytes_examined,
+            bundles_to_remove: acc.bundles_to_remove + each.bundles_to_remove,
+            bytes_to_remove: acc.bytes_to_remove + each.bytes_to_remove,
+        })
+    }
+}
+
+// The bundles a cleanup would remove from a single storage directory.
+#[derive(Debug)]
+struct DirectoryCleanupPlan {
+    // All the bundles in the directory, sorted from lowest to highest priority.
+    info: Vec<ZoneBundleInfo>,
+    // Indices into `info` of the bundles to remove.
+    to_remove: Vec<usize>,
+}
+
+impl DirectoryCleanupPlan {
+    fn estimate(&self) -> CleanupEstimate {
+        CleanupEstimate {
+            bundles_examined: self.info.len() as u64,
+            bytes_examined: self.info.iter().map(|each| each.bytes).sum(),
+            bundles_to_remove: self.to_remove.len() as u64,
+            bytes_to_remove: self
+                .to_remove
+                .iter()
+                .map(|&i| self.info[i].bytes)
+                .sum(),
+        }
+    }
+}
+
+// Decide which old bundles a cleanup should remove, according to the strategy,
+// without removing anything.
+//
+// If every directory is within its storage limit, no bundles are enumerated and
+// no plans are returned.
+async fn plan_cleanup(
+    log: &Logger,
+    storage_dirs: &[Utf8PathBuf],
+    context: &CleanupContext,
+) -> Result<
+    PartialResult<BTreeMap<Utf8PathBuf, DirectoryCleanupPlan>>,
+    BundleError,
+> {
+    // First, determine how much space we are allowed to use and have used.
+    //
+    // Directories we can't read are skipped, so that one bad disk doesn't stop
+    // us from cleaning up the others.
+    //
+    // Let's avoid doing anything at all if we're still within the limits.
+    let (usages, errors) =
+        compute_bundle_utilization(log, storage_dirs, context).await;
+    let PartialResult { result: usages, mut directory_errors } =
+        partial_result(log, storage_dirs.len(), usages, errors)?;
+    if usages.values().all(|usage| usage.bytes_used <= usage.bytes_available) {
+        debug!(log, "all usages below storage limit, returning");
+        return Ok(PartialResult { result: BTreeMap::new(), directory_errors });
+    }
+
+    // There's some work to do, let's enumerate all the bundles.
+    let (bundles, errors) = enumerate_zone_bundles(log, &storage_dirs).await;
+    let PartialResult { result: bundles, directory_errors: errors } =
+        partial_result(log, storage_dirs.len(), bundles, errors)?;
+    directory_errors.extend(errors);
+    debug!(
+        log,
+        "enumerated {} zone bundles across {} directories",
+        bundles.values().map(Vec::len).sum::<usize>(),
+        bundles.len(),
+    );
+
+    // Select bundles from each storage directory, until we fall below the
+    // number of bytes we would like to use to satisfy the storage limit.
+    let mut plans = BTreeMap::new();
+    for (dir, mut info) in bundles.into_iter() {
+        let Some(current_usage) = usages.get(&dir) else {
+            continue;
+        };
+
+        // Sort all the bundles in the current directory, using the priority
+        // described in `context.priority`.
+        info.sort_by(|lhs, rhs| context.priority.compare_bundles(lhs, rhs));
+
+        // Remove bundles until we fall below the threshold, or until only the
+        // bundles we must keep remain.
+        let (to_remove, n_bytes) = select_bundles_to_remove(
+            &info,
+            context.min_keep_per_zone,
+            current_usage.bytes_used,
+            current_usage.bytes_available,
+        );
+        if n_bytes > current_usage.bytes_available {
+            warn!(
+                log,
+                "zone bundles will exceed the storage limit, to keep the \
+                most recent bundles of each zone";
+                "directory" => dir.as_str(),
+                "min_keep_per_zone" => context.min_keep_per_zone,
+                "bytes_used" => n_bytes,
+                "bytes_available" => current_usage.bytes_available,
+            );
+        }
+        plans.insert(dir, DirectoryCleanupPlan { info, to_remove });
+    }
+    Ok(PartialResult { result: plans, directory_errors })
+}
+
+// Run a cleanup, removing old bundles according to the strategy.
+//
+// Return the number of bundles removed and the new usage.
+async fn run_cleanup(
+    log: &Logger,
+    storage_dirs: &[Utf8PathBuf],
+    context: &CleanupContext,
+) -> Result<PartialResult<BTreeMap<Utf8PathBuf, CleanupCount>>, BundleError> {
+    let PartialResult { result: plans, directory_errors } =
+        plan_cleanup(log, storage_dirs, context).await?;
+
+    // Remove the selected bundles from each storage directory.
+    let mut cleanup_counts = BTreeMap::new();
+    for (dir, DirectoryCleanupPlan { info, to_remove }) in plans.into_iter() {
+        debug!(
+            log,
+            "cleaning up bundles from directory";
+            "directory" => dir.as_str()
+        );
+        let mut count = CleanupCount::default();
+        for i in to_remove.into_iter() {
+            let each = &info[i];
+
+            // Report which dimension of the priority order ranked this bundle
+            // below the next one, to make tuning the order easier.
+            let deciding_dimension = info.get(i + 1).and_then(|next| {
+                context.priority.compare_bundles_with_reason(each, next).1
+            });
+            debug!(
+                log,
+                "removing zone bundle";
+                "path" => %each.path,
+                "cause" => ?each.metadata.cause,
+                "time_created" => %each.metadata.time_created,
+                "deciding_dimension" => ?deciding_dimension,
+            );
+            tokio::fs::remove_file(&each.path).await.map_err(|_| {
+                BundleError::Cleanup(anyhow!("failed to remove bundle"))
+            })?;
+            trace!(log, "removed old zone bundle"; "info" => ?&each);
+            if let (Some(zone_dir), Some(name)) =
+                (each.path.parent(), each.path.file_name())
+            {
+                update_zone_bundle_index(log, zone_dir, |index| {
+                    index.bundles.remove(name);
+                })
+                .await;
+            }
+            count.bundles += 1;
+            count.bytes += each.bytes;
+        }
+
+        cleanup_counts.insert(dir, count);
+    }
+    info!(log, "finished bundle cleanup"; "cleanup_counts" => ?&cleanup_counts);
+    Ok(PartialResult { result: cleanup_counts, directory_errors })
+}
+
+// Select the bundles to remove from a single storage directory.
+//
+// `info` must already be sorted from lowest to highest priority. Bundles are
+// selected in that order until the bytes used fall to `bytes_available`, except
+// that the `min_keep_per_zone` most recent bundles of each zone are never
+// selected, even if that means the storage limit can't be met.
+//
+// This returns the indices into `info` of the bundles to remove, and the
+// number of bytes that will be used once they're gone.
+fn select_bundles_to_remove(
+    info: &[ZoneBundleInfo],
+    min_keep_per_zone: u32,
+    bytes_used: u64,
+    bytes_available: u64,
+) -> (Vec<usize>, u64) {
+    // Find the bundles we must keep, the newest ones for each zone.
+    let mut by_zone: BTreeMap<&str, Vec<&ZoneBundleInfo>> = BTreeMap::new();
+    for each in info.iter() {
+        by_zone.entry(&each.metadata.id.zone_name).or_default().push(each);
+    }
+    let mut keep = BTreeSet::new();
+    for bundles in by_zone.values_mut() {
+        bundles.sort_by(|lhs, rhs| {
+            rhs.metadata.time_created.cmp(&lhs.metadata.time_created)
+        });
+        keep.extend(
+            bundles
+                .iter()
+                .take(min_keep_per_zone as usize)
+                .map(|each| &each.path),
+        );
+    }
+
+    let mut to_remove = Vec::new();
+    let mut n_bytes = bytes_used;
+    for (i, each) in info.iter().enumerate() {
+        if n_bytes <= bytes_available {
+            break;
+        }
+        if keep.contains(&each.path) {
+            continue;
+        }
+        to_remove.push(i);
+        n_bytes = n_bytes.saturating_sub(each.bytes);
+    }
+    (to_remove, n_bytes)
+}
+
+// Return the total utilization for all zone bundles.
+//
+// Directories whose utilization can't be computed are skipped, and their errors
+// are returned alongside the utilization of the others.
+async fn compute_bundle_utilization(
+    log: &Logger,
+    storage_dirs: &[Utf8PathBuf],
+    context: &CleanupContext,
+) -> (BTreeMap<Utf8PathBuf, BundleUtilization>, DirectoryErrors) {
+    let mut out = BTreeMap::new();
+    let mut errors = DirectoryErrors::new();
+    for dir in storage_dirs.iter() {
+        match compute_directory_utilization(log, dir, context).await {
+            Ok(utilization) => {
+                out.insert(dir.clone(), utilization);
+            }
+            Err(e) => {
+                errors.insert(dir.clone(), e);
+            }
+        }
+    }
+    (out, errors)
+}
+
+// Return the utilization of a single storage directory.
+async fn compute_directory_utilization(
+    log: &Logger,
+    dir: &Utf8PathBuf,
+    context: &CleanupContext,
+) -> Result<BundleUtilization, BundleError> {
+    debug!(log, "computing bundle usage"; "directory" => %dir);
+    // Fetch the ZFS dataset quota.
+    let dataset_quota = zfs_quota(dir).await?;
+    debug!(log, "computed dataset quota"; "quota" => dataset_quota);
+
+    // Compute the bytes available, using the provided storage limit.
+    let bytes_available = context.storage_limit.bytes_available(dataset_quota);
+    debug!(
+        log,
+        "computed bytes available";
+        "storage_limit" => %context.storage_limit,
+        "bytes_available" => bytes_available
+    );
+
+    // Compute the size of the actual storage directory.
+    //
+    // TODO-correctness: This takes into account the directories themselves,
+    // and may be not quite what we want. But it is very easy and pretty
+    // close.
+    let bytes_used = disk_usage(dir).await?;
+    debug!(log, "computed bytes used"; "bytes_used" => bytes_used);
+    Ok(BundleUtilization { dataset_quota, bytes_available, bytes_used })
+}
+
+// Recompress the oldest bundles in each directory that is within its storage
+// limit, returning the number of bytes reclaimed in each.
+//
+// Directories whose utilization can't be computed, or which are over their
+// limit, are skipped. In the latter case, it's more important to free space by
+// cleaning up bundles than to shrink them.
+async fn run_recompaction<T>(
+    log: &Logger,
+    storage_dirs: &[Utf8PathBuf],
+    context: &CleanupContext,
+    active_reads: &ActiveReads,
+    lock: &Mutex<T>,
+) -> BTreeMap<Utf8PathBuf, u64> {
+    let (usages, _errors) =
+        compute_bundle_utilization(log, storage_dirs, context).await;
+    let mut out = BTreeMap::new();
+    for (dir, usage) in usages.into_iter() {
+        if usage.bytes_used > usage.bytes_available {
+            debug!(
+                log,
+                "skipping recompaction of directory over its storage limit";
+                "directory" => %dir,
+            );
+            continue;
+        }
+        match recompress_oldest_bundles(
+            log,
+            &dir,
+            active_reads,
+            lock,
+            MAX_RECOMPRESSED_PER_CLEANUP,
+        )
+        .await
+        {
+            Ok(reclaimed) => {
+                out.insert(dir, reclaimed);
+            }
+            Err(e) => warn!(
+                log,
+                "failed to recompress zone bundles";
+                "directory" => %dir,
+                "reason" => ?e,
+            ),
+        }
+    }
+    out
+}
+
+// Recompress up to `max` of the oldest bundles in a storage directory which are
+// not already at the highest compression level, and not being read.
+//
+// Recompression is slow, so it's done without holding `lock`, which is only
+// taken to enumerate the bundles, and to move each recompressed bundle into
+// place and update the index.
+//
+// Return the number of bytes reclaimed.
+async fn recompress_oldest_bundles<T>(
+    log: &Logger,
+    dir: &Utf8PathBuf,
+    active_reads: &ActiveReads,
+    lock: &Mutex<T>,
+    max: usize,
+) -> Result<u64, BundleError> {
+    let (bundles, errors) = {
+        let _lock = lock.lock().await;
+        enumerate_zone_bundles(log, std::slice::from_ref(dir)).await
+    };
+    if let Some((_dir, err)) = errors.into_iter().next() {
+        return Err(err);
+    }
+    let mut info: Vec<_> = bundles.into_values().flatten().collect();
+    info.sort_by(|lhs, rhs| {
+        lhs.metadata.time_created.cmp(&rhs.metadata.time_created)
+    });
+
+    let mut reclaimed = 0;
+    let mut n_recompressed = 0;
+    for each in info.into_iter() {
+        if n_recompressed >= max {
+            break;
+        }
+        if active_reads.contains(&each.path) {
+            debug!(
+                log,
+                "skipping recompression of bundle being read";
+                "path" => %each.path,
+            );
+            continue;
+        }
+        match is_fully_compressed(&each.path).await {
+            Ok(true) => continue,
+            Ok(false) => {}
+            Err(e) => {
+                warn!(
+                    log,
+                    "failed to check zone bundle compression, skipping it";
+                    "path" => %each.path,
+                    "reason" => ?e,
+                );
+                continue;
+            }
+        }
+        n_recompressed += 1;
+        let Some((tmp_path, new_bytes)) =
+            recompress_bundle(each.path.clone()).await?
+        else {
+            continue;
+        };
+
+        // The hash of the bundle has changed with its contents.
+        let content_hash = compute_content_hash(&tmp_path).await.ok();
+
+        let _lock = lock.lock().await;
+        if !replace_recompressed_bundle(&tmp_path, &each.path).await? {
+            debug!(
+                log,
+                "zone bundle removed while being recompressed";
+                "path" => %each.path,
+            );
+            continue;
+        }
+        debug!(
+            log,
+            "recompressed zone bundle";
+            "path" => %each.path,
+            "old_bytes" => each.bytes,
+            "new_bytes" => new_bytes,
+        );
+        reclaimed += each.bytes.saturating_sub(new_bytes);
+        if let (Some(zone_dir), Some(name)) =
+            (each.path.parent(), each.path.file_name())
+        {
+            update_zone_bundle_index(log, zone_dir, |index| {
+                if let Some(md) = index.bundles.get_mut(name) {
+                    md.content_hash = content_hash;
+                }
+            })
+            .await;
+        }
+    }
+    Ok(reclaimed)
+}
+
+// Return true if the bundle at `path` was compressed at the highest level.
+//
+// This is recorded in the extra flags of the gzip header, which are 2 for the
+// slowest, best compression.
+async fn is_fully_compressed(path: &Utf8Path) -> Result<bool, BundleError> {
+    const XFL_OFFSET: usize = 8;
+    const XFL_BEST: u8 = 2;
+    let mut header = [0u8; XFL_OFFSET + 1];
+    let mut file = tokio::fs::File::open(path).await.map_err(|err| {
+        BundleError::OpenBundleFile { path: path.to_owned(), err }
+    })?;
+    tokio::io::AsyncReadExt::read_exact(&mut file, &mut header).await.map_err(
+        |err| BundleError::ReadBundleData { path: path.to_owned(), err },
+    )?;
+    Ok(header[XFL_OFFSET] == XFL_BEST)
+}
+
+// Recompress the bundle at `path` at the highest compression level, into a
+// temporary file alongside it, returning that file's path and size in bytes.
+//
+// The uncompressed archive is unchanged. If recompressing would make the bundle
+// larger, the temporary file is removed and `None` is returned. Otherwise, it's
+// up to the caller to move it into place with `replace_recompressed_bundle`.
+async fn recompress_bundle(
+    path: Utf8PathBuf,
+) -> Result<Option<(Utf8PathBuf, u64)>, BundleError> {
+    let task =
+        tokio::task::spawn_blocking(move || recompress_bundle_impl(&path));
+    task.await?
+}
+
+fn recompress_bundle_impl(
+    path: &Utf8Path,
+) -> Result<Option<(Utf8PathBuf, u64)>, BundleError> {
+    let old_bytes = std::fs::metadata(path)
+        .map_err(|err| BundleError::Metadata { path: path.to_owned(), err })?
+        .len();
+    let reader = std::fs::File::open(path).map_err(|err| {
+        BundleError::OpenBundleFile { path: path.to_owned(), err }
+    })?;
+    let mut decoder = GzDecoder::new(std::io::BufReader::new(reader));
+
+    // Write the new bundle alongside the original, to be moved into place once
+    // it's complete. Anyone with the original open can continue reading it
+    // undisturbed.
+    let tmp_path =
+        Utf8PathBuf::from(format!("{path}{ZONE_BUNDLE_RECOMPRESS_TMP_SUFFIX}"));
+    let copy_err = |err| BundleError::CopyArchive {
+        from: path.to_owned(),
+        to: tmp_path.clone(),
+        err,
+    };
+    let tmp = std::fs::File::create(&tmp_path).map_err(|err| {
+        BundleError::OpenBundleFile { path: tmp_path.clone(), err }
+    })?;
+    let mut encoder = flate2::GzBuilder::new()
+        .filename(path.file_name().unwrap_or_default())
+        .write(tmp, flate2::Compression::best());
+    let new_bytes = std::io::copy(&mut decoder, &mut encoder)
+        .and_then(|_| encoder.finish())
+        .and_then(|tmp| tmp.sync_all().and_then(|_| tmp.metadata()))
+        .map(|md| md.len())
+        .map_err(|err| {
+            let _ = std::fs::remove_file(&tmp_path);
+            copy_err(err)
+        })?;
+    if new_bytes > old_bytes {
+        let _ = std::fs::remove_file(&tmp_path);
+        return Ok(None);
+    }
+    Ok(Some((tmp_path, new_bytes)))
+}
+
+// Move the recompressed copy of a bundle at `tmp_path` into place at `path`.
+//
+// The bundle may have been removed while it was being recompressed, e.g., by a
+// cleanup. Rather than bring it back, the copy is removed too, and false is
+// returned.
+async fn replace_recompressed_bundle(
+    tmp_path: &Utf8Path,
+    path: &Utf8Path,
+) -> Result<bool, BundleError> {
+    let copy_err = |err| BundleError::CopyArchive {
+        from: path.to_owned(),
+        to: tmp_path.to_owned(),
+        err,
+    };
+    let exists = tokio::fs::try_exists(path).await;
+    if !matches!(exists, Ok(true)) {
+        let _ = tokio::fs::remove_file(tmp_path).await;
+        return exists.map(|_| false).map_err(copy_err);
+    }
+    if let Err(err) = tokio::fs::rename(tmp_path, path).await {
+        let _ = tokio::fs::remove_file(tmp_path).await;
+        return Err(copy_err(err));
+    }
+    Ok(true)
+}
+
+/// Context provided for the zone bundle cleanup task.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize,
+)]
+pub struct CleanupContext {
+    /// The period on which automatic checks and cleanup is performed.
+    pub period: CleanupPeriod,
+    /// The limit on the dataset quota available for zone bundles.
+    pub storage_limit: StorageLimit,
+    /// The priority ordering for keeping old bundles.
+    pub priority: PriorityOrder,
+    /// The number of most recent bundles of each zone which are never removed
+    /// by automatic cleanup.
+    ///
+    /// This takes precedence over the storage limit: if keeping these bundles
+    /// means the limit can't be met, they're kept anyway, and a warning is
+    /// logged.
+    #[serde(default)]
+    pub min_keep_per_zone: u32,
+    /// Whether to recompress old bundles while there's no storage pressure.
+    ///
+    /// When enabled, bundles are created with fast compression, and each
+    /// periodic cleanup recompresses a few of the oldest bundles in every
+    /// directory within its storage limit at the highest compression level.
+    /// This trades idle CPU time for space. Bundles being read are skipped.
+    #[serde(default)]
+    pub recompress_when_idle: bool,
+}
+
+// Return the number of bytes occupied by the provided directory.
+//
+// This returns an error if:
+//
+// - The "du" command fails
+// - Parsing stdout fails
+// - Parsing the actual size as a u64 fails
+async fn disk_usage(path: &Utf8PathBuf) -> Result<u64, BundleError> {
+    // Each OS implements slightly different `du` options.
+    //
+    // Linux and illumos support the "apparent" size in bytes, though using
+    // different options. macOS doesn't support bytes at all, and has a minimum
+    // block size of 512.
+    //
+    // We'll suffer the lower resolution on macOS, and get higher resolution on
+    // the others.
+    cfg_if::cfg_if! {
+        if #[cfg(target_os = "illumos")] {
+            const BLOCK_SIZE: u64 = 1;
+            const DU_ARG: &str = "-A";
+        } else if #[cfg(target_os = "linux")] {
+            const BLOCK_SIZE: u64 = 1;
+            const DU_ARG: &str = "-b";
+        } else if #[cfg(target_os = "macos")] {
+            const BLOCK_SIZE: u64 = 512;
+            const DU_ARG: &str = "-k";
+        } else {
+            compile_error!("unsupported target OS");
+        }
+    }
+    const DU: &str = "/usr/bin/du";
+    let args = &[DU_ARG, "-s", path.as_str()];
+    let output = Command::new(DU).args(args).output().await.map_err(|err| {
+        BundleError::Command { cmd: format!("{DU} {}", args.join(" ")), err }
+    })?;
+    let err = |msg: &str| {
+        BundleError::Cleanup(anyhow!(
+            "failed to fetch disk usage for {}: {}",
+            path,
+            msg,
+        ))
+    };
+    if !output.status.success() {
+        return Err(err("du command failed"));
+    }
+    let Ok(s) = std::str::from_utf8(&output.stdout) else {
+        return Err(err("non-UTF8 stdout"));
+    };
+    let Some(line) = s.lines().next() else {
+        return Err(err("no lines in du output"));
+    };
+    let Some(part) = line.trim().split_ascii_whitespace().next() else {
+        return Err(err("no disk usage size computed in output"));
+    };
+    part.parse()
+        .map(|x: u64| x.saturating_mul(BLOCK_SIZE))
+        .map_err(|_| err("failed to parse du output"))
+}
+
+// Return the quota for a ZFS dataset, or the available size.
+//
+// This fails if:
+//
+// - The "zfs" command fails
+// - Parsing stdout fails
+// - Parsing the actual quota as a u64 fails
+async fn zfs_quota(path: &Utf8PathBuf) -> Result<u64, BundleError> {
+    let args = &["list", "-Hpo", "quota,avail", path.as_str()];
+    let output =
+        Command::new(ZFS).args(args).output().await.map_err(|err| {
+            BundleError::Command {
+                cmd: format!("{ZFS} {}", args.join(" ")),
+                err,
+            }
+        })?;
+    let err = |msg: &str| {
+        BundleError::Cleanup(anyhow!(
+            "failed to fetch ZFS quota for {}: {}",
+            path,
+            msg,
+        ))
+    };
+    if !output.status.success() {
+        return Err(err("zfs list command failed"));
+    }
+    let Ok(s) = std::str::from_utf8(&output.stdout) else {
+        return Err(err("non-UTF8 stdout"));
+    };
+    let Some(line) = s.lines().next() else {
+        return Err(err("no lines in zfs list output"));
+    };
+    let mut parts = line.split_ascii_whitespace();
+    let quota = parts.next().ok_or_else(|| err("no quota part of line"))?;
+    let avail = parts.next().ok_or_else(|| err("no avail part of line"))?;
+
+    // Parse the available space, which is always defined.
+    let avail = avail
+        .trim()
+        .parse()
+        .map_err(|_| err("failed to parse available space"))?;
+
+    // Quotas can be reported a few different ways.
+    //
+    // If the dataset is a volume (which should not happen, but we don't enforce
+    // here), then this is a literal dash `-`. Something without a quota is
+    // reported as `0`. Anything else is an integer.
+    //
+    // No quota is reported as `u64::MAX`.
+    match quota.trim() {
+        "-" | "0" => Ok(avail),
+        x => x.parse().or(Ok(avail)),
+    }
+}
 
-    #[error("I/O error opening bundle tarball '{path}'")]
-    OpenBundleFile {
-        path: Utf8PathBuf,
-        #[source]
-        err: std::io::Error,
-    },
+/// The limit on space allowed for zone bundles, as a percentage of the overall
+/// dataset's quota.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    JsonSchema,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+#[cfg_attr(test, derive(test_strategy::Arbitrary))]
+pub struct StorageLimit(
+    #[cfg_attr(
+        test,
+        strategy((StorageLimit::MIN.0 + 1)..=StorageLimit::MAX.0)
+    )]
+    u8,
+);
 
-    #[error("I/O error adding bundle tarball data to '{tarball_path}'")]
-    AddBundleData {
-        tarball_path: Utf8PathBuf,
-        #[source]
-        err: std::io::Error,
-    },
+impl std::fmt::Display for StorageLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}%", self.as_u8())
+    }
+}
 
-    #[error("I/O error reading bundle tarball data from '{path}'")]
-    ReadBundleData {
-        path: Utf8PathBuf,
-        #[source]
-        err: std::io::Error,
-    },
+impl Default for StorageLimit {
+    fn default() -> Self {
+        StorageLimit(25)
+    }
+}
 
-    #[error("I/O error copying bundle tarball from '{from}' to '{to}'")]
-    CopyArchive {
-        from: Utf8PathBuf,
-        to: Utf8PathBuf,
-        #[source]
-        err: std::io::Error,
-    },
+impl StorageLimit {
+    /// Minimum percentage of dataset quota supported.
+    pub const MIN: Self = Self(0);
 
-    #[error("I/O error reading directory '{directory}'")]
-    ReadDirectory {
-        directory: Utf8PathBuf,
-        #[source]
-        err: std::io::Error,
-    },
+    /// Maximum percentage of dataset quota supported.
+    pub const MAX: Self = Self(50);
 
-    #[error("I/O error fetching metadata for '{path}'")]
-    Metadata {
-        path: Utf8PathBuf,
-        #[source]
-        err: std::io::Error,
-    },
+    /// Construct a new limit allowed for zone bundles.
+    ///
+    /// This should be expressed as a percentage, in the range (Self::MIN,
+    /// Self::MAX].
+    pub const fn new(percentage: u8) -> Result<Self, BundleError> {
+        if percentage > Self::MIN.0 && percentage <= Self::MAX.0 {
+            Ok(Self(percentage))
+        } else {
+            Err(BundleError::InvalidStorageLimit)
+        }
+    }
 
-    #[error("TOML serialization failure")]
-    Serialization(#[from] toml::ser::Error),
+    /// Return the contained quota percentage.
+    pub const fn as_u8(&self) -> u8 {
+        self.0
+    }
 
-    #[error("TOML deserialization failure")]
-    Deserialization(#[from] toml::de::Error),
+    // Compute the number of bytes available from a dataset quota, in bytes.
+    const fn bytes_available(&self, dataset_quota: u64) -> u64 {
+        (dataset_quota * self.as_u8() as u64) / 100
+    }
+}
 
-    #[error("No zone named '{name}' is available for bundling")]
-    NoSuchZone { name: String },
+/// A dimension along with bundles can be sorted, to determine priority.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Deserialize,
+    Eq,
+    Hash,
+    JsonSchema,
+    Serialize,
+    Ord,
+    PartialEq,
+    PartialOrd,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PriorityDimension {
+    /// Sorting by time, with older bundles with lower priority.
+    Time,
+    /// Sorting by the cause for creating the bundle.
+    Cause,
+    // TODO-completeness: Support zone or zone type (e.g., service vs instance)?
+}
 
-    #[error("No storage available for bundles")]
-    NoStorage,
+/// The priority order for bundles during cleanup.
+///
+/// Bundles are sorted along the dimensions in [`PriorityDimension`], with each
+/// dimension appearing exactly once. During cleanup, lesser-priority bundles
+/// are pruned first, to maintain the dataset quota. Note that bundles are
+/// sorted by each dimension in the order in which they appear, with each
+/// dimension having higher priority than the next.
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
+#[cfg_attr(test, derive(test_strategy::Arbitrary))]
+pub struct PriorityOrder(
+    // Any permutation of the dimensions is a valid order.
+    #[cfg_attr(
+        test,
+        strategy(
+            Just(PriorityOrder::DEFAULT.0.to_vec())
+                .prop_shuffle()
+                .prop_map(|dims| PriorityOrder::new(&dims).unwrap().0)
+        )
+    )]
+    [PriorityDimension; PriorityOrder::EXPECTED_SIZE],
+);
 
-    #[error("Failed to join zone bundling task")]
-    Task(#[from] tokio::task::JoinError),
+impl std::ops::Deref for PriorityOrder {
+    type Target = [PriorityDimension; PriorityOrder::EXPECTED_SIZE];
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
 
-    #[error("Failed to create bundle: {0}")]
-    BundleFailed(#[from] anyhow::Error),
+impl Default for PriorityOrder {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
 
-    #[error("Zone error")]
-    Zone(#[from] AdmError),
+impl PriorityOrder {
+    // NOTE: Must match the number of variants in `PriorityDimension`.
+    const EXPECTED_SIZE: usize = 2;
+    const DEFAULT: Self =
+        Self([PriorityDimension::Cause, PriorityDimension::Time]);
+
+    /// Construct a new priority order.
+    ///
+    /// This requires that each dimension appear exactly once.
+    pub fn new(dims: &[PriorityDimension]) -> Result<Self, BundleError> {
+        if dims.len() != Self::EXPECTED_SIZE {
+            return Err(BundleError::InvalidPriorityOrder);
+        }
+        let mut seen = HashSet::new();
+        for dim in dims.iter() {
+            if !seen.insert(dim) {
+                return Err(BundleError::InvalidPriorityOrder);
+            }
+        }
+        Ok(Self(dims.try_into().unwrap()))
+    }
+
+    // Order zone bundle info according to the contained priority.
+    //
+    // We sort the info by each dimension, in the order in which it appears.
+    // That means earlier dimensions have higher priority than later ones.
+    fn compare_bundles(
+        &self,
+        lhs: &ZoneBundleInfo,
+        rhs: &ZoneBundleInfo,
+    ) -> Ordering {
+        self.compare_bundles_with_reason(lhs, rhs).0
+    }
+
+    // Order zone bundle info according to the contained priority, and return
+    // the dimension which decided the order.
+    //
+    // The dimension is `None` if the bundles are equal in every dimension.
+    fn compare_bundles_with_reason(
+        &self,
+        lhs: &ZoneBundleInfo,
+        rhs: &ZoneBundleInfo,
+    ) -> (Ordering, Option<PriorityDimension>) {
+        for dim in self.0.iter() {
+            let ord = match dim {
+                PriorityDimension::Cause => {
+                    lhs.metadata.cause.cmp(&rhs.metadata.cause)
+                }
+                PriorityDimension::Time => {
+                    lhs.metadata.time_created.cmp(&rhs.metadata.time_created)
+                }
+            };
+            if matches!(ord, Ordering::Equal) {
+                continue;
+            }
+            return (ord, Some(*dim));
+        }
+        (Ordering::Equal, None)
+    }
+}
+
+/// A period on which bundles are automatically cleaned up.
+#[derive(
+    Clone, Copy, Deserialize, JsonSchema, PartialEq, PartialOrd, Serialize,
+)]
+#[cfg_attr(test, derive(test_strategy::Arbitrary))]
+pub struct CleanupPeriod(
+    #[cfg_attr(
+        test,
+        strategy(
+            (CleanupPeriod::MIN.0.as_secs()..=CleanupPeriod::MAX.0.as_secs())
+                .prop_map(Duration::from_secs)
+        )
+    )]
+    Duration,
+);
+
+impl Default for CleanupPeriod {
+    fn default() -> Self {
+        Self(Duration::from_secs(600))
+    }
+}
+
+impl CleanupPeriod {
+    /// The minimum supported cleanup period.
+    pub const MIN: Self = Self(Duration::from_secs(60));
+
+    /// The maximum supported cleanup period.
+    pub const MAX: Self = Self(Duration::from_secs(60 * 60 * 24));
+
+    /// Construct a new cleanup period, checking that it's valid.
+    pub fn new(duration: Duration) -> Result<Self, BundleError> {
+        if duration >= Self::MIN.as_duration()
+            && duration <= Self::MAX.as_duration()
+        {
+            Ok(Self(duration))
+        } else {
+            Err(BundleError::InvalidCleanupPeriod)
+        }
+    }
+
+    /// Return the period as a duration.
+    pub const fn as_duration(&self) -> Duration {
+        self.0
+    }
+}
+
+impl TryFrom<Duration> for CleanupPeriod {
+    type Error = BundleError;
+
+    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
+        Self::new(duration)
+    }
+}
+
+impl std::fmt::Debug for CleanupPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bundle_matches;
+    use super::compute_content_hash;
+    use super::default_zone_wide_commands;
+    use super::delete_bundles_for_zone;
+    use super::diff_zone_bundles_impl;
+    use super::disk_usage;
+    use super::enumerate_zone_bundles;
+    use super::extract_zone_bundle_file_impl;
+    use super::extract_zone_bundle_metadata_and_hash_impl;
+    use super::filter_zone_bundles;
+    use super::insert_data;
+    use super::is_excluded_from_auto_bundle;
+    use super::is_fully_compressed;
+    use super::list_zone_bundles;
+    use super::metrics::ZoneBundleMetrics;
+    use super::metrics::MAX_SAMPLES;
+    use super::read_zone_bundle_index;
+    use super::recompress_oldest_bundles;
+    use super::replace_recompressed_bundle;
+    use super::select_bundles_to_remove;
+    use super::supervise_cleanup_task;
+    use super::validate_zone_name;
+    use super::validate_zone_wide_command;
+    use super::ActiveReads;
+    use super::BundleCopies;
+    use super::BundleDetailLevel;
+    use super::BundleEntryDiff;
+    use super::BundleError;
+    use super::BundleFile;
+    use super::BundleFiles;
+    use super::BundleUtilization;
+    use super::CleanupContext;
+    use super::CleanupPeriod;
+    use super::ManagedZones;
+    use super::PriorityDimension;
+    use super::PriorityOrder;
+    use super::StorageLimit;
+    use super::StorageResources;
+    use super::Utf8Path;
+    use super::Utf8PathBuf;
+    use super::ZoneBundleCause;
+    use super::ZoneBundleId;
+    use super::ZoneBundleInfo;
+    use super::ZoneBundleMetadata;
+    use super::ZoneBundler;
+    use super::CLEANUP_TASK_HEALTHY_RUNTIME;
+    use super::MAX_EXTRACTED_FILE_SIZE;
+    use super::MAX_TEXT_DIFF_SIZE;
+    use super::ZONE_BUNDLE_CREATE_TMP_SUFFIX;
+    use super::ZONE_BUNDLE_INDEX_FILENAME;
+    use super::ZONE_BUNDLE_METADATA_FILENAME;
+    use anyhow::Context;
+    use camino_tempfile::Utf8TempDir;
+    use chrono::TimeZone;
+    use chrono::Utc;
+    use futures::TryStreamExt;
+    use illumos_utils::zone::MockZones;
+    use proptest::prelude::*;
+    use sha2::Digest;
+    use sha2::Sha256;
+    use slog::Logger;
+    use std::collections::BTreeMap;
+    use std::collections::BTreeSet;
+    use std::io::Read;
+    use std::os::unix::process::CommandExt;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use test_strategy::proptest;
+    use tokio::sync::Mutex;
 
-    #[error(transparent)]
-    PathBuf(#[from] FromPathBufError),
+    #[test]
+    fn test_sort_zone_bundle_cause() {
+        use ZoneBundleCause::*;
+        let mut original = [
+            ExplicitRequest,
+            Other,
+            TerminatedInstance,
+            Scheduled,
+            UnexpectedZone,
+        ];
+        let expected = [
+            Other,
+            Scheduled,
+            UnexpectedZone,
+            TerminatedInstance,
+            ExplicitRequest,
+        ];
+        original.sort();
+        assert_eq!(original, expected);
+    }
 
-    #[error("Zone '{name}' cannot currently be bundled")]
-    Unavailable { name: String },
+    #[test]
+    fn test_auto_bundle_exclusions() {
+        let exclusions = BTreeSet::from([String::from("oxz_noisy")]);
+        let excluded = "oxz_noisy_4b3b7e5e";
+        let included = "oxz_quiet_4b3b7e5e";
+
+        // Automatic bundles of an excluded zone are skipped...
+        assert!(is_excluded_from_auto_bundle(
+            &exclusions,
+            excluded,
+            ZoneBundleCause::UnexpectedZone,
+        ));
+        assert!(is_excluded_from_auto_bundle(
+            &exclusions,
+            excluded,
+            ZoneBundleCause::TerminatedInstance,
+        ));
 
-    #[error("Storage limit must be expressed as a percentage in (0, 100]")]
-    InvalidStorageLimit,
+        // ... but explicit requests still create them.
+        assert!(!is_excluded_from_auto_bundle(
+            &exclusions,
+            excluded,
+            ZoneBundleCause::ExplicitRequest,
+        ));
+        assert!(!is_excluded_from_auto_bundle(
+            &exclusions,
+            excluded,
+            ZoneBundleCause::Other,
+        ));
 
-    #[error(
-        "Cleanup period must be between {min:?} and {max:?}, inclusive",
-        min = CleanupPeriod::MIN,
-        max = CleanupPeriod::MAX,
-    )]
-    InvalidCleanupPeriod,
+        // Other zones are unaffected.
+        assert!(!is_excluded_from_auto_bundle(
+            &exclusions,
+            included,
+            ZoneBundleCause::UnexpectedZone,
+        ));
+        assert!(!is_excluded_from_auto_bundle(
+            &BTreeSet::new(),
+            excluded,
+            ZoneBundleCause::UnexpectedZone,
+        ));
+    }
 
-    #[error(
-        "Invalid priority ordering. Each element must appear exactly once."
-    )]
-    InvalidPriorityOrder,
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_bundler_with_storage_dirs_override() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_bundler_with_storage_dirs_override",
+        );
+        let ctx = setup_bundler_test(&logctx.log, 1, |_| {
+            fake_command("", Duration::ZERO)
+        });
+        let bundler = &ctx.bundler;
+
+        // The bundle is written to the override directory.
+        const ZONE_NAME: &str = "oxz_overridden";
+        let info = bundler
+            .create_by_name(
+                ZONE_NAME,
+                ZoneBundleCause::ExplicitRequest,
+                BTreeMap::new(),
+                BundleDetailLevel::Full,
+            )
+            .await
+            .expect("failed to create bundle");
+        let bundle_path = ctx.storage_dirs[0]
+            .path()
+            .join(ZONE_NAME)
+            .join(format!("{}.tar.gz", info.id.bundle_id));
+        assert!(bundle_path.exists(), "missing bundle at {bundle_path}");
+
+        // It can be listed...
+        let bundles = bundler.list(None, None).await.unwrap().result;
+        assert_eq!(bundles.len(), 1);
+        assert_eq!(bundles[0].id, info.id);
+        let bundles = bundler.list_for_zone(ZONE_NAME).await.unwrap().result;
+        assert_eq!(bundles.len(), 1);
+        assert_eq!(bundles[0].id, info.id);
+
+        // ... and cleaned up.
+        let count = bundler.delete_for_zone(ZONE_NAME).await.unwrap().result;
+        assert_eq!(count.bundles, 1);
+        assert!(!bundle_path.exists());
+        assert!(bundler.list(None, None).await.unwrap().result.is_empty());
+
+        // Deleting the bundles of a zone that never had any deletes nothing,
+        // rather than failing.
+        let count =
+            bundler.delete_for_zone("oxz_no_bundles").await.unwrap().result;
+        assert_eq!(count.bundles, 0);
+        logctx.cleanup_successful();
+    }
 
-    #[error("Cleanup failed")]
-    Cleanup(#[source] anyhow::Error),
-}
+    #[tokio::test(start_paused = true)]
+    #[serial_test::serial]
+    async fn test_scheduled_capture_fires_after_interval() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_scheduled_capture_fires_after_interval",
+        );
+        let ctx = setup_bundler_test(&logctx.log, 1, |_| {
+            fake_command("", Duration::ZERO)
+        });
+        let bundler = &ctx.bundler;
+        const ZONE_NAME: &str = "oxz_scheduled";
+
+        // Intervals that are too short are rejected.
+        let err = bundler
+            .schedule_capture(ZONE_NAME, Duration::from_secs(1))
+            .await
+            .expect_err("scheduled a capture with too short an interval");
+        assert!(matches!(err, BundleError::InvalidCaptureInterval));
+        assert!(bundler.scheduled_captures().await.is_empty());
 
-// Helper function to write an array of bytes into the tar archive, with
-// the provided name.
-fn insert_data<W: std::io::Write>(
-    builder: &mut Builder<W>,
-    name: &str,
-    contents: &[u8],
-) -> Result<(), BundleError> {
-    let mtime = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .context("failed to compute mtime")?
-        .as_secs();
+        const INTERVAL: Duration = Duration::from_secs(60 * 60);
+        bundler.schedule_capture(ZONE_NAME, INTERVAL).await.unwrap();
+        assert_eq!(
+            bundler.scheduled_captures().await,
+            BTreeMap::from([(String::from(ZONE_NAME), INTERVAL)]),
+        );
 
-    let mut hdr = Header::new_ustar();
-    hdr.set_size(contents.len().try_into().unwrap());
-    hdr.set_mode(0o444);
-    hdr.set_mtime(mtime);
-    hdr.set_entry_type(tar::EntryType::Regular);
-    // NOTE: This internally sets the path and checksum.
-    builder.append_data(&mut hdr, name, Cursor::new(contents)).map_err(|err| {
-        BundleError::AddBundleData { tarball_path: name.into(), err }
-    })
-}
+        // Nothing is captured before the interval elapses...
+        tokio::time::sleep(INTERVAL / 2).await;
+        assert!(bundler.list(None, None).await.unwrap().result.is_empty());
 
-// Create a service bundle for the provided zone.
-//
-// This runs a series of debugging commands in the zone, to collect data about
-// the state of the zone and any Oxide service processes running inside. The
-// data is packaged into a tarball, and placed in the provided output
-// directories.
-async fn create(
-    log: &Logger,
-    zone: &RunningZone,
-    context: &ZoneBundleContext,
-) -> Result<ZoneBundleMetadata, BundleError> {
-    // Fetch the directory into which we'll store data, and ensure it exists.
-    if context.storage_dirs.is_empty() {
-        warn!(log, "no directories available for zone bundles");
-        return Err(BundleError::NoStorage);
-    }
-    let mut zone_bundle_dirs = Vec::with_capacity(context.storage_dirs.len());
-    for dir in context.storage_dirs.iter() {
-        let bundle_dir = dir.join(zone.name());
-        debug!(log, "creating bundle directory"; "dir" => %bundle_dir);
-        tokio::fs::create_dir_all(&bundle_dir).await.map_err(|err| {
-            BundleError::CreateDirectory {
-                directory: bundle_dir.to_owned(),
-                err,
+        // ... but a bundle is captured soon after.
+        tokio::time::sleep(INTERVAL / 2).await;
+        let mut bundles = Vec::new();
+        for _ in 0..100 {
+            bundles = bundler.list_for_zone(ZONE_NAME).await.unwrap().result;
+            if !bundles.is_empty() {
+                break;
             }
-        })?;
-        zone_bundle_dirs.push(bundle_dir);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(bundles.len(), 1, "no scheduled bundle was captured");
+        assert_eq!(bundles[0].cause, ZoneBundleCause::Scheduled);
+
+        // No more are captured once the zone is unscheduled.
+        assert!(bundler.unschedule_capture(ZONE_NAME).await);
+        assert!(!bundler.unschedule_capture(ZONE_NAME).await);
+        tokio::time::sleep(INTERVAL * 2).await;
+        assert_eq!(
+            bundler.list_for_zone(ZONE_NAME).await.unwrap().result.len(),
+            1
+        );
+        logctx.cleanup_successful();
     }
 
-    // Create metadata and the tarball writer.
-    //
-    // We'll write the contents of the bundle into a gzipped tar archive,
-    // including metadata and a file for the output of each command we run in
-    // the zone.
-    let zone_metadata = ZoneBundleMetadata::new(zone.name(), context.cause);
-    let filename = format!("{}.tar.gz", zone_metadata.id.bundle_id);
-    let full_path = zone_bundle_dirs[0].join(&filename);
-    let file = match tokio::fs::OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(&full_path)
-        .await
-    {
-        Ok(f) => f.into_std().await,
-        Err(e) => {
-            error!(
-                log,
-                "failed to create bundle file";
-                "zone" => zone.name(),
-                "file" => %full_path,
-                "error" => ?e,
-            );
-            return Err(BundleError::OpenBundleFile {
-                path: full_path.to_owned(),
-                err: e,
-            });
-        }
-    };
-    debug!(
-        log,
-        "created bundle tarball file";
-        "zone" => zone.name(),
-        "path" => %full_path
-    );
-    let gz = flate2::GzBuilder::new()
-        .filename(filename.as_str())
-        .write(file, flate2::Compression::best());
-    let mut builder = Builder::new(gz);
+    #[tokio::test(start_paused = true)]
+    async fn test_cleanup_on_start_waits_for_bundle_directories() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_cleanup_on_start_waits_for_bundle_directories",
+        );
 
-    // Write the metadata file itself, in TOML format.
-    let contents = toml::to_string(&zone_metadata)?;
-    insert_data(
-        &mut builder,
-        ZONE_BUNDLE_METADATA_FILENAME,
-        contents.as_bytes(),
-    )?;
-    debug!(
-        log,
-        "wrote zone bundle metadata";
-        "zone" => zone.name(),
-    );
-    for cmd in ZONE_WIDE_COMMANDS {
-        debug!(
-            log,
-            "running zone bundle command";
-            "zone" => zone.name(),
-            "command" => ?cmd,
+        // The only bundle directory can't be created while a file is in the
+        // way, as when its debug dataset doesn't exist yet.
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let blocker = tmpdir.path().join("debug");
+        std::fs::write(&blocker, b"").unwrap();
+        let bundler = ZoneBundler::new(
+            logctx.log.clone(),
+            StorageResources::new_for_test(),
+            CleanupContext::default(),
+            Some(vec![blocker.join("bundles")]),
         );
-        let output = match zone.run_cmd(cmd) {
-            Ok(s) => s,
-            Err(e) => format!("{}", e),
-        };
-        let contents = format!("Command: {:?}\n{}", cmd, output).into_bytes();
-        if let Err(e) = insert_data(&mut builder, cmd[0], &contents) {
-            error!(
-                log,
-                "failed to save zone bundle command output";
-                "zone" => zone.name(),
-                "command" => ?cmd,
-                "error" => ?e,
+        bundler.cleanup_on_start().await;
+
+        // The cleanup stays pending however long that lasts...
+        tokio::time::sleep(ZoneBundler::START_CLEANUP_RETRY_INTERVAL * 5).await;
+        assert!(bundler.inner.lock().await.last_cleanup_at.is_none());
+
+        // ... and runs once the directory can be created.
+        std::fs::remove_file(&blocker).unwrap();
+        let start = std::time::Instant::now();
+        while bundler.inner.lock().await.last_cleanup_at.is_none() {
+            assert!(
+                start.elapsed() < Duration::from_secs(30),
+                "cleanup did not run once the directory existed",
             );
+            tokio::time::sleep(ZoneBundler::START_CLEANUP_RETRY_INTERVAL).await;
         }
+        logctx.cleanup_successful();
     }
 
-    // Debugging commands run on the specific processes this zone defines.
-    const ZONE_PROCESS_COMMANDS: [&str; 3] = [
-        "pfiles", "pstack",
-        "pargs",
-        // TODO-completeness: We may want `gcore`, since that encompasses
-        // the above commands and much more. It seems like overkill now,
-        // however.
-    ];
-    let procs = match zone
-        .service_processes()
-        .context("failed to enumerate zone service processes")
-    {
-        Ok(p) => {
-            debug!(
-                log,
-                "enumerated service processes";
-                "zone" => zone.name(),
-                "procs" => ?p,
-            );
-            p
-        }
-        Err(e) => {
-            error!(
-                log,
-                "failed to enumerate zone service processes";
-                "zone" => zone.name(),
-                "error" => ?e,
-            );
-            return Err(BundleError::from(e));
-        }
-    };
-    for svc in procs.into_iter() {
-        let pid_s = svc.pid.to_string();
-        for cmd in ZONE_PROCESS_COMMANDS {
-            let args = &[cmd, &pid_s];
-            debug!(
-                log,
-                "running zone bundle command";
-                "zone" => zone.name(),
-                "command" => ?args,
-            );
-            let output = match zone.run_cmd(args) {
-                Ok(s) => s,
-                Err(e) => format!("{}", e),
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_diff_bundles_with_differing_command_output() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_diff_bundles_with_differing_command_output",
+        );
+
+        // Every command has the same output in each bundle, except `uptime`,
+        // which reports the number of times it's been run.
+        let n_uptimes = Arc::new(AtomicUsize::new(0));
+        let n_uptimes_clone = n_uptimes.clone();
+        let ctx = setup_bundler_test(&logctx.log, 1, move |cmd| {
+            let is_uptime = cmd.get_args().any(|arg| arg == "uptime");
+            let stdout = if is_uptime {
+                let n = n_uptimes_clone.fetch_add(1, Ordering::SeqCst);
+                format!("up {n} days\n")
+            } else {
+                String::from("unchanging\n")
             };
-            let contents =
-                format!("Command: {:?}\n{}", args, output).into_bytes();
-
-            // There may be multiple Oxide service processes for which we
-            // want to capture the command output. Name each output after
-            // the command and PID to disambiguate.
-            let filename = format!("{}.{}", cmd, svc.pid);
-            if let Err(e) = insert_data(&mut builder, &filename, &contents) {
-                error!(
-                    log,
-                    "failed to save zone bundle command output";
-                    "zone" => zone.name(),
-                    "command" => ?args,
-                    "error" => ?e,
-                );
-            }
+            fake_command(&stdout, Duration::ZERO)
+        });
+        let bundler = &ctx.bundler;
+        const ZONE_NAME: &str = "oxz_diffed";
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let metadata = bundler
+                .create_by_name(
+                    ZONE_NAME,
+                    ZoneBundleCause::ExplicitRequest,
+                    BTreeMap::new(),
+                    BundleDetailLevel::Full,
+                )
+                .await
+                .expect("failed to create bundle");
+            ids.push(metadata.id.bundle_id);
         }
+        assert_eq!(n_uptimes.load(Ordering::SeqCst), 2);
 
-        // We may need to extract log files that have been archived out of the
-        // zone filesystem itself. See `crate::dump_setup` for the logic which
-        // does this.
-        let archived_log_files = find_archived_log_files(
-            log,
-            zone.name(),
-            &svc.service_name,
-            &context.extra_log_dirs,
-        )
-        .await;
-
-        // Copy any log files, current and rotated, into the tarball as
-        // well.
-        //
-        // Safety: This pathbuf was retrieved by locating an existing file
-        // on the filesystem, so we're sure it has a name and the unwrap is
-        // safe.
-        debug!(
-            log,
-            "appending current log file to zone bundle";
-            "zone" => zone.name(),
-            "log_file" => %svc.log_file,
-        );
-        if let Err(e) = builder.append_path_with_name(
-            &svc.log_file,
-            svc.log_file.file_name().unwrap(),
-        ) {
-            error!(
-                log,
-                "failed to append current log file to zone bundle";
-                "zone" => zone.name(),
-                "log_file" => %svc.log_file,
-                "error" => ?e,
-            );
-            return Err(BundleError::AddBundleData {
-                tarball_path: svc.log_file.file_name().unwrap().into(),
-                err: e,
-            });
-        }
-        for f in svc.rotated_log_files.iter().chain(archived_log_files.iter()) {
-            debug!(
-                log,
-                "appending rotated log file to zone bundle";
-                "zone" => zone.name(),
-                "log_file" => %f,
-            );
-            if let Err(e) =
-                builder.append_path_with_name(f, f.file_name().unwrap())
-            {
-                error!(
-                    log,
-                    "failed to append rotated log file to zone bundle";
-                    "zone" => zone.name(),
-                    "log_file" => %f,
-                    "error" => ?e,
-                );
-                return Err(BundleError::AddBundleData {
-                    tarball_path: f.file_name().unwrap().into(),
-                    err: e,
-                });
+        let diff = bundler.diff(ZONE_NAME, &ids[0], &ids[1]).await.unwrap();
+        assert_eq!(diff.first.id.bundle_id, ids[0]);
+        assert_eq!(diff.second.id.bundle_id, ids[1]);
+        assert_eq!(
+            diff.entries.keys().collect::<Vec<_>>(),
+            vec!["uptime"],
+            "only the uptime output should differ",
+        );
+        let BundleEntryDiff::Text { diff } = &diff.entries["uptime"] else {
+            panic!("expected a text diff, found {:?}", diff.entries["uptime"]);
+        };
+        assert!(diff.contains("-up 0 days"), "unexpected diff: {diff}");
+        assert!(diff.contains("+up 1 days"), "unexpected diff: {diff}");
+
+        // Comparing a bundle with itself finds no differences.
+        let diff = bundler.diff(ZONE_NAME, &ids[0], &ids[0]).await.unwrap();
+        assert!(diff.entries.is_empty());
+
+        // Both bundles must exist.
+        let missing = uuid::Uuid::new_v4();
+        let err = bundler.diff(ZONE_NAME, &ids[0], &missing).await.unwrap_err();
+        assert!(matches!(
+            err,
+            BundleError::NoSuchBundle { bundle_id, .. } if bundle_id == missing
+        ));
+        logctx.cleanup_successful();
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_hung_command_times_out() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_hung_command_times_out",
+        );
+        const TIMEOUT: Duration = Duration::from_millis(100);
+        const ZONE_NAME: &str = "oxz_hung";
+
+        // `uptime` hangs for much longer than the timeout, while every other
+        // command finishes right away.
+        let hung_pid = Arc::new(std::sync::Mutex::new(None));
+        let hung_pid_clone = hung_pid.clone();
+        let ctx = setup_bundler_test(&logctx.log, 1, move |cmd| {
+            if cmd.get_args().any(|arg| arg == "uptime") {
+                let child = fake_command("finished\n", TIMEOUT * 50);
+                *hung_pid_clone.lock().unwrap() = Some(child.id());
+                child
+            } else {
+                fake_command("finished\n", Duration::ZERO)
             }
+        });
+        let bundler = &ctx.bundler;
+        bundler.set_command_timeout(TIMEOUT).await;
+
+        // The bundle completes without waiting for the hung command.
+        let start = std::time::Instant::now();
+        let metadata = bundler
+            .create_by_name(
+                ZONE_NAME,
+                ZoneBundleCause::ExplicitRequest,
+                BTreeMap::new(),
+                BundleDetailLevel::Full,
+            )
+            .await
+            .expect("failed to create bundle");
+        assert!(start.elapsed() < TIMEOUT * 50, "bundle waited for command");
+
+        // The timeout is recorded in place of its output, and the other
+        // commands' output is still collected.
+        let extract = |entry_path: &'static str| {
+            let id = metadata.id.bundle_id;
+            async move {
+                let file = bundler
+                    .extract_file(ZONE_NAME, &id, entry_path)
+                    .await
+                    .unwrap();
+                String::from_utf8(read_bundle_file(file).await).unwrap()
+            }
+        };
+        let uptime = extract("uptime").await;
+        assert!(
+            uptime.contains("command timed out after 100ms"),
+            "unexpected output: {uptime}"
+        );
+        let ptree = extract("ptree").await;
+        assert!(ptree.contains("finished"), "unexpected output: {ptree}");
+
+        // The hung command was killed, rather than left running.
+        let pid = hung_pid.lock().unwrap().expect("uptime was never run");
+        let is_running = || {
+            // Safety: `kill(2)` has no memory safety requirements.
+            unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+        };
+        let deadline = std::time::Instant::now() + TIMEOUT * 20;
+        while is_running() && std::time::Instant::now() < deadline {
+            tokio::time::sleep(TIMEOUT / 10).await;
         }
+        assert!(!is_running(), "hung command is still running");
+        logctx.cleanup_successful();
     }
 
-    // Finish writing out the tarball itself.
-    builder.into_inner().context("Failed to build bundle")?;
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_minimal_bundle_skips_process_introspection() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_minimal_bundle_skips_process_introspection",
+        );
+        let ctx = setup_bundler_test(&logctx.log, 1, |_| {
+            fake_command("fake command output", Duration::ZERO)
+        });
+        let bundler = &ctx.bundler;
+
+        // Stub out a zone with a service log file, which a full bundle would
+        // collect.
+        const ZONE_NAME: &str = "oxz_minimal";
+        let log_dir =
+            ctx.zonepath.path().join(ZONE_NAME).join("root/var/svc/log");
+        std::fs::create_dir_all(&log_dir).unwrap();
+        std::fs::write(log_dir.join("oxide-fake:default.log"), "fake log")
+            .unwrap();
+
+        let metadata = bundler
+            .create_by_name(
+                ZONE_NAME,
+                ZoneBundleCause::ExplicitRequest,
+                BTreeMap::new(),
+                BundleDetailLevel::Minimal,
+            )
+            .await
+            .expect("failed to create bundle");
+        assert_eq!(metadata.detail, BundleDetailLevel::Minimal);
 
-    // Copy the bundle to the other locations. We really want the bundles to
-    // be duplicates, not an additional, new bundle.
-    //
-    // TODO-robustness: We should probably create the bundle in a temp dir, and
-    // copy it to all the final locations. This would make it easier to cleanup
-    // the final locations should that last copy fail for any of them.
-    //
-    // See: https://github.com/oxidecomputer/omicron/issues/3876.
-    for other_dir in zone_bundle_dirs.iter().skip(1) {
-        let to = other_dir.join(&filename);
-        debug!(log, "copying bundle"; "from" => %full_path, "to" => %to);
-        tokio::fs::copy(&full_path, &to).await.map_err(|err| {
-            BundleError::CopyArchive { from: full_path.to_owned(), to, err }
-        })?;
+        // The bundle has the metadata and zone-wide command output, and
+        // nothing else.
+        let paths = bundler
+            .bundle_paths(ZONE_NAME, &metadata.id.bundle_id)
+            .await
+            .unwrap();
+        let file = std::fs::File::open(&paths[0]).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        let names: BTreeSet<String> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                entry.unwrap().path().unwrap().to_string_lossy().into_owned()
+            })
+            .collect();
+        assert_eq!(names.len(), 1 + metadata.zone_wide_commands.len());
+        assert!(names.contains(ZONE_BUNDLE_METADATA_FILENAME));
+        // Per-process outputs are named like `pfiles.<PID>`, and log files all
+        // have an extension, while zone-wide outputs are named for the binary.
+        assert!(
+            names
+                .iter()
+                .filter(|name| *name != ZONE_BUNDLE_METADATA_FILENAME)
+                .all(|name| !name.contains('.')),
+            "unexpected per-process or log entries: {names:?}"
+        );
+
+        // The detail level is also recorded in the bundle itself.
+        let file = bundler
+            .extract_file(
+                ZONE_NAME,
+                &metadata.id.bundle_id,
+                ZONE_BUNDLE_METADATA_FILENAME,
+            )
+            .await
+            .unwrap();
+        let contents = read_bundle_file(file).await;
+        let stored: ZoneBundleMetadata =
+            toml::from_str(std::str::from_utf8(&contents).unwrap()).unwrap();
+        assert_eq!(stored.detail, BundleDetailLevel::Minimal);
+        logctx.cleanup_successful();
     }
 
-    info!(log, "finished zone bundle"; "metadata" => ?zone_metadata);
-    Ok(zone_metadata)
-}
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_bundle_copies_are_identical() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_bundle_copies_are_identical",
+        );
+        let ctx = setup_bundler_test(&logctx.log, 2, |_| {
+            fake_command("fake command output", Duration::ZERO)
+        });
+        let bundler = &ctx.bundler;
+        let storage_dirs = &ctx.storage_dirs;
+
+        const ZONE_NAME: &str = "oxz_copies";
+        let log_dir =
+            ctx.zonepath.path().join(ZONE_NAME).join("root/var/svc/log");
+        std::fs::create_dir_all(&log_dir).unwrap();
+        std::fs::write(log_dir.join("oxide-fake:default.log"), "fake log")
+            .unwrap();
+
+        for single_pass in [false, true] {
+            bundler.set_single_pass_copies(single_pass).await;
+            let metadata = bundler
+                .create_by_name(
+                    ZONE_NAME,
+                    ZoneBundleCause::ExplicitRequest,
+                    BTreeMap::new(),
+                    BundleDetailLevel::Full,
+                )
+                .await
+                .expect("failed to create bundle");
 
-// Find log files for the specified zone / SMF service, which may have been
-// archived out to a U.2 dataset.
-//
-// Note that errors are logged, rather than failing the whole function, so that
-// one failed listing does not prevent collecting any other log files.
-async fn find_archived_log_files(
-    log: &Logger,
-    zone_name: &str,
-    svc_name: &str,
-    dirs: &[Utf8PathBuf],
-) -> Vec<Utf8PathBuf> {
-    // The `dirs` should be things like
-    // `/pool/ext/<ZPOOL_UUID>/crypt/debug/<ZONE_NAME>`, but it's really up to
-    // the caller to verify these exist and possibly contain what they expect.
-    //
-    // Within that, we'll just look for things that appear to be Oxide-managed
-    // SMF service log files.
-    let mut files = Vec::new();
-    for dir in dirs.iter() {
-        if dir.exists() {
-            let mut rd = match tokio::fs::read_dir(&dir).await {
-                Ok(rd) => rd,
-                Err(e) => {
-                    error!(
-                        log,
-                        "failed to read zone debug directory";
-                        "directory" => ?dir,
-                        "reason" => ?e,
+            // Each directory has a copy of the bundle, and they're all the
+            // same.
+            let paths = bundler
+                .bundle_paths(ZONE_NAME, &metadata.id.bundle_id)
+                .await
+                .unwrap();
+            assert_eq!(paths.len(), storage_dirs.len());
+            for dir in storage_dirs.iter() {
+                assert!(
+                    paths.iter().any(|path| path.starts_with(dir.path())),
+                    "no bundle in {dir:?}: {paths:?}"
+                );
+            }
+            let contents = std::fs::read(&paths[0]).unwrap();
+            assert!(!contents.is_empty());
+            assert_eq!(std::fs::read(&paths[1]).unwrap(), contents);
+
+            // The recorded hash matches every copy.
+            let hash = hex::encode(Sha256::digest(&contents));
+            assert_eq!(metadata.content_hash.as_deref(), Some(hash.as_str()));
+
+            // No temporary files are left behind.
+            for path in paths.iter() {
+                for entry in path.parent().unwrap().read_dir_utf8().unwrap() {
+                    let name = entry.unwrap().file_name().to_string();
+                    assert!(
+                        !name.ends_with(ZONE_BUNDLE_CREATE_TMP_SUFFIX),
+                        "temporary file {name} left in {path}"
                     );
-                    continue;
-                }
-            };
-            loop {
-                match rd.next_entry().await {
-                    Ok(None) => break,
-                    Ok(Some(entry)) => {
-                        let Ok(path) = Utf8PathBuf::try_from(entry.path())
-                        else {
-                            error!(
-                                log,
-                                "skipping possible archived log file with \
-                                non-UTF-8 path";
-                                "path" => ?entry.path(),
-                            );
-                            continue;
-                        };
-                        let fname = path.file_name().unwrap();
-                        let is_oxide = is_oxide_smf_log_file(fname);
-                        let contains = fname.contains(svc_name);
-                        if is_oxide && contains {
-                            debug!(
-                                log,
-                                "found archived log file";
-                                "zone_name" => zone_name,
-                                "service_name" => svc_name,
-                                "path" => ?path,
-                            );
-                            files.push(path);
-                        } else {
-                            debug!(
-                                log,
-                                "skipping non-matching log file";
-                                "filename" => fname,
-                                "is_oxide_smf_log_file" => is_oxide,
-                                "contains_svc_name" => contains,
-                            );
-                        }
-                    }
-                    Err(e) => {
-                        error!(
-                            log,
-                            "failed to fetch zone debug directory entry";
-                            "directory" => ?dir,
-                            "reason" => ?e,
-                        );
-                    }
                 }
             }
-        } else {
-            // The logic in `dump_setup` picks some U.2 in which to start
-            // archiving logs, and thereafter tries to keep placing new ones
-            // there, subject to space constraints. It's not really an error for
-            // there to be no entries for the named zone in any particular U.2
-            // debug dataset.
-            slog::trace!(
-                log,
-                "attempting to find archived log files in \
-                non-existent directory";
-                "directory" => ?dir,
-            );
         }
+        logctx.cleanup_successful();
     }
-    files
-}
 
-// Extract the zone bundle metadata from a file, if it exists.
-fn extract_zone_bundle_metadata_impl(
-    path: &Utf8PathBuf,
-) -> Result<ZoneBundleMetadata, BundleError> {
-    // Build a reader for the whole archive.
-    let reader = std::fs::File::open(path).map_err(|err| {
-        BundleError::OpenBundleFile { path: path.clone(), err }
-    })?;
-    let buf_reader = std::io::BufReader::new(reader);
-    let gz = GzDecoder::new(buf_reader);
-    let mut archive = Archive::new(gz);
+    #[test]
+    fn test_bundle_files_drops_copy_that_fails_to_write() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_bundle_files_drops_copy_that_fails_to_write",
+        );
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let good = tmpdir.path().join("good.tar.gz");
+        let bad = tmpdir.path().join("bad.tar.gz");
+        let good_file = std::fs::File::create(&good).unwrap();
+        std::fs::write(&bad, "").unwrap();
+
+        // Writes to a file opened read-only fail, as they would if the disk it
+        // is on failed partway through writing the bundle.
+        let bad_file = std::fs::File::open(&bad).unwrap();
+        let mut files = BundleFiles::new(
+            &logctx.log,
+            vec![(bad.clone(), bad_file), (good.clone(), good_file)],
+        );
+        std::io::Write::write_all(&mut files, b"some data")
+            .expect("one failed copy should not fail the write");
+        std::io::Write::write_all(&mut files, b" and more").unwrap();
+
+        // The good copy is complete, and the failed one is removed.
+        assert_eq!(files.finish().unwrap(), vec![good.clone()]);
+        assert_eq!(std::fs::read(&good).unwrap(), b"some data and more");
+        assert!(!bad.exists());
+        logctx.cleanup_successful();
+    }
 
-    // Find the metadata entry, if it exists.
-    let entries = archive.entries().map_err(|err| {
-        BundleError::ReadBundleData { path: path.clone(), err }
-    })?;
-    let Some(md_entry) = entries
-        // The `Archive::entries` iterator
-        // returns a result, so filter to those
-        // that are OK first.
-        .filter_map(Result::ok)
-        .find(|entry| {
-            entry
-                .path()
-                .map(|p| p.to_str() == Some(ZONE_BUNDLE_METADATA_FILENAME))
-                .unwrap_or(false)
-        })
-    else {
-        return Err(BundleError::from(anyhow!(
-            "Zone bundle is missing metadata file"
-        )));
-    };
+    #[test]
+    fn test_bundle_files_fails_when_every_copy_fails() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_bundle_files_fails_when_every_copy_fails",
+        );
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let paths = [
+            tmpdir.path().join("first.tar.gz"),
+            tmpdir.path().join("second.tar.gz"),
+        ];
+        let files = paths
+            .iter()
+            .map(|path| {
+                std::fs::write(path, "").unwrap();
+                (path.clone(), std::fs::File::open(path).unwrap())
+            })
+            .collect();
+        let mut files = BundleFiles::new(&logctx.log, files);
+        std::io::Write::write_all(&mut files, b"some data")
+            .expect_err("writing should fail once every copy has");
+        for path in paths.iter() {
+            assert!(!path.exists());
+        }
+        logctx.cleanup_successful();
+    }
 
-    // Extract its contents and parse as metadata.
-    let contents = std::io::read_to_string(md_entry).map_err(|err| {
-        BundleError::ReadBundleData { path: path.clone(), err }
-    })?;
-    toml::from_str(&contents).map_err(BundleError::from)
-}
+    #[tokio::test]
+    async fn test_bundle_copies_removes_partial_files() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_bundle_copies_removes_partial_files",
+        );
+        let dirs = [
+            camino_tempfile::tempdir().unwrap(),
+            camino_tempfile::tempdir().unwrap(),
+        ];
+        let dir_paths: Vec<_> =
+            dirs.iter().map(|dir| dir.path().to_owned()).collect();
+
+        // Copies which are never persisted are removed when dropped, e.g., on
+        // an error creating the bundle.
+        let copies =
+            BundleCopies::new(&logctx.log, &dir_paths, "bundle.tar.gz", true);
+        let copy_paths: Vec<_> = copies
+            .iter()
+            .map(|copy| (copy.tmp_path.clone(), copy.path.clone()))
+            .collect();
+        for (tmp_path, _) in copy_paths.iter() {
+            std::fs::write(tmp_path, "partial").unwrap();
+        }
+        drop(copies);
+        for (tmp_path, path) in copy_paths.iter() {
+            assert!(!tmp_path.exists());
+            assert!(!path.exists());
+        }
 
-// List the extant zone bundles for the provided zone, in the provided
-// directory.
-async fn list_bundles_for_zone(
-    log: &Logger,
-    path: &Utf8Path,
-    zone_name: &str,
-) -> Result<Vec<(Utf8PathBuf, ZoneBundleMetadata)>, BundleError> {
-    let zone_bundle_dir = path.join(zone_name);
-    Ok(filter_zone_bundles(log, &zone_bundle_dir, |md| {
-        md.id.zone_name == zone_name
-    })
-    .await?
-    .into_iter()
-    .collect::<Vec<_>>())
-}
+        // Persisted copies are renamed into place, and not removed.
+        let copies =
+            BundleCopies::new(&logctx.log, &dir_paths, "bundle.tar.gz", true);
+        for (tmp_path, _) in copy_paths.iter() {
+            std::fs::write(tmp_path, "complete").unwrap();
+        }
+        let persisted = copies.persist().await.unwrap();
+        assert_eq!(persisted.len(), copy_paths.len());
+        for (tmp_path, path) in copy_paths.iter() {
+            assert!(!tmp_path.exists());
+            assert_eq!(std::fs::read(path).unwrap(), b"complete");
+        }
+        logctx.cleanup_successful();
+    }
 
-// Extract zone bundle metadata from the provided file, if possible.
-async fn extract_zone_bundle_metadata(
-    path: Utf8PathBuf,
-) -> Result<ZoneBundleMetadata, BundleError> {
-    let task = tokio::task::spawn_blocking(move || {
-        extract_zone_bundle_metadata_impl(&path)
-    });
-    task.await?
-}
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_create_all_bundles_each_running_zone() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_create_all_bundles_each_running_zone",
+        );
+        let ctx = setup_bundler_test(&logctx.log, 1, |_| {
+            fake_command("fake command output", Duration::ZERO)
+        });
+        let bundler = &ctx.bundler;
+
+        const ZONE_NAMES: [&str; 3] = ["oxz_first", "oxz_second", "oxz_third"];
+        let running_ctx = MockZones::running_names_context();
+        running_ctx.expect().returning(|| {
+            Ok(ZONE_NAMES.iter().map(|name| name.to_string()).collect())
+        });
+
+        let bundles = bundler
+            .create_all(
+                ZoneBundleCause::ExplicitRequest,
+                BTreeMap::new(),
+                BundleDetailLevel::Full,
+            )
+            .await
+            .expect("failed to create bundles");
+        let zones: Vec<_> =
+            bundles.iter().map(|b| b.id.zone_name.as_str()).collect();
+        assert_eq!(zones, ZONE_NAMES);
+        assert!(bundles
+            .iter()
+            .all(|b| b.cause == ZoneBundleCause::ExplicitRequest));
 
-// Find zone bundles in the provided directory, which match the filter function.
-async fn filter_zone_bundles(
-    log: &Logger,
-    directory: &Utf8PathBuf,
-    filter: impl Fn(&ZoneBundleMetadata) -> bool,
-) -> Result<BTreeMap<Utf8PathBuf, ZoneBundleMetadata>, BundleError> {
-    let mut out = BTreeMap::new();
-    debug!(log, "searching directory for zone bundles"; "directory" => %directory);
-    let mut rd = tokio::fs::read_dir(directory).await.map_err(|err| {
-        BundleError::ReadDirectory { directory: directory.to_owned(), err }
-    })?;
-    while let Some(entry) = rd.next_entry().await.map_err(|err| {
-        BundleError::ReadDirectory { directory: directory.to_owned(), err }
-    })? {
-        let path = Utf8PathBuf::try_from(entry.path())?;
-        debug!(log, "checking path as zone bundle"; "path" => %path);
-        match extract_zone_bundle_metadata(path.clone()).await {
-            Ok(md) => {
-                trace!(log, "extracted zone bundle metadata"; "metadata" => ?md);
-                if filter(&md) {
-                    trace!(log, "filter matches bundle metadata"; "metadata" => ?md);
-                    out.insert(path, md);
+        // Each zone has exactly the one bundle just created.
+        for bundle in bundles.iter() {
+            let listed = bundler
+                .list_for_zone(&bundle.id.zone_name)
+                .await
+                .unwrap()
+                .result;
+            assert_eq!(listed.len(), 1);
+            assert_eq!(listed[0].id, bundle.id);
+        }
+        logctx.cleanup_successful();
+    }
+
+    // Stands in for the sled agent, managing exactly one zone, and recording
+    // the zones it's asked to bundle.
+    struct FakeManagedZones {
+        managed: &'static str,
+        bundled: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ManagedZones for FakeManagedZones {
+        async fn create_zone_bundle(
+            &self,
+            zone_name: &str,
+            cause: ZoneBundleCause,
+            annotations: BTreeMap<String, String>,
+            _detail: BundleDetailLevel,
+        ) -> Result<ZoneBundleMetadata, BundleError> {
+            if zone_name != self.managed {
+                return Err(BundleError::NoSuchZone {
+                    name: zone_name.to_string(),
+                });
+            }
+            self.bundled.lock().unwrap().push(zone_name.to_string());
+            Ok(ZoneBundleMetadata::new(zone_name, cause, annotations))
+        }
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_create_all_bundles_managed_zones_through_sled_agent() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_create_all_bundles_managed_zones_through_sled_agent",
+        );
+        let ctx = setup_bundler_test(&logctx.log, 1, |_| {
+            fake_command("fake command output", Duration::ZERO)
+        });
+        let bundler = &ctx.bundler;
+        const MANAGED: &str = "oxz_managed";
+        const UNMANAGED: &str = "oxz_unmanaged";
+        let managed_zones = Arc::new(FakeManagedZones {
+            managed: MANAGED,
+            bundled: std::sync::Mutex::new(Vec::new()),
+        });
+        let weak: std::sync::Weak<dyn ManagedZones> =
+            Arc::downgrade(&managed_zones);
+        bundler.set_managed_zones(weak).await;
+
+        let running_ctx = MockZones::running_names_context();
+        running_ctx
+            .expect()
+            .returning(|| Ok(vec![MANAGED.to_string(), UNMANAGED.to_string()]));
+
+        let bundles = bundler
+            .create_all(
+                ZoneBundleCause::ExplicitRequest,
+                BTreeMap::new(),
+                BundleDetailLevel::Full,
+            )
+            .await
+            .expect("failed to create bundles");
+        let zones: Vec<_> =
+            bundles.iter().map(|b| b.id.zone_name.as_str()).collect();
+        assert_eq!(zones, [MANAGED, UNMANAGED]);
+        assert_eq!(*managed_zones.bundled.lock().unwrap(), [MANAGED]);
+
+        // Only the zone the sled agent doesn't manage was bundled by name.
+        assert!(bundler
+            .list_for_zone(MANAGED)
+            .await
+            .unwrap()
+            .result
+            .is_empty());
+        assert_eq!(
+            bundler.list_for_zone(UNMANAGED).await.unwrap().result.len(),
+            1
+        );
+        logctx.cleanup_successful();
+    }
+
+    #[test]
+    fn test_diff_zone_bundles() {
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let write_bundle = |name: &str, entries: &[(&str, &[u8])]| {
+            let path = tmpdir.path().join(name);
+            let file = std::fs::File::create(&path).unwrap();
+            let gz = flate2::GzBuilder::new()
+                .write(file, flate2::Compression::fast());
+            let mut builder = tar::Builder::new(gz);
+            for (entry_path, contents) in entries.iter() {
+                insert_data(&mut builder, entry_path, contents).unwrap();
+            }
+            builder.into_inner().unwrap().finish().unwrap();
+            path
+        };
+        let large =
+            vec![b'a'; usize::try_from(MAX_TEXT_DIFF_SIZE).unwrap() + 1];
+        let mut large_changed = large.clone();
+        large_changed[0] = b'b';
+        let first = write_bundle(
+            "first.tar.gz",
+            &[
+                ("same", b"same\n"),
+                ("changed", b"before\n"),
+                ("binary", &[0xff, 0x01]),
+                ("became-binary", b"text\n"),
+                ("large", &large),
+                ("removed", b"gone\n"),
+            ],
+        );
+        let second = write_bundle(
+            "second.tar.gz",
+            &[
+                ("added", b"new\n"),
+                ("same", b"same\n"),
+                ("large", &large_changed),
+                ("became-binary", &[0xff, 0x02]),
+                ("binary", &[0xff, 0x02]),
+                ("changed", b"after\n"),
+            ],
+        );
+        let diffs = diff_zone_bundles_impl(&first, &second).unwrap();
+        assert_eq!(
+            diffs,
+            BTreeMap::from([
+                (String::from("added"), BundleEntryDiff::OnlyInSecond),
+                (String::from("became-binary"), BundleEntryDiff::Differs),
+                (String::from("binary"), BundleEntryDiff::Differs),
+                (String::from("large"), BundleEntryDiff::Differs),
+                (
+                    String::from("changed"),
+                    BundleEntryDiff::Text {
+                        diff: String::from(
+                            "--- changed\n+++ changed\n\
+                            @@ -1 +1 @@\n-before\n+after\n"
+                        ),
+                    },
+                ),
+                (String::from("removed"), BundleEntryDiff::OnlyInFirst),
+            ]),
+        );
+    }
+
+    #[test]
+    fn test_zone_bundle_metrics_are_drained() {
+        use oximeter::types::Datum;
+        use oximeter::types::FieldValue;
+        use oximeter::Producer;
+
+        let mut metrics = ZoneBundleMetrics::new();
+        metrics
+            .record(
+                "oxz_whatever",
+                ZoneBundleCause::TerminatedInstance,
+                std::time::Duration::from_millis(1500),
+                1024,
+            )
+            .unwrap();
+        let samples: Vec<_> = metrics.produce().unwrap().collect();
+        assert_eq!(samples.len(), 2);
+        for sample in samples.iter() {
+            let fields = sample.fields();
+            let field = |name: &str| {
+                fields.iter().find(|f| f.name == name).unwrap().value.clone()
+            };
+            assert_eq!(
+                field("zone_name"),
+                FieldValue::String(String::from("oxz_whatever"))
+            );
+            assert_eq!(
+                field("cause"),
+                FieldValue::String(String::from("terminated_instance"))
+            );
+            match sample.timeseries_name.as_str() {
+                "zone_bundle:creation_duration" => {
+                    assert_eq!(sample.measurement.datum(), &Datum::F64(1.5))
                 }
-            }
-            Err(e) => {
-                warn!(
-                    log,
-                    "failed to extract zone bundle metadata, skipping";
-                    "path" => %path,
-                    "reason" => ?e,
-                );
+                "zone_bundle:size" => {
+                    assert_eq!(sample.measurement.datum(), &Datum::U64(1024))
+                }
+                name => panic!("unexpected timeseries: {name}"),
             }
         }
+
+        // Samples are only produced once.
+        assert_eq!(metrics.produce().unwrap().count(), 0);
     }
-    Ok(out)
-}
 
-// Get the paths to a zone bundle, if it exists.
-//
-// Zone bundles are replicated in multiple storage directories. This returns
-// every path at which the bundle with the provided ID exists, in the same
-// order as `directories`.
-async fn get_zone_bundle_paths(
-    log: &Logger,
-    directories: &[Utf8PathBuf],
-    zone_name: &str,
-    id: &Uuid,
-) -> Result<Vec<Utf8PathBuf>, BundleError> {
-    let mut out = Vec::with_capacity(directories.len());
-    for dir in directories {
-        let mut rd = tokio::fs::read_dir(dir).await.map_err(|err| {
-            BundleError::ReadDirectory { directory: dir.to_owned(), err }
-        })?;
-        while let Some(entry) = rd.next_entry().await.map_err(|err| {
-            BundleError::ReadDirectory { directory: dir.to_owned(), err }
-        })? {
-            let search_dir = Utf8PathBuf::try_from(entry.path())?;
-            out.extend(
-                filter_zone_bundles(log, &search_dir, |md| {
-                    md.id.zone_name == zone_name && md.id.bundle_id == *id
-                })
-                .await?
-                .into_keys(),
+    #[test]
+    fn test_zone_bundle_metrics_are_capped() {
+        use oximeter::types::FieldValue;
+        use oximeter::Producer;
+
+        let mut metrics = ZoneBundleMetrics::new();
+        let n_bundles = MAX_SAMPLES;
+        for i in 0..n_bundles {
+            metrics
+                .record(
+                    &format!("oxz_{i}"),
+                    ZoneBundleCause::Scheduled,
+                    std::time::Duration::from_secs(1),
+                    1024,
+                )
+                .unwrap();
+        }
+
+        // Only the newest samples are kept.
+        let samples: Vec<_> = metrics.produce().unwrap().collect();
+        assert_eq!(samples.len(), MAX_SAMPLES);
+        let zone_name = |sample: &oximeter::Sample| {
+            sample
+                .fields()
+                .into_iter()
+                .find(|f| f.name == "zone_name")
+                .unwrap()
+                .value
+        };
+        assert_eq!(
+            zone_name(&samples[0]),
+            FieldValue::String(format!("oxz_{}", n_bundles / 2))
+        );
+        assert_eq!(
+            zone_name(samples.last().unwrap()),
+            FieldValue::String(format!("oxz_{}", n_bundles - 1))
+        );
+    }
+
+    #[test]
+    fn test_validate_zone_name() {
+        for name in ["oxz_switch", "oxz_crucible_1234", "global"] {
+            validate_zone_name(name).unwrap();
+        }
+        for name in ["", ".", "..", "../oxz_switch", "oxz_a/b", "/", "a\0b"] {
+            assert!(
+                matches!(
+                    validate_zone_name(name),
+                    Err(BundleError::InvalidZoneName { .. }),
+                ),
+                "zone name should be invalid: {name:?}",
             );
         }
     }
-    Ok(out)
-}
 
-/// The portion of a debug dataset used for zone bundles.
-#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, Serialize)]
-pub struct BundleUtilization {
-    /// The total dataset quota, in bytes.
-    pub dataset_quota: u64,
-    /// The total number of bytes available for zone bundles.
-    ///
-    /// This is `dataset_quota` multiplied by the context's storage limit.
-    pub bytes_available: u64,
-    /// Total bundle usage, in bytes.
-    pub bytes_used: u64,
-}
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_zone_bundle_paths_reject_invalid_zone_name() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_zone_bundle_paths_reject_invalid_zone_name",
+        );
+        let ctx = setup_bundler_test(&logctx.log, 1, |_| {
+            fake_command("fake command output", Duration::ZERO)
+        });
+        let bundler = &ctx.bundler;
+        const NAME: &str = "../escaped";
+        let id = uuid::Uuid::new_v4();
+        assert!(matches!(
+            bundler.list_for_zone(NAME).await,
+            Err(BundleError::InvalidZoneName { .. }),
+        ));
+        assert!(matches!(
+            bundler.bundle_paths(NAME, &id).await,
+            Err(BundleError::InvalidZoneName { .. }),
+        ));
+        assert!(matches!(
+            bundler.find_bundle(NAME, &id).await,
+            Err(BundleError::InvalidZoneName { .. }),
+        ));
+        assert!(matches!(
+            bundler.delete_for_zone(NAME).await,
+            Err(BundleError::InvalidZoneName { .. }),
+        ));
 
-#[derive(Clone, Debug, PartialEq)]
-struct ZoneBundleInfo {
-    // The raw metadata for the bundle
-    metadata: ZoneBundleMetadata,
-    // The full path to the bundle
-    path: Utf8PathBuf,
-    // The number of bytes consumed on disk by the bundle
-    bytes: u64,
-}
+        // Nothing was created outside the bundle directory.
+        let parent = ctx.storage_dirs[0].path().parent().unwrap();
+        assert!(!parent.join("escaped").exists());
+        logctx.cleanup_successful();
+    }
 
-// Enumerate all zone bundles under the provided directory.
-async fn enumerate_zone_bundles(
-    log: &Logger,
-    dirs: &[Utf8PathBuf],
-) -> Result<BTreeMap<Utf8PathBuf, Vec<ZoneBundleInfo>>, BundleError> {
-    let mut out = BTreeMap::new();
+    #[test]
+    fn test_validate_zone_wide_command() {
+        let cmd = |args: &[&str]| -> Vec<String> {
+            args.iter().map(|arg| arg.to_string()).collect()
+        };
 
-    // Each of these is a storage directory.
-    //
-    // We should have under here zone-names, followed by bundles within each of
-    // those.
-    for dir in dirs.iter() {
-        let mut rd = tokio::fs::read_dir(dir).await.map_err(|err| {
-            BundleError::ReadDirectory { directory: dir.to_owned(), err }
-        })?;
-        let mut info_by_dir = Vec::new();
-        while let Some(zone_dir) = rd.next_entry().await.map_err(|err| {
-            BundleError::ReadDirectory { directory: dir.to_owned(), err }
-        })? {
-            let mut zone_rd = tokio::fs::read_dir(zone_dir.path())
-                .await
-                .map_err(|err| BundleError::ReadDirectory {
-                    directory: zone_dir.path().try_into().unwrap(),
-                    err,
-                })?;
-            while let Some(maybe_bundle) =
-                zone_rd.next_entry().await.map_err(|err| {
-                    BundleError::ReadDirectory {
-                        directory: zone_dir.path().try_into().unwrap(),
-                        err,
+        // The default commands must all be allowed.
+        for command in default_zone_wide_commands() {
+            validate_zone_wide_command(&command).unwrap();
+        }
+        validate_zone_wide_command(&cmd(&["ipadm", "show-addr"])).unwrap();
+        validate_zone_wide_command(&cmd(&["arp", "-an"])).unwrap();
+
+        // Binaries outside the allow-list, including by path, are rejected.
+        for command in [
+            cmd(&[]),
+            cmd(&["rm", "-rf", "/"]),
+            cmd(&["/usr/bin/ptree"]),
+            cmd(&["ipadm", "delete-addr", "net0/v4"]),
+            cmd(&["ipadm"]),
+            cmd(&["arp", "-d", "10.0.0.1"]),
+        ] {
+            assert!(
+                matches!(
+                    validate_zone_wide_command(&command),
+                    Err(BundleError::DisallowedCommand { .. }),
+                ),
+                "command should be disallowed: {command:?}",
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cleanup_task_restarted_after_panic() {
+        const PERIOD: std::time::Duration = std::time::Duration::from_secs(60);
+        let log = Logger::root(slog::Discard, slog::o!());
+
+        // A stand-in for the periodic cleanup task, which panics during its
+        // first cleanup iteration and counts all the others.
+        let iterations = Arc::new(AtomicUsize::new(0));
+        let iterations_clone = iterations.clone();
+        let supervisor =
+            tokio::task::spawn(supervise_cleanup_task(log, move || {
+                let iterations = iterations_clone.clone();
+                async move {
+                    loop {
+                        tokio::time::sleep(PERIOD).await;
+                        if iterations.fetch_add(1, Ordering::SeqCst) == 0 {
+                            panic!("injected cleanup panic");
+                        }
                     }
-                })?
-            {
-                // TODO-robustness: What do we do with files that do _not_
-                // appear to be valid zone bundles.
-                //
-                // On the one hand, someone may have put something there
-                // intentionally. On the other hand, that would be weird, and we
-                // _also_ know that it's possible that IO errors happen while
-                // creating the bundle that render it impossible to recover the
-                // metadata. So it's plausible that we end up with a lot of
-                // detritus here in that case.
-                let path = Utf8PathBuf::try_from(maybe_bundle.path())?;
-                if let Ok(metadata) =
-                    extract_zone_bundle_metadata(path.clone()).await
-                {
-                    let info = ZoneBundleInfo {
-                        metadata,
-                        path: path.clone(),
-                        bytes: maybe_bundle
-                            .metadata()
-                            .await
-                            .map_err(|err| BundleError::Metadata { path, err })?
-                            .len(),
+                }
+            }));
+
+        // The first iteration panics, but after the restart backoff, cleanup
+        // should resume on the following period.
+        tokio::time::sleep(PERIOD * 3).await;
+        let count = iterations.load(Ordering::SeqCst);
+        assert!(count >= 2, "cleanup did not resume after a panic: {count}");
+
+        // Aborting the supervisor also stops the task it's supervising.
+        supervisor.abort();
+        assert!(supervisor.await.unwrap_err().is_cancelled());
+        let count = iterations.load(Ordering::SeqCst);
+        tokio::time::sleep(PERIOD * 3).await;
+        assert_eq!(iterations.load(Ordering::SeqCst), count);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cleanup_task_backoff_resets_after_healthy_run() {
+        const N_QUICK_PANICS: usize = 6;
+        let log = Logger::root(slog::Discard, slog::o!());
+
+        // A stand-in for the periodic cleanup task, which panics right away
+        // several times, then panics once more after running for a while.
+        let starts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let starts_clone = starts.clone();
+        let supervisor =
+            tokio::task::spawn(supervise_cleanup_task(log, move || {
+                let starts = starts_clone.clone();
+                async move {
+                    let n_starts = {
+                        let mut starts = starts.lock().unwrap();
+                        starts.push(tokio::time::Instant::now());
+                        starts.len()
                     };
-                    info_by_dir.push(info);
-                } else {
-                    warn!(
-                        log,
-                        "found non-zone-bundle file in zone bundle directory";
-                        "path" => %path,
-                    );
+                    if n_starts <= N_QUICK_PANICS {
+                        panic!("injected quick panic");
+                    } else if n_starts == N_QUICK_PANICS + 1 {
+                        tokio::time::sleep(CLEANUP_TASK_HEALTHY_RUNTIME).await;
+                        panic!("injected panic after a healthy run");
+                    }
+                    std::future::pending::<()>().await;
                 }
+            }));
+        while starts.lock().unwrap().len() < N_QUICK_PANICS + 2 {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        supervisor.abort();
+
+        // The restarts after quick panics back off exponentially, so the last
+        // of them waits at least several seconds...
+        let starts = starts.lock().unwrap().clone();
+        let last_quick_delay =
+            starts[N_QUICK_PANICS] - starts[N_QUICK_PANICS - 1];
+        assert!(
+            last_quick_delay > Duration::from_secs(1),
+            "restarts did not back off: {last_quick_delay:?}",
+        );
+
+        // ... but the restart after a healthy run starts from scratch.
+        let healthy_run_ended =
+            starts[N_QUICK_PANICS] + CLEANUP_TASK_HEALTHY_RUNTIME;
+        let delay_after_healthy_run =
+            starts[N_QUICK_PANICS + 1] - healthy_run_ended;
+        assert!(
+            delay_after_healthy_run < Duration::from_secs(1),
+            "backoff was not reset: {delay_after_healthy_run:?}",
+        );
+    }
+
+    #[test]
+    fn test_priority_dimension() {
+        assert!(PriorityOrder::new(&[]).is_err());
+        assert!(PriorityOrder::new(&[PriorityDimension::Cause]).is_err());
+        assert!(PriorityOrder::new(&[
+            PriorityDimension::Cause,
+            PriorityDimension::Cause
+        ])
+        .is_err());
+        assert!(PriorityOrder::new(&[
+            PriorityDimension::Cause,
+            PriorityDimension::Cause,
+            PriorityDimension::Time
+        ])
+        .is_err());
+
+        assert!(PriorityOrder::new(&[
+            PriorityDimension::Cause,
+            PriorityDimension::Time
+        ])
+        .is_ok());
+        assert_eq!(
+            PriorityOrder::new(&PriorityOrder::default().0).unwrap(),
+            PriorityOrder::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_disk_usage() {
+        let path =
+            Utf8PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src"));
+        let usage = disk_usage(&path).await.unwrap();
+        // Run `du -As /path/to/omicron/sled-agent/src`, which currently shows this
+        // directory is ~450 KiB.
+        assert!(
+            usage >= 1024 * 400,
+            "sled-agent manifest directory disk usage not correct?"
+        );
+        let path = Utf8PathBuf::from("/some/nonexistent/path");
+        assert!(disk_usage(&path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bundle_error_is_transient() {
+        let io_error = || std::io::Error::from(std::io::ErrorKind::Interrupted);
+        assert!(BundleError::ReadDirectory {
+            directory: Utf8PathBuf::from("/some/directory"),
+            err: io_error(),
+        }
+        .is_transient());
+        assert!(BundleError::CopyArchive {
+            from: Utf8PathBuf::from("/a/bundle.tar.gz"),
+            to: Utf8PathBuf::from("/b/bundle.tar.gz"),
+            err: io_error(),
+        }
+        .is_transient());
+        assert!(BundleError::NoStorage.is_transient());
+        assert!(BundleError::Unavailable { name: String::from("oxz_zone") }
+            .is_transient());
+
+        assert!(!BundleError::InvalidStorageLimit.is_transient());
+        assert!(!BundleError::InvalidCleanupPeriod.is_transient());
+        assert!(!BundleError::NoSuchZone { name: String::from("oxz_zone") }
+            .is_transient());
+        assert!(!BundleError::UnsupportedVersion { found: 2, supported: 1 }
+            .is_transient());
+        assert!(!BundleError::BundleFailed(anyhow::anyhow!("failed"))
+            .is_transient());
+
+        // Only cancelled tasks are worth retrying, not those that panicked.
+        let task = tokio::spawn(std::future::pending::<()>());
+        task.abort();
+        let err = task.await.unwrap_err();
+        assert!(BundleError::Task(err).is_transient());
+        let err = tokio::spawn(async { panic!("bundling panicked") })
+            .await
+            .unwrap_err();
+        assert!(!BundleError::Task(err).is_transient());
+    }
+
+    #[test]
+    fn test_storage_limit_bytes_available() {
+        let pct = StorageLimit(1);
+        assert_eq!(pct.bytes_available(100), 1);
+        assert_eq!(pct.bytes_available(1000), 10);
+
+        let pct = StorageLimit(100);
+        assert_eq!(pct.bytes_available(100), 100);
+        assert_eq!(pct.bytes_available(1000), 1000);
+
+        let pct = StorageLimit(100);
+        assert_eq!(pct.bytes_available(99), 99);
+
+        let pct = StorageLimit(99);
+        assert_eq!(pct.bytes_available(1), 0);
+
+        // Test non-power of 10.
+        let pct = StorageLimit(25);
+        assert_eq!(pct.bytes_available(32768), 8192);
+    }
+
+    #[test]
+    fn test_compare_bundles() {
+        use PriorityDimension::*;
+        let time_first = PriorityOrder([Time, Cause]);
+        let cause_first = PriorityOrder([Cause, Time]);
+
+        fn make_info(
+            year: i32,
+            month: u32,
+            day: u32,
+            cause: ZoneBundleCause,
+        ) -> ZoneBundleInfo {
+            ZoneBundleInfo {
+                metadata: ZoneBundleMetadata {
+                    id: ZoneBundleId {
+                        zone_name: String::from("oxz_whatever"),
+                        bundle_id: uuid::Uuid::new_v4(),
+                    },
+                    time_created: Utc
+                        .with_ymd_and_hms(year, month, day, 0, 0, 0)
+                        .single()
+                        .unwrap(),
+                    cause,
+                    version: 0,
+                    content_hash: None,
+                    annotations: BTreeMap::new(),
+                    zone_wide_commands: Vec::new(),
+                    detail: BundleDetailLevel::Full,
+                },
+                path: Utf8PathBuf::from("/some/path"),
+                bytes: 0,
             }
         }
-        out.insert(dir.clone(), info_by_dir);
+
+        let info = [
+            make_info(2020, 1, 2, ZoneBundleCause::TerminatedInstance),
+            make_info(2020, 1, 2, ZoneBundleCause::ExplicitRequest),
+            make_info(2020, 1, 1, ZoneBundleCause::TerminatedInstance),
+            make_info(2020, 1, 1, ZoneBundleCause::ExplicitRequest),
+        ];
+
+        let mut sorted = info.clone();
+        sorted.sort_by(|lhs, rhs| time_first.compare_bundles(lhs, rhs));
+        // Low -> high priority
+        // [old/terminated, old/explicit, new/terminated, new/explicit]
+        let expected = [
+            info[2].clone(),
+            info[3].clone(),
+            info[0].clone(),
+            info[1].clone(),
+        ];
+        assert_eq!(
+            sorted, expected,
+            "sorting zone bundles by time-then-cause failed"
+        );
+
+        let mut sorted = info.clone();
+        sorted.sort_by(|lhs, rhs| cause_first.compare_bundles(lhs, rhs));
+        // Low -> high priority
+        // [old/terminated, new/terminated, old/explicit, new/explicit]
+        let expected = [
+            info[2].clone(),
+            info[0].clone(),
+            info[3].clone(),
+            info[1].clone(),
+        ];
+        assert_eq!(
+            sorted, expected,
+            "sorting zone bundles by cause-then-time failed"
+        );
+    }
+
+    #[test]
+    fn test_compare_bundles_with_reason() {
+        use PriorityDimension::*;
+        let make_info = |cause| ZoneBundleInfo {
+            metadata: ZoneBundleMetadata {
+                id: ZoneBundleId {
+                    zone_name: String::from("oxz_whatever"),
+                    bundle_id: uuid::Uuid::new_v4(),
+                },
+                time_created: Utc
+                    .with_ymd_and_hms(2020, 1, 1, 0, 0, 0)
+                    .single()
+                    .unwrap(),
+                cause,
+                version: 0,
+                content_hash: None,
+                annotations: BTreeMap::new(),
+                zone_wide_commands: Vec::new(),
+                detail: BundleDetailLevel::Full,
+            },
+            path: Utf8PathBuf::from("/some/path"),
+            bytes: 0,
+        };
+
+        // These bundles were created at the same time, so whichever order is
+        // used, only their cause can distinguish them.
+        let terminated = make_info(ZoneBundleCause::TerminatedInstance);
+        let explicit = make_info(ZoneBundleCause::ExplicitRequest);
+        for order in
+            [PriorityOrder([Time, Cause]), PriorityOrder([Cause, Time])]
+        {
+            assert_eq!(
+                order.compare_bundles_with_reason(&terminated, &explicit),
+                (std::cmp::Ordering::Less, Some(Cause)),
+            );
+            assert_eq!(
+                order.compare_bundles_with_reason(&explicit, &terminated),
+                (std::cmp::Ordering::Greater, Some(Cause)),
+            );
+            assert_eq!(
+                order.compare_bundles_with_reason(&explicit, &explicit),
+                (std::cmp::Ordering::Equal, None),
+            );
+        }
     }
-    Ok(out)
-}
 
-/// The count of bundles / bytes removed during a cleanup operation.
-#[derive(Clone, Copy, Debug, Default, Deserialize, JsonSchema, Serialize)]
-pub struct CleanupCount {
-    /// The number of bundles removed.
-    bundles: u64,
-    /// The number of bytes removed.
-    bytes: u64,
-}
+    #[tokio::test]
+    async fn test_unreadable_storage_directory_is_skipped() {
+        let log = Logger::root(slog::Discard, slog::o!());
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let good_dir = tmpdir.path().join("good");
+        let bad_dir = tmpdir.path().join("bad");
+        let good = insert_fake_bundle_with_zone_name(
+            &good_dir,
+            2020,
+            1,
+            1,
+            ZoneBundleCause::ExplicitRequest,
+            "oxz_good",
+        )
+        .await
+        .unwrap();
+        insert_fake_bundle_with_zone_name(
+            &bad_dir,
+            2020,
+            1,
+            1,
+            ZoneBundleCause::ExplicitRequest,
+            "oxz_bad",
+        )
+        .await
+        .unwrap();
+
+        // Make the second directory unreadable, as though its disk had
+        // faulted out from under us.
+        tokio::fs::remove_dir_all(&bad_dir).await.unwrap();
+        tokio::fs::write(&bad_dir, b"not a directory").await.unwrap();
+        let dirs = [bad_dir.clone(), good_dir.clone()];
+
+        // Listing still finds the bundle in the healthy directory, and reports
+        // the error from the other.
+        let (bundles, errors) = list_zone_bundles(&log, &dirs, |_| true).await;
+        assert_eq!(bundles, BTreeSet::from([good.metadata.clone()]));
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            errors.get(&bad_dir),
+            Some(BundleError::ReadDirectory { directory, .. })
+                if directory == &bad_dir
+        ));
 
-// Run a cleanup, removing old bundles according to the strategy.
-//
-// Return the number of bundles removed and the new usage.
-async fn run_cleanup(
-    log: &Logger,
-    storage_dirs: &[Utf8PathBuf],
-    context: &CleanupContext,
-) -> Result<BTreeMap<Utf8PathBuf, CleanupCount>, BundleError> {
-    // First, determine how much space we are allowed to use and have used.
-    //
-    // Let's avoid doing anything at all if we're still within the limits.
-    let usages = compute_bundle_utilization(log, storage_dirs, context).await?;
-    if usages.values().all(|usage| usage.bytes_used <= usage.bytes_available) {
-        debug!(log, "all usages below storage limit, returning");
-        return Ok(BTreeMap::new());
-    }
+        // The bundler returns the error to its callers alongside the results.
+        let bundler = ZoneBundler::new(
+            log.clone(),
+            StorageResources::new_for_test(),
+            CleanupContext::default(),
+            Some(dirs.to_vec()),
+        );
+        let listed = bundler.list(None, None).await.unwrap();
+        assert_eq!(listed.result, [good.metadata.clone()]);
+        assert_eq!(
+            listed.directory_errors.keys().collect::<Vec<_>>(),
+            [&bad_dir]
+        );
 
-    // There's some work to do, let's enumerate all the bundles.
-    let bundles = enumerate_zone_bundles(log, &storage_dirs).await?;
-    debug!(
-        log,
-        "enumerated {} zone bundles across {} directories",
-        bundles.values().map(Vec::len).sum::<usize>(),
-        bundles.len(),
-    );
+        // As does enumerating bundles for cleanup.
+        let (info, errors) = enumerate_zone_bundles(&log, &dirs).await;
+        assert_eq!(info.len(), 1);
+        assert_eq!(info[&good_dir], [good]);
+        assert!(errors.contains_key(&bad_dir));
+    }
 
-    // Remove bundles from each storage directory, until we fall below the
-    // number of bytes we would like to use to satisfy the storage limit.
-    let mut cleanup_counts = BTreeMap::new();
-    for (dir, mut info) in bundles.into_iter() {
-        debug!(
-            log,
-            "cleaning up bundles from directory";
-            "directory" => dir.as_str()
+    #[tokio::test]
+    async fn test_list_page_spans_directories() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "test_list_page_spans_directories",
+        );
+        let first = camino_tempfile::tempdir().unwrap();
+        let second = camino_tempfile::tempdir().unwrap();
+        let bundler = ZoneBundler::new(
+            logctx.log.clone(),
+            StorageResources::new_for_test(),
+            CleanupContext::default(),
+            Some(vec![first.path().to_owned(), second.path().to_owned()]),
         );
-        let mut count = CleanupCount::default();
 
-        // Sort all the bundles in the current directory, using the priority
-        // described in `context.priority`.
-        info.sort_by(|lhs, rhs| context.priority.compare_bundles(lhs, rhs));
-        let current_usage = usages.get(&dir).unwrap();
+        // Mirror several bundles into both directories, as bundles normally
+        // are, and leave one only in the second.
+        let mut expected = BTreeSet::new();
+        for day in 1..=4 {
+            let info = insert_fake_bundle_with_zone_name(
+                first.path(),
+                2020,
+                1,
+                day,
+                ZoneBundleCause::ExplicitRequest,
+                &format!("oxz_paged_{}", day % 2),
+            )
+            .await
+            .unwrap();
+            let relative = info.path.strip_prefix(first.path()).unwrap();
+            let copy = second.path().join(relative);
+            tokio::fs::create_dir_all(copy.parent().unwrap()).await.unwrap();
+            tokio::fs::copy(&info.path, &copy).await.unwrap();
+            expected.insert(info.metadata.id);
+        }
+        let only_second = insert_fake_bundle_with_zone_name(
+            second.path(),
+            2020,
+            1,
+            5,
+            ZoneBundleCause::ExplicitRequest,
+            "oxz_paged_0",
+        )
+        .await
+        .unwrap();
+        expected.insert(only_second.metadata.id);
 
-        // Remove bundles until we fall below the threshold.
-        let mut n_bytes = current_usage.bytes_used;
-        for each in info.into_iter() {
-            if n_bytes <= current_usage.bytes_available {
+        // Page through the bundles a few at a time. Each logical bundle should
+        // be listed exactly once, in order of ID.
+        let mut listed = Vec::new();
+        let mut last_seen = None;
+        loop {
+            let page = bundler
+                .list_page(None, None, last_seen.as_ref(), 2)
+                .await
+                .unwrap()
+                .result;
+            assert!(page.len() <= 2);
+            let Some(last) = page.last() else {
                 break;
-            }
-            tokio::fs::remove_file(&each.path).await.map_err(|_| {
-                BundleError::Cleanup(anyhow!("failed to remove bundle"))
-            })?;
-            trace!(log, "removed old zone bundle"; "info" => ?&each);
-            n_bytes = n_bytes.saturating_sub(each.bytes);
-            count.bundles += 1;
-            count.bytes += each.bytes;
+            };
+            last_seen = Some(last.id.clone());
+            listed.extend(page.into_iter().map(|bundle| bundle.id));
         }
+        assert_eq!(listed, expected.into_iter().collect::<Vec<_>>());
 
-        cleanup_counts.insert(dir, count);
+        // Zones before the cursor aren't read at all. Add a zone which sorts
+        // before the others, whose directory is a file that can't be read as
+        // one, and page from the last bundle of the next zone.
+        let last_of_first_zone = bundler
+            .list_for_zone("oxz_paged_0")
+            .await
+            .unwrap()
+            .result
+            .pop()
+            .unwrap();
+        for dir in [&first, &second] {
+            let zone_dir = dir.path().join("oxz_early");
+            tokio::fs::write(&zone_dir, "not a directory").await.unwrap();
+        }
+        let page = bundler
+            .list_page(None, None, Some(&last_of_first_zone.id), 2)
+            .await
+            .unwrap();
+        assert!(page.directory_errors.is_empty());
+        assert_eq!(page.result.len(), 2);
+        assert!(page
+            .result
+            .iter()
+            .all(|bundle| bundle.id.zone_name == "oxz_paged_1"));
+        logctx.cleanup_successful();
     }
-    info!(log, "finished bundle cleanup"; "cleanup_counts" => ?&cleanup_counts);
-    Ok(cleanup_counts)
-}
 
-// Return the total utilization for all zone bundles.
-async fn compute_bundle_utilization(
-    log: &Logger,
-    storage_dirs: &[Utf8PathBuf],
-    context: &CleanupContext,
-) -> Result<BTreeMap<Utf8PathBuf, BundleUtilization>, BundleError> {
-    let mut out = BTreeMap::new();
-    for dir in storage_dirs.iter() {
-        debug!(log, "computing bundle usage"; "directory" => %dir);
-        // Fetch the ZFS dataset quota.
-        let dataset_quota = zfs_quota(dir).await?;
-        debug!(log, "computed dataset quota"; "quota" => dataset_quota);
-
-        // Compute the bytes available, using the provided storage limit.
-        let bytes_available =
-            context.storage_limit.bytes_available(dataset_quota);
-        debug!(
-            log,
-            "computed bytes available";
-            "storage_limit" => %context.storage_limit,
-            "bytes_available" => bytes_available
-        );
+    #[tokio::test]
+    async fn test_list_bundles_by_annotation() {
+        let log = Logger::root(slog::Discard, slog::o!());
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let dirs = [tmpdir.path().to_owned()];
+        let tagged = insert_fake_bundle_with_annotations(
+            tmpdir.path(),
+            2020,
+            1,
+            1,
+            ZoneBundleCause::ExplicitRequest,
+            "oxz_tagged",
+            BTreeMap::from([(
+                String::from("incident"),
+                String::from("INCIDENT-1234"),
+            )]),
+        )
+        .await
+        .unwrap();
+        let untagged = insert_fake_bundle_with_zone_name(
+            tmpdir.path(),
+            2020,
+            1,
+            2,
+            ZoneBundleCause::ExplicitRequest,
+            "oxz_untagged",
+        )
+        .await
+        .unwrap();
 
-        // Compute the size of the actual storage directory.
-        //
-        // TODO-correctness: This takes into account the directories themselves,
-        // and may be not quite what we want. But it is very easy and pretty
-        // close.
-        let bytes_used = disk_usage(dir).await?;
-        debug!(log, "computed bytes used"; "bytes_used" => bytes_used);
-        out.insert(
-            dir.clone(),
-            BundleUtilization { dataset_quota, bytes_available, bytes_used },
+        // The annotations are read back from the bundle itself.
+        let list = |annotation| {
+            list_zone_bundles(&log, &dirs, move |md| {
+                bundle_matches(md, None, annotation)
+            })
+        };
+        let (bundles, errors) = list(Some(("incident", "INCIDENT-1234"))).await;
+        assert!(errors.is_empty());
+        assert_eq!(bundles, BTreeSet::from([tagged.metadata.clone()]));
+
+        // Both the key and value must match.
+        let (bundles, _) = list(Some(("incident", "INCIDENT-5678"))).await;
+        assert!(bundles.is_empty());
+        let (bundles, _) = list(Some(("ticket", "INCIDENT-1234"))).await;
+        assert!(bundles.is_empty());
+
+        // Without an annotation, everything is listed.
+        let (bundles, _) = list(None).await;
+        assert_eq!(
+            bundles,
+            BTreeSet::from([tagged.metadata, untagged.metadata])
         );
     }
-    Ok(out)
-}
-
-/// Context provided for the zone bundle cleanup task.
-#[derive(
-    Clone, Copy, Debug, Default, Deserialize, JsonSchema, PartialEq, Serialize,
-)]
-pub struct CleanupContext {
-    /// The period on which automatic checks and cleanup is performed.
-    pub period: CleanupPeriod,
-    /// The limit on the dataset quota available for zone bundles.
-    pub storage_limit: StorageLimit,
-    /// The priority ordering for keeping old bundles.
-    pub priority: PriorityOrder,
-}
-
-// Return the number of bytes occupied by the provided directory.
-//
-// This returns an error if:
-//
-// - The "du" command fails
-// - Parsing stdout fails
-// - Parsing the actual size as a u64 fails
-async fn disk_usage(path: &Utf8PathBuf) -> Result<u64, BundleError> {
-    // Each OS implements slightly different `du` options.
-    //
-    // Linux and illumos support the "apparent" size in bytes, though using
-    // different options. macOS doesn't support bytes at all, and has a minimum
-    // block size of 512.
-    //
-    // We'll suffer the lower resolution on macOS, and get higher resolution on
-    // the others.
-    cfg_if::cfg_if! {
-        if #[cfg(target_os = "illumos")] {
-            const BLOCK_SIZE: u64 = 1;
-            const DU_ARG: &str = "-A";
-        } else if #[cfg(target_os = "linux")] {
-            const BLOCK_SIZE: u64 = 1;
-            const DU_ARG: &str = "-b";
-        } else if #[cfg(target_os = "macos")] {
-            const BLOCK_SIZE: u64 = 512;
-            const DU_ARG: &str = "-k";
-        } else {
-            compile_error!("unsupported target OS");
-        }
-    }
-    const DU: &str = "/usr/bin/du";
-    let args = &[DU_ARG, "-s", path.as_str()];
-    let output = Command::new(DU).args(args).output().await.map_err(|err| {
-        BundleError::Command { cmd: format!("{DU} {}", args.join(" ")), err }
-    })?;
-    let err = |msg: &str| {
-        BundleError::Cleanup(anyhow!(
-            "failed to fetch disk usage for {}: {}",
-            path,
-            msg,
-        ))
-    };
-    if !output.status.success() {
-        return Err(err("du command failed"));
-    }
-    let Ok(s) = std::str::from_utf8(&output.stdout) else {
-        return Err(err("non-UTF8 stdout"));
-    };
-    let Some(line) = s.lines().next() else {
-        return Err(err("no lines in du output"));
-    };
-    let Some(part) = line.trim().split_ascii_whitespace().next() else {
-        return Err(err("no disk usage size computed in output"));
-    };
-    part.parse()
-        .map(|x: u64| x.saturating_mul(BLOCK_SIZE))
-        .map_err(|_| err("failed to parse du output"))
-}
 
-// Return the quota for a ZFS dataset, or the available size.
-//
-// This fails if:
-//
-// - The "zfs" command fails
-// - Parsing stdout fails
-// - Parsing the actual quota as a u64 fails
-async fn zfs_quota(path: &Utf8PathBuf) -> Result<u64, BundleError> {
-    let args = &["list", "-Hpo", "quota,avail", path.as_str()];
-    let output =
-        Command::new(ZFS).args(args).output().await.map_err(|err| {
-            BundleError::Command {
-                cmd: format!("{ZFS} {}", args.join(" ")),
-                err,
-            }
-        })?;
-    let err = |msg: &str| {
-        BundleError::Cleanup(anyhow!(
-            "failed to fetch ZFS quota for {}: {}",
-            path,
-            msg,
-        ))
-    };
-    if !output.status.success() {
-        return Err(err("zfs list command failed"));
+    #[test]
+    fn test_metadata_without_annotations() {
+        // Metadata as written by bundles before annotations were supported.
+        let contents = r#"
+            time_created = "2020-01-01T00:00:00Z"
+            version = 0
+            cause = "explicit_request"
+
+            [id]
+            zone_name = "oxz_old"
+            bundle_id = "6a8e2c2e-9d6b-4e58-a0d4-5e0c1b0c9c37"
+        "#;
+        let metadata: ZoneBundleMetadata = toml::from_str(contents).unwrap();
+        assert_eq!(metadata.version, 0);
+        assert!(metadata.annotations.is_empty());
+        assert!(!metadata.has_annotation("incident", "INCIDENT-1234"));
     }
-    let Ok(s) = std::str::from_utf8(&output.stdout) else {
-        return Err(err("non-UTF8 stdout"));
-    };
-    let Some(line) = s.lines().next() else {
-        return Err(err("no lines in zfs list output"));
-    };
-    let mut parts = line.split_ascii_whitespace();
-    let quota = parts.next().ok_or_else(|| err("no quota part of line"))?;
-    let avail = parts.next().ok_or_else(|| err("no avail part of line"))?;
-
-    // Parse the available space, which is always defined.
-    let avail = avail
-        .trim()
-        .parse()
-        .map_err(|_| err("failed to parse available space"))?;
 
-    // Quotas can be reported a few different ways.
-    //
-    // If the dataset is a volume (which should not happen, but we don't enforce
-    // here), then this is a literal dash `-`. Something without a quota is
-    // reported as `0`. Anything else is an integer.
-    //
-    // No quota is reported as `u64::MAX`.
-    match quota.trim() {
-        "-" | "0" => Ok(avail),
-        x => x.parse().or(Ok(avail)),
+    // Create a fake zone bundle in `dir`, compressed at the provided level, and
+    // containing a large, compressible log file alongside its metadata.
+    async fn insert_compressible_fake_bundle(
+        dir: &Utf8Path,
+        day: u32,
+        compression: flate2::Compression,
+    ) -> anyhow::Result<ZoneBundleInfo> {
+        let metadata = ZoneBundleMetadata {
+            time_created: Utc
+                .with_ymd_and_hms(2020, 1, day, 0, 0, 0)
+                .single()
+                .context("invalid day")?,
+            ..ZoneBundleMetadata::new(
+                "oxz_compressible",
+                ZoneBundleCause::ExplicitRequest,
+                BTreeMap::new(),
+            )
+        };
+        let zone_dir = dir.join(&metadata.id.zone_name);
+        tokio::fs::create_dir_all(&zone_dir)
+            .await
+            .context("failed to create zone directory")?;
+        let path = zone_dir.join(format!("{}.tar.gz", metadata.id.bundle_id));
+        let file = std::fs::File::create(&path)
+            .context("failed to create zone bundle file")?;
+        let gz = flate2::GzBuilder::new().write(file, compression);
+        let mut builder = tar::Builder::new(gz);
+        insert_data(
+            &mut builder,
+            super::ZONE_BUNDLE_METADATA_FILENAME,
+            toml::to_string(&metadata)?.as_bytes(),
+        )?;
+        let log: String = (0..20_000)
+            .map(|i| format!("{i}: request {} completed\n", i * 7919 % 1000))
+            .collect();
+        insert_data(&mut builder, "oxide-fake.log", log.as_bytes())?;
+        builder.into_inner()?.finish()?;
+        let bytes = tokio::fs::metadata(&path).await?.len();
+        Ok(ZoneBundleInfo { metadata, path, bytes })
     }
-}
 
-/// The limit on space allowed for zone bundles, as a percentage of the overall
-/// dataset's quota.
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Deserialize,
-    JsonSchema,
-    PartialEq,
-    PartialOrd,
-    Serialize,
-)]
-pub struct StorageLimit(u8);
+    #[test]
+    fn test_long_entry_names_round_trip() {
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let long_name = |prefix: &str| {
+            format!("{prefix}-{}.log", "oxide-very-long-service-name".repeat(5))
+        };
 
-impl std::fmt::Display for StorageLimit {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}%", self.as_u8())
+        // One long name written as data, and one appended from a file.
+        let data_name = long_name("data");
+        let file_name = long_name("file");
+        assert!(data_name.len() > 100);
+        assert!(file_name.len() > 100);
+        let file_path = tmpdir.path().join(&file_name);
+        std::fs::write(&file_path, b"from a file").unwrap();
+
+        let path = tmpdir.path().join("bundle.tar.gz");
+        let file = std::fs::File::create(&path).unwrap();
+        let gz =
+            flate2::GzBuilder::new().write(file, flate2::Compression::fast());
+        let mut builder = tar::Builder::new(gz);
+        insert_data(&mut builder, &data_name, b"from data").unwrap();
+        builder.append_path_with_name(&file_path, &file_name).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let bundle_id = uuid::Uuid::new_v4();
+        assert_eq!(
+            extract_to_vec(&path, &bundle_id, &data_name).unwrap(),
+            b"from data",
+        );
+        assert_eq!(
+            extract_to_vec(&path, &bundle_id, &file_name).unwrap(),
+            b"from a file",
+        );
     }
-}
 
-impl Default for StorageLimit {
-    fn default() -> Self {
-        StorageLimit(25)
+    // Return the decompressed contents of a bundle.
+    fn decompress_bundle(path: &Utf8Path) -> Vec<u8> {
+        let file = std::fs::File::open(path).unwrap();
+        let mut out = Vec::new();
+        flate2::read::GzDecoder::new(file).read_to_end(&mut out).unwrap();
+        out
     }
-}
 
-impl StorageLimit {
-    /// Minimum percentage of dataset quota supported.
-    pub const MIN: Self = Self(0);
+    #[tokio::test]
+    async fn test_recompress_oldest_bundles() {
+        let log = Logger::root(slog::Discard, slog::o!());
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let dir = tmpdir.path().to_owned();
+        let mut info = Vec::new();
+        for day in 1..=3 {
+            info.push(
+                insert_compressible_fake_bundle(
+                    &dir,
+                    day,
+                    flate2::Compression::fast(),
+                )
+                .await
+                .unwrap(),
+            );
+        }
+        let contents: Vec<_> =
+            info.iter().map(|each| decompress_bundle(&each.path)).collect();
+        let size = |each: &ZoneBundleInfo| {
+            std::fs::metadata(&each.path).unwrap().len()
+        };
 
-    /// Maximum percentage of dataset quota supported.
-    pub const MAX: Self = Self(50);
+        // While the oldest bundle is being read, only the next oldest is
+        // recompressed.
+        let active_reads = ActiveReads::default();
+        let lock = Mutex::new(());
+        let guard = active_reads.start(&info[0].path);
+        let reclaimed =
+            recompress_oldest_bundles(&log, &dir, &active_reads, &lock, 1)
+                .await
+                .unwrap();
+        assert!(reclaimed > 0);
+        assert_eq!(reclaimed, info[1].bytes - size(&info[1]));
+        assert_eq!(size(&info[0]), info[0].bytes);
+        assert_eq!(size(&info[2]), info[2].bytes);
+        assert!(is_fully_compressed(&info[1].path).await.unwrap());
+        assert!(!is_fully_compressed(&info[0].path).await.unwrap());
+
+        // The index records the new hash of the recompressed bundle.
+        let zone_dir = info[1].path.parent().unwrap();
+        let index = read_zone_bundle_index(&log, zone_dir).await.unwrap();
+        assert_eq!(
+            index.bundles[info[1].path.file_name().unwrap()].content_hash,
+            Some(compute_content_hash(&info[1].path).await.unwrap()),
+        );
 
-    /// Construct a new limit allowed for zone bundles.
-    ///
-    /// This should be expressed as a percentage, in the range (Self::MIN,
-    /// Self::MAX].
-    pub const fn new(percentage: u8) -> Result<Self, BundleError> {
-        if percentage > Self::MIN.0 && percentage <= Self::MAX.0 {
-            Ok(Self(percentage))
-        } else {
-            Err(BundleError::InvalidStorageLimit)
+        // Once it's no longer being read, the rest are recompressed, and
+        // nothing more is done after that.
+        drop(guard);
+        let reclaimed =
+            recompress_oldest_bundles(&log, &dir, &active_reads, &lock, 10)
+                .await
+                .unwrap();
+        assert!(reclaimed > 0);
+        let reclaimed =
+            recompress_oldest_bundles(&log, &dir, &active_reads, &lock, 10)
+                .await
+                .unwrap();
+        assert_eq!(reclaimed, 0);
+
+        // Every bundle is smaller, but extracts to exactly the same archive.
+        for (each, contents) in info.iter().zip(contents.iter()) {
+            assert!(size(each) < each.bytes);
+            assert!(is_fully_compressed(&each.path).await.unwrap());
+            assert_eq!(&decompress_bundle(&each.path), contents);
         }
     }
 
-    /// Return the contained quota percentage.
-    pub const fn as_u8(&self) -> u8 {
-        self.0
+    #[tokio::test]
+    async fn test_recompressed_bundle_is_not_resurrected() {
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("bundle.tar.gz");
+        let tmp_path = tmpdir.path().join("bundle.tar.gz.recompress.tmp");
+
+        // The bundle was removed while it was being recompressed, so the copy
+        // is discarded too.
+        tokio::fs::write(&tmp_path, b"recompressed").await.unwrap();
+        assert!(!replace_recompressed_bundle(&tmp_path, &path).await.unwrap());
+        assert!(!tmp_path.exists());
+        assert!(!path.exists());
+
+        // Otherwise, the copy replaces the original.
+        tokio::fs::write(&path, b"original").await.unwrap();
+        tokio::fs::write(&tmp_path, b"recompressed").await.unwrap();
+        assert!(replace_recompressed_bundle(&tmp_path, &path).await.unwrap());
+        assert!(!tmp_path.exists());
+        assert_eq!(tokio::fs::read(&path).await.unwrap(), b"recompressed");
     }
 
-    // Compute the number of bytes available from a dataset quota, in bytes.
-    const fn bytes_available(&self, dataset_quota: u64) -> u64 {
-        (dataset_quota * self.as_u8() as u64) / 100
+    #[tokio::test]
+    async fn test_delete_bundles_for_zone() {
+        let log = Logger::root(slog::Discard, slog::o!());
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let dirs = [tmpdir.path().join("first"), tmpdir.path().join("second")];
+
+        // Several bundles for the zone we're removing, in each directory, and
+        // one for some other zone.
+        let mut expected_bytes = 0;
+        for dir in dirs.iter() {
+            for day in 1..=3 {
+                let info = insert_fake_bundle_with_zone_name(
+                    dir,
+                    2020,
+                    1,
+                    day,
+                    ZoneBundleCause::ExplicitRequest,
+                    "oxz_doomed",
+                )
+                .await
+                .unwrap();
+                expected_bytes += info.bytes;
+            }
+        }
+        let survivor = insert_fake_bundle_with_zone_name(
+            &dirs[0],
+            2020,
+            1,
+            1,
+            ZoneBundleCause::ExplicitRequest,
+            "oxz_survivor",
+        )
+        .await
+        .unwrap();
+
+        let (count, errors) =
+            delete_bundles_for_zone(&log, &dirs, "oxz_doomed").await;
+        assert!(errors.is_empty());
+        assert_eq!(count.bundles, 6);
+        assert_eq!(count.bytes, expected_bytes);
+
+        // Only the other zone's bundle remains.
+        let (bundles, errors) = list_zone_bundles(&log, &dirs, |_| true).await;
+        assert!(errors.is_empty());
+        assert_eq!(bundles, BTreeSet::from([survivor.metadata]));
     }
-}
 
-/// A dimension along with bundles can be sorted, to determine priority.
-#[derive(
-    Clone,
-    Copy,
-    Debug,
-    Deserialize,
-    Eq,
-    Hash,
-    JsonSchema,
-    Serialize,
-    Ord,
-    PartialEq,
-    PartialOrd,
-)]
-#[serde(rename_all = "snake_case")]
-pub enum PriorityDimension {
-    /// Sorting by time, with older bundles with lower priority.
-    Time,
-    /// Sorting by the cause for creating the bundle.
-    Cause,
-    // TODO-completeness: Support zone or zone type (e.g., service vs instance)?
-}
+    #[test]
+    fn test_total_bundle_utilization() {
+        let utilization = BTreeMap::from([
+            (
+                Utf8PathBuf::from("/pool/int/a/debug/bundle/zone"),
+                BundleUtilization {
+                    dataset_quota: 1000,
+                    bytes_available: 250,
+                    bytes_used: 100,
+                },
+            ),
+            (
+                Utf8PathBuf::from("/pool/int/b/debug/bundle/zone"),
+                BundleUtilization {
+                    dataset_quota: 2000,
+                    bytes_available: 500,
+                    bytes_used: 10,
+                },
+            ),
+        ]);
+        let total: BundleUtilization = utilization.into_values().sum();
+        assert_eq!(
+            total,
+            BundleUtilization {
+                dataset_quota: 3000,
+                bytes_available: 750,
+                bytes_used: 110,
+            }
+        );
+        assert_eq!(
+            std::iter::empty::<BundleUtilization>().sum::<BundleUtilization>(),
+            BundleUtilization::default(),
+        );
+    }
 
-/// The priority order for bundles during cleanup.
-///
-/// Bundles are sorted along the dimensions in [`PriorityDimension`], with each
-/// dimension appearing exactly once. During cleanup, lesser-priority bundles
-/// are pruned first, to maintain the dataset quota. Note that bundles are
-/// sorted by each dimension in the order in which they appear, with each
-/// dimension having higher priority than the next.
-#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Serialize)]
-pub struct PriorityOrder([PriorityDimension; PriorityOrder::EXPECTED_SIZE]);
+    #[test]
+    fn test_min_keep_per_zone() {
+        const MIN_KEEP: u32 = 2;
+        const BUNDLE_SIZE: u64 = 100;
+        let make_info = |zone_name: &str, day| ZoneBundleInfo {
+            metadata: ZoneBundleMetadata {
+                id: ZoneBundleId {
+                    zone_name: String::from(zone_name),
+                    bundle_id: uuid::Uuid::new_v4(),
+                },
+                time_created: Utc
+                    .with_ymd_and_hms(2020, 1, day, 0, 0, 0)
+                    .single()
+                    .unwrap(),
+                cause: ZoneBundleCause::UnexpectedZone,
+                version: 0,
+                content_hash: None,
+                annotations: BTreeMap::new(),
+                zone_wide_commands: Vec::new(),
+                detail: BundleDetailLevel::Full,
+            },
+            path: Utf8PathBuf::from(format!("/{zone_name}/{day}.tar.gz")),
+            bytes: BUNDLE_SIZE,
+        };
 
-impl std::ops::Deref for PriorityOrder {
-    type Target = [PriorityDimension; PriorityOrder::EXPECTED_SIZE];
-    fn deref(&self) -> &Self::Target {
-        &self.0
+        // A few zones with different numbers of bundles, one with fewer than
+        // we'd like to keep.
+        let mut info = Vec::new();
+        for (zone_name, n_bundles) in [("oxz_a", 5), ("oxz_b", 3), ("oxz_c", 1)]
+        {
+            for day in 1..=n_bundles {
+                info.push(make_info(zone_name, day));
+            }
+        }
+        info.sort_by(|lhs, rhs| {
+            PriorityOrder::default().compare_bundles(lhs, rhs)
+        });
+        let bytes_used = BUNDLE_SIZE * info.len() as u64;
+
+        // With no storage available at all, everything could be removed, but
+        // the most recent bundles of each zone are kept.
+        let (to_remove, n_bytes) =
+            select_bundles_to_remove(&info, MIN_KEEP, bytes_used, 0);
+        let kept: Vec<_> = info
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !to_remove.contains(i))
+            .map(|(_, each)| each.path.as_str())
+            .collect();
+        let mut kept_sorted = kept.clone();
+        kept_sorted.sort();
+        assert_eq!(
+            kept_sorted,
+            [
+                "/oxz_a/4.tar.gz",
+                "/oxz_a/5.tar.gz",
+                "/oxz_b/2.tar.gz",
+                "/oxz_b/3.tar.gz",
+                "/oxz_c/1.tar.gz",
+            ]
+        );
+        assert_eq!(n_bytes, BUNDLE_SIZE * kept.len() as u64);
+
+        // Without the guarantee, everything goes.
+        let (to_remove, n_bytes) =
+            select_bundles_to_remove(&info, 0, bytes_used, 0);
+        assert_eq!(to_remove.len(), info.len());
+        assert_eq!(n_bytes, 0);
+
+        // When there's space, only as much as needed is removed, in priority
+        // order.
+        let (to_remove, n_bytes) = select_bundles_to_remove(
+            &info,
+            MIN_KEEP,
+            bytes_used,
+            bytes_used - BUNDLE_SIZE,
+        );
+        assert_eq!(to_remove, [0]);
+        assert_eq!(n_bytes, bytes_used - BUNDLE_SIZE);
     }
-}
 
-impl Default for PriorityOrder {
-    fn default() -> Self {
-        Self::DEFAULT
+    #[proptest]
+    fn test_arbitrary_cleanup_settings_are_valid(
+        period: CleanupPeriod,
+        limit: StorageLimit,
+        priority: PriorityOrder,
+    ) {
+        assert_eq!(CleanupPeriod::new(period.as_duration()).unwrap(), period);
+        assert_eq!(StorageLimit::new(limit.as_u8()).unwrap(), limit);
+        assert_eq!(PriorityOrder::new(&*priority).unwrap(), priority);
     }
-}
 
-impl PriorityOrder {
-    // NOTE: Must match the number of variants in `PriorityDimension`.
-    const EXPECTED_SIZE: usize = 2;
-    const DEFAULT: Self =
-        Self([PriorityDimension::Cause, PriorityDimension::Time]);
+    // Generate info for a bundle in one of a few zones, with an arbitrary
+    // cause, creation time, and size.
+    fn arb_bundle_info() -> impl Strategy<Value = ZoneBundleInfo> {
+        (
+            prop::sample::select(vec!["oxz_a", "oxz_b", "oxz_c"]),
+            any::<ZoneBundleCause>(),
+            0..1_000_000i64,
+            1..1000u64,
+        )
+            .prop_map(|(zone_name, cause, secs, bytes)| {
+                let bundle_id = uuid::Uuid::new_v4();
+                ZoneBundleInfo {
+                    metadata: ZoneBundleMetadata {
+                        id: ZoneBundleId {
+                            zone_name: String::from(zone_name),
+                            bundle_id,
+                        },
+                        time_created: Utc.timestamp_opt(secs, 0).unwrap(),
+                        cause,
+                        version: 0,
+                        content_hash: None,
+                        annotations: BTreeMap::new(),
+                        zone_wide_commands: Vec::new(),
+                        detail: BundleDetailLevel::Full,
+                    },
+                    path: Utf8PathBuf::from(format!(
+                        "/{zone_name}/{bundle_id}.tar.gz"
+                    )),
+                    bytes,
+                }
+            })
+    }
 
-    /// Construct a new priority order.
-    ///
-    /// This requires that each dimension appear exactly once.
-    pub fn new(dims: &[PriorityDimension]) -> Result<Self, BundleError> {
-        if dims.len() != Self::EXPECTED_SIZE {
-            return Err(BundleError::InvalidPriorityOrder);
+    // Check that cleanup removes bundles strictly in priority order, and only
+    // as many as needed to fit within the limit.
+    #[proptest]
+    fn test_cleanup_removes_lowest_priority_first(
+        #[strategy(prop::collection::vec(arb_bundle_info(), 0..32))] info: Vec<
+            ZoneBundleInfo,
+        >,
+        priority: PriorityOrder,
+        #[strategy(0..4u32)] min_keep_per_zone: u32,
+        #[strategy(0..32_000u64)] bytes_available: u64,
+    ) {
+        let mut info = info;
+        info.sort_by(|lhs, rhs| priority.compare_bundles(lhs, rhs));
+        let bytes_used = info.iter().map(|each| each.bytes).sum();
+        let (to_remove, n_bytes) = select_bundles_to_remove(
+            &info,
+            min_keep_per_zone,
+            bytes_used,
+            bytes_available,
+        );
+
+        // Find the bundles which are always kept, the newest in each zone.
+        let mut by_zone: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (i, each) in info.iter().enumerate() {
+            by_zone.entry(&each.metadata.id.zone_name).or_default().push(i);
         }
-        let mut seen = HashSet::new();
-        for dim in dims.iter() {
-            if !seen.insert(dim) {
-                return Err(BundleError::InvalidPriorityOrder);
+        let mut protected = BTreeSet::new();
+        for indices in by_zone.values_mut() {
+            indices.sort_by(|lhs, rhs| {
+                info[*rhs]
+                    .metadata
+                    .time_created
+                    .cmp(&info[*lhs].metadata.time_created)
+            });
+            protected.extend(indices.iter().take(min_keep_per_zone as usize));
+        }
+
+        // Protected bundles are never removed, and every other bundle ranks
+        // at least as high as the bundles removed before it.
+        let removed: BTreeSet<_> = to_remove.iter().copied().collect();
+        assert_eq!(removed.len(), to_remove.len());
+        for i in removed.iter() {
+            assert!(!protected.contains(i));
+        }
+        let kept = (0..info.len())
+            .filter(|i| !removed.contains(i) && !protected.contains(i));
+        if let Some(last_removed) = to_remove.last() {
+            for i in kept.clone() {
+                assert!(i > *last_removed);
+                assert_ne!(
+                    priority.compare_bundles(&info[i], &info[*last_removed]),
+                    std::cmp::Ordering::Less,
+                );
             }
         }
-        Ok(Self(dims.try_into().unwrap()))
+
+        // The reported usage accounts for exactly the removed bundles, and is
+        // within the limit unless there was nothing more we could remove.
+        let bytes_removed: u64 = to_remove.iter().map(|i| info[*i].bytes).sum();
+        assert_eq!(n_bytes, bytes_used - bytes_removed);
+        assert!(n_bytes <= bytes_available || kept.count() == 0);
+
+        // Removing one fewer bundle would not have been enough.
+        if let Some(last_removed) = to_remove.last() {
+            assert!(n_bytes + info[*last_removed].bytes > bytes_available);
+        }
     }
 
-    // Order zone bundle info according to the contained priority.
-    //
-    // We sort the info by each dimension, in the order in which it appears.
-    // That means earlier dimensions have higher priority than later ones.
-    fn compare_bundles(
-        &self,
-        lhs: &ZoneBundleInfo,
-        rhs: &ZoneBundleInfo,
-    ) -> Ordering {
-        for dim in self.0.iter() {
-            let ord = match dim {
-                PriorityDimension::Cause => {
-                    lhs.metadata.cause.cmp(&rhs.metadata.cause)
-                }
-                PriorityDimension::Time => {
-                    lhs.metadata.time_created.cmp(&rhs.metadata.time_created)
-                }
-            };
-            if matches!(ord, Ordering::Equal) {
-                continue;
+    #[test]
+    fn test_extract_zone_bundle_metadata_rejects_newer_version() {
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("bundle.tar.gz");
+
+        // Write a bundle from a future agent, whose metadata has a newer
+        // version and a field we know nothing about.
+        let mut metadata = toml::Value::try_from(ZoneBundleMetadata::new(
+            "oxz_whatever",
+            ZoneBundleCause::ExplicitRequest,
+            BTreeMap::new(),
+        ))
+        .unwrap();
+        let table = metadata.as_table_mut().unwrap();
+        let newer = ZoneBundleMetadata::VERSION + 1;
+        table.insert(String::from("version"), toml::Value::from(newer));
+        table.insert(
+            String::from("cause"),
+            toml::Value::from("some_future_cause"),
+        );
+        let file = std::fs::File::create(&path).unwrap();
+        let gz =
+            flate2::GzBuilder::new().write(file, flate2::Compression::best());
+        let mut builder = tar::Builder::new(gz);
+        insert_data(
+            &mut builder,
+            ZONE_BUNDLE_METADATA_FILENAME,
+            metadata.to_string().as_bytes(),
+        )
+        .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        match extract_zone_bundle_metadata_and_hash_impl(&path) {
+            Err(BundleError::UnsupportedVersion { found, supported }) => {
+                assert_eq!(found, newer);
+                assert_eq!(supported, ZoneBundleMetadata::VERSION);
             }
-            return ord;
+            other => panic!("expected UnsupportedVersion, found {other:?}"),
         }
-        Ordering::Equal
     }
-}
 
-/// A period on which bundles are automatically cleaned up.
-#[derive(
-    Clone, Copy, Deserialize, JsonSchema, PartialEq, PartialOrd, Serialize,
-)]
-pub struct CleanupPeriod(Duration);
+    #[tokio::test]
+    async fn test_extract_zone_bundle_metadata_accepts_older_version() {
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("bundle.tar.gz");
 
-impl Default for CleanupPeriod {
-    fn default() -> Self {
-        Self(Duration::from_secs(600))
+        // Write a bundle from an agent that predates scheduled captures.
+        let mut metadata = ZoneBundleMetadata::new(
+            "oxz_whatever",
+            ZoneBundleCause::ExplicitRequest,
+            BTreeMap::new(),
+        );
+        metadata.version = 1;
+        let file = std::fs::File::create(&path).unwrap();
+        let gz =
+            flate2::GzBuilder::new().write(file, flate2::Compression::best());
+        let mut builder = tar::Builder::new(gz);
+        insert_data(
+            &mut builder,
+            ZONE_BUNDLE_METADATA_FILENAME,
+            toml::to_string(&metadata).unwrap().as_bytes(),
+        )
+        .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let (read, hash) =
+            extract_zone_bundle_metadata_and_hash_impl(&path).unwrap();
+        assert_eq!(read, metadata);
+
+        // The hash covers the whole file, not just the metadata read from it.
+        assert_eq!(hash, compute_content_hash(&path).await.unwrap());
     }
-}
 
-impl CleanupPeriod {
-    /// The minimum supported cleanup period.
-    pub const MIN: Self = Self(Duration::from_secs(60));
+    #[test]
+    fn test_scheduled_bundles_use_current_version() {
+        // Agents before version 2 can't parse the scheduled cause, so bundles
+        // with it must be rejected by them rather than misread.
+        let metadata = ZoneBundleMetadata::new(
+            "oxz_whatever",
+            ZoneBundleCause::Scheduled,
+            BTreeMap::new(),
+        );
+        assert!(metadata.version >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_extract_zone_bundle_file() {
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let info = insert_fake_bundle_with_zone_name(
+            tmpdir.path(),
+            2020,
+            1,
+            1,
+            ZoneBundleCause::ExplicitRequest,
+            "oxz_whatever",
+        )
+        .await
+        .unwrap();
+
+        // Build a bundle with a few known entries, next to the fake one.
+        let path = info.path.with_file_name("known-entries.tar.gz");
+        let file = std::fs::File::create(&path).unwrap();
+        let gz =
+            flate2::GzBuilder::new().write(file, flate2::Compression::best());
+        let mut builder = tar::Builder::new(gz);
+        let entries: [(&str, &[u8]); 3] = [
+            ("ptree", b"ptree output"),
+            ("oxide-foo:default.log", b"some log lines\n"),
+            ("uptime", b"uptime output"),
+        ];
+        for (name, contents) in entries {
+            insert_data(&mut builder, name, contents).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let id = info.metadata.id.bundle_id;
+        for (name, contents) in entries {
+            assert_eq!(
+                extract_to_vec(&path, &id, name).unwrap(),
+                contents,
+                "extracted wrong contents for {name}"
+            );
+        }
+
+        // A missing entry is reported distinctly from other failures.
+        match extract_to_vec(&path, &id, "not-there") {
+            Err(BundleError::NoSuchBundleEntry { bundle_id, entry_path }) => {
+                assert_eq!(bundle_id, id);
+                assert_eq!(entry_path, "not-there");
+            }
+            other => panic!("expected NoSuchBundleEntry, found {other:?}"),
+        }
 
-    /// The maximum supported cleanup period.
-    pub const MAX: Self = Self(Duration::from_secs(60 * 60 * 24));
+        // The fake bundle's metadata can be extracted like any other file.
+        let metadata =
+            extract_to_vec(&info.path, &id, "metadata.toml").unwrap();
+        let metadata: ZoneBundleMetadata =
+            toml::from_str(std::str::from_utf8(&metadata).unwrap()).unwrap();
+        assert_eq!(
+            metadata,
+            ZoneBundleMetadata { content_hash: None, ..info.metadata },
+            "the bundle's own metadata can't include its hash"
+        );
 
-    /// Construct a new cleanup period, checking that it's valid.
-    pub fn new(duration: Duration) -> Result<Self, BundleError> {
-        if duration >= Self::MIN.as_duration()
-            && duration <= Self::MAX.as_duration()
-        {
-            Ok(Self(duration))
-        } else {
-            Err(BundleError::InvalidCleanupPeriod)
+        // Files larger than the provided maximum are rejected.
+        match extract_zone_bundle_file_impl(&path, &id, "ptree", 4, |_, _| ()) {
+            Err(BundleError::BundleEntryTooLarge {
+                size, max_size, ..
+            }) => {
+                assert_eq!(size, 12);
+                assert_eq!(max_size, 4);
+            }
+            other => panic!("expected BundleEntryTooLarge, found {other:?}"),
         }
     }
 
-    /// Return the period as a duration.
-    pub const fn as_duration(&self) -> Duration {
-        self.0
+    // Extract the full contents of a file from the zone bundle at `path`.
+    fn extract_to_vec(
+        path: &Utf8PathBuf,
+        bundle_id: &uuid::Uuid,
+        entry_path: &str,
+    ) -> Result<Vec<u8>, BundleError> {
+        extract_zone_bundle_file_impl(
+            path,
+            bundle_id,
+            entry_path,
+            MAX_EXTRACTED_FILE_SIZE,
+            |_, entry| {
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents).map(|_| contents)
+            },
+        )?
+        .map_err(|err| BundleError::ReadBundleData { path: path.clone(), err })
     }
-}
-
-impl TryFrom<Duration> for CleanupPeriod {
-    type Error = BundleError;
 
-    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
-        Self::new(duration)
+    // Read the full contents of a file extracted from a zone bundle.
+    pub(super) async fn read_bundle_file(file: BundleFile) -> Vec<u8> {
+        file.into_stream().try_concat().await.unwrap()
     }
-}
 
-impl std::fmt::Debug for CleanupPeriod {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        self.0.fmt(f)
+    // Spawn a real process standing in for a command run in a zone, which
+    // prints `stdout` after sleeping for the whole seconds in `delay`.
+    pub(super) fn fake_command(
+        stdout: &str,
+        delay: Duration,
+    ) -> std::process::Child {
+        std::process::Command::new("sh")
+            .arg("-c")
+            .arg(r#"sleep "$0"; printf %s "$1""#)
+            .arg(delay.as_secs().to_string())
+            .arg(stdout)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .process_group(0)
+            .spawn()
+            .expect("failed to spawn fake command")
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::disk_usage;
-    use super::PriorityDimension;
-    use super::PriorityOrder;
-    use super::StorageLimit;
-    use super::Utf8PathBuf;
-    use super::ZoneBundleCause;
-    use super::ZoneBundleId;
-    use super::ZoneBundleInfo;
-    use super::ZoneBundleMetadata;
-    use chrono::TimeZone;
-    use chrono::Utc;
 
-    #[test]
-    fn test_sort_zone_bundle_cause() {
-        use ZoneBundleCause::*;
-        let mut original =
-            [ExplicitRequest, Other, TerminatedInstance, UnexpectedZone];
-        let expected =
-            [Other, UnexpectedZone, TerminatedInstance, ExplicitRequest];
-        original.sort();
-        assert_eq!(original, expected);
+    // Returns the expectations for bundling zones known only to the OS.
+    //
+    // Each zone's zonepath is the directory under `zonepath` named for the
+    // zone, and the commands run in it are spawned with `spawn`.
+    pub(super) fn expect_zones_to_bundle(
+        zonepath: &Utf8Path,
+        mut spawn: impl FnMut(&mut std::process::Command) -> std::process::Child
+            + Send
+            + 'static,
+    ) -> Vec<Box<dyn std::any::Any>> {
+        let zonepath_ctx = MockZones::zonepath_context();
+        let path = zonepath.to_owned();
+        zonepath_ctx
+            .expect()
+            .returning(move |name| Ok(Some(path.join(name).into())));
+        let spawn_ctx = illumos_utils::spawn_context();
+        spawn_ctx.expect().returning(move |cmd| Ok(spawn(cmd)));
+        vec![Box::new(zonepath_ctx), Box::new(spawn_ctx)]
     }
 
-    #[test]
-    fn test_priority_dimension() {
-        assert!(PriorityOrder::new(&[]).is_err());
-        assert!(PriorityOrder::new(&[PriorityDimension::Cause]).is_err());
-        assert!(PriorityOrder::new(&[
-            PriorityDimension::Cause,
-            PriorityDimension::Cause
-        ])
-        .is_err());
-        assert!(PriorityOrder::new(&[
-            PriorityDimension::Cause,
-            PriorityDimension::Cause,
-            PriorityDimension::Time
-        ])
-        .is_err());
-
-        assert!(PriorityOrder::new(&[
-            PriorityDimension::Cause,
-            PriorityDimension::Time
-        ])
-        .is_ok());
-        assert_eq!(
-            PriorityOrder::new(&PriorityOrder::default().0).unwrap(),
-            PriorityOrder::default()
-        );
+    // A bundler writing into temporary directories, rather than the datasets
+    // of the sled's disks.
+    struct BundlerTestContext {
+        bundler: ZoneBundler,
+        storage_dirs: Vec<Utf8TempDir>,
+        zonepath: Utf8TempDir,
+        _expectations: Vec<Box<dyn std::any::Any>>,
     }
 
-    #[tokio::test]
-    async fn test_disk_usage() {
-        let path =
-            Utf8PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/src"));
-        let usage = disk_usage(&path).await.unwrap();
-        // Run `du -As /path/to/omicron/sled-agent/src`, which currently shows this
-        // directory is ~450 KiB.
-        assert!(
-            usage >= 1024 * 400,
-            "sled-agent manifest directory disk usage not correct?"
+    // Set up a bundler storing bundles in `n_storage_dirs` directories, which
+    // bundles zones as described in `expect_zones_to_bundle()`.
+    fn setup_bundler_test(
+        log: &Logger,
+        n_storage_dirs: usize,
+        spawn: impl FnMut(&mut std::process::Command) -> std::process::Child
+            + Send
+            + 'static,
+    ) -> BundlerTestContext {
+        let storage_dirs: Vec<_> = (0..n_storage_dirs)
+            .map(|_| camino_tempfile::tempdir().unwrap())
+            .collect();
+        let bundler = ZoneBundler::new(
+            log.clone(),
+            StorageResources::new_for_test(),
+            CleanupContext::default(),
+            Some(
+                storage_dirs.iter().map(|dir| dir.path().to_owned()).collect(),
+            ),
         );
-        let path = Utf8PathBuf::from("/some/nonexistent/path");
-        assert!(disk_usage(&path).await.is_err());
+        let zonepath = camino_tempfile::tempdir().unwrap();
+        let _expectations = expect_zones_to_bundle(zonepath.path(), spawn);
+        BundlerTestContext { bundler, storage_dirs, zonepath, _expectations }
     }
 
-    #[test]
-    fn test_storage_limit_bytes_available() {
-        let pct = StorageLimit(1);
-        assert_eq!(pct.bytes_available(100), 1);
-        assert_eq!(pct.bytes_available(1000), 10);
-
-        let pct = StorageLimit(100);
-        assert_eq!(pct.bytes_available(100), 100);
-        assert_eq!(pct.bytes_available(1000), 1000);
-
-        let pct = StorageLimit(100);
-        assert_eq!(pct.bytes_available(99), 99);
+    const INDEX_TEST_ZONE: &str = "oxz_whatever";
 
-        let pct = StorageLimit(99);
-        assert_eq!(pct.bytes_available(1), 0);
+    // Create a directory with two fake bundles and one file that isn't a
+    // bundle at all, for testing the bundle index.
+    async fn setup_index_test(
+        dir: &Utf8Path,
+    ) -> anyhow::Result<(Utf8PathBuf, Vec<ZoneBundleInfo>)> {
+        let mut info = Vec::new();
+        for day in [1, 2] {
+            info.push(
+                insert_fake_bundle_with_zone_name(
+                    dir,
+                    2020,
+                    1,
+                    day,
+                    ZoneBundleCause::ExplicitRequest,
+                    INDEX_TEST_ZONE,
+                )
+                .await?,
+            );
+        }
+        let zone_dir = dir.join(INDEX_TEST_ZONE);
+        tokio::fs::write(zone_dir.join("not-a-bundle"), b"detritus").await?;
+        Ok((zone_dir, info))
+    }
 
-        // Test non-power of 10.
-        let pct = StorageLimit(25);
-        assert_eq!(pct.bytes_available(32768), 8192);
+    fn expected_bundles(
+        info: &[ZoneBundleInfo],
+    ) -> BTreeMap<Utf8PathBuf, ZoneBundleMetadata> {
+        info.iter().map(|i| (i.path.clone(), i.metadata.clone())).collect()
     }
 
-    #[test]
-    fn test_compare_bundles() {
-        use PriorityDimension::*;
-        let time_first = PriorityOrder([Time, Cause]);
-        let cause_first = PriorityOrder([Cause, Time]);
+    #[tokio::test]
+    async fn test_zone_bundle_index_hit() {
+        let log = Logger::root(slog::Discard, slog::o!());
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let (zone_dir, info) = setup_index_test(tmpdir.path()).await.unwrap();
+
+        // The first listing has no index to use, and builds it.
+        let bundles =
+            filter_zone_bundles(&log, &zone_dir, |_| true).await.unwrap();
+        assert_eq!(bundles, expected_bundles(&info));
+        let index = read_zone_bundle_index(&log, &zone_dir)
+            .await
+            .expect("listing should have written an index");
+        assert_eq!(index.bundles.len(), 2);
+        assert_eq!(
+            index.invalid,
+            BTreeSet::from([String::from("not-a-bundle")])
+        );
 
-        fn make_info(
-            year: i32,
-            month: u32,
-            day: u32,
-            cause: ZoneBundleCause,
-        ) -> ZoneBundleInfo {
-            ZoneBundleInfo {
-                metadata: ZoneBundleMetadata {
-                    id: ZoneBundleId {
-                        zone_name: String::from("oxz_whatever"),
-                        bundle_id: uuid::Uuid::new_v4(),
-                    },
-                    time_created: Utc
-                        .with_ymd_and_hms(year, month, day, 0, 0, 0)
-                        .single()
-                        .unwrap(),
-                    cause,
-                    version: 0,
-                },
-                path: Utf8PathBuf::from("/some/path"),
-                bytes: 0,
-            }
+        // The hash of each bundle is recorded in the index.
+        for i in info.iter() {
+            let contents = tokio::fs::read(&i.path).await.unwrap();
+            let expected = hex::encode(Sha256::digest(&contents));
+            let indexed = &index.bundles[i.path.file_name().unwrap()];
+            assert_eq!(
+                indexed.content_hash.as_deref(),
+                Some(expected.as_str())
+            );
         }
 
-        let info = [
-            make_info(2020, 1, 2, ZoneBundleCause::TerminatedInstance),
-            make_info(2020, 1, 2, ZoneBundleCause::ExplicitRequest),
-            make_info(2020, 1, 1, ZoneBundleCause::TerminatedInstance),
-            make_info(2020, 1, 1, ZoneBundleCause::ExplicitRequest),
-        ];
+        // Clobber the contents of one bundle. The next listing should use the
+        // index rather than reading the bundle, so it should still be found.
+        tokio::fs::write(&info[0].path, b"garbage").await.unwrap();
+        let bundles =
+            filter_zone_bundles(&log, &zone_dir, |_| true).await.unwrap();
+        assert_eq!(bundles, expected_bundles(&info));
 
-        let mut sorted = info.clone();
-        sorted.sort_by(|lhs, rhs| time_first.compare_bundles(lhs, rhs));
-        // Low -> high priority
-        // [old/terminated, old/explicit, new/terminated, new/explicit]
-        let expected = [
-            info[2].clone(),
-            info[3].clone(),
-            info[0].clone(),
-            info[1].clone(),
-        ];
+        // Filters are applied to the indexed metadata.
+        let bundles = filter_zone_bundles(&log, &zone_dir, |md| {
+            md.id.bundle_id == info[1].metadata.id.bundle_id
+        })
+        .await
+        .unwrap();
+        assert_eq!(bundles, expected_bundles(&info[1..]));
+    }
+
+    #[tokio::test]
+    async fn test_zone_bundle_index_rebuilt_when_corrupt() {
+        let log = Logger::root(slog::Discard, slog::o!());
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let (zone_dir, info) = setup_index_test(tmpdir.path()).await.unwrap();
+        filter_zone_bundles(&log, &zone_dir, |_| true).await.unwrap();
+        let index = read_zone_bundle_index(&log, &zone_dir).await.unwrap();
+
+        let index_path = zone_dir.join(ZONE_BUNDLE_INDEX_FILENAME);
+        tokio::fs::write(&index_path, b"{ not json").await.unwrap();
+        assert!(read_zone_bundle_index(&log, &zone_dir).await.is_none());
+
+        // Listing falls back to scanning every bundle, and rewrites the index.
+        let bundles =
+            filter_zone_bundles(&log, &zone_dir, |_| true).await.unwrap();
+        assert_eq!(bundles, expected_bundles(&info));
         assert_eq!(
-            sorted, expected,
-            "sorting zone bundles by time-then-cause failed"
+            read_zone_bundle_index(&log, &zone_dir).await,
+            Some(index),
+            "index should have been rebuilt"
         );
+    }
 
-        let mut sorted = info.clone();
-        sorted.sort_by(|lhs, rhs| cause_first.compare_bundles(lhs, rhs));
-        // Low -> high priority
-        // [old/terminated, new/terminated, old/explicit, new/explicit]
-        let expected = [
-            info[2].clone(),
-            info[0].clone(),
-            info[3].clone(),
-            info[1].clone(),
-        ];
+    #[tokio::test]
+    async fn test_zone_bundle_index_stale_after_delete() {
+        let log = Logger::root(slog::Discard, slog::o!());
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let (zone_dir, info) = setup_index_test(tmpdir.path()).await.unwrap();
+        filter_zone_bundles(&log, &zone_dir, |_| true).await.unwrap();
+
+        // Remove a bundle out from under the index. The listing should notice
+        // that the index no longer matches the directory.
+        tokio::fs::remove_file(&info[0].path).await.unwrap();
+        let bundles =
+            filter_zone_bundles(&log, &zone_dir, |_| true).await.unwrap();
+        assert_eq!(bundles, expected_bundles(&info[1..]));
+
+        let index = read_zone_bundle_index(&log, &zone_dir).await.unwrap();
         assert_eq!(
-            sorted, expected,
-            "sorting zone bundles by cause-then-time failed"
+            index.bundles.keys().collect::<Vec<_>>(),
+            vec![info[1].path.file_name().unwrap()],
         );
     }
+
+    // Create a fake zone bundle in `dir`, containing only its metadata.
+    pub(super) async fn insert_fake_bundle_with_zone_name(
+        dir: &Utf8Path,
+        year: i32,
+        month: u32,
+        day: u32,
+        cause: ZoneBundleCause,
+        zone_name: &str,
+    ) -> anyhow::Result<ZoneBundleInfo> {
+        insert_fake_bundle_with_annotations(
+            dir,
+            year,
+            month,
+            day,
+            cause,
+            zone_name,
+            BTreeMap::new(),
+        )
+        .await
+    }
+
+    // Create a fake zone bundle in `dir` with the provided annotations,
+    // containing only its metadata.
+    async fn insert_fake_bundle_with_annotations(
+        dir: &Utf8Path,
+        year: i32,
+        month: u32,
+        day: u32,
+        cause: ZoneBundleCause,
+        zone_name: &str,
+        annotations: BTreeMap<String, String>,
+    ) -> anyhow::Result<ZoneBundleInfo> {
+        let metadata = ZoneBundleMetadata {
+            id: ZoneBundleId {
+                zone_name: String::from(zone_name),
+                bundle_id: uuid::Uuid::new_v4(),
+            },
+            time_created: Utc
+                .with_ymd_and_hms(year, month, day, 0, 0, 0)
+                .single()
+                .context("invalid year/month/day")?,
+            cause,
+            version: 0,
+            content_hash: None,
+            annotations,
+            zone_wide_commands: Vec::new(),
+            detail: BundleDetailLevel::Full,
+        };
+
+        let zone_dir = dir.join(&metadata.id.zone_name);
+        tokio::fs::create_dir_all(&zone_dir)
+            .await
+            .context("failed to create zone directory")?;
+        let path = zone_dir.join(format!("{}.tar.gz", metadata.id.bundle_id));
+
+        // Create a tarball at the path with this fake metadata.
+        let file = tokio::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(&path)
+            .await
+            .context("failed to open zone bundle path")?
+            .into_std()
+            .await;
+        let gz = flate2::GzBuilder::new()
+            .filename(path.as_str())
+            .write(file, flate2::Compression::best());
+        let mut builder = tar::Builder::new(gz);
+        let contents = toml::to_string(&metadata)?;
+        super::insert_data(
+            &mut builder,
+            super::ZONE_BUNDLE_METADATA_FILENAME,
+            contents.as_bytes(),
+        )?;
+        let _ = builder.into_inner().context("failed to finish tarball")?;
+        let bytes = tokio::fs::metadata(&path).await?.len();
+
+        // Record the hash of the finished bundle, as `create` does.
+        let metadata = ZoneBundleMetadata {
+            content_hash: Some(super::compute_content_hash(&path).await?),
+            ..metadata
+        };
+        Ok(ZoneBundleInfo { metadata, path, bytes })
+    }
 }
 
 #[cfg(all(target_os = "illumos", test))]
 mod illumos_tests {
     use super::find_archived_log_files;
+    use super::insert_process_command_outputs;
+    use super::tests::expect_zones_to_bundle;
+    use super::tests::fake_command;
+    use super::tests::insert_fake_bundle_with_zone_name;
+    use super::tests::read_bundle_file;
     use super::zfs_quota;
+    use super::BundleDetailLevel;
+    use super::BundleError;
     use super::CleanupContext;
     use super::CleanupPeriod;
     use super::PriorityOrder;
@@ -1783,16 +7252,19 @@ mod illumos_tests {
     use super::Utf8PathBuf;
     use super::Uuid;
     use super::ZoneBundleCause;
-    use super::ZoneBundleId;
     use super::ZoneBundleInfo;
-    use super::ZoneBundleMetadata;
     use super::ZoneBundler;
     use super::ZFS;
     use anyhow::Context;
-    use chrono::TimeZone;
-    use chrono::Utc;
+    use illumos_utils::zone::MockZones;
+    use illumos_utils::zone::ZLOGIN;
+    use oximeter::types::Datum;
+    use oximeter::types::ProducerRegistry;
+    use oximeter::types::ProducerResultsItem;
     use slog::Drain;
     use slog::Logger;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
     use tokio::process::Command;
 
     #[tokio::test]
@@ -1874,8 +7346,12 @@ mod illumos_tests {
         let log = test_logger();
         let context = CleanupContext::default();
         let resource_wrapper = ResourceWrapper::new().await;
-        let bundler =
-            ZoneBundler::new(log, resource_wrapper.resources.clone(), context);
+        let bundler = ZoneBundler::new(
+            log,
+            resource_wrapper.resources.clone(),
+            context,
+            None,
+        );
         Ok(CleanupTestContext { resource_wrapper, context, bundler })
     }
 
@@ -1897,12 +7373,16 @@ mod illumos_tests {
                 &ctx.context.priority.iter().copied().rev().collect::<Vec<_>>(),
             )
             .unwrap(),
+            min_keep_per_zone: ctx.context.min_keep_per_zone + 1,
+            recompress_when_idle: !ctx.context.recompress_when_idle,
         };
         ctx.bundler
             .update_cleanup_context(
                 Some(new_context.period),
                 Some(new_context.storage_limit),
                 Some(new_context.priority),
+                Some(new_context.min_keep_per_zone),
+                Some(new_context.recompress_when_idle),
             )
             .await
             .expect("failed to set context");
@@ -1988,7 +7468,7 @@ mod illumos_tests {
     async fn test_utilization_body(
         ctx: CleanupTestContext,
     ) -> anyhow::Result<()> {
-        let utilization = ctx.bundler.utilization().await?;
+        let utilization = ctx.bundler.utilization().await?.result;
         let paths = utilization.keys().cloned().collect::<Vec<_>>();
 
         // Check that we've looked at all the paths in the context.
@@ -2022,6 +7502,19 @@ mod illumos_tests {
             "there should be basically zero bytes used"
         );
 
+        // The total covers every directory, with none missing.
+        let total = ctx.bundler.total_utilization().await?;
+        anyhow::ensure!(
+            total.directory_errors.is_empty(),
+            "unexpected directory errors: {:?}",
+            total.directory_errors,
+        );
+        anyhow::ensure!(
+            total.result.dataset_quota
+                == utilization.values().map(|u| u.dataset_quota).sum::<u64>(),
+            "total utilization doesn't cover every directory"
+        );
+
         // Now let's add a fake bundle, and make sure that we get the right size
         // back.
         let info = insert_fake_bundle(
@@ -2033,7 +7526,7 @@ mod illumos_tests {
         )
         .await?;
 
-        let new_utilization = ctx.bundler.utilization().await?;
+        let new_utilization = ctx.bundler.utilization().await?.result;
         anyhow::ensure!(
             paths == new_utilization.keys().cloned().collect::<Vec<_>>(),
             "paths should not change"
@@ -2095,13 +7588,19 @@ mod illumos_tests {
         // First, reduce the storage limit, so that we only need to add a few
         // bundles.
         ctx.bundler
-            .update_cleanup_context(None, Some(StorageLimit(2)), None)
+            .update_cleanup_context(
+                None,
+                Some(StorageLimit(2)),
+                None,
+                None,
+                None,
+            )
             .await
             .context("failed to update cleanup context")?;
 
         let mut day = 1;
         let mut info = Vec::new();
-        let mut utilization = ctx.bundler.utilization().await?;
+        let mut utilization = ctx.bundler.utilization().await?.result;
         loop {
             let us = utilization
                 .values()
@@ -2120,12 +7619,16 @@ mod illumos_tests {
             .await?;
             day += 1;
             info.push(it);
-            utilization = ctx.bundler.utilization().await?;
+            utilization = ctx.bundler.utilization().await?.result;
         }
 
         // Trigger a cleanup.
-        let counts =
-            ctx.bundler.cleanup().await.context("failed to run cleanup")?;
+        let counts = ctx
+            .bundler
+            .cleanup()
+            .await
+            .context("failed to run cleanup")?
+            .result;
 
         // We should have cleaned up items in the same paths that we have in the
         // context.
@@ -2158,6 +7661,180 @@ mod illumos_tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_cleanup_on_start() {
+        run_test_with_zfs_dataset(test_cleanup_on_start_body).await;
+    }
+
+    async fn test_cleanup_on_start_body(
+        ctx: CleanupTestContext,
+    ) -> anyhow::Result<()> {
+        // Add fake bundles until we're over a reduced storage limit, as in
+        // `test_cleanup`. The default period is long enough that the bundler
+        // won't clean them up during the test.
+        ctx.bundler
+            .update_cleanup_context(
+                None,
+                Some(StorageLimit(2)),
+                None,
+                None,
+                None,
+            )
+            .await
+            .context("failed to update cleanup context")?;
+        let mut day = 1;
+        let mut utilization = ctx.bundler.utilization().await?.result;
+        loop {
+            let us = utilization
+                .values()
+                .next()
+                .context("no utilization information")?;
+            if us.bytes_used > us.bytes_available {
+                break;
+            }
+            insert_fake_bundle(
+                &ctx.resource_wrapper.dirs[0],
+                2020,
+                1,
+                day,
+                ZoneBundleCause::ExplicitRequest,
+            )
+            .await?;
+            day += 1;
+            utilization = ctx.bundler.utilization().await?.result;
+        }
+
+        // Start a new bundler over the same, over-quota, directories, which
+        // should reclaim space well before its first period elapses.
+        let context =
+            CleanupContext { storage_limit: StorageLimit(2), ..ctx.context };
+        let bundler = ZoneBundler::new(
+            test_logger(),
+            ctx.resource_wrapper.resources.clone(),
+            context,
+            None,
+        );
+        bundler.cleanup_on_start().await;
+        const TIMEOUT: Duration = Duration::from_secs(30);
+        anyhow::ensure!(
+            TIMEOUT < context.period.as_duration(),
+            "the timeout must be shorter than the cleanup period",
+        );
+        let start = std::time::Instant::now();
+        loop {
+            let utilization = bundler.utilization().await?.result;
+            let us = utilization
+                .values()
+                .next()
+                .context("no utilization information")?;
+            if us.bytes_used <= us.bytes_available {
+                break;
+            }
+            anyhow::ensure!(
+                start.elapsed() < TIMEOUT,
+                "space was not reclaimed after starting the bundler",
+            );
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_estimate_cleanup() {
+        run_test_with_zfs_dataset(test_estimate_cleanup_body).await;
+    }
+
+    async fn test_estimate_cleanup_body(
+        ctx: CleanupTestContext,
+    ) -> anyhow::Result<()> {
+        // Add fake bundles until we're over a reduced storage limit, as in
+        // `test_cleanup`.
+        ctx.bundler
+            .update_cleanup_context(
+                None,
+                Some(StorageLimit(2)),
+                None,
+                None,
+                None,
+            )
+            .await
+            .context("failed to update cleanup context")?;
+        let mut day = 1;
+        let mut info = Vec::new();
+        let mut utilization = ctx.bundler.utilization().await?.result;
+        loop {
+            let us = utilization
+                .values()
+                .next()
+                .context("no utilization information")?;
+            if us.bytes_used > us.bytes_available {
+                break;
+            }
+            let it = insert_fake_bundle(
+                &ctx.resource_wrapper.dirs[0],
+                2020,
+                1,
+                day,
+                ZoneBundleCause::ExplicitRequest,
+            )
+            .await?;
+            day += 1;
+            info.push(it);
+            utilization = ctx.bundler.utilization().await?.result;
+        }
+
+        // The estimate examines every bundle, but must not remove any.
+        let estimate = ctx
+            .bundler
+            .estimate_cleanup()
+            .await
+            .context("failed to estimate cleanup")?;
+        anyhow::ensure!(
+            estimate.bundles_examined == info.len() as u64,
+            "expected the estimate to examine every bundle",
+        );
+        anyhow::ensure!(
+            estimate.bytes_examined
+                == info.iter().map(|each| each.bytes).sum::<u64>(),
+            "expected the estimate to examine the bytes of every bundle",
+        );
+        for each in info.iter() {
+            let exists = tokio::fs::try_exists(&each.path)
+                .await
+                .context("failed to check if file exists")?;
+            anyhow::ensure!(exists, "estimating cleanup removed a bundle");
+        }
+
+        // A real cleanup should then remove exactly what was estimated.
+        let counts = ctx
+            .bundler
+            .cleanup()
+            .await
+            .context("failed to run cleanup")?
+            .result;
+        let bundles: u64 = counts.values().map(|count| count.bundles).sum();
+        let bytes: u64 = counts.values().map(|count| count.bytes).sum();
+        anyhow::ensure!(
+            estimate.bundles_to_remove > 0,
+            "expected the estimate to remove some bundles",
+        );
+        anyhow::ensure!(
+            estimate.bundles_to_remove == bundles,
+            "estimated removing {} bundles, but cleanup removed {}",
+            estimate.bundles_to_remove,
+            bundles,
+        );
+        anyhow::ensure!(
+            estimate.bytes_to_remove == bytes,
+            "estimated removing {} bytes, but cleanup removed {}",
+            estimate.bytes_to_remove,
+            bytes,
+        );
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_list_with_filter() {
         run_test_with_zfs_dataset(test_list_with_filter_body).await;
@@ -2184,7 +7861,7 @@ mod illumos_tests {
         }
 
         // Listing with no filter should return all of them.
-        let all_md = ctx.bundler.list(None).await?;
+        let all_md = ctx.bundler.list(None, None).await?.result;
         anyhow::ensure!(
             all_md
                 == info
@@ -2199,7 +7876,7 @@ mod illumos_tests {
         // So filters like `oxz_` should return all of them, while ones on the
         // index should return exactly that one matching.
         let filt = Some("oxz_");
-        let all_md = ctx.bundler.list(filt).await?;
+        let all_md = ctx.bundler.list(filt, None).await?.result;
         anyhow::ensure!(
             all_md
                 == info
@@ -2210,7 +7887,8 @@ mod illumos_tests {
         );
         for i in 0..N_BUNDLES {
             let filt = Some(i.to_string());
-            let matching_md = ctx.bundler.list(filt.as_deref()).await?;
+            let matching_md =
+                ctx.bundler.list(filt.as_deref(), None).await?.result;
             let expected_md = &info[i].metadata;
             anyhow::ensure!(
                 matching_md.len() == 1,
@@ -2242,58 +7920,6 @@ mod illumos_tests {
         .await
     }
 
-    async fn insert_fake_bundle_with_zone_name(
-        dir: &Utf8Path,
-        year: i32,
-        month: u32,
-        day: u32,
-        cause: ZoneBundleCause,
-        zone_name: &str,
-    ) -> anyhow::Result<ZoneBundleInfo> {
-        let metadata = ZoneBundleMetadata {
-            id: ZoneBundleId {
-                zone_name: String::from(zone_name),
-                bundle_id: uuid::Uuid::new_v4(),
-            },
-            time_created: Utc
-                .with_ymd_and_hms(year, month, day, 0, 0, 0)
-                .single()
-                .context("invalid year/month/day")?,
-            cause,
-            version: 0,
-        };
-
-        let zone_dir = dir.join(&metadata.id.zone_name);
-        tokio::fs::create_dir_all(&zone_dir)
-            .await
-            .context("failed to create zone directory")?;
-        let path = zone_dir.join(format!("{}.tar.gz", metadata.id.bundle_id));
-
-        // Create a tarball at the path with this fake metadata.
-        let file = tokio::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(&path)
-            .await
-            .context("failed to open zone bundle path")?
-            .into_std()
-            .await;
-        let gz = flate2::GzBuilder::new()
-            .filename(path.as_str())
-            .write(file, flate2::Compression::best());
-        let mut builder = tar::Builder::new(gz);
-        let contents = toml::to_string(&metadata)?;
-        super::insert_data(
-            &mut builder,
-            super::ZONE_BUNDLE_METADATA_FILENAME,
-            contents.as_bytes(),
-        )?;
-        let _ = builder.into_inner().context("failed to finish tarball")?;
-        let bytes = tokio::fs::metadata(&path).await?.len();
-        Ok(ZoneBundleInfo { metadata, path, bytes })
-    }
-
     #[tokio::test]
     async fn test_find_archived_log_files() {
         let log = test_logger();
@@ -2338,4 +7964,271 @@ mod illumos_tests {
             .zip(should_match.iter())
             .all(|(file, name)| { file.file_name().unwrap() == *name }));
     }
+
+    #[tokio::test]
+    async fn test_process_commands_include_ptree() {
+        let log = test_logger();
+        let tmpdir = camino_tempfile::tempdir().unwrap();
+        let path = tmpdir.path().join("bundle.tar.gz");
+
+        // Run the per-process commands on this test process, in the global
+        // zone, in place of a service process in a real zone.
+        let pid = std::process::id();
+        let file = std::fs::File::create(&path).unwrap();
+        let gz =
+            flate2::GzBuilder::new().write(file, flate2::Compression::best());
+        let mut builder = tar::Builder::new(gz);
+        insert_process_command_outputs(
+            &log,
+            "global",
+            &mut builder,
+            pid,
+            |args| async move {
+                let output = std::process::Command::new(&args[0])
+                    .args(&args[1..])
+                    .output()
+                    .expect("failed to run process command");
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            },
+        )
+        .await;
+        builder.into_inner().unwrap().finish().unwrap();
+
+        // The bundle should contain this process's subtree, filed by PID.
+        let file = std::fs::File::open(&path).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        let expected = format!("ptree.{pid}");
+        let mut entry = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .find(|entry| {
+                entry.path().unwrap() == std::path::Path::new(&expected)
+            })
+            .unwrap_or_else(|| panic!("bundle is missing {expected}"));
+        let mut contents = String::new();
+        std::io::Read::read_to_string(&mut entry, &mut contents).unwrap();
+        assert!(
+            contents.contains(&pid.to_string()),
+            "ptree output doesn't mention process {pid}: {contents}"
+        );
+    }
+
+    // Name of a zone that exists at the OS level, but isn't managed.
+    const UNMANAGED_ZONE_NAME: &str = "oxz_unmanaged";
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_create_by_name_for_unmanaged_zone() {
+        run_test_with_zfs_dataset(test_create_by_name_for_unmanaged_zone_body)
+            .await;
+    }
+
+    async fn test_create_by_name_for_unmanaged_zone_body(
+        ctx: CleanupTestContext,
+    ) -> anyhow::Result<()> {
+        // Stub out a zone the OS knows about, with a zonepath containing a
+        // service log file. Commands are run in the zone through `zlogin`.
+        let zonepath = camino_tempfile::tempdir()?;
+        let log_dir =
+            zonepath.path().join(UNMANAGED_ZONE_NAME).join("root/var/svc/log");
+        std::fs::create_dir_all(&log_dir)?;
+        let log_file = "oxide-fake:default.log";
+        std::fs::write(log_dir.join(log_file), "fake log contents")?;
+        let _expectations =
+            expect_zones_to_bundle(zonepath.path(), |command| {
+                let args: Vec<_> = command.get_args().collect();
+                assert_eq!(args[0], std::ffi::OsStr::new(ZLOGIN));
+                assert_eq!(args[1], std::ffi::OsStr::new(UNMANAGED_ZONE_NAME));
+                fake_command("fake command output", Duration::ZERO)
+            });
+
+        let info = ctx
+            .bundler
+            .create_by_name(
+                UNMANAGED_ZONE_NAME,
+                ZoneBundleCause::ExplicitRequest,
+                BTreeMap::new(),
+                BundleDetailLevel::Full,
+            )
+            .await
+            .context("failed to bundle unmanaged zone")?;
+        assert_eq!(info.id.zone_name, UNMANAGED_ZONE_NAME);
+
+        // The bundle should contain the zone-wide commands and the log file.
+        let uptime = ctx
+            .bundler
+            .extract_file(UNMANAGED_ZONE_NAME, &info.id.bundle_id, "uptime")
+            .await?;
+        let uptime = read_bundle_file(uptime).await;
+        assert!(String::from_utf8(uptime)?.contains("fake command output"));
+        let log = ctx
+            .bundler
+            .extract_file(UNMANAGED_ZONE_NAME, &info.id.bundle_id, log_file)
+            .await?;
+        assert_eq!(read_bundle_file(log).await, b"fake log contents");
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_create_by_name_for_nonexistent_zone() {
+        run_test_with_zfs_dataset(
+            test_create_by_name_for_nonexistent_zone_body,
+        )
+        .await;
+    }
+
+    async fn test_create_by_name_for_nonexistent_zone_body(
+        ctx: CleanupTestContext,
+    ) -> anyhow::Result<()> {
+        let zonepath_ctx = MockZones::zonepath_context();
+        zonepath_ctx.expect().returning(|_| Ok(None));
+        let err = ctx
+            .bundler
+            .create_by_name(
+                UNMANAGED_ZONE_NAME,
+                ZoneBundleCause::ExplicitRequest,
+                BTreeMap::new(),
+                BundleDetailLevel::Full,
+            )
+            .await
+            .expect_err("bundled a zone the OS doesn't know about");
+        match err {
+            BundleError::NoSuchZone { name } => {
+                assert_eq!(name, UNMANAGED_ZONE_NAME)
+            }
+            _ => panic!("expected NoSuchZone, found: {err:?}"),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_custom_zone_wide_command_is_captured() {
+        run_test_with_zfs_dataset(
+            test_custom_zone_wide_command_is_captured_body,
+        )
+        .await;
+    }
+
+    async fn test_custom_zone_wide_command_is_captured_body(
+        ctx: CleanupTestContext,
+    ) -> anyhow::Result<()> {
+        let commands = vec![vec![String::from("arp"), String::from("-an")]];
+        ctx.bundler.set_zone_wide_commands(commands.clone()).await?;
+        assert_eq!(ctx.bundler.zone_wide_commands().await, commands);
+
+        // Bundle a zone that only exists at the OS level, where commands are
+        // run through `zlogin`, so we can supply the command's output.
+        let zonepath = camino_tempfile::tempdir()?;
+        let _expectations =
+            expect_zones_to_bundle(zonepath.path(), |command| {
+                let args: Vec<_> = command.get_args().skip(2).collect();
+                assert_eq!(args, ["arp", "-an"], "ran an unexpected command");
+                fake_command("fake arp table", Duration::ZERO)
+            });
+
+        let info = ctx
+            .bundler
+            .create_by_name(
+                UNMANAGED_ZONE_NAME,
+                ZoneBundleCause::ExplicitRequest,
+                BTreeMap::new(),
+                BundleDetailLevel::Full,
+            )
+            .await
+            .context("failed to create bundle")?;
+        assert_eq!(info.zone_wide_commands, commands);
+        let arp = ctx
+            .bundler
+            .extract_file(UNMANAGED_ZONE_NAME, &info.id.bundle_id, "arp")
+            .await?;
+        let arp = read_bundle_file(arp).await;
+        assert!(String::from_utf8(arp)?.contains("fake arp table"));
+
+        // The default commands were not run.
+        let err = ctx
+            .bundler
+            .extract_file(UNMANAGED_ZONE_NAME, &info.id.bundle_id, "uptime")
+            .await
+            .expect_err("bundle should not contain default commands");
+        assert!(matches!(err, BundleError::NoSuchBundleEntry { .. }));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_zone_wide_command_is_rejected() {
+        let ctx = setup_fake_cleanup_task().await.unwrap();
+        let defaults = ctx.bundler.zone_wide_commands().await;
+        let err = ctx
+            .bundler
+            .set_zone_wide_commands(vec![vec![String::from("rm")]])
+            .await
+            .expect_err("set a disallowed command");
+        assert!(matches!(err, BundleError::DisallowedCommand { .. }));
+        assert_eq!(ctx.bundler.zone_wide_commands().await, defaults);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn test_bundle_creation_produces_metrics() {
+        run_test_with_zfs_dataset(test_bundle_creation_produces_metrics_body)
+            .await;
+    }
+
+    async fn test_bundle_creation_produces_metrics_body(
+        ctx: CleanupTestContext,
+    ) -> anyhow::Result<()> {
+        let registry = ProducerRegistry::new();
+        ctx.bundler.register_metrics(&registry)?;
+
+        let zonepath = camino_tempfile::tempdir()?;
+        let _expectations = expect_zones_to_bundle(zonepath.path(), |_| {
+            fake_command("fake command output", Duration::ZERO)
+        });
+
+        let before = std::time::Instant::now();
+        ctx.bundler
+            .create_by_name(
+                UNMANAGED_ZONE_NAME,
+                ZoneBundleCause::ExplicitRequest,
+                BTreeMap::new(),
+                BundleDetailLevel::Full,
+            )
+            .await
+            .context("failed to create bundle")?;
+        let elapsed = before.elapsed();
+
+        let samples: Vec<_> = registry
+            .collect()
+            .into_iter()
+            .flat_map(|item| match item {
+                ProducerResultsItem::Ok(samples) => samples,
+                ProducerResultsItem::Err(e) => {
+                    panic!("failed to produce samples: {e}")
+                }
+            })
+            .collect();
+        let duration = samples
+            .iter()
+            .find(|s| s.timeseries_name == "zone_bundle:creation_duration")
+            .expect("no sample for bundle creation duration");
+        let Datum::F64(seconds) = duration.measurement.datum() else {
+            panic!("unexpected datum: {:?}", duration.measurement.datum());
+        };
+        assert!(
+            *seconds > 0.0 && *seconds <= elapsed.as_secs_f64(),
+            "implausible bundle creation duration: {seconds}s",
+        );
+        let size = samples
+            .iter()
+            .find(|s| s.timeseries_name == "zone_bundle:size")
+            .expect("no sample for bundle size");
+        let Datum::U64(bytes) = size.measurement.datum() else {
+            panic!("unexpected datum: {:?}", size.measurement.datum());
+        };
+        assert!(*bytes > 0);
+        Ok(())
+    }
 }
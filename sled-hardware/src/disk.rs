@@ -646,6 +646,21 @@ impl Disk {
     pub fn slot(&self) -> i64 {
         self.slot
     }
+
+    /// Returns the host boot flash slot paired with this disk, if it's an M.2.
+    ///
+    /// The host loads its phase 2 image from the M.2 in the same slot (A or
+    /// B) as the phase 1 image it booted from flash, so the boot disk's slot
+    /// tells us which flash slot the host booted.
+    pub fn host_boot_slot(&self) -> Option<u16> {
+        match (self.variant, self.slot) {
+            // These are the M.2 slots 0x11 and 0x12, as described in the
+            // illumos hardware monitor's `slot_to_disk_variant`.
+            (DiskVariant::M2, 0x11) => Some(0),
+            (DiskVariant::M2, 0x12) => Some(1),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
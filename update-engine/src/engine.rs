@@ -1295,4 +1295,71 @@ mod tests {
 
         logctx.cleanup_successful();
     }
+
+    #[tokio::test]
+    async fn abort_interrupts_waiting_step() {
+        let logctx = test_setup_log("abort_interrupts_waiting_step");
+
+        // Make a buffer big enough that the engine can never fill it up.
+        let (sender, receiver) = mpsc::channel(512);
+        let engine: UpdateEngine<TestSpec> =
+            UpdateEngine::new(&logctx.log, sender);
+
+        // A step that waits for a signal that never arrives, while
+        // periodically polling for something else. This is the shape of steps
+        // like wicketd's wait for the installinator to start, which have no
+        // explicit abort branch: aborting the engine must still interrupt
+        // them.
+        let (_start_sender, mut start_receiver) = oneshot::channel::<()>();
+        engine
+            .new_step("foo".to_owned(), 0, "Waiting to start", |_| async move {
+                let mut interval =
+                    tokio::time::interval(std::time::Duration::from_millis(10));
+                loop {
+                    tokio::select! {
+                        _ = &mut start_receiver => break,
+                        _ = interval.tick() => {}
+                    }
+                }
+                StepSuccess::new(()).into()
+            })
+            .register();
+
+        let execution = engine.execute();
+        let abort_handle = execution.abort_handle();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            abort_handle
+                .abort("test abort")
+                .expect("engine is still running")
+                .await;
+        });
+
+        let result =
+            tokio::time::timeout(std::time::Duration::from_secs(30), execution)
+                .await
+                .expect("engine exited promptly after being aborted");
+        match result {
+            Err(ExecutionError::Aborted { component, message, .. }) => {
+                assert_eq!(component, "foo");
+                assert_eq!(message, "test abort");
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        let events: Vec<_> = ReceiverStream::new(receiver).collect().await;
+        let last_event = events.last().unwrap();
+        assert!(
+            matches!(
+                last_event,
+                Event::Step(StepEvent {
+                    kind: StepEventKind::ExecutionAborted { .. },
+                    ..
+                })
+            ),
+            "event didn't match: {last_event:?}"
+        );
+
+        logctx.cleanup_successful();
+    }
 }
@@ -11,6 +11,7 @@ use serde::Deserialize;
 use serde::Serialize;
 use std::collections::BTreeSet;
 use std::net::IpAddr;
+use std::net::Ipv6Addr;
 
 // The portion of `CurrentRssUserConfig` that can be posted in one shot; it is
 // provided by the wicket user uploading a TOML file, currently.
@@ -28,4 +29,152 @@ pub struct PutRssUserConfigInsensitive {
     pub external_dns_ips: Vec<IpAddr>,
     pub external_dns_zone_name: String,
     pub rack_network_config: RackNetworkConfig,
+
+    /// The rack's IPv6 subnet.
+    ///
+    /// If not provided, RSS uses its default rack subnet.
+    pub rack_subnet: Option<Ipv6Addr>,
+}
+
+impl PutRssUserConfigInsensitive {
+    /// Checks that every external DNS IP is within one of the internal
+    /// services IP pool ranges, from which RSS allocates the external DNS
+    /// zones' addresses.
+    ///
+    /// On failure, the error message lists every external DNS IP that is not
+    /// in the pool.
+    pub fn validate_external_dns_ips(&self) -> Result<(), String> {
+        let outside_pool: Vec<_> = self
+            .external_dns_ips
+            .iter()
+            .filter(|ip| {
+                !self
+                    .internal_services_ip_pool_ranges
+                    .iter()
+                    .any(|range| range.contains(**ip))
+            })
+            .map(|ip| ip.to_string())
+            .collect();
+
+        if outside_pool.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "external DNS IPs must be within an internal services IP pool \
+                 range; not in any range: {}",
+                outside_pool.join(", ")
+            ))
+        }
+    }
+
+    /// Checks that the rack subnet, if provided, is usable by RSS.
+    pub fn validate_rack_subnet(&self) -> Result<(), String> {
+        match self.rack_subnet {
+            Some(subnet) => validate_rack_subnet(subnet),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Checks that `subnet` is a valid rack subnet: a unique local (`fd00::/8`)
+/// address that is the first address of its /56 rack prefix.
+pub fn validate_rack_subnet(subnet: Ipv6Addr) -> Result<(), String> {
+    if subnet.octets()[0] != 0xfd {
+        return Err(format!(
+            "rack subnet {subnet} must be a unique local address in fd00::/8"
+        ));
+    }
+    let prefix = address::Ipv6Subnet::<{ address::RACK_PREFIX }>::new(subnet)
+        .net()
+        .network();
+    if prefix != subnet {
+        return Err(format!(
+            "rack subnet {subnet} must be the start of a /{} prefix \
+             (e.g., {prefix})",
+            address::RACK_PREFIX
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with(
+        ranges: &[(&str, &str)],
+        external_dns_ips: &[&str],
+    ) -> PutRssUserConfigInsensitive {
+        PutRssUserConfigInsensitive {
+            bootstrap_sleds: BTreeSet::new(),
+            ntp_servers: Vec::new(),
+            dns_servers: Vec::new(),
+            internal_services_ip_pool_ranges: ranges
+                .iter()
+                .map(|(first, last)| {
+                    address::IpRange::try_from((
+                        first.parse::<IpAddr>().unwrap(),
+                        last.parse::<IpAddr>().unwrap(),
+                    ))
+                    .unwrap()
+                })
+                .collect(),
+            external_dns_ips: external_dns_ips
+                .iter()
+                .map(|ip| ip.parse().unwrap())
+                .collect(),
+            external_dns_zone_name: "oxide.test".into(),
+            rack_network_config: RackNetworkConfig {
+                infra_ip_first: "172.30.0.1".parse().unwrap(),
+                infra_ip_last: "172.30.0.10".parse().unwrap(),
+                uplinks: Vec::new(),
+            },
+            rack_subnet: None,
+        }
+    }
+
+    #[test]
+    fn rack_subnet_validation() {
+        let mut config = config_with(&[], &[]);
+        assert_eq!(config.validate_rack_subnet(), Ok(()));
+
+        config.rack_subnet = Some("fd00:1122:3344:0100::".parse().unwrap());
+        assert_eq!(config.validate_rack_subnet(), Ok(()));
+
+        // Not a unique local address.
+        config.rack_subnet = Some("2001:db8:0:100::".parse().unwrap());
+        assert!(config.validate_rack_subnet().is_err());
+
+        // Not the start of a /56.
+        config.rack_subnet = Some("fd00:1122:3344:0101::".parse().unwrap());
+        assert_eq!(
+            config.validate_rack_subnet(),
+            Err("rack subnet fd00:1122:3344:101:: must be the start of a /56 \
+                 prefix (e.g., fd00:1122:3344:100::)"
+                .to_string()),
+        );
+    }
+
+    #[test]
+    fn external_dns_ips_in_pool() {
+        let config = config_with(
+            &[("10.0.0.1", "10.0.0.5"), ("fd00::1", "fd00::10")],
+            &["10.0.0.1", "10.0.0.5", "fd00::8"],
+        );
+        assert_eq!(config.validate_external_dns_ips(), Ok(()));
+    }
+
+    #[test]
+    fn external_dns_ips_outside_pool() {
+        let config = config_with(
+            &[("10.0.0.1", "10.0.0.5")],
+            &["10.0.0.3", "10.0.0.6", "fd00::1"],
+        );
+        assert_eq!(
+            config.validate_external_dns_ips(),
+            Err("external DNS IPs must be within an internal services IP \
+                 pool range; not in any range: 10.0.0.6, fd00::1"
+                .to_string()),
+        );
+    }
 }
@@ -5,11 +5,14 @@
 // Copyright 2023 Oxide Computer Company
 
 use gateway_client::types::PowerState;
+use omicron_common::update::ArtifactHash;
 use omicron_common::update::ArtifactId;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 use std::fmt;
+use std::net::SocketAddrV6;
+use std::time::Duration;
 use thiserror::Error;
 use update_engine::errors::NestedEngineError;
 use update_engine::StepSpec;
@@ -40,9 +43,11 @@ pub enum UpdateComponent {
 #[serde(tag = "id", rename_all = "snake_case")]
 pub enum UpdateStepId {
     TestStep,
+    WaitingForPreviousUpdateGroup,
     SetHostPowerState { state: PowerState },
     InterrogateRot,
     InterrogateSp,
+    CheckingComponentsUpToDate,
     SpComponentUpdate,
     SettingInstallinatorImageId,
     ClearingInstallinatorImageId,
@@ -50,6 +55,8 @@ pub enum UpdateStepId {
     WaitingForTrampolinePhase2Upload,
     DownloadingInstallinator,
     RunningInstallinator,
+    WaitingForOperatorConfirmation,
+    VerifyingHostBootSlot,
 }
 
 impl StepSpec for WicketdEngineSpec {
@@ -123,19 +130,41 @@ impl StepSpec for SpComponentUpdateSpec {
     type Error = SpComponentUpdateTerminalError;
 }
 
+/// Progress metadata reported while an SP component update is being written.
+#[derive(
+    Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, JsonSchema,
+)]
+pub struct SpComponentWriteProgress {
+    /// The estimated number of seconds remaining until the write completes,
+    /// based on a moving average of the recent write rate.
+    ///
+    /// This is `None` until enough progress has been observed to compute a
+    /// rate.
+    pub estimated_seconds_remaining: Option<u64>,
+}
+
+/// Progress metadata reported while a sled's SP fetches the trampoline phase 2
+/// image from MGS.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TrampolinePhase2Progress {
+    /// The address of the MGS instance serving the image.
+    ///
+    /// The SP may fetch the image from either scrimlet's MGS, but wicketd only
+    /// sees progress (and reports it) when the image is being served by the
+    /// MGS wicketd talks to.
+    pub serving_mgs: SocketAddrV6,
+}
+
 #[derive(Debug, Error)]
 pub enum UpdateTerminalError {
     #[error("updating power state failed")]
     UpdatePowerStateFailed {
-        // Note that this is an HTTP error, which currently prints out the
-        // entire cause chain. This doesn't obey the usual Rust rules about
-        // printing out the chain in the Display section, but just accept this
-        // for now.
-        //
-        // This is true for all the other gateway_client::Errors in this section
-        // as well.
+        // Errors from MGS are flattened (by wicketd) into a message carrying
+        // their entire cause chain, so that every one of them is reported in
+        // full. This is true for all the other MGS errors in this section as
+        // well.
         #[source]
-        error: gateway_client::Error<gateway_client::types::Error>,
+        error: anyhow::Error,
     },
     #[error("getting currently-active RoT slot failed")]
     GetRotActiveSlotFailed {
@@ -157,47 +186,85 @@ pub enum UpdateTerminalError {
     #[error("getting RoT caboose failed")]
     GetRotCabooseFailed {
         #[source]
-        error: gateway_client::Error<gateway_client::types::Error>,
+        error: anyhow::Error,
     },
     #[error("getting SP caboose failed")]
     GetSpCabooseFailed {
         #[source]
-        error: gateway_client::Error<gateway_client::types::Error>,
+        error: anyhow::Error,
+    },
+    #[error("SP active version {version:?} is not a valid version")]
+    UnparseableSpVersion {
+        version: String,
+        #[source]
+        error: anyhow::Error,
     },
     #[error("TUF repository missing SP image for board {board}")]
     MissingSpImageForBoard { board: String },
     #[error("setting installinator image ID failed")]
     SetInstallinatorImageIdFailed {
         #[source]
-        error: gateway_client::Error<gateway_client::types::Error>,
+        error: anyhow::Error,
     },
     #[error("setting host boot flash slot failed")]
     SetHostBootFlashSlotFailed {
         #[source]
         error: anyhow::Error,
     },
+    #[error("getting the boot flash slot the host booted from failed")]
+    GetHostBootedSlotFailed {
+        #[source]
+        error: anyhow::Error,
+    },
+    #[error(
+        "host booted from boot flash slot {booted_slot} instead of \
+         {expected_slot}"
+    )]
+    HostUnexpectedBootSlot { expected_slot: u16, booted_slot: u16 },
     #[error("setting host startup options failed for {description}")]
     SetHostStartupOptionsFailed {
         description: &'static str,
         #[source]
-        error: gateway_client::Error<gateway_client::types::Error>,
+        error: anyhow::Error,
     },
-    #[error("failed to upload trampoline phase 2 to MGS (was a new TUF repo uploaded?)")]
-    // Currently, the only way this error variant can be produced is if the
-    // upload task died or was replaced because a new TUF repository was
-    // uploaded. In the future, we may want to produce errors here if the upload
-    // to MGS fails too many times, for example.
+    #[error("failed to upload trampoline phase 2 to MGS (was a new TUF repo uploaded, or did the upload run out of retries?)")]
+    // This error variant is produced if the upload task died or was replaced
+    // because a new TUF repository was uploaded, or if the upload failed more
+    // times than `trampoline_phase_2_upload_max_attempts` allows.
     TrampolinePhase2UploadFailed,
     #[error("downloading installinator failed")]
     DownloadingInstallinatorFailed {
         #[source]
         error: anyhow::Error,
     },
+    #[error(
+        "installinator did not start within {timeout:?} (did the host boot?)"
+    )]
+    InstallinatorStartTimeout { timeout: Duration },
     #[error("running installinator failed")]
     RunningInstallinatorFailed {
         #[source]
         error: anyhow::Error,
     },
+    #[error(
+        "installinator reported writing M.2 slots {slots_written:?} after \
+         attempting {slots_attempted:?}"
+    )]
+    // Slots are reported by name (e.g. "A"), since this crate doesn't depend
+    // on installinator-common.
+    InvalidInstallinatorSlots {
+        slots_attempted: Vec<String>,
+        slots_written: Vec<String>,
+    },
+    #[error(
+        "TUF repository changed during the update: it started with plan \
+         {expected}, but the current plan is {}",
+        display_plan_hash(.current)
+    )]
+    // Produced before updating a component if the repository has been
+    // replaced since the update started, so that artifacts from two
+    // repositories are never applied by a single update.
+    RepositoryChanged { expected: ArtifactHash, current: Option<ArtifactHash> },
 }
 
 impl update_engine::AsError for UpdateTerminalError {
@@ -248,6 +315,13 @@ impl update_engine::AsError for SpComponentUpdateTerminalError {
     }
 }
 
+fn display_plan_hash(hash: &Option<ArtifactHash>) -> String {
+    match hash {
+        Some(hash) => hash.to_string(),
+        None => "missing".to_owned(),
+    }
+}
+
 fn display_artifact_id(artifact: &ArtifactId) -> String {
     format!(
         "{}:{} (version {})",
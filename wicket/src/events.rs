@@ -1,6 +1,7 @@
 // This Source Code Form is subject to the terms of the Mozilla Public
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
+use crate::rack_setup::redact_rss_config;
 use crate::{keymap::Cmd, state::ComponentId, State};
 use camino::Utf8PathBuf;
 use humantime::format_rfc3339;
@@ -11,7 +12,7 @@ use std::time::{Duration, SystemTime};
 use wicket_common::update_events::EventReport;
 use wicketd_client::types::{
     ArtifactId, CurrentRssUserConfig, GetLocationResponse, IgnitionCommand,
-    RackOperationStatus, RackV1Inventory, SemverVersion,
+    RackOperationStatus, RackV1Inventory, SemverVersion, SpCabooses,
 };
 
 /// Event report type returned by the get_artifacts_and_event_reports API call.
@@ -28,6 +29,9 @@ pub enum Event {
     /// An Inventory Update Event
     Inventory { inventory: RackV1Inventory, mgs_last_seen: Duration },
 
+    /// Freshly-read cabooses for a single component
+    SpCabooses { component_id: ComponentId, cabooses: SpCabooses },
+
     /// TUF repo artifacts unpacked by wicketd, and event reports
     ArtifactsAndEventReports {
         system_version: Option<SemverVersion>,
@@ -78,7 +82,9 @@ pub enum Action {
     StartUpdate(ComponentId),
     AbortUpdate(ComponentId),
     ClearUpdateState(ComponentId),
+    ClearAllUpdateState(Vec<ComponentId>),
     Ignition(ComponentId, IgnitionCommand),
+    RefreshCabooses(ComponentId),
     StartRackSetup,
     StartRackReset,
 }
@@ -94,7 +100,9 @@ impl Action {
             | Action::StartUpdate(_)
             | Action::AbortUpdate(_)
             | Action::ClearUpdateState(_)
+            | Action::ClearAllUpdateState(_)
             | Action::Ignition(_, _)
+            | Action::RefreshCabooses(_)
             | Action::StartRackSetup
             | Action::StartRackReset => true,
         }
@@ -133,6 +141,8 @@ impl Recorder {
     }
 
     /// Dump the current snapshot to disk
+    ///
+    /// Secret rack setup config values are redacted before writing.
     pub fn dump(&mut self) -> anyhow::Result<()> {
         let timestamp = format_rfc3339(SystemTime::now());
         let mut path: Utf8PathBuf = match std::env::var("WICKET_DUMP_PATH") {
@@ -144,7 +154,7 @@ impl Recorder {
         };
         path.push(format!("{}.wicket.dump", timestamp));
         let file = File::create(path)?;
-        ciborium::ser::into_writer(&self.snapshot, file)?;
+        ciborium::ser::into_writer(&self.snapshot.redacted(), file)?;
         Ok(())
     }
 }
@@ -173,6 +183,22 @@ impl Snapshot {
         self.max_events == self.history.len()
     }
 
+    /// Return a copy of this snapshot with secret rack setup config values
+    /// redacted (see [`redact_rss_config`]), in both the state and the event
+    /// history.
+    fn redacted(&self) -> Snapshot {
+        let mut snapshot = self.clone();
+        if let Some(config) = &mut snapshot.state.rss_config {
+            *config = redact_rss_config(config);
+        }
+        for event in &mut snapshot.history {
+            if let Event::RssConfig(config) = event {
+                *config = redact_rss_config(config);
+            }
+        }
+        snapshot
+    }
+
     fn take(&mut self, state: &State, event: Event) {
         self.start += self.history.len();
         self.state = state.clone();
@@ -54,6 +54,13 @@ pub enum Cmd {
     /// Display ignition control for the given selection
     Ignition,
 
+    /// Refresh data for the given selection out-of-band, instead of waiting
+    /// for the next periodic update
+    Refresh,
+
+    /// Cycle through the filters applied to the current list
+    CycleFilter,
+
     /// Move up or scroll up
     Up,
 
@@ -80,6 +87,10 @@ pub enum Cmd {
     /// screen).
     ResetState,
 
+    /// Reset screen-specific state for every item at once (e.g., clearing the
+    /// update state for all completed/failed updates).
+    ResetAllState,
+
     /// Begin rack setup.
     StartRackSetup,
 
@@ -205,6 +216,12 @@ impl KeyHandler {
                         self.seq = None;
                         return Some(Cmd::ResetState);
                     }
+                    KeyCode::Char('x')
+                        if event.modifiers == KeyModifiers::CONTROL =>
+                    {
+                        self.seq = None;
+                        return Some(Cmd::ResetAllState);
+                    }
                     KeyCode::Char('s')
                         if event.modifiers == KeyModifiers::CONTROL =>
                     {
@@ -234,6 +251,8 @@ impl KeyHandler {
             KeyCode::Char('c') => Cmd::Collapse,
             KeyCode::Char('d') => Cmd::Details,
             KeyCode::Char('i') => Cmd::Ignition,
+            KeyCode::Char('r') => Cmd::Refresh,
+            KeyCode::Char('f') => Cmd::CycleFilter,
             KeyCode::Up => Cmd::Up,
             KeyCode::Down => Cmd::Down,
             KeyCode::Right => Cmd::Right,
@@ -12,8 +12,12 @@ use clap::Subcommand;
 use omicron_passwords::Password;
 use omicron_passwords::PasswordHashString;
 use slog::Logger;
+use std::fs::OpenOptions;
 use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
 use std::io::Read;
+use std::io::Write;
 use std::mem;
 use std::net::SocketAddrV6;
 use std::time::Duration;
@@ -23,9 +27,16 @@ use wicketd_client::types::NewPasswordHash;
 use wicketd_client::types::PutRssRecoveryUserPasswordHash;
 use zeroize::Zeroizing;
 
+mod config_diff;
 mod config_toml;
+mod redact;
 
+pub(crate) use config_toml::validate_rss_config;
+pub(crate) use config_toml::ConfigValidationIssue;
 use config_toml::TomlTemplate;
+pub(crate) use config_toml::ValidationSeverity;
+pub(crate) use redact::redact_rss_config;
+pub(crate) use redact::LoggedRssConfig;
 
 const WICKETD_TIMEOUT: Duration = Duration::from_secs(5);
 
@@ -37,7 +48,14 @@ pub(crate) enum SetupArgs {
     GetConfig,
 
     /// Set the current rack configuration from a filled-in TOML template
-    SetConfig,
+    ///
+    /// The changes from the current configuration are shown, and must be
+    /// confirmed on the terminal before they're uploaded.
+    SetConfig {
+        /// Upload the configuration without asking for confirmation
+        #[clap(long)]
+        yes: bool,
+    },
 
     /// Reset the configuration to its original (empty) state.
     ///
@@ -87,13 +105,14 @@ impl SetupArgs {
                     .context("error fetching current config from wicketd")?
                     .into_inner();
 
-                let template = TomlTemplate::populate(&config.insensitive);
+                let template = TomlTemplate::populate(&config.insensitive)
+                    .context("error building config template")?;
 
                 // This is intentionally not `println`; our template already
                 // includes the final newline.
                 print!("{template}");
             }
-            SetupArgs::SetConfig => {
+            SetupArgs::SetConfig { yes } => {
                 let mut config = String::new();
                 slog::info!(log, "reading config from stdin...");
                 io::stdin()
@@ -104,6 +123,29 @@ impl SetupArgs {
                 let config: PutRssUserConfigInsensitive =
                     toml::de::from_str(&config)
                         .context("failed to parse config TOML")?;
+                if let Err(err) = config
+                    .validate_external_dns_ips()
+                    .and_then(|()| config.validate_rack_subnet())
+                {
+                    bail!("invalid config: {err}");
+                }
+
+                let old_config = client
+                    .get_rss_config()
+                    .await
+                    .context("error fetching current config from wicketd")?
+                    .into_inner();
+
+                let diff = config_diff::diff(&old_config.insensitive, &config);
+                if diff.is_empty() {
+                    slog::info!(log, "config unchanged");
+                } else {
+                    // As with `GetConfig`, `diff` already ends with a newline.
+                    print!("{diff}");
+                    if !yes && !confirm("upload these changes?")? {
+                        bail!("config upload cancelled");
+                    }
+                }
 
                 slog::info!(log, "uploading config to wicketd...");
                 client
@@ -112,6 +154,27 @@ impl SetupArgs {
                     .context("error uploading config to wicketd")?;
 
                 slog::info!(log, "config upload complete");
+
+                // The config was accepted, but may still have problems that
+                // would prevent rack setup from starting. The upload itself
+                // succeeded, so failing to check for them isn't an error.
+                match client.get_rss_config().await {
+                    Ok(new_config) => {
+                        let new_config = new_config.into_inner();
+                        for issue in
+                            validate_rss_config(&new_config.insensitive)
+                        {
+                            slog::warn!(log, "{issue}");
+                        }
+                    }
+                    Err(err) => {
+                        slog::warn!(
+                            log,
+                            "failed to fetch uploaded config to check it \
+                             for problems: {err}"
+                        );
+                    }
+                }
             }
             SetupArgs::ResetConfig => {
                 slog::info!(log, "instructing wicketd to reset config...");
@@ -228,6 +291,24 @@ impl SetupArgs {
     }
 }
 
+/// Asks the operator to confirm `prompt` on the terminal.
+///
+/// stdin holds the config being uploaded, so this reads the answer from
+/// `/dev/tty` instead.
+fn confirm(prompt: &str) -> Result<bool> {
+    let mut tty =
+        OpenOptions::new().read(true).write(true).open("/dev/tty").context(
+            "failed to open terminal to confirm changes \
+             (do you need to use `ssh -t`, or pass `--yes`?)",
+        )?;
+    write!(tty, "{prompt} [y/N] ").context("failed to write prompt")?;
+    let mut answer = String::new();
+    BufReader::new(tty)
+        .read_line(&mut answer)
+        .context("failed to read confirmation")?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
 fn read_and_hash_password(log: &Logger) -> Result<PasswordHashString> {
     let pass1 = rpassword::prompt_password(
         "Password for recovery user of recovery silo: ",
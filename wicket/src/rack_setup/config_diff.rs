@@ -0,0 +1,562 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Comparison of the current rack setup config with one about to be uploaded,
+//! for showing operators what will change.
+
+use omicron_common::api::internal::shared;
+use std::collections::BTreeMap;
+use std::fmt;
+use wicket_common::rack_setup::PutRssUserConfigInsensitive;
+use wicketd_client::types::BootstrapSledDescription;
+use wicketd_client::types::CurrentRssUserConfigInsensitive;
+use wicketd_client::types::IpRange;
+use wicketd_client::types::UplinkConfig;
+
+/// The differences between two rack setup configs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ConfigDiff {
+    pub(crate) entries: Vec<ConfigDiffEntry>,
+}
+
+impl ConfigDiff {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl fmt::Display for ConfigDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{entry}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A single difference between two rack setup configs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ConfigDiffEntry {
+    /// `value` is present in the new config but not the old one.
+    Added { field: &'static str, value: String },
+
+    /// `value` is present in the old config but not the new one.
+    Removed { field: &'static str, value: String },
+
+    /// `field` is present in both configs but its value changed.
+    Changed { field: &'static str, old: String, new: String },
+}
+
+impl fmt::Display for ConfigDiffEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigDiffEntry::Added { field, value } => {
+                write!(f, "+ {field}: {value}")
+            }
+            ConfigDiffEntry::Removed { field, value } => {
+                write!(f, "- {field}: {value}")
+            }
+            ConfigDiffEntry::Changed { field, old, new } => {
+                write!(f, "~ {field}: {old} -> {new}")
+            }
+        }
+    }
+}
+
+/// An entry in a list-valued field.
+///
+/// Entries are matched up between the old and new lists by `key`; `value` is
+/// how the entry is displayed, and a matched entry whose `value` differs is
+/// reported as changed.
+struct ListEntry {
+    key: String,
+    value: String,
+}
+
+impl ListEntry {
+    fn new(value: String) -> Self {
+        Self { key: value.clone(), value }
+    }
+}
+
+/// Compares the current config `old` with `new`, which is about to be
+/// uploaded, returning the entries that will be added, removed, or changed.
+pub(crate) fn diff(
+    old: &CurrentRssUserConfigInsensitive,
+    new: &PutRssUserConfigInsensitive,
+) -> ConfigDiff {
+    let mut entries = Vec::new();
+
+    // Bootstrap sleds are a set, so compare them in slot order. `new` only
+    // names them by slot; describe them using what wicketd told us about the
+    // current ones where we can.
+    let known_sleds: BTreeMap<u32, &BootstrapSledDescription> =
+        old.bootstrap_sleds.iter().map(|sled| (sled.id.slot, sled)).collect();
+    diff_lists(
+        &mut entries,
+        "bootstrap_sleds",
+        known_sleds.iter().map(|(&slot, sled)| sled_entry(slot, sled)),
+        new.bootstrap_sleds.iter().map(|&slot| match known_sleds.get(&slot) {
+            Some(sled) => sled_entry(slot, sled),
+            None => ListEntry {
+                key: slot.to_string(),
+                value: format!("sled {slot}"),
+            },
+        }),
+    );
+    diff_lists(
+        &mut entries,
+        "ntp_servers",
+        old.ntp_servers.iter().cloned().map(ListEntry::new),
+        new.ntp_servers.iter().cloned().map(ListEntry::new),
+    );
+    diff_lists(
+        &mut entries,
+        "dns_servers",
+        old.dns_servers.iter().map(|ip| ListEntry::new(ip.to_string())),
+        new.dns_servers.iter().map(|ip| ListEntry::new(ip.to_string())),
+    );
+    diff_lists(
+        &mut entries,
+        "internal_services_ip_pool_ranges",
+        old.internal_services_ip_pool_ranges
+            .iter()
+            .map(|range| ListEntry::new(ip_range_to_string(range))),
+        new.internal_services_ip_pool_ranges.iter().map(|range| {
+            ListEntry::new(format!(
+                "{}-{}",
+                range.first_address(),
+                range.last_address()
+            ))
+        }),
+    );
+    diff_lists(
+        &mut entries,
+        "external_dns_ips",
+        old.external_dns_ips.iter().map(|ip| ListEntry::new(ip.to_string())),
+        new.external_dns_ips.iter().map(|ip| ListEntry::new(ip.to_string())),
+    );
+    diff_values(
+        &mut entries,
+        "external_dns_zone_name",
+        &old.external_dns_zone_name,
+        &new.external_dns_zone_name,
+    );
+    diff_values(
+        &mut entries,
+        "rack_subnet",
+        &display_or_unset(old.rack_subnet),
+        &display_or_unset(new.rack_subnet),
+    );
+
+    let old_network = old.rack_network_config.as_ref();
+    let new_network = &new.rack_network_config;
+    diff_values(
+        &mut entries,
+        "rack_network_config.infra_ip_first",
+        &display_or_unset(old_network.map(|c| c.infra_ip_first)),
+        &new_network.infra_ip_first.to_string(),
+    );
+    diff_values(
+        &mut entries,
+        "rack_network_config.infra_ip_last",
+        &display_or_unset(old_network.map(|c| c.infra_ip_last)),
+        &new_network.infra_ip_last.to_string(),
+    );
+    // Uplinks are matched up by switch and port, so that a changed setting
+    // on an existing uplink is reported as a change rather than a removal
+    // and an addition.
+    diff_lists(
+        &mut entries,
+        "rack_network_config.uplinks",
+        old_network
+            .map_or(&[][..], |c| c.uplinks.as_slice())
+            .iter()
+            .map(uplink_entry),
+        new_network.uplinks.iter().map(new_uplink_entry),
+    );
+
+    ConfigDiff { entries }
+}
+
+/// Compares two lists in order.
+///
+/// Entries are matched up one-for-one, so duplicates are counted. Unmatched
+/// entries are reported as added or removed, matched entries whose values
+/// differ as changed, and if the matched entries aren't in the same order
+/// the whole reordering is reported as a change.
+fn diff_lists(
+    entries: &mut Vec<ConfigDiffEntry>,
+    field: &'static str,
+    old: impl Iterator<Item = ListEntry>,
+    new: impl Iterator<Item = ListEntry>,
+) {
+    let old: Vec<_> = old.collect();
+    let new: Vec<_> = new.collect();
+
+    // For each entry in `old`, the index of its match in `new` (if any).
+    let mut matched_new = vec![false; new.len()];
+    let mut matches = Vec::new();
+    for old_entry in &old {
+        let found = new.iter().enumerate().position(|(i, new_entry)| {
+            !matched_new[i] && new_entry.key == old_entry.key
+        });
+        match found {
+            Some(i) => {
+                matched_new[i] = true;
+                matches.push((old_entry, i));
+                if old_entry.value != new[i].value {
+                    entries.push(ConfigDiffEntry::Changed {
+                        field,
+                        old: old_entry.value.clone(),
+                        new: new[i].value.clone(),
+                    });
+                }
+            }
+            None => entries.push(ConfigDiffEntry::Removed {
+                field,
+                value: old_entry.value.clone(),
+            }),
+        }
+    }
+    for (new_entry, matched) in new.iter().zip(&matched_new) {
+        if !matched {
+            entries.push(ConfigDiffEntry::Added {
+                field,
+                value: new_entry.value.clone(),
+            });
+        }
+    }
+
+    // `matches` is in old order; if the indices into `new` aren't increasing,
+    // the entries present in both lists were reordered.
+    if matches.windows(2).any(|pair| pair[0].1 > pair[1].1) {
+        let old_order = matches.iter().map(|(entry, _)| entry.key.as_str());
+        let mut new_indices: Vec<_> = matches.iter().map(|(_, i)| *i).collect();
+        new_indices.sort_unstable();
+        let new_order = new_indices.iter().map(|&i| new[i].key.as_str());
+        entries.push(ConfigDiffEntry::Changed {
+            field,
+            old: format!(
+                "order [{}]",
+                old_order.collect::<Vec<_>>().join(", ")
+            ),
+            new: format!(
+                "order [{}]",
+                new_order.collect::<Vec<_>>().join(", ")
+            ),
+        });
+    }
+}
+
+fn diff_values(
+    entries: &mut Vec<ConfigDiffEntry>,
+    field: &'static str,
+    old: &str,
+    new: &str,
+) {
+    if old != new {
+        entries.push(ConfigDiffEntry::Changed {
+            field,
+            old: old.to_string(),
+            new: new.to_string(),
+        });
+    }
+}
+
+fn sled_entry(slot: u32, sled: &BootstrapSledDescription) -> ListEntry {
+    ListEntry { key: slot.to_string(), value: sled_to_string(sled) }
+}
+
+fn uplink_entry(uplink: &UplinkConfig) -> ListEntry {
+    uplink_list_entry(
+        &uplink.switch,
+        &uplink.uplink_port,
+        &uplink.gateway_ip,
+        &uplink.uplink_cidr,
+        &uplink.uplink_port_speed,
+        &uplink.uplink_port_fec,
+        uplink.uplink_vid,
+    )
+}
+
+fn new_uplink_entry(uplink: &shared::UplinkConfig) -> ListEntry {
+    uplink_list_entry(
+        &uplink.switch,
+        &uplink.uplink_port,
+        &uplink.gateway_ip,
+        &uplink.uplink_cidr,
+        &uplink.uplink_port_speed,
+        &uplink.uplink_port_fec,
+        uplink.uplink_vid,
+    )
+}
+
+/// Builds the entry for an uplink from the fields shared by the current
+/// (`wicketd_client`) and new (`omicron_common`) uplink types, so that both
+/// are displayed the same way.
+fn uplink_list_entry(
+    switch: &dyn fmt::Display,
+    port: &str,
+    gateway_ip: &dyn fmt::Display,
+    cidr: &dyn fmt::Display,
+    speed: &dyn fmt::Debug,
+    fec: &dyn fmt::Debug,
+    vid: Option<u16>,
+) -> ListEntry {
+    let key = format!("{switch} {port}");
+    let vid = match vid {
+        Some(vid) => format!(", vid {vid}"),
+        None => String::new(),
+    };
+    let value = format!(
+        "{key} (gateway {gateway_ip}, cidr {cidr}, speed {speed:?}, \
+         fec {fec:?}{vid})"
+    );
+    ListEntry { key, value }
+}
+
+fn sled_to_string(sled: &BootstrapSledDescription) -> String {
+    format!("sled {} ({})", sled.id.slot, sled.baseboard.identifier())
+}
+
+fn ip_range_to_string(range: &IpRange) -> String {
+    match range {
+        IpRange::V4(range) => format!("{}-{}", range.first, range.last),
+        IpRange::V6(range) => format!("{}-{}", range.first, range.last),
+    }
+}
+
+fn display_or_unset<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "(unset)".to_string(), |value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use omicron_common::address;
+    use wicketd_client::types::Baseboard;
+    use wicketd_client::types::Ipv4Range;
+    use wicketd_client::types::PortFec;
+    use wicketd_client::types::PortSpeed;
+    use wicketd_client::types::RackNetworkConfig;
+    use wicketd_client::types::SpIdentifier;
+    use wicketd_client::types::SpType;
+    use wicketd_client::types::SwitchLocation;
+
+    fn sled(slot: u32) -> BootstrapSledDescription {
+        BootstrapSledDescription {
+            id: SpIdentifier { slot, type_: SpType::Sled },
+            baseboard: Baseboard::Gimlet {
+                model: "model".into(),
+                revision: 1,
+                identifier: format!("serial{slot}"),
+            },
+            bootstrap_ip: None,
+        }
+    }
+
+    fn uplink(port: &str, speed: PortSpeed) -> UplinkConfig {
+        UplinkConfig {
+            gateway_ip: "172.30.0.10".parse().unwrap(),
+            uplink_cidr: "172.30.0.1/24".parse().unwrap(),
+            uplink_port_speed: speed,
+            uplink_port_fec: PortFec::None,
+            uplink_port: port.into(),
+            uplink_vid: None,
+            switch: SwitchLocation::Switch0,
+        }
+    }
+
+    fn new_uplink(
+        port: &str,
+        speed: shared::PortSpeed,
+    ) -> shared::UplinkConfig {
+        shared::UplinkConfig {
+            gateway_ip: "172.30.0.10".parse().unwrap(),
+            uplink_cidr: "172.30.0.1/24".parse().unwrap(),
+            uplink_port_speed: speed,
+            uplink_port_fec: shared::PortFec::None,
+            uplink_port: port.into(),
+            uplink_vid: None,
+            switch: shared::SwitchLocation::Switch0,
+        }
+    }
+
+    fn old_config() -> CurrentRssUserConfigInsensitive {
+        CurrentRssUserConfigInsensitive {
+            bootstrap_sleds: vec![sled(1), sled(2)],
+            dns_servers: vec!["1.1.1.1".parse().unwrap()],
+            external_dns_zone_name: "oxide.computer".into(),
+            internal_services_ip_pool_ranges: vec![IpRange::V4(Ipv4Range {
+                first: "10.0.0.1".parse().unwrap(),
+                last: "10.0.0.5".parse().unwrap(),
+            })],
+            external_dns_ips: vec!["10.0.0.1".parse().unwrap()],
+            ntp_servers: vec!["ntp1.com".into(), "ntp2.com".into()],
+            rack_subnet: None,
+            rack_network_config: Some(RackNetworkConfig {
+                infra_ip_first: "172.30.0.1".parse().unwrap(),
+                infra_ip_last: "172.30.0.10".parse().unwrap(),
+                uplinks: vec![
+                    uplink("port0", PortSpeed::Speed100G),
+                    uplink("port1", PortSpeed::Speed100G),
+                ],
+            }),
+        }
+    }
+
+    /// The config that, once uploaded, `old_config()` describes.
+    fn new_config() -> PutRssUserConfigInsensitive {
+        PutRssUserConfigInsensitive {
+            bootstrap_sleds: [1, 2].into_iter().collect(),
+            dns_servers: vec!["1.1.1.1".parse().unwrap()],
+            external_dns_zone_name: "oxide.computer".into(),
+            internal_services_ip_pool_ranges: vec![address::IpRange::V4(
+                address::Ipv4Range::new(
+                    "10.0.0.1".parse().unwrap(),
+                    "10.0.0.5".parse().unwrap(),
+                )
+                .unwrap(),
+            )],
+            external_dns_ips: vec!["10.0.0.1".parse().unwrap()],
+            ntp_servers: vec!["ntp1.com".into(), "ntp2.com".into()],
+            rack_subnet: None,
+            rack_network_config: shared::RackNetworkConfig {
+                infra_ip_first: "172.30.0.1".parse().unwrap(),
+                infra_ip_last: "172.30.0.10".parse().unwrap(),
+                uplinks: vec![
+                    new_uplink("port0", shared::PortSpeed::Speed100G),
+                    new_uplink("port1", shared::PortSpeed::Speed100G),
+                ],
+            },
+        }
+    }
+
+    fn diff_lines(
+        old: &CurrentRssUserConfigInsensitive,
+        new: &PutRssUserConfigInsensitive,
+    ) -> Vec<String> {
+        diff(old, new).entries.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn identical_configs_have_no_diff() {
+        assert_eq!(diff(&old_config(), &new_config()), ConfigDiff::default());
+
+        // Bootstrap sleds are a set, so the order wicketd lists them in
+        // doesn't matter.
+        let mut old = old_config();
+        old.bootstrap_sleds.reverse();
+        assert_eq!(diff(&old, &new_config()), ConfigDiff::default());
+    }
+
+    #[test]
+    fn reordering_is_a_change() {
+        let mut new = new_config();
+        new.ntp_servers.reverse();
+        new.rack_network_config.uplinks.reverse();
+
+        assert_eq!(
+            diff_lines(&old_config(), &new),
+            [
+                "~ ntp_servers: order [ntp1.com, ntp2.com] -> \
+                 order [ntp2.com, ntp1.com]",
+                "~ rack_network_config.uplinks: \
+                 order [switch0 port0, switch0 port1] -> \
+                 order [switch0 port1, switch0 port0]",
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates_are_counted() {
+        let mut new = new_config();
+        new.dns_servers.push("1.1.1.1".parse().unwrap());
+
+        assert_eq!(diff_lines(&old_config(), &new), ["+ dns_servers: 1.1.1.1"]);
+    }
+
+    #[test]
+    fn diff_captures_each_kind_of_change() {
+        let old = old_config();
+        let mut new = new_config();
+
+        // Added and removed list entries.
+        new.ntp_servers = vec!["ntp1.com".into(), "ntp3.com".into()];
+        new.dns_servers.push("2.2.2.2".parse().unwrap());
+        new.bootstrap_sleds = [1, 3].into_iter().collect();
+        new.internal_services_ip_pool_ranges = vec![address::IpRange::V6(
+            address::Ipv6Range::new(
+                "fd00::1".parse().unwrap(),
+                "fd00::5".parse().unwrap(),
+            )
+            .unwrap(),
+        )];
+
+        // Changed scalar values.
+        new.external_dns_zone_name = "example.com".into();
+        new.rack_subnet = Some("fd00:1122:3344:100::".parse().unwrap());
+        let network = &mut new.rack_network_config;
+        network.infra_ip_last = "172.30.0.20".parse().unwrap();
+
+        // An uplink with changed settings, one removed, and one added.
+        network.uplinks = vec![
+            new_uplink("port0", shared::PortSpeed::Speed400G),
+            new_uplink("port2", shared::PortSpeed::Speed100G),
+        ];
+
+        let expected = [
+            "- bootstrap_sleds: sled 2 (serial2)",
+            "+ bootstrap_sleds: sled 3",
+            "- ntp_servers: ntp2.com",
+            "+ ntp_servers: ntp3.com",
+            "+ dns_servers: 2.2.2.2",
+            "- internal_services_ip_pool_ranges: 10.0.0.1-10.0.0.5",
+            "+ internal_services_ip_pool_ranges: fd00::1-fd00::5",
+            "~ external_dns_zone_name: oxide.computer -> example.com",
+            "~ rack_subnet: (unset) -> fd00:1122:3344:100::",
+            "~ rack_network_config.infra_ip_last: 172.30.0.10 -> 172.30.0.20",
+            "~ rack_network_config.uplinks: \
+             switch0 port0 (gateway 172.30.0.10, cidr 172.30.0.1/24, \
+             speed Speed100G, fec None) -> \
+             switch0 port0 (gateway 172.30.0.10, cidr 172.30.0.1/24, \
+             speed Speed400G, fec None)",
+            "- rack_network_config.uplinks: \
+             switch0 port1 (gateway 172.30.0.10, cidr 172.30.0.1/24, \
+             speed Speed100G, fec None)",
+            "+ rack_network_config.uplinks: \
+             switch0 port2 (gateway 172.30.0.10, cidr 172.30.0.1/24, \
+             speed Speed100G, fec None)",
+        ];
+        assert_eq!(diff_lines(&old, &new), expected);
+    }
+
+    #[test]
+    fn diff_network_config_initially_unset() {
+        let mut old = old_config();
+        old.rack_network_config = None;
+
+        let diff = diff(&old, &new_config());
+        assert!(diff.entries.contains(&ConfigDiffEntry::Changed {
+            field: "rack_network_config.infra_ip_first",
+            old: "(unset)".into(),
+            new: "172.30.0.1".into(),
+        }));
+        assert_eq!(
+            diff.entries
+                .iter()
+                .filter(|entry| matches!(
+                    entry,
+                    ConfigDiffEntry::Added {
+                        field: "rack_network_config.uplinks",
+                        ..
+                    }
+                ))
+                .count(),
+            2,
+            "both uplinks added: {diff}"
+        );
+    }
+}
@@ -3,11 +3,19 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! Support for the TOML file we give to and accept from clients for setting
-//! (most of) the rack setup configuration.
+//! (most of) the rack setup configuration, and for validating the
+//! configuration it describes.
 
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
 use serde::Serialize;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::fmt;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
 use toml_edit::Array;
 use toml_edit::Document;
 use toml_edit::Formatted;
@@ -15,9 +23,13 @@ use toml_edit::InlineTable;
 use toml_edit::Item;
 use toml_edit::Table;
 use toml_edit::Value;
+use wicket_common::rack_setup::validate_rack_subnet;
+use wicketd_client::types::Baseboard;
 use wicketd_client::types::BootstrapSledDescription;
 use wicketd_client::types::CurrentRssUserConfigInsensitive;
 use wicketd_client::types::IpRange;
+use wicketd_client::types::PortFec;
+use wicketd_client::types::PortSpeed;
 use wicketd_client::types::RackNetworkConfig;
 use wicketd_client::types::SpType;
 
@@ -27,12 +39,18 @@ static TEMPLATE: &str = include_str!("config_template.toml");
 // nice/indented.
 const ARRAY_SEP: &str = "\n    ";
 
+// Comment following an uplink's VLAN ID, since the uplinks we generate don't
+// carry the template's docs for each field.
+const UPLINK_VID_COMMENT: &str = " # VLAN tag applied to this uplink's traffic";
+
 pub(super) struct TomlTemplate {
     doc: Document,
 }
 
 impl TomlTemplate {
-    pub(crate) fn populate(config: &CurrentRssUserConfigInsensitive) -> Self {
+    pub(crate) fn populate(
+        config: &CurrentRssUserConfigInsensitive,
+    ) -> Result<Self> {
         let mut doc = TEMPLATE.parse::<Document>().unwrap();
 
         *doc.get_mut("external_dns_zone_name")
@@ -96,9 +114,21 @@ impl TomlTemplate {
         populate_network_table(
             doc.get_mut("rack_network_config").unwrap().as_table_mut().unwrap(),
             config.rack_network_config.as_ref(),
-        );
+        )
+        .context("failed to populate rack_network_config")?;
 
-        Self { doc }
+        // The template lists `rack_subnet` immediately before the
+        // `[rack_network_config]` table; if it isn't set, it's commented out
+        // in place.
+        match config.rack_subnet {
+            Some(subnet) => {
+                *doc.get_mut("rack_subnet").unwrap().as_value_mut().unwrap() =
+                    Value::String(Formatted::new(subnet.to_string()));
+            }
+            None => comment_out_key(&mut doc, "rack_subnet"),
+        }
+
+        Ok(Self { doc })
     }
 }
 
@@ -108,6 +138,32 @@ impl fmt::Display for TomlTemplate {
     }
 }
 
+// Replace the top-level `key` with a comment (preserving its doc comments),
+// so the generated file shows where the key would go without setting it.
+//
+// Must only be called for keys that directly precede `[rack_network_config]`
+// in the template.
+fn comment_out_key(doc: &mut Document, key: &str) {
+    let docs = doc
+        .as_table()
+        .key_decor(key)
+        .and_then(|decor| decor.prefix())
+        .and_then(|prefix| prefix.as_str())
+        .unwrap_or("")
+        .to_string();
+    doc.remove(key);
+
+    let table =
+        doc.get_mut("rack_network_config").unwrap().as_table_mut().unwrap();
+    let existing = table
+        .decor()
+        .prefix()
+        .and_then(|prefix| prefix.as_str())
+        .unwrap_or("")
+        .to_string();
+    table.decor_mut().set_prefix(format!("{docs}# {key} = \"\"\n{existing}"));
+}
+
 fn format_multiline_array(array: &mut Array) {
     for element in array.iter_mut() {
         element.decor_mut().set_prefix(ARRAY_SEP);
@@ -119,7 +175,6 @@ fn format_multiline_array(array: &mut Array) {
 fn build_sleds_array(sleds: &[BootstrapSledDescription]) -> Array {
     // Helper function to build the comment attached to a given sled.
     fn sled_comment(sled: &BootstrapSledDescription, end: &str) -> String {
-        use wicketd_client::types::Baseboard;
         let ip = sled
             .bootstrap_ip
             .map(|ip| Cow::from(format!("{ip}")))
@@ -174,43 +229,73 @@ fn build_sleds_array(sleds: &[BootstrapSledDescription]) -> Array {
     array
 }
 
+// Helper function to serialize enums into their appropriate string
+// representations.
+fn enum_to_toml_string<T: Serialize + fmt::Debug>(value: &T) -> Result<String> {
+    let toml_value = toml::Value::try_from(value)
+        .with_context(|| format!("failed to serialize {value:?} to TOML"))?;
+    match toml_value {
+        toml::Value::String(s) => Ok(s),
+        other => bail!(
+            "expected {value:?} to serialize to a TOML string, \
+             but got {other}"
+        ),
+    }
+}
+
 fn populate_network_table(
     table: &mut Table,
     config: Option<&RackNetworkConfig>,
-) {
-    // Helper function to serialize enums into their appropriate string
-    // representations.
-    fn enum_to_toml_string<T: Serialize>(value: &T) -> String {
-        let value = toml::Value::try_from(value).unwrap();
-        match value {
-            toml::Value::String(s) => s,
-            other => {
-                panic!("improper use of enum_to_toml_string: got {other:?}");
-            }
+) -> Result<()> {
+    // The template's placeholders aren't valid values, so if there's no
+    // network config yet we fill in defaults that parse but that RSS will
+    // reject (there are no uplinks), forcing the user to fill them in.
+    let (infra_ip_first, infra_ip_last, uplinks) = match config {
+        Some(config) => (
+            config.infra_ip_first,
+            config.infra_ip_last,
+            config.uplinks.as_slice(),
+        ),
+        None => {
+            table.key_decor_mut("infra_ip_first").unwrap().set_prefix(
+                "# Not yet configured; replace these placeholders with the \
+                 range of\n# IP addresses to use for network infrastructure.\n",
+            );
+            (Ipv4Addr::UNSPECIFIED, Ipv4Addr::UNSPECIFIED, &[][..])
         }
-    }
-
-    let Some(config) = config else {
-        return;
     };
 
     for (property, value) in [
-        ("infra_ip_first", config.infra_ip_first.to_string()),
-        ("infra_ip_last", config.infra_ip_last.to_string()),
+        ("infra_ip_first", infra_ip_first.to_string()),
+        ("infra_ip_last", infra_ip_last.to_string()),
     ] {
         *table.get_mut(property).unwrap().as_value_mut().unwrap() =
             Value::String(Formatted::new(value));
     }
 
-    // If `config.uplinks` is empty, we'll leave the template uplinks in place;
-    // otherwise, replace it with the user's uplinks.
-    if !config.uplinks.is_empty() {
+    // If there are no uplinks, replace the template uplink with an empty list,
+    // leaving the template's example in a comment; otherwise, replace it with
+    // the user's uplinks.
+    if uplinks.is_empty() {
+        comment_out_example_uplink(table);
+    } else {
+        // Keep the operator's order (validation errors refer to uplinks by
+        // index), but start each run of uplinks on the same switch with a
+        // comment naming the switch.
+        let mut prev_switch = None;
+
         *table.get_mut("uplinks").unwrap().as_array_of_tables_mut().unwrap() =
-            config
-                .uplinks
+            uplinks
                 .iter()
-                .map(|cfg| {
+                .map(|cfg| -> Result<Table> {
                     let mut uplink = Table::new();
+                    if prev_switch != Some(&cfg.switch) {
+                        uplink.decor_mut().set_prefix(format!(
+                            "\n# Uplinks on {}\n",
+                            cfg.switch
+                        ));
+                        prev_switch = Some(&cfg.switch);
+                    }
                     let mut last_key = None;
                     for (property, value) in [
                         ("switch", cfg.switch.to_string()),
@@ -218,11 +303,13 @@ fn populate_network_table(
                         ("uplink_port", cfg.uplink_port.to_string()),
                         (
                             "uplink_port_speed",
-                            enum_to_toml_string(&cfg.uplink_port_speed),
+                            enum_to_toml_string(&cfg.uplink_port_speed)
+                                .context("invalid uplink_port_speed")?,
                         ),
                         (
                             "uplink_port_fec",
-                            enum_to_toml_string(&cfg.uplink_port_fec),
+                            enum_to_toml_string(&cfg.uplink_port_fec)
+                                .context("invalid uplink_port_fec")?,
                         ),
                         ("uplink_cidr", cfg.uplink_cidr.to_string()),
                     ] {
@@ -234,11 +321,11 @@ fn populate_network_table(
                     }
 
                     if let Some(uplink_vid) = cfg.uplink_vid {
+                        let mut value = Formatted::new(i64::from(uplink_vid));
+                        value.decor_mut().set_suffix(UPLINK_VID_COMMENT);
                         uplink.insert(
                             "uplink_vid",
-                            Item::Value(Value::Integer(Formatted::new(
-                                i64::from(uplink_vid),
-                            ))),
+                            Item::Value(Value::Integer(value)),
                         );
                     } else {
                         // Unwraps: We know `last_key` is `Some(_)`, because we
@@ -255,9 +342,342 @@ fn populate_network_table(
                             .set_suffix("\n# uplink_vid =");
                     }
 
-                    uplink
+                    Ok(uplink)
                 })
-                .collect();
+                .collect::<Result<_>>()?;
+    }
+
+    Ok(())
+}
+
+fn comment_out_example_uplink(table: &mut Table) {
+    const UPLINKS_HEADER: &str = "[[rack_network_config.uplinks]]";
+
+    // The example uplink is the last thing in the template.
+    let (_, example) = TEMPLATE
+        .split_once(UPLINKS_HEADER)
+        .expect("template contains an example uplink");
+
+    let mut comment = String::from(
+        "\n# No uplinks are configured. Replace `uplinks = []` with one or \
+         more\n# uplinks of this form:\n#\n",
+    );
+    for line in UPLINKS_HEADER.lines().chain(example.lines()) {
+        if line.is_empty() {
+            comment.push_str("#\n");
+        } else {
+            comment.push_str(&format!("# {line}\n"));
+        }
+    }
+
+    table.remove("uplinks");
+    table.insert("uplinks", Item::Value(Value::Array(Array::new())));
+    table.key_decor_mut("uplinks").unwrap().set_prefix(comment);
+}
+
+/// How serious a problem found in the rack setup configuration is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum ValidationSeverity {
+    /// The configuration can be used, but probably isn't what was intended.
+    Warning,
+    /// Rack setup must not be started until this is fixed.
+    Error,
+}
+
+/// A single problem found in the rack setup configuration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ConfigValidationIssue {
+    /// The path to the offending field, as it's named in the TOML template,
+    /// e.g., `rack_network_config.uplinks[0].uplink_cidr`.
+    pub(crate) field: String,
+    pub(crate) severity: ValidationSeverity,
+    pub(crate) message: String,
+}
+
+impl ConfigValidationIssue {
+    fn error(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            severity: ValidationSeverity::Error,
+            message: message.into(),
+        }
+    }
+
+    fn warning(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            severity: ValidationSeverity::Warning,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn is_error(&self) -> bool {
+        self.severity == ValidationSeverity::Error
+    }
+}
+
+impl fmt::Display for ConfigValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            ValidationSeverity::Warning => "warning",
+            ValidationSeverity::Error => "error",
+        };
+        write!(f, "{severity}: {}: {}", self.field, self.message)
+    }
+}
+
+/// Check the rack setup configuration, returning every problem found.
+///
+/// wicketd checks the configuration as it's uploaded and again when rack setup
+/// starts, but stops at the first problem. This instead runs every check, so
+/// that all the problems can be shown together. Rack setup should only be
+/// started if none of them are errors.
+pub(crate) fn validate_rss_config(
+    config: &CurrentRssUserConfigInsensitive,
+) -> Vec<ConfigValidationIssue> {
+    let mut issues = Vec::new();
+
+    validate_bootstrap_sleds(&config.bootstrap_sleds, &mut issues);
+
+    for (field, is_empty) in [
+        ("ntp_servers", config.ntp_servers.is_empty()),
+        ("dns_servers", config.dns_servers.is_empty()),
+        (
+            "internal_services_ip_pool_ranges",
+            config.internal_services_ip_pool_ranges.is_empty(),
+        ),
+        ("external_dns_ips", config.external_dns_ips.is_empty()),
+    ] {
+        if is_empty {
+            issues.push(ConfigValidationIssue::error(
+                field,
+                "at least one entry is required",
+            ));
+        }
+    }
+    if config.external_dns_zone_name.is_empty() {
+        issues.push(ConfigValidationIssue::error(
+            "external_dns_zone_name",
+            "must be set",
+        ));
+    }
+
+    for (i, range) in config.internal_services_ip_pool_ranges.iter().enumerate()
+    {
+        let (first, last) = match range {
+            IpRange::V4(r) => (IpAddr::from(r.first), IpAddr::from(r.last)),
+            IpRange::V6(r) => (IpAddr::from(r.first), IpAddr::from(r.last)),
+        };
+        if first > last {
+            issues.push(ConfigValidationIssue::error(
+                format!("internal_services_ip_pool_ranges[{i}]"),
+                format!("first address {first} is after last address {last}"),
+            ));
+        }
+    }
+
+    // RSS allocates the external DNS zones' addresses from the internal
+    // services IP pool, so each must be within one of its ranges.
+    for (i, ip) in config.external_dns_ips.iter().enumerate() {
+        if !config
+            .internal_services_ip_pool_ranges
+            .iter()
+            .any(|range| ip_range_contains(range, *ip))
+        {
+            issues.push(ConfigValidationIssue::error(
+                format!("external_dns_ips[{i}]"),
+                format!(
+                    "{ip} is not within any internal services IP pool range"
+                ),
+            ));
+        }
+    }
+
+    if let Some(subnet) = config.rack_subnet {
+        if let Err(message) = validate_rack_subnet(subnet) {
+            issues.push(ConfigValidationIssue::error("rack_subnet", message));
+        }
+    }
+
+    match config.rack_network_config.as_ref() {
+        Some(network_config) => {
+            validate_rack_network_config(network_config, &mut issues)
+        }
+        None => issues.push(ConfigValidationIssue::error(
+            "rack_network_config",
+            "must be set",
+        )),
+    }
+
+    issues
+}
+
+fn validate_bootstrap_sleds(
+    sleds: &[BootstrapSledDescription],
+    issues: &mut Vec<ConfigValidationIssue>,
+) {
+    const FIELD: &str = "bootstrap_sleds";
+
+    if sleds.is_empty() {
+        issues.push(ConfigValidationIssue::error(
+            FIELD,
+            "at least one sled is required",
+        ));
+    }
+
+    let mut seen_slots = BTreeSet::new();
+    for sled in sleds {
+        let slot = sled.id.slot;
+        if sled.id.type_ != SpType::Sled {
+            issues.push(ConfigValidationIssue::error(
+                FIELD,
+                format!("{:?} {slot} is not a sled", sled.id.type_),
+            ));
+            continue;
+        }
+        if !seen_slots.insert(slot) {
+            issues.push(ConfigValidationIssue::error(
+                FIELD,
+                format!("cubby {slot} is listed more than once"),
+            ));
+        }
+        if sled.bootstrap_ip.is_none() {
+            issues.push(ConfigValidationIssue::warning(
+                FIELD,
+                format!(
+                    "the sled in cubby {slot} hasn't been found on the \
+                     bootstrap network"
+                ),
+            ));
+        }
+        if !matches!(sled.baseboard, Baseboard::Gimlet { .. }) {
+            issues.push(ConfigValidationIssue::warning(
+                FIELD,
+                format!("the sled in cubby {slot} is not a Gimlet"),
+            ));
+        }
+    }
+}
+
+fn validate_rack_network_config(
+    config: &RackNetworkConfig,
+    issues: &mut Vec<ConfigValidationIssue>,
+) {
+    // The template fills in unspecified addresses until the user provides
+    // real ones.
+    for (field, ip) in [
+        ("rack_network_config.infra_ip_first", config.infra_ip_first),
+        ("rack_network_config.infra_ip_last", config.infra_ip_last),
+    ] {
+        if ip.is_unspecified() {
+            issues.push(ConfigValidationIssue::error(field, "must be set"));
+        }
+    }
+    if config.infra_ip_first > config.infra_ip_last {
+        issues.push(ConfigValidationIssue::error(
+            "rack_network_config.infra_ip_first",
+            format!(
+                "must not be after infra_ip_last ({})",
+                config.infra_ip_last
+            ),
+        ));
+    }
+
+    if config.uplinks.is_empty() {
+        issues.push(ConfigValidationIssue::error(
+            "rack_network_config.uplinks",
+            "at least one uplink is required",
+        ));
+    }
+
+    let mut ports = BTreeMap::new();
+    for (i, uplink) in config.uplinks.iter().enumerate() {
+        let field =
+            |name: &str| format!("rack_network_config.uplinks[{i}].{name}");
+
+        let uplink_ip = uplink.uplink_cidr.ip();
+        if uplink_ip < config.infra_ip_first || uplink_ip > config.infra_ip_last
+        {
+            issues.push(ConfigValidationIssue::error(
+                field("uplink_cidr"),
+                format!(
+                    "{uplink_ip} is not within infra_ip_first..infra_ip_last"
+                ),
+            ));
+        }
+        if !uplink.uplink_cidr.contains(uplink.gateway_ip) {
+            issues.push(ConfigValidationIssue::warning(
+                field("gateway_ip"),
+                format!(
+                    "{} is not within uplink_cidr {}",
+                    uplink.gateway_ip, uplink.uplink_cidr
+                ),
+            ));
+        }
+
+        if let Some(issue) = validate_port_speed_and_fec(
+            &uplink.uplink_port_speed,
+            &uplink.uplink_port_fec,
+            field,
+        ) {
+            issues.push(issue);
+        }
+
+        let port = (uplink.switch.to_string(), uplink.uplink_port.as_str());
+        if let Some(other) = ports.insert(port, i) {
+            issues.push(ConfigValidationIssue::error(
+                field("uplink_port"),
+                format!(
+                    "{} on {} is also used by uplink {other}",
+                    uplink.uplink_port, uplink.switch
+                ),
+            ));
+        }
+    }
+}
+
+// Check that the FEC setting of a port can be used with its speed.
+//
+// wicketd accepts any combination of speed and FEC, so unusual combinations are
+// only warnings: rejecting them here would refuse configs wicketd accepts.
+fn validate_port_speed_and_fec(
+    speed: &PortSpeed,
+    fec: &PortFec,
+    field: impl Fn(&str) -> String,
+) -> Option<ConfigValidationIssue> {
+    match (speed, fec) {
+        // An unset speed is almost certainly a mistake, but wicketd accepts
+        // it.
+        (PortSpeed::Speed0G, _) => Some(ConfigValidationIssue::warning(
+            field("uplink_port_speed"),
+            "is not set (0G)",
+        )),
+        // PAM4 links normally use RS FEC.
+        (PortSpeed::Speed200G | PortSpeed::Speed400G, PortFec::Rs) => None,
+        (PortSpeed::Speed200G | PortSpeed::Speed400G, _) => {
+            Some(ConfigValidationIssue::warning(
+                field("uplink_port_fec"),
+                format!("{speed} links normally use RS FEC, not {fec}"),
+            ))
+        }
+        // Firecode FEC isn't defined for 100G links, though a peer may still
+        // accept it.
+        (PortSpeed::Speed100G, PortFec::Firecode) => {
+            Some(ConfigValidationIssue::warning(
+                field("uplink_port_fec"),
+                format!("{speed} links normally use RS FEC, not Firecode"),
+            ))
+        }
+        _ => None,
+    }
+}
+
+// Return true if `ip` is within `range`.
+fn ip_range_contains(range: &IpRange, ip: IpAddr) -> bool {
+    match (range, ip) {
+        (IpRange::V4(r), IpAddr::V4(ip)) => r.first <= ip && ip <= r.last,
+        (IpRange::V6(r), IpAddr::V6(ip)) => r.first <= ip && ip <= r.last,
+        _ => false,
     }
 }
 
@@ -309,6 +729,7 @@ mod tests {
                 .collect(),
             external_dns_ips: value.external_dns_ips,
             ntp_servers: value.ntp_servers,
+            rack_subnet: value.rack_subnet,
             rack_network_config: InternalRackNetworkConfig {
                 infra_ip_first: rnc.infra_ip_first,
                 infra_ip_last: rnc.infra_ip_last,
@@ -356,9 +777,8 @@ mod tests {
         }
     }
 
-    #[test]
-    fn round_trip_nonempty_config() {
-        let config = CurrentRssUserConfigInsensitive {
+    fn nonempty_config() -> CurrentRssUserConfigInsensitive {
+        CurrentRssUserConfigInsensitive {
             bootstrap_sleds: vec![
                 BootstrapSledDescription {
                     id: SpIdentifier { slot: 1, type_: SpType::Sled },
@@ -392,6 +812,7 @@ mod tests {
             )],
             external_dns_ips: vec!["10.0.0.1".parse().unwrap()],
             ntp_servers: vec!["ntp1.com".into(), "ntp2.com".into()],
+            rack_subnet: Some("fd00:1122:3344:0100::".parse().unwrap()),
             rack_network_config: Some(RackNetworkConfig {
                 infra_ip_first: "172.30.0.1".parse().unwrap(),
                 infra_ip_last: "172.30.0.10".parse().unwrap(),
@@ -405,10 +826,311 @@ mod tests {
                     switch: SwitchLocation::Switch0,
                 }],
             }),
-        };
-        let template = TomlTemplate::populate(&config).to_string();
+        }
+    }
+
+    #[test]
+    fn round_trip_nonempty_config() {
+        let config = nonempty_config();
+        let template = TomlTemplate::populate(&config).unwrap().to_string();
+        let parsed: PutRssUserConfigInsensitive =
+            toml::de::from_str(&template).unwrap();
+        assert_eq!(put_config_from_current_config(config), parsed);
+    }
+
+    #[test]
+    fn round_trip_empty_network_config() {
+        let mut config = nonempty_config();
+        config.rack_network_config = None;
+
+        let template = TomlTemplate::populate(&config).unwrap().to_string();
+        assert!(
+            template.contains("# [[rack_network_config.uplinks]]\n"),
+            "example uplink commented out:\n{template}"
+        );
+
+        let parsed: PutRssUserConfigInsensitive =
+            toml::de::from_str(&template).unwrap();
+        assert_eq!(
+            parsed.rack_network_config,
+            InternalRackNetworkConfig {
+                infra_ip_first: Ipv4Addr::UNSPECIFIED,
+                infra_ip_last: Ipv4Addr::UNSPECIFIED,
+                uplinks: Vec::new(),
+            }
+        );
+
+        // Uploading that default and fetching the config again must render
+        // the same template.
+        config.rack_network_config = Some(RackNetworkConfig {
+            infra_ip_first: Ipv4Addr::UNSPECIFIED,
+            infra_ip_last: Ipv4Addr::UNSPECIFIED,
+            uplinks: Vec::new(),
+        });
+        let template = TomlTemplate::populate(&config).unwrap().to_string();
+        let reparsed: PutRssUserConfigInsensitive =
+            toml::de::from_str(&template).unwrap();
+        assert_eq!(parsed, reparsed);
+    }
+
+    #[test]
+    fn enum_to_toml_string_rejects_non_strings() {
+        #[derive(Debug, Serialize)]
+        #[serde(rename_all = "snake_case")]
+        enum Example {
+            Unit,
+            Newtype(u8),
+        }
+
+        assert_eq!(enum_to_toml_string(&Example::Unit).unwrap(), "unit");
+
+        let err = enum_to_toml_string(&Example::Newtype(3)).unwrap_err();
+        assert!(
+            err.to_string().contains("to serialize to a TOML string"),
+            "unexpected error: {err:#}"
+        );
+    }
+
+    #[test]
+    fn round_trip_unset_optional_keys() {
+        let mut config = nonempty_config();
+        config.rack_subnet = None;
+
+        let template = TomlTemplate::populate(&config).unwrap().to_string();
+        let subnet = template
+            .find("# rack_subnet = \"\"\n")
+            .expect("rack_subnet commented out");
+        let network = template.find("[rack_network_config]").unwrap();
+        assert!(
+            subnet < network,
+            "commented-out key in template order:\n{template}"
+        );
+
+        let parsed: PutRssUserConfigInsensitive =
+            toml::de::from_str(&template).unwrap();
+        assert_eq!(parsed.rack_subnet, None);
+        assert_eq!(put_config_from_current_config(config), parsed);
+    }
+
+    #[test]
+    fn round_trip_ipv6_internal_services_ip_pool_ranges() {
+        let mut config = nonempty_config();
+        // Production racks use IPv6 ranges; include a V4 range in the middle
+        // to check that mixed ranges keep their order.
+        config.internal_services_ip_pool_ranges = vec![
+            IpRange::V6(wicketd_client::types::Ipv6Range {
+                first: "fd00:1122:3344:1::1".parse().unwrap(),
+                last: "fd00:1122:3344:1::ff".parse().unwrap(),
+            }),
+            IpRange::V4(wicketd_client::types::Ipv4Range {
+                first: "10.0.0.1".parse().unwrap(),
+                last: "10.0.0.5".parse().unwrap(),
+            }),
+            IpRange::V6(wicketd_client::types::Ipv6Range {
+                first: "fd00:1122:3344:2::1".parse().unwrap(),
+                last: "fd00:1122:3344:2::1".parse().unwrap(),
+            }),
+        ];
+
+        let template = TomlTemplate::populate(&config).unwrap().to_string();
+        assert!(
+            template.contains(
+                r#"{ first = "fd00:1122:3344:1::1", last = "fd00:1122:3344:1::ff" }"#
+            ),
+            "V6 range present in template:\n{template}"
+        );
+
+        let parsed: PutRssUserConfigInsensitive =
+            toml::de::from_str(&template).unwrap();
+        assert!(
+            matches!(
+                parsed.internal_services_ip_pool_ranges.as_slice(),
+                [
+                    omicron_common::address::IpRange::V6(_),
+                    omicron_common::address::IpRange::V4(_),
+                    omicron_common::address::IpRange::V6(_),
+                ]
+            ),
+            "ranges parsed with the right versions in order: {:?}",
+            parsed.internal_services_ip_pool_ranges
+        );
+        assert_eq!(put_config_from_current_config(config), parsed);
+    }
+
+    #[test]
+    fn uplink_vid_comment() {
+        let mut config = nonempty_config();
+        let template = TomlTemplate::populate(&config).unwrap().to_string();
+        assert!(
+            !template.contains(UPLINK_VID_COMMENT),
+            "no VLAN ID comment without a VLAN ID:\n{template}"
+        );
+        assert!(
+            template.contains("\n# uplink_vid =\n"),
+            "VLAN ID placeholder present:\n{template}"
+        );
+
+        config.rack_network_config.as_mut().unwrap().uplinks[0].uplink_vid =
+            Some(100);
+        let template = TomlTemplate::populate(&config).unwrap().to_string();
+        assert!(
+            template
+                .contains(&format!("uplink_vid = 100{UPLINK_VID_COMMENT}\n")),
+            "VLAN ID comment present:\n{template}"
+        );
+
+        let parsed: PutRssUserConfigInsensitive =
+            toml::de::from_str(&template).unwrap();
+        assert_eq!(parsed.rack_network_config.uplinks[0].uplink_vid, Some(100));
+        assert_eq!(put_config_from_current_config(config), parsed);
+    }
+
+    #[test]
+    fn uplinks_grouped_by_switch() {
+        let mut config = nonempty_config();
+        let network_config = config.rack_network_config.as_mut().unwrap();
+        let uplink = network_config.uplinks[0].clone();
+        network_config.uplinks = [
+            ("port0", SwitchLocation::Switch0),
+            ("port1", SwitchLocation::Switch0),
+            ("port2", SwitchLocation::Switch1),
+            ("port3", SwitchLocation::Switch0),
+        ]
+        .into_iter()
+        .map(|(port, switch)| UplinkConfig {
+            uplink_port: port.into(),
+            switch,
+            ..uplink.clone()
+        })
+        .collect();
+
+        let template = TomlTemplate::populate(&config).unwrap().to_string();
+
+        // The uplinks stay in the operator's order, with a header each time
+        // the switch changes.
+        let mut rest = template.as_str();
+        for needle in [
+            "# Uplinks on switch0\n[[rack_network_config.uplinks]]\n",
+            "uplink_port = \"port0\"",
+            "uplink_port = \"port1\"",
+            "# Uplinks on switch1\n[[rack_network_config.uplinks]]\n",
+            "uplink_port = \"port2\"",
+            "# Uplinks on switch0\n[[rack_network_config.uplinks]]\n",
+            "uplink_port = \"port3\"",
+        ] {
+            let index = rest.find(needle).unwrap_or_else(|| {
+                panic!("{needle:?} in order in:\n{template}")
+            });
+            rest = &rest[index + needle.len()..];
+        }
+        assert_eq!(
+            template.matches("# Uplinks on").count(),
+            3,
+            "one header per run of uplinks on the same switch:\n{template}"
+        );
+
+        // Parsing the template yields the same uplinks, in the same order.
         let parsed: PutRssUserConfigInsensitive =
             toml::de::from_str(&template).unwrap();
         assert_eq!(put_config_from_current_config(config), parsed);
     }
+
+    #[test]
+    fn validate_rss_config_reports_every_issue() {
+        use ValidationSeverity::Error;
+        use ValidationSeverity::Warning;
+
+        let mut config = nonempty_config();
+        // The second sled is listed twice, and the first hasn't been found.
+        let duplicate = config.bootstrap_sleds[1].clone();
+        config.bootstrap_sleds.push(duplicate);
+        config.ntp_servers.clear();
+        config.external_dns_zone_name.clear();
+        config.external_dns_ips =
+            vec!["10.0.0.1".parse().unwrap(), "10.0.0.9".parse().unwrap()];
+        let network_config = config.rack_network_config.as_mut().unwrap();
+        let uplink = &mut network_config.uplinks[0];
+        uplink.uplink_cidr = "172.30.1.1/24".parse().unwrap();
+        let second_uplink = UplinkConfig {
+            uplink_port_speed: PortSpeed::Speed100G,
+            uplink_port_fec: PortFec::Firecode,
+            ..uplink.clone()
+        };
+        network_config.uplinks.push(second_uplink);
+
+        let issues = validate_rss_config(&config);
+        let found: Vec<_> = issues
+            .iter()
+            .map(|issue| (issue.field.as_str(), issue.severity))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("bootstrap_sleds", Warning),
+                ("bootstrap_sleds", Error),
+                ("ntp_servers", Error),
+                ("external_dns_zone_name", Error),
+                ("external_dns_ips[1]", Error),
+                ("rack_network_config.uplinks[0].uplink_cidr", Error),
+                ("rack_network_config.uplinks[0].gateway_ip", Warning),
+                ("rack_network_config.uplinks[0].uplink_port_fec", Warning),
+                ("rack_network_config.uplinks[1].uplink_cidr", Error),
+                ("rack_network_config.uplinks[1].gateway_ip", Warning),
+                ("rack_network_config.uplinks[1].uplink_port_fec", Warning),
+                ("rack_network_config.uplinks[1].uplink_port", Error),
+            ],
+            "unexpected issues: {issues:#?}"
+        );
+        assert!(
+            issues[4].message.contains("10.0.0.9"),
+            "unexpected message: {}",
+            issues[4]
+        );
+
+        // A missing network config is reported as a single issue, rather than
+        // one for each of its fields.
+        config.rack_network_config = None;
+        let issues = validate_rss_config(&config);
+        assert_eq!(
+            issues.last().map(|issue| issue.field.as_str()),
+            Some("rack_network_config")
+        );
+
+        // Fixing everything leaves nothing to report.
+        let mut config = nonempty_config();
+        for sled in config.bootstrap_sleds.iter_mut() {
+            sled.bootstrap_ip = Some(Ipv6Addr::LOCALHOST);
+        }
+        let uplink =
+            &mut config.rack_network_config.as_mut().unwrap().uplinks[0];
+        uplink.uplink_port_fec = PortFec::Rs;
+        assert_eq!(validate_rss_config(&config), Vec::new());
+    }
+
+    #[test]
+    fn unset_port_speed_is_a_warning() {
+        let mut config = nonempty_config();
+        for sled in config.bootstrap_sleds.iter_mut() {
+            sled.bootstrap_ip = Some(Ipv6Addr::LOCALHOST);
+        }
+        let uplink =
+            &mut config.rack_network_config.as_mut().unwrap().uplinks[0];
+        uplink.uplink_port_speed = PortSpeed::Speed0G;
+        uplink.uplink_port_fec = PortFec::Rs;
+
+        // wicketd accepts an unset speed, so it mustn't block rack setup.
+        let issues = validate_rss_config(&config);
+        let found: Vec<_> = issues
+            .iter()
+            .map(|issue| (issue.field.as_str(), issue.severity))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(
+                "rack_network_config.uplinks[0].uplink_port_speed",
+                ValidationSeverity::Warning
+            )],
+            "unexpected issues: {issues:#?}"
+        );
+    }
 }
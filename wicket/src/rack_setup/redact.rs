@@ -0,0 +1,108 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Redaction of sensitive rack setup config values, so they never end up in
+//! wicket's logs.
+
+use std::fmt;
+use wicketd_client::types::CurrentRssUserConfig;
+use wicketd_client::types::CurrentRssUserConfigSensitive;
+
+/// A value that must not be logged.
+///
+/// Both `Debug` and `Display` render the value as `<redacted>`.
+#[derive(Clone, Copy)]
+pub(crate) struct Redacted<T>(pub(crate) T);
+
+impl<T> fmt::Debug for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl<T> fmt::Display for Redacted<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// A rack setup config with its sensitive fields redacted, for logging.
+pub(crate) struct LoggedRssConfig<'a>(pub(crate) &'a CurrentRssUserConfig);
+
+impl fmt::Debug for LoggedRssConfig<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Destructure the config, so that adding a field forces us to decide
+        // whether it's sensitive.
+        let CurrentRssUserConfig { insensitive, sensitive } = self.0;
+        f.debug_struct("CurrentRssUserConfig")
+            .field("insensitive", insensitive)
+            .field("sensitive", &Redacted(sensitive))
+            .finish()
+    }
+}
+
+/// Return a copy of `config` that is safe to write into debug dumps.
+///
+/// A replayed dump should show the state wicket really saw, so we only drop
+/// values that are secret. None of the current fields are: the sensitive part
+/// of the config only says whether certificates and a password were uploaded.
+pub(crate) fn redact_rss_config(
+    config: &CurrentRssUserConfig,
+) -> CurrentRssUserConfig {
+    // Destructure the config, so that adding a field forces us to decide
+    // whether it's secret.
+    let CurrentRssUserConfig { insensitive, sensitive } = config;
+    let CurrentRssUserConfigSensitive {
+        num_external_certificates,
+        recovery_silo_password_set,
+    } = sensitive;
+    CurrentRssUserConfig {
+        insensitive: insensitive.clone(),
+        sensitive: CurrentRssUserConfigSensitive {
+            num_external_certificates: *num_external_certificates,
+            recovery_silo_password_set: *recovery_silo_password_set,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wicketd_client::types::CurrentRssUserConfigInsensitive;
+    use wicketd_client::types::CurrentRssUserConfigSensitive;
+
+    #[test]
+    fn sensitive_fields_are_redacted() {
+        let config = CurrentRssUserConfig {
+            insensitive: CurrentRssUserConfigInsensitive {
+                bootstrap_sleds: Vec::new(),
+                dns_servers: Vec::new(),
+                external_dns_zone_name: "oxide.test".into(),
+                internal_services_ip_pool_ranges: Vec::new(),
+                external_dns_ips: Vec::new(),
+                ntp_servers: vec!["ntp.oxide.test".into()],
+                rack_subnet: None,
+                rack_network_config: None,
+            },
+            sensitive: CurrentRssUserConfigSensitive {
+                num_external_certificates: 3,
+                recovery_silo_password_set: true,
+            },
+        };
+
+        let logged = format!("{:?}", LoggedRssConfig(&config));
+        assert!(logged.contains("ntp.oxide.test"), "{logged}");
+        assert!(logged.contains("oxide.test"), "{logged}");
+        assert!(logged.contains("sensitive: <redacted>"), "{logged}");
+        assert!(!logged.contains("num_external_certificates"), "{logged}");
+        assert!(!logged.contains("recovery_silo_password_set"), "{logged}");
+
+        assert_eq!(format!("{}", Redacted("hunter2")), "<redacted>");
+        assert_eq!(format!("{:?}", Redacted("hunter2")), "<redacted>");
+
+        // Dumps keep the whole config: none of it is secret, and a replayed
+        // dump must show the real state.
+        assert_eq!(redact_rss_config(&config), config);
+    }
+}
@@ -4,6 +4,7 @@
 
 use anyhow::bail;
 use anyhow::Context;
+use camino::Utf8PathBuf;
 use crossterm::event::Event as TermEvent;
 use crossterm::event::EventStream;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
@@ -16,7 +17,7 @@ use futures::StreamExt;
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 use slog::Logger;
-use slog::{debug, error, info};
+use slog::{debug, error, info, warn};
 use std::env::VarError;
 use std::io::{stdout, Stdout};
 use std::net::SocketAddrV6;
@@ -32,6 +33,8 @@ use wicketd_client::types::UpdateSimulatedResult;
 use wicketd_client::types::UpdateTestError;
 
 use crate::events::EventReportMap;
+use crate::keymap::ShowPopupCmd;
+use crate::state::RackUpdateState;
 use crate::ui::Screen;
 use crate::wicketd::{self, WicketdHandle, WicketdManager};
 use crate::{Action, Cmd, Event, KeyHandler, Recorder, State, TICK_INTERVAL};
@@ -119,6 +122,17 @@ impl RunnerCore {
                         }
                     }
                 } else {
+                    // Record start failures regardless of which screen or
+                    // popup (if any) is displayed, so they aren't lost.
+                    if let Cmd::ShowPopup(ShowPopupCmd::StartUpdateResponse {
+                        component_id,
+                        response: Err(message),
+                    }) = &cmd
+                    {
+                        self.state
+                            .update_state
+                            .start_failed(*component_id, message.clone());
+                    }
                     let action = self.screen.on(&mut self.state, cmd);
                     self.handle_action(action, wicketd)?;
                 }
@@ -133,6 +147,10 @@ impl RunnerCore {
                 self.state.inventory.update_inventory(inventory)?;
                 self.screen.draw(&self.state, &mut self.terminal)?;
             }
+            Event::SpCabooses { component_id, cabooses } => {
+                self.state.inventory.merge_cabooses(component_id, cabooses);
+                self.screen.draw(&self.state, &mut self.terminal)?;
+            }
             Event::ArtifactsAndEventReports {
                 system_version,
                 artifacts,
@@ -179,6 +197,24 @@ impl RunnerCore {
                 self.screen.draw(&self.state, &mut self.terminal)?;
             }
             Action::StartUpdate(component_id) => {
+                // Never start an update from a malformed repository, even if
+                // the action was produced before the repository changed.
+                if let Some(message) =
+                    self.state.update_state.start_blocked_reason()
+                {
+                    warn!(self.log, "Not updating {component_id}: {message}");
+                    self.state
+                        .update_state
+                        .start_failed(component_id, message.clone());
+                    let action = self.screen.on(
+                        &mut self.state,
+                        Cmd::ShowPopup(ShowPopupCmd::StartUpdateResponse {
+                            component_id,
+                            response: Err(message),
+                        }),
+                    );
+                    return self.handle_action(action, wicketd);
+                }
                 if let Some(wicketd) = wicketd {
                     let test_error = get_update_test_error(
                         "WICKET_TEST_START_UPDATE_ERROR",
@@ -216,6 +252,15 @@ impl RunnerCore {
                             .state
                             .force_update_state
                             .force_update_sp,
+                        fail_on_unparseable_sp_version: false,
+                        pause_before_host_boot: false,
+                        verify_host_boot_slot: false,
+                        installinator_start_timeout_secs: None,
+                        mgs_status_poll_interval_ms: None,
+                        mgs_installinator_poll_interval_ms: None,
+                        trampoline_phase_2_upload_max_attempts: None,
+                        host_only: false,
+                        update_groups: Vec::new(),
                     };
                     wicketd.tx.blocking_send(
                         wicketd::Request::StartUpdate { component_id, options },
@@ -230,6 +275,7 @@ impl RunnerCore {
 
                     let options = AbortUpdateOptions {
                         message: "Aborted by wicket user".to_owned(),
+                        clear_state: false,
                         test_error,
                     };
                     wicketd.tx.blocking_send(
@@ -252,6 +298,25 @@ impl RunnerCore {
                     )?;
                 }
             }
+            Action::ClearAllUpdateState(component_ids) => {
+                if let Some(wicketd) = wicketd {
+                    let test_error = get_update_test_error(
+                        "WICKET_TEST_CLEAR_UPDATE_STATE_ERROR",
+                    )?;
+
+                    for component_id in component_ids {
+                        let options = ClearUpdateStateOptions {
+                            test_error: test_error.clone(),
+                        };
+                        wicketd.tx.blocking_send(
+                            wicketd::Request::ClearUpdateState {
+                                component_id,
+                                options,
+                            },
+                        )?;
+                    }
+                }
+            }
             Action::Ignition(component_id, ignition_command) => {
                 if let Some(wicketd) = wicketd {
                     wicketd.tx.blocking_send(
@@ -262,6 +327,13 @@ impl RunnerCore {
                     )?;
                 }
             }
+            Action::RefreshCabooses(component_id) => {
+                if let Some(wicketd) = wicketd {
+                    wicketd.tx.blocking_send(
+                        wicketd::Request::RefreshCabooses(component_id),
+                    )?;
+                }
+            }
             Action::StartRackSetup => {
                 if let Some(wicketd) = wicketd {
                     wicketd
@@ -281,6 +353,37 @@ impl RunnerCore {
     }
 }
 
+/// Returns where the update state is saved between wicket sessions.
+///
+/// This is `WICKET_UPDATE_STATE_PATH` if set, and otherwise
+/// `wicket/update-state.cbor` in the current user's state directory
+/// (`$XDG_STATE_HOME`, or `~/.local/state` if that isn't set).
+fn update_state_path() -> anyhow::Result<Utf8PathBuf> {
+    if let Some(path) = env_path("WICKET_UPDATE_STATE_PATH")? {
+        return Ok(path);
+    }
+    let state_dir = match env_path("XDG_STATE_HOME")? {
+        // Relative paths in XDG_STATE_HOME are invalid and must be ignored.
+        Some(dir) if dir.is_absolute() => dir,
+        _ => match env_path("HOME")? {
+            Some(home) => home.join(".local/state"),
+            None => bail!("neither XDG_STATE_HOME nor HOME is set"),
+        },
+    };
+    Ok(state_dir.join("wicket/update-state.cbor"))
+}
+
+fn env_path(env_var: &str) -> anyhow::Result<Option<Utf8PathBuf>> {
+    match std::env::var(env_var) {
+        Ok(path) if path.is_empty() => Ok(None),
+        Ok(path) => Ok(Some(path.into())),
+        Err(VarError::NotPresent) => Ok(None),
+        Err(VarError::NotUnicode(_)) => {
+            bail!("{env_var} is not valid unicode");
+        }
+    }
+}
+
 fn get_update_test_error(
     env_var: &str,
 ) -> Result<Option<UpdateTestError>, anyhow::Error> {
@@ -367,6 +470,15 @@ pub struct Runner {
 
     // A recorder for debugging the history of events for use in a debugger.
     recorder: Recorder,
+
+    // Where the update state is persisted between wicket sessions, if
+    // anywhere.
+    update_state_path: Option<Utf8PathBuf>,
+
+    // The update state as it was last persisted (or restored), serialized by
+    // `RackUpdateState::to_saved`, so it's only written out again when it
+    // changes.
+    saved_update_state: Option<Vec<u8>>,
 }
 
 #[allow(clippy::new_without_default)]
@@ -379,7 +491,37 @@ impl Runner {
             .unwrap();
         let (wicketd, wicketd_manager) =
             WicketdManager::new(&log, events_tx.clone(), wicketd_addr);
-        let core = RunnerCore::new(log);
+        let mut core = RunnerCore::new(log);
+
+        let update_state_path = match update_state_path() {
+            Ok(path) => Some(path),
+            Err(error) => {
+                warn!(core.log, "not persisting update state: {error:#}");
+                None
+            }
+        };
+
+        let mut saved_update_state = None;
+        // Show the update state from a previous session (if any) until fresh
+        // data arrives from wicketd.
+        if let Some(path) = &update_state_path {
+            if path.exists() {
+                match RackUpdateState::load(path) {
+                    Ok(update_state) => {
+                        info!(core.log, "restored update state from {path}");
+                        saved_update_state = update_state.to_saved().ok();
+                        core.state.update_state = update_state;
+                    }
+                    Err(error) => {
+                        warn!(
+                            core.log,
+                            "failed to restore update state: {error:#}"
+                        );
+                    }
+                }
+            }
+        }
+
         Runner {
             core,
             events_rx,
@@ -388,6 +530,8 @@ impl Runner {
             wicketd_manager: Some(wicketd_manager),
             tokio_rt,
             recorder: Recorder::new(MAX_RECORDED_EVENTS),
+            update_state_path,
+            saved_update_state,
         }
     }
 
@@ -409,6 +553,11 @@ impl Runner {
             // unwrap is safe because we always hold onto a UnboundedSender
             let event = self.events_rx.blocking_recv().unwrap();
             self.recorder.push(&self.core.state, event.clone());
+            // The saved parts of the update state are built from the
+            // artifacts and event reports wicketd sends us, so only check
+            // whether they need saving after one of those arrives.
+            let save_update_state =
+                matches!(event, Event::ArtifactsAndEventReports { .. });
             if self.core.handle_event(
                 event,
                 Some(&mut self.recorder),
@@ -417,10 +566,35 @@ impl Runner {
                 // Event::Shutdown received
                 break;
             }
+            if save_update_state {
+                self.save_update_state();
+            }
         }
         Ok(())
     }
 
+    fn save_update_state(&mut self) {
+        let Some(path) = &self.update_state_path else {
+            return;
+        };
+        let saved = match self.core.state.update_state.to_saved() {
+            Ok(saved) => saved,
+            Err(error) => {
+                warn!(self.core.log, "failed to save update state: {error:#}");
+                return;
+            }
+        };
+        if self.saved_update_state.as_ref() == Some(&saved) {
+            return;
+        }
+        // Don't retry a failed save until the state changes again, so a
+        // failure isn't logged on every report.
+        if let Err(error) = RackUpdateState::write_saved(path, &saved) {
+            warn!(self.core.log, "failed to save update state: {error:#}");
+        }
+        self.saved_update_state = Some(saved);
+    }
+
     fn start_tokio_runtime(&mut self) {
         let events_tx = self.events_tx.clone();
         let log = self.core.log.clone();
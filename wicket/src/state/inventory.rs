@@ -4,15 +4,16 @@
 
 //! Information about all top-level Oxide components (sleds, switches, PSCs)
 
-use anyhow::anyhow;
 use once_cell::sync::Lazy;
 use ratatui::text::Text;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::iter::Iterator;
+use std::time::SystemTime;
+use thiserror::Error;
 use wicketd_client::types::{
-    RackV1Inventory, RotInventory, RotSlot, SpComponentCaboose,
+    RackV1Inventory, RotInventory, RotSlot, SpCabooses, SpComponentCaboose,
     SpComponentInfo, SpIgnition, SpState, SpType,
 };
 
@@ -27,7 +28,7 @@ pub static ALL_COMPONENT_IDS: Lazy<Vec<ComponentId>> = Lazy::new(|| {
 
 /// Inventory is the most recent information about rack composition as
 /// received from MGS.
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Inventory {
     power: BTreeMap<ComponentId, PowerState>,
     inventory: BTreeMap<ComponentId, Component>,
@@ -46,6 +47,15 @@ impl Inventory {
         self.inventory.keys()
     }
 
+    /// Return the ids of the components in [`ALL_COMPONENT_IDS`] which are
+    /// present in the inventory, in the same order.
+    pub fn present_components(&self) -> impl Iterator<Item = ComponentId> + '_ {
+        ALL_COMPONENT_IDS
+            .iter()
+            .copied()
+            .filter(|id| self.inventory.contains_key(id))
+    }
+
     pub fn update_inventory(
         &mut self,
         inventory: RackV1Inventory,
@@ -64,26 +74,11 @@ impl Inventory {
                 rot: sp.rot,
             };
 
-            // Validate and get a ComponentId
-            let (id, component) = match type_ {
-                SpType::Sled => {
-                    if i > 31 {
-                        return Err(anyhow!("Invalid sled slot: {}", i));
-                    }
-                    (ComponentId::Sled(i as u8), Component::Sled(sp))
-                }
-                SpType::Switch => {
-                    if i > 1 {
-                        return Err(anyhow!("Invalid switch slot: {}", i));
-                    }
-                    (ComponentId::Switch(i as u8), Component::Switch(sp))
-                }
-                SpType::Power => {
-                    if i > 1 {
-                        return Err(anyhow!("Invalid power shelf slot: {}", i));
-                    }
-                    (ComponentId::Psc(i as u8), Component::Psc(sp))
-                }
+            let id = ComponentId::try_new(type_, i)?;
+            let component = match id {
+                ComponentId::Sled(_) => Component::Sled(sp),
+                ComponentId::Switch(_) => Component::Switch(sp),
+                ComponentId::Psc(_) => Component::Psc(sp),
             };
             new_inventory.inventory.insert(id, component);
 
@@ -96,11 +91,180 @@ impl Inventory {
 
         Ok(())
     }
+
+    /// Return a pretty-printed JSON snapshot of everything in the inventory,
+    /// for attaching to support tickets.
+    ///
+    /// The snapshot records when it was taken, followed by each component in
+    /// order, with its power state and full SP details (including every
+    /// caboose). The layout is stable, so snapshots taken over time can be
+    /// diffed.
+    pub fn snapshot_json(&self) -> String {
+        let snapshot = InventorySnapshot {
+            taken_at: humantime::format_rfc3339_seconds(SystemTime::now())
+                .to_string(),
+            components: self
+                .inventory
+                .iter()
+                .map(|(id, component)| ComponentSnapshot {
+                    id: *id,
+                    power_state: self.power.get(id).copied(),
+                    component,
+                })
+                .collect(),
+        };
+        // Unwrap: every map in the snapshot is keyed by strings, so this can't
+        // fail.
+        serde_json::to_string_pretty(&snapshot).unwrap()
+    }
+
+    /// Return the components running a different version of an SP or RoT
+    /// image than most components of the same type, e.g., a sled left behind
+    /// by a partial update.
+    ///
+    /// The active SP image and both RoT slots are each compared separately.
+    /// Unknown versions are ignored. If no single version of an image is the
+    /// most common for a type, there's no majority to compare against, and
+    /// nothing is reported for that image.
+    pub fn version_outliers(&self) -> Vec<(ComponentId, VersionMismatch)> {
+        let mut outliers = Vec::new();
+        for image in FirmwareImage::ALL {
+            // The known versions of this image, grouped by component type.
+            let mut groups: [Vec<(ComponentId, String)>; 3] =
+                Default::default();
+            for (id, component) in self.inventory.iter() {
+                let version = image.version(component);
+                if version == UNKNOWN_VERSION {
+                    continue;
+                }
+                let group = match id {
+                    ComponentId::Sled(_) => 0,
+                    ComponentId::Switch(_) => 1,
+                    ComponentId::Psc(_) => 2,
+                };
+                groups[group].push((*id, version));
+            }
+
+            for group in groups {
+                let Some(majority_version) = majority_version(&group) else {
+                    continue;
+                };
+                outliers.extend(
+                    group
+                        .into_iter()
+                        .filter(|(_, version)| *version != majority_version)
+                        .map(|(id, version)| {
+                            let mismatch = VersionMismatch {
+                                image,
+                                version,
+                                majority_version: majority_version.clone(),
+                            };
+                            (id, mismatch)
+                        }),
+                );
+            }
+        }
+        outliers.sort_by_key(|(id, mismatch)| (*id, mismatch.image));
+        outliers
+    }
+
+    /// Merge freshly-read cabooses for a single component into the inventory.
+    ///
+    /// Cabooses that could not be read (i.e., are `None` in `cabooses`) leave
+    /// the existing values in place; the next full inventory update will
+    /// reconcile them.
+    pub fn merge_cabooses(&mut self, id: ComponentId, cabooses: SpCabooses) {
+        let Some(component) = self.inventory.get_mut(&id) else {
+            return;
+        };
+        let sp = component.sp_mut();
+        let SpCabooses { sp_active, sp_inactive, rot_a, rot_b } = cabooses;
+        if sp_active.is_some() {
+            sp.caboose_active = sp_active;
+        }
+        if sp_inactive.is_some() {
+            sp.caboose_inactive = sp_inactive;
+        }
+        if let Some(rot) = sp.rot.as_mut() {
+            if rot_a.is_some() {
+                rot.caboose_a = rot_a;
+            }
+            if rot_b.is_some() {
+                rot.caboose_b = rot_b;
+            }
+        }
+    }
+}
+
+// Return the single most common version among `versions`, if there is one.
+fn majority_version(versions: &[(ComponentId, String)]) -> Option<String> {
+    let mut counts = BTreeMap::new();
+    for (_, version) in versions {
+        *counts.entry(version.as_str()).or_insert(0usize) += 1;
+    }
+    let max = counts.values().copied().max()?;
+    let mut most_common = counts.into_iter().filter(|(_, n)| *n == max);
+    let (version, _) = most_common.next()?;
+    if most_common.next().is_some() {
+        return None;
+    }
+    Some(version.to_string())
+}
+
+/// A firmware image of a component whose version can be compared with the
+/// same image on other components.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FirmwareImage {
+    /// The active SP image.
+    SpActive,
+    /// The RoT image in slot A.
+    RotA,
+    /// The RoT image in slot B.
+    RotB,
+}
+
+impl FirmwareImage {
+    pub const ALL: [FirmwareImage; 3] =
+        [FirmwareImage::SpActive, FirmwareImage::RotA, FirmwareImage::RotB];
+
+    fn version(&self, component: &Component) -> String {
+        match self {
+            FirmwareImage::SpActive => component.sp_version_active(),
+            FirmwareImage::RotA => component.rot_version_a(),
+            FirmwareImage::RotB => component.rot_version_b(),
+        }
+    }
+}
+
+/// A component's version of an image, which differs from the version on most
+/// components of the same type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionMismatch {
+    pub image: FirmwareImage,
+    pub version: String,
+    pub majority_version: String,
+}
+
+// The document produced by `Inventory::snapshot_json()`.
+//
+// This isn't just `Inventory`, whose maps are keyed by `ComponentId` and so
+// can't be represented in JSON.
+#[derive(Debug, Serialize)]
+struct InventorySnapshot<'a> {
+    taken_at: String,
+    components: Vec<ComponentSnapshot<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ComponentSnapshot<'a> {
+    id: ComponentId,
+    power_state: Option<PowerState>,
+    component: &'a Component,
 }
 
 // We just print the debug info on the screen for now
 #[allow(unused)]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Sp {
     ignition: Option<SpIgnition>,
     state: Option<SpState>,
@@ -140,15 +304,21 @@ impl Sp {
 }
 
 // XXX: Eventually a Sled will have a host component.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Component {
     Sled(Sp),
     Switch(Sp),
     Psc(Sp),
 }
 
+// The version reported for an image whose caboose couldn't be read.
+const UNKNOWN_VERSION: &str = "UNKNOWN";
+
 fn version_or_unknown(caboose: Option<&SpComponentCaboose>) -> String {
-    caboose.and_then(|c| c.version.as_deref()).unwrap_or("UNKNOWN").to_string()
+    caboose
+        .and_then(|c| c.version.as_deref())
+        .unwrap_or(UNKNOWN_VERSION)
+        .to_string()
 }
 
 impl Component {
@@ -160,6 +330,14 @@ impl Component {
         }
     }
 
+    fn sp_mut(&mut self) -> &mut Sp {
+        match self {
+            Component::Sled(sp) => sp,
+            Component::Switch(sp) => sp,
+            Component::Psc(sp) => sp,
+        }
+    }
+
     pub fn sp_version_active(&self) -> String {
         version_or_unknown(self.sp().caboose_active.as_ref())
     }
@@ -205,11 +383,56 @@ pub enum ComponentId {
 }
 
 impl ComponentId {
+    /// The largest valid sled slot.
+    pub const MAX_SLED_SLOT: u8 = 31;
+    /// The largest valid switch slot.
+    pub const MAX_SWITCH_SLOT: u8 = 1;
+    /// The largest valid power shelf slot.
+    pub const MAX_PSC_SLOT: u8 = 1;
+
+    /// Construct a `ComponentId` from an SP type and slot, as reported by
+    /// MGS, checking that the slot is in range for the type.
+    pub fn try_new(
+        sp_type: SpType,
+        slot: u32,
+    ) -> Result<Self, InvalidComponentId> {
+        let max = match sp_type {
+            SpType::Sled => Self::MAX_SLED_SLOT,
+            SpType::Switch => Self::MAX_SWITCH_SLOT,
+            SpType::Power => Self::MAX_PSC_SLOT,
+        };
+        let i = match u8::try_from(slot) {
+            Ok(i) if i <= max => i,
+            _ => return Err(InvalidComponentId { sp_type, slot }),
+        };
+        Ok(match sp_type {
+            SpType::Sled => ComponentId::Sled(i),
+            SpType::Switch => ComponentId::Switch(i),
+            SpType::Power => ComponentId::Psc(i),
+        })
+    }
+
     pub fn name(&self) -> String {
         self.to_string()
     }
 }
 
+/// An SP slot that is out of range for its type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("invalid {} slot: {slot}", sp_type_description(*.sp_type))]
+pub struct InvalidComponentId {
+    pub sp_type: SpType,
+    pub slot: u32,
+}
+
+fn sp_type_description(sp_type: SpType) -> &'static str {
+    match sp_type {
+        SpType::Sled => "sled",
+        SpType::Switch => "switch",
+        SpType::Power => "power shelf",
+    }
+}
+
 impl Display for ComponentId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -234,17 +457,18 @@ pub struct ParsableComponentId<'a> {
 impl<'a> TryFrom<ParsableComponentId<'a>> for ComponentId {
     type Error = ();
     fn try_from(value: ParsableComponentId<'a>) -> Result<Self, Self::Error> {
-        let i: u8 = value.i.parse().map_err(|_| ())?;
-        match (value.sp_type, i) {
-            ("sled", 0..=31) => Ok(ComponentId::Sled(i)),
-            ("switch", 0..=1) => Ok(ComponentId::Switch(i)),
-            ("power", 0..=1) => Ok(ComponentId::Psc(i)),
-            _ => Err(()),
-        }
+        let sp_type = match value.sp_type {
+            "sled" => SpType::Sled,
+            "switch" => SpType::Switch,
+            "power" => SpType::Power,
+            _ => return Err(()),
+        };
+        let slot: u32 = value.i.parse().map_err(|_| ())?;
+        ComponentId::try_new(sp_type, slot).map_err(|_| ())
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PowerState {
     /// Working
     A0,
@@ -269,3 +493,264 @@ impl PowerState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wicketd_client::types::{SpIdentifier, SpInventory};
+
+    fn caboose(version: &str) -> SpComponentCaboose {
+        SpComponentCaboose {
+            board: "test-board".to_owned(),
+            git_commit: "0000000".to_owned(),
+            name: "test".to_owned(),
+            version: Some(version.to_owned()),
+        }
+    }
+
+    fn sp_inventory(type_: SpType, slot: u32) -> SpInventory {
+        SpInventory {
+            id: SpIdentifier { type_, slot },
+            ignition: None,
+            state: None,
+            components: None,
+            caboose_active: Some(caboose("1.0.0")),
+            caboose_inactive: Some(caboose("0.9.0")),
+            rot: Some(RotInventory {
+                active: RotSlot::A,
+                caboose_a: Some(caboose("1.0.0")),
+                caboose_b: Some(caboose("0.9.0")),
+            }),
+        }
+    }
+
+    #[test]
+    fn merge_cabooses_only_changes_one_component() {
+        let mut inventory = Inventory::default();
+        inventory
+            .update_inventory(RackV1Inventory {
+                sps: vec![
+                    sp_inventory(SpType::Sled, 0),
+                    sp_inventory(SpType::Sled, 1),
+                    sp_inventory(SpType::Switch, 0),
+                ],
+            })
+            .unwrap();
+        let before = inventory.clone();
+
+        // Sled 1 was just updated: its SP and RoT slot B have new versions.
+        // The inactive SP slot couldn't be read, so it's left alone.
+        inventory.merge_cabooses(
+            ComponentId::Sled(1),
+            SpCabooses {
+                sp_active: Some(caboose("2.0.0")),
+                sp_inactive: None,
+                rot_a: Some(caboose("1.0.0")),
+                rot_b: Some(caboose("2.0.0")),
+            },
+        );
+
+        let sled1 = inventory.get_inventory(&ComponentId::Sled(1)).unwrap();
+        assert_eq!(sled1.sp_version_active(), "2.0.0");
+        assert_eq!(sled1.sp_version_inactive(), "0.9.0");
+        assert_eq!(sled1.rot_version_a(), "1.0.0");
+        assert_eq!(sled1.rot_version_b(), "2.0.0");
+
+        // Everything else is unchanged.
+        for id in [ComponentId::Sled(0), ComponentId::Switch(0)] {
+            assert_eq!(
+                inventory.get_inventory(&id),
+                before.get_inventory(&id),
+                "{id} is unchanged"
+            );
+        }
+        assert_eq!(inventory.components().count(), 3);
+
+        // Merging into a component that isn't in the inventory is a no-op.
+        let after = inventory.clone();
+        inventory.merge_cabooses(
+            ComponentId::Sled(2),
+            SpCabooses {
+                sp_active: Some(caboose("2.0.0")),
+                sp_inactive: None,
+                rot_a: None,
+                rot_b: None,
+            },
+        );
+        assert_eq!(inventory, after);
+    }
+
+    #[test]
+    fn present_components_in_canonical_order() {
+        let mut inventory = Inventory::default();
+        assert_eq!(inventory.present_components().count(), 0);
+
+        // MGS may report components in any order.
+        inventory
+            .update_inventory(RackV1Inventory {
+                sps: vec![
+                    sp_inventory(SpType::Power, 0),
+                    sp_inventory(SpType::Switch, 1),
+                    sp_inventory(SpType::Sled, 16),
+                    sp_inventory(SpType::Sled, 3),
+                ],
+            })
+            .unwrap();
+        assert_eq!(
+            inventory.present_components().collect::<Vec<_>>(),
+            vec![
+                ComponentId::Sled(3),
+                ComponentId::Sled(16),
+                ComponentId::Switch(1),
+                ComponentId::Psc(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn snapshot_json_includes_every_component() {
+        let mut inventory = Inventory::default();
+        inventory
+            .update_inventory(RackV1Inventory {
+                sps: vec![
+                    sp_inventory(SpType::Switch, 0),
+                    sp_inventory(SpType::Sled, 7),
+                ],
+            })
+            .unwrap();
+
+        let snapshot: serde_json::Value =
+            serde_json::from_str(&inventory.snapshot_json()).unwrap();
+        let taken_at = snapshot["taken_at"].as_str().unwrap();
+        humantime::parse_rfc3339(taken_at).expect("valid timestamp");
+
+        let components = snapshot["components"].as_array().unwrap();
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0]["id"], serde_json::json!({ "Sled": 7 }));
+        assert_eq!(components[1]["id"], serde_json::json!({ "Switch": 0 }));
+        for component in components {
+            assert_eq!(component["power_state"], "A2");
+            let (kind, sp) = component["component"]
+                .as_object()
+                .unwrap()
+                .iter()
+                .next()
+                .unwrap();
+            assert!(kind == "Sled" || kind == "Switch", "unexpected {kind}");
+            for key in [
+                "ignition",
+                "state",
+                "caboose_active",
+                "caboose_inactive",
+                "components",
+                "rot",
+            ] {
+                assert!(sp.get(key).is_some(), "{kind} snapshot has {key}");
+            }
+            assert_eq!(sp["caboose_active"]["version"], "1.0.0");
+            assert_eq!(sp["caboose_inactive"]["version"], "0.9.0");
+            assert_eq!(sp["rot"]["caboose_b"]["version"], "0.9.0");
+        }
+    }
+
+    #[test]
+    fn version_outliers_reports_minority_versions() {
+        let mut inventory = Inventory::default();
+        inventory
+            .update_inventory(RackV1Inventory {
+                sps: vec![
+                    sp_inventory(SpType::Sled, 0),
+                    sp_inventory(SpType::Sled, 1),
+                    sp_inventory(SpType::Sled, 2),
+                    // Switches are only compared with each other, and with
+                    // one of each version there's no majority.
+                    sp_inventory(SpType::Switch, 0),
+                    sp_inventory(SpType::Switch, 1),
+                ],
+            })
+            .unwrap();
+        assert_eq!(inventory.version_outliers(), Vec::new());
+
+        // Sled 1 was left behind on an older SP, and switch 1 was updated.
+        for id in [ComponentId::Sled(1), ComponentId::Switch(1)] {
+            let version =
+                if id == ComponentId::Sled(1) { "0.5.0" } else { "2.0.0" };
+            inventory.merge_cabooses(
+                id,
+                SpCabooses {
+                    sp_active: Some(caboose(version)),
+                    sp_inactive: None,
+                    rot_a: None,
+                    rot_b: None,
+                },
+            );
+        }
+        // Sled 2's RoT slot B couldn't be read, which isn't a mismatch.
+        let Component::Sled(sp) =
+            inventory.inventory.get_mut(&ComponentId::Sled(2)).unwrap()
+        else {
+            unreachable!();
+        };
+        sp.rot.as_mut().unwrap().caboose_b = None;
+
+        assert_eq!(
+            inventory.version_outliers(),
+            vec![(
+                ComponentId::Sled(1),
+                VersionMismatch {
+                    image: FirmwareImage::SpActive,
+                    version: "0.5.0".to_string(),
+                    majority_version: "1.0.0".to_string(),
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn component_id_try_new_bounds() {
+        let valid = [
+            (SpType::Sled, 0, ComponentId::Sled(0)),
+            (SpType::Sled, 31, ComponentId::Sled(31)),
+            (SpType::Switch, 0, ComponentId::Switch(0)),
+            (SpType::Switch, 1, ComponentId::Switch(1)),
+            (SpType::Power, 0, ComponentId::Psc(0)),
+            (SpType::Power, 1, ComponentId::Psc(1)),
+        ];
+        for (sp_type, slot, expected) in valid {
+            assert_eq!(
+                ComponentId::try_new(sp_type, slot),
+                Ok(expected),
+                "{sp_type:?} {slot} is valid"
+            );
+        }
+
+        let invalid = [
+            (SpType::Sled, 32, "invalid sled slot: 32"),
+            (SpType::Switch, 2, "invalid switch slot: 2"),
+            (SpType::Power, 2, "invalid power shelf slot: 2"),
+            // Larger than a u8.
+            (SpType::Sled, 256, "invalid sled slot: 256"),
+        ];
+        for (sp_type, slot, message) in invalid {
+            let error = ComponentId::try_new(sp_type, slot).unwrap_err();
+            assert_eq!(error, InvalidComponentId { sp_type, slot });
+            assert_eq!(error.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn update_inventory_rejects_invalid_slot() {
+        let mut inventory = Inventory::default();
+        let error = inventory
+            .update_inventory(RackV1Inventory {
+                sps: vec![
+                    sp_inventory(SpType::Sled, 0),
+                    sp_inventory(SpType::Switch, 2),
+                ],
+            })
+            .unwrap_err();
+        assert_eq!(error.to_string(), "invalid switch slot: 2");
+        // The inventory is left untouched.
+        assert_eq!(inventory, Inventory::default());
+    }
+}
@@ -12,14 +12,14 @@ mod update;
 
 pub use force_update::ForceUpdateState;
 pub use inventory::{
-    Component, ComponentId, Inventory, ParsableComponentId, PowerState, Sp,
-    ALL_COMPONENT_IDS,
+    Component, ComponentId, FirmwareImage, InvalidComponentId, Inventory,
+    ParsableComponentId, PowerState, Sp, VersionMismatch, ALL_COMPONENT_IDS,
 };
 pub use rack::{KnightRiderMode, RackState};
 pub use status::{Liveness, ServiceStatus};
 pub use update::{
-    update_component_title, RackUpdateState, UpdateItemState,
-    UpdateRunningState,
+    update_component_title, CompletionEstimate, RackUpdateState, UpdateFilter,
+    UpdateItemState, UpdateRunningState,
 };
 
 use serde::{Deserialize, Serialize};
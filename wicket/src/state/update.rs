@@ -4,21 +4,27 @@
 
 use ratatui::style::Style;
 use wicket_common::update_events::{
-    EventReport, ProgressEventKind, StepEventKind, UpdateComponent,
-    UpdateStepId,
+    EventReport, ProgressEvent, ProgressEventKind, StepEventKind,
+    UpdateComponent, UpdateStepId,
 };
 
 use crate::{events::EventReportMap, ui::defaults::style};
 
 use super::{ComponentId, ParsableComponentId, ALL_COMPONENT_IDS};
+use anyhow::Context;
+use camino::Utf8Path;
 use omicron_common::api::internal::nexus::KnownArtifactKind;
 use serde::{Deserialize, Serialize};
 use slog::{warn, Logger};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fmt::Display;
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::time::{Duration, SystemTime};
 use wicketd_client::types::{ArtifactId, SemverVersion};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RackUpdateState {
     pub items: BTreeMap<ComponentId, UpdateItem>,
     pub system_version: Option<SemverVersion>,
@@ -27,6 +33,9 @@ pub struct RackUpdateState {
     // The update item currently selected is recorded in
     // state.rack_state.selected.
     pub status_view_displayed: bool,
+    /// The filter applied to the items shown in the update list.
+    #[serde(default)]
+    pub filter: UpdateFilter,
 }
 
 impl RackUpdateState {
@@ -66,7 +75,242 @@ impl RackUpdateState {
             artifacts: vec![],
             artifact_versions: BTreeMap::default(),
             status_view_displayed: false,
+            filter: UpdateFilter::default(),
+        }
+    }
+
+    /// Returns the items matched by [`Self::filter`], in component order.
+    pub fn filtered_items(
+        &self,
+    ) -> impl Iterator<Item = (&ComponentId, &UpdateItem)> + '_ {
+        self.items.iter().filter(|(_, item)| self.filter.matches(item))
+    }
+
+    /// Serializes the parts of this state that are saved between wicket
+    /// sessions.
+    ///
+    /// UI-only state (the filter, and whether the status view is displayed)
+    /// isn't saved.
+    pub fn to_saved(&self) -> anyhow::Result<Vec<u8>> {
+        let saved = SavedUpdateStateRef {
+            items: &self.items,
+            system_version: &self.system_version,
+            artifacts: &self.artifacts,
+            artifact_versions: &self.artifact_versions,
+        };
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&saved, &mut buf)
+            .context("error serializing update state")?;
+        Ok(buf)
+    }
+
+    /// Writes state serialized by [`Self::to_saved`] to `path`, so that a
+    /// later wicket session can pick up where this one left off via
+    /// [`Self::load`].
+    ///
+    /// The parent directory is created (readable only by the current user) if
+    /// it doesn't already exist.
+    pub fn write_saved(path: &Utf8Path, saved: &[u8]) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(parent)
+                .with_context(|| format!("error creating {parent}"))?;
+        }
+
+        // Write to a temporary file and rename it into place, so that a
+        // partially-written state is never observed by `load`. A temporary
+        // file left behind by an earlier session is removed first so that
+        // the new one can be created exclusively.
+        let temp_path = path.with_extension("tmp");
+        match std::fs::remove_file(&temp_path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("error removing {temp_path}"));
+            }
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&temp_path)
+            .with_context(|| format!("error creating {temp_path}"))?;
+        file.write_all(saved).with_context(|| {
+            format!("error writing update state to {temp_path}")
+        })?;
+        std::fs::rename(&temp_path, path)
+            .with_context(|| format!("error renaming {temp_path} to {path}"))?;
+        Ok(())
+    }
+
+    /// Loads a state previously written by [`Self::write_saved`].
+    ///
+    /// The loaded state is only used until the first call to
+    /// [`Self::update_artifacts_and_reports`], which always prefers live data
+    /// from wicketd over anything restored here.
+    pub fn load(path: &Utf8Path) -> anyhow::Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("error opening {path}"))?;
+        let SavedUpdateState {
+            items,
+            system_version,
+            artifacts,
+            artifact_versions,
+        } = ciborium::de::from_reader(file).with_context(|| {
+            format!("error reading update state from {path}")
+        })?;
+        Ok(RackUpdateState {
+            items,
+            system_version,
+            artifacts,
+            artifact_versions,
+            status_view_displayed: false,
+            filter: UpdateFilter::default(),
+        })
+    }
+
+    /// Returns the text shown in the update header describing the system
+    /// version that starting an update will install.
+    ///
+    /// Without a repository there's nothing to install (every item is
+    /// awaiting one), so the text says so instead.
+    pub fn system_version_header(&self) -> String {
+        match &self.system_version {
+            Some(version) => format!("INSTALLING SYSTEM VERSION {version}"),
+            None => "NO REPOSITORY LOADED".to_owned(),
+        }
+    }
+
+    /// Returns the versions of each kind of artifact whose versions are
+    /// inconsistent with the rest of the loaded repository.
+    ///
+    /// A kind is inconsistent if its artifacts have more than one version.
+    /// Versions aren't compared against the system version: SP and RoT images
+    /// carry the versions they were built with. Note that
+    /// [`Self::artifact_versions`] only records one of the versions.
+    pub fn inconsistent_artifact_versions(
+        &self,
+    ) -> BTreeMap<KnownArtifactKind, BTreeSet<SemverVersion>> {
+        let mut versions: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+        for id in &self.artifacts {
+            if let Ok(known) = id.kind.parse() {
+                versions.entry(known).or_default().insert(id.version.clone());
+            }
+        }
+        versions.retain(|_, versions| versions.len() > 1);
+        versions
+    }
+
+    /// Returns why no update can be started from the loaded repository, if it
+    /// is malformed.
+    pub fn start_blocked_reason(&self) -> Option<String> {
+        let inconsistent = self.inconsistent_artifact_versions();
+        if inconsistent.is_empty() {
+            return None;
+        }
+        let kinds: Vec<_> = inconsistent
+            .iter()
+            .map(|(kind, versions)| {
+                let versions: Vec<_> =
+                    versions.iter().map(ToString::to_string).collect();
+                format!("{kind} ({})", versions.join(", "))
+            })
+            .collect();
+        let system_version = match &self.system_version {
+            Some(version) => format!(" (system version {version})"),
+            None => String::new(),
+        };
+        Some(format!(
+            "The loaded TUF repository{system_version} has artifacts of \
+             inconsistent versions, so it may be malformed: {}. Upload a \
+             different repository to update.",
+            kinds.join("; ")
+        ))
+    }
+
+    /// Returns the overall progress of the rack update, as the percentage of
+    /// components across all items that have been either updated or skipped.
+    ///
+    /// Returns `None` if no repository has been uploaded yet.
+    pub fn overall_progress_percent(&self) -> Option<u8> {
+        if self.artifacts.is_empty() {
+            return None;
         }
+
+        let mut total = 0usize;
+        let mut done = 0usize;
+        for item in self.items.values() {
+            for (_, state) in item.iter() {
+                total += 1;
+                if matches!(
+                    state,
+                    UpdateState::Running(
+                        UpdateRunningState::Updated
+                            | UpdateRunningState::Skipped
+                    )
+                ) {
+                    done += 1;
+                }
+            }
+        }
+
+        if total == 0 {
+            return Some(0);
+        }
+        // This is at most 100, so the cast can't truncate.
+        Some((done * 100 / total) as u8)
+    }
+
+    /// Returns an estimate of when the components still to be updated, among
+    /// the items whose update has started, will have been updated.
+    ///
+    /// wicketd updates items concurrently, but the components of each item
+    /// one after another. This assumes each remaining component takes as long
+    /// as the average of those updated so far, less the time already spent on
+    /// the component being updated, so the update completes when the item
+    /// with the most time remaining does. The estimate is a range around that
+    /// time, which is wide when only a few components have been updated and
+    /// narrows as more complete.
+    ///
+    /// Returns `None` if nothing remains to be updated, or if no component
+    /// has been updated yet to base an estimate on.
+    pub fn estimated_completion(
+        &self,
+        now: SystemTime,
+    ) -> Option<CompletionEstimate> {
+        let completed: Vec<_> = self
+            .items
+            .values()
+            .flat_map(|item| item.completed_durations())
+            .collect();
+        if completed.is_empty() {
+            return None;
+        }
+
+        // There are at most a few hundred components, so this can't truncate.
+        let samples = completed.len() as u32;
+        let average = completed.iter().sum::<Duration>() / samples;
+        let expected = self
+            .items
+            .values()
+            .filter(|item| item.remaining_components() > 0)
+            .map(|item| {
+                (average * item.remaining_components())
+                    .saturating_sub(item.in_progress_elapsed())
+            })
+            .max()?;
+        // The error in the average shrinks with the square root of the number
+        // of samples. With a single sample, the estimate ranges from now to
+        // twice the expected time.
+        let margin = expected.div_f64(f64::from(samples).sqrt());
+        Some(CompletionEstimate {
+            earliest: now + expected.saturating_sub(margin),
+            expected: now + expected,
+            latest: now + expected + margin,
+        })
     }
 
     pub fn item_state(&self, component: ComponentId) -> UpdateItemState {
@@ -78,6 +322,9 @@ impl RackUpdateState {
                 UpdateItemStateImpl::UpdateStarted => {
                     UpdateItemState::UpdateStarted
                 }
+                UpdateItemStateImpl::FailedToStart { message } => {
+                    UpdateItemState::FailedToStart { message }
+                }
                 UpdateItemStateImpl::RunningOrCompleted {
                     event_report,
                     ..
@@ -86,6 +333,35 @@ impl RackUpdateState {
         }
     }
 
+    /// Records that a request to start updating `component_id` failed with
+    /// `message`.
+    ///
+    /// wicketd has no event report for an update that never started, so the
+    /// item stays in this state until it's started again or reset.
+    pub fn start_failed(&mut self, component_id: ComponentId, message: String) {
+        if let Some(item) = self.items.get_mut(&component_id) {
+            item.state = UpdateItemStateImpl::FailedToStart { message };
+        }
+    }
+
+    /// Resets a single item to the "not started" state.
+    pub fn reset_item(&mut self, component_id: ComponentId) {
+        if let Some(item) = self.items.get_mut(&component_id) {
+            item.reset();
+        }
+    }
+
+    /// Resets every item to the "not started" state.
+    ///
+    /// This doesn't affect the loaded repository. Items with updates that are
+    /// still running in wicketd will be repopulated by the next call to
+    /// [`Self::update_artifacts_and_reports`].
+    pub fn reset_all(&mut self) {
+        for item in self.items.values_mut() {
+            item.reset();
+        }
+    }
+
     pub fn update_artifacts_and_reports(
         &mut self,
         logger: &Logger,
@@ -93,6 +369,13 @@ impl RackUpdateState {
         artifacts: Vec<ArtifactId>,
         reports: EventReportMap,
     ) {
+        // If the set of artifacts has changed (e.g. a new TUF repo was
+        // uploaded, or this state was restored from disk and the repo has
+        // been replaced since), any item state we have is stale.
+        if self.artifacts != artifacts {
+            self.reset_all();
+        }
+
         self.system_version = system_version;
         self.artifacts = artifacts;
         self.artifact_versions.clear();
@@ -124,15 +407,36 @@ impl RackUpdateState {
             }
         }
 
-        // Reset all component IDs that weren't updated.
+        // Reset all component IDs that weren't updated, except those that
+        // failed to start: wicketd never returns reports for them, and the
+        // failure should stay visible until it's been dealt with.
         for (id, item) in &mut self.items {
-            if !updated_component_ids.contains(id) {
+            if !updated_component_ids.contains(id)
+                && !matches!(
+                    item.state,
+                    UpdateItemStateImpl::FailedToStart { .. }
+                )
+            {
                 item.reset();
             }
         }
     }
 }
 
+/// An estimate of when a rack update will complete, returned by
+/// [`RackUpdateState::estimated_completion`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompletionEstimate {
+    /// The earliest time the update is likely to complete.
+    pub earliest: SystemTime,
+
+    /// The most likely time the update will complete.
+    pub expected: SystemTime,
+
+    /// The latest time the update is likely to complete.
+    pub latest: SystemTime,
+}
+
 /// The current status of an updating item.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum UpdateItemState<'a> {
@@ -147,6 +451,12 @@ pub enum UpdateItemState<'a> {
     /// yet.
     UpdateStarted,
 
+    /// The request to start the update failed.
+    FailedToStart {
+        /// The error returned for the request.
+        message: &'a str,
+    },
+
     /// The update is running, or has completed or failed.
     RunningOrCompleted {
         /// The latest event report.
@@ -158,6 +468,97 @@ pub enum UpdateItemState<'a> {
     // * already up to date.
 }
 
+/// A filter over the items shown in the update list.
+/// The parts of [`RackUpdateState`] written by
+/// [`RackUpdateState::to_saved`].
+#[derive(Serialize)]
+struct SavedUpdateStateRef<'a> {
+    items: &'a BTreeMap<ComponentId, UpdateItem>,
+    system_version: &'a Option<SemverVersion>,
+    artifacts: &'a Vec<ArtifactId>,
+    artifact_versions: &'a BTreeMap<KnownArtifactKind, SemverVersion>,
+}
+
+/// The owned counterpart of [`SavedUpdateStateRef`], read back by
+/// [`RackUpdateState::load`].
+#[derive(Deserialize)]
+struct SavedUpdateState {
+    items: BTreeMap<ComponentId, UpdateItem>,
+    system_version: Option<SemverVersion>,
+    artifacts: Vec<ArtifactId>,
+    artifact_versions: BTreeMap<KnownArtifactKind, SemverVersion>,
+}
+
+#[derive(
+    Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum UpdateFilter {
+    /// Show every item.
+    #[default]
+    All,
+
+    /// Show only items with a component that failed to update, or whose
+    /// update was aborted.
+    Failed,
+
+    /// Show only items with a component that is currently updating.
+    Updating,
+}
+
+impl UpdateFilter {
+    /// Returns the filter that follows this one, wrapping back to
+    /// [`UpdateFilter::All`].
+    pub fn next(self) -> Self {
+        match self {
+            UpdateFilter::All => UpdateFilter::Failed,
+            UpdateFilter::Failed => UpdateFilter::Updating,
+            UpdateFilter::Updating => UpdateFilter::All,
+        }
+    }
+
+    /// Returns true if `item` should be shown under this filter, i.e., if any
+    /// of its components match it.
+    pub fn matches(self, item: &UpdateItem) -> bool {
+        match self {
+            UpdateFilter::All => true,
+            UpdateFilter::Failed | UpdateFilter::Updating => {
+                item.iter().any(|(_, state)| self.matches_state(&state))
+            }
+        }
+    }
+
+    fn matches_state(self, state: &UpdateState) -> bool {
+        match self {
+            UpdateFilter::All => true,
+            UpdateFilter::Failed => matches!(
+                state,
+                UpdateState::FailedToStart
+                    | UpdateState::Running(
+                        UpdateRunningState::Failed
+                            | UpdateRunningState::Aborted
+                    )
+            ),
+            UpdateFilter::Updating => matches!(
+                state,
+                UpdateState::Running(
+                    UpdateRunningState::Updating
+                        | UpdateRunningState::Retrying { .. }
+                )
+            ),
+        }
+    }
+}
+
+impl Display for UpdateFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateFilter::All => write!(f, "ALL"),
+            UpdateFilter::Failed => write!(f, "FAILED"),
+            UpdateFilter::Updating => write!(f, "UPDATING"),
+        }
+    }
+}
+
 /// Internal state for an individual item inside a `RackUpdateState`.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UpdateItem {
@@ -183,10 +584,163 @@ impl UpdateItem {
         matches!(self.state, UpdateItemStateImpl::RunningOrCompleted { .. })
     }
 
+    /// Returns the progress percentages seen so far for `component`, oldest
+    /// first.
+    ///
+    /// This is accumulated across event reports, so it can be used to show
+    /// whether an update is steadily progressing or stalled.
+    pub fn progress_series(&self, component: UpdateComponent) -> Vec<u8> {
+        match &self.state {
+            UpdateItemStateImpl::NotStarted
+            | UpdateItemStateImpl::UpdateStarted
+            | UpdateItemStateImpl::FailedToStart { .. } => Vec::new(),
+            UpdateItemStateImpl::RunningOrCompleted {
+                progress_history,
+                ..
+            } => progress_series(progress_history, component),
+        }
+    }
+
+    /// Returns how long each updated component took, as the sum of the
+    /// elapsed times of its completed steps.
+    ///
+    /// Skipped components are excluded, since they aren't representative of
+    /// how long an update takes.
+    fn completed_durations(&self) -> Vec<Duration> {
+        let UpdateItemStateImpl::RunningOrCompleted {
+            components,
+            event_report,
+            ..
+        } = &self.state
+        else {
+            return Vec::new();
+        };
+
+        components
+            .iter()
+            .filter(|(_, state)| **state == UpdateRunningState::Updated)
+            .map(|(component, _)| {
+                event_report
+                    .step_events
+                    .iter()
+                    .filter_map(|event| match &event.kind {
+                        StepEventKind::StepCompleted {
+                            step,
+                            step_elapsed,
+                            ..
+                        }
+                        | StepEventKind::ExecutionCompleted {
+                            last_step: step,
+                            step_elapsed,
+                            ..
+                        } if step.info.component == *component => {
+                            Some(*step_elapsed)
+                        }
+                        _ => None,
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+
+    /// Returns the time already spent on the components currently being
+    /// updated, as of the latest event report.
+    fn in_progress_elapsed(&self) -> Duration {
+        let UpdateItemStateImpl::RunningOrCompleted {
+            components,
+            event_report,
+            ..
+        } = &self.state
+        else {
+            return Duration::ZERO;
+        };
+
+        components
+            .iter()
+            .filter(|(_, state)| {
+                matches!(
+                    state,
+                    UpdateRunningState::Updating
+                        | UpdateRunningState::Retrying { .. }
+                )
+            })
+            .map(|(component, _)| {
+                // The steps of this component that have completed...
+                let completed_steps: Duration = event_report
+                    .step_events
+                    .iter()
+                    .filter_map(|event| match &event.kind {
+                        StepEventKind::StepCompleted {
+                            step,
+                            step_elapsed,
+                            ..
+                        } if step.info.component == *component => {
+                            Some(*step_elapsed)
+                        }
+                        _ => None,
+                    })
+                    .sum();
+                // ... and the one in progress.
+                let current_step = event_report
+                    .progress_events
+                    .iter()
+                    .filter_map(|event| match &event.kind {
+                        ProgressEventKind::WaitingForProgress {
+                            step,
+                            step_elapsed,
+                            ..
+                        }
+                        | ProgressEventKind::Progress {
+                            step,
+                            step_elapsed,
+                            ..
+                        }
+                        | ProgressEventKind::Nested {
+                            step,
+                            step_elapsed,
+                            ..
+                        } if step.info.component == *component => {
+                            Some(*step_elapsed)
+                        }
+                        _ => None,
+                    })
+                    .max()
+                    .unwrap_or_default();
+                completed_steps + current_step
+            })
+            .sum()
+    }
+
+    /// Returns the number of components still to be updated, if this item's
+    /// update has started.
+    fn remaining_components(&self) -> u32 {
+        let remaining = match &self.state {
+            UpdateItemStateImpl::NotStarted
+            | UpdateItemStateImpl::FailedToStart { .. } => 0,
+            UpdateItemStateImpl::UpdateStarted => self.components.len(),
+            UpdateItemStateImpl::RunningOrCompleted { components, .. } => {
+                components
+                    .values()
+                    .filter(|state| {
+                        matches!(
+                            state,
+                            UpdateRunningState::Waiting
+                                | UpdateRunningState::Updating
+                                | UpdateRunningState::Retrying { .. }
+                        )
+                    })
+                    .count()
+            }
+        };
+        // An item has at most a handful of components.
+        remaining as u32
+    }
+
     pub fn event_report(&self) -> Option<&EventReport> {
         match &self.state {
             UpdateItemStateImpl::NotStarted
-            | UpdateItemStateImpl::UpdateStarted => None,
+            | UpdateItemStateImpl::UpdateStarted
+            | UpdateItemStateImpl::FailedToStart { .. } => None,
             UpdateItemStateImpl::RunningOrCompleted {
                 event_report, ..
             } => Some(event_report),
@@ -210,7 +764,8 @@ impl UpdateItem {
 
         match &mut self.state {
             state @ UpdateItemStateImpl::NotStarted
-            | state @ UpdateItemStateImpl::UpdateStarted => {
+            | state @ UpdateItemStateImpl::UpdateStarted
+            | state @ UpdateItemStateImpl::FailedToStart { .. } => {
                 // Transition to the running state.
                 let components = self
                     .components
@@ -220,6 +775,7 @@ impl UpdateItem {
                     .collect();
                 *state = UpdateItemStateImpl::RunningOrCompleted {
                     components,
+                    progress_history: Vec::new(),
                     event_report: new_event_report,
                 };
             }
@@ -230,6 +786,24 @@ impl UpdateItem {
             }
         }
 
+        // Each report only carries the latest progress event for each step, so
+        // keep a history of the ones we've seen.
+        if let UpdateItemStateImpl::RunningOrCompleted {
+            progress_history,
+            event_report,
+            ..
+        } = &mut self.state
+        {
+            for progress_event in &event_report.progress_events {
+                if !progress_history.contains(progress_event) {
+                    progress_history.push(progress_event.clone());
+                }
+            }
+            let excess =
+                progress_history.len().saturating_sub(MAX_PROGRESS_HISTORY);
+            progress_history.drain(..excess);
+        }
+
         let (components, event_report) = match &mut self.state {
             UpdateItemStateImpl::RunningOrCompleted {
                 components,
@@ -237,7 +811,8 @@ impl UpdateItem {
                 ..
             } => (components, &*event_report),
             UpdateItemStateImpl::NotStarted
-            | UpdateItemStateImpl::UpdateStarted => {
+            | UpdateItemStateImpl::UpdateStarted
+            | UpdateItemStateImpl::FailedToStart { .. } => {
                 unreachable!(
                     "above block means it's always in the Running state"
                 )
@@ -251,16 +826,45 @@ impl UpdateItem {
                 StepEventKind::NoStepsDefined
                 | StepEventKind::ExecutionStarted { .. }
                 | StepEventKind::ProgressReset { .. }
-                | StepEventKind::AttemptRetry { .. }
                 | StepEventKind::Nested { .. }
                 | StepEventKind::Unknown => (),
 
+                StepEventKind::AttemptRetry { step, next_attempt, .. } => {
+                    update_component_state(
+                        components,
+                        Some(step.info.component),
+                        UpdateRunningState::Retrying { attempt: *next_attempt },
+                    );
+                }
+
                 StepEventKind::ExecutionCompleted {
                     last_step: step,
                     last_outcome: outcome,
                     ..
                 }
                 | StepEventKind::StepCompleted { step, outcome, .. } => {
+                    match step.info.id {
+                        // An update whose RoT and SP already match the
+                        // repository runs this step (after waiting for the
+                        // previous update group, if any) instead of updating
+                        // them. A sled's host is still updated afterwards.
+                        UpdateStepId::CheckingComponentsUpToDate => {
+                            for component in
+                                [UpdateComponent::Rot, UpdateComponent::Sp]
+                            {
+                                update_component_state(
+                                    components,
+                                    Some(component),
+                                    UpdateRunningState::Skipped,
+                                );
+                            }
+                            continue;
+                        }
+                        // In that case, waiting for the previous group is the
+                        // only RoT step, but it doesn't update the RoT.
+                        UpdateStepId::WaitingForPreviousUpdateGroup => continue,
+                        _ => (),
+                    }
                     if step.info.is_last_step_in_component() {
                         // The RoT and SP components each have two steps in
                         // them. If the second step ("Updating RoT/SP") is
@@ -307,21 +911,26 @@ impl UpdateItem {
             }
         }
 
-        // Mark any known artifacts as updating
+        // Mark any known artifacts as updating. If the step in progress is
+        // being retried, mark it as such so that it's distinguishable from a
+        // step that's simply taking a long time.
         for progress_event in &event_report.progress_events {
-            let component = match &progress_event.kind {
-                ProgressEventKind::WaitingForProgress { step, .. }
-                | ProgressEventKind::Progress { step, .. }
-                | ProgressEventKind::Nested { step, .. } => {
-                    Some(step.info.component)
+            let (component, attempt) = match &progress_event.kind {
+                ProgressEventKind::WaitingForProgress {
+                    step, attempt, ..
+                }
+                | ProgressEventKind::Progress { step, attempt, .. }
+                | ProgressEventKind::Nested { step, attempt, .. } => {
+                    (Some(step.info.component), *attempt)
                 }
-                ProgressEventKind::Unknown => None,
+                ProgressEventKind::Unknown => (None, 1),
             };
-            update_component_state(
-                components,
-                component,
-                UpdateRunningState::Updating,
-            );
+            let new_state = if attempt > 1 {
+                UpdateRunningState::Retrying { attempt }
+            } else {
+                UpdateRunningState::Updating
+            };
+            update_component_state(components, component, new_state);
         }
     }
 
@@ -336,6 +945,9 @@ impl UpdateItem {
             let state = match &self.state {
                 UpdateItemStateImpl::NotStarted => UpdateState::NotStarted,
                 UpdateItemStateImpl::UpdateStarted => UpdateState::Starting,
+                UpdateItemStateImpl::FailedToStart { .. } => {
+                    UpdateState::FailedToStart
+                }
                 UpdateItemStateImpl::RunningOrCompleted {
                     components, ..
                 } => UpdateState::Running(components[component]),
@@ -379,9 +991,16 @@ impl UpdateState {
 enum UpdateItemStateImpl {
     NotStarted,
     UpdateStarted,
+    FailedToStart {
+        message: String,
+    },
     RunningOrCompleted {
         event_report: EventReport,
         components: BTreeMap<UpdateComponent, UpdateRunningState>,
+        // Progress events seen across all event reports, oldest first, capped
+        // at `MAX_PROGRESS_HISTORY`.
+        #[serde(default)]
+        progress_history: Vec<ProgressEvent>,
     },
 }
 
@@ -390,6 +1009,11 @@ pub enum UpdateRunningState {
     Waiting,
     Updated,
     Updating,
+    /// A step within this component failed and is being retried.
+    Retrying {
+        /// The attempt number currently being made.
+        attempt: usize,
+    },
     Skipped,
     Failed,
     Aborted,
@@ -401,6 +1025,9 @@ impl Display for UpdateRunningState {
             UpdateRunningState::Waiting => write!(f, "WAITING"),
             UpdateRunningState::Updated => write!(f, "UPDATED"),
             UpdateRunningState::Updating => write!(f, "UPDATING"),
+            UpdateRunningState::Retrying { attempt } => {
+                write!(f, "RETRYING (ATTEMPT {attempt})")
+            }
             UpdateRunningState::Skipped => write!(f, "SKIPPED"),
             UpdateRunningState::Failed => write!(f, "FAILED"),
             UpdateRunningState::Aborted => write!(f, "ABORTED"),
@@ -413,9 +1040,9 @@ impl UpdateRunningState {
         match self {
             UpdateRunningState::Waiting => style::deselected(),
             UpdateRunningState::Updated => style::successful_update(),
-            UpdateRunningState::Updating | UpdateRunningState::Skipped => {
-                style::start_update()
-            }
+            UpdateRunningState::Updating
+            | UpdateRunningState::Retrying { .. }
+            | UpdateRunningState::Skipped => style::start_update(),
             UpdateRunningState::Failed | UpdateRunningState::Aborted => {
                 style::failed_update()
             }
@@ -437,6 +1064,42 @@ fn update_component_state(
     }
 }
 
+/// The maximum number of progress events kept by each [`UpdateItem`].
+const MAX_PROGRESS_HISTORY: usize = 256;
+
+/// Extracts the sequence of progress percentages for `component` from
+/// `progress_events`, ordered by when the events occurred.
+///
+/// Events for other components, and events without a known total, are
+/// skipped.
+pub fn progress_series(
+    progress_events: &[ProgressEvent],
+    component: UpdateComponent,
+) -> Vec<u8> {
+    let mut events: Vec<_> = progress_events
+        .iter()
+        .filter(|event| match &event.kind {
+            ProgressEventKind::WaitingForProgress { step, .. }
+            | ProgressEventKind::Progress { step, .. }
+            | ProgressEventKind::Nested { step, .. } => {
+                step.info.component == component
+            }
+            ProgressEventKind::Unknown => false,
+        })
+        .collect();
+    events.sort_by_key(|event| event.total_elapsed);
+
+    events
+        .into_iter()
+        .filter_map(|event| {
+            let counter = event.kind.progress_counter()?;
+            let total = counter.total.filter(|&total| total > 0)?;
+            // This is at most 100, so the cast can't truncate.
+            Some((counter.current.min(total) * 100 / total) as u8)
+        })
+        .collect()
+}
+
 #[allow(unused)]
 pub fn update_component_title(component: UpdateComponent) -> &'static str {
     match component {
@@ -445,3 +1108,830 @@ pub fn update_component_title(component: UpdateComponent) -> &'static str {
         UpdateComponent::Host => "HOST",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use update_engine::events::{ProgressCounter, ProgressUnits};
+    use update_engine::ExecutionId;
+    use wicket_common::update_events::{
+        ProgressEvent, StepEvent, StepInfo, StepInfoWithMetadata, StepOutcome,
+    };
+
+    fn step_info(
+        component: UpdateComponent,
+        id: UpdateStepId,
+        component_index: usize,
+        total_component_steps: usize,
+    ) -> StepInfoWithMetadata {
+        StepInfoWithMetadata {
+            info: StepInfo {
+                id,
+                component,
+                description: "test step".into(),
+                index: 0,
+                component_index,
+                total_component_steps,
+            },
+            metadata: None,
+        }
+    }
+
+    fn step_event(event_index: usize, kind: StepEventKind) -> StepEvent {
+        StepEvent {
+            spec: "WicketdEngineSpec".to_owned(),
+            execution_id: ExecutionId(Default::default()),
+            event_index,
+            total_elapsed: Duration::ZERO,
+            kind,
+        }
+    }
+
+    fn progress_event(kind: ProgressEventKind) -> ProgressEvent {
+        ProgressEvent {
+            spec: "WicketdEngineSpec".to_owned(),
+            execution_id: ExecutionId(Default::default()),
+            total_elapsed: Duration::ZERO,
+            kind,
+        }
+    }
+
+    fn event_report(
+        step_events: Vec<StepEvent>,
+        progress_events: Vec<ProgressEvent>,
+    ) -> EventReport {
+        let last_seen = step_events.last().map(|event| event.event_index);
+        EventReport {
+            step_events,
+            progress_events,
+            root_execution_id: Some(ExecutionId(Default::default())),
+            last_seen,
+        }
+    }
+
+    fn sled_item() -> UpdateItem {
+        UpdateItem::new(
+            ComponentId::Sled(0),
+            vec![
+                UpdateComponent::Rot,
+                UpdateComponent::Sp,
+                UpdateComponent::Host,
+            ],
+        )
+    }
+
+    fn running_state(
+        item: &UpdateItem,
+        component: UpdateComponent,
+    ) -> UpdateRunningState {
+        let (_, state) = item
+            .iter()
+            .find(|(c, _)| *c == component)
+            .expect("component is present in item");
+        match state {
+            UpdateState::Running(state) => state,
+            UpdateState::NotStarted
+            | UpdateState::Starting
+            | UpdateState::FailedToStart => {
+                panic!("component {component:?} is not running")
+            }
+        }
+    }
+
+    #[test]
+    fn attempt_retry_is_visible() {
+        let mut item = sled_item();
+        let step = step_info(
+            UpdateComponent::Sp,
+            UpdateStepId::SpComponentUpdate,
+            1,
+            2,
+        );
+
+        let report = event_report(
+            vec![step_event(
+                0,
+                StepEventKind::AttemptRetry {
+                    step: step.clone(),
+                    next_attempt: 2,
+                    step_elapsed: Duration::from_secs(5),
+                    attempt_elapsed: Duration::from_secs(5),
+                    message: "SP reset during update".into(),
+                },
+            )],
+            vec![progress_event(ProgressEventKind::WaitingForProgress {
+                step,
+                attempt: 2,
+                step_elapsed: Duration::from_secs(5),
+                attempt_elapsed: Duration::ZERO,
+            })],
+        );
+        item.update(report);
+
+        let state = running_state(&item, UpdateComponent::Sp);
+        assert_eq!(state, UpdateRunningState::Retrying { attempt: 2 });
+        assert_eq!(state.to_string(), "RETRYING (ATTEMPT 2)");
+
+        // Other components are unaffected.
+        assert_eq!(
+            running_state(&item, UpdateComponent::Rot),
+            UpdateRunningState::Waiting
+        );
+        assert_eq!(
+            running_state(&item, UpdateComponent::Host),
+            UpdateRunningState::Waiting
+        );
+    }
+
+    fn progress_at(
+        step: &StepInfoWithMetadata,
+        elapsed_secs: u64,
+        current: u64,
+        total: Option<u64>,
+    ) -> ProgressEvent {
+        let elapsed = Duration::from_secs(elapsed_secs);
+        ProgressEvent {
+            total_elapsed: elapsed,
+            ..progress_event(ProgressEventKind::Progress {
+                step: step.clone(),
+                attempt: 1,
+                metadata: Default::default(),
+                progress: Some(ProgressCounter {
+                    current,
+                    total,
+                    units: ProgressUnits::BYTES,
+                }),
+                step_elapsed: elapsed,
+                attempt_elapsed: elapsed,
+            })
+        }
+    }
+
+    #[test]
+    fn progress_series_orders_and_filters_events() {
+        let sp_step = step_info(
+            UpdateComponent::Sp,
+            UpdateStepId::SpComponentUpdate,
+            1,
+            2,
+        );
+        let rot_step = step_info(
+            UpdateComponent::Rot,
+            UpdateStepId::SpComponentUpdate,
+            0,
+            1,
+        );
+
+        let events = vec![
+            progress_at(&sp_step, 30, 75, Some(100)),
+            progress_at(&sp_step, 10, 25, Some(100)),
+            progress_at(&rot_step, 20, 50, Some(100)),
+            progress_event(ProgressEventKind::WaitingForProgress {
+                step: sp_step.clone(),
+                attempt: 1,
+                step_elapsed: Duration::ZERO,
+                attempt_elapsed: Duration::ZERO,
+            }),
+            // Without a total, there's no percentage.
+            progress_at(&sp_step, 20, 50, None),
+            progress_at(&sp_step, 40, 100, Some(100)),
+        ];
+
+        assert_eq!(
+            progress_series(&events, UpdateComponent::Sp),
+            vec![25, 75, 100]
+        );
+        assert_eq!(progress_series(&events, UpdateComponent::Rot), vec![50]);
+        assert_eq!(
+            progress_series(&events, UpdateComponent::Host),
+            Vec::<u8>::new()
+        );
+    }
+
+    #[test]
+    fn progress_series_accumulates_across_reports() {
+        let mut item = sled_item();
+        let step = step_info(
+            UpdateComponent::Sp,
+            UpdateStepId::SpComponentUpdate,
+            1,
+            2,
+        );
+        assert_eq!(item.progress_series(UpdateComponent::Sp), Vec::<u8>::new());
+
+        // The same event showing up in consecutive reports (because progress
+        // hasn't moved) is only recorded once.
+        for (elapsed_secs, current) in [(10, 20), (20, 40), (20, 40), (30, 60)]
+        {
+            item.update(event_report(
+                vec![step_event(0, StepEventKind::NoStepsDefined)],
+                vec![progress_at(&step, elapsed_secs, current, Some(200))],
+            ));
+        }
+
+        assert_eq!(item.progress_series(UpdateComponent::Sp), vec![10, 20, 30]);
+        assert_eq!(
+            item.progress_series(UpdateComponent::Rot),
+            Vec::<u8>::new()
+        );
+    }
+
+    #[test]
+    fn up_to_date_step_marks_rot_and_sp_skipped() {
+        let mut item = sled_item();
+        let step = step_info(
+            UpdateComponent::Sp,
+            UpdateStepId::CheckingComponentsUpToDate,
+            0,
+            1,
+        );
+
+        item.update(event_report(
+            vec![step_event(
+                0,
+                StepEventKind::ExecutionCompleted {
+                    last_step: step,
+                    last_attempt: 1,
+                    last_outcome: StepOutcome::Success {
+                        message: Some("already up to date".into()),
+                        metadata: None,
+                    },
+                    step_elapsed: Duration::ZERO,
+                    attempt_elapsed: Duration::ZERO,
+                },
+            )],
+            vec![],
+        ));
+
+        for component in [UpdateComponent::Rot, UpdateComponent::Sp] {
+            assert_eq!(
+                running_state(&item, component),
+                UpdateRunningState::Skipped,
+                "component {component:?}"
+            );
+        }
+
+        // The host isn't checked, so it's still updated.
+        assert_eq!(
+            running_state(&item, UpdateComponent::Host),
+            UpdateRunningState::Waiting
+        );
+    }
+
+    fn artifact(kind: KnownArtifactKind, version: &str) -> ArtifactId {
+        ArtifactId {
+            kind: kind.to_string(),
+            name: format!("{kind}"),
+            version: version.parse().unwrap(),
+        }
+    }
+
+    fn running_report(component: UpdateComponent) -> EventReport {
+        let step = step_info(component, UpdateStepId::SpComponentUpdate, 1, 2);
+        // A report with no step events is treated as a reset, so include one.
+        event_report(
+            vec![step_event(0, StepEventKind::NoStepsDefined)],
+            vec![progress_event(ProgressEventKind::WaitingForProgress {
+                step,
+                attempt: 1,
+                step_elapsed: Duration::ZERO,
+                attempt_elapsed: Duration::ZERO,
+            })],
+        )
+    }
+
+    fn running_update_state(log: &Logger) -> RackUpdateState {
+        let mut state = RackUpdateState::new();
+        let mut reports = EventReportMap::new();
+        reports
+            .entry("sled".to_owned())
+            .or_default()
+            .insert("3".to_owned(), running_report(UpdateComponent::Sp));
+        state.update_artifacts_and_reports(
+            log,
+            Some("1.0.0".parse().unwrap()),
+            vec![artifact(KnownArtifactKind::GimletSp, "1.0.0")],
+            reports,
+        );
+        state
+    }
+
+    fn test_logger() -> Logger {
+        Logger::root(slog::Discard, slog::o!())
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let log = test_logger();
+        let mut state = running_update_state(&log);
+        assert!(state.items[&ComponentId::Sled(3)].is_running());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8Path::from_path(dir.path())
+            .expect("tempdir is valid UTF-8")
+            .join("wicket/update-state.cbor");
+        let saved = state.to_saved().unwrap();
+        RackUpdateState::write_saved(&path, &saved).unwrap();
+
+        let mode = |path: &Utf8Path| {
+            std::os::unix::fs::PermissionsExt::mode(
+                &path.metadata().unwrap().permissions(),
+            ) & 0o777
+        };
+        assert_eq!(mode(path.parent().unwrap()), 0o700);
+        assert_eq!(mode(&path), 0o600);
+
+        let loaded = RackUpdateState::load(&path).unwrap();
+        assert_eq!(loaded, state);
+
+        // UI-only state isn't saved, so changing it doesn't change what would
+        // be written, and it's reset on load.
+        state.status_view_displayed = true;
+        state.filter = UpdateFilter::Failed;
+        assert_eq!(state.to_saved().unwrap(), saved);
+        let loaded = RackUpdateState::load(&path).unwrap();
+        assert!(!loaded.status_view_displayed);
+        assert_eq!(loaded.filter, UpdateFilter::All);
+    }
+
+    #[test]
+    fn artifact_change_resets_restored_items() {
+        let log = test_logger();
+        let mut state = running_update_state(&log);
+        assert_eq!(
+            running_state(
+                &state.items[&ComponentId::Sled(3)],
+                UpdateComponent::Sp
+            ),
+            UpdateRunningState::Updating
+        );
+
+        // A new report arrives against a different set of artifacts (e.g. the
+        // repository was replaced while wicket wasn't running). The item must
+        // be rebuilt from the live report rather than carrying forward the
+        // restored component states.
+        let mut reports = EventReportMap::new();
+        reports.entry("sled".to_owned()).or_default().insert(
+            "3".to_owned(),
+            event_report(
+                vec![step_event(0, StepEventKind::NoStepsDefined)],
+                vec![],
+            ),
+        );
+        state.update_artifacts_and_reports(
+            &log,
+            Some("2.0.0".parse().unwrap()),
+            vec![artifact(KnownArtifactKind::GimletSp, "2.0.0")],
+            reports,
+        );
+        assert_eq!(
+            running_state(
+                &state.items[&ComponentId::Sled(3)],
+                UpdateComponent::Sp
+            ),
+            UpdateRunningState::Waiting
+        );
+    }
+
+    fn set_running_states(
+        state: &mut RackUpdateState,
+        id: ComponentId,
+        states: &[(UpdateComponent, UpdateRunningState)],
+    ) {
+        let item = state.items.get_mut(&id).unwrap();
+        let mut components: BTreeMap<_, _> = item
+            .components
+            .iter()
+            .map(|component| (*component, UpdateRunningState::Waiting))
+            .collect();
+        for (component, running_state) in states {
+            components.insert(*component, *running_state);
+        }
+        item.state = UpdateItemStateImpl::RunningOrCompleted {
+            event_report: running_report(UpdateComponent::Sp),
+            components,
+            progress_history: Vec::new(),
+        };
+    }
+
+    fn complete_all(state: &mut RackUpdateState, id: ComponentId) {
+        let components = state.items[&id].components.clone();
+        let states: Vec<_> = components
+            .into_iter()
+            .map(|component| (component, UpdateRunningState::Updated))
+            .collect();
+        set_running_states(state, id, &states);
+    }
+
+    #[test]
+    fn overall_progress() {
+        let mut state = RackUpdateState::new();
+
+        // Without a repository, there's no progress to report.
+        assert_eq!(state.overall_progress_percent(), None);
+
+        state.artifacts = vec![artifact(KnownArtifactKind::GimletSp, "1.0.0")];
+        assert_eq!(state.overall_progress_percent(), Some(0));
+
+        // There are 32 sleds with 3 components each, and 2 switches and 1 PSC
+        // with 2 components each, for a total of 102 components. Complete
+        // 16 sleds (48 components), switch 0 (2 components, one of which was
+        // skipped) and the RoT on PSC 0 (1 component): 51 / 102 = 50%.
+        for i in 0..16 {
+            complete_all(&mut state, ComponentId::Sled(i));
+        }
+        set_running_states(
+            &mut state,
+            ComponentId::Switch(0),
+            &[
+                (UpdateComponent::Rot, UpdateRunningState::Updated),
+                (UpdateComponent::Sp, UpdateRunningState::Skipped),
+            ],
+        );
+        set_running_states(
+            &mut state,
+            ComponentId::Psc(0),
+            &[
+                (UpdateComponent::Rot, UpdateRunningState::Updated),
+                (UpdateComponent::Sp, UpdateRunningState::Failed),
+            ],
+        );
+        // A sled with components that are still in progress doesn't count.
+        set_running_states(
+            &mut state,
+            ComponentId::Sled(16),
+            &[
+                (UpdateComponent::Rot, UpdateRunningState::Updating),
+                (UpdateComponent::Sp, UpdateRunningState::Waiting),
+            ],
+        );
+        assert_eq!(state.overall_progress_percent(), Some(50));
+
+        for id in ALL_COMPONENT_IDS.iter() {
+            complete_all(&mut state, *id);
+        }
+        assert_eq!(state.overall_progress_percent(), Some(100));
+    }
+
+    #[test]
+    fn system_version_header_describes_loaded_repository() {
+        let mut state = RackUpdateState::new();
+        assert_eq!(state.system_version_header(), "NO REPOSITORY LOADED");
+
+        state.system_version = Some("1.0.0".parse().unwrap());
+        assert_eq!(
+            state.system_version_header(),
+            "INSTALLING SYSTEM VERSION 1.0.0"
+        );
+    }
+
+    #[test]
+    fn inconsistent_artifact_versions_block_start() {
+        let mut state = RackUpdateState::new();
+        assert_eq!(state.start_blocked_reason(), None);
+
+        // SP and RoT images take their versions from their own builds, not
+        // from the system version, so differing from it is fine.
+        state.system_version = Some("1.0.0".parse().unwrap());
+        state.artifacts = vec![
+            artifact(KnownArtifactKind::GimletSp, "1.0.47"),
+            artifact(KnownArtifactKind::GimletRot, "1.0.3"),
+            artifact(KnownArtifactKind::Host, "1.0.0"),
+        ];
+        assert!(state.inconsistent_artifact_versions().is_empty());
+        assert_eq!(state.start_blocked_reason(), None);
+
+        // Two SP images of different versions are not.
+        state.artifacts.push(artifact(KnownArtifactKind::GimletSp, "1.0.48"));
+        let expected: BTreeSet<SemverVersion> =
+            ["1.0.47".parse().unwrap(), "1.0.48".parse().unwrap()].into();
+        assert_eq!(
+            state.inconsistent_artifact_versions(),
+            BTreeMap::from([(KnownArtifactKind::GimletSp, expected)]),
+        );
+        let reason = state
+            .start_blocked_reason()
+            .expect("starting an update is blocked");
+        assert!(
+            reason.contains(&format!(
+                "{} (1.0.47, 1.0.48)",
+                KnownArtifactKind::GimletSp
+            )),
+            "unexpected reason: {reason}"
+        );
+    }
+
+    #[test]
+    fn reset_all_clears_items() {
+        let mut state = RackUpdateState::new();
+        state.system_version = Some("1.0.0".parse().unwrap());
+        state.artifacts = vec![artifact(KnownArtifactKind::GimletSp, "1.0.0")];
+        let artifacts = state.artifacts.clone();
+
+        complete_all(&mut state, ComponentId::Sled(0));
+        complete_all(&mut state, ComponentId::Switch(1));
+        set_running_states(
+            &mut state,
+            ComponentId::Sled(7),
+            &[(UpdateComponent::Sp, UpdateRunningState::Failed)],
+        );
+        state.items.get_mut(&ComponentId::Psc(0)).unwrap().state =
+            UpdateItemStateImpl::UpdateStarted;
+        assert_eq!(state.overall_progress_percent(), Some(4));
+
+        state.reset_all();
+        for id in ALL_COMPONENT_IDS.iter() {
+            assert_eq!(
+                state.item_state(*id),
+                UpdateItemState::NotStarted,
+                "{id} was reset"
+            );
+        }
+        assert_eq!(state.overall_progress_percent(), Some(0));
+
+        // The loaded repository is untouched.
+        assert_eq!(state.system_version, Some("1.0.0".parse().unwrap()));
+        assert_eq!(state.artifacts, artifacts);
+
+        // Resetting again is a no-op.
+        let before = state.clone();
+        state.reset_all();
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn start_failure_marks_components_failed_to_start() {
+        let log = test_logger();
+        let mut state = running_update_state(&log);
+        let id = ComponentId::Sled(5);
+        let message = "TUF repository unavailable";
+
+        state.start_failed(id, message.to_owned());
+        assert_eq!(
+            state.item_state(id),
+            UpdateItemState::FailedToStart { message }
+        );
+        for (component, update_state) in state.items[&id].iter() {
+            assert!(
+                matches!(update_state, UpdateState::FailedToStart),
+                "component {component:?} failed to start"
+            );
+        }
+
+        // wicketd doesn't report on updates that never started, but that
+        // doesn't clear the failure.
+        let mut reports = EventReportMap::new();
+        reports
+            .entry("sled".to_owned())
+            .or_default()
+            .insert("3".to_owned(), running_report(UpdateComponent::Sp));
+        let artifacts = state.artifacts.clone();
+        state.update_artifacts_and_reports(
+            &log,
+            Some("1.0.0".parse().unwrap()),
+            artifacts,
+            reports,
+        );
+        assert_eq!(
+            state.item_state(id),
+            UpdateItemState::FailedToStart { message }
+        );
+
+        // Once a later attempt starts, the item is running.
+        state
+            .items
+            .get_mut(&id)
+            .unwrap()
+            .update(running_report(UpdateComponent::Sp));
+        assert!(state.items[&id].is_running());
+
+        // A failure can also be cleared explicitly.
+        state.start_failed(id, message.to_owned());
+        state.reset_item(id);
+        assert_eq!(state.item_state(id), UpdateItemState::NotStarted);
+    }
+
+    #[test]
+    fn filter_shows_only_failed_items() {
+        let mut state = RackUpdateState::new();
+        state.artifacts = vec![artifact(KnownArtifactKind::GimletSp, "1.0.0")];
+
+        complete_all(&mut state, ComponentId::Sled(0));
+        set_running_states(
+            &mut state,
+            ComponentId::Sled(3),
+            &[
+                (UpdateComponent::Rot, UpdateRunningState::Updated),
+                (UpdateComponent::Sp, UpdateRunningState::Failed),
+            ],
+        );
+        set_running_states(
+            &mut state,
+            ComponentId::Sled(5),
+            &[(UpdateComponent::Rot, UpdateRunningState::Updating)],
+        );
+        set_running_states(
+            &mut state,
+            ComponentId::Switch(1),
+            &[(UpdateComponent::Sp, UpdateRunningState::Aborted)],
+        );
+        set_running_states(
+            &mut state,
+            ComponentId::Psc(0),
+            &[(
+                UpdateComponent::Sp,
+                UpdateRunningState::Retrying { attempt: 2 },
+            )],
+        );
+
+        // By default, nothing is filtered out.
+        assert_eq!(state.filter, UpdateFilter::All);
+        assert_eq!(state.filtered_items().count(), ALL_COMPONENT_IDS.len());
+
+        state.filter = UpdateFilter::Failed;
+        let failed: Vec<_> =
+            state.filtered_items().map(|(id, _)| *id).collect();
+        assert_eq!(failed, [ComponentId::Sled(3), ComponentId::Switch(1)]);
+
+        state.filter = UpdateFilter::Updating;
+        let updating: Vec<_> =
+            state.filtered_items().map(|(id, _)| *id).collect();
+        assert_eq!(updating, [ComponentId::Sled(5), ComponentId::Psc(0)]);
+
+        // Cycling through the filters returns to showing everything.
+        assert_eq!(state.filter.next(), UpdateFilter::All);
+    }
+
+    /// Marks every component of `id` as updated, with each component's update
+    /// step taking the corresponding number of seconds in `durations`.
+    fn complete_with_durations(
+        state: &mut RackUpdateState,
+        id: ComponentId,
+        durations: &[u64],
+    ) {
+        let item = state.items.get_mut(&id).unwrap();
+        assert_eq!(item.components.len(), durations.len());
+        let step_events = item
+            .components
+            .iter()
+            .zip(durations)
+            .enumerate()
+            .map(|(i, (component, secs))| {
+                let step = step_info(
+                    *component,
+                    UpdateStepId::SpComponentUpdate,
+                    1,
+                    2,
+                );
+                let elapsed = Duration::from_secs(*secs);
+                step_event(
+                    i,
+                    StepEventKind::StepCompleted {
+                        step: step.clone(),
+                        attempt: 1,
+                        outcome: StepOutcome::Success {
+                            message: None,
+                            metadata: None,
+                        },
+                        next_step: step,
+                        step_elapsed: elapsed,
+                        attempt_elapsed: elapsed,
+                    },
+                )
+            })
+            .collect();
+        item.state = UpdateItemStateImpl::RunningOrCompleted {
+            event_report: event_report(step_events, vec![]),
+            components: item
+                .components
+                .iter()
+                .map(|component| (*component, UpdateRunningState::Updated))
+                .collect(),
+            progress_history: Vec::new(),
+        };
+    }
+
+    /// Returns the width of `estimate`'s range relative to the expected time
+    /// remaining.
+    fn relative_width(estimate: &CompletionEstimate, now: SystemTime) -> f64 {
+        let width = estimate.latest.duration_since(estimate.earliest).unwrap();
+        let expected = estimate.expected.duration_since(now).unwrap();
+        width.as_secs_f64() / expected.as_secs_f64()
+    }
+
+    #[test]
+    fn estimated_completion_is_wide_with_sparse_data() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut state = RackUpdateState::new();
+        state.artifacts = vec![artifact(KnownArtifactKind::GimletSp, "1.0.0")];
+
+        // Nothing has been updated yet, so there's nothing to estimate from.
+        for i in 0..4 {
+            state.items.get_mut(&ComponentId::Sled(i)).unwrap().state =
+                UpdateItemStateImpl::UpdateStarted;
+        }
+        assert_eq!(state.estimated_completion(now), None);
+
+        // One sled's 3 components have been updated in a minute each, and 3
+        // sleds (9 components) remain. The sleds are updated concurrently, so
+        // they should all be done after their 3 components are.
+        complete_with_durations(&mut state, ComponentId::Sled(0), &[60; 3]);
+        let estimate = state.estimated_completion(now).unwrap();
+        assert_eq!(estimate.expected, now + Duration::from_secs(3 * 60));
+        assert!(estimate.earliest < estimate.expected);
+        assert!(estimate.latest > estimate.expected);
+        let width = relative_width(&estimate, now);
+        assert!(width > 1.0, "estimate is wide: {width}");
+    }
+
+    #[test]
+    fn estimated_completion_is_tight_with_many_samples() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut state = RackUpdateState::new();
+        state.artifacts = vec![artifact(KnownArtifactKind::GimletSp, "1.0.0")];
+
+        // 30 sleds have been updated, with components taking a minute on
+        // average, and 2 sleds (3 components each) remain.
+        for i in 0..30 {
+            complete_with_durations(
+                &mut state,
+                ComponentId::Sled(i),
+                &[50, 60, 70],
+            );
+        }
+        for i in 30..32 {
+            state.items.get_mut(&ComponentId::Sled(i)).unwrap().state =
+                UpdateItemStateImpl::UpdateStarted;
+        }
+
+        let estimate = state.estimated_completion(now).unwrap();
+        assert_eq!(estimate.expected, now + Duration::from_secs(3 * 60));
+        let width = relative_width(&estimate, now);
+        assert!(width < 0.25, "estimate is tight: {width}");
+
+        // Once everything has been updated, there's nothing left to estimate.
+        for i in 30..32 {
+            complete_with_durations(&mut state, ComponentId::Sled(i), &[60; 3]);
+        }
+        assert_eq!(state.estimated_completion(now), None);
+    }
+
+    #[test]
+    fn estimated_completion_subtracts_time_in_progress() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut state = RackUpdateState::new();
+        state.artifacts = vec![artifact(KnownArtifactKind::GimletSp, "1.0.0")];
+        complete_with_durations(&mut state, ComponentId::Sled(0), &[60; 3]);
+
+        // Sled 1 is partway through updating its RoT, the first of its 3
+        // components.
+        let set_rot_elapsed = |state: &mut RackUpdateState, secs| {
+            let item = state.items.get_mut(&ComponentId::Sled(1)).unwrap();
+            let step = step_info(
+                UpdateComponent::Rot,
+                UpdateStepId::SpComponentUpdate,
+                1,
+                2,
+            );
+            let elapsed = Duration::from_secs(secs);
+            item.state = UpdateItemStateImpl::RunningOrCompleted {
+                event_report: event_report(
+                    vec![step_event(0, StepEventKind::NoStepsDefined)],
+                    vec![progress_event(
+                        ProgressEventKind::WaitingForProgress {
+                            step,
+                            attempt: 1,
+                            step_elapsed: elapsed,
+                            attempt_elapsed: elapsed,
+                        },
+                    )],
+                ),
+                components: item
+                    .components
+                    .iter()
+                    .map(|component| {
+                        let state = if *component == UpdateComponent::Rot {
+                            UpdateRunningState::Updating
+                        } else {
+                            UpdateRunningState::Waiting
+                        };
+                        (*component, state)
+                    })
+                    .collect(),
+                progress_history: Vec::new(),
+            };
+        };
+
+        // The time already spent on the RoT doesn't need to be spent again.
+        set_rot_elapsed(&mut state, 40);
+        let estimate = state.estimated_completion(now).unwrap();
+        assert_eq!(estimate.expected, now + Duration::from_secs(3 * 60 - 40));
+
+        // As the step goes on, the estimate holds rather than moving later.
+        set_rot_elapsed(&mut state, 100);
+        let later = now + Duration::from_secs(60);
+        let estimate = state.estimated_completion(later).unwrap();
+        assert_eq!(estimate.expected, now + Duration::from_secs(3 * 60 - 40));
+    }
+}
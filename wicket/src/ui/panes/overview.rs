@@ -192,6 +192,7 @@ impl Control for RackView {
                 .bg(OX_GRAY_DARK)
                 .fg(OX_OFF_WHITE),
             suspicious_style: Style::default().bg(OX_RED).fg(OX_WHITE),
+            fault_style: Style::default().bg(OX_YELLOW).fg(TUI_BLACK),
             switch_style: Style::default().bg(OX_GRAY_DARK).fg(OX_WHITE),
             power_shelf_style: Style::default().bg(OX_GRAY).fg(OX_OFF_WHITE),
             sled_style: Style::default().bg(OX_GREEN_LIGHT).fg(TUI_BLACK),
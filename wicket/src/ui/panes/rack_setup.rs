@@ -7,6 +7,9 @@ use super::push_text_lines;
 use super::ComputedScrollOffset;
 use super::PendingScroll;
 use crate::keymap::ShowPopupCmd;
+use crate::rack_setup::validate_rss_config;
+use crate::rack_setup::ConfigValidationIssue;
+use crate::rack_setup::ValidationSeverity;
 use crate::ui::defaults::style;
 use crate::ui::widgets::BoxConnector;
 use crate::ui::widgets::BoxConnectorKind;
@@ -32,6 +35,7 @@ use ratatui::widgets::Paragraph;
 use std::borrow::Cow;
 use wicketd_client::types::Baseboard;
 use wicketd_client::types::CurrentRssUserConfig;
+use wicketd_client::types::CurrentRssUserConfigInsensitive;
 use wicketd_client::types::IpRange;
 use wicketd_client::types::RackOperationStatus;
 
@@ -85,6 +89,11 @@ pub struct RackSetupPane {
     scroll_offset: usize,
     pending_scroll: Option<PendingScroll>,
 
+    // The configuration we last validated, and the issues found in it, so we
+    // only validate each configuration once.
+    validation:
+        Option<(CurrentRssUserConfigInsensitive, Vec<ConfigValidationIssue>)>,
+
     popup: Option<Popup>,
 }
 
@@ -107,6 +116,7 @@ impl Default for RackSetupPane {
             ],
             scroll_offset: 0,
             pending_scroll: None,
+            validation: None,
             popup: None,
         }
     }
@@ -460,7 +470,12 @@ impl Control for RackSetupPane {
                 Some(Action::Redraw)
             }
             Cmd::StartRackSetup => match state.rack_setup_state.as_ref() {
-                Ok(RackOperationStatus::Uninitialized { .. }) => {
+                Ok(RackOperationStatus::Uninitialized { .. })
+                    if rss_config_is_ready(validation_issues(
+                        &mut self.validation,
+                        state.rss_config.as_ref(),
+                    )) =>
+                {
                     self.popup = Some(Popup::new_rack_setup());
                     Some(Action::Redraw)
                 }
@@ -516,9 +531,12 @@ impl Control for RackSetupPane {
         let contents_block = block
             .clone()
             .borders(Borders::LEFT | Borders::RIGHT | Borders::TOP);
+        let issues =
+            validation_issues(&mut self.validation, state.rss_config.as_ref());
         let text = rss_config_text(
             state.rack_setup_state.as_ref(),
             state.rss_config.as_ref(),
+            issues,
         );
         let y_offset = ComputedScrollOffset::new(
             self.scroll_offset,
@@ -536,7 +554,9 @@ impl Control for RackSetupPane {
 
         // Draw the help bar
         let help = match state.rack_setup_state.as_ref() {
-            Ok(RackOperationStatus::Uninitialized { .. }) => {
+            Ok(RackOperationStatus::Uninitialized { .. })
+                if rss_config_is_ready(issues) =>
+            {
                 &self.rack_uninitialized_help
             }
             Ok(RackOperationStatus::Initialized { .. }) => {
@@ -569,9 +589,32 @@ impl Control for RackSetupPane {
     }
 }
 
+// Return the issues found in `config` (if there is one), validating it only if
+// it differs from the configuration `validation` was computed for.
+fn validation_issues<'a>(
+    validation: &'a mut Option<(
+        CurrentRssUserConfigInsensitive,
+        Vec<ConfigValidationIssue>,
+    )>,
+    config: Option<&CurrentRssUserConfig>,
+) -> Option<&'a [ConfigValidationIssue]> {
+    let config = &config?.insensitive;
+    if validation.as_ref().map_or(true, |(validated, _)| validated != config) {
+        *validation = Some((config.clone(), validate_rss_config(config)));
+    }
+    validation.as_ref().map(|(_, issues)| issues.as_slice())
+}
+
+// Return true if there is a configuration and it has no errors (given the
+// issues found in it), i.e., if rack setup may be started.
+fn rss_config_is_ready(issues: Option<&[ConfigValidationIssue]>) -> bool {
+    issues.map_or(false, |issues| !issues.iter().any(|issue| issue.is_error()))
+}
+
 fn rss_config_text<'a>(
     setup_state: Result<&RackOperationStatus, &String>,
     config: Option<&'a CurrentRssUserConfig>,
+    issues: Option<&[ConfigValidationIssue]>,
 ) -> Text<'a> {
     fn dyn_span<'a>(
         ok: bool,
@@ -817,6 +860,35 @@ fn rss_config_text<'a>(
             .collect(),
     );
 
+    // Show every problem with the configuration as a checklist. Rack setup
+    // can't be started until the errors are fixed. These checks only cover
+    // the fields above: the certificates and recovery password are checked
+    // by wicketd.
+    let issues = issues.unwrap_or_default();
+    if issues.is_empty() {
+        spans.push(Line::from(vec![
+            Span::styled("Configuration checks: ", label_style),
+            Span::styled("No problems found", ok_style),
+            Span::styled(
+                " (certificates and password are checked by wicketd)",
+                label_style,
+            ),
+        ]));
+    } else {
+        spans.push(Span::styled("Configuration checks: ", label_style).into());
+        for issue in issues {
+            let (marker, style) = match issue.severity {
+                ValidationSeverity::Error => ("  ✘ ", bad_style),
+                ValidationSeverity::Warning => ("  ! ", warn_style),
+            };
+            spans.push(Line::from(vec![
+                Span::styled(marker, style),
+                Span::styled(format!("{}: ", issue.field), label_style),
+                Span::styled(issue.message.clone(), style),
+            ]));
+        }
+    }
+
     // Add a "trailing newline" for scrolling to work correctly.
     spans.push(Line::default());
 
@@ -3,13 +3,14 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, SystemTime};
 
 use super::{align_by, help_text, push_text_lines, Control, PendingScroll};
 use crate::keymap::ShowPopupCmd;
 use crate::state::{
-    update_component_title, ComponentId, Inventory, UpdateItemState,
-    ALL_COMPONENT_IDS,
+    update_component_title, ComponentId, Inventory, UpdateFilter,
+    UpdateItemState, ALL_COMPONENT_IDS,
 };
 use crate::ui::defaults::style;
 use crate::ui::widgets::{
@@ -53,6 +54,15 @@ impl UpdatePanePopup {
         Self::StartUpdate { popup_state: StartUpdatePopupState::Prompting }
     }
 
+    fn new_start_update_failed(message: String) -> Self {
+        Self::StartUpdate {
+            popup_state: StartUpdatePopupState::Failed {
+                message,
+                scroll_offset: PopupScrollOffset::default(),
+            },
+        }
+    }
+
     fn new_step_logs() -> Self {
         Self::StepLogs { scroll_offset: PopupScrollOffset::default() }
     }
@@ -144,11 +154,15 @@ pub struct UpdatePane {
     not_started_help: Vec<(&'static str, &'static str)>,
     running_help: Vec<(&'static str, &'static str)>,
     completed_help: Vec<(&'static str, &'static str)>,
+    failed_to_start_help: Vec<(&'static str, &'static str)>,
 
     /// TODO: Move following  state into global `State` so that recorder snapshots
     /// capture all state.
     tree_state: TreeState,
     items: Vec<TreeItem<'static>>,
+    // The component shown by each entry in `items`, which only contains the
+    // components present in the inventory and matched by the current filter.
+    item_ids: Vec<ComponentId>,
 
     // Per-component update state that isn't serializable.
     component_state: BTreeMap<ComponentId, ComponentUpdateListState>,
@@ -182,17 +196,27 @@ impl UpdatePane {
                 .iter()
                 .map(|id| TreeItem::new(*id, vec![]))
                 .collect(),
+            item_ids: ALL_COMPONENT_IDS.to_vec(),
             help: vec![
                 ("Expand", "<e>"),
                 ("Collapse", "<c>"),
                 ("Move", "<Up/Down>"),
                 ("Details", "<d>"),
                 ("Ignition", "<i>"),
+                ("Refresh", "<r>"),
+                ("Filter", "<f>"),
                 ("Update", "<Enter>"),
             ],
             not_started_help: vec![("Start", "<Ctrl-U>")],
             running_help: vec![("Abort", "<Ctrl-R Ctrl-A>")],
-            completed_help: vec![("Clear", "<Ctrl-R Ctrl-R>")],
+            completed_help: vec![
+                ("Clear", "<Ctrl-R Ctrl-R>"),
+                ("Clear All", "<Ctrl-R Ctrl-X>"),
+            ],
+            failed_to_start_help: vec![
+                ("Retry", "<Ctrl-U>"),
+                ("Clear", "<Ctrl-R Ctrl-R>"),
+            ],
             component_state: ALL_COMPONENT_IDS
                 .iter()
                 .map(|id| (*id, ComponentUpdateListState::default()))
@@ -279,6 +303,17 @@ impl UpdatePane {
                     progress_event_spans(progress_event, "Progress:");
                 body.lines.push(progress_spans);
 
+                // Show how progress has moved over time, so a stalled update
+                // stands out.
+                let series = state.update_state.items[&selected]
+                    .progress_series(step_info.component);
+                if series.len() > 1 {
+                    body.lines.push(Line::from(vec![
+                        Span::styled("History: ", style::selected()),
+                        Span::styled(sparkline(&series), style::plain_text()),
+                    ]));
+                }
+
                 // TODO: show previous attempts
             }
             StepStatus::Completed { info: Some(info) } => {
@@ -788,10 +823,18 @@ impl UpdatePane {
         let versions = state.update_state.artifact_versions.clone();
         let inventory = &state.inventory;
 
-        self.items = state
+        // Only components present in the inventory are listed, so that a
+        // partially-populated rack doesn't show a row for every empty slot.
+        let present: BTreeSet<_> = inventory.present_components().collect();
+        let shown: Vec<_> = state
             .update_state
-            .items
-            .iter()
+            .filtered_items()
+            .filter(|(id, _)| present.contains(id))
+            .collect();
+
+        self.item_ids = shown.iter().map(|(id, _)| **id).collect();
+        self.items = shown
+            .into_iter()
             .map(|(id, states)| {
                 let children: Vec<_> = states
                     .iter()
@@ -823,6 +866,10 @@ impl UpdatePane {
                 TreeItem::new(*id, children)
             })
             .collect();
+
+        // The set of items shown may have changed along with their state, so
+        // the tree selection must be recomputed.
+        self.ensure_selection_matches_rack_state(state);
     }
 
     fn update_component_list_items(
@@ -922,10 +969,21 @@ impl UpdatePane {
             Cmd::StartUpdate => {
                 let selected = state.rack_state.selected;
                 match state.update_state.item_state(selected) {
-                    UpdateItemState::NotStarted => {
+                    UpdateItemState::NotStarted
+                    | UpdateItemState::FailedToStart { .. } => {
                         // If an update hasn't been started or has failed to
-                        // start, "Press ... to start" is displayed.
-                        self.popup = Some(UpdatePanePopup::new_start_update());
+                        // start, "Press ... to start" is displayed. Don't let
+                        // an update start from a malformed repository, though.
+                        let popup =
+                            match state.update_state.start_blocked_reason() {
+                                Some(message) => {
+                                    UpdatePanePopup::new_start_update_failed(
+                                        message,
+                                    )
+                                }
+                                None => UpdatePanePopup::new_start_update(),
+                            };
+                        self.popup = Some(popup);
                         Some(Action::Redraw)
                     }
                     UpdateItemState::AwaitingRepository
@@ -935,6 +993,7 @@ impl UpdatePane {
             }
             Cmd::AbortUpdate => self.handle_abort_update(state),
             Cmd::ResetState => self.handle_clear_update_state(state),
+            Cmd::ResetAllState => self.handle_clear_all_update_state(state),
             Cmd::GotoTop => {
                 let id_state = self
                     .component_state
@@ -988,7 +1047,8 @@ impl UpdatePane {
             }
             UpdateItemState::AwaitingRepository
             | UpdateItemState::NotStarted
-            | UpdateItemState::UpdateStarted => None,
+            | UpdateItemState::UpdateStarted
+            | UpdateItemState::FailedToStart { .. } => None,
         }
     }
 
@@ -997,9 +1057,50 @@ impl UpdatePane {
         state: &mut State,
     ) -> Option<Action> {
         let selected = state.rack_state.selected;
-        match state.update_state.item_state(selected) {
+        if let UpdateItemState::FailedToStart { .. } =
+            state.update_state.item_state(selected)
+        {
+            // wicketd has no state for an update that never started, so
+            // there's nothing to ask it to clear.
+            state.update_state.reset_item(selected);
+            Some(Action::Redraw)
+        } else if self.is_update_finished(state, selected) {
+            self.popup = Some(UpdatePanePopup::new_clear_update_state());
+            Some(Action::ClearUpdateState(selected))
+        } else {
+            None
+        }
+    }
+
+    fn handle_clear_all_update_state(
+        &mut self,
+        state: &mut State,
+    ) -> Option<Action> {
+        // Only ask wicketd to clear updates that have reached a terminal
+        // state; running updates will reappear in the next event report.
+        let finished: Vec<_> = ALL_COMPONENT_IDS
+            .iter()
+            .copied()
+            .filter(|id| self.is_update_finished(state, *id))
+            .collect();
+        state.update_state.reset_all();
+        if finished.is_empty() {
+            Some(Action::Redraw)
+        } else {
+            Some(Action::ClearAllUpdateState(finished))
+        }
+    }
+
+    /// Returns true if an update for `component_id` has reached a terminal
+    /// state, and can therefore be cleared.
+    fn is_update_finished(
+        &self,
+        state: &State,
+        component_id: ComponentId,
+    ) -> bool {
+        match state.update_state.item_state(component_id) {
             UpdateItemState::RunningOrCompleted { .. } => {
-                let id_state = self.component_state.get(&selected).unwrap();
+                let id_state = self.component_state.get(&component_id).unwrap();
                 let event_buffer = &id_state.event_buffer;
                 if let Some(root_execution_id) =
                     event_buffer.root_execution_id()
@@ -1012,23 +1113,18 @@ impl UpdatePane {
                     match summary.execution_status {
                         ExecutionStatus::Completed { .. }
                         | ExecutionStatus::Failed { .. }
-                        | ExecutionStatus::Aborted { .. } => {
-                            // If execution has reached a terminal
-                            // state, we can clear it.
-                            self.popup =
-                                Some(UpdatePanePopup::new_clear_update_state());
-                            Some(Action::ClearUpdateState(selected))
-                        }
+                        | ExecutionStatus::Aborted { .. } => true,
                         ExecutionStatus::NotStarted
-                        | ExecutionStatus::Running { .. } => None,
+                        | ExecutionStatus::Running { .. } => false,
                     }
                 } else {
-                    None
+                    false
                 }
             }
             UpdateItemState::AwaitingRepository
             | UpdateItemState::NotStarted
-            | UpdateItemState::UpdateStarted => None,
+            | UpdateItemState::UpdateStarted
+            | UpdateItemState::FailedToStart { .. } => false,
         }
     }
 
@@ -1036,7 +1132,8 @@ impl UpdatePane {
         // We only show the toggle spans for force updating the SP/RoT when the
         // user could potentially start an update.
         match state.update_state.item_state(state.rack_state.selected) {
-            UpdateItemState::NotStarted => true,
+            UpdateItemState::NotStarted
+            | UpdateItemState::FailedToStart { .. } => true,
             UpdateItemState::AwaitingRepository
             | UpdateItemState::UpdateStarted
             | UpdateItemState::RunningOrCompleted { .. } => false,
@@ -1109,6 +1206,17 @@ impl UpdatePane {
                         popup_state @ StartUpdatePopupState::Prompting,
                         Cmd::Yes,
                     ) => {
+                        // The repository may have changed while the prompt
+                        // was displayed, so check it again.
+                        if let Some(message) =
+                            state.update_state.start_blocked_reason()
+                        {
+                            *popup_state = StartUpdatePopupState::Failed {
+                                message,
+                                scroll_offset: PopupScrollOffset::default(),
+                            };
+                            return Some(Action::Redraw);
+                        }
                         // Trigger the update
                         let selected = state.rack_state.selected;
                         info!(self.log, "Updating {}", selected);
@@ -1271,18 +1379,44 @@ impl UpdatePane {
         }
     }
 
+    /// Returns the component for the item selected in the tree, if any.
+    ///
+    /// This is `None` if the selected component is hidden by the current
+    /// filter.
+    fn selected_item_id(&self) -> Option<ComponentId> {
+        let selected = self.tree_state.selected();
+        selected.first().and_then(|index| self.item_ids.get(*index)).copied()
+    }
+
     // When we switch panes, we may have moved around in the rack. We want to
     // ensure that the currently selected rack component in the  update tree
     // matches what was selected in the rack or inventory views. We already do
     // the converse when on this pane and move around the tree.
     fn ensure_selection_matches_rack_state(&mut self, state: &State) {
-        let selected = self.tree_state.selected();
-        if state.rack_state.selected != ALL_COMPONENT_IDS[selected[0]] {
-            let index = ALL_COMPONENT_IDS
+        if self.selected_item_id() != Some(state.rack_state.selected) {
+            // If the selected component is hidden by the current filter,
+            // nothing is selected in the tree.
+            let selected = self
+                .item_ids
                 .iter()
                 .position(|&id| id == state.rack_state.selected)
-                .unwrap();
-            self.tree_state.select(vec![index]);
+                .map_or_else(Vec::new, |index| vec![index]);
+            self.tree_state.select(selected);
+        }
+    }
+
+    /// Switches to the next filter for the update list.
+    ///
+    /// If the selected component is hidden by the new filter, the first
+    /// component that is still shown is selected instead.
+    fn cycle_filter(&mut self, state: &mut State) {
+        state.update_state.filter = state.update_state.filter.next();
+        self.update_items(state);
+        if self.selected_item_id().is_none() {
+            if let Some(id) = self.item_ids.first() {
+                state.rack_state.selected = *id;
+                self.tree_state.select_first();
+            }
         }
     }
 
@@ -1300,12 +1434,51 @@ impl UpdatePane {
             .border_type(BorderType::Rounded)
             .style(border_style);
 
-        // Draw the title/tab bar
-        let title_bar = Paragraph::new(Line::from(vec![Span::styled(
-            "UPDATE STATUS",
-            header_style,
-        )]))
-        .block(block.clone());
+        // Draw the title/tab bar, warning if there's no repository to install
+        // from.
+        let version_style = if state.update_state.system_version.is_some() {
+            style::plain_text_bold()
+        } else {
+            style::warning()
+        };
+        let mut title = vec![
+            Span::styled("UPDATE STATUS", header_style),
+            Span::styled(" | ", style::divider()),
+            Span::styled(
+                state.update_state.system_version_header(),
+                version_style,
+            ),
+        ];
+        if let Some(percent) = state.update_state.overall_progress_percent() {
+            title.push(Span::styled(
+                format!(" ({percent}% COMPLETE)"),
+                style::plain_text(),
+            ));
+        }
+        if let Some(estimate) =
+            state.update_state.estimated_completion(SystemTime::now())
+        {
+            let margin = estimate
+                .latest
+                .duration_since(estimate.expected)
+                .unwrap_or_default();
+            title.push(Span::styled(
+                format!(
+                    " (DONE AROUND {}, ±{})",
+                    format_utc_time(estimate.expected),
+                    format_minutes(margin),
+                ),
+                style::plain_text(),
+            ));
+        }
+        let filter = state.update_state.filter;
+        if filter != UpdateFilter::All {
+            title.push(Span::styled(
+                format!(" (SHOWING ONLY {filter})"),
+                style::plain_text(),
+            ));
+        }
+        let title_bar = Paragraph::new(Line::from(title)).block(block.clone());
         frame.render_widget(title_bar, self.title_rect);
 
         // Draw the table headers
@@ -1575,6 +1748,42 @@ impl UpdatePane {
                 };
                 status_view.render(frame);
             }
+            UpdateItemState::FailedToStart { message } => {
+                let status_text = Text::from(Line::from(vec![
+                    Span::styled("Update ", style::plain_text()),
+                    Span::styled(
+                        "failed to start",
+                        style::failed_update_bold(),
+                    ),
+                ]));
+
+                let message_text = Text::from(Line::from(Span::styled(
+                    message.to_owned(),
+                    style::plain_text(),
+                )));
+
+                // Wrap the text to the screen width.
+                let options = crate::ui::wrap::Options {
+                    // Subtract 2 for borders.
+                    width: self.status_view_main_rect.width.saturating_sub(2)
+                        as usize,
+                    initial_indent: Span::raw(""),
+                    subsequent_indent: Span::raw(""),
+                    break_words: true,
+                };
+                let wrapped_text = wrap_text(&message_text, options);
+
+                let status_view = StatusView {
+                    status_view_rect: self.status_view_main_rect,
+                    help_rect: self.help_rect,
+                    title: "UPDATE STATUS".into(),
+                    status_text,
+                    widget: Paragraph::new(wrapped_text),
+                    help_text: Some(help_text(&self.failed_to_start_help)),
+                    block,
+                };
+                status_view.render(frame);
+            }
             UpdateItemState::RunningOrCompleted { .. } => {
                 let id_state = self
                     .component_state
@@ -1614,6 +1823,38 @@ impl UpdatePane {
     }
 }
 
+/// The maximum number of values shown by [`sparkline`].
+const MAX_SPARKLINE_LEN: usize = 60;
+
+/// Renders the most recent percentages in `series` as a line of block
+/// characters, one per value.
+fn sparkline(series: &[u8]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let start = series.len().saturating_sub(MAX_SPARKLINE_LEN);
+    series[start..]
+        .iter()
+        .map(|&percent| {
+            let percent = usize::from(percent.min(100));
+            BARS[percent * (BARS.len() - 1) / 100]
+        })
+        .collect()
+}
+
+/// Formats the time of day of `time` in UTC, e.g. "14:32 UTC".
+fn format_utc_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    format!("{:02}:{:02} UTC", secs / 3600 % 24, secs / 60 % 60)
+}
+
+/// Formats `duration` as a whole number of minutes, rounding up.
+fn format_minutes(duration: Duration) -> String {
+    format!("{} MIN", (duration.as_secs() + 59) / 60)
+}
+
 fn progress_event_spans(
     progress_event: &ProgressEvent,
     header: &str,
@@ -2323,14 +2564,16 @@ impl Control for UpdatePane {
         match cmd {
             Cmd::Up => {
                 self.tree_state.key_up(&self.items);
-                let selected = self.tree_state.selected();
-                state.rack_state.selected = ALL_COMPONENT_IDS[selected[0]];
+                if let Some(id) = self.selected_item_id() {
+                    state.rack_state.selected = id;
+                }
                 Some(Action::Redraw)
             }
             Cmd::Down => {
                 self.tree_state.key_down(&self.items);
-                let selected = self.tree_state.selected();
-                state.rack_state.selected = ALL_COMPONENT_IDS[selected[0]];
+                if let Some(id) = self.selected_item_id() {
+                    state.rack_state.selected = id;
+                }
                 Some(Action::Redraw)
             }
             Cmd::Collapse | Cmd::Left => {
@@ -2349,6 +2592,13 @@ impl Control for UpdatePane {
                 self.tree_state.key_right();
                 Some(Action::Redraw)
             }
+            // Don't act on the selected component if the current filter hides
+            // it, since the user can't see what they'd be acting on.
+            Cmd::Enter | Cmd::Ignition | Cmd::Refresh
+                if self.selected_item_id().is_none() =>
+            {
+                None
+            }
             Cmd::Enter => {
                 state.update_state.status_view_displayed = true;
                 Some(Action::Redraw)
@@ -2358,15 +2608,27 @@ impl Control for UpdatePane {
                 self.popup = Some(UpdatePanePopup::new_ignition());
                 Some(Action::Redraw)
             }
+            Cmd::Refresh => {
+                // Re-read the selected component's cabooses so the operator
+                // can confirm the versions after an update.
+                Some(Action::RefreshCabooses(state.rack_state.selected))
+            }
+            Cmd::CycleFilter => {
+                self.cycle_filter(state);
+                Some(Action::Redraw)
+            }
             Cmd::GotoTop => {
-                self.tree_state.select_first();
-                state.rack_state.selected = ALL_COMPONENT_IDS[0];
+                if let Some(id) = self.item_ids.first() {
+                    self.tree_state.select_first();
+                    state.rack_state.selected = *id;
+                }
                 Some(Action::Redraw)
             }
             Cmd::GotoBottom => {
-                self.tree_state.select_last(&self.items);
-                state.rack_state.selected =
-                    ALL_COMPONENT_IDS[ALL_COMPONENT_IDS.len() - 1];
+                if let Some(id) = self.item_ids.last() {
+                    self.tree_state.select_last(&self.items);
+                    state.rack_state.selected = *id;
+                }
                 Some(Action::Redraw)
             }
             _ => None,
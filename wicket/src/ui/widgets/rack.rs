@@ -16,7 +16,7 @@ use ratatui::widgets::Block;
 use ratatui::widgets::Borders;
 use ratatui::widgets::Paragraph;
 use ratatui::widgets::Widget;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use wicketd_client::types::SpIgnition;
 
 #[derive(Debug, Clone)]
@@ -24,6 +24,7 @@ pub struct Rack<'a> {
     pub inventory: &'a Inventory,
     pub state: &'a RackState,
     pub suspicious_style: Style,
+    pub fault_style: Style,
     pub not_present_style: Style,
     pub sled_style: Style,
     pub sled_selected_style: Style,
@@ -40,8 +41,9 @@ impl<'a> Rack<'a> {
         let component_id = ComponentId::Sled(i);
         let presence =
             ComponentPresence::for_component(self.inventory, &component_id);
+        let faulted = has_ignition_fault(self.inventory, &component_id);
         let mut block = Block::default()
-            .title(format!("SLD{}", i))
+            .title(title(format!("SLD{}", i), faulted))
             .borders(borders(sled.height));
         if self.state.selected == component_id {
             block = block
@@ -49,6 +51,7 @@ impl<'a> Rack<'a> {
                 .border_style(self.border_selected_style);
         } else {
             let style = match presence {
+                _ if faulted => self.fault_style,
                 ComponentPresence::Present => self.sled_style,
                 ComponentPresence::NotPresent => self.not_present_style,
                 ComponentPresence::Suspicious => self.suspicious_style,
@@ -96,8 +99,9 @@ impl<'a> Rack<'a> {
         let component_id = ComponentId::Switch(i);
         let presence =
             ComponentPresence::for_component(self.inventory, &component_id);
+        let faulted = has_ignition_fault(self.inventory, &component_id);
         let mut block = Block::default()
-            .title(format!("SW{}", i))
+            .title(title(format!("SW{}", i), faulted))
             .borders(borders(switch.height));
         if self.state.selected == component_id {
             block = block
@@ -105,6 +109,7 @@ impl<'a> Rack<'a> {
                 .border_style(self.border_selected_style);
         } else {
             let style = match presence {
+                _ if faulted => self.fault_style,
                 ComponentPresence::Present => self.switch_style,
                 ComponentPresence::NotPresent => self.not_present_style,
                 ComponentPresence::Suspicious => self.suspicious_style,
@@ -128,8 +133,9 @@ impl<'a> Rack<'a> {
         let component_id = ComponentId::Psc(i);
         let presence =
             ComponentPresence::for_component(self.inventory, &component_id);
+        let faulted = has_ignition_fault(self.inventory, &component_id);
         let mut block = Block::default()
-            .title(format!("PWR{}", i))
+            .title(title(format!("PWR{}", i), faulted))
             .borders(borders(power_shelf.height));
         if self.state.selected == component_id {
             block = block
@@ -137,6 +143,7 @@ impl<'a> Rack<'a> {
                 .border_style(self.border_selected_style);
         } else {
             let style = match presence {
+                _ if faulted => self.fault_style,
                 ComponentPresence::Present => self.power_shelf_style,
                 ComponentPresence::NotPresent => self.not_present_style,
                 ComponentPresence::Suspicious => self.suspicious_style,
@@ -204,6 +211,30 @@ impl ComponentPresence {
     }
 }
 
+// Glyph appended to the title of a component with an ignition fault.
+const FAULT_GLYPH: &str = "⚠";
+
+// Return true if ignition reports a fault for the component: its SP or RoT
+// has faulted, or it failed to reach power state A2 or A3.
+fn has_ignition_fault(inventory: &Inventory, component: &ComponentId) -> bool {
+    let ignition = inventory
+        .get_inventory(component)
+        .and_then(|component| component.sp().ignition());
+    matches!(
+        ignition,
+        Some(SpIgnition::Yes { flt_a2, flt_a3, flt_rot, flt_sp, .. })
+            if *flt_a2 || *flt_a3 || *flt_rot || *flt_sp
+    )
+}
+
+fn title(name: String, faulted: bool) -> String {
+    if faulted {
+        format!("{name} {FAULT_GLYPH}")
+    } else {
+        name
+    }
+}
+
 // Each of the top and bottom borders take one line. The rendering looks
 // better with all borders, but to save space, we don't draw the bottom
 // border if we don't have 3 lines available.
@@ -219,7 +250,15 @@ impl<'a> Widget for Rack<'a> {
     fn render(self, rect: Rect, buf: &mut Buffer) {
         match resize(rect) {
             ComponentRects::Displayed { rects_map, .. } => {
+                // Slots without a component in the inventory are left blank
+                // (unless selected), so a partially-populated rack only shows
+                // what's installed.
+                let present: BTreeSet<_> =
+                    self.inventory.present_components().collect();
                 for (id, rect) in rects_map {
+                    if !present.contains(&id) && self.state.selected != id {
+                        continue;
+                    }
                     match id {
                         ComponentId::Sled(i) => self.draw_sled(buf, rect, i),
                         ComponentId::Switch(i) => {
@@ -418,3 +457,107 @@ pub fn make_even(val: u16) -> u16 {
         val + 1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wicketd_client::types::{
+        RackV1Inventory, SpIdentifier, SpIgnitionSystemType, SpInventory,
+        SpType,
+    };
+
+    fn sled_with_ignition(slot: u32, flt_sp: bool) -> SpInventory {
+        SpInventory {
+            id: SpIdentifier { type_: SpType::Sled, slot },
+            ignition: Some(SpIgnition::Yes {
+                ctrl_detect_0: true,
+                ctrl_detect_1: false,
+                flt_a2: false,
+                flt_a3: false,
+                flt_rot: false,
+                flt_sp,
+                id: SpIgnitionSystemType::Gimlet,
+                power: true,
+            }),
+            state: None,
+            components: None,
+            caboose_active: None,
+            caboose_inactive: None,
+            rot: None,
+        }
+    }
+
+    #[test]
+    fn faulted_component_uses_fault_style() {
+        let mut inventory = Inventory::default();
+        inventory
+            .update_inventory(RackV1Inventory {
+                sps: vec![
+                    sled_with_ignition(3, true),
+                    sled_with_ignition(4, false),
+                ],
+            })
+            .unwrap();
+        let state = RackState::new();
+        let fault_style = Style::default().bg(Color::Yellow);
+        let suspicious_style = Style::default().bg(Color::Red);
+        let rack = Rack {
+            inventory: &inventory,
+            state: &state,
+            suspicious_style,
+            fault_style,
+            not_present_style: Style::default().bg(Color::DarkGray),
+            sled_style: Style::default().bg(Color::Green),
+            sled_selected_style: Style::default().bg(Color::Magenta),
+            switch_style: Style::default().bg(Color::Gray),
+            switch_selected_style: Style::default().bg(Color::Magenta),
+            power_shelf_style: Style::default().bg(Color::Gray),
+            power_shelf_selected_style: Style::default().bg(Color::Magenta),
+            border_style: Style::default(),
+            border_selected_style: Style::default(),
+        };
+
+        let area = Rect::new(0, 0, 80, 80);
+        let mut buf = Buffer::empty(area);
+        rack.render(area, &mut buf);
+
+        let ComponentRects::Displayed { rects_map, .. } = resize(area) else {
+            panic!("window is tall enough to draw the rack");
+        };
+        let top_row = |rect: &Rect| {
+            (rect.left()..rect.right())
+                .map(|x| buf.get(x, rect.top()).symbol.as_str())
+                .collect::<String>()
+        };
+
+        // The faulted sled is drawn in the fault style, with the fault glyph
+        // in its title...
+        let faulted = rects_map[&ComponentId::Sled(3)];
+        let inside = buf.get(faulted.left() + 1, faulted.top() + 1);
+        assert_eq!(inside.bg, Color::Yellow);
+        assert!(
+            top_row(&faulted).contains(&format!("SLD3 {FAULT_GLYPH}")),
+            "fault glyph in title: {:?}",
+            top_row(&faulted)
+        );
+
+        // ... while a sled without a fault is drawn as it would be otherwise,
+        // i.e., as suspicious, since it has no SP state.
+        let healthy = rects_map[&ComponentId::Sled(4)];
+        let inside = buf.get(healthy.left() + 1, healthy.top() + 1);
+        assert_eq!(inside.bg, Color::Red);
+        assert!(!top_row(&healthy).contains(FAULT_GLYPH));
+
+        // Slots without a component in the inventory aren't drawn, except for
+        // the selected one.
+        assert_ne!(state.selected, ComponentId::Sled(5));
+        let empty = rects_map[&ComponentId::Sled(5)];
+        assert_eq!(top_row(&empty).trim(), "");
+        let selected = rects_map[&state.selected];
+        assert!(
+            top_row(&selected).contains("SLD"),
+            "selected slot drawn: {:?}",
+            top_row(&selected)
+        );
+    }
+}
@@ -4,19 +4,21 @@
 
 //! Code for talking to wicketd
 
-use slog::{o, warn, Logger};
+use slog::{debug, o, warn, Logger};
 use std::convert::From;
 use std::net::SocketAddrV6;
 use tokio::sync::mpsc::{self, Sender, UnboundedSender};
 use tokio::time::{interval, Duration, MissedTickBehavior};
 use wicketd_client::types::{
-    AbortUpdateOptions, ClearUpdateStateOptions, GetInventoryParams,
+    AbortUpdateOptions, ClearUpdateStateOptions,
+    GetArtifactsAndEventReportsParams, GetInventoryParams,
     GetInventoryResponse, GetLocationResponse, IgnitionCommand, SpIdentifier,
     SpType, StartUpdateOptions, StartUpdateParams,
 };
 
 use crate::events::EventReportMap;
 use crate::keymap::ShowPopupCmd;
+use crate::rack_setup::LoggedRssConfig;
 use crate::state::ComponentId;
 use crate::{Cmd, Event};
 
@@ -64,6 +66,7 @@ pub enum Request {
         options: ClearUpdateStateOptions,
     },
     IgnitionCommand(ComponentId, IgnitionCommand),
+    RefreshCabooses(ComponentId),
     StartRackSetup,
     StartRackReset,
 }
@@ -137,6 +140,9 @@ impl WicketdManager {
                                 poll_interval_now_tx.clone(),
                             );
                         }
+                        Request::RefreshCabooses(component_id) => {
+                            self.refresh_cabooses(component_id);
+                        }
                         Request::StartRackSetup => {
                             self.start_rack_initialization();
                         }
@@ -282,6 +288,35 @@ impl WicketdManager {
         });
     }
 
+    fn refresh_cabooses(&self, component_id: ComponentId) {
+        let log = self.log.clone();
+        let addr = self.wicketd_addr;
+        let events_tx = self.events_tx.clone();
+        tokio::spawn(async move {
+            let client = create_wicketd_client(&log, addr, WICKETD_TIMEOUT);
+            let sp: SpIdentifier = component_id.into();
+            match client.get_sp_cabooses(sp.type_, sp.slot).await {
+                Ok(cabooses) => {
+                    _ = events_tx.send(Event::SpCabooses {
+                        component_id,
+                        cabooses: cabooses.into_inner(),
+                    });
+                }
+                Err(error) => {
+                    // There's nobody to return this error to; the operator can
+                    // try again, and the next inventory poll will pick up any
+                    // changes regardless.
+                    warn!(
+                        log,
+                        "Failed to refresh cabooses for {}: {}",
+                        component_id,
+                        error
+                    );
+                }
+            }
+        });
+    }
+
     fn start_rack_initialization(&self) {
         let log = self.log.clone();
         let addr = self.wicketd_addr;
@@ -418,6 +453,10 @@ impl WicketdManager {
                         if Some(&rsp) == prev.as_ref() {
                             continue;
                         }
+                        debug!(
+                            log, "RSS config changed";
+                            "config" => ?LoggedRssConfig(&rsp),
+                        );
                         prev = Some(rsp.clone());
                         let _ = tx.send(Event::RssConfig(rsp));
                     }
@@ -443,7 +482,14 @@ impl WicketdManager {
             loop {
                 ticker.tick().await;
                 // TODO: We should really be using ETAGs here
-                match client.get_artifacts_and_event_reports().await {
+                //
+                // We always ask for full event reports (an empty `last_seen`),
+                // since our update state replaces each report wholesale rather
+                // than merging deltas.
+                let params = GetArtifactsAndEventReportsParams {
+                    last_seen: Default::default(),
+                };
+                match client.get_artifacts_and_event_reports(&params).await {
                     Ok(val) => {
                         // TODO: Only send on changes
                         let rsp = val.into_inner();
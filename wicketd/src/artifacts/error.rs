@@ -73,8 +73,16 @@ pub(super) enum RepositoryError {
     #[error("multiple artifacts found for kind `{0:?}`")]
     DuplicateArtifactKind(KnownArtifactKind),
 
-    #[error("duplicate board found for kind `{kind:?}`: `{board}`")]
-    DuplicateBoardEntry { board: String, kind: KnownArtifactKind },
+    #[error(
+        "duplicate board found for kind `{kind:?}`: `{board}` \
+         (in both {first:?} and {second:?})"
+    )]
+    DuplicateBoardEntry {
+        board: String,
+        kind: KnownArtifactKind,
+        first: ArtifactId,
+        second: ArtifactId,
+    },
 
     #[error("error parsing artifact {id:?} as hubris archive")]
     ParsingHubrisArchive {
@@ -84,6 +84,18 @@ impl ExtractedArtifactDataHandle {
 
         Ok(ReaderStream::new(file))
     }
+
+    /// Async stream to read the contents of this artifact on demand, along
+    /// with the artifact's total size in bytes.
+    ///
+    /// The size was recorded when the artifact was extracted, so callers
+    /// reporting progress don't need to `stat` the file separately.
+    pub(crate) async fn reader_stream_with_size(
+        &self,
+    ) -> anyhow::Result<(u64, ReaderStream<impl AsyncRead>)> {
+        let stream = self.reader_stream().await?;
+        Ok((self.file_size as u64, stream))
+    }
 }
 
 /// `ExtractedArtifacts` is a temporary wrapper around a `Utf8TempDir` for use
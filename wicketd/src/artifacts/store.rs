@@ -32,6 +32,22 @@ impl WicketdArtifactStore {
         Self { log, artifacts_with_plan: Default::default() }
     }
 
+    /// Replaces the repository without the checks the update tracker
+    /// performs.
+    ///
+    /// Only for integration tests, which use this to swap the repository
+    /// out from under a running update.
+    #[doc(hidden)]
+    pub async fn put_repository_for_test<T>(
+        &self,
+        data: T,
+    ) -> Result<(), HttpError>
+    where
+        T: io::Read + io::Seek + Send + 'static,
+    {
+        self.put_repository(data).await
+    }
+
     pub(crate) async fn put_repository<T>(
         &self,
         data: T,
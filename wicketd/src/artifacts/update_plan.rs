@@ -22,6 +22,8 @@ use omicron_common::update::ArtifactHash;
 use omicron_common::update::ArtifactHashId;
 use omicron_common::update::ArtifactId;
 use omicron_common::update::ArtifactKind;
+use sha2::Digest;
+use sha2::Sha256;
 use slog::info;
 use slog::Logger;
 use std::collections::btree_map;
@@ -74,6 +76,42 @@ pub struct UpdatePlan {
     pub control_plane_hash: ArtifactHash,
 }
 
+impl UpdatePlan {
+    /// Returns a hash of everything this plan would apply.
+    ///
+    /// Plans built from different repositories have different hashes, unless
+    /// the repositories contain the same system version and artifacts.
+    pub(crate) fn content_hash(&self) -> ArtifactHash {
+        let mut hasher = Sha256::new();
+        hasher.update(self.system_version.to_string());
+        for sp_artifacts in [&self.gimlet_sp, &self.psc_sp, &self.sidecar_sp] {
+            for (board, artifact) in sp_artifacts {
+                // Terminate each board name, so that one ending can't run into
+                // the next.
+                hasher.update(&board.0);
+                hasher.update([0]);
+                hasher.update(artifact.data.hash().0);
+            }
+        }
+        for artifact in [
+            &self.gimlet_rot_a,
+            &self.gimlet_rot_b,
+            &self.psc_rot_a,
+            &self.psc_rot_b,
+            &self.sidecar_rot_a,
+            &self.sidecar_rot_b,
+            &self.host_phase_1,
+            &self.trampoline_phase_1,
+            &self.trampoline_phase_2,
+        ] {
+            hasher.update(artifact.data.hash().0);
+        }
+        hasher.update(self.host_phase_2_hash.0);
+        hasher.update(self.control_plane_hash.0);
+        ArtifactHash(hasher.finalize().into())
+    }
+}
+
 /// `UpdatePlanBuilder` mirrors all the fields of `UpdatePlan`, but they're all
 /// optional: it can be filled in as we read a TUF repository.
 /// [`UpdatePlanBuilder::build()`] will (fallibly) convert from the builder to
@@ -247,6 +285,8 @@ impl<'a> UpdatePlanBuilder<'a> {
                 return Err(RepositoryError::DuplicateBoardEntry {
                     board: slot.key().0.clone(),
                     kind: artifact_kind,
+                    first: slot.get().id.clone(),
+                    second: artifact_id,
                 });
             }
         };
@@ -1051,13 +1091,109 @@ mod tests {
         logctx.cleanup_successful();
     }
 
+    #[test]
+    fn test_update_plan_rejects_duplicate_sp_boards() {
+        let logctx =
+            test_setup_log("test_update_plan_rejects_duplicate_sp_boards");
+
+        let mut by_id = BTreeMap::new();
+        let mut by_hash = HashMap::new();
+        let mut plan_builder =
+            UpdatePlanBuilder::new("0.0.0".parse().unwrap(), &logctx.log)
+                .unwrap();
+
+        let data = make_fake_sp_image("test-board");
+        let hash = ArtifactHash(Sha256::digest(&data).into());
+        let make_id = |kind: KnownArtifactKind, version| ArtifactId {
+            name: "test-board".to_string(),
+            version,
+            kind: kind.into(),
+        };
+        let first =
+            make_id(KnownArtifactKind::GimletSp, "0.0.0".parse().unwrap());
+        let second =
+            make_id(KnownArtifactKind::GimletSp, "1.0.0".parse().unwrap());
+
+        plan_builder
+            .add_artifact(
+                first.clone(),
+                hash,
+                io::BufReader::new(io::Cursor::new(&data)),
+                &mut by_id,
+                &mut by_hash,
+            )
+            .unwrap();
+
+        // The same board is fine for a different kind of SP.
+        plan_builder
+            .add_artifact(
+                make_id(KnownArtifactKind::PscSp, "0.0.0".parse().unwrap()),
+                hash,
+                io::BufReader::new(io::Cursor::new(&data)),
+                &mut by_id,
+                &mut by_hash,
+            )
+            .unwrap();
+
+        // But a second Gimlet SP image for the same board is rejected.
+        let error = plan_builder
+            .add_artifact(
+                second.clone(),
+                hash,
+                io::BufReader::new(io::Cursor::new(&data)),
+                &mut by_id,
+                &mut by_hash,
+            )
+            .unwrap_err();
+        match error {
+            RepositoryError::DuplicateBoardEntry {
+                board,
+                kind,
+                first: error_first,
+                second: error_second,
+            } => {
+                assert_eq!(board, "test-board");
+                assert_eq!(kind, KnownArtifactKind::GimletSp);
+                assert_eq!(error_first, first);
+                assert_eq!(error_second, second);
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+
+        logctx.cleanup_successful();
+    }
+
+    #[tokio::test]
+    async fn test_reader_stream_reports_artifact_size() {
+        let logctx = test_setup_log("test_reader_stream_reports_artifact_size");
+        let mut extracted_artifacts =
+            ExtractedArtifacts::new(&logctx.log).unwrap();
+
+        let data = make_random_bytes();
+        let hash_id = ArtifactHashId {
+            kind: KnownArtifactKind::ControlPlane.into(),
+            hash: ArtifactHash(Sha256::digest(&data).into()),
+        };
+        let handle =
+            extracted_artifacts.store(hash_id, io::Cursor::new(&data)).unwrap();
+
+        let (size, _) = handle.reader_stream_with_size().await.unwrap();
+        assert_eq!(size, data.len() as u64);
+        assert_eq!(read_to_vec(&handle).await, data);
+
+        logctx.cleanup_successful();
+    }
+
+    // Read the contents of an artifact, checking that the size reported with
+    // the stream matches what was read.
     async fn read_to_vec(data: &ExtractedArtifactDataHandle) -> Vec<u8> {
         let mut buf = Vec::with_capacity(data.file_size());
-        let mut stream = data.reader_stream().await.unwrap();
+        let (size, mut stream) = data.reader_stream_with_size().await.unwrap();
         while let Some(data) = stream.next().await {
             let data = data.unwrap();
             buf.extend_from_slice(&data);
         }
+        assert_eq!(size, buf.len() as u64, "reported size doesn't match data");
         buf
     }
 }
@@ -7,9 +7,10 @@
 use clap::Parser;
 use omicron_common::cmd::{fatal, CmdError};
 use sled_hardware::Baseboard;
-use std::net::SocketAddrV6;
+use std::net::{Ipv6Addr, SocketAddrV6};
 use std::path::PathBuf;
-use wicketd::{self, run_openapi, Config, Server};
+use uuid::Uuid;
+use wicketd::{self, run_openapi, serve_update_metrics, Config, Server};
 
 #[derive(Debug, Parser)]
 #[clap(name = "wicketd", about = "See README.adoc for more information")]
@@ -36,6 +37,18 @@ enum Args {
 
         #[clap(long)]
         baseboard_file: Option<PathBuf>,
+
+        /// The ID with which update metrics are registered with Nexus
+        ///
+        /// Metrics are exported only if this and `--underlay-address` are
+        /// both set. "unknown", our SMF manifest's default, is treated as
+        /// unset.
+        #[clap(long, action)]
+        metrics_id: Option<String>,
+
+        /// The underlay address from which update metrics are exported
+        #[clap(long, action)]
+        underlay_address: Option<String>,
     },
 }
 
@@ -57,6 +70,8 @@ async fn do_run() -> Result<(), CmdError> {
             artifact_address,
             mgs_address,
             baseboard_file,
+            metrics_id,
+            underlay_address,
         } => {
             let baseboard = if let Some(baseboard_file) = baseboard_file {
                 let baseboard_file =
@@ -96,9 +111,50 @@ async fn do_run() -> Result<(), CmdError> {
             let log = config.log.to_logger("wicketd").map_err(|msg| {
                 CmdError::Failure(format!("initializing logger: {}", msg))
             })?;
-            let server =
-                Server::start(log, args).await.map_err(CmdError::Failure)?;
+
+            let metrics_config =
+                parse_metrics_config(metrics_id, underlay_address)?;
+
+            let server = Server::start(log.clone(), args)
+                .await
+                .map_err(CmdError::Failure)?;
+
+            // The underlay address is only known once the rack is initialized,
+            // so until wicketd is next started after that, metrics aren't
+            // exported.
+            if let Some((id, address)) = metrics_config {
+                tokio::spawn(serve_update_metrics(
+                    log,
+                    server.update_tracker.metrics().clone(),
+                    id,
+                    address,
+                ));
+            }
             server.wait_for_finish().await.map_err(CmdError::Failure)
         }
     }
 }
+
+// Parse the producer ID and underlay address used to export metrics, returning
+// `None` if either is unset.
+fn parse_metrics_config(
+    id: Option<String>,
+    address: Option<String>,
+) -> Result<Option<(Uuid, Ipv6Addr)>, CmdError> {
+    let known = |value: Option<String>| value.filter(|v| v != "unknown");
+    let (Some(id), Some(address)) = (known(id), known(address)) else {
+        return Ok(None);
+    };
+    let id = Uuid::try_parse(&id).map_err(|err| {
+        CmdError::Failure(format!(
+            "failed to parse metrics ID ({id:?}) as a UUID: {err}"
+        ))
+    })?;
+    let address = address.parse::<Ipv6Addr>().map_err(|err| {
+        CmdError::Failure(format!(
+            "failed to parse underlay address ({address:?}) as an IPv6 \
+            address: {err}"
+        ))
+    })?;
+    Ok(Some((id, address)))
+}
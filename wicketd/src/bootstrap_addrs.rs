@@ -16,6 +16,7 @@ use std::time::Duration;
 use tokio::task::JoinHandle;
 use tokio_stream::StreamExt;
 
+#[derive(Debug)]
 pub(crate) struct BootstrapPeers {
     // We use a standard mutex here, not a tokio mutex, even though this is
     // shared with a tokio task. We only keep it locked long enough to insert a
@@ -21,6 +21,10 @@ use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::OnceLock;
 
+/// Port on which the bootstrap agent dropshot server within sled-agent is
+/// listening.
+pub(crate) const BOOTSTRAP_AGENT_HTTP_PORT: u16 = 80;
+
 /// Shared state used by API handlers
 pub struct ServerContext {
     pub mgs_handle: MgsHandle,
@@ -31,7 +35,7 @@ pub struct ServerContext {
     /// (plugging us into a different switch would require powering off our sled
     /// and physically moving it).
     pub(crate) local_switch_id: OnceLock<SpIdentifier>,
-    pub(crate) bootstrap_peers: BootstrapPeers,
+    pub(crate) bootstrap_peers: Arc<BootstrapPeers>,
     pub(crate) update_tracker: Arc<UpdateTracker>,
     pub(crate) baseboard: Option<Baseboard>,
     pub(crate) rss_config: Mutex<CurrentRssConfig>,
@@ -40,10 +44,6 @@ pub struct ServerContext {
 
 impl ServerContext {
     pub(crate) fn bootstrap_agent_addr(&self) -> Result<SocketAddrV6> {
-        // Port on which the bootstrap agent dropshot server within sled-agent
-        // is listening.
-        const BOOTSTRAP_AGENT_HTTP_PORT: u16 = 80;
-
         let ip = self.bootstrap_agent_ip()?;
         Ok(SocketAddrV6::new(ip, BOOTSTRAP_AGENT_HTTP_PORT, 0, 0))
     }
@@ -34,6 +34,15 @@ impl fmt::Display for SpIdentifierDisplay {
     }
 }
 
+/// Returns true if `sp`'s slot is within the legal range for its type: sleds
+/// 0-31, switches 0-1, and PSCs 0-1.
+pub(crate) fn is_valid_sp_identifier(sp: &SpIdentifier) -> bool {
+    match sp.type_ {
+        SpType::Sled => sp.slot <= 31,
+        SpType::Switch | SpType::Power => sp.slot <= 1,
+    }
+}
+
 pub(crate) fn sps_to_string<S: Into<SpIdentifierDisplay>>(
     sps: impl IntoIterator<Item = S>,
 ) -> String {
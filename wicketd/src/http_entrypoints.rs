@@ -12,6 +12,8 @@ use crate::mgs::MgsHandle;
 use crate::mgs::ShutdownInProgress;
 use crate::preflight_check::UplinkEventReport;
 use crate::RackV1Inventory;
+use crate::SpCabooses;
+use crate::StartUpdateError;
 use bootstrap_agent_client::types::RackInitId;
 use bootstrap_agent_client::types::RackOperationStatus;
 use bootstrap_agent_client::types::RackResetId;
@@ -69,14 +71,19 @@ pub fn api() -> WicketdApiDescription {
         api.register(post_run_rack_setup)?;
         api.register(post_run_rack_reset)?;
         api.register(get_inventory)?;
+        api.register(get_sp_cabooses)?;
         api.register(get_location)?;
         api.register(put_repository)?;
+        api.register(get_artifacts)?;
         api.register(get_artifacts_and_event_reports)?;
         api.register(get_baseboard)?;
         api.register(post_start_update)?;
+        api.register(post_start_update_all)?;
         api.register(post_abort_update)?;
+        api.register(post_proceed_update)?;
         api.register(post_clear_update_state)?;
         api.register(get_update_sp)?;
+        api.register(get_update_sp_state)?;
         api.register(post_ignition_command)?;
         api.register(post_start_preflight_uplink_check)?;
         api.register(get_preflight_uplink_report)?;
@@ -171,6 +178,7 @@ pub struct CurrentRssUserConfigInsensitive {
     pub external_dns_ips: Vec<IpAddr>,
     pub external_dns_zone_name: String,
     pub rack_network_config: Option<RackNetworkConfig>,
+    pub rack_subnet: Option<Ipv6Addr>,
 }
 
 // This is a summary of the subset of `RackInitializeRequest` that is sensitive;
@@ -551,6 +559,57 @@ async fn get_inventory(
     }
 }
 
+/// Re-read the cabooses of an SP and its RoT from MGS.
+///
+/// Unlike `get_inventory`, this re-reads the cabooses even if the SP's state
+/// hasn't changed, so it can be used to confirm that a new version has landed
+/// immediately after an update. wicketd's cached inventory is updated with
+/// the results.
+#[endpoint {
+    method = GET,
+    path = "/inventory/cabooses/{type}/{slot}",
+}]
+async fn get_sp_cabooses(
+    rqctx: RequestContext<ServerContext>,
+    target: Path<SpIdentifier>,
+) -> Result<HttpResponseOk<SpCabooses>, HttpError> {
+    let target = target.into_inner();
+    let response = rqctx
+        .context()
+        .mgs_handle
+        .get_inventory_refreshing_cabooses(target)
+        .await;
+    let inventory = match response {
+        Ok(GetInventoryResponse::Response { inventory, .. }) => inventory,
+        Ok(GetInventoryResponse::Unavailable) => {
+            return Err(HttpError::for_unavail(
+                None,
+                "Rack inventory not yet available".into(),
+            ));
+        }
+        Err(GetInventoryError::InvalidSpIdentifier) => {
+            return Err(HttpError::for_bad_request(
+                None,
+                format!("Invalid SP identifier {target:?}"),
+            ));
+        }
+        Err(GetInventoryError::ShutdownInProgress) => {
+            return Err(HttpError::for_unavail(
+                None,
+                "Server is shutting down".into(),
+            ));
+        }
+    };
+    let sp =
+        inventory.sps.iter().find(|sp| sp.id == target).ok_or_else(|| {
+            HttpError::for_unavail(
+                None,
+                format!("No inventory for SP {target:?}"),
+            )
+        })?;
+    Ok(HttpResponseOk(SpCabooses::from(sp)))
+}
+
 /// Upload a TUF repository to the server.
 ///
 /// At any given time, wicketd will keep at most one TUF repository in memory.
@@ -614,8 +673,34 @@ pub struct InstallableArtifacts {
     pub installable: Vec<ArtifactHashId>,
 }
 
-/// The response to a `get_artifacts` call: the system version, and the list of
-/// all artifacts currently held by wicketd.
+/// The response to a `get_artifacts` call: the system version, and the IDs of
+/// all artifacts in the most-recently-uploaded TUF repository.
+#[derive(Clone, Debug, JsonSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct GetArtifactsResponse {
+    pub system_version: Option<SemverVersion>,
+    pub artifacts: Vec<ArtifactId>,
+}
+
+/// An endpoint used to report the artifacts in the current TUF repository,
+/// without any event reports.
+///
+/// The order of the returned artifacts is unspecified, and may change between
+/// calls even if the total set of artifacts has not.
+#[endpoint {
+    method = GET,
+    path = "/artifacts",
+}]
+async fn get_artifacts(
+    rqctx: RequestContext<ServerContext>,
+) -> Result<HttpResponseOk<GetArtifactsResponse>, HttpError> {
+    let response = rqctx.context().update_tracker.artifacts().await;
+    Ok(HttpResponseOk(response))
+}
+
+/// The response to a `get_artifacts_and_event_reports` call: the system
+/// version, the list of all artifacts currently held by wicketd, and the event
+/// reports for all updates.
 #[derive(Clone, Debug, JsonSchema, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub struct GetArtifactsAndEventReportsResponse {
@@ -635,21 +720,56 @@ pub struct GetArtifactsAndEventReportsResponse {
     pub artifacts: Vec<InstallableArtifacts>,
 
     pub event_reports: BTreeMap<SpType, BTreeMap<u32, EventReport>>,
+
+    /// SPs whose updates are still running, but haven't reported any events in
+    /// a long time and may be stuck.
+    ///
+    /// This is a heuristic: a listed update might still finish on its own, but
+    /// an operator may want to abort it. Updates that are deliberately waiting
+    /// (for a previous update group, or for confirmation to boot the host) are
+    /// never listed.
+    ///
+    /// Defaults to empty, for responses from a wicketd that predates this
+    /// field.
+    #[serde(default)]
+    pub possibly_stuck: Vec<SpIdentifier>,
+}
+
+#[derive(
+    Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq,
+)]
+pub struct GetArtifactsAndEventReportsParams {
+    /// For each SP, the index of the last step event the caller has already
+    /// seen (the `last_seen` field of a previous event report).
+    ///
+    /// Event reports for SPs listed here only contain step events after that
+    /// index; SPs not listed get full event reports.
+    #[serde(default)]
+    pub last_seen: BTreeMap<SpType, BTreeMap<u32, usize>>,
 }
 
 /// An endpoint used to report all available artifacts and event reports.
 ///
 /// The order of the returned artifacts is unspecified, and may change between
 /// calls even if the total set of artifacts has not.
+///
+/// This is a `POST` because the caller sends the events it has already seen
+/// in the request body, which `GET` requests can't reliably carry.
 #[endpoint {
-    method = GET,
+    method = POST,
     path = "/artifacts-and-event-reports",
 }]
 async fn get_artifacts_and_event_reports(
     rqctx: RequestContext<ServerContext>,
+    body_params: TypedBody<GetArtifactsAndEventReportsParams>,
 ) -> Result<HttpResponseOk<GetArtifactsAndEventReportsResponse>, HttpError> {
-    let response =
-        rqctx.context().update_tracker.artifacts_and_event_reports().await;
+    let GetArtifactsAndEventReportsParams { last_seen } =
+        body_params.into_inner();
+    let response = rqctx
+        .context()
+        .update_tracker
+        .artifacts_and_event_reports(&last_seen)
+        .await;
     Ok(HttpResponseOk(response))
 }
 
@@ -690,6 +810,60 @@ pub(crate) struct StartUpdateOptions {
     /// If true, skip the check on the current SP version and always update it
     /// regardless of whether the update appears to be neeeded.
     pub(crate) skip_sp_version_check: bool,
+
+    /// If true, fail the update if the SP's current version can't be parsed,
+    /// rather than warning and updating it regardless.
+    pub(crate) fail_on_unparseable_sp_version: bool,
+
+    /// If true, pause sled updates just before booting the host, until an
+    /// operator confirms via `post_proceed_update`.
+    pub(crate) pause_before_host_boot: bool,
+
+    /// If true, verify that a sled's host booted from the boot flash slot it
+    /// was updated to, failing the update if it fell back to the other slot.
+    pub(crate) verify_host_boot_slot: bool,
+
+    /// If passed in, fails sled updates if installinator hasn't reported any
+    /// progress within these many seconds of the host starting to boot.
+    ///
+    /// Defaults to waiting indefinitely, since booting the host (including DRAM
+    /// training and fetching the trampoline phase 2 image) can take a long
+    /// time.
+    pub(crate) installinator_start_timeout_secs: Option<u64>,
+
+    /// How often to poll MGS for the status of an SP component update, in
+    /// milliseconds.
+    ///
+    /// Defaults to 300ms if not passed in or zero.
+    pub(crate) mgs_status_poll_interval_ms: Option<u64>,
+
+    /// How often to poll MGS for trampoline phase 2 progress while waiting
+    /// for installinator to start, in milliseconds.
+    ///
+    /// Defaults to 3 seconds if not passed in or zero.
+    pub(crate) mgs_installinator_poll_interval_ms: Option<u64>,
+
+    /// The maximum number of attempts to upload the trampoline phase 2 image
+    /// to MGS before failing sled updates waiting on it.
+    ///
+    /// Defaults to retrying forever if not passed in or zero.
+    pub(crate) trampoline_phase_2_upload_max_attempts: Option<u32>,
+
+    /// If true, only update the host OS of sleds, leaving their RoT and SP
+    /// firmware alone and reporting those components as skipped.
+    ///
+    /// Switches and PSCs have no host, so updating them in this mode skips
+    /// every component.
+    pub(crate) host_only: bool,
+
+    /// Groups of targets to update in order.
+    ///
+    /// Every update in a group must finish (successfully or not) before any
+    /// update in the next group starts. Targets not listed in any group are
+    /// updated together after the last group. If empty, all targets are
+    /// updated at once.
+    #[serde(default)]
+    pub(crate) update_groups: Vec<BTreeSet<SpIdentifier>>,
 }
 
 /// A simulated result for a component update.
@@ -716,6 +890,10 @@ pub(crate) struct AbortUpdateOptions {
     /// The message to abort the update with.
     pub(crate) message: String,
 
+    /// If true, clear the update state once the abort completes, as with
+    /// `post_clear_update_state`.
+    pub(crate) clear_state: bool,
+
     /// If passed in, fails the force cancel update operation with a simulated
     /// error.
     pub(crate) test_error: Option<UpdateTestError>,
@@ -928,30 +1106,12 @@ async fn post_start_update(
 
         // If we have the state of the SP, are we allowed to update it? We
         // refuse to try to update our own sled.
-        match &rqctx.baseboard {
-            Some(baseboard) => {
-                if baseboard.identifier() == sp_state.serial_number
-                    && baseboard.model() == sp_state.model
-                    && baseboard.revision() == i64::from(sp_state.revision)
-                {
-                    self_update = Some(*target);
-                    continue;
-                }
-            }
-            None => {
-                // We don't know our own baseboard, which is a very questionable
-                // state to be in! For now, we will hard-code the possibly
-                // locations where we could be running: scrimlets can only be in
-                // cubbies 14 or 16, so we refuse to update either of those.
-                let target_is_scrimlet = matches!(
-                    (target.type_, target.slot),
-                    (SpType::Sled, 14 | 16)
-                );
-                if target_is_scrimlet {
-                    maybe_self_update.insert(*target);
-                    continue;
-                }
+        match check_self_update(rqctx.baseboard.as_ref(), *target, sp_state) {
+            Some(SelfUpdate::Definite) => self_update = Some(*target),
+            Some(SelfUpdate::Possible) => {
+                maybe_self_update.insert(*target);
             }
+            None => {}
         }
     }
 
@@ -963,19 +1123,7 @@ async fn post_start_update(
             sps_to_string(&inventory_absent)
         ));
     }
-    if let Some(self_update) = self_update {
-        errors.push(format!(
-            "cannot update sled where wicketd is running ({})",
-            SpIdentifierDisplay(self_update)
-        ));
-    }
-    if !maybe_self_update.is_empty() {
-        errors.push(format!(
-            "wicketd does not know its own baseboard details: \
-             refusing to update either scrimlet ({})",
-            sps_to_string(&inventory_absent)
-        ));
-    }
+    errors.extend(self_update_errors(self_update, &maybe_self_update));
 
     if let Some(test_error) = &params.options.test_error {
         errors.push(test_error.into_error_string(log, "starting update").await);
@@ -990,7 +1138,11 @@ async fn post_start_update(
     } else {
         // We've already found errors, so all we want to do is to check whether
         // the update tracker thinks there are any errors as well.
-        match rqctx.update_tracker.update_pre_checks(params.targets).await {
+        match rqctx
+            .update_tracker
+            .update_pre_checks(params.targets, &params.options)
+            .await
+        {
             Ok(()) => Vec::new(),
             Err(errors) => errors,
         }
@@ -1024,6 +1176,156 @@ async fn post_start_update(
     }
 }
 
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct PathSpType {
+    #[serde(rename = "type")]
+    type_: SpType,
+}
+
+/// An endpoint to start updating every present SP of a given type.
+///
+/// Targets are expanded from wicketd's cached inventory, skipping SPs whose
+/// state isn't present. The sled wicketd is running on is never a target (if
+/// wicketd doesn't know its own baseboard, both scrimlets are skipped).
+///
+/// Returns the SPs being updated.
+#[endpoint {
+    method = POST,
+    path = "/update-all/{type}",
+}]
+async fn post_start_update_all(
+    rqctx: RequestContext<ServerContext>,
+    path: Path<PathSpType>,
+    options: TypedBody<StartUpdateOptions>,
+) -> Result<HttpResponseOk<Vec<SpIdentifier>>, HttpError> {
+    let log = &rqctx.log;
+    let rqctx = rqctx.context();
+    let PathSpType { type_ } = path.into_inner();
+    let options = options.into_inner();
+
+    let mut inventory = match rqctx.mgs_handle.get_cached_inventory().await {
+        Ok(GetInventoryResponse::Response { inventory, .. }) => inventory,
+        Ok(GetInventoryResponse::Unavailable) => {
+            return Err(HttpError::for_unavail(
+                None,
+                "Rack inventory not yet available".into(),
+            ));
+        }
+        Err(ShutdownInProgress) => {
+            return Err(HttpError::for_unavail(
+                None,
+                "Server is shutting down".into(),
+            ));
+        }
+    };
+
+    // Never target the sled we're running on, but remember why we skipped it:
+    // if it was the only candidate, that's the reason we can't start.
+    let mut self_update = None;
+    let mut maybe_self_update = BTreeSet::new();
+    inventory.sps.retain(|sp| {
+        let sp_state = match &sp.state {
+            Some(sp_state) if sp.id.type_ == type_ => sp_state,
+            _ => return true,
+        };
+        match check_self_update(rqctx.baseboard.as_ref(), sp.id, sp_state) {
+            Some(SelfUpdate::Definite) => self_update = Some(sp.id),
+            Some(SelfUpdate::Possible) => {
+                maybe_self_update.insert(sp.id);
+            }
+            None => return true,
+        }
+        false
+    });
+
+    if let Some(test_error) = &options.test_error {
+        return Err(HttpError::for_bad_request(
+            None,
+            test_error.into_error_string(log, "starting update").await,
+        ));
+    }
+
+    match rqctx
+        .update_tracker
+        .start_all_of_type(type_, &inventory, options)
+        .await
+    {
+        Ok(sps) => Ok(HttpResponseOk(sps.into_iter().collect())),
+        Err(errors) => {
+            let skipped = self_update_errors(self_update, &maybe_self_update);
+            // If we skipped every present SP as (possibly) ourselves, the
+            // tracker only sees that there are none; report why we skipped
+            // them instead.
+            let errors = match errors.as_slice() {
+                [StartUpdateError::NoPresentTargets(_)]
+                    if !skipped.is_empty() =>
+                {
+                    skipped
+                }
+                _ => errors.iter().map(|error| error.to_string()).collect(),
+            };
+            Err(HttpError::for_bad_request(None, itertools::join(errors, "; ")))
+        }
+    }
+}
+
+/// Why we refuse to update an SP that may be the sled we're running on.
+enum SelfUpdate {
+    /// The SP is the sled wicketd is running on.
+    Definite,
+    /// wicketd doesn't know its own baseboard, and the SP is a scrimlet that
+    /// it could be running on.
+    Possible,
+}
+
+/// Checks whether `target`, whose state is `sp_state`, may be the sled wicketd
+/// is running on.
+fn check_self_update(
+    baseboard: Option<&Baseboard>,
+    target: SpIdentifier,
+    sp_state: &gateway_client::types::SpState,
+) -> Option<SelfUpdate> {
+    match baseboard {
+        Some(baseboard) => {
+            let is_self = baseboard.identifier() == sp_state.serial_number
+                && baseboard.model() == sp_state.model
+                && baseboard.revision() == i64::from(sp_state.revision);
+            is_self.then_some(SelfUpdate::Definite)
+        }
+        None => {
+            // We don't know our own baseboard, which is a very questionable
+            // state to be in! For now, we will hard-code the possibly
+            // locations where we could be running: scrimlets can only be in
+            // cubbies 14 or 16, so we refuse to update either of those.
+            let target_is_scrimlet =
+                matches!((target.type_, target.slot), (SpType::Sled, 14 | 16));
+            target_is_scrimlet.then_some(SelfUpdate::Possible)
+        }
+    }
+}
+
+/// Returns the errors to report for targets refused by [`check_self_update`].
+fn self_update_errors(
+    self_update: Option<SpIdentifier>,
+    maybe_self_update: &BTreeSet<SpIdentifier>,
+) -> Vec<String> {
+    let mut errors = Vec::new();
+    if let Some(self_update) = self_update {
+        errors.push(format!(
+            "cannot update sled where wicketd is running ({})",
+            SpIdentifierDisplay(self_update)
+        ));
+    }
+    if !maybe_self_update.is_empty() {
+        errors.push(format!(
+            "wicketd does not know its own baseboard details: \
+             refusing to update either scrimlet ({})",
+            sps_to_string(maybe_self_update)
+        ));
+    }
+    errors
+}
+
 /// An endpoint to get the status of any update being performed or recently
 /// completed on a single SP.
 #[endpoint {
@@ -1060,12 +1362,33 @@ async fn post_abort_update(
         return Err(test_error.into_http_error(log, "aborting update").await);
     }
 
-    match rqctx
-        .context()
-        .update_tracker
-        .abort_update(target, opts.message)
-        .await
-    {
+    let update_tracker = &rqctx.context().update_tracker;
+    let res = if opts.clear_state {
+        update_tracker.abort_and_clear_update(target, opts.message).await
+    } else {
+        update_tracker.abort_update(target, opts.message).await
+    };
+    match res {
+        Ok(()) => Ok(HttpResponseUpdatedNoContent {}),
+        Err(err) => Err(err.to_http_error()),
+    }
+}
+
+/// Lets an update that's paused before booting the host proceed.
+///
+/// This is only valid for sled updates started with `pause_before_host_boot`
+/// that are waiting for confirmation.
+#[endpoint {
+    method = POST,
+    path = "/proceed-update/{type}/{slot}",
+}]
+async fn post_proceed_update(
+    rqctx: RequestContext<ServerContext>,
+    target: Path<SpIdentifier>,
+) -> Result<HttpResponseUpdatedNoContent, HttpError> {
+    let target = target.into_inner();
+
+    match rqctx.context().update_tracker.proceed_update(target).await {
         Ok(()) => Ok(HttpResponseUpdatedNoContent {}),
         Err(err) => Err(err.to_http_error()),
     }
@@ -1099,6 +1422,43 @@ async fn post_clear_update_state(
     }
 }
 
+/// Gets a summary of the state of any update on a single SP.
+///
+/// This is much cheaper than fetching the full event report with
+/// `get_update_sp`, so it's suitable for frequently polling many SPs.
+#[endpoint {
+    method = GET,
+    path = "/update-state/{type}/{slot}",
+}]
+async fn get_update_sp_state(
+    rqctx: RequestContext<ServerContext>,
+    target: Path<SpIdentifier>,
+) -> Result<HttpResponseOk<SpUpdateStateSummary>, HttpError> {
+    let state =
+        rqctx.context().update_tracker.update_state(target.into_inner()).await;
+    Ok(HttpResponseOk(state))
+}
+
+/// A summary of the state of an SP's update.
+#[derive(Clone, Copy, Debug, JsonSchema, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SpUpdateStateSummary {
+    /// No update has been started for this SP (or its state was cleared).
+    NotStarted,
+
+    /// The update is currently running.
+    Running,
+
+    /// The update finished successfully.
+    Succeeded,
+
+    /// The update failed.
+    Failed,
+
+    /// The update was aborted.
+    Aborted,
+}
+
 #[derive(Serialize, Deserialize, JsonSchema)]
 struct PathSpIgnitionCommand {
     #[serde(rename = "type")]
@@ -50,6 +50,32 @@ pub struct RotInventory {
     pub caboose_b: Option<SpComponentCaboose>,
 }
 
+/// The cabooses of an SP and its RoT, freshly re-read from MGS.
+///
+/// Any caboose that could not be read is `None`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SpCabooses {
+    pub sp_active: Option<SpComponentCaboose>,
+    pub sp_inactive: Option<SpComponentCaboose>,
+    pub rot_a: Option<SpComponentCaboose>,
+    pub rot_b: Option<SpComponentCaboose>,
+}
+
+impl From<&SpInventory> for SpCabooses {
+    fn from(sp: &SpInventory) -> Self {
+        let (rot_a, rot_b) = match &sp.rot {
+            Some(rot) => (rot.caboose_a.clone(), rot.caboose_b.clone()),
+            None => (None, None),
+        };
+        Self {
+            sp_active: sp.caboose_active.clone(),
+            sp_inactive: sp.caboose_inactive.clone(),
+            rot_a,
+            rot_b,
+        }
+    }
+}
+
 /// The current state of the v1 Rack as known to wicketd
 #[derive(Clone, Debug, Serialize, JsonSchema)]
 #[serde(tag = "inventory", rename_all = "snake_case")]
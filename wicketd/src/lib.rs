@@ -13,6 +13,7 @@ mod inventory;
 pub mod mgs;
 mod preflight_check;
 mod rss_config;
+mod update_metrics;
 mod update_tracker;
 
 use anyhow::{anyhow, Result};
@@ -22,7 +23,7 @@ pub use config::Config;
 pub(crate) use context::ServerContext;
 use dropshot::{ConfigDropshot, HandlerTaskMode, HttpServer};
 pub use installinator_progress::{IprUpdateTracker, RunningUpdateState};
-pub use inventory::{RackV1Inventory, SpInventory};
+pub use inventory::{RackV1Inventory, SpCabooses, SpInventory};
 use mgs::make_mgs_client;
 pub(crate) use mgs::{MgsHandle, MgsManager};
 use omicron_common::FileKv;
@@ -34,6 +35,8 @@ use std::{
     net::{SocketAddr, SocketAddrV6},
     sync::Arc,
 };
+pub use update_metrics::serve_update_metrics;
+pub use update_metrics::UpdateMetrics;
 pub use update_tracker::{StartUpdateError, UpdateTracker};
 
 /// Run the OpenAPI generator for the API; which emits the OpenAPI spec
@@ -95,16 +98,17 @@ impl Server {
         let (ipr_artifact, ipr_update_tracker) =
             crate::installinator_progress::new(&log);
 
+        let bootstrap_peers = Arc::new(BootstrapPeers::new(&log));
+
         let store = WicketdArtifactStore::new(&log);
         let update_tracker = Arc::new(UpdateTracker::new(
             args.mgs_address,
             &log,
             store.clone(),
             ipr_update_tracker.clone(),
+            bootstrap_peers.clone(),
         ));
 
-        let bootstrap_peers = BootstrapPeers::new(&log);
-
         let wicketd_server = {
             let ds_log = log.new(o!("component" => "dropshot (wicketd)"));
             let mgs_client = make_mgs_client(log.clone(), args.mgs_address);
@@ -43,6 +43,8 @@ enum MgsRequest {
         reply_tx:
             oneshot::Sender<Result<GetInventoryResponse, GetInventoryError>>,
         force_refresh: Vec<SpIdentifier>,
+        // If set, the SPs in `force_refresh` also re-read their cabooses.
+        refresh_cabooses: bool,
     },
 }
 
@@ -95,11 +97,33 @@ impl MgsHandle {
     pub async fn get_inventory_refreshing_sps(
         &self,
         force_refresh: Vec<SpIdentifier>,
+    ) -> Result<GetInventoryResponse, GetInventoryError> {
+        self.get_inventory_impl(force_refresh, false).await
+    }
+
+    /// Like `get_inventory_refreshing_sps()` for a single SP, but also re-read
+    /// its (and its RoT's) cabooses even if its state hasn't changed.
+    pub async fn get_inventory_refreshing_cabooses(
+        &self,
+        sp: SpIdentifier,
+    ) -> Result<GetInventoryResponse, GetInventoryError> {
+        self.get_inventory_impl(vec![sp], true).await
+    }
+
+    async fn get_inventory_impl(
+        &self,
+        force_refresh: Vec<SpIdentifier>,
+        refresh_cabooses: bool,
     ) -> Result<GetInventoryResponse, GetInventoryError> {
         let (reply_tx, reply_rx) = oneshot::channel();
         let etag = None;
         self.tx
-            .send(MgsRequest::GetInventory { etag, reply_tx, force_refresh })
+            .send(MgsRequest::GetInventory {
+                etag,
+                reply_tx,
+                force_refresh,
+                refresh_cabooses,
+            })
             .await
             .map_err(|_| GetInventoryError::ShutdownInProgress)?;
         reply_rx.await.map_err(|_| GetInventoryError::ShutdownInProgress)?
@@ -244,13 +268,19 @@ impl MgsManager {
 
                 Some(request) = self.rx.recv() => {
                     match request {
-                        MgsRequest::GetInventory { reply_tx, force_refresh, .. } => {
+                        MgsRequest::GetInventory {
+                            reply_tx,
+                            force_refresh,
+                            refresh_cabooses,
+                            ..
+                        } => {
                             self.handle_get_inventory_request(
                                 &ignition_task_handle,
                                 &sp_task_handles,
                                 last_successful_mgs_response,
                                 reply_tx,
                                 force_refresh,
+                                refresh_cabooses,
                             );
                         }
                     }
@@ -305,6 +335,7 @@ impl MgsManager {
             Result<GetInventoryResponse, GetInventoryError>,
         >,
         force_refresh: Vec<SpIdentifier>,
+        refresh_cabooses: bool,
     ) {
         if force_refresh.is_empty() {
             // No force refresh: just return our latest cached inventory.
@@ -319,7 +350,11 @@ impl MgsManager {
                 return;
             };
 
-            handle.fetch_now();
+            if refresh_cabooses {
+                handle.refresh_cabooses_now();
+            } else {
+                handle.fetch_now();
+            }
         }
 
         // Also fetch new data from ignition for any force refresh request; this
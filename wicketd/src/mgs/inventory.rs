@@ -14,6 +14,9 @@ use gateway_messages::SpComponent;
 use slog::warn;
 use slog::Logger;
 use std::collections::BTreeMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
 use tokio::sync::mpsc;
 use tokio::sync::watch;
 use tokio::task;
@@ -181,6 +184,7 @@ pub(super) struct SpStateFetcher {
     task: task::JoinHandle<()>,
     ignition_presence_tx: watch::Sender<Option<IgnitionPresence>>,
     fetch_now_tx: mpsc::Sender<()>,
+    refresh_cabooses: Arc<AtomicBool>,
 }
 
 impl Drop for SpStateFetcher {
@@ -211,17 +215,20 @@ impl SpStateFetcher {
 
         let (ignition_presence_tx, ignition_presence_rx) = watch::channel(None);
 
+        let refresh_cabooses = Arc::new(AtomicBool::new(false));
+
         let task = tokio::spawn(sp_fetching_task(
             id,
             data_tx,
             fetch_now_rx,
             ignition_presence_rx,
+            Arc::clone(&refresh_cabooses),
             mgs_client,
             log,
         ));
 
         (
-            Self { task, ignition_presence_tx, fetch_now_tx },
+            Self { task, ignition_presence_tx, fetch_now_tx, refresh_cabooses },
             ReceiverStream::new(data_rx),
         )
     }
@@ -249,6 +256,13 @@ impl SpStateFetcher {
             }
         }
     }
+
+    /// Like `fetch_now()`, but also re-read all cabooses even if the SP's
+    /// state hasn't changed since they were last fetched.
+    pub(super) fn refresh_cabooses_now(&self) {
+        self.refresh_cabooses.store(true, Ordering::SeqCst);
+        self.fetch_now();
+    }
 }
 
 async fn sp_fetching_task(
@@ -256,6 +270,7 @@ async fn sp_fetching_task(
     tx: mpsc::Sender<FetchedSpData>,
     mut fetch_now: mpsc::Receiver<()>,
     mut ignition_presence: watch::Receiver<Option<IgnitionPresence>>,
+    refresh_cabooses: Arc<AtomicBool>,
     mgs_client: gateway_client::Client,
     log: Logger,
 ) {
@@ -314,6 +329,18 @@ async fn sp_fetching_task(
         };
         let mut mgs_received = Instant::now();
 
+        // A caboose can change without the SP's state changing (e.g., after
+        // writing a new image to an inactive slot), so our caller can ask us
+        // to discard our cached cabooses.
+        if refresh_cabooses.swap(false, Ordering::SeqCst) {
+            caboose_active = None;
+            caboose_inactive = None;
+            if let Some(rot) = rot.as_mut() {
+                rot.caboose_a = None;
+                rot.caboose_b = None;
+            }
+        }
+
         if rot.is_none() || prev_state.as_ref() != Some(&state) {
             match &state.rot {
                 RotState::Enabled { active, .. } => {
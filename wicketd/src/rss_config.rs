@@ -35,9 +35,9 @@ use std::net::IpAddr;
 use std::net::Ipv6Addr;
 use wicket_common::rack_setup::PutRssUserConfigInsensitive;
 
-// TODO-correctness For now, we always use the same rack subnet when running
-// RSS. When we get to multirack, this will be wrong, but there are many other
-// RSS-related things that need to change then too.
+// TODO-correctness Unless the user provides one, we always use the same rack
+// subnet when running RSS. When we get to multirack, this will be wrong, but
+// there are many other RSS-related things that need to change then too.
 const RACK_SUBNET: Ipv6Addr =
     Ipv6Addr::new(0xfd00, 0x1122, 0x3344, 0x0100, 0, 0, 0, 0);
 
@@ -65,6 +65,7 @@ pub(crate) struct CurrentRssConfig {
     external_certificates: Vec<Certificate>,
     recovery_silo_password_hash: Option<omicron_passwords::NewPasswordHash>,
     rack_network_config: Option<RackNetworkConfig>,
+    rack_subnet: Option<Ipv6Addr>,
 
     // External certificates are uploaded in two separate actions (cert then
     // key, or vice versa). Here we store a partial certificate; once we have
@@ -252,7 +253,7 @@ impl CurrentRssConfig {
             .collect();
 
         let request = RackInitializeRequest {
-            rack_subnet: RACK_SUBNET,
+            rack_subnet: self.rack_subnet.unwrap_or(RACK_SUBNET),
             trust_quorum_peers,
             bootstrap_discovery: BootstrapAddressDiscovery::OnlyThese(
                 bootstrap_ips,
@@ -354,15 +355,22 @@ impl CurrentRssConfig {
         value: PutRssUserConfigInsensitive,
         our_baseboard: Option<&Baseboard>,
     ) -> Result<(), String> {
-        // Updating can only fail in two ways:
+        // Updating can only fail in three ways:
         //
         // 1. If we have a real gimlet baseboard, that baseboard must be present
         //    in our inventory and in `value`'s list of sleds: we cannot exclude
         //    ourself from the rack.
         // 2. `value`'s bootstrap sleds includes sleds that aren't in our
         //    `inventory`.
+        // 3. `value`'s external DNS IPs aren't all within its internal services
+        //    IP pool, which RSS would reject later.
 
-        // First, confirm we have ourself in the inventory _and_ the user didn't
+        // First, confirm the external DNS IPs can be allocated from the
+        // internal services IP pool.
+        value.validate_external_dns_ips()?;
+        value.validate_rack_subnet()?;
+
+        // Next, confirm we have ourself in the inventory _and_ the user didn't
         // remove us from the list.
         if let Some(our_baseboard @ Baseboard::Gimlet { .. }) = our_baseboard {
             let our_slot = self
@@ -390,7 +398,7 @@ impl CurrentRssConfig {
             }
         }
 
-        // Next, confirm the user's list only consists of sleds in our
+        // Finally, confirm the user's list only consists of sleds in our
         // inventory.
         let mut bootstrap_sleds = BTreeSet::new();
         for slot in value.bootstrap_sleds {
@@ -414,6 +422,7 @@ impl CurrentRssConfig {
         self.external_dns_ips = value.external_dns_ips;
         self.external_dns_zone_name = value.external_dns_zone_name;
         self.rack_network_config = Some(value.rack_network_config);
+        self.rack_subnet = value.rack_subnet;
 
         Ok(())
     }
@@ -446,6 +455,7 @@ impl From<&'_ CurrentRssConfig> for CurrentRssUserConfig {
                 external_dns_ips: rss.external_dns_ips.clone(),
                 external_dns_zone_name: rss.external_dns_zone_name.clone(),
                 rack_network_config: rss.rack_network_config.clone(),
+                rack_subnet: rss.rack_subnet,
             },
         }
     }
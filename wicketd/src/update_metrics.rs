@@ -0,0 +1,397 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Metrics about updates driven by wicketd, reported via oximeter.
+
+use chrono::DateTime;
+use chrono::Utc;
+use dropshot::ConfigDropshot;
+use gateway_client::types::SpIdentifier;
+use gateway_client::types::SpType;
+use internal_dns::resolver::Resolver;
+use internal_dns::ServiceName;
+use omicron_common::api::internal::nexus::ProducerEndpoint;
+use omicron_common::backoff::retry_notify;
+use omicron_common::backoff::retry_policy_internal_service;
+use omicron_common::backoff::BackoffError;
+use oximeter::histogram::Histogram;
+use oximeter::types::Cumulative;
+use oximeter::types::ProducerRegistry;
+use oximeter::Datum;
+use oximeter::DatumType;
+use oximeter::FieldType;
+use oximeter::FieldValue;
+use oximeter::Measurement;
+use oximeter::Metric;
+use oximeter::MetricsError;
+use oximeter::Producer;
+use oximeter::Sample;
+use oximeter::Target;
+use oximeter_producer::LogConfig;
+use oximeter_producer::Server as ProducerServer;
+use slog::error;
+use slog::info;
+use slog::warn;
+use slog::Logger;
+use std::collections::BTreeMap;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+use wicket_common::update_events::StepEvent;
+use wicket_common::update_events::StepEventKind;
+use wicket_common::update_events::StepInfoWithMetadata;
+use wicket_common::update_events::UpdateComponent;
+use wicket_common::update_events::UpdateStepId;
+
+/// An [`oximeter::Target`] for updates of a single SP and the components
+/// behind it.
+#[derive(Debug, Clone, Target)]
+struct WicketdUpdate {
+    sp_type: String,
+    sp_slot: u32,
+}
+
+/// An [`oximeter::Metric`] about a single step of an update.
+///
+/// Every step metric has the same fields, and differs only in its name and
+/// datum:
+///
+/// - `step_started`: the number of times a step was started.
+/// - `step_completed`: the number of times a step completed successfully
+///   (including completing with a warning or being skipped).
+/// - `step_failed`: the number of times a step failed, ending the update.
+/// - `step_retried`: the number of times a step was retried.
+/// - `step_duration`: a histogram of how long successful steps took to
+///   complete, in seconds.
+#[derive(Debug, Clone)]
+struct StepMetric<D> {
+    name: &'static str,
+    component: String,
+    step_id: String,
+    datum: D,
+}
+
+impl<D> Metric for StepMetric<D>
+where
+    D: oximeter::traits::Datum,
+    for<'a> Datum: From<&'a D>,
+{
+    type Datum = D;
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn field_names(&self) -> &'static [&'static str] {
+        &["component", "step_id"]
+    }
+
+    fn field_types(&self) -> Vec<FieldType> {
+        vec![FieldType::String, FieldType::String]
+    }
+
+    fn field_values(&self) -> Vec<FieldValue> {
+        vec![
+            FieldValue::String(self.component.clone()),
+            FieldValue::String(self.step_id.clone()),
+        ]
+    }
+
+    fn datum_type(&self) -> DatumType {
+        self.datum.datum_type()
+    }
+
+    fn datum(&self) -> &D {
+        &self.datum
+    }
+
+    fn datum_mut(&mut self) -> &mut D {
+        &mut self.datum
+    }
+
+    fn measure(&self, timestamp: DateTime<Utc>) -> Measurement {
+        Measurement::new(timestamp, Datum::from(&self.datum))
+    }
+
+    fn start_time(&self) -> Option<DateTime<Utc>> {
+        self.datum.start_time()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+struct StepKey {
+    sp: SpIdentifier,
+    component: &'static str,
+    step_id: &'static str,
+}
+
+#[derive(Debug)]
+struct StepCounters {
+    started: Cumulative<i64>,
+    completed: Cumulative<i64>,
+    failed: Cumulative<i64>,
+    retried: Cumulative<i64>,
+    duration: Histogram<f64>,
+}
+
+impl StepCounters {
+    fn new() -> Self {
+        Self {
+            started: Cumulative::new(0),
+            completed: Cumulative::new(0),
+            failed: Cumulative::new(0),
+            retried: Cumulative::new(0),
+            // Steps range from well under a second (interrogating an SP) to
+            // tens of minutes (running installinator), so cover [0.01s,
+            // 10000s).
+            duration: Histogram::span_decades(-2, 4)
+                .expect("valid histogram bins"),
+        }
+    }
+
+    fn record_completed(&mut self, step_elapsed: Duration) {
+        self.completed.increment();
+        // Durations are always non-negative and finite, so this can't fail.
+        _ = self.duration.sample(step_elapsed.as_secs_f64());
+    }
+}
+
+/// An [`oximeter::Producer`] tracking the steps run by wicketd's update
+/// drivers.
+///
+/// Update drivers feed step events into [`Self::record_event`] as they
+/// arrive; each call to [`Producer::produce`] reports the current counts for
+/// every (SP, component, step) seen so far.
+#[derive(Clone, Debug, Default)]
+pub struct UpdateMetrics {
+    steps: Arc<Mutex<BTreeMap<StepKey, StepCounters>>>,
+}
+
+impl UpdateMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the step transition described by `event`, for an update of
+    /// `sp`.
+    ///
+    /// Only top-level steps are recorded; events from nested engines are
+    /// ignored.
+    pub(crate) fn record_event(&self, sp: SpIdentifier, event: &StepEvent) {
+        let mut steps = self.steps.lock().unwrap();
+        let steps = &mut *steps;
+
+        match &event.kind {
+            StepEventKind::ExecutionStarted { first_step, .. } => {
+                counters_for(steps, sp, first_step).started.increment();
+            }
+            StepEventKind::AttemptRetry { step, .. } => {
+                counters_for(steps, sp, step).retried.increment();
+            }
+            StepEventKind::StepCompleted {
+                step,
+                next_step,
+                step_elapsed,
+                ..
+            } => {
+                counters_for(steps, sp, step).record_completed(*step_elapsed);
+                counters_for(steps, sp, next_step).started.increment();
+            }
+            StepEventKind::ExecutionCompleted {
+                last_step,
+                step_elapsed,
+                ..
+            } => {
+                counters_for(steps, sp, last_step)
+                    .record_completed(*step_elapsed);
+            }
+            StepEventKind::ExecutionFailed { failed_step, .. } => {
+                counters_for(steps, sp, failed_step).failed.increment();
+            }
+            StepEventKind::NoStepsDefined
+            | StepEventKind::ProgressReset { .. }
+            | StepEventKind::ExecutionAborted { .. }
+            | StepEventKind::Nested { .. }
+            | StepEventKind::Unknown => {}
+        }
+    }
+}
+
+fn counters_for<'a>(
+    steps: &'a mut BTreeMap<StepKey, StepCounters>,
+    sp: SpIdentifier,
+    step: &StepInfoWithMetadata,
+) -> &'a mut StepCounters {
+    let key = StepKey {
+        sp,
+        component: component_name(&step.info.component),
+        step_id: step_id_name(&step.info.id),
+    };
+    steps.entry(key).or_insert_with(StepCounters::new)
+}
+
+impl Producer for UpdateMetrics {
+    fn produce(
+        &mut self,
+    ) -> Result<Box<dyn Iterator<Item = Sample> + 'static>, MetricsError> {
+        let steps = self.steps.lock().unwrap();
+        let mut samples = Vec::with_capacity(steps.len() * 5);
+        for (key, counters) in steps.iter() {
+            let target = WicketdUpdate {
+                sp_type: sp_type_name(key.sp.type_).to_string(),
+                sp_slot: key.sp.slot,
+            };
+            let metric = |name, datum| StepMetric {
+                name,
+                component: key.component.to_string(),
+                step_id: key.step_id.to_string(),
+                datum,
+            };
+            for (name, count) in [
+                ("step_started", counters.started),
+                ("step_completed", counters.completed),
+                ("step_failed", counters.failed),
+                ("step_retried", counters.retried),
+            ] {
+                samples.push(Sample::new(&target, &metric(name, count))?);
+            }
+            samples.push(Sample::new(
+                &target,
+                &StepMetric {
+                    name: "step_duration",
+                    component: key.component.to_string(),
+                    step_id: key.step_id.to_string(),
+                    datum: counters.duration.clone(),
+                },
+            )?);
+        }
+        Ok(Box::new(samples.into_iter()))
+    }
+}
+
+// How often oximeter collects update metrics.
+const COLLECTION_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Serves `metrics` to oximeter from any port of the underlay `address`, for
+/// as long as wicketd runs.
+///
+/// The server is registered with Nexus as producer `id`. Nexus is found
+/// through the internal DNS servers on the underlay, retrying until it's
+/// reachable, since the underlay is often only just up.
+pub async fn serve_update_metrics(
+    log: Logger,
+    metrics: UpdateMetrics,
+    id: Uuid,
+    address: Ipv6Addr,
+) {
+    let log = log.new(slog::o!("component" => "UpdateMetricsProducer"));
+    let registry = ProducerRegistry::with_id(id);
+    if let Err(err) = registry.register_producer(metrics) {
+        error!(log, "failed to register update metrics"; "error" => %err);
+        return;
+    }
+    let server_address = SocketAddr::new(address.into(), 0);
+    let start_server = || async {
+        let resolver = Resolver::new_from_ip(log.clone(), address)
+            .map_err(|err| BackoffError::transient(err.to_string()))?;
+        let nexus_address = resolver
+            .lookup_socket_v6(ServiceName::Nexus)
+            .await
+            .map_err(|err| BackoffError::transient(err.to_string()))?;
+        let config = oximeter_producer::Config {
+            server_info: ProducerEndpoint {
+                id,
+                address: server_address,
+                base_route: String::from("/collect"),
+                interval: COLLECTION_INTERVAL,
+            },
+            registration_address: nexus_address.into(),
+            dropshot: ConfigDropshot {
+                bind_address: server_address,
+                ..Default::default()
+            },
+            log: LogConfig::Logger(log.clone()),
+        };
+        ProducerServer::with_registry(registry.clone(), &config)
+            .await
+            .map_err(|err| BackoffError::transient(err.to_string()))
+    };
+    let log_failure = |err, delay| {
+        warn!(
+            log,
+            "failed to start update metrics producer, will retry in {:?}",
+            delay;
+            "error" => err,
+        );
+    };
+    let server = retry_notify(
+        retry_policy_internal_service(),
+        start_server,
+        log_failure,
+    )
+    .await
+    .expect("Expected an infinite retry loop starting the server");
+    info!(
+        log,
+        "started update metrics producer";
+        "address" => %server.address(),
+    );
+    if let Err(err) = server.serve_forever().await {
+        error!(log, "update metrics producer failed"; "error" => %err);
+    }
+}
+
+fn sp_type_name(sp_type: SpType) -> &'static str {
+    match sp_type {
+        SpType::Sled => "sled",
+        SpType::Switch => "switch",
+        SpType::Power => "power",
+    }
+}
+
+fn component_name(component: &UpdateComponent) -> &'static str {
+    match component {
+        UpdateComponent::Rot => "rot",
+        UpdateComponent::Sp => "sp",
+        UpdateComponent::Host => "host",
+    }
+}
+
+// These names match the serialized `id` of each step.
+fn step_id_name(step_id: &UpdateStepId) -> &'static str {
+    match step_id {
+        UpdateStepId::TestStep => "test_step",
+        UpdateStepId::WaitingForPreviousUpdateGroup => {
+            "waiting_for_previous_update_group"
+        }
+        UpdateStepId::SetHostPowerState { .. } => "set_host_power_state",
+        UpdateStepId::InterrogateRot => "interrogate_rot",
+        UpdateStepId::InterrogateSp => "interrogate_sp",
+        UpdateStepId::CheckingComponentsUpToDate => {
+            "checking_components_up_to_date"
+        }
+        UpdateStepId::SpComponentUpdate => "sp_component_update",
+        UpdateStepId::SettingInstallinatorImageId => {
+            "setting_installinator_image_id"
+        }
+        UpdateStepId::ClearingInstallinatorImageId => {
+            "clearing_installinator_image_id"
+        }
+        UpdateStepId::SettingHostStartupOptions => {
+            "setting_host_startup_options"
+        }
+        UpdateStepId::WaitingForTrampolinePhase2Upload => {
+            "waiting_for_trampoline_phase2_upload"
+        }
+        UpdateStepId::DownloadingInstallinator => "downloading_installinator",
+        UpdateStepId::RunningInstallinator => "running_installinator",
+        UpdateStepId::WaitingForOperatorConfirmation => {
+            "waiting_for_operator_confirmation"
+        }
+        UpdateStepId::VerifyingHostBootSlot => "verifying_host_boot_slot",
+    }
+}
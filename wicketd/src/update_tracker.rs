@@ -5,15 +5,23 @@
 // Copyright 2023 Oxide Computer Company
 
 use crate::artifacts::ArtifactIdData;
+use crate::artifacts::Board;
 use crate::artifacts::UpdatePlan;
 use crate::artifacts::WicketdArtifactStore;
+use crate::bootstrap_addrs::BootstrapPeers;
+use crate::context::BOOTSTRAP_AGENT_HTTP_PORT;
+use crate::helpers::is_valid_sp_identifier;
 use crate::helpers::sps_to_string;
 use crate::http_entrypoints::GetArtifactsAndEventReportsResponse;
+use crate::http_entrypoints::GetArtifactsResponse;
+use crate::http_entrypoints::SpUpdateStateSummary;
 use crate::http_entrypoints::StartUpdateOptions;
 use crate::http_entrypoints::UpdateSimulatedResult;
 use crate::installinator_progress::IprStartReceiver;
 use crate::installinator_progress::IprUpdateTracker;
 use crate::mgs::make_mgs_client;
+use crate::update_metrics::UpdateMetrics;
+use crate::RackV1Inventory;
 use anyhow::anyhow;
 use anyhow::bail;
 use anyhow::ensure;
@@ -37,6 +45,7 @@ use installinator_common::WriteOutput;
 use omicron_common::api::external::SemverVersion;
 use omicron_common::backoff;
 use omicron_common::update::ArtifactHash;
+use sled_hardware::Baseboard;
 use slog::error;
 use slog::info;
 use slog::o;
@@ -45,10 +54,15 @@ use slog::Logger;
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::future::Future;
 use std::io;
 use std::net::SocketAddrV6;
+use std::num::NonZeroU32;
 use std::sync::Arc;
 use std::sync::Mutex as StdMutex;
+use std::sync::Weak;
 use std::time::Duration;
 use std::time::Instant;
 use thiserror::Error;
@@ -59,9 +73,12 @@ use tokio::sync::Mutex;
 use tokio::task::JoinHandle;
 use update_engine::events::ProgressUnits;
 use update_engine::AbortHandle;
+use update_engine::AbortWaiter;
+use update_engine::ExecutionStatus;
 use update_engine::StepSpec;
 use uuid::Uuid;
 use wicket_common::update_events::ComponentRegistrar;
+use wicket_common::update_events::Event;
 use wicket_common::update_events::EventBuffer;
 use wicket_common::update_events::EventReport;
 use wicket_common::update_events::SharedStepHandle;
@@ -69,6 +86,7 @@ use wicket_common::update_events::SpComponentUpdateSpec;
 use wicket_common::update_events::SpComponentUpdateStage;
 use wicket_common::update_events::SpComponentUpdateStepId;
 use wicket_common::update_events::SpComponentUpdateTerminalError;
+use wicket_common::update_events::SpComponentWriteProgress;
 use wicket_common::update_events::StepContext;
 use wicket_common::update_events::StepHandle;
 use wicket_common::update_events::StepProgress;
@@ -79,6 +97,7 @@ use wicket_common::update_events::StepWarning;
 use wicket_common::update_events::TestStepComponent;
 use wicket_common::update_events::TestStepId;
 use wicket_common::update_events::TestStepSpec;
+use wicket_common::update_events::TrampolinePhase2Progress;
 use wicket_common::update_events::UpdateComponent;
 use wicket_common::update_events::UpdateEngine;
 use wicket_common::update_events::UpdateStepId;
@@ -92,28 +111,223 @@ struct SpUpdateData {
     // hold it only log enough to update its state or push a new update event
     // into its running log; occasionally we hold it long enough to clone it.
     event_buffer: Arc<StdMutex<EventBuffer>>,
+    // When the event buffer last received an event.
+    activity: UpdateActivity,
+    // Closed once the previous update group has finished; `None` for the first
+    // group.
+    previous_group: Option<watch::Receiver<()>>,
+    // Present if this update pauses before booting the host.
+    host_boot_checkpoint: Option<HostBootCheckpoint>,
+}
+
+impl SpUpdateData {
+    /// Generates an event report containing only step events after
+    /// `last_seen`, or all step events if `last_seen` is `None`.
+    ///
+    /// The report's `last_seen` is the high-water mark the caller should pass
+    /// in next time.
+    fn event_report_since(&self, last_seen: Option<usize>) -> EventReport {
+        self.event_buffer.lock().unwrap().generate_report_since(last_seen)
+    }
+
+    /// Summarizes the state of this update, without generating a full event
+    /// report.
+    fn state_summary(&self) -> SpUpdateStateSummary {
+        let task_finished = self.task.is_finished();
+        let event_buffer = self.event_buffer.lock().unwrap();
+        let execution_status =
+            event_buffer.root_execution_id().and_then(|execution_id| {
+                event_buffer
+                    .steps()
+                    .summarize()
+                    .get(&execution_id)
+                    .map(|summary| summary.execution_status)
+            });
+        update_state_summary(task_finished, execution_status)
+    }
+
+    /// Returns true if this update is still running, but hasn't produced any
+    /// events in at least `threshold`.
+    ///
+    /// Updates that are deliberately waiting (for the previous update group to
+    /// finish, or for an operator to confirm booting the host) are never
+    /// considered stuck.
+    fn is_possibly_stuck(&self, threshold: Duration) -> bool {
+        if self.task.is_finished() {
+            return false;
+        }
+
+        // `has_changed` only fails once the previous group has finished.
+        let waiting_for_previous_group =
+            self.previous_group.as_ref().map_or(false, |previous_group| {
+                previous_group.has_changed().is_ok()
+            });
+        let waiting_for_operator = self
+            .host_boot_checkpoint
+            .as_ref()
+            .map_or(false, |checkpoint| checkpoint.is_waiting());
+        if waiting_for_previous_group || waiting_for_operator {
+            return false;
+        }
+
+        self.activity.idle_time() >= threshold
+    }
+}
+
+/// Summarizes the state of an update from whether its task has finished and
+/// the status of its root execution, if known.
+fn update_state_summary(
+    task_finished: bool,
+    execution_status: Option<ExecutionStatus>,
+) -> SpUpdateStateSummary {
+    // The task outlives the engine (it records the engine's final events), so
+    // the update is running until the task finishes.
+    if !task_finished {
+        return SpUpdateStateSummary::Running;
+    }
+
+    match execution_status {
+        Some(ExecutionStatus::Completed { .. }) => {
+            SpUpdateStateSummary::Succeeded
+        }
+        Some(ExecutionStatus::Aborted { .. }) => SpUpdateStateSummary::Aborted,
+        // If the task finished without the engine reaching a terminal state
+        // (e.g., the task panicked), the update can't have succeeded.
+        Some(
+            ExecutionStatus::Failed { .. }
+            | ExecutionStatus::NotStarted
+            | ExecutionStatus::Running { .. },
+        )
+        | None => SpUpdateStateSummary::Failed,
+    }
+}
+
+/// Tracks when an update last produced an event.
+#[derive(Clone, Debug)]
+struct UpdateActivity {
+    last_event: Arc<StdMutex<tokio::time::Instant>>,
+}
+
+impl UpdateActivity {
+    fn new() -> Self {
+        Self {
+            last_event: Arc::new(StdMutex::new(tokio::time::Instant::now())),
+        }
+    }
+
+    /// Records that the update just produced an event.
+    fn record_event(&self) {
+        *self.last_event.lock().unwrap() = tokio::time::Instant::now();
+    }
+
+    /// Returns how long it's been since the update last produced an event (or
+    /// since it started, if it hasn't produced any).
+    fn idle_time(&self) -> Duration {
+        self.last_event.lock().unwrap().elapsed()
+    }
 }
 
 #[derive(Debug)]
 struct UploadTrampolinePhase2ToMgsStatus {
     hash: ArtifactHash,
-    // The upload task retries forever until it succeeds, so we don't need to
-    // keep a "tried but failed" variant here; we just need to know the ID of
-    // the uploaded image once it's done.
-    uploaded_image_id: Option<HostPhase2RecoveryImageId>,
+    state: UploadTrampolinePhase2ToMgsState,
+}
+
+#[derive(Debug)]
+enum UploadTrampolinePhase2ToMgsState {
+    InProgress,
+    Uploaded(HostPhase2RecoveryImageId),
+    // The upload ran out of attempts. This can only happen if a maximum number
+    // of attempts was configured; by default the upload task retries forever.
+    Failed,
 }
 
 #[derive(Debug)]
 struct UploadTrampolinePhase2ToMgs {
-    // The tuple is the ID of the Trampoline image and a boolean for whether or
-    // not it is complete. The upload task retries forever until it succeeds, so
-    // we don't need to keep a "tried but failed" variant here.
+    // The hash of the trampoline image and the state of its upload.
     status: watch::Receiver<UploadTrampolinePhase2ToMgsStatus>,
     task: JoinHandle<()>,
+    // Shared by every update that may need the image. Once the last of them
+    // goes away, the upload is cancelled if it's still in progress.
+    interest: Weak<UploadTrampolinePhase2ToMgsInterest>,
+}
+
+impl UploadTrampolinePhase2ToMgs {
+    /// Returns true if every update interested in this upload went away
+    /// before it finished, which cancels the upload task.
+    fn was_cancelled(&self) -> bool {
+        self.interest.strong_count() == 0
+            && matches!(
+                self.status.borrow().state,
+                UploadTrampolinePhase2ToMgsState::InProgress
+            )
+    }
+
+    /// Returns a handle that keeps this upload running for as long as it (or
+    /// any clone of it) is alive.
+    fn register_interest(
+        &mut self,
+        log: &Logger,
+    ) -> Arc<UploadTrampolinePhase2ToMgsInterest> {
+        if let Some(interest) = self.interest.upgrade() {
+            return interest;
+        }
+        let interest = Arc::new(UploadTrampolinePhase2ToMgsInterest {
+            status: self.status.clone(),
+            upload_task: self.task.abort_handle(),
+            log: log.clone(),
+        });
+        self.interest = Arc::downgrade(&interest);
+        interest
+    }
+}
+
+/// An update's interest in the trampoline phase 2 upload to MGS.
+///
+/// The upload task retries forever by default, so we don't want it to outlive
+/// the updates that need it: dropping the last reference to this cancels the
+/// upload if it hasn't finished yet.
+#[derive(Debug)]
+struct UploadTrampolinePhase2ToMgsInterest {
+    status: watch::Receiver<UploadTrampolinePhase2ToMgsStatus>,
+    upload_task: tokio::task::AbortHandle,
+    log: Logger,
+}
+
+impl Drop for UploadTrampolinePhase2ToMgsInterest {
+    fn drop(&mut self) {
+        if matches!(
+            self.status.borrow().state,
+            UploadTrampolinePhase2ToMgsState::InProgress
+        ) {
+            info!(
+                self.log,
+                "no updates need the trampoline phase 2 upload to MGS; \
+                 cancelling it",
+            );
+            self.upload_task.abort();
+        }
+    }
 }
 
+/// The SP firmware slot we interrogate and update.
+///
+/// The SP only has one updateable firmware slot ("the inactive bank"). We want
+/// to ask about slot 0 (the active slot)'s current version, and we are supposed
+/// to always pass 0 when updating.
+const SP_FIRMWARE_SLOT: u16 = 0;
+
+/// How long a running update can go without producing any events before we
+/// report it as possibly stuck.
+///
+/// The longest legitimate quiet period is while the SP fetches the trampoline
+/// phase 2 image from the other scrimlet's MGS (where we see no progress) at
+/// about 167 KiB/sec, so this is generous.
+const POSSIBLY_STUCK_THRESHOLD: Duration = Duration::from_secs(30 * 60);
+
 #[derive(Debug)]
 pub struct UpdateTracker {
+    mgs_addr: SocketAddrV6,
     mgs_client: gateway_client::Client,
     sp_update_data: Mutex<UpdateTrackerData>,
 
@@ -125,12 +339,22 @@ pub struct UpdateTracker {
     // status of individual SP updates: we'll start this upload the first time a
     // sled update starts that uses it, and any update (including that one or
     // any future sled updates) will pause at the appropriate time (if needed)
-    // to wait for the upload to complete.
+    // to wait for the upload to complete. If every update that might need the
+    // upload goes away before it completes, we cancel it.
     upload_trampoline_phase_2_to_mgs:
         Mutex<Option<UploadTrampolinePhase2ToMgs>>,
 
+    // A handle to the same artifact store as in `sp_update_data`, which update
+    // drivers use to check that the repository hasn't changed under them.
+    artifact_store: WicketdArtifactStore,
+
+    // Used to find each sled's bootstrap agent, which reports the slot its
+    // host booted from.
+    bootstrap_peers: Arc<BootstrapPeers>,
+
     log: Logger,
     ipr_update_tracker: IprUpdateTracker,
+    metrics: UpdateMetrics,
 }
 
 impl UpdateTracker {
@@ -139,40 +363,80 @@ impl UpdateTracker {
         log: &Logger,
         artifact_store: WicketdArtifactStore,
         ipr_update_tracker: IprUpdateTracker,
+        bootstrap_peers: Arc<BootstrapPeers>,
     ) -> Self {
         let log = log.new(o!("component" => "wicketd update planner"));
-        let sp_update_data = Mutex::new(UpdateTrackerData::new(artifact_store));
+        let sp_update_data =
+            Mutex::new(UpdateTrackerData::new(artifact_store.clone()));
         let mgs_client = make_mgs_client(log.clone(), mgs_addr);
         let upload_trampoline_phase_2_to_mgs = Mutex::default();
 
         Self {
+            mgs_addr,
             mgs_client,
             sp_update_data,
             log,
             upload_trampoline_phase_2_to_mgs,
+            artifact_store,
+            bootstrap_peers,
             ipr_update_tracker,
+            metrics: UpdateMetrics::new(),
         }
     }
 
+    /// Returns the oximeter producer tracking the steps of updates started by
+    /// this tracker.
+    pub fn metrics(&self) -> &UpdateMetrics {
+        &self.metrics
+    }
+
     pub(crate) async fn start(
         &self,
         sps: BTreeSet<SpIdentifier>,
         opts: StartUpdateOptions,
     ) -> Result<(), Vec<StartUpdateError>> {
+        let update_groups = opts.update_groups.clone();
+        let host_only = opts.host_only;
         let imp = RealSpawnUpdateDriver { update_tracker: self, opts };
-        self.start_impl(sps, Some(imp)).await
+        self.start_impl(sps, &update_groups, host_only, Some(imp)).await
+    }
+
+    /// Starts updating every SP of type `sp_type` that's present in
+    /// `inventory`, returning the SPs being updated.
+    ///
+    /// Expanding the targets here, rather than having callers enumerate them,
+    /// avoids racing against inventory changes.
+    pub(crate) async fn start_all_of_type(
+        &self,
+        sp_type: SpType,
+        inventory: &RackV1Inventory,
+        opts: StartUpdateOptions,
+    ) -> Result<BTreeSet<SpIdentifier>, Vec<StartUpdateError>> {
+        let sps = present_sps_of_type(inventory, sp_type)
+            .map_err(|error| vec![error])?;
+        self.start(sps.clone(), opts).await?;
+        Ok(sps)
     }
 
     /// Starts a fake update that doesn't perform any steps, but simply waits
     /// for a watch receiver to resolve.
+    ///
+    /// `update_groups` sequences the fake updates the same way as the
+    /// `update_groups` option of a real update.
     #[doc(hidden)]
     pub async fn start_fake_update(
         &self,
         sps: BTreeSet<SpIdentifier>,
+        update_groups: Vec<BTreeSet<SpIdentifier>>,
         watch_receiver: watch::Receiver<()>,
     ) -> Result<(), Vec<StartUpdateError>> {
-        let imp = FakeUpdateDriver { watch_receiver, log: self.log.clone() };
-        self.start_impl(sps, Some(imp)).await
+        let imp = FakeUpdateDriver {
+            watch_receiver,
+            artifact_store: self.artifact_store.clone(),
+            metrics: self.metrics.clone(),
+            log: self.log.clone(),
+        };
+        self.start_impl(sps, &update_groups, false, Some(imp)).await
     }
 
     pub(crate) async fn clear_update_state(
@@ -192,6 +456,43 @@ impl UpdateTracker {
         update_data.abort_update(sp, message).await
     }
 
+    /// Aborts a running update, then clears its state once the abort
+    /// completes.
+    ///
+    /// Unlike calling [`Self::abort_update`] followed by
+    /// [`Self::clear_update_state`], the abort is requested and the state
+    /// removed under a single acquisition of the lock, so nobody can observe
+    /// the aborted update's state in between.
+    pub(crate) async fn abort_and_clear_update(
+        &self,
+        sp: SpIdentifier,
+        message: String,
+    ) -> Result<(), AbortUpdateError> {
+        let (waiter, task) = {
+            let mut update_data = self.sp_update_data.lock().await;
+            update_data.abort_and_remove_update(sp, message)?
+        };
+
+        // Wait for the abort to be processed, and for the task to finish
+        // wrapping up (e.g., recording the final events), so that the SP is
+        // truly idle once we return. This is done without holding the lock so
+        // that other tracker operations aren't blocked in the meantime. The
+        // task's result doesn't matter: even if it panicked, the state is
+        // cleared.
+        waiter.await;
+        _ = task.await;
+        Ok(())
+    }
+
+    /// Lets an update that's paused before booting the host proceed.
+    pub(crate) async fn proceed_update(
+        &self,
+        sp: SpIdentifier,
+    ) -> Result<(), ProceedUpdateError> {
+        let update_data = self.sp_update_data.lock().await;
+        update_data.proceed_update(sp)
+    }
+
     /// Checks whether an update can be started for the given SPs, without
     /// actually starting it.
     ///
@@ -203,13 +504,22 @@ impl UpdateTracker {
     pub(crate) async fn update_pre_checks(
         &self,
         sps: BTreeSet<SpIdentifier>,
+        opts: &StartUpdateOptions,
     ) -> Result<(), Vec<StartUpdateError>> {
-        self.start_impl::<NeverUpdateDriver>(sps, None).await
+        self.start_impl::<NeverUpdateDriver>(
+            sps,
+            &opts.update_groups,
+            opts.host_only,
+            None,
+        )
+        .await
     }
 
     async fn start_impl<Spawn>(
         &self,
         sps: BTreeSet<SpIdentifier>,
+        update_groups: &[BTreeSet<SpIdentifier>],
+        host_only: bool,
         spawn_update_driver: Option<Spawn>,
     ) -> Result<(), Vec<StartUpdateError>>
     where
@@ -219,6 +529,18 @@ impl UpdateTracker {
 
         let mut errors = Vec::new();
 
+        // Check that all of these SPs exist, rather than relying on MGS to
+        // reject them partway through an update.
+        let invalid_targets: Vec<_> = sps
+            .iter()
+            .filter(|sp| !is_valid_sp_identifier(sp))
+            .copied()
+            .collect();
+
+        if !invalid_targets.is_empty() {
+            errors.push(StartUpdateError::InvalidTarget(invalid_targets));
+        }
+
         // Check that we're not already updating any of these SPs.
         let update_in_progress: Vec<_> = sps
             .iter()
@@ -239,6 +561,44 @@ impl UpdateTracker {
             errors.push(StartUpdateError::UpdateInProgress(update_in_progress));
         }
 
+        // Only sleds have a host, so a host-only update of anything else would
+        // skip every step.
+        if host_only {
+            let not_sleds: Vec<_> = sps
+                .iter()
+                .filter(|sp| sp.type_ != SpType::Sled)
+                .copied()
+                .collect();
+            if !not_sleds.is_empty() {
+                errors.push(StartUpdateError::HostOnlyNotSled(not_sleds));
+            }
+        }
+
+        // Check that the update groups only contain targets of this update,
+        // and that no target is in more than one group.
+        let mut grouped = BTreeSet::new();
+        let mut not_targets = BTreeSet::new();
+        let mut duplicates = BTreeSet::new();
+        for sp in update_groups.iter().flatten() {
+            if !sps.contains(sp) {
+                not_targets.insert(*sp);
+            }
+            if !grouped.insert(*sp) {
+                duplicates.insert(*sp);
+            }
+        }
+
+        if !not_targets.is_empty() {
+            errors.push(StartUpdateError::UpdateGroupNotTarget(
+                not_targets.into_iter().collect(),
+            ));
+        }
+        if !duplicates.is_empty() {
+            errors.push(StartUpdateError::UpdateGroupDuplicateTarget(
+                duplicates.into_iter().collect(),
+            ));
+        }
+
         let plan = update_data.artifact_store.current_plan();
         if plan.is_none() {
             // (1), referred to below.
@@ -257,36 +617,36 @@ impl UpdateTracker {
         if let Some(mut spawn_update_driver) = spawn_update_driver {
             let setup_data = spawn_update_driver.setup(&plan).await;
 
-            for sp in sps {
-                match update_data.sp_update_data.entry(sp) {
-                    // Vacant: this is the first time we've started an update to this
-                    // sp.
-                    Entry::Vacant(slot) => {
-                        slot.insert(
-                            spawn_update_driver
-                                .spawn_update_driver(
-                                    sp,
-                                    plan.clone(),
-                                    &setup_data,
-                                )
-                                .await,
-                        );
-                    }
-                    // Occupied: we've previously started an update to this sp.
-                    Entry::Occupied(mut slot) => {
-                        assert!(
-                            slot.get().task.is_finished(),
-                            "we just checked that the task was finished"
-                        );
-                        slot.insert(
-                            spawn_update_driver
-                                .spawn_update_driver(
-                                    sp,
-                                    plan.clone(),
-                                    &setup_data,
-                                )
-                                .await,
-                        );
+            // All drivers are spawned now (so they're visible as in progress),
+            // but drivers in later groups wait for earlier groups to finish
+            // before doing anything.
+            let groups = sequence_update_groups(&sps, update_groups);
+            let sequencing = UpdateGroupSequencing::for_groups(groups.len());
+            for (group, sequencing) in groups.into_iter().zip(sequencing) {
+                for sp in group {
+                    let sp_update_data = spawn_update_driver
+                        .spawn_update_driver(
+                            sp,
+                            plan.clone(),
+                            &setup_data,
+                            sequencing.clone(),
+                        )
+                        .await;
+                    match update_data.sp_update_data.entry(sp) {
+                        // Vacant: this is the first time we've started an
+                        // update to this sp.
+                        Entry::Vacant(slot) => {
+                            slot.insert(sp_update_data);
+                        }
+                        // Occupied: we've previously started an update to this
+                        // sp.
+                        Entry::Occupied(mut slot) => {
+                            assert!(
+                                slot.get().task.is_finished(),
+                                "we just checked that the task was finished"
+                            );
+                            slot.insert(sp_update_data);
+                        }
                     }
                 }
             }
@@ -298,20 +658,26 @@ impl UpdateTracker {
     fn spawn_upload_trampoline_phase_2_to_mgs(
         &self,
         plan: &UpdatePlan,
+        max_attempts: Option<NonZeroU32>,
     ) -> UploadTrampolinePhase2ToMgs {
         let artifact = plan.trampoline_phase_2.clone();
         let (status_tx, status_rx) =
             watch::channel(UploadTrampolinePhase2ToMgsStatus {
                 hash: artifact.data.hash(),
-                uploaded_image_id: None,
+                state: UploadTrampolinePhase2ToMgsState::InProgress,
             });
         let task = tokio::spawn(upload_trampoline_phase_2_to_mgs(
             self.mgs_client.clone(),
             artifact,
+            max_attempts,
             status_tx,
             self.log.clone(),
         ));
-        UploadTrampolinePhase2ToMgs { status: status_rx, task }
+        UploadTrampolinePhase2ToMgs {
+            status: status_rx,
+            task,
+            interest: Weak::new(),
+        }
     }
 
     /// Updates the repository stored inside the update tracker.
@@ -326,9 +692,36 @@ impl UpdateTracker {
         update_data.put_repository(data).await
     }
 
-    /// Gets a list of artifacts stored in the update repository.
+    /// Gets the system version and the IDs of the artifacts stored in the
+    /// update repository.
+    pub(crate) async fn artifacts(&self) -> GetArtifactsResponse {
+        let update_data = self.sp_update_data.lock().await;
+
+        let (system_version, artifacts) = match update_data
+            .artifact_store
+            .system_version_and_artifact_ids()
+        {
+            Some((system_version, artifacts)) => (
+                Some(system_version),
+                artifacts
+                    .into_iter()
+                    .map(|artifact| artifact.artifact_id)
+                    .collect(),
+            ),
+            None => (None, Vec::new()),
+        };
+
+        GetArtifactsResponse { system_version, artifacts }
+    }
+
+    /// Gets a list of artifacts stored in the update repository, along with
+    /// event reports for all updates.
+    ///
+    /// For SPs present in `last_seen`, the event report only contains step
+    /// events after the given index.
     pub(crate) async fn artifacts_and_event_reports(
         &self,
+        last_seen: &BTreeMap<SpType, BTreeMap<u32, usize>>,
     ) -> GetArtifactsAndEventReportsResponse {
         let update_data = self.sp_update_data.lock().await;
 
@@ -343,21 +736,44 @@ impl UpdateTracker {
         };
 
         let mut event_reports = BTreeMap::new();
+        let mut possibly_stuck = Vec::new();
         for (sp, update_data) in &update_data.sp_update_data {
-            let event_report =
-                update_data.event_buffer.lock().unwrap().generate_report();
+            let since = last_seen
+                .get(&sp.type_)
+                .and_then(|by_slot| by_slot.get(&sp.slot))
+                .copied();
+            let event_report = update_data.event_report_since(since);
             let inner: &mut BTreeMap<_, _> =
                 event_reports.entry(sp.type_).or_default();
             inner.insert(sp.slot, event_report);
+
+            if update_data.is_possibly_stuck(POSSIBLY_STUCK_THRESHOLD) {
+                possibly_stuck.push(*sp);
+            }
         }
 
         GetArtifactsAndEventReportsResponse {
             system_version,
             artifacts,
             event_reports,
+            possibly_stuck,
         }
     }
 
+    /// Returns a summary of the state of `sp`'s update.
+    pub(crate) async fn update_state(
+        &self,
+        sp: SpIdentifier,
+    ) -> SpUpdateStateSummary {
+        let update_data = self.sp_update_data.lock().await;
+        update_data
+            .sp_update_data
+            .get(&sp)
+            .map_or(SpUpdateStateSummary::NotStarted, |update_data| {
+                update_data.state_summary()
+            })
+    }
+
     pub(crate) async fn event_report(&self, sp: SpIdentifier) -> EventReport {
         let mut update_data = self.sp_update_data.lock().await;
         match update_data.sp_update_data.entry(sp) {
@@ -385,12 +801,15 @@ trait SpawnUpdateDriver {
 
     /// Spawn the update driver for the given SP.
     ///
-    /// This is called once per SP.
+    /// This is called once per SP. The driver must wait for the previous
+    /// update group (if any) before doing anything else, and must hold
+    /// `sequencing` until it finishes.
     async fn spawn_update_driver(
         &mut self,
         sp: SpIdentifier,
         plan: UpdatePlan,
         setup_data: &Self::Setup,
+        sequencing: UpdateGroupSequencing,
     ) -> SpUpdateData;
 }
 
@@ -405,44 +824,63 @@ struct RealSpawnUpdateDriver<'tr> {
 
 #[async_trait::async_trait]
 impl<'tr> SpawnUpdateDriver for RealSpawnUpdateDriver<'tr> {
-    type Setup = watch::Receiver<UploadTrampolinePhase2ToMgsStatus>;
+    type Setup = Arc<UploadTrampolinePhase2ToMgsInterest>;
 
     async fn setup(&mut self, plan: &UpdatePlan) -> Self::Setup {
         // Do we need to upload this plan's trampoline phase 2 to MGS?
 
         let mut upload_trampoline_phase_2_to_mgs =
             self.update_tracker.upload_trampoline_phase_2_to_mgs.lock().await;
+        let max_attempts = self
+            .opts
+            .trampoline_phase_2_upload_max_attempts
+            .and_then(NonZeroU32::new);
 
         match upload_trampoline_phase_2_to_mgs.as_mut() {
             Some(prev) => {
                 // We've previously started an upload - does it match
-                // this artifact? If not, cancel the old task (which
-                // might still be trying to upload) and start a new one
-                // with our current image.
-                if prev.status.borrow().hash
-                    != plan.trampoline_phase_2.data.hash()
-                {
-                    // It does _not_ match - we have a new plan with a
-                    // different trampoline image. If the old task is
+                // this artifact, and has it neither given up nor been
+                // cancelled? If not, cancel the old task (which might still be
+                // trying to upload) and start a new one with our current
+                // image.
+                let restart = {
+                    let status = prev.status.borrow();
+                    status.hash != plan.trampoline_phase_2.data.hash()
+                        || matches!(
+                            status.state,
+                            UploadTrampolinePhase2ToMgsState::Failed
+                        )
+                } || prev.was_cancelled();
+                if restart {
+                    // Either we have a new plan with a different trampoline
+                    // image, the previous upload ran out of attempts, or every
+                    // update that needed it went away. If the old task is
                     // still running, cancel it, and start a new one.
                     prev.task.abort();
                     *prev = self
                         .update_tracker
-                        .spawn_upload_trampoline_phase_2_to_mgs(&plan);
+                        .spawn_upload_trampoline_phase_2_to_mgs(
+                            &plan,
+                            max_attempts,
+                        );
                 }
             }
             None => {
                 *upload_trampoline_phase_2_to_mgs = Some(
-                    self.update_tracker
-                        .spawn_upload_trampoline_phase_2_to_mgs(&plan),
+                    self.update_tracker.spawn_upload_trampoline_phase_2_to_mgs(
+                        &plan,
+                        max_attempts,
+                    ),
                 );
             }
         }
 
         // Both branches above leave `upload_trampoline_phase_2_to_mgs`
-        // with data, so we can unwrap here to clone the `watch`
-        // channel.
-        upload_trampoline_phase_2_to_mgs.as_ref().unwrap().status.clone()
+        // with data, so we can unwrap here to register our interest in it.
+        upload_trampoline_phase_2_to_mgs
+            .as_mut()
+            .unwrap()
+            .register_interest(&self.update_tracker.log)
     }
 
     async fn spawn_update_driver(
@@ -450,6 +888,7 @@ impl<'tr> SpawnUpdateDriver for RealSpawnUpdateDriver<'tr> {
         sp: SpIdentifier,
         plan: UpdatePlan,
         setup_data: &Self::Setup,
+        sequencing: UpdateGroupSequencing,
     ) -> SpUpdateData {
         // Generate an ID for this update; the update tracker will send it to the
         // sled as part of the InstallinatorImageId, and installinator will send it
@@ -457,14 +896,32 @@ impl<'tr> SpawnUpdateDriver for RealSpawnUpdateDriver<'tr> {
         let update_id = Uuid::new_v4();
 
         let event_buffer = Arc::new(StdMutex::new(EventBuffer::new(16)));
+        let activity = UpdateActivity::new();
+        let previous_group = sequencing.previous_group.clone();
         let ipr_start_receiver =
             self.update_tracker.ipr_update_tracker.register(update_id);
+        let host_boot_checkpoint =
+            self.opts.pause_before_host_boot.then(HostBootCheckpoint::new);
 
         let update_cx = UpdateContext {
             update_id,
             sp,
+            mgs_addr: self.update_tracker.mgs_addr,
             mgs_client: self.update_tracker.mgs_client.clone(),
             upload_trampoline_phase_2_to_mgs: setup_data.clone(),
+            poll_intervals: MgsPollIntervals::from_options(&self.opts),
+            installinator_start_timeout: self
+                .opts
+                .installinator_start_timeout_secs
+                .map(Duration::from_secs),
+            host_boot_checkpoint: host_boot_checkpoint.clone(),
+            verify_host_boot_slot: self.opts.verify_host_boot_slot,
+            bootstrap_peers: self.update_tracker.bootstrap_peers.clone(),
+            repository_check: RepositoryCheck::new(
+                self.update_tracker.artifact_store.clone(),
+                &plan,
+            ),
+            metrics: self.update_tracker.metrics.clone(),
             log: self.update_tracker.log.new(o!(
                 "sp" => format!("{sp:?}"),
                 "update_id" => update_id.to_string(),
@@ -481,8 +938,10 @@ impl<'tr> SpawnUpdateDriver for RealSpawnUpdateDriver<'tr> {
             plan,
             update_cx,
             event_buffer.clone(),
+            activity.clone(),
             ipr_start_receiver,
             self.opts.clone(),
+            sequencing,
             abort_handle_sender,
         ));
 
@@ -490,17 +949,27 @@ impl<'tr> SpawnUpdateDriver for RealSpawnUpdateDriver<'tr> {
             .await
             .expect("abort handle is sent immediately");
 
-        SpUpdateData { task, abort_handle, event_buffer }
+        SpUpdateData {
+            task,
+            abort_handle,
+            event_buffer,
+            activity,
+            previous_group,
+            host_boot_checkpoint,
+        }
     }
 }
 
 /// A fake implementation of [`SpawnUpdateDriver`].
 ///
 /// This implementation is only used by tests. It contains a single step that
-/// waits for a [`watch::Receiver`] to resolve.
+/// waits for a [`watch::Receiver`] to resolve, then checks that the repository
+/// hasn't changed, as a real update does before updating each component.
 #[derive(Debug)]
 struct FakeUpdateDriver {
     watch_receiver: watch::Receiver<()>,
+    artifact_store: WicketdArtifactStore,
+    metrics: UpdateMetrics,
     log: Logger,
 }
 
@@ -512,13 +981,20 @@ impl SpawnUpdateDriver for FakeUpdateDriver {
 
     async fn spawn_update_driver(
         &mut self,
-        _sp: SpIdentifier,
-        _plan: UpdatePlan,
+        sp: SpIdentifier,
+        plan: UpdatePlan,
         _setup_data: &Self::Setup,
+        mut sequencing: UpdateGroupSequencing,
     ) -> SpUpdateData {
+        let repository_check =
+            RepositoryCheck::new(self.artifact_store.clone(), &plan);
         let (sender, mut receiver) = mpsc::channel(128);
         let event_buffer = Arc::new(StdMutex::new(EventBuffer::new(16)));
         let event_buffer_2 = event_buffer.clone();
+        let activity = UpdateActivity::new();
+        let activity_2 = activity.clone();
+        let previous_group = sequencing.previous_group.clone();
+        let metrics = self.metrics.clone();
         let log = self.log.clone();
 
         let engine = UpdateEngine::new(&log, sender);
@@ -527,6 +1003,8 @@ impl SpawnUpdateDriver for FakeUpdateDriver {
         let mut watch_receiver = self.watch_receiver.clone();
 
         let task = tokio::spawn(async move {
+            sequencing.register_wait_step(&engine, UpdateComponent::Host);
+
             // The step component and ID have been chosen arbitrarily here --
             // they aren't important.
             engine
@@ -539,6 +1017,7 @@ impl SpawnUpdateDriver for FakeUpdateDriver {
                         // (typically a test) sends a value over the watch
                         // channel.
                         _ = watch_receiver.changed().await;
+                        repository_check.check()?;
                         StepSuccess::new(()).into()
                     },
                 )
@@ -547,7 +1026,11 @@ impl SpawnUpdateDriver for FakeUpdateDriver {
             // Spawn a task to accept all events from the executing engine.
             let event_receiving_task = tokio::spawn(async move {
                 while let Some(event) = receiver.recv().await {
+                    if let Event::Step(event) = &event {
+                        metrics.record_event(sp, event);
+                    }
                     event_buffer_2.lock().unwrap().add_event(event);
+                    activity_2.record_event();
                 }
             });
 
@@ -561,9 +1044,19 @@ impl SpawnUpdateDriver for FakeUpdateDriver {
             // Wait for all events to be received and written to the event
             // buffer.
             event_receiving_task.await.expect("event receiving task panicked");
+
+            // Only now can the next update group start.
+            drop(sequencing);
         });
 
-        SpUpdateData { task, abort_handle, event_buffer }
+        SpUpdateData {
+            task,
+            abort_handle,
+            event_buffer,
+            activity,
+            previous_group,
+            host_boot_checkpoint: None,
+        }
     }
 }
 
@@ -584,11 +1077,126 @@ impl SpawnUpdateDriver for NeverUpdateDriver {
         _sp: SpIdentifier,
         _plan: UpdatePlan,
         _setup_data: &Self::Setup,
+        _sequencing: UpdateGroupSequencing,
     ) -> SpUpdateData {
         unreachable!("this update driver cannot be constructed")
     }
 }
 
+/// Splits `sps` into the groups to update, in order: each nonempty group in
+/// `update_groups`, then a final group of any targets not in those groups.
+fn sequence_update_groups(
+    sps: &BTreeSet<SpIdentifier>,
+    update_groups: &[BTreeSet<SpIdentifier>],
+) -> Vec<BTreeSet<SpIdentifier>> {
+    let mut remaining = sps.clone();
+    let mut groups = Vec::with_capacity(update_groups.len() + 1);
+    for group in update_groups {
+        if !group.is_empty() {
+            remaining.retain(|sp| !group.contains(sp));
+            groups.push(group.clone());
+        }
+    }
+    if !remaining.is_empty() {
+        groups.push(remaining);
+    }
+    groups
+}
+
+/// Sequences the update drivers for a group of SPs after those for the
+/// previous group.
+///
+/// Every driver in a group holds a clone of the same
+/// `UpdateGroupSequencing`. Drivers in the next group wait until all of those
+/// clones have been dropped.
+#[derive(Clone, Debug)]
+struct UpdateGroupSequencing {
+    // Closed once every driver in the previous group has finished; `None` for
+    // the first group.
+    previous_group: Option<watch::Receiver<()>>,
+    // Held by every driver in this group until that driver finishes. Never
+    // read; we only care about when it's dropped.
+    _this_group: Arc<watch::Sender<()>>,
+}
+
+impl UpdateGroupSequencing {
+    /// Returns the sequencing for each of `count` update groups, in order.
+    fn for_groups(count: usize) -> Vec<Self> {
+        let mut previous_group = None;
+        (0..count)
+            .map(|_| {
+                let (sender, receiver) = watch::channel(());
+                Self {
+                    previous_group: previous_group.replace(receiver),
+                    _this_group: Arc::new(sender),
+                }
+            })
+            .collect()
+    }
+
+    /// If there's a previous update group, registers a step on `engine` that
+    /// waits for every driver in that group to finish.
+    ///
+    /// This must be called before registering any other steps.
+    fn register_wait_step(
+        &mut self,
+        engine: &UpdateEngine<'_>,
+        component: UpdateComponent,
+    ) {
+        let Some(mut previous_group) = self.previous_group.take() else {
+            return;
+        };
+
+        engine
+            .new_step(
+                component,
+                UpdateStepId::WaitingForPreviousUpdateGroup,
+                "Waiting for the previous update group to finish",
+                move |_cx| async move {
+                    // Nothing is ever sent on this channel, so this only
+                    // returns (with an error) once every driver in the previous
+                    // group has dropped its sender.
+                    _ = previous_group.changed().await;
+                    StepSuccess::new(()).into()
+                },
+            )
+            .register();
+    }
+}
+
+/// Checks that the repository an update was started with is still the current
+/// one.
+///
+/// `put_repository` refuses to replace the repository while updates are
+/// running, but an update must never apply artifacts from two repositories, so
+/// each driver also checks before updating any component.
+#[derive(Clone, Debug)]
+struct RepositoryCheck {
+    artifact_store: WicketdArtifactStore,
+    plan_hash: ArtifactHash,
+}
+
+impl RepositoryCheck {
+    fn new(artifact_store: WicketdArtifactStore, plan: &UpdatePlan) -> Self {
+        Self { artifact_store, plan_hash: plan.content_hash() }
+    }
+
+    /// Fails with [`UpdateTerminalError::RepositoryChanged`] if the current
+    /// plan differs from the one the update was started with.
+    fn check(&self) -> Result<(), UpdateTerminalError> {
+        let current =
+            self.artifact_store.current_plan().map(|plan| plan.content_hash());
+        if current == Some(self.plan_hash) {
+            Ok(())
+        } else {
+            Err(UpdateTerminalError::RepositoryChanged {
+                expected: self.plan_hash,
+                current,
+            })
+        }
+    }
+}
+
 #[derive(Debug)]
 struct UpdateTrackerData {
     artifact_store: WicketdArtifactStore,
@@ -622,6 +1230,17 @@ impl UpdateTrackerData {
         sp: SpIdentifier,
         message: String,
     ) -> Result<(), AbortUpdateError> {
+        self.request_abort(sp, message)?.await;
+        Ok(())
+    }
+
+    /// Asks a running update to abort, returning a future that resolves once
+    /// the abort has been processed.
+    fn request_abort(
+        &self,
+        sp: SpIdentifier,
+        message: String,
+    ) -> Result<AbortWaiter, AbortUpdateError> {
         let Some(update_data) = self.sp_update_data.get(&sp) else {
             return Err(AbortUpdateError::UpdateNotStarted);
         };
@@ -635,16 +1254,44 @@ impl UpdateTrackerData {
             return Err(AbortUpdateError::UpdateFinished);
         }
 
-        match update_data.abort_handle.abort(message) {
-            Ok(waiter) => {
-                waiter.await;
-                Ok(())
-            }
-            Err(_) => {
-                // This occurs if the engine has finished execution and has been
-                // dropped.
-                Err(AbortUpdateError::UpdateFinished)
-            }
+        update_data.abort_handle.abort(message).map_err(|_| {
+            // This occurs if the engine has finished execution and has been
+            // dropped.
+            AbortUpdateError::UpdateFinished
+        })
+    }
+
+    /// Asks a running update to abort and removes its state, returning the
+    /// abort waiter and the update's task so the caller can wait on them
+    /// without holding the lock.
+    fn abort_and_remove_update(
+        &mut self,
+        sp: SpIdentifier,
+        message: String,
+    ) -> Result<(AbortWaiter, JoinHandle<()>), AbortUpdateError> {
+        let waiter = self.request_abort(sp, message)?;
+        let update_data = self
+            .sp_update_data
+            .remove(&sp)
+            .expect("request_abort succeeded, so the update data exists");
+        Ok((waiter, update_data.task))
+    }
+
+    fn proceed_update(
+        &self,
+        sp: SpIdentifier,
+    ) -> Result<(), ProceedUpdateError> {
+        let Some(update_data) = self.sp_update_data.get(&sp) else {
+            return Err(ProceedUpdateError::UpdateNotStarted);
+        };
+
+        if update_data.task.is_finished() {
+            return Err(ProceedUpdateError::UpdateFinished);
+        }
+
+        match &update_data.host_boot_checkpoint {
+            Some(checkpoint) if checkpoint.proceed() => Ok(()),
+            _ => Err(ProceedUpdateError::NotWaitingForConfirmation),
         }
     }
 
@@ -680,10 +1327,42 @@ impl UpdateTrackerData {
 
 #[derive(Debug, Clone, Error, Eq, PartialEq)]
 pub enum StartUpdateError {
+    #[error("invalid update targets: {}", sps_to_string(.0))]
+    InvalidTarget(Vec<SpIdentifier>),
     #[error("no TUF repository available")]
     TufRepositoryUnavailable,
     #[error("targets are already being updated: {}", sps_to_string(.0))]
     UpdateInProgress(Vec<SpIdentifier>),
+    #[error(
+        "update groups contain SPs that are not update targets: {}",
+        sps_to_string(.0)
+    )]
+    UpdateGroupNotTarget(Vec<SpIdentifier>),
+    #[error("SPs are in more than one update group: {}", sps_to_string(.0))]
+    UpdateGroupDuplicateTarget(Vec<SpIdentifier>),
+    #[error("host-only updates can only target sleds: {}", sps_to_string(.0))]
+    HostOnlyNotSled(Vec<SpIdentifier>),
+    #[error("no SPs of type {0:?} are present in inventory")]
+    NoPresentTargets(SpType),
+}
+
+/// Returns the SPs of type `sp_type` whose state is present in `inventory`.
+///
+/// Returns an error if there are none.
+fn present_sps_of_type(
+    inventory: &RackV1Inventory,
+    sp_type: SpType,
+) -> Result<BTreeSet<SpIdentifier>, StartUpdateError> {
+    let sps: BTreeSet<_> = inventory
+        .sps
+        .iter()
+        .filter(|sp| sp.id.type_ == sp_type && sp.state.is_some())
+        .map(|sp| sp.id)
+        .collect();
+    if sps.is_empty() {
+        return Err(StartUpdateError::NoPresentTargets(sp_type));
+    }
+    Ok(sps)
 }
 
 #[derive(Debug, Clone, Error, Eq, PartialEq)]
@@ -726,263 +1405,404 @@ impl AbortUpdateError {
     }
 }
 
-#[derive(Debug)]
-struct UpdateDriver {}
+#[derive(Debug, Clone, Error, Eq, PartialEq)]
+pub enum ProceedUpdateError {
+    #[error("update task not started")]
+    UpdateNotStarted,
 
-impl UpdateDriver {
-    async fn run(
-        self,
-        plan: UpdatePlan,
-        update_cx: UpdateContext,
-        event_buffer: Arc<StdMutex<EventBuffer>>,
-        ipr_start_receiver: IprStartReceiver,
-        opts: StartUpdateOptions,
-        abort_handle_sender: oneshot::Sender<AbortHandle>,
-    ) {
-        let update_cx = &update_cx;
+    #[error("update task already finished")]
+    UpdateFinished,
 
-        // TODO: We currently do updates in the order RoT -> SP -> host. This is
-        // generally the correct order, but in some cases there might be a bug
-        // which forces us to update components in the order SP -> RoT -> host.
-        // How do we handle that?
-        //
-        // Broadly, there are two ways to do this:
-        //
-        // 1. Add metadata to artifacts.json indicating the order in which
-        //    components should be updated. There are a lot of options in the
-        //    design space here, from a simple boolean to a list or DAG
-        //    expressing the order, or something even more dynamic than that.
-        //
-        // 2. Skip updating components that match the same version. This would
-        //    let us ship two separate archives in case there's a bug: one with
-        //    the newest components for the SP and RoT, and one without.
+    #[error("update is not waiting for confirmation to boot the host")]
+    NotWaitingForConfirmation,
+}
 
-        // Build the update executor.
-        let (sender, mut receiver) = mpsc::channel(128);
-        let mut engine = UpdateEngine::new(&update_cx.log, sender);
-        let abort_handle = engine.abort_handle();
-        _ = abort_handle_sender.send(abort_handle);
+impl ProceedUpdateError {
+    pub(crate) fn to_http_error(&self) -> HttpError {
+        let message = DisplayErrorChain::new(self).to_string();
 
-        if let Some(secs) = opts.test_step_seconds {
-            define_test_steps(&engine, secs);
+        match self {
+            ProceedUpdateError::UpdateNotStarted
+            | ProceedUpdateError::UpdateFinished
+            | ProceedUpdateError::NotWaitingForConfirmation => {
+                HttpError::for_bad_request(None, message)
+            }
         }
+    }
+}
 
-        let (rot_a, rot_b, sp_artifacts) = match update_cx.sp.type_ {
-            SpType::Sled => (
-                plan.gimlet_rot_a.clone(),
-                plan.gimlet_rot_b.clone(),
-                &plan.gimlet_sp,
-            ),
-            SpType::Power => {
-                (plan.psc_rot_a.clone(), plan.psc_rot_b.clone(), &plan.psc_sp)
+/// Converts an error from MGS into one whose message carries its full cause
+/// chain.
+///
+/// Update errors are reported (and serialized into event reports) as messages,
+/// so without this, inner causes of MGS errors could be lost along the way.
+fn mgs_error(
+    error: gateway_client::Error<gateway_client::types::Error>,
+) -> anyhow::Error {
+    anyhow!(DisplayErrorChain::new(&error).to_string())
+}
+
+/// Executes `engine`, recording every event it sends to `receiver` in
+/// `event_buffer`.
+async fn execute_engine(
+    engine: UpdateEngine<'_>,
+    mut receiver: mpsc::Receiver<Event>,
+    update_cx: &UpdateContext,
+    event_buffer: Arc<StdMutex<EventBuffer>>,
+    activity: UpdateActivity,
+) {
+    // Spawn a task to accept all events from the executing engine.
+    let sp = update_cx.sp;
+    let metrics = update_cx.metrics.clone();
+    let event_receiving_task = tokio::spawn(async move {
+        while let Some(event) = receiver.recv().await {
+            if let Event::Step(event) = &event {
+                metrics.record_event(sp, event);
             }
-            SpType::Switch => (
-                plan.sidecar_rot_a.clone(),
-                plan.sidecar_rot_b.clone(),
-                &plan.sidecar_sp,
-            ),
-        };
+            event_buffer.lock().unwrap().add_event(event);
+            activity.record_event();
+        }
+    });
 
-        let rot_registrar = engine.for_component(UpdateComponent::Rot);
-        let sp_registrar = engine.for_component(UpdateComponent::Sp);
+    // Execute the update engine.
+    match engine.execute().await {
+        Ok(_cx) => (),
+        Err(err) => {
+            error!(update_cx.log, "update failed"; "err" => %err);
+        }
+    }
 
-        // To update the RoT, we have to know which slot (A or B) it is
-        // currently executing; we must update the _other_ slot. We also want to
-        // know its current version (so we can skip updating if we only need to
-        // update the SP and/or host).
-        let rot_interrogation =
-            rot_registrar
-                .new_step(
-                    UpdateStepId::InterrogateRot,
-                    "Checking current RoT version and active slot",
-                    |_cx| async move {
-                        update_cx.interrogate_rot(rot_a, rot_b).await
-                    },
-                )
-                .register();
+    // Wait for all events to be received and written to the update log.
+    event_receiving_task.await.expect("event receiving task panicked");
+}
 
-        // The SP only has one updateable firmware slot ("the inactive bank").
-        // We want to ask about slot 0 (the active slot)'s current version, and
-        // we are supposed to always pass 0 when updating.
-        let sp_firmware_slot = 0;
+/// Registers the only step of an update whose components are all already up
+/// to date.
+fn register_up_to_date_step(engine: &UpdateEngine<'_>, message: String) {
+    engine
+        .new_step(
+            UpdateComponent::Sp,
+            UpdateStepId::CheckingComponentsUpToDate,
+            "Checking whether components are already up to date",
+            move |_cx| async move {
+                StepSuccess::new(()).with_message(message).into()
+            },
+        )
+        .register();
+}
 
-        // To update the SP, we want to know both its version and its board (so
-        // we can map to the correct artifact from our update plan).
-        let sp_artifact_and_version = sp_registrar
+/// Registers a skipped "Updating RoT" and "Updating SP" step, in place of
+/// the steps that interrogate and update them, for a host-only update.
+fn register_host_only_skipped_steps(engine: &UpdateEngine<'_>) {
+    for (component, name) in [
+        (UpdateComponent::Rot, "Updating RoT"),
+        (UpdateComponent::Sp, "Updating SP"),
+    ] {
+        engine
             .new_step(
-                UpdateStepId::InterrogateSp,
-                "Checking SP board and current version",
+                component,
+                UpdateStepId::SpComponentUpdate,
+                name,
                 move |_cx| async move {
-                    let caboose = update_cx
-                        .mgs_client
-                        .sp_component_caboose_get(
-                            update_cx.sp.type_,
-                            update_cx.sp.slot,
-                            SpComponent::SP_ITSELF.const_as_str(),
-                            sp_firmware_slot,
-                        )
-                        .await
-                        .map_err(|error| {
-                            UpdateTerminalError::GetSpCabooseFailed { error }
-                        })?
-                        .into_inner();
-
-                    let Some(sp_artifact) = sp_artifacts.get(&caboose.board)
-                    else {
-                        return Err(
-                            UpdateTerminalError::MissingSpImageForBoard {
-                                board: caboose.board,
-                            },
-                        );
-                    };
-                    let sp_artifact = sp_artifact.clone();
-
-                    let message = format!(
-                        "SP board {}, version {} (git commit {})",
-                        caboose.board,
-                        caboose.version.as_deref().unwrap_or("unknown"),
-                        caboose.git_commit
-                    );
-                    match caboose.version.map(|v| v.parse::<SemverVersion>()) {
-                        Some(Ok(version)) => {
-                            StepSuccess::new((sp_artifact, Some(version)))
-                                .with_message(message)
-                                .into()
-                        }
-                        Some(Err(err)) => StepWarning::new(
-                            (sp_artifact, None),
-                            format!(
-                                "{message} (failed to parse SP version: {err})"
-                            ),
-                        )
-                        .into(),
-                        None => StepWarning::new((sp_artifact, None), message)
-                            .into(),
-                    }
+                    StepSkipped::new((), "host-only update").into()
                 },
             )
             .register();
-        // Send the update to the RoT.
-        let inner_cx =
-            SpComponentUpdateContext::new(update_cx, UpdateComponent::Rot);
-        rot_registrar
-            .new_step(
-                UpdateStepId::SpComponentUpdate,
-                "Updating RoT",
-                move |cx| async move {
-                    if let Some(result) = opts.test_simulate_rot_result {
-                        return simulate_result(result);
-                    }
+    }
+}
 
-                    let rot_interrogation =
-                        rot_interrogation.into_value(cx.token()).await;
-
-                    let rot_has_this_version = rot_interrogation
-                        .active_version_matches_artifact_to_apply();
-
-                    // If this RoT already has this version, skip the rest of
-                    // this step, UNLESS we've been told to skip this version
-                    // check.
-                    if rot_has_this_version && !opts.skip_rot_version_check {
-                        return StepSkipped::new(
-                            (),
-                            format!(
-                                "RoT active slot already at version {}",
-                                rot_interrogation.artifact_to_apply.id.version
-                            ),
-                        )
-                        .into();
-                    }
+/// Registers the steps that interrogate and update the RoT and the SP.
+fn register_rot_and_sp_steps<'a>(
+    update_cx: &'a UpdateContext,
+    engine: &UpdateEngine<'a>,
+    rot_a: ArtifactIdData,
+    rot_b: ArtifactIdData,
+    sp_artifacts: &'a BTreeMap<Board, ArtifactIdData>,
+    opts: StartUpdateOptions,
+) {
+    let rot_registrar = engine.for_component(UpdateComponent::Rot);
+    let sp_registrar = engine.for_component(UpdateComponent::Sp);
+
+    // To update the RoT, we have to know which slot (A or B) it is
+    // currently executing; we must update the _other_ slot. We also want to
+    // know its current version (so we can skip updating if we only need to
+    // update the SP and/or host).
+    let rot_interrogation = rot_registrar
+        .new_step(
+            UpdateStepId::InterrogateRot,
+            "Checking current RoT version and active slot",
+            |_cx| async move { update_cx.interrogate_rot(rot_a, rot_b).await },
+        )
+        .register();
 
-                    cx.with_nested_engine(|engine| {
-                        inner_cx.register_steps(
-                            engine,
-                            rot_interrogation.slot_to_update,
-                            &rot_interrogation.artifact_to_apply,
-                        );
-                        Ok(())
-                    })
-                    .await?;
+    // To update the SP, we want to know both its version and its board (so
+    // we can map to the correct artifact from our update plan).
+    let sp_artifact_and_version = sp_registrar
+        .new_step(
+            UpdateStepId::InterrogateSp,
+            "Checking SP board and current version",
+            move |_cx| async move {
+                update_cx
+                    .interrogate_sp(
+                        sp_artifacts,
+                        SP_FIRMWARE_SLOT,
+                        opts.fail_on_unparseable_sp_version,
+                    )
+                    .await
+            },
+        )
+        .register();
+    // Send the update to the RoT.
+    let inner_cx =
+        SpComponentUpdateContext::new(update_cx, UpdateComponent::Rot);
+    rot_registrar
+        .new_step(
+            UpdateStepId::SpComponentUpdate,
+            "Updating RoT",
+            move |cx| async move {
+                if let Some(result) = opts.test_simulate_rot_result {
+                    return simulate_result(result);
+                }
 
-                    // If we updated despite the RoT already having the version
-                    // we updated to, make this step return a warning with that
-                    // message; otherwise, this is a normal success.
-                    if rot_has_this_version {
-                        StepWarning::new(
-                            (),
-                            format!(
-                                "RoT updated despite already having version {}",
-                                rot_interrogation.artifact_to_apply.id.version
-                            ),
-                        )
-                        .into()
-                    } else {
-                        StepSuccess::new(()).into()
-                    }
-                },
-            )
-            .register();
+                let rot_interrogation =
+                    rot_interrogation.into_value(cx.token()).await;
+
+                let rot_has_this_version = rot_interrogation
+                    .active_version_matches_artifact_to_apply();
+
+                // If this RoT already has this version, skip the rest of
+                // this step, UNLESS we've been told to skip this version
+                // check.
+                if rot_has_this_version && !opts.skip_rot_version_check {
+                    return StepSkipped::new(
+                        (),
+                        format!(
+                            "RoT active slot already at version {}",
+                            rot_interrogation.artifact_to_apply.id.version
+                        ),
+                    )
+                    .into();
+                }
 
-        let inner_cx =
-            SpComponentUpdateContext::new(update_cx, UpdateComponent::Sp);
-        sp_registrar
-            .new_step(
-                UpdateStepId::SpComponentUpdate,
-                "Updating SP",
-                move |cx| async move {
-                    if let Some(result) = opts.test_simulate_sp_result {
-                        return simulate_result(result);
-                    }
+                update_cx.repository_check.check()?;
+                cx.with_nested_engine(|engine| {
+                    inner_cx.register_steps(
+                        engine,
+                        rot_interrogation.slot_to_update,
+                        &rot_interrogation.artifact_to_apply,
+                    );
+                    Ok(())
+                })
+                .await?;
 
-                    let (sp_artifact, sp_version) =
-                        sp_artifact_and_version.into_value(cx.token()).await;
-
-                    let sp_has_this_version =
-                        Some(&sp_artifact.id.version) == sp_version.as_ref();
-
-                    // If this SP already has this version, skip the rest of
-                    // this step, UNLESS we've been told to skip this version
-                    // check.
-                    if sp_has_this_version && !opts.skip_sp_version_check {
-                        return StepSkipped::new(
-                            (),
-                            format!(
-                                "SP already at version {}",
-                                sp_artifact.id.version
-                            ),
-                        )
-                        .into();
-                    }
+                // If we updated despite the RoT already having the version
+                // we updated to, make this step return a warning with that
+                // message; otherwise, this is a normal success.
+                if rot_has_this_version {
+                    StepWarning::new(
+                        (),
+                        format!(
+                            "RoT updated despite already having version {}",
+                            rot_interrogation.artifact_to_apply.id.version
+                        ),
+                    )
+                    .into()
+                } else {
+                    StepSuccess::new(()).into()
+                }
+            },
+        )
+        .register();
 
-                    cx.with_nested_engine(|engine| {
-                        inner_cx.register_steps(
-                            engine,
-                            sp_firmware_slot,
-                            &sp_artifact,
-                        );
-                        Ok(())
-                    })
-                    .await?;
+    let inner_cx =
+        SpComponentUpdateContext::new(update_cx, UpdateComponent::Sp);
+    sp_registrar
+        .new_step(
+            UpdateStepId::SpComponentUpdate,
+            "Updating SP",
+            move |cx| async move {
+                if let Some(result) = opts.test_simulate_sp_result {
+                    return simulate_result(result);
+                }
 
-                    // If we updated despite the SP already having the version
-                    // we updated to, make this step return a warning with that
-                    // message; otherwise, this is a normal success.
-                    if sp_has_this_version {
-                        StepWarning::new(
-                            (),
-                            format!(
-                                "SP updated despite already having version {}",
-                                sp_artifact.id.version
-                            ),
-                        )
-                        .into()
-                    } else {
-                        StepSuccess::new(()).into()
-                    }
-                },
+                let (sp_artifact, sp_version) =
+                    sp_artifact_and_version.into_value(cx.token()).await;
+
+                let sp_has_this_version =
+                    Some(&sp_artifact.id.version) == sp_version.as_ref();
+
+                // If this SP already has this version, skip the rest of
+                // this step, UNLESS we've been told to skip this version
+                // check.
+                if sp_has_this_version && !opts.skip_sp_version_check {
+                    return StepSkipped::new(
+                        (),
+                        format!(
+                            "SP already at version {}",
+                            sp_artifact.id.version
+                        ),
+                    )
+                    .into();
+                }
+
+                update_cx.repository_check.check()?;
+                cx.with_nested_engine(|engine| {
+                    inner_cx.register_steps(
+                        engine,
+                        SP_FIRMWARE_SLOT,
+                        &sp_artifact,
+                    );
+                    Ok(())
+                })
+                .await?;
+
+                // If we updated despite the SP already having the version
+                // we updated to, make this step return a warning with that
+                // message; otherwise, this is a normal success.
+                if sp_has_this_version {
+                    StepWarning::new(
+                        (),
+                        format!(
+                            "SP updated despite already having version {}",
+                            sp_artifact.id.version
+                        ),
+                    )
+                    .into()
+                } else {
+                    StepSuccess::new(()).into()
+                }
+            },
+        )
+        .register();
+}
+
+/// Returns true if an update with `opts` may skip the RoT and SP steps when
+/// both are already up to date.
+///
+/// Host-only updates don't (they don't check the RoT and SP at all), and
+/// neither do updates told to skip a version check, or updates that run test
+/// steps or simulate results.
+fn can_skip_when_up_to_date(opts: &StartUpdateOptions) -> bool {
+    !opts.host_only
+        && !opts.skip_rot_version_check
+        && !opts.skip_sp_version_check
+        && opts.test_step_seconds.is_none()
+        && opts.test_simulate_rot_result.is_none()
+        && opts.test_simulate_sp_result.is_none()
+}
+
+/// If the RoT and SP both already run their target versions, returns a message
+/// saying so.
+fn up_to_date_message(
+    rot_target: &SemverVersion,
+    rot_active: Option<&SemverVersion>,
+    sp_target: &SemverVersion,
+    sp_active: Option<&SemverVersion>,
+) -> Option<String> {
+    (Some(rot_target) == rot_active && Some(sp_target) == sp_active).then(
+        || {
+            format!(
+                "RoT and SP already at versions {rot_target} and {sp_target}"
             )
-            .register();
+        },
+    )
+}
+
+#[derive(Debug)]
+struct UpdateDriver {}
+
+impl UpdateDriver {
+    async fn run(
+        self,
+        plan: UpdatePlan,
+        update_cx: UpdateContext,
+        event_buffer: Arc<StdMutex<EventBuffer>>,
+        activity: UpdateActivity,
+        ipr_start_receiver: IprStartReceiver,
+        opts: StartUpdateOptions,
+        mut sequencing: UpdateGroupSequencing,
+        abort_handle_sender: oneshot::Sender<AbortHandle>,
+    ) {
+        let update_cx = &update_cx;
+
+        // TODO: We currently do updates in the order RoT -> SP -> host. This is
+        // generally the correct order, but in some cases there might be a bug
+        // which forces us to update components in the order SP -> RoT -> host.
+        // How do we handle that?
+        //
+        // Broadly, there are two ways to do this:
+        //
+        // 1. Add metadata to artifacts.json indicating the order in which
+        //    components should be updated. There are a lot of options in the
+        //    design space here, from a simple boolean to a list or DAG
+        //    expressing the order, or something even more dynamic than that.
+        //
+        // 2. Skip updating components that match the same version. This would
+        //    let us ship two separate archives in case there's a bug: one with
+        //    the newest components for the SP and RoT, and one without.
+
+        let (rot_a, rot_b, sp_artifacts) = match update_cx.sp.type_ {
+            SpType::Sled => (
+                plan.gimlet_rot_a.clone(),
+                plan.gimlet_rot_b.clone(),
+                &plan.gimlet_sp,
+            ),
+            SpType::Power => {
+                (plan.psc_rot_a.clone(), plan.psc_rot_b.clone(), &plan.psc_sp)
+            }
+            SpType::Switch => (
+                plan.sidecar_rot_a.clone(),
+                plan.sidecar_rot_b.clone(),
+                &plan.sidecar_sp,
+            ),
+        };
+
+        // Build the update executor, and hand back its abort handle before we
+        // talk to MGS at all: starting the update waits for it.
+        let (sender, receiver) = mpsc::channel(128);
+        let mut engine = UpdateEngine::new(&update_cx.log, sender);
+        let abort_handle = engine.abort_handle();
+        _ = abort_handle_sender.send(abort_handle);
+
+        // If the RoT and SP already run the versions in the plan, we replace
+        // their steps with a single one rather than registering (and skipping)
+        // every update step. This makes re-running an update against an
+        // already-updated rack fast. (An abort requested meanwhile takes effect
+        // as soon as the engine starts.)
+        let up_to_date = update_cx
+            .check_up_to_date(rot_a.clone(), rot_b.clone(), sp_artifacts, &opts)
+            .await;
+
+        // The RoT is the first component we update, so that's where we wait
+        // for the previous update group (if any). An up-to-date SP still waits
+        // its turn, so that later groups don't start early.
+        sequencing.register_wait_step(&engine, UpdateComponent::Rot);
+
+        if let Some(message) = up_to_date {
+            register_up_to_date_step(&engine, message);
+        } else {
+            if let Some(secs) = opts.test_step_seconds {
+                define_test_steps(&engine, secs);
+            }
+
+            // A host-only update leaves the RoT and SP alone and goes straight
+            // to updating the host.
+            if opts.host_only {
+                register_host_only_skipped_steps(&engine);
+            } else {
+                register_rot_and_sp_steps(
+                    update_cx,
+                    &engine,
+                    rot_a,
+                    rot_b,
+                    sp_artifacts,
+                    opts,
+                );
+            }
+        }
 
+        // We don't know the host OS version, so a sled's host is always
+        // updated.
         if update_cx.sp.type_ == SpType::Sled {
             self.register_sled_steps(
                 update_cx,
@@ -992,23 +1812,11 @@ impl UpdateDriver {
             );
         }
 
-        // Spawn a task to accept all events from the executing engine.
-        let event_receiving_task = tokio::spawn(async move {
-            while let Some(event) = receiver.recv().await {
-                event_buffer.lock().unwrap().add_event(event);
-            }
-        });
-
-        // Execute the update engine.
-        match engine.execute().await {
-            Ok(_cx) => (),
-            Err(err) => {
-                error!(update_cx.log, "update failed"; "err" => %err);
-            }
-        }
+        execute_engine(engine, receiver, update_cx, event_buffer, activity)
+            .await;
 
-        // Wait for all events to be received and written to the update log.
-        event_receiving_task.await.expect("event receiving task panicked");
+        // Only now can the next update group start.
+        drop(sequencing);
     }
 
     fn register_sled_steps<'a>(
@@ -1038,13 +1846,10 @@ impl UpdateDriver {
                             ipr_start_receiver,
                             image_id,
                         )
-                        .await
-                        .map_err(|error| {
-                            UpdateTerminalError::DownloadingInstallinatorFailed { error }
-                        })?;
+                        .await?;
 
-                        StepSuccess::new(report_receiver).into()
-                    },
+                    StepSuccess::new(report_receiver).into()
+                },
             )
             .register();
 
@@ -1064,14 +1869,8 @@ impl UpdateDriver {
                             }
                         })?;
 
-                    let slots_to_update = write_output
-                        .slots_written
-                        .into_iter()
-                        .map(|slot| match slot {
-                            M2Slot::A => 0,
-                            M2Slot::B => 1,
-                        })
-                        .collect::<BTreeSet<u16>>();
+                    let slots_to_update =
+                        host_boot_slots_written(&write_output)?;
 
                     StepSuccess::new(slots_to_update).into()
                 },
@@ -1116,7 +1915,7 @@ impl UpdateDriver {
         // complete. We started a task to do this the first time a sled update
         // was started with this plan.
         let mut upload_trampoline_phase_2_to_mgs =
-            update_cx.upload_trampoline_phase_2_to_mgs.clone();
+            update_cx.upload_trampoline_phase_2_to_mgs.status.clone();
 
         let image_id_step_handle = registrar.new_step(
             UpdateStepId::WaitingForTrampolinePhase2Upload,
@@ -1131,12 +1930,18 @@ impl UpdateDriver {
                         }
                     )?;
 
-                    if let Some(image_id) = upload_trampoline_phase_2_to_mgs
-                        .borrow()
-                        .uploaded_image_id
-                        .as_ref()
-                    {
-                        return StepSuccess::new(image_id.clone()).into();
+                    match &upload_trampoline_phase_2_to_mgs.borrow().state {
+                        UploadTrampolinePhase2ToMgsState::InProgress => {}
+                        UploadTrampolinePhase2ToMgsState::Uploaded(
+                            image_id,
+                        ) => {
+                            return StepSuccess::new(image_id.clone()).into();
+                        }
+                        UploadTrampolinePhase2ToMgsState::Failed => {
+                            return Err(
+                                UpdateTerminalError::TrampolinePhase2UploadFailed,
+                            );
+                        }
                     }
                 }
             },
@@ -1161,9 +1966,8 @@ impl UpdateDriver {
                         )
                         .await
                         .map_err(|error| {
-                            // HTTP-ERROR-FULL-CAUSE-CHAIN
                             UpdateTerminalError::SetInstallinatorImageIdFailed {
-                                error,
+                                error: mgs_error(error),
                             }
                         })?;
 
@@ -1211,7 +2015,7 @@ impl UpdateDriver {
                         .map_err(|error| {
                             UpdateTerminalError::SetHostStartupOptionsFailed {
                                 description: "recovery mode",
-                                error,
+                                error: mgs_error(error),
                             }
                         })?;
 
@@ -1279,7 +2083,7 @@ impl UpdateDriver {
                 StepSuccess::new(()).into()
             }).register();
 
-        registrar
+        let slot_to_boot = registrar
             .new_step(
                 UpdateStepId::SettingHostStartupOptions,
                 "Setting startup options for standard boot",
@@ -1330,18 +2134,32 @@ impl UpdateDriver {
                         )
                         .await
                         .map_err(|error| {
-                            // HTTP-ERROR-FULL-CAUSE-CHAIN
                             UpdateTerminalError::SetHostStartupOptionsFailed {
                                 description: "standard boot",
-                                error,
+                                error: mgs_error(error),
                             }
                         })?;
 
-                    StepSuccess::new(()).into()
+                    StepSuccess::new(slot_to_boot).into()
                 },
             )
             .register();
 
+        // If requested, give the operator a chance to inspect the sled before
+        // the host boots.
+        if let Some(checkpoint) = &update_cx.host_boot_checkpoint {
+            registrar
+                .new_step(
+                    UpdateStepId::WaitingForOperatorConfirmation,
+                    "Waiting for operator confirmation to boot the host",
+                    move |cx| async move {
+                        checkpoint.wait(&cx).await;
+                        StepSuccess::new(()).into()
+                    },
+                )
+                .register();
+        }
+
         // Boot the host.
         registrar
             .new_step(
@@ -1352,6 +2170,35 @@ impl UpdateDriver {
                 },
             )
             .register();
+
+        // If requested, make sure the host came up from the slot we just set,
+        // rather than falling back to the one it booted from previously.
+        if update_cx.verify_host_boot_slot {
+            registrar
+                .new_step(
+                    UpdateStepId::VerifyingHostBootSlot,
+                    "Verifying host boot slot",
+                    move |cx| async move {
+                        // The host has to boot all the way into the sled
+                        // agent before it can tell us, which takes a while.
+                        const WAIT_FOR_BOOT_TIMEOUT: Duration =
+                            Duration::from_secs(15 * 60);
+                        let slot_to_boot =
+                            slot_to_boot.into_value(cx.token()).await;
+                        let booted_slot = update_cx
+                            .wait_for_host_boot(WAIT_FOR_BOOT_TIMEOUT)
+                            .await
+                            .map_err(|error| {
+                                UpdateTerminalError::GetHostBootedSlotFailed {
+                                    error,
+                                }
+                            })?;
+                        check_host_boot_slot(slot_to_boot, booted_slot)?;
+                        StepSuccess::new(()).into()
+                    },
+                )
+                .register();
+        }
     }
 
     fn register_deliver_host_phase1_steps<'a>(
@@ -1383,6 +2230,7 @@ impl UpdateDriver {
                         slots_to_update.into_value(cx.token()).await;
 
                     for boot_slot in slots_to_update {
+                        update_cx.repository_check.check()?;
                         cx.with_nested_engine(|engine| {
                             inner_cx
                                 .register_steps(engine, boot_slot, artifact);
@@ -1470,17 +2318,75 @@ impl RotInterrogation {
     }
 }
 
-fn simulate_result(
-    result: UpdateSimulatedResult,
-) -> Result<StepResult<()>, UpdateTerminalError> {
-    match result {
-        UpdateSimulatedResult::Success => {
-            StepSuccess::new(()).with_message("Simulated success result").into()
-        }
-        UpdateSimulatedResult::Warning => {
-            StepWarning::new((), "Simulated warning result").into()
-        }
-        UpdateSimulatedResult::Skipped => {
+/// Returns the host boot flash slot corresponding to an M.2 slot written by
+/// installinator.
+fn host_boot_slot(slot: M2Slot) -> u16 {
+    match slot {
+        M2Slot::A => 0,
+        M2Slot::B => 1,
+    }
+}
+
+/// Returns the host boot flash slots matching the M.2 slots installinator
+/// wrote, which the host phase 1 is then installed to.
+///
+/// Fails if installinator didn't write any slots, or wrote a slot it didn't
+/// report attempting, since we can't tell which slot to boot from either way.
+fn host_boot_slots_written(
+    write_output: &WriteOutput,
+) -> Result<BTreeSet<u16>, UpdateTerminalError> {
+    if write_output.slots_written.is_empty()
+        || !write_output.slots_written.is_subset(&write_output.slots_attempted)
+    {
+        let names = |slots: &BTreeSet<M2Slot>| {
+            slots.iter().map(|slot| slot.to_string()).collect()
+        };
+        return Err(UpdateTerminalError::InvalidInstallinatorSlots {
+            slots_attempted: names(&write_output.slots_attempted),
+            slots_written: names(&write_output.slots_written),
+        });
+    }
+
+    Ok(write_output.slots_written.iter().copied().map(host_boot_slot).collect())
+}
+
+/// Checks that the host booted from `expected_slot`, after being told to.
+fn check_host_boot_slot(
+    expected_slot: u16,
+    booted_slot: u16,
+) -> Result<(), UpdateTerminalError> {
+    if booted_slot == expected_slot {
+        Ok(())
+    } else {
+        Err(UpdateTerminalError::HostUnexpectedBootSlot {
+            expected_slot,
+            booted_slot,
+        })
+    }
+}
+
+/// Asks a sled's bootstrap agent which boot flash slot its host booted from.
+async fn get_host_booted_slot(
+    bootstrap_agent: &bootstrap_agent_client::Client,
+) -> anyhow::Result<u16> {
+    bootstrap_agent
+        .host_boot_slot_get()
+        .await
+        .context("failed to get host boot slot from bootstrap agent")
+        .map(|res| res.into_inner().slot)
+}
+
+fn simulate_result(
+    result: UpdateSimulatedResult,
+) -> Result<StepResult<()>, UpdateTerminalError> {
+    match result {
+        UpdateSimulatedResult::Success => {
+            StepSuccess::new(()).with_message("Simulated success result").into()
+        }
+        UpdateSimulatedResult::Warning => {
+            StepWarning::new((), "Simulated warning result").into()
+        }
+        UpdateSimulatedResult::Skipped => {
             StepSkipped::new((), "Simulated skipped result").into()
         }
         UpdateSimulatedResult::Failure => {
@@ -1489,12 +2395,219 @@ fn simulate_result(
     }
 }
 
+/// Builds the result of the `InterrogateSp` step from the SP's active
+/// `version`, passing `sp_artifact` through.
+///
+/// If the version is missing or can't be parsed, we warn and proceed without
+/// it (which means the SP is always updated), unless
+/// `fail_on_unparseable_version` is set, in which case an unparseable version
+/// fails the update.
+fn sp_interrogation_result<T>(
+    sp_artifact: T,
+    version: Option<String>,
+    message: String,
+    fail_on_unparseable_version: bool,
+) -> Result<StepResult<(T, Option<SemverVersion>)>, UpdateTerminalError> {
+    let Some(version) = version else {
+        return StepWarning::new((sp_artifact, None), message).into();
+    };
+    match version.parse::<SemverVersion>() {
+        Ok(version) => StepSuccess::new((sp_artifact, Some(version)))
+            .with_message(message)
+            .into(),
+        Err(error) if fail_on_unparseable_version => {
+            Err(UpdateTerminalError::UnparseableSpVersion {
+                version,
+                error: error.into(),
+            })
+        }
+        Err(error) => StepWarning::new(
+            (sp_artifact, None),
+            format!("{message} (failed to parse SP version: {error})"),
+        )
+        .into(),
+    }
+}
+
+/// How often an update polls MGS.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct MgsPollIntervals {
+    /// How often we poll MGS for the progress of an SP component update once
+    /// it starts.
+    component_update_status: Duration,
+
+    /// How often we poll MGS for trampoline phase 2 progress while waiting for
+    /// installinator to start.
+    installinator_progress: Duration,
+}
+
+impl Default for MgsPollIntervals {
+    fn default() -> Self {
+        Self {
+            component_update_status: Duration::from_millis(300),
+            installinator_progress: Duration::from_secs(3),
+        }
+    }
+}
+
+impl MgsPollIntervals {
+    fn from_options(opts: &StartUpdateOptions) -> Self {
+        let default = Self::default();
+        let millis = |ms: Option<u64>, default: Duration| {
+            ms.filter(|&ms| ms > 0).map_or(default, Duration::from_millis)
+        };
+        Self {
+            component_update_status: millis(
+                opts.mgs_status_poll_interval_ms,
+                default.component_update_status,
+            ),
+            installinator_progress: millis(
+                opts.mgs_installinator_poll_interval_ms,
+                default.installinator_progress,
+            ),
+        }
+    }
+}
+
+/// Waits for the first progress report from installinator, driving
+/// `poll_progress` in the meantime.
+///
+/// Fails with [`UpdateTerminalError::InstallinatorStartTimeout`] if `timeout`
+/// is set and elapses first. With no timeout, this waits indefinitely.
+///
+/// There's no explicit abort branch here: if the installinator never starts,
+/// an operator abort cancels the update engine, which drops this future at its
+/// next await point and reports the step as aborted.
+async fn wait_for_installinator_start(
+    ipr_start_receiver: IprStartReceiver,
+    timeout: Option<Duration>,
+    poll_progress: impl Future<Output = Infallible>,
+) -> Result<watch::Receiver<EventReport<InstallinatorSpec>>, UpdateTerminalError>
+{
+    let deadline = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        receiver = ipr_start_receiver => {
+            // Received the first progress from the installinator.
+            receiver.map_err(|_| {
+                UpdateTerminalError::DownloadingInstallinatorFailed {
+                    error: anyhow!("start sender died"),
+                }
+            })
+        }
+        () = deadline => {
+            // `timeout` is always set if the deadline fires.
+            let timeout = timeout.unwrap_or_default();
+            Err(UpdateTerminalError::InstallinatorStartTimeout { timeout })
+        }
+        never = poll_progress => match never {},
+    }
+}
+
+/// Converts `progress` reported by the MGS at `mgs_addr` into step progress,
+/// if it's for the trampoline phase 2 image we uploaded.
+fn trampoline_phase2_step_progress(
+    progress: HostPhase2Progress,
+    uploaded_trampoline_phase2_id: &HostPhase2RecoveryImageId,
+    mgs_addr: SocketAddrV6,
+) -> Option<StepProgress> {
+    match progress {
+        HostPhase2Progress::Available {
+            image_id, offset, total_size, ..
+        } if &image_id == uploaded_trampoline_phase2_id => {
+            let metadata = TrampolinePhase2Progress { serving_mgs: mgs_addr };
+            Some(StepProgress::with_current_and_total(
+                offset,
+                total_size,
+                ProgressUnits::BYTES,
+                serde_json::to_value(&metadata)
+                    .expect("metadata is serializable"),
+            ))
+        }
+        HostPhase2Progress::Available { .. } | HostPhase2Progress::None => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum HostBootCheckpointState {
+    NotReached,
+    Waiting,
+    Proceed,
+}
+
+/// A checkpoint before booting the host at the end of a sled update, where
+/// the update waits for an operator to confirm that it should proceed.
+#[derive(Clone, Debug)]
+struct HostBootCheckpoint {
+    state: Arc<watch::Sender<HostBootCheckpointState>>,
+}
+
+impl HostBootCheckpoint {
+    fn new() -> Self {
+        let (state, _) = watch::channel(HostBootCheckpointState::NotReached);
+        Self { state: Arc::new(state) }
+    }
+
+    /// Marks the checkpoint as reached, then waits until [`Self::proceed`] is
+    /// called.
+    async fn wait(&self, cx: &StepContext) {
+        let mut receiver = self.state.subscribe();
+        self.state.send_replace(HostBootCheckpointState::Waiting);
+        cx.send_progress(StepProgress::with_current_and_total(
+            0,
+            1,
+            "confirmations",
+            serde_json::Value::Null,
+        ))
+        .await;
+
+        while *receiver.borrow_and_update() != HostBootCheckpointState::Proceed
+        {
+            // We hold the sender, so this can't fail.
+            _ = receiver.changed().await;
+        }
+    }
+
+    /// Returns true if an update is currently waiting at this checkpoint.
+    fn is_waiting(&self) -> bool {
+        *self.state.borrow() == HostBootCheckpointState::Waiting
+    }
+
+    /// Lets an update waiting at this checkpoint proceed.
+    ///
+    /// Returns false if the update isn't waiting at this checkpoint.
+    fn proceed(&self) -> bool {
+        self.state.send_if_modified(|state| {
+            if *state == HostBootCheckpointState::Waiting {
+                *state = HostBootCheckpointState::Proceed;
+                true
+            } else {
+                false
+            }
+        })
+    }
+}
+
 struct UpdateContext {
     update_id: Uuid,
     sp: SpIdentifier,
+    mgs_addr: SocketAddrV6,
     mgs_client: gateway_client::Client,
-    upload_trampoline_phase_2_to_mgs:
-        watch::Receiver<UploadTrampolinePhase2ToMgsStatus>,
+    // Held for as long as this update runs, which keeps the trampoline phase 2
+    // upload going until it's no longer needed.
+    upload_trampoline_phase_2_to_mgs: Arc<UploadTrampolinePhase2ToMgsInterest>,
+    poll_intervals: MgsPollIntervals,
+    installinator_start_timeout: Option<Duration>,
+    host_boot_checkpoint: Option<HostBootCheckpoint>,
+    verify_host_boot_slot: bool,
+    bootstrap_peers: Arc<BootstrapPeers>,
+    repository_check: RepositoryCheck,
+    metrics: UpdateMetrics,
     log: slog::Logger,
 }
 
@@ -1555,6 +2668,87 @@ impl UpdateContext {
         })
     }
 
+    /// Checks whether the RoT and SP already run the versions in the plan.
+    ///
+    /// Returns a message describing the versions if so, and `None` if not (or
+    /// if this update can't finish early at all; see
+    /// [`can_skip_when_up_to_date`]). Errors also return `None`: the full
+    /// update will interrogate the components again and report them.
+    async fn check_up_to_date(
+        &self,
+        rot_a: ArtifactIdData,
+        rot_b: ArtifactIdData,
+        sp_artifacts: &BTreeMap<Board, ArtifactIdData>,
+        opts: &StartUpdateOptions,
+    ) -> Option<String> {
+        if !can_skip_when_up_to_date(opts) {
+            return None;
+        }
+
+        let rot = self.interrogate_rot(rot_a, rot_b).await.ok()?.output;
+        let (sp_artifact, sp_version) = self
+            .interrogate_sp(
+                sp_artifacts,
+                SP_FIRMWARE_SLOT,
+                opts.fail_on_unparseable_sp_version,
+            )
+            .await
+            .ok()?
+            .output;
+        up_to_date_message(
+            &rot.artifact_to_apply.id.version,
+            rot.active_version.as_ref(),
+            &sp_artifact.id.version,
+            sp_version.as_ref(),
+        )
+    }
+
+    /// Reads the SP's board and the version in `sp_firmware_slot`, returning
+    /// the artifact in `sp_artifacts` for that board along with the version.
+    async fn interrogate_sp(
+        &self,
+        sp_artifacts: &BTreeMap<Board, ArtifactIdData>,
+        sp_firmware_slot: u16,
+        fail_on_unparseable_sp_version: bool,
+    ) -> Result<
+        StepResult<(ArtifactIdData, Option<SemverVersion>)>,
+        UpdateTerminalError,
+    > {
+        let caboose = self
+            .mgs_client
+            .sp_component_caboose_get(
+                self.sp.type_,
+                self.sp.slot,
+                SpComponent::SP_ITSELF.const_as_str(),
+                sp_firmware_slot,
+            )
+            .await
+            .map_err(|error| UpdateTerminalError::GetSpCabooseFailed {
+                error: mgs_error(error),
+            })?
+            .into_inner();
+
+        let Some(sp_artifact) = sp_artifacts.get(&caboose.board) else {
+            return Err(UpdateTerminalError::MissingSpImageForBoard {
+                board: caboose.board,
+            });
+        };
+        let sp_artifact = sp_artifact.clone();
+
+        let message = format!(
+            "SP board {}, version {} (git commit {})",
+            caboose.board,
+            caboose.version.as_deref().unwrap_or("unknown"),
+            caboose.git_commit
+        );
+        sp_interrogation_result(
+            sp_artifact,
+            caboose.version,
+            message,
+            fail_on_unparseable_sp_version,
+        )
+    }
+
     async fn interrogate_rot(
         &self,
         rot_a: ArtifactIdData,
@@ -1593,7 +2787,7 @@ impl UpdateContext {
             )
             .await
             .map_err(|error| UpdateTerminalError::GetRotCabooseFailed {
-                error,
+                error: mgs_error(error),
             })?
             .into_inner();
 
@@ -1655,14 +2849,105 @@ impl UpdateContext {
         }
     }
 
+    /// Poll the sled's bootstrap agent asking which boot flash slot the host
+    /// booted from, allowing failures up to a fixed timeout to give time for
+    /// it to boot.
+    ///
+    /// Intended to be called after the host has been powered on. We don't ask
+    /// the SP: its active slot is the one we told it to boot, which says
+    /// nothing about whether the host fell back to the other slot.
+    async fn wait_for_host_boot(
+        &self,
+        timeout: Duration,
+    ) -> anyhow::Result<u16> {
+        // The host takes minutes to boot, so only warn about failures once a
+        // minute rather than on every attempt.
+        const WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+        let baseboard = self.get_sled_baseboard().await?;
+        let mut ticker = tokio::time::interval(Duration::from_secs(5));
+        let mut last_error = None;
+
+        // Bound the whole wait, including any request that hangs rather than
+        // failing.
+        let poll = async {
+            let mut last_warning: Option<Instant> = None;
+            loop {
+                ticker.tick().await;
+                let result = match self.bootstrap_peers.sleds().get(&baseboard)
+                {
+                    Some(ip) => {
+                        let addr = SocketAddrV6::new(
+                            *ip,
+                            BOOTSTRAP_AGENT_HTTP_PORT,
+                            0,
+                            0,
+                        );
+                        let bootstrap_agent =
+                            bootstrap_agent_client::Client::new(
+                                &format!("http://{addr}"),
+                                self.log.clone(),
+                            );
+                        get_host_booted_slot(&bootstrap_agent).await
+                    }
+                    None => Err(anyhow!(
+                        "bootstrap agent not yet found for {baseboard:?}"
+                    )),
+                };
+                match result {
+                    Ok(slot) => return slot,
+                    Err(error) => {
+                        if last_warning.map_or(true, |last_warning| {
+                            last_warning.elapsed() >= WARNING_INTERVAL
+                        }) {
+                            warn!(
+                                self.log,
+                                "failed getting host boot slot (will retry)";
+                                "error" => %error,
+                            );
+                            last_warning = Some(Instant::now());
+                        }
+                        last_error = Some(error);
+                    }
+                }
+            }
+        };
+
+        match tokio::time::timeout(timeout, poll).await {
+            Ok(slot) => Ok(slot),
+            Err(_) => Err(last_error
+                .unwrap_or_else(|| anyhow!("no response from bootstrap agent"))
+                .context(format!(
+                    "host did not report its boot slot within {timeout:?}"
+                ))),
+        }
+    }
+
+    /// Returns the baseboard of the sled we're updating, which identifies its
+    /// bootstrap agent.
+    async fn get_sled_baseboard(&self) -> anyhow::Result<Baseboard> {
+        let state = self
+            .mgs_client
+            .sp_get(self.sp.type_, self.sp.slot)
+            .await
+            .context("failed to get SP state")?
+            .into_inner();
+        Ok(Baseboard::new_gimlet(
+            state.serial_number,
+            state.model,
+            state.revision.into(),
+        ))
+    }
+
     async fn wait_for_first_installinator_progress(
         &self,
         cx: &StepContext,
-        mut ipr_start_receiver: IprStartReceiver,
+        ipr_start_receiver: IprStartReceiver,
         image_id: HostPhase2RecoveryImageId,
-    ) -> anyhow::Result<watch::Receiver<EventReport<InstallinatorSpec>>> {
-        const MGS_PROGRESS_POLL_INTERVAL: Duration = Duration::from_secs(3);
-
+    ) -> Result<
+        watch::Receiver<EventReport<InstallinatorSpec>>,
+        UpdateTerminalError,
+    > {
         // Waiting for the installinator to start is a little strange. It can't
         // start until the host boots, which requires all the normal boot things
         // (DRAM training, etc.), but also fetching the trampoline phase 2 image
@@ -1688,7 +2973,8 @@ impl UpdateContext {
         //
         // Throughout this function, we do not fail if a request to MGS fails -
         // these are all "best effort" progress; our real failure mode is if
-        // installinator tells us it has failed.
+        // installinator tells us it has failed, or if it doesn't start within
+        // `installinator_start_timeout` (when one is set).
         if let Err(err) = self
             .mgs_client
             .sp_host_phase2_progress_delete(self.sp.type_, self.sp.slot)
@@ -1700,21 +2986,31 @@ impl UpdateContext {
             );
         }
 
-        let mut interval = tokio::time::interval(MGS_PROGRESS_POLL_INTERVAL);
-        interval
-            .set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
-
-        loop {
-            tokio::select! {
-                receiver = &mut ipr_start_receiver => {
-                    // Received the first progress from the installinator.
-                    break receiver.context("start sender died");
-                }
-                _ = interval.tick() => {
-                    self.poll_trampoline_phase2_progress(cx, &image_id).await;
-                }
+        let poll_progress = async {
+            let mut interval = tokio::time::interval(
+                self.poll_intervals.installinator_progress,
+            );
+            interval.set_missed_tick_behavior(
+                tokio::time::MissedTickBehavior::Delay,
+            );
+            let mut logged_serving_mgs = false;
+            loop {
+                interval.tick().await;
+                self.poll_trampoline_phase2_progress(
+                    cx,
+                    &image_id,
+                    &mut logged_serving_mgs,
+                )
+                .await;
             }
-        }
+        };
+
+        wait_for_installinator_start(
+            ipr_start_receiver,
+            self.installinator_start_timeout,
+            poll_progress,
+        )
+        .await
     }
 
     /// Polls MGS for the latest trampoline phase 2 progress.
@@ -1723,10 +3019,15 @@ impl UpdateContext {
     /// phase 2 is present within all phase 1 ROMs, both host and trampoline.
     /// This is why the API has the name "host phase 2" in it. However, for this
     /// update flow it is only activated for trampoline images.
+    ///
+    /// The SP may fetch its phase 2 image from either scrimlet's MGS, and we
+    /// only see progress if it's fetching from ours. When we do, we log (once,
+    /// tracked by `logged_serving_mgs`) and report which MGS is serving it.
     async fn poll_trampoline_phase2_progress(
         &self,
         cx: &StepContext,
         uploaded_trampoline_phase2_id: &HostPhase2RecoveryImageId,
+        logged_serving_mgs: &mut bool,
     ) {
         match self
             .mgs_client
@@ -1734,24 +3035,25 @@ impl UpdateContext {
             .await
             .map(|response| response.into_inner())
         {
-            Ok(HostPhase2Progress::Available {
-                image_id,
-                offset,
-                total_size,
-                ..
-            }) => {
+            Ok(progress @ HostPhase2Progress::Available { .. }) => {
                 // Does this image ID match the one we uploaded? If so,
                 // record our current progress; if not, this is probably
                 // stale data from a past update, and we have no progress
                 // information.
-                if &image_id == uploaded_trampoline_phase2_id {
-                    cx.send_progress(StepProgress::with_current_and_total(
-                        offset,
-                        total_size,
-                        ProgressUnits::BYTES,
-                        Default::default(),
-                    ))
-                    .await;
+                if let Some(progress) = trampoline_phase2_step_progress(
+                    progress,
+                    uploaded_trampoline_phase2_id,
+                    self.mgs_addr,
+                ) {
+                    if !*logged_serving_mgs {
+                        info!(
+                            self.log,
+                            "SP is fetching trampoline phase 2 from our MGS";
+                            "mgs_addr" => %self.mgs_addr,
+                        );
+                        *logged_serving_mgs = true;
+                    }
+                    cx.send_progress(progress).await;
                 }
             }
             Ok(HostPhase2Progress::None) => {
@@ -1777,7 +3079,7 @@ impl UpdateContext {
             .await
             .map(|response| response.into_inner())
             .map_err(|error| UpdateTerminalError::UpdatePowerStateFailed {
-                error,
+                error: mgs_error(error),
             })?;
         StepSuccess::new(()).into()
     }
@@ -1824,7 +3126,7 @@ impl UpdateContext {
             .map(|res| res.into_inner())
     }
 
-    async fn poll_component_update<S: StepSpec>(
+    async fn poll_component_update<S>(
         &self,
         cx: StepContext<S>,
         stage: ComponentUpdateStage,
@@ -1832,97 +3134,172 @@ impl UpdateContext {
         component: &str,
     ) -> anyhow::Result<()>
     where
-        S::ProgressMetadata: Default,
+        S: StepSpec<ProgressMetadata = serde_json::Value>,
     {
-        // How often we poll MGS for the progress of an update once it starts.
-        const STATUS_POLL_FREQ: Duration = Duration::from_millis(300);
-
-        loop {
-            let status = self
-                .mgs_client
-                .sp_component_update_status(
-                    self.sp.type_,
-                    self.sp.slot,
-                    component,
-                )
-                .await?
-                .into_inner();
+        let mgs_client = &self.mgs_client;
+        let sp = self.sp;
+        poll_component_update_status(
+            &self.log,
+            cx,
+            stage,
+            update_id,
+            self.poll_intervals.component_update_status,
+            || async move {
+                let status = mgs_client
+                    .sp_component_update_status(sp.type_, sp.slot, component)
+                    .await?
+                    .into_inner();
+                Ok(status)
+            },
+        )
+        .await
+    }
+}
 
-            match status {
-                SpUpdateStatus::None => {
-                    bail!("SP no longer processing update (did it reset?)")
-                }
-                SpUpdateStatus::Preparing { id, progress } => {
-                    ensure!(id == update_id, "SP processing different update");
-                    if stage == ComponentUpdateStage::Preparing {
-                        if let Some(progress) = progress {
-                            cx.send_progress(
-                                StepProgress::with_current_and_total(
-                                    progress.current as u64,
-                                    progress.total as u64,
-                                    // The actual units here depend on the
-                                    // component being updated and are a bit
-                                    // hard to explain succinctly:
-                                    // https://github.com/oxidecomputer/omicron/pull/3267#discussion_r1229700370
-                                    ProgressUnits::new("preparation steps"),
-                                    Default::default(),
-                                ),
-                            )
-                            .await;
-                        }
-                    } else {
-                        warn!(
-                            self.log,
-                            "component update moved backwards \
-                             from {stage:?} to preparing"
-                        );
+/// Polls the status of an SP component update via `fetch_status` every
+/// `period` until it leaves `stage`, reporting progress along the way.
+async fn poll_component_update_status<S, F, Fut>(
+    log: &slog::Logger,
+    cx: StepContext<S>,
+    stage: ComponentUpdateStage,
+    update_id: Uuid,
+    period: Duration,
+    mut fetch_status: F,
+) -> anyhow::Result<()>
+where
+    S: StepSpec<ProgressMetadata = serde_json::Value>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<SpUpdateStatus>>,
+{
+    let mut rate_estimator = WriteRateEstimator::new();
+
+    loop {
+        let status = fetch_status().await?;
+
+        match status {
+            SpUpdateStatus::None => {
+                bail!("SP no longer processing update (did it reset?)")
+            }
+            SpUpdateStatus::Preparing { id, progress } => {
+                ensure!(id == update_id, "SP processing different update");
+                if stage == ComponentUpdateStage::Preparing {
+                    if let Some(progress) = progress {
+                        cx.send_progress(StepProgress::with_current_and_total(
+                            progress.current as u64,
+                            progress.total as u64,
+                            // The actual units here depend on the
+                            // component being updated and are a bit
+                            // hard to explain succinctly:
+                            // https://github.com/oxidecomputer/omicron/pull/3267#discussion_r1229700370
+                            ProgressUnits::new("preparation steps"),
+                            Default::default(),
+                        ))
+                        .await;
                     }
+                } else {
+                    warn!(
+                        log,
+                        "component update moved backwards \
+                         from {stage:?} to preparing"
+                    );
                 }
-                SpUpdateStatus::InProgress {
-                    bytes_received,
-                    id,
-                    total_bytes,
-                } => {
-                    ensure!(id == update_id, "SP processing different update");
-                    match stage {
-                        ComponentUpdateStage::Preparing => {
-                            // The prepare step is done -- exit this loop and move
-                            // to the next stage.
-                            return Ok(());
-                        }
-                        ComponentUpdateStage::InProgress => {
-                            cx.send_progress(
-                                StepProgress::with_current_and_total(
-                                    bytes_received as u64,
-                                    total_bytes as u64,
-                                    ProgressUnits::BYTES,
-                                    Default::default(),
-                                ),
-                            )
-                            .await;
-                        }
+            }
+            SpUpdateStatus::InProgress { bytes_received, id, total_bytes } => {
+                ensure!(id == update_id, "SP processing different update");
+                match stage {
+                    ComponentUpdateStage::Preparing => {
+                        // The prepare step is done -- exit this loop and move
+                        // to the next stage.
+                        return Ok(());
+                    }
+                    ComponentUpdateStage::InProgress => {
+                        let eta = rate_estimator.record(
+                            Instant::now(),
+                            bytes_received as u64,
+                            total_bytes as u64,
+                        );
+                        let metadata = SpComponentWriteProgress {
+                            estimated_seconds_remaining: eta
+                                .map(|eta| eta.as_secs()),
+                        };
+                        cx.send_progress(StepProgress::with_current_and_total(
+                            bytes_received as u64,
+                            total_bytes as u64,
+                            ProgressUnits::BYTES,
+                            serde_json::to_value(&metadata)
+                                .expect("metadata is serializable"),
+                        ))
+                        .await;
                     }
                 }
-                SpUpdateStatus::Complete { id } => {
-                    ensure!(id == update_id, "SP processing different update");
-                    return Ok(());
-                }
-                SpUpdateStatus::Aborted { id } => {
-                    ensure!(id == update_id, "SP processing different update");
-                    bail!("update aborted");
-                }
-                SpUpdateStatus::Failed { code, id } => {
-                    ensure!(id == update_id, "SP processing different update");
-                    bail!("update failed (error code {code})");
-                }
-                SpUpdateStatus::RotError { message, id } => {
-                    ensure!(id == update_id, "SP processing different update");
-                    bail!("update failed (rot error message {message})");
-                }
             }
+            SpUpdateStatus::Complete { id } => {
+                ensure!(id == update_id, "SP processing different update");
+                return Ok(());
+            }
+            SpUpdateStatus::Aborted { id } => {
+                ensure!(id == update_id, "SP processing different update");
+                bail!("update aborted");
+            }
+            SpUpdateStatus::Failed { code, id } => {
+                ensure!(id == update_id, "SP processing different update");
+                bail!("update failed (error code {code})");
+            }
+            SpUpdateStatus::RotError { message, id } => {
+                ensure!(id == update_id, "SP processing different update");
+                bail!("update failed (rot error message {message})");
+            }
+        }
+
+        tokio::time::sleep(period).await;
+    }
+}
+
+/// Estimates the time remaining for an SP component write from a moving
+/// average of the write rate over the last few polls.
+#[derive(Debug)]
+struct WriteRateEstimator {
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl WriteRateEstimator {
+    /// The number of samples the moving average is computed over.
+    const WINDOW: usize = 8;
+
+    fn new() -> Self {
+        Self { samples: VecDeque::with_capacity(Self::WINDOW) }
+    }
+
+    /// Records that `bytes_received` out of `total_bytes` had been written at
+    /// `now`, and returns the estimated time remaining if it can be computed.
+    fn record(
+        &mut self,
+        now: Instant,
+        bytes_received: u64,
+        total_bytes: u64,
+    ) -> Option<Duration> {
+        // If progress moved backwards, the old samples no longer describe
+        // this write; start over.
+        if let Some(&(_, last_bytes)) = self.samples.back() {
+            if bytes_received < last_bytes {
+                self.samples.clear();
+            }
+        }
+        if self.samples.len() == Self::WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((now, bytes_received));
 
-            tokio::time::sleep(STATUS_POLL_FREQ).await;
+        let &(first_time, first_bytes) = self.samples.front()?;
+        let elapsed = now.saturating_duration_since(first_time).as_secs_f64();
+        let bytes = bytes_received - first_bytes;
+        if elapsed <= 0.0 || bytes == 0 {
+            return None;
         }
+
+        let bytes_per_sec = bytes as f64 / elapsed;
+        let remaining = total_bytes.saturating_sub(bytes_received);
+        Some(Duration::from_secs_f64(remaining as f64 / bytes_per_sec))
     }
 }
 
@@ -1935,6 +3312,7 @@ enum ComponentUpdateStage {
 async fn upload_trampoline_phase_2_to_mgs(
     mgs_client: gateway_client::Client,
     artifact: ArtifactIdData,
+    max_attempts: Option<NonZeroU32>,
     status: watch::Sender<UploadTrampolinePhase2ToMgsStatus>,
     log: Logger,
 ) {
@@ -1959,11 +3337,74 @@ async fn upload_trampoline_phase_2_to_mgs(
                     image_stream,
                 ))
                 .await
-                .map_err(|e| backoff::BackoffError::transient(e.to_string()))
+                .map(|response| response.into_inner())
+                .map_err(|e| {
+                    backoff::BackoffError::transient(mgs_error(e).to_string())
+                })
+        }
+    };
+
+    let state =
+        match retry_trampoline_phase_2_upload(upload_task, max_attempts, &log)
+            .await
+        {
+            Ok(uploaded_image_id) => {
+                UploadTrampolinePhase2ToMgsState::Uploaded(uploaded_image_id)
+            }
+            Err(err) => {
+                error!(
+                    log,
+                    "giving up uploading trampoline phase 2 to MGS";
+                    "err" => %err,
+                );
+                UploadTrampolinePhase2ToMgsState::Failed
+            }
+        };
+
+    // Notify all receivers that we've uploaded the image (or given up).
+    _ = status.send(UploadTrampolinePhase2ToMgsStatus { hash, state });
+
+    // Wait for all receivers to be gone before we exit, so they don't get recv
+    // errors unless we're cancelled.
+    status.closed().await;
+}
+
+/// Retries `upload_task` until it succeeds, or until it has been attempted
+/// `max_attempts` times.
+///
+/// With no `max_attempts`, this retries forever and never returns an error.
+async fn retry_trampoline_phase_2_upload<F, Fut>(
+    mut upload_task: F,
+    max_attempts: Option<NonZeroU32>,
+    log: &Logger,
+) -> Result<HostPhase2RecoveryImageId, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<
+        Output = Result<
+            HostPhase2RecoveryImageId,
+            backoff::BackoffError<String>,
+        >,
+    >,
+{
+    let mut attempts = 0;
+    let bounded_task = || {
+        attempts += 1;
+        let exhausted = max_attempts.map_or(false, |max| attempts >= max.get());
+        let fut = upload_task();
+        async move {
+            fut.await.map_err(|err| match err {
+                backoff::BackoffError::Transient { err, .. } if exhausted => {
+                    backoff::BackoffError::permanent(format!(
+                        "{err} (after {attempts} attempts)"
+                    ))
+                }
+                err => err,
+            })
         }
     };
 
-    let log_failure = move |err, delay| {
+    let log_failure = |err, delay| {
         warn!(
             log,
             "failed to upload trampoline phase 2 to MGS, will retry in {:?}",
@@ -1972,26 +3413,15 @@ async fn upload_trampoline_phase_2_to_mgs(
         );
     };
 
-    // retry_policy_internal_service_aggressive() retries forever, so we can
-    // unwrap this call to retry_notify
-    let uploaded_image_id = backoff::retry_notify(
+    // Without `max_attempts`, retry_policy_internal_service_aggressive()
+    // retries forever, so this only returns an error once we've run out of
+    // attempts.
+    backoff::retry_notify(
         backoff::retry_policy_internal_service_aggressive(),
-        upload_task,
+        bounded_task,
         log_failure,
     )
     .await
-    .unwrap()
-    .into_inner();
-
-    // Notify all receivers that we've uploaded the image.
-    _ = status.send(UploadTrampolinePhase2ToMgsStatus {
-        hash,
-        uploaded_image_id: Some(uploaded_image_id),
-    });
-
-    // Wait for all receivers to be gone before we exit, so they don't get recv
-    // errors unless we're cancelled.
-    status.closed().await;
 }
 
 struct SpComponentUpdateContext<'a> {
@@ -2028,10 +3458,10 @@ impl<'a> SpComponentUpdateContext<'a> {
             .new_step(
                 SpComponentUpdateStepId::Sending,
                 format!("Sending data to MGS (slot {firmware_slot})"),
-                move |_cx| async move {
-                    let data_stream = artifact
+                move |cx| async move {
+                    let (total_size, data_stream) = artifact
                         .data
-                        .reader_stream()
+                        .reader_stream_with_size()
                         .await
                         .map_err(|error| {
                             SpComponentUpdateTerminalError::SpComponentUpdateFailed {
@@ -2041,8 +3471,16 @@ impl<'a> SpComponentUpdateContext<'a> {
                             }
                         })?;
 
-                    // TODO: we should be able to report some sort of progress
-                    // here for the file upload.
+                    // TODO: we should be able to report progress as the upload
+                    // proceeds; for now we can only report the total size.
+                    cx.send_progress(StepProgress::with_current_and_total(
+                        0,
+                        total_size,
+                        ProgressUnits::BYTES,
+                        Default::default(),
+                    ))
+                    .await;
+
                     update_cx
                         .mgs_client
                         .sp_component_update(
@@ -2058,7 +3496,7 @@ impl<'a> SpComponentUpdateContext<'a> {
                             SpComponentUpdateTerminalError::SpComponentUpdateFailed {
                                 stage: SpComponentUpdateStage::Sending,
                                 artifact: artifact.id.clone(),
-                                error: anyhow!(error),
+                                error: mgs_error(error),
                             }
                         })?;
 
@@ -2228,3 +3666,904 @@ impl<'a> SpComponentUpdateContext<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpInventory;
+    use wicket_common::update_events::StepEventKind;
+    use wicket_common::update_events::StepOutcome;
+
+    fn start_update_options(
+        mgs_status_poll_interval_ms: Option<u64>,
+        mgs_installinator_poll_interval_ms: Option<u64>,
+    ) -> StartUpdateOptions {
+        StartUpdateOptions {
+            test_error: None,
+            test_step_seconds: None,
+            test_simulate_rot_result: None,
+            test_simulate_sp_result: None,
+            skip_rot_version_check: false,
+            skip_sp_version_check: false,
+            fail_on_unparseable_sp_version: false,
+            pause_before_host_boot: false,
+            verify_host_boot_slot: false,
+            installinator_start_timeout_secs: None,
+            mgs_status_poll_interval_ms,
+            mgs_installinator_poll_interval_ms,
+            trampoline_phase_2_upload_max_attempts: None,
+            host_only: false,
+            update_groups: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn mgs_poll_intervals_from_options() {
+        // Unset and zero values use the defaults.
+        assert_eq!(
+            MgsPollIntervals::from_options(&start_update_options(None, None)),
+            MgsPollIntervals::default(),
+        );
+        assert_eq!(
+            MgsPollIntervals::from_options(&start_update_options(
+                Some(0),
+                Some(0)
+            )),
+            MgsPollIntervals::default(),
+        );
+
+        assert_eq!(
+            MgsPollIntervals::from_options(&start_update_options(
+                Some(1000),
+                Some(10_000)
+            )),
+            MgsPollIntervals {
+                component_update_status: Duration::from_secs(1),
+                installinator_progress: Duration::from_secs(10),
+            },
+        );
+    }
+
+    /// Runs `poll_component_update_status` for an update that's still
+    /// preparing for `preparing_polls` polls before completing, returning the
+    /// time of each poll.
+    async fn component_update_poll_times(
+        period: Duration,
+        preparing_polls: usize,
+    ) -> Vec<tokio::time::Instant> {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let (sender, _receiver) = mpsc::channel(128);
+        let engine = UpdateEngine::new(&log, sender);
+        let update_id = Uuid::new_v4();
+        let poll_times = Arc::new(StdMutex::new(Vec::new()));
+        let poll_times_2 = poll_times.clone();
+
+        engine
+            .new_step(
+                UpdateComponent::Sp,
+                UpdateStepId::SpComponentUpdate,
+                "Polling component update status",
+                move |cx| async move {
+                    poll_component_update_status(
+                        &log,
+                        cx,
+                        ComponentUpdateStage::Preparing,
+                        update_id,
+                        period,
+                        || {
+                            let mut poll_times = poll_times_2.lock().unwrap();
+                            poll_times.push(tokio::time::Instant::now());
+                            let status = if poll_times.len() > preparing_polls {
+                                SpUpdateStatus::Complete { id: update_id }
+                            } else {
+                                SpUpdateStatus::Preparing {
+                                    id: update_id,
+                                    progress: None,
+                                }
+                            };
+                            async move { Ok(status) }
+                        },
+                    )
+                    .await?;
+                    StepSuccess::new(()).into()
+                },
+            )
+            .register();
+        engine.execute().await.expect("engine execution succeeded");
+
+        let poll_times = poll_times.lock().unwrap();
+        poll_times.clone()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn component_update_polls_at_configured_interval() {
+        for period in [
+            MgsPollIntervals::default().component_update_status,
+            Duration::from_millis(1500),
+        ] {
+            let start = tokio::time::Instant::now();
+            let poll_times = component_update_poll_times(period, 4).await;
+
+            // The first poll is immediate, and each later one waits `period`
+            // after the previous poll completes.
+            let expected: Vec<_> =
+                (0..5u32).map(|i| start + period * i).collect();
+            assert_eq!(poll_times, expected, "period {period:?}");
+        }
+    }
+
+    #[test]
+    fn sequence_update_groups_appends_ungrouped_targets() {
+        let sled = |slot| SpIdentifier { slot, type_: SpType::Sled };
+        let switch = |slot| SpIdentifier { slot, type_: SpType::Switch };
+
+        let sps: BTreeSet<_> =
+            [switch(0), switch(1), sled(0), sled(1), sled(2)].into();
+
+        // With no groups, everything is updated at once.
+        assert_eq!(sequence_update_groups(&sps, &[]), vec![sps.clone()]);
+
+        // Empty groups are skipped, and anything not in a group goes last.
+        let update_groups = [
+            BTreeSet::from([switch(0), switch(1)]),
+            BTreeSet::new(),
+            BTreeSet::from([sled(2)]),
+        ];
+        assert_eq!(
+            sequence_update_groups(&sps, &update_groups),
+            vec![
+                BTreeSet::from([switch(0), switch(1)]),
+                BTreeSet::from([sled(2)]),
+                BTreeSet::from([sled(0), sled(1)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn trampoline_phase2_progress_reports_serving_mgs() {
+        let mgs_addr: SocketAddrV6 =
+            "[fd00:1122:3344:101::2]:12225".parse().unwrap();
+        let uploaded =
+            HostPhase2RecoveryImageId { sha256_hash: "ab".repeat(32) };
+        let available = |image_id: &HostPhase2RecoveryImageId| {
+            HostPhase2Progress::Available {
+                age: gateway_client::types::Duration { secs: 1, nanos: 0 },
+                image_id: image_id.clone(),
+                offset: 1024,
+                total_size: 4096,
+            }
+        };
+
+        let progress = trampoline_phase2_step_progress(
+            available(&uploaded),
+            &uploaded,
+            mgs_addr,
+        )
+        .expect("progress reported for our image");
+        match progress {
+            StepProgress::Progress { progress, metadata } => {
+                let progress = progress.expect("progress counter present");
+                assert_eq!(progress.current, 1024);
+                assert_eq!(progress.total, Some(4096));
+                let metadata: TrampolinePhase2Progress =
+                    serde_json::from_value(metadata).unwrap();
+                assert_eq!(metadata.serving_mgs, mgs_addr);
+            }
+            other => panic!("unexpected progress: {other:?}"),
+        }
+
+        // Progress for some other image (e.g., from a past update) isn't ours.
+        let other = HostPhase2RecoveryImageId { sha256_hash: "cd".repeat(32) };
+        assert!(trampoline_phase2_step_progress(
+            available(&other),
+            &uploaded,
+            mgs_addr
+        )
+        .is_none());
+        assert!(trampoline_phase2_step_progress(
+            HostPhase2Progress::None,
+            &uploaded,
+            mgs_addr
+        )
+        .is_none());
+    }
+
+    #[derive(serde::Serialize, schemars::JsonSchema)]
+    struct FakeHostBootSlot {
+        slot: u16,
+    }
+
+    #[dropshot::endpoint {
+        method = GET,
+        path = "/host-boot-slot",
+    }]
+    async fn fake_host_boot_slot_get(
+        rqctx: dropshot::RequestContext<u16>,
+    ) -> Result<dropshot::HttpResponseOk<FakeHostBootSlot>, HttpError> {
+        Ok(dropshot::HttpResponseOk(FakeHostBootSlot {
+            slot: *rqctx.context(),
+        }))
+    }
+
+    // Start a stand-in for the bootstrap agent of a sled whose host booted
+    // from `booted_slot`.
+    fn start_fake_bootstrap_agent(
+        log: &Logger,
+        booted_slot: u16,
+    ) -> dropshot::HttpServer<u16> {
+        let mut api = dropshot::ApiDescription::new();
+        api.register(fake_host_boot_slot_get).unwrap();
+        let config = dropshot::ConfigDropshot {
+            bind_address: "[::1]:0".parse().unwrap(),
+            ..Default::default()
+        };
+        dropshot::HttpServerStarter::new(&config, api, booted_slot, log)
+            .unwrap()
+            .start()
+    }
+
+    #[tokio::test]
+    async fn host_boot_slot_is_checked_against_the_booted_slot() {
+        let logctx = omicron_test_utils::dev::test_setup_log(
+            "host_boot_slot_is_checked_against_the_booted_slot",
+        );
+
+        // The SP was told to boot slot 1, but the host fell back to slot 0.
+        let server = start_fake_bootstrap_agent(&logctx.log, 0);
+        let bootstrap_agent = bootstrap_agent_client::Client::new(
+            &format!("http://{}", server.local_addr()),
+            logctx.log.clone(),
+        );
+        let booted_slot = get_host_booted_slot(&bootstrap_agent).await.unwrap();
+        assert_eq!(booted_slot, 0);
+        match check_host_boot_slot(1, booted_slot) {
+            Err(UpdateTerminalError::HostUnexpectedBootSlot {
+                expected_slot,
+                booted_slot,
+            }) => {
+                assert_eq!(expected_slot, 1);
+                assert_eq!(booted_slot, 0);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        // Had it been told to boot slot 0, verification would pass.
+        check_host_boot_slot(0, booted_slot).unwrap();
+
+        server.close().await.unwrap();
+        logctx.cleanup_successful();
+    }
+
+    #[test]
+    fn installinator_slots_map_to_host_boot_slots() {
+        let write_output = WriteOutput {
+            slots_attempted: [M2Slot::A, M2Slot::B].into_iter().collect(),
+            slots_written: [M2Slot::B].into_iter().collect(),
+        };
+        assert_eq!(
+            host_boot_slots_written(&write_output).unwrap(),
+            [1].into_iter().collect::<BTreeSet<u16>>(),
+        );
+
+        // Writing no slots leaves nothing to boot from.
+        let write_output = WriteOutput {
+            slots_attempted: [M2Slot::A, M2Slot::B].into_iter().collect(),
+            slots_written: BTreeSet::new(),
+        };
+        match host_boot_slots_written(&write_output) {
+            Err(UpdateTerminalError::InvalidInstallinatorSlots {
+                slots_attempted,
+                slots_written,
+            }) => {
+                assert_eq!(slots_attempted, ["A", "B"]);
+                assert!(slots_written.is_empty());
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+
+        // A slot that wasn't attempted is also unexpected.
+        let write_output = WriteOutput {
+            slots_attempted: [M2Slot::A].into_iter().collect(),
+            slots_written: [M2Slot::A, M2Slot::B].into_iter().collect(),
+        };
+        assert!(matches!(
+            host_boot_slots_written(&write_output),
+            Err(UpdateTerminalError::InvalidInstallinatorSlots { .. })
+        ));
+    }
+
+    #[test]
+    fn update_state_summary_from_execution_status() {
+        let step_key = update_engine::StepKey {
+            execution_id: update_engine::ExecutionId(Uuid::new_v4()),
+            index: 0,
+        };
+
+        // Until the task finishes, the update is running regardless of the
+        // engine's status.
+        for status in [
+            None,
+            Some(ExecutionStatus::Running { step_key }),
+            Some(ExecutionStatus::Completed { step_key }),
+        ] {
+            assert_eq!(
+                update_state_summary(false, status),
+                SpUpdateStateSummary::Running,
+                "status: {status:?}",
+            );
+        }
+
+        assert_eq!(
+            update_state_summary(
+                true,
+                Some(ExecutionStatus::Completed { step_key })
+            ),
+            SpUpdateStateSummary::Succeeded,
+        );
+        assert_eq!(
+            update_state_summary(
+                true,
+                Some(ExecutionStatus::Aborted { step_key })
+            ),
+            SpUpdateStateSummary::Aborted,
+        );
+
+        // A failed engine, or a task that finished without the engine
+        // finishing, is a failure.
+        for status in [
+            None,
+            Some(ExecutionStatus::NotStarted),
+            Some(ExecutionStatus::Running { step_key }),
+            Some(ExecutionStatus::Failed { step_key }),
+        ] {
+            assert_eq!(
+                update_state_summary(true, status),
+                SpUpdateStateSummary::Failed,
+                "status: {status:?}",
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn quiet_update_is_possibly_stuck() {
+        const THRESHOLD: Duration = Duration::from_secs(60);
+
+        let log = slog::Logger::root(slog::Discard, o!());
+        let (sender, _receiver) = mpsc::channel(128);
+        let engine = UpdateEngine::new(&log, sender);
+
+        // A running update that never produces any events.
+        let (previous_group_sender, previous_group) = watch::channel(());
+        let mut update_data = SpUpdateData {
+            task: tokio::spawn(std::future::pending()),
+            abort_handle: engine.abort_handle(),
+            event_buffer: Arc::new(StdMutex::new(EventBuffer::new(16))),
+            activity: UpdateActivity::new(),
+            previous_group: Some(previous_group),
+            host_boot_checkpoint: Some(HostBootCheckpoint::new()),
+        };
+
+        // While waiting for the previous update group, the update isn't stuck
+        // no matter how long it's quiet.
+        tokio::time::sleep(2 * THRESHOLD).await;
+        assert!(!update_data.is_possibly_stuck(THRESHOLD));
+
+        // Once the previous group finishes, the update is stuck until it
+        // produces another event.
+        drop(previous_group_sender);
+        assert!(update_data.is_possibly_stuck(THRESHOLD));
+        update_data.activity.record_event();
+        assert!(!update_data.is_possibly_stuck(THRESHOLD));
+
+        // It's not stuck until it's been quiet for the full threshold.
+        tokio::time::sleep(THRESHOLD - Duration::from_secs(1)).await;
+        assert!(!update_data.is_possibly_stuck(THRESHOLD));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(update_data.is_possibly_stuck(THRESHOLD));
+
+        // Waiting for an operator to confirm booting the host isn't stuck
+        // either.
+        let checkpoint = update_data.host_boot_checkpoint.as_ref().unwrap();
+        checkpoint.state.send_replace(HostBootCheckpointState::Waiting);
+        assert!(!update_data.is_possibly_stuck(THRESHOLD));
+        assert!(checkpoint.proceed());
+        assert!(update_data.is_possibly_stuck(THRESHOLD));
+
+        // A finished update is never stuck.
+        update_data.task.abort();
+        _ = (&mut update_data.task).await;
+        assert!(!update_data.is_possibly_stuck(THRESHOLD));
+    }
+
+    #[tokio::test]
+    async fn trampoline_phase_2_upload_gives_up_after_max_attempts() {
+        let log = slog::Logger::root(slog::Discard, o!());
+
+        // An upload that always fails.
+        let mut attempts = 0;
+        let upload_task = || {
+            attempts += 1;
+            async {
+                Err::<HostPhase2RecoveryImageId, _>(
+                    backoff::BackoffError::transient("MGS is down".to_owned()),
+                )
+            }
+        };
+
+        let err = retry_trampoline_phase_2_upload(
+            upload_task,
+            NonZeroU32::new(3),
+            &log,
+        )
+        .await
+        .expect_err("upload gave up");
+        assert_eq!(attempts, 3);
+        assert!(err.contains("MGS is down (after 3 attempts)"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn trampoline_phase_2_upload_cancelled_once_unneeded() {
+        let log = slog::Logger::root(slog::Discard, o!());
+
+        // An upload that never finishes, as if MGS were unreachable.
+        let (status_tx, status) =
+            watch::channel(UploadTrampolinePhase2ToMgsStatus {
+                hash: ArtifactHash([0; 32]),
+                state: UploadTrampolinePhase2ToMgsState::InProgress,
+            });
+        let mut upload = UploadTrampolinePhase2ToMgs {
+            status,
+            task: tokio::spawn(async move {
+                let _status_tx = status_tx;
+                std::future::pending().await
+            }),
+            interest: Weak::new(),
+        };
+
+        // Every update started alongside the upload shares one interest.
+        let interest = upload.register_interest(&log);
+        assert!(Arc::ptr_eq(&interest, &upload.register_interest(&log)));
+
+        // Start the only sled update, which holds the interest for as long as
+        // it runs.
+        let (sender, _receiver) = mpsc::channel(128);
+        let engine = UpdateEngine::new(&log, sender);
+        let (started_sender, started) = oneshot::channel();
+        engine
+            .new_step(
+                UpdateComponent::Host,
+                UpdateStepId::WaitingForTrampolinePhase2Upload,
+                "Waiting for trampoline phase 2 upload to MGS",
+                move |_cx| async move {
+                    let _interest = interest;
+                    _ = started_sender.send(());
+                    std::future::pending::<()>().await;
+                    StepSuccess::new(()).into()
+                },
+            )
+            .register();
+        let abort_handle = engine.abort_handle();
+        let engine_task = tokio::spawn(async move { engine.execute().await });
+        started.await.expect("step started");
+        assert!(!upload.was_cancelled());
+        assert!(!upload.task.is_finished());
+
+        // Aborting the update cancels the upload.
+        abort_handle.abort("aborted by test").expect("engine running");
+        engine_task
+            .await
+            .expect("engine task didn't panic")
+            .expect_err("engine was aborted");
+        assert!(upload.was_cancelled());
+        let err =
+            tokio::time::timeout(Duration::from_secs(10), &mut upload.task)
+                .await
+                .expect("upload task finished")
+                .expect_err("upload task was cancelled");
+        assert!(err.is_cancelled(), "{err}");
+    }
+
+    #[test]
+    fn present_sps_of_type_expands_all_sleds() {
+        let sp = |type_, slot, present: bool| {
+            let mut sp = SpInventory::new(SpIdentifier { type_, slot });
+            if present {
+                sp.state = Some(gateway_client::types::SpState {
+                    serial_number: format!("serial-{slot}"),
+                    model: "model".to_owned(),
+                    revision: 0,
+                    hubris_archive_id: "archive".to_owned(),
+                    base_mac_address: [0; 6],
+                    power_state: PowerState::A0,
+                    rot: gateway_client::types::RotState::CommunicationFailed {
+                        message: "not simulated".to_owned(),
+                    },
+                });
+            }
+            sp
+        };
+        let sled = |slot| SpIdentifier { slot, type_: SpType::Sled };
+
+        let inventory = RackV1Inventory {
+            sps: vec![
+                sp(SpType::Switch, 0, true),
+                sp(SpType::Sled, 3, true),
+                sp(SpType::Sled, 7, false),
+                sp(SpType::Sled, 1, true),
+                sp(SpType::Power, 0, true),
+            ],
+        };
+
+        // Only sleds with state present are expanded.
+        assert_eq!(
+            present_sps_of_type(&inventory, SpType::Sled),
+            Ok([sled(1), sled(3)].into()),
+        );
+
+        // With no present SPs of the type, expansion fails.
+        let inventory = RackV1Inventory {
+            sps: vec![sp(SpType::Switch, 0, true), sp(SpType::Sled, 7, false)],
+        };
+        assert_eq!(
+            present_sps_of_type(&inventory, SpType::Sled),
+            Err(StartUpdateError::NoPresentTargets(SpType::Sled)),
+        );
+    }
+
+    #[test]
+    fn mgs_errors_carry_full_cause_chain() {
+        // A communication error wrapping a URL parse error.
+        let parse_error = "not a url".parse::<reqwest::Url>().unwrap_err();
+        let reqwest_error = reqwest::Client::new()
+            .get("not a url")
+            .build()
+            .expect_err("building request with invalid URL fails");
+        let error = UpdateTerminalError::GetSpCabooseFailed {
+            error: mgs_error(gateway_client::Error::CommunicationError(
+                reqwest_error,
+            )),
+        };
+
+        let message = DisplayErrorChain::new(&error).to_string();
+        assert!(
+            message.starts_with("getting SP caboose failed: "),
+            "unexpected message: {message}"
+        );
+        assert!(
+            message.contains(&parse_error.to_string()),
+            "message {message:?} does not contain inner cause {parse_error:?}"
+        );
+    }
+
+    #[test]
+    fn can_skip_when_up_to_date_respects_options() {
+        let opts = start_update_options(None, None);
+        assert!(can_skip_when_up_to_date(&opts));
+
+        let mut skip_rot = opts.clone();
+        skip_rot.skip_rot_version_check = true;
+        assert!(!can_skip_when_up_to_date(&skip_rot));
+
+        let mut skip_sp = opts.clone();
+        skip_sp.skip_sp_version_check = true;
+        assert!(!can_skip_when_up_to_date(&skip_sp));
+
+        let mut simulated = opts.clone();
+        simulated.test_simulate_sp_result =
+            Some(UpdateSimulatedResult::Success);
+        assert!(!can_skip_when_up_to_date(&simulated));
+
+        let mut host_only = opts.clone();
+        host_only.host_only = true;
+        assert!(!can_skip_when_up_to_date(&host_only));
+    }
+
+    #[test]
+    fn up_to_date_message_requires_all_components_to_match() {
+        let rot = SemverVersion::new(1, 0, 0);
+        let sp = SemverVersion::new(2, 0, 0);
+        let old = SemverVersion::new(0, 9, 0);
+
+        assert_eq!(
+            up_to_date_message(&rot, Some(&rot), &sp, Some(&sp)).as_deref(),
+            Some("RoT and SP already at versions 1.0.0 and 2.0.0"),
+        );
+        assert_eq!(up_to_date_message(&rot, Some(&old), &sp, Some(&sp)), None);
+        assert_eq!(up_to_date_message(&rot, Some(&rot), &sp, Some(&old)), None);
+        assert_eq!(up_to_date_message(&rot, None, &sp, Some(&sp)), None);
+        assert_eq!(up_to_date_message(&rot, Some(&rot), &sp, None), None);
+    }
+
+    #[tokio::test]
+    async fn up_to_date_update_finishes_with_single_step() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let (sender, mut receiver) = mpsc::channel(128);
+        let engine = UpdateEngine::new(&log, sender);
+
+        // An SP whose components are already at the target versions.
+        let version = SemverVersion::new(1, 0, 0);
+        let message = up_to_date_message(
+            &version,
+            Some(&version),
+            &version,
+            Some(&version),
+        )
+        .expect("all components up to date");
+        register_up_to_date_step(&engine, message.clone());
+        engine.execute().await.expect("engine execution succeeded");
+
+        let mut completed = None;
+        while let Ok(event) = receiver.try_recv() {
+            if let Event::Step(event) = event {
+                if let StepEventKind::ExecutionCompleted {
+                    last_step,
+                    last_outcome,
+                    ..
+                } = event.kind
+                {
+                    completed = Some((last_step, last_outcome));
+                }
+            }
+        }
+        let (last_step, last_outcome) = completed.expect("execution completed");
+        assert_eq!(last_step.info.id, UpdateStepId::CheckingComponentsUpToDate);
+        assert_eq!(last_step.info.index, 0);
+        assert_eq!(last_step.info.total_component_steps, 1);
+        match last_outcome {
+            StepOutcome::Success { message: Some(m), .. } => {
+                assert_eq!(m, message);
+            }
+            other => panic!("unexpected outcome: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn event_report_since_returns_newer_events() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let (sender, mut receiver) = mpsc::channel(128);
+        let engine = UpdateEngine::new(&log, sender);
+        for _ in 0..3 {
+            engine
+                .new_step(
+                    UpdateComponent::Sp,
+                    UpdateStepId::TestStep,
+                    "Test step",
+                    |_cx| async move { StepSuccess::new(()).into() },
+                )
+                .register();
+        }
+        let abort_handle = engine.abort_handle();
+        engine.execute().await.expect("engine execution succeeded");
+
+        let update_data = SpUpdateData {
+            task: tokio::spawn(async {}),
+            abort_handle,
+            event_buffer: Arc::new(StdMutex::new(EventBuffer::new(16))),
+            activity: UpdateActivity::new(),
+            previous_group: None,
+            host_boot_checkpoint: None,
+        };
+        {
+            let mut event_buffer = update_data.event_buffer.lock().unwrap();
+            while let Ok(event) = receiver.try_recv() {
+                event_buffer.add_event(event);
+            }
+        }
+
+        // A full report contains every step event, and its high-water mark is
+        // the index of the last one.
+        let full = update_data.event_report_since(None);
+        assert!(full.step_events.len() > 2, "engine produced step events");
+        let last_index = full.step_events.last().unwrap().event_index;
+        assert_eq!(full.last_seen, Some(last_index));
+
+        // Fetching since an earlier event only returns the newer ones.
+        let since = full.step_events[1].event_index;
+        let delta = update_data.event_report_since(Some(since));
+        let expected: Vec<_> = full
+            .step_events
+            .iter()
+            .map(|event| event.event_index)
+            .filter(|&index| index > since)
+            .collect();
+        let actual: Vec<_> =
+            delta.step_events.iter().map(|event| event.event_index).collect();
+        assert_eq!(actual, expected);
+        assert_eq!(delta.last_seen, Some(last_index));
+
+        // Once caught up, there are no new step events, and the high-water
+        // mark stays where it was.
+        let caught_up = update_data.event_report_since(Some(last_index));
+        assert!(caught_up.step_events.is_empty());
+        assert_eq!(caught_up.last_seen, Some(last_index));
+    }
+
+    #[tokio::test]
+    async fn host_boot_checkpoint_waits_for_proceed() {
+        let log = slog::Logger::root(slog::Discard, o!());
+        let checkpoint = HostBootCheckpoint::new();
+
+        // Nothing is waiting yet, so there's nothing to proceed.
+        assert!(!checkpoint.proceed());
+
+        let (sender, mut receiver) = mpsc::channel(128);
+        let engine = UpdateEngine::new(&log, sender);
+        let step_checkpoint = checkpoint.clone();
+        engine
+            .new_step(
+                UpdateComponent::Host,
+                UpdateStepId::WaitingForOperatorConfirmation,
+                "Waiting for operator confirmation to boot the host",
+                move |cx| async move {
+                    step_checkpoint.wait(&cx).await;
+                    StepSuccess::new(()).into()
+                },
+            )
+            .register();
+        let task = tokio::spawn(async move { engine.execute().await });
+
+        // Wait for the step to report that it's waiting (skipping the
+        // `WaitingForProgress` event sent when the step starts).
+        loop {
+            let event = receiver.recv().await.expect("engine is running");
+            if let Event::Progress(event) = event {
+                if matches!(
+                    event.kind,
+                    update_engine::events::ProgressEventKind::Progress { .. }
+                ) {
+                    break;
+                }
+            }
+        }
+
+        // The step stays blocked until we proceed.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!task.is_finished(), "step blocks until proceed");
+
+        assert!(checkpoint.proceed());
+        // Proceeding twice is an error.
+        assert!(!checkpoint.proceed());
+
+        tokio::time::timeout(Duration::from_secs(10), task)
+            .await
+            .expect("step finished after proceeding")
+            .expect("engine task didn't panic")
+            .expect("engine execution succeeded");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn installinator_start_timeout_fires() {
+        const TIMEOUT: Duration = Duration::from_secs(600);
+
+        // Keep the sender alive but never send any installinator progress.
+        let (_start_sender, start_receiver) = oneshot::channel();
+        let start = tokio::time::Instant::now();
+        let error = wait_for_installinator_start(
+            start_receiver,
+            Some(TIMEOUT),
+            std::future::pending(),
+        )
+        .await
+        .expect_err("installinator never started");
+
+        match error {
+            UpdateTerminalError::InstallinatorStartTimeout { timeout } => {
+                assert_eq!(timeout, TIMEOUT);
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+        assert_eq!(start.elapsed(), TIMEOUT);
+
+        // With no timeout, we keep waiting.
+        let (_start_sender, start_receiver) = oneshot::channel();
+        let wait = wait_for_installinator_start(
+            start_receiver,
+            None,
+            std::future::pending(),
+        );
+        tokio::time::timeout(Duration::from_secs(24 * 60 * 60), wait)
+            .await
+            .expect_err("no timeout means waiting indefinitely");
+    }
+
+    #[test]
+    fn sp_interrogation_result_unparseable_version() {
+        let message = "SP board test, version garbage".to_owned();
+
+        // By default, we warn and proceed without a version.
+        let result = sp_interrogation_result(
+            (),
+            Some("garbage".to_owned()),
+            message.clone(),
+            false,
+        )
+        .expect("unparseable version is a warning by default");
+        assert_eq!(result.output, ((), None));
+        assert!(
+            matches!(result.outcome, StepOutcome::Warning { .. }),
+            "unexpected outcome: {:?}",
+            result.outcome,
+        );
+
+        // In strict mode, it's an error.
+        let error = sp_interrogation_result(
+            (),
+            Some("garbage".to_owned()),
+            message.clone(),
+            true,
+        )
+        .expect_err("unparseable version fails in strict mode");
+        match error {
+            UpdateTerminalError::UnparseableSpVersion { version, .. } => {
+                assert_eq!(version, "garbage");
+            }
+            other => panic!("unexpected error: {other:?}"),
+        }
+
+        // A valid version is unaffected by strict mode, and a missing version
+        // is still just a warning.
+        let result = sp_interrogation_result(
+            (),
+            Some("1.2.3".to_owned()),
+            message.clone(),
+            true,
+        )
+        .expect("valid version succeeds");
+        assert_eq!(result.output, ((), Some(SemverVersion::new(1, 2, 3))));
+        let result = sp_interrogation_result((), None, message, true)
+            .expect("missing version is a warning");
+        assert!(
+            matches!(result.outcome, StepOutcome::Warning { .. }),
+            "unexpected outcome: {:?}",
+            result.outcome,
+        );
+    }
+
+    #[test]
+    fn write_rate_estimator_eta() {
+        const TOTAL: u64 = 1_000_000;
+        let start = Instant::now();
+        let mut estimator = WriteRateEstimator::new();
+
+        // A single sample isn't enough to compute a rate.
+        assert_eq!(estimator.record(start, 0, TOTAL), None);
+
+        // Feed a steady 10_000 bytes/sec; after 10 seconds 100_000 bytes are
+        // written and 900_000 remain, so ~90 seconds should be left.
+        let mut eta = None;
+        for i in 1..=10 {
+            eta = estimator.record(
+                start + Duration::from_secs(i),
+                i * 10_000,
+                TOTAL,
+            );
+        }
+        let eta = eta.expect("ETA computed");
+        assert!(
+            eta.as_secs_f64() > 89.0 && eta.as_secs_f64() < 91.0,
+            "unexpected ETA: {eta:?}"
+        );
+
+        // Speeding up to 100_000 bytes/sec should bring the ETA down once the
+        // window has moved past the slow samples.
+        let mut bytes = 100_000;
+        let mut eta = None;
+        for i in 11..=(10 + WriteRateEstimator::WINDOW as u64) {
+            bytes += 100_000;
+            eta =
+                estimator.record(start + Duration::from_secs(i), bytes, TOTAL);
+        }
+        let eta = eta.expect("ETA computed");
+        let expected = (TOTAL - bytes) as f64 / 100_000.0;
+        assert!(
+            (eta.as_secs_f64() - expected).abs() < 1.0,
+            "unexpected ETA: {eta:?} (expected ~{expected}s)"
+        );
+
+        // Progress moving backwards resets the estimate.
+        assert_eq!(
+            estimator.record(start + Duration::from_secs(30), 0, TOTAL),
+            None
+        );
+    }
+}
@@ -16,12 +16,20 @@ use omicron_common::{
     api::internal::nexus::KnownArtifactKind,
     update::{ArtifactHashId, ArtifactKind},
 };
+use oximeter::{
+    types::{Datum, FieldValue},
+    Producer,
+};
 use tokio::sync::watch;
 use uuid::Uuid;
-use wicket_common::update_events::{StepEventKind, UpdateComponent};
+use wicket_common::update_events::{
+    EventReport, StepEventKind, StepOutcome, UpdateComponent, UpdateStepId,
+};
 use wicketd::{RunningUpdateState, StartUpdateError};
 use wicketd_client::types::{
-    GetInventoryParams, GetInventoryResponse, SpIdentifier, SpType,
+    AbortUpdateOptions, ClearUpdateStateOptions,
+    GetArtifactsAndEventReportsParams, GetInventoryParams,
+    GetInventoryResponse, SpIdentifier, SpType, SpUpdateStateSummary,
     StartUpdateOptions, StartUpdateParams,
 };
 
@@ -32,21 +40,10 @@ async fn test_updates() {
     let log = wicketd_testctx.log();
 
     let temp_dir = Utf8TempDir::new().expect("temp dir created");
-    let archive_path = temp_dir.path().join("archive.zip");
-
-    let args = tufaceous::Args::try_parse_from([
-        "tufaceous",
-        "assemble",
-        "../tufaceous/manifests/fake.toml",
-        archive_path.as_str(),
-    ])
-    .expect("args parsed correctly");
-
-    args.exec(log).expect("assemble command completed successfully");
-
-    // Read the archive and upload it to the server.
     let zip_bytes =
-        fs_err::read(&archive_path).expect("archive read correctly");
+        assemble_repository(log, &temp_dir, FAKE_MANIFEST, "archive.zip");
+
+    // Upload the archive to the server.
     wicketd_testctx
         .wicketd_client
         .put_repository(zip_bytes)
@@ -56,7 +53,9 @@ async fn test_updates() {
     // List out the artifacts in the repository.
     let response = wicketd_testctx
         .wicketd_client
-        .get_artifacts_and_event_reports()
+        .get_artifacts_and_event_reports(&GetArtifactsAndEventReportsParams {
+            last_seen: Default::default(),
+        })
         .await
         .expect("get_artifacts_and_event_reports succeeded")
         .into_inner();
@@ -93,6 +92,32 @@ async fn test_updates() {
         assert!(expected_installable_kinds.insert(add));
     }
 
+    // The artifact-only listing should match the artifact portion of the
+    // combined response.
+    let artifacts_response = wicketd_testctx
+        .wicketd_client
+        .get_artifacts()
+        .await
+        .expect("get_artifacts succeeded")
+        .into_inner();
+    assert_eq!(
+        format!("{:?}", artifacts_response.system_version),
+        format!("{:?}", response.system_version),
+        "system versions match"
+    );
+    let mut artifact_ids = artifacts_response.artifacts;
+    artifact_ids.sort();
+    let mut expected_artifact_ids: Vec<_> = response
+        .artifacts
+        .iter()
+        .map(|artifact| artifact.artifact_id.clone())
+        .collect();
+    expected_artifact_ids.sort();
+    assert_eq!(
+        artifact_ids, expected_artifact_ids,
+        "artifact IDs match the combined response"
+    );
+
     // Ensure that this is a sensible result.
     let mut kinds = BTreeSet::new();
     let mut installable_kinds = BTreeSet::new();
@@ -181,27 +206,152 @@ async fn test_updates() {
 }
 
 #[tokio::test]
-async fn test_installinator_fetch() {
-    let gateway = gateway_setup::test_setup("test_updates", SpPort::One).await;
+async fn test_host_only_update() {
+    let gateway =
+        gateway_setup::test_setup("test_host_only_update", SpPort::One).await;
     let wicketd_testctx = WicketdTestContext::setup(gateway).await;
     let log = wicketd_testctx.log();
 
     let temp_dir = Utf8TempDir::new().expect("temp dir created");
-    let archive_path = temp_dir.path().join("archive.zip");
+    let zip_bytes =
+        assemble_repository(log, &temp_dir, FAKE_MANIFEST, "archive.zip");
 
-    let args = tufaceous::Args::try_parse_from([
-        "tufaceous",
-        "assemble",
-        "../tufaceous/manifests/fake.toml",
-        archive_path.as_str(),
-    ])
-    .expect("args parsed correctly");
+    wicketd_testctx
+        .wicketd_client
+        .put_repository(zip_bytes)
+        .await
+        .expect("bytes read and archived");
 
-    args.exec(log).expect("assemble command completed successfully");
+    let target_sp = SpIdentifier { type_: SpType::Sled, slot: 0 };
+    let options = StartUpdateOptions { host_only: true, ..Default::default() };
+    let params = StartUpdateParams { targets: vec![target_sp], options };
+    wicketd_testctx
+        .wicketd_client
+        .post_start_update(&params)
+        .await
+        .expect("update started successfully");
+
+    // Wait for the SP step to complete, by which point the RoT step has too.
+    let sp = gateway_client::types::SpIdentifier {
+        slot: 0,
+        type_: gateway_client::types::SpType::Sled,
+    };
+    let sp_completed = async {
+        loop {
+            let report = get_event_report(&wicketd_testctx, sp).await;
+            let completed = report.step_events.iter().any(|event| {
+                matches!(
+                    &event.kind,
+                    StepEventKind::StepCompleted { step, .. }
+                        if step.info.component == UpdateComponent::Sp
+                )
+            });
+            if completed {
+                break report;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    let report = tokio::time::timeout(Duration::from_secs(10), sp_completed)
+        .await
+        .expect("SP step completed within 10 seconds");
+
+    // The RoT and SP each have a single step, which was skipped; every other
+    // step updates the host.
+    let steps = report
+        .step_events
+        .iter()
+        .find_map(|event| match &event.kind {
+            StepEventKind::ExecutionStarted { steps, .. } => Some(steps),
+            _ => None,
+        })
+        .expect("update started");
+    for component in [UpdateComponent::Rot, UpdateComponent::Sp] {
+        let component_steps: Vec<_> = steps
+            .iter()
+            .filter(|step| step.component == component)
+            .map(|step| step.id.clone())
+            .collect();
+        assert_eq!(
+            component_steps,
+            [UpdateStepId::SpComponentUpdate],
+            "{component:?} steps"
+        );
+    }
+    assert!(
+        steps.iter().any(|step| step.component == UpdateComponent::Host),
+        "host steps present: {steps:#?}"
+    );
+    for event in &report.step_events {
+        if let StepEventKind::StepCompleted { step, outcome, .. } = &event.kind
+        {
+            if step.info.component != UpdateComponent::Host {
+                assert!(
+                    outcome.is_skipped(),
+                    "{:?} step was skipped: {outcome:?}",
+                    step.info.component
+                );
+            }
+        }
+    }
+
+    wicketd_testctx.teardown().await;
+}
 
-    // Read the archive and upload it to the server.
+#[tokio::test]
+async fn test_host_only_update_rejects_switch() {
+    let gateway = gateway_setup::test_setup(
+        "test_host_only_update_rejects_switch",
+        SpPort::One,
+    )
+    .await;
+    let wicketd_testctx = WicketdTestContext::setup(gateway).await;
+    let log = wicketd_testctx.log();
+
+    let temp_dir = Utf8TempDir::new().expect("temp dir created");
     let zip_bytes =
-        fs_err::read(&archive_path).expect("archive read correctly");
+        assemble_repository(log, &temp_dir, FAKE_MANIFEST, "archive.zip");
+
+    wicketd_testctx
+        .wicketd_client
+        .put_repository(zip_bytes)
+        .await
+        .expect("bytes read and archived");
+
+    // A switch has no host, so a host-only update would skip every step.
+    let target_sp = SpIdentifier { type_: SpType::Switch, slot: 0 };
+    let options = StartUpdateOptions { host_only: true, ..Default::default() };
+    let params = StartUpdateParams { targets: vec![target_sp], options };
+    let error = wicketd_testctx
+        .wicketd_client
+        .post_start_update(&params)
+        .await
+        .expect_err("host-only update of a switch is rejected");
+    let message = match error {
+        wicketd_client::Error::ErrorResponse(response) => {
+            response.into_inner().message
+        }
+        other => panic!("unexpected error: {other}"),
+    };
+    assert!(
+        message.contains("host-only updates can only target sleds"),
+        "unexpected error message: {message}"
+    );
+
+    wicketd_testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_installinator_fetch() {
+    let gateway = gateway_setup::test_setup("test_updates", SpPort::One).await;
+    let wicketd_testctx = WicketdTestContext::setup(gateway).await;
+    let log = wicketd_testctx.log();
+
+    let temp_dir = Utf8TempDir::new().expect("temp dir created");
+    let zip_bytes =
+        assemble_repository(log, &temp_dir, FAKE_MANIFEST, "archive.zip");
+
+    // Upload the archive to the server.
     wicketd_testctx
         .wicketd_client
         .put_repository(zip_bytes)
@@ -324,21 +474,10 @@ async fn test_update_races() {
     let log = wicketd_testctx.log();
 
     let temp_dir = Utf8TempDir::new().expect("temp dir created");
-    let archive_path = temp_dir.path().join("archive.zip");
-
-    let args = tufaceous::Args::try_parse_from([
-        "tufaceous",
-        "assemble",
-        "../tufaceous/manifests/fake.toml",
-        archive_path.as_str(),
-    ])
-    .expect("args parsed correctly");
-
-    args.exec(log).expect("assemble command completed successfully");
-
-    // Read the archive and upload it to the server.
     let zip_bytes =
-        fs_err::read(&archive_path).expect("archive read correctly");
+        assemble_repository(log, &temp_dir, FAKE_MANIFEST, "archive.zip");
+
+    // Upload the archive to the server.
     wicketd_testctx
         .wicketd_client
         .put_repository(zip_bytes.clone())
@@ -356,7 +495,7 @@ async fn test_update_races() {
     wicketd_testctx
         .server
         .update_tracker
-        .start_fake_update(sps.clone(), receiver)
+        .start_fake_update(sps.clone(), Vec::new(), receiver)
         .await
         .expect("start_fake_update successful");
 
@@ -375,7 +514,7 @@ async fn test_update_races() {
         let err = wicketd_testctx
             .server
             .update_tracker
-            .start_fake_update(sps, receiver)
+            .start_fake_update(sps, Vec::new(), receiver)
             .await
             .expect_err("start_fake_update failed while update is running");
         assert_eq!(err.len(), 1, "one error returned: {err:?}");
@@ -401,6 +540,46 @@ async fn test_update_races() {
         "last event is execution completed: {last_event:#?}"
     );
 
+    // The fake update's single step should have been recorded as started and
+    // completed, and never failed or retried.
+    let samples: Vec<_> = wicketd_testctx
+        .server
+        .update_tracker
+        .metrics()
+        .clone()
+        .produce()
+        .expect("update metrics produced")
+        .collect();
+    assert_eq!(samples.len(), 5, "one sample per metric: {samples:#?}");
+    for sample in &samples {
+        assert!(
+            sample.target_fields().any(|field| field.name == "sp_type"
+                && field.value == FieldValue::from("sled")),
+            "sample is for sled 0: {sample:#?}"
+        );
+        assert!(
+            sample.metric_fields().any(|field| field.name == "step_id"
+                && field.value == FieldValue::from("running_installinator")),
+            "sample is for the fake step: {sample:#?}"
+        );
+    }
+    let step_count = |timeseries_name: &str| {
+        let sample = samples
+            .iter()
+            .find(|sample| sample.timeseries_name == timeseries_name)
+            .unwrap_or_else(|| panic!("{timeseries_name} sample produced"));
+        match sample.measurement.datum() {
+            Datum::CumulativeI64(count) => count.value(),
+            other => {
+                panic!("unexpected datum for {timeseries_name}: {other:?}")
+            }
+        }
+    };
+    assert_eq!(step_count("wicketd_update:step_started"), 1);
+    assert_eq!(step_count("wicketd_update:step_completed"), 1);
+    assert_eq!(step_count("wicketd_update:step_failed"), 0);
+    assert_eq!(step_count("wicketd_update:step_retried"), 0);
+
     // Try uploading the repository again -- since no updates are running, this
     // should succeed.
     wicketd_testctx
@@ -423,3 +602,644 @@ async fn test_update_races() {
 
     wicketd_testctx.teardown().await;
 }
+
+#[tokio::test]
+async fn test_update_invalid_target() {
+    let gateway =
+        gateway_setup::test_setup("test_update_invalid_target", SpPort::One)
+            .await;
+    let wicketd_testctx = WicketdTestContext::setup(gateway).await;
+
+    // There are only two switches, so slot 5 is out of range.
+    let valid_sp = gateway_client::types::SpIdentifier {
+        slot: 0,
+        type_: gateway_client::types::SpType::Switch,
+    };
+    let invalid_sp = gateway_client::types::SpIdentifier {
+        slot: 5,
+        type_: gateway_client::types::SpType::Switch,
+    };
+    let sps: BTreeSet<_> = [valid_sp, invalid_sp].into_iter().collect();
+
+    let (_, receiver) = watch::channel(());
+    let errors = wicketd_testctx
+        .server
+        .update_tracker
+        .start_fake_update(sps, Vec::new(), receiver)
+        .await
+        .expect_err("start_fake_update failed with an invalid target");
+    assert!(
+        errors.contains(&StartUpdateError::InvalidTarget(vec![invalid_sp])),
+        "invalid target reported: {errors:?}"
+    );
+
+    wicketd_testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_update_groups() {
+    let gateway =
+        gateway_setup::test_setup("test_update_groups", SpPort::One).await;
+    let wicketd_testctx = WicketdTestContext::setup(gateway).await;
+    let log = wicketd_testctx.log();
+
+    let temp_dir = Utf8TempDir::new().expect("temp dir created");
+    let zip_bytes =
+        assemble_repository(log, &temp_dir, FAKE_MANIFEST, "archive.zip");
+
+    wicketd_testctx
+        .wicketd_client
+        .put_repository(zip_bytes)
+        .await
+        .expect("bytes read and archived");
+
+    // Update the switch first, then the sled.
+    let switch = gateway_client::types::SpIdentifier {
+        slot: 0,
+        type_: gateway_client::types::SpType::Switch,
+    };
+    let sled = gateway_client::types::SpIdentifier {
+        slot: 0,
+        type_: gateway_client::types::SpType::Sled,
+    };
+    let sps: BTreeSet<_> = [switch, sled].into_iter().collect();
+    let update_groups = vec![[switch].into_iter().collect()];
+
+    let (sender, receiver) = watch::channel(());
+    wicketd_testctx
+        .server
+        .update_tracker
+        .start_fake_update(sps, update_groups, receiver)
+        .await
+        .expect("start_fake_update successful");
+
+    // Wait until the switch update is blocked on the watch channel, and the
+    // sled update is blocked on the switch update.
+    let first_step_ids = async {
+        loop {
+            let switch_step = first_step_id(&wicketd_testctx, switch).await;
+            let sled_step = first_step_id(&wicketd_testctx, sled).await;
+            if let (Some(switch_step), Some(sled_step)) =
+                (switch_step, sled_step)
+            {
+                break (switch_step, sled_step);
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    let (switch_step, sled_step) =
+        tokio::time::timeout(Duration::from_secs(10), first_step_ids)
+            .await
+            .expect("both updates started within 10 seconds");
+    assert_eq!(switch_step, UpdateStepId::RunningInstallinator);
+    assert_eq!(sled_step, UpdateStepId::WaitingForPreviousUpdateGroup);
+
+    // The sled update must still be waiting: nothing has completed a step.
+    let sled_report = get_event_report(&wicketd_testctx, sled).await;
+    assert!(
+        sled_report.step_events.iter().all(|event| matches!(
+            event.kind,
+            StepEventKind::ExecutionStarted { .. }
+        )),
+        "sled update is waiting for the switch update: {sled_report:#?}"
+    );
+
+    // Unblock the switch update. Once it finishes, the sled update proceeds
+    // (its fake step has already seen the watch channel change).
+    sender.send(()).expect("receiver kept open by update engine");
+
+    let completed = async {
+        loop {
+            let sled_report = get_event_report(&wicketd_testctx, sled).await;
+            if let Some(event) = sled_report.step_events.iter().find(|event| {
+                matches!(event.kind, StepEventKind::ExecutionCompleted { .. })
+            }) {
+                break event.clone();
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    let sled_completed =
+        tokio::time::timeout(Duration::from_secs(10), completed)
+            .await
+            .expect("sled update completed within 10 seconds");
+
+    match sled_completed.kind {
+        StepEventKind::ExecutionCompleted { last_step, .. } => {
+            assert_eq!(last_step.info.id, UpdateStepId::RunningInstallinator);
+        }
+        other => panic!("unexpected event kind: {other:?}"),
+    }
+
+    // The sled update could only get past its first step once the switch
+    // update finished.
+    let switch_report = get_event_report(&wicketd_testctx, switch).await;
+    assert!(
+        switch_report.step_events.iter().any(|event| matches!(
+            event.kind,
+            StepEventKind::ExecutionCompleted { .. }
+        )),
+        "switch update completed: {switch_report:#?}"
+    );
+
+    wicketd_testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_abort_and_clear_update() {
+    let gateway =
+        gateway_setup::test_setup("test_abort_and_clear_update", SpPort::One)
+            .await;
+    let wicketd_testctx = WicketdTestContext::setup(gateway).await;
+    let log = wicketd_testctx.log();
+
+    let temp_dir = Utf8TempDir::new().expect("temp dir created");
+    let zip_bytes =
+        assemble_repository(log, &temp_dir, FAKE_MANIFEST, "archive.zip");
+
+    wicketd_testctx
+        .wicketd_client
+        .put_repository(zip_bytes)
+        .await
+        .expect("bytes read and archived");
+
+    let sp = gateway_client::types::SpIdentifier {
+        slot: 0,
+        type_: gateway_client::types::SpType::Sled,
+    };
+    let sps: BTreeSet<_> = [sp].into_iter().collect();
+
+    // Start a fake update that never finishes on its own.
+    let (_sender, receiver) = watch::channel(());
+    wicketd_testctx
+        .server
+        .update_tracker
+        .start_fake_update(sps.clone(), Vec::new(), receiver)
+        .await
+        .expect("start_fake_update successful");
+
+    let started = async {
+        while first_step_id(&wicketd_testctx, sp).await.is_none() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), started)
+        .await
+        .expect("update started within 10 seconds");
+
+    // Abort the update, clearing its state.
+    wicketd_testctx
+        .wicketd_client
+        .post_abort_update(
+            SpType::Sled,
+            0,
+            &AbortUpdateOptions {
+                message: "aborted by test".to_owned(),
+                clear_state: true,
+                test_error: None,
+            },
+        )
+        .await
+        .expect("abort and clear succeeded");
+
+    // The update's state is gone, rather than showing the aborted update...
+    let report = get_event_report(&wicketd_testctx, sp).await;
+    assert!(
+        report.step_events.is_empty() && report.progress_events.is_empty(),
+        "update state was cleared: {report:#?}"
+    );
+
+    // ... so there's nothing left to abort...
+    wicketd_testctx
+        .wicketd_client
+        .post_abort_update(
+            SpType::Sled,
+            0,
+            &AbortUpdateOptions {
+                message: "aborted by test".to_owned(),
+                clear_state: true,
+                test_error: None,
+            },
+        )
+        .await
+        .expect_err("no update to abort");
+
+    // ... and a new update can be started right away.
+    let (_sender, receiver) = watch::channel(());
+    wicketd_testctx
+        .server
+        .update_tracker
+        .start_fake_update(sps, Vec::new(), receiver)
+        .await
+        .expect("start_fake_update successful after abort and clear");
+
+    wicketd_testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_update_state_summary() {
+    let gateway =
+        gateway_setup::test_setup("test_update_state_summary", SpPort::One)
+            .await;
+    let wicketd_testctx = WicketdTestContext::setup(gateway).await;
+    let log = wicketd_testctx.log();
+
+    let temp_dir = Utf8TempDir::new().expect("temp dir created");
+    let zip_bytes =
+        assemble_repository(log, &temp_dir, FAKE_MANIFEST, "archive.zip");
+
+    wicketd_testctx
+        .wicketd_client
+        .put_repository(zip_bytes)
+        .await
+        .expect("bytes read and archived");
+
+    let sp = gateway_client::types::SpIdentifier {
+        slot: 0,
+        type_: gateway_client::types::SpType::Sled,
+    };
+    let sps: BTreeSet<_> = [sp].into_iter().collect();
+
+    // Nothing has been started yet.
+    assert_eq!(
+        get_update_state(&wicketd_testctx).await,
+        SpUpdateStateSummary::NotStarted
+    );
+
+    // A fake update that's blocked on the watch channel is running.
+    let (sender, receiver) = watch::channel(());
+    wicketd_testctx
+        .server
+        .update_tracker
+        .start_fake_update(sps.clone(), Vec::new(), receiver)
+        .await
+        .expect("start_fake_update successful");
+    assert_eq!(
+        get_update_state(&wicketd_testctx).await,
+        SpUpdateStateSummary::Running
+    );
+
+    // Unblocking the update lets it succeed.
+    sender.send(()).expect("receiver kept open by update engine");
+    wait_for_update_state(&wicketd_testctx, SpUpdateStateSummary::Succeeded)
+        .await;
+
+    // Clearing the update state goes back to not started.
+    wicketd_testctx
+        .wicketd_client
+        .post_clear_update_state(
+            SpType::Sled,
+            0,
+            &ClearUpdateStateOptions { test_error: None },
+        )
+        .await
+        .expect("update state cleared");
+    assert_eq!(
+        get_update_state(&wicketd_testctx).await,
+        SpUpdateStateSummary::NotStarted
+    );
+
+    // Aborting a running update reports it as aborted.
+    let (_sender, receiver) = watch::channel(());
+    wicketd_testctx
+        .server
+        .update_tracker
+        .start_fake_update(sps, Vec::new(), receiver)
+        .await
+        .expect("start_fake_update successful");
+    let started = async {
+        while first_step_id(&wicketd_testctx, sp).await.is_none() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), started)
+        .await
+        .expect("update started within 10 seconds");
+    wicketd_testctx
+        .wicketd_client
+        .post_abort_update(
+            SpType::Sled,
+            0,
+            &AbortUpdateOptions {
+                message: "aborted by test".to_owned(),
+                clear_state: false,
+                test_error: None,
+            },
+        )
+        .await
+        .expect("update aborted");
+    wait_for_update_state(&wicketd_testctx, SpUpdateStateSummary::Aborted)
+        .await;
+
+    wicketd_testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_repository_swap_mid_update() {
+    let gateway = gateway_setup::test_setup(
+        "test_repository_swap_mid_update",
+        SpPort::One,
+    )
+    .await;
+    let wicketd_testctx = WicketdTestContext::setup(gateway).await;
+    let log = wicketd_testctx.log();
+
+    // Assemble two repositories that differ only in their system version.
+    let temp_dir = Utf8TempDir::new().expect("temp dir created");
+    let manifest =
+        fs_err::read_to_string(FAKE_MANIFEST).expect("manifest read correctly");
+    let other_manifest = manifest.replacen(
+        "system_version = \"1.0.0\"",
+        "system_version = \"2.0.0\"",
+        1,
+    );
+    assert_ne!(manifest, other_manifest, "system version was replaced");
+    let other_manifest_path = temp_dir.path().join("other.toml");
+    fs_err::write(&other_manifest_path, other_manifest)
+        .expect("manifest written correctly");
+    let zip_bytes =
+        assemble_repository(log, &temp_dir, FAKE_MANIFEST, "archive.zip");
+    let other_zip_bytes = assemble_repository(
+        log,
+        &temp_dir,
+        other_manifest_path.as_str(),
+        "other.zip",
+    );
+
+    wicketd_testctx
+        .wicketd_client
+        .put_repository(zip_bytes)
+        .await
+        .expect("bytes read and archived");
+
+    let sp = gateway_client::types::SpIdentifier {
+        slot: 0,
+        type_: gateway_client::types::SpType::Sled,
+    };
+    let sps: BTreeSet<_> = [sp].into_iter().collect();
+    let (sender, receiver) = watch::channel(());
+    wicketd_testctx
+        .server
+        .update_tracker
+        .start_fake_update(sps, Vec::new(), receiver)
+        .await
+        .expect("start_fake_update successful");
+
+    // Swap the repository out from under the running update, bypassing the
+    // update tracker's check, then let the update continue.
+    wicketd_testctx
+        .server
+        .artifact_store
+        .put_repository_for_test(std::io::Cursor::new(other_zip_bytes))
+        .await
+        .expect("repository replaced");
+    sender.send(()).expect("receiver kept open by update engine");
+
+    // The update fails rather than carrying on with the new repository.
+    wait_for_update_state(&wicketd_testctx, SpUpdateStateSummary::Failed).await;
+    let report = get_event_report(&wicketd_testctx, sp).await;
+    let message = report
+        .step_events
+        .iter()
+        .find_map(|event| match &event.kind {
+            StepEventKind::ExecutionFailed { message, .. } => {
+                Some(message.clone())
+            }
+            _ => None,
+        })
+        .expect("update failed");
+    assert!(
+        message.contains("TUF repository changed during the update"),
+        "unexpected failure message: {message}"
+    );
+
+    wicketd_testctx.teardown().await;
+}
+
+#[tokio::test]
+async fn test_up_to_date_update_finishes_early() {
+    let gateway = gateway_setup::test_setup(
+        "test_up_to_date_update_finishes_early",
+        SpPort::One,
+    )
+    .await;
+    let wicketd_testctx = WicketdTestContext::setup(gateway).await;
+    let log = wicketd_testctx.log();
+
+    // Assemble a repository whose switch RoT and SP match what the simulated
+    // sidecar reports: version 0.0.1, and (for the SP) its board.
+    let temp_dir = Utf8TempDir::new().expect("temp dir created");
+    let caboose = hubtools::CabooseBuilder::default()
+        .git_commit("this-is-fake-data")
+        .board("SimSidecarSp")
+        .version("0.0.1")
+        .name("SimSidecar")
+        .build();
+    let mut builder = hubtools::HubrisArchiveBuilder::with_fake_image();
+    builder.write_caboose(caboose.as_slice()).expect("caboose written");
+    let switch_sp_path = temp_dir.path().join("switch-sp.zip");
+    fs_err::write(
+        &switch_sp_path,
+        builder.build_to_vec().expect("archive built"),
+    )
+    .expect("archive written");
+
+    let manifest =
+        fs_err::read_to_string(FAKE_MANIFEST).expect("manifest read correctly");
+    let up_to_date_manifest = manifest
+        .replacen(
+            "name = \"fake-switch-sp\"\n\
+             version = \"1.0.0\"\n\
+             source = { kind = \"fake\", size = \"1MiB\" }",
+            &format!(
+                "name = \"fake-switch-sp\"\n\
+                 version = \"0.0.1\"\n\
+                 source = {{ kind = \"file\", path = \"{switch_sp_path}\" }}"
+            ),
+            1,
+        )
+        .replacen(
+            "name = \"fake-switch-rot\"\nversion = \"1.0.0\"",
+            "name = \"fake-switch-rot\"\nversion = \"0.0.1\"",
+            1,
+        );
+    assert_eq!(
+        up_to_date_manifest.matches("0.0.1").count(),
+        2,
+        "switch SP and RoT versions were replaced"
+    );
+    let manifest_path = temp_dir.path().join("up-to-date.toml");
+    fs_err::write(&manifest_path, up_to_date_manifest)
+        .expect("manifest written correctly");
+    let zip_bytes = assemble_repository(
+        log,
+        &temp_dir,
+        manifest_path.as_str(),
+        "archive.zip",
+    );
+
+    wicketd_testctx
+        .wicketd_client
+        .put_repository(zip_bytes)
+        .await
+        .expect("bytes read and archived");
+
+    let target_sp = SpIdentifier { type_: SpType::Switch, slot: 0 };
+    let params = StartUpdateParams {
+        targets: vec![target_sp],
+        options: StartUpdateOptions::default(),
+    };
+    wicketd_testctx
+        .wicketd_client
+        .post_start_update(&params)
+        .await
+        .expect("update started successfully");
+
+    let sp = gateway_client::types::SpIdentifier {
+        slot: 0,
+        type_: gateway_client::types::SpType::Switch,
+    };
+    let finished = async {
+        loop {
+            let report = get_event_report(&wicketd_testctx, sp).await;
+            let finished = report.step_events.iter().any(|event| {
+                matches!(
+                    event.kind,
+                    StepEventKind::ExecutionCompleted { .. }
+                        | StepEventKind::ExecutionFailed { .. }
+                )
+            });
+            if finished {
+                break report;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    let report = tokio::time::timeout(Duration::from_secs(10), finished)
+        .await
+        .expect("update finished within 10 seconds");
+
+    // The update ran the single up-to-date step instead of interrogating and
+    // updating the RoT and SP.
+    let steps = report
+        .step_events
+        .iter()
+        .find_map(|event| match &event.kind {
+            StepEventKind::ExecutionStarted { steps, .. } => Some(steps),
+            _ => None,
+        })
+        .expect("update started");
+    let step_ids: Vec<_> = steps.iter().map(|step| step.id.clone()).collect();
+    assert_eq!(step_ids, [UpdateStepId::CheckingComponentsUpToDate]);
+    let outcome = report
+        .step_events
+        .iter()
+        .find_map(|event| match &event.kind {
+            StepEventKind::ExecutionCompleted { last_outcome, .. } => {
+                Some(last_outcome)
+            }
+            _ => None,
+        })
+        .unwrap_or_else(|| panic!("update succeeded: {report:#?}"));
+    assert!(
+        matches!(
+            outcome,
+            StepOutcome::Success { message: Some(message), .. }
+                if message.contains("already at versions 0.0.1 and 0.0.1")
+        ),
+        "unexpected outcome: {outcome:?}"
+    );
+
+    wicketd_testctx.teardown().await;
+}
+
+/// The manifest for the fake TUF repository used by these tests.
+const FAKE_MANIFEST: &str = "../tufaceous/manifests/fake.toml";
+
+/// Assembles the TUF repository described by `manifest_path` into
+/// `archive_name` within `temp_dir`, returning the archive's contents.
+fn assemble_repository(
+    log: &slog::Logger,
+    temp_dir: &Utf8TempDir,
+    manifest_path: &str,
+    archive_name: &str,
+) -> Vec<u8> {
+    let archive_path = temp_dir.path().join(archive_name);
+    let args = tufaceous::Args::try_parse_from([
+        "tufaceous",
+        "assemble",
+        manifest_path,
+        archive_path.as_str(),
+    ])
+    .expect("args parsed correctly");
+
+    args.exec(log).expect("assemble command completed successfully");
+
+    fs_err::read(&archive_path).expect("archive read correctly")
+}
+
+async fn get_update_state(
+    wicketd_testctx: &WicketdTestContext,
+) -> SpUpdateStateSummary {
+    wicketd_testctx
+        .wicketd_client
+        .get_update_sp_state(SpType::Sled, 0)
+        .await
+        .expect("received update state successfully")
+        .into_inner()
+}
+
+/// Waits for sled 0's update to reach the given (terminal) state.
+async fn wait_for_update_state(
+    wicketd_testctx: &WicketdTestContext,
+    expected: SpUpdateStateSummary,
+) {
+    let wait = async {
+        loop {
+            let state = get_update_state(wicketd_testctx).await;
+            if state == expected {
+                break;
+            }
+            assert_eq!(
+                state,
+                SpUpdateStateSummary::Running,
+                "update is running until it reaches {expected:?}"
+            );
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), wait)
+        .await
+        .unwrap_or_else(|_| panic!("update reached {expected:?} in time"));
+}
+
+async fn get_event_report(
+    wicketd_testctx: &WicketdTestContext,
+    sp: gateway_client::types::SpIdentifier,
+) -> EventReport {
+    let sp_type = match sp.type_ {
+        gateway_client::types::SpType::Sled => SpType::Sled,
+        gateway_client::types::SpType::Switch => SpType::Switch,
+        gateway_client::types::SpType::Power => SpType::Power,
+    };
+    wicketd_testctx
+        .wicketd_client
+        .get_update_sp(sp_type, sp.slot)
+        .await
+        .expect("received event buffer successfully")
+        .into_inner()
+}
+
+/// Returns the ID of the first step of `sp`'s update, if it has started.
+async fn first_step_id(
+    wicketd_testctx: &WicketdTestContext,
+    sp: gateway_client::types::SpIdentifier,
+) -> Option<UpdateStepId> {
+    let report = get_event_report(wicketd_testctx, sp).await;
+    report.step_events.iter().find_map(|event| match &event.kind {
+        StepEventKind::ExecutionStarted { first_step, .. } => {
+            Some(first_step.info.id.clone())
+        }
+        _ => None,
+    })
+}